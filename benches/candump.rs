@@ -0,0 +1,77 @@
+// socketcan/benches/candump.rs
+//
+// Throughput of candump log parsing.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! Measures how many candump lines per second can be parsed, both from
+//! memory with `dump::parse_line()` and through a buffered `dump::Reader`.
+//!
+//! Run with:
+//!
+//! ```text
+//! $ cargo bench --bench candump
+//! ```
+
+use socketcan::dump;
+use std::{hint::black_box, time::Instant};
+
+const N_LINES: usize = 2_000_000;
+
+fn log() -> Vec<u8> {
+    let mut log = Vec::new();
+    for i in 0..N_LINES {
+        let line = match i % 4 {
+            0 => format!(
+                "({}.{:06}) can0 123#DEADBEEF\n",
+                1_700_000_000 + i / 1000,
+                i % 1000
+            ),
+            1 => format!(
+                "({}.{:06}) can1 18FEF100#0011223344556677\n",
+                1_700_000_000,
+                i % 1000
+            ),
+            2 => format!("({}.{:06}) can0 701#7F\n", 1_700_000_000, i % 1000),
+            _ => format!(
+                "({}.{:06}) can0 456##1000102030405060708090A0B0C0D0E0F\n",
+                1_700_000_000,
+                i % 1000
+            ),
+        };
+        log.extend_from_slice(line.as_bytes());
+    }
+    log
+}
+
+fn report(name: &str, start: Instant) {
+    let secs = start.elapsed().as_secs_f64();
+    println!(
+        "{:<12} {:>8.0} ms  {:>6.2} M lines/s",
+        name,
+        secs * 1e3,
+        N_LINES as f64 / secs / 1e6
+    );
+}
+
+fn main() {
+    let log = log();
+
+    let start = Instant::now();
+    for line in log.split_inclusive(|&c| c == b'\n') {
+        black_box(dump::parse_line(line).unwrap());
+    }
+    report("parse_line", start);
+
+    let start = Instant::now();
+    let mut reader = dump::Reader::from_reader(&log[..]);
+    while let Some(rec) = reader.next_record().unwrap() {
+        black_box(rec);
+    }
+    report("Reader", start);
+}
@@ -0,0 +1,508 @@
+// socketcan-derive/src/lib.rs
+//
+// Derive macro for typed CAN messages.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! Derive macro for the `socketcan::message::CanMessage` trait.
+//!
+//! This is re-exported by the `socketcan` crate with its `derive` feature,
+//! and should be used from there. See the `socketcan::message` module for
+//! the attributes it accepts.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{
+    parse_macro_input, spanned::Spanned, Data, DeriveInput, Error, Expr, Fields, LitInt, Result,
+    Type,
+};
+
+/// Derives `CanMessage` for a struct with named fields, along with
+/// conversions to and from a `CanFrame`. The conversion from a frame fails
+/// if the frame doesn't have the message ID.
+#[proc_macro_derive(CanMessage, attributes(can_message, can))]
+pub fn derive_can_message(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+// ===== Attributes =====
+
+/// The largest extended CAN ID.
+const MAX_EXTENDED_ID: u32 = 0x1FFF_FFFF;
+
+/// The message attributes, from `#[can_message(...)]` on the struct.
+struct MessageAttrs {
+    id: LitInt,
+    extended: bool,
+    len: usize,
+}
+
+/// The kind of a field, from its type.
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Bool,
+    Int { bits: u16, signed: bool },
+    Float,
+}
+
+/// A field with its bit layout, from `#[can(...)]`.
+struct FieldSpec {
+    ident: syn::Ident,
+    ty: Type,
+    kind: Kind,
+    start: u16,
+    len: u16,
+    big_endian: bool,
+    signed: bool,
+    scale: Option<Expr>,
+    offset: Option<Expr>,
+}
+
+fn parse_message_attrs(input: &DeriveInput) -> Result<MessageAttrs> {
+    let mut id = None;
+    let mut extended = false;
+    let mut len = 8;
+
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|a| a.path().is_ident("can_message"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("id") {
+                id = Some(meta.value()?.parse::<LitInt>()?);
+            } else if meta.path.is_ident("extended") {
+                extended = true;
+            } else if meta.path.is_ident("len") {
+                len = meta.value()?.parse::<LitInt>()?.base10_parse()?;
+            } else {
+                return Err(meta.error("unknown can_message attribute"));
+            }
+            Ok(())
+        })?;
+    }
+
+    let id = id.ok_or_else(|| {
+        Error::new(
+            input.ident.span(),
+            "missing #[can_message(id = ...)] attribute",
+        )
+    })?;
+    if id.base10_parse::<u32>()? > MAX_EXTENDED_ID {
+        return Err(Error::new(id.span(), "message ID must fit in 29 bits"));
+    }
+    if len > 8 {
+        return Err(Error::new(
+            input.ident.span(),
+            "message length must be 8 bytes or less",
+        ));
+    }
+    Ok(MessageAttrs { id, extended, len })
+}
+
+fn field_kind(ty: &Type) -> Option<Kind> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+    let name = path.path.get_ident()?.to_string();
+    let kind = match name.as_str() {
+        "bool" => Kind::Bool,
+        "f32" | "f64" => Kind::Float,
+        "u8" => Kind::Int {
+            bits: 8,
+            signed: false,
+        },
+        "u16" => Kind::Int {
+            bits: 16,
+            signed: false,
+        },
+        "u32" => Kind::Int {
+            bits: 32,
+            signed: false,
+        },
+        "u64" => Kind::Int {
+            bits: 64,
+            signed: false,
+        },
+        "i8" => Kind::Int {
+            bits: 8,
+            signed: true,
+        },
+        "i16" => Kind::Int {
+            bits: 16,
+            signed: true,
+        },
+        "i32" => Kind::Int {
+            bits: 32,
+            signed: true,
+        },
+        "i64" => Kind::Int {
+            bits: 64,
+            signed: true,
+        },
+        _ => return None,
+    };
+    Some(kind)
+}
+
+fn parse_field(field: &syn::Field) -> Result<FieldSpec> {
+    let ident = field.ident.clone().expect("named field");
+    let kind = field_kind(&field.ty).ok_or_else(|| {
+        Error::new(
+            field.ty.span(),
+            "field must be a bool, an integer, or a floating point type",
+        )
+    })?;
+
+    let mut start = None;
+    let mut len = None;
+    let mut big_endian = false;
+    let mut signed = matches!(kind, Kind::Int { signed: true, .. });
+    let mut scale = None;
+    let mut offset = None;
+
+    for attr in field.attrs.iter().filter(|a| a.path().is_ident("can")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("start") {
+                start = Some(meta.value()?.parse::<LitInt>()?.base10_parse()?);
+            } else if meta.path.is_ident("len") {
+                len = Some(meta.value()?.parse::<LitInt>()?.base10_parse()?);
+            } else if meta.path.is_ident("big_endian") {
+                big_endian = true;
+            } else if meta.path.is_ident("signed") {
+                signed = true;
+            } else if meta.path.is_ident("scale") {
+                scale = Some(meta.value()?.parse::<Expr>()?);
+            } else if meta.path.is_ident("offset") {
+                offset = Some(meta.value()?.parse::<Expr>()?);
+            } else {
+                return Err(meta.error("unknown can attribute"));
+            }
+            Ok(())
+        })?;
+    }
+
+    let start =
+        start.ok_or_else(|| Error::new(ident.span(), "missing #[can(start = ...)] attribute"))?;
+    let len = match (len, kind) {
+        (Some(len), _) => len,
+        (None, Kind::Bool) => 1,
+        (None, Kind::Int { bits, .. }) => bits,
+        (None, Kind::Float) => {
+            return Err(Error::new(
+                ident.span(),
+                "floating point fields need a #[can(len = ...)]",
+            ))
+        }
+    };
+    if len == 0 || len > 64 {
+        return Err(Error::new(
+            ident.span(),
+            "field length must be 1 to 64 bits",
+        ));
+    }
+    if let Kind::Int { bits, .. } = kind {
+        if len > bits {
+            return Err(Error::new(
+                ident.span(),
+                format!(
+                    "field length of {} bits is wider than its {}-bit type",
+                    len, bits
+                ),
+            ));
+        }
+    }
+    if kind != Kind::Float && (scale.is_some() || offset.is_some()) {
+        return Err(Error::new(
+            ident.span(),
+            "scale and offset are only allowed on floating point fields",
+        ));
+    }
+
+    Ok(FieldSpec {
+        ident,
+        ty: field.ty.clone(),
+        kind,
+        start,
+        len,
+        big_endian,
+        signed,
+        scale,
+        offset,
+    })
+}
+
+// ===== Layout =====
+
+impl FieldSpec {
+    /// Gets the index of the last payload bit of the field, walking from
+    /// the start bit in the field's byte order.
+    ///
+    /// This is the same numbering as a `socketcan::signal::Signal`.
+    fn last_bit(&self) -> usize {
+        let start = self.start as usize;
+        let len = self.len as usize;
+        if self.big_endian {
+            let msb_lin = (start / 8) * 8 + (7 - start % 8);
+            let lsb_lin = msb_lin + len - 1;
+            (lsb_lin / 8) * 8 + (7 - lsb_lin % 8)
+        } else {
+            start + len - 1
+        }
+    }
+
+    /// Checks that the field fits in a payload of `size` bytes.
+    fn check_layout(&self, size: usize) -> Result<()> {
+        let nbits = 8 * size;
+        if (self.start as usize) < nbits && self.last_bit() < nbits {
+            Ok(())
+        } else {
+            Err(Error::new(
+                self.ident.span(),
+                format!(
+                    "field at bit {} with length {} doesn't fit in the {}-byte message",
+                    self.start, self.len, size
+                ),
+            ))
+        }
+    }
+}
+
+// ===== Code generation =====
+
+impl FieldSpec {
+    /// The expression for the field's `Signal`.
+    fn signal(&self) -> TokenStream2 {
+        let Self {
+            start, len, signed, ..
+        } = self;
+        let order = self.order();
+        let scale = self
+            .scale
+            .as_ref()
+            .map_or_else(|| quote!(1.0), |e| quote!((#e) as f64));
+        let offset = self
+            .offset
+            .as_ref()
+            .map_or_else(|| quote!(0.0), |e| quote!((#e) as f64));
+        quote! {
+            ::socketcan::signal::Signal {
+                start: #start,
+                len: #len,
+                order: #order,
+                signed: #signed,
+                scale: #scale,
+                offset: #offset,
+            }
+        }
+    }
+
+    fn order(&self) -> TokenStream2 {
+        if self.big_endian {
+            quote!(::socketcan::signal::ByteOrder::BigEndian)
+        } else {
+            quote!(::socketcan::signal::ByteOrder::LittleEndian)
+        }
+    }
+
+    fn decode(&self) -> TokenStream2 {
+        let Self { ident, ty, .. } = self;
+        let sig = self.signal();
+        let val = match self.kind {
+            Kind::Bool => quote!(#sig.decode_raw(data).unwrap_or_default() != 0),
+            Kind::Int { .. } => quote!(#sig.decode_raw(data).unwrap_or_default() as #ty),
+            Kind::Float => quote!(#sig.decode(data).unwrap_or_default() as #ty),
+        };
+        quote!(#ident: #val)
+    }
+
+    fn encode(&self) -> TokenStream2 {
+        let Self {
+            ident, start, len, ..
+        } = self;
+        let order = self.order();
+        // The layout was checked against the message length, so inserting
+        // only fails for a short payload, where the field is left out.
+        let insert = |val| {
+            quote! {
+                ::socketcan::signal::insert_bits(data, #start, #len, #order, #val)
+                    .unwrap_or_default();
+            }
+        };
+        match self.kind {
+            Kind::Bool => insert(quote!(u64::from(self.#ident))),
+            Kind::Int { .. } => insert(quote!(self.#ident as u64)),
+            Kind::Float => {
+                let sig = self.signal();
+                let zero = insert(quote!(0));
+                quote! {
+                    if (#sig).encode(data, self.#ident as f64).is_err() {
+                        #zero
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn expand(input: DeriveInput) -> Result<TokenStream2> {
+    let attrs = parse_message_attrs(&input)?;
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(Error::new(
+                    input.ident.span(),
+                    "CanMessage can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(Error::new(
+                input.ident.span(),
+                "CanMessage can only be derived for structs",
+            ))
+        }
+    };
+    let fields = fields.iter().map(parse_field).collect::<Result<Vec<_>>>()?;
+    for field in &fields {
+        field.check_layout(attrs.len)?;
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let MessageAttrs { id, extended, len } = attrs;
+    let decodes = fields.iter().map(FieldSpec::decode);
+    let encodes = fields.iter().map(FieldSpec::encode);
+
+    Ok(quote! {
+        impl #impl_generics ::socketcan::message::CanMessage for #name #ty_generics #where_clause {
+            const ID: u32 = #id;
+            const EXTENDED: bool = #extended;
+            const LEN: usize = #len;
+
+            fn decode(data: &[u8]) -> Self {
+                Self {
+                    #(#decodes,)*
+                }
+            }
+
+            fn encode(&self, data: &mut [u8]) {
+                #(#encodes)*
+            }
+        }
+
+        impl #impl_generics ::core::convert::TryFrom<::socketcan::CanFrame>
+            for #name #ty_generics #where_clause
+        {
+            type Error = ::socketcan::message::MessageError;
+
+            fn try_from(frame: ::socketcan::CanFrame) -> ::core::result::Result<Self, Self::Error> {
+                <Self as ::socketcan::message::CanMessage>::from_frame(&frame).ok_or_else(|| {
+                    ::socketcan::message::MessageError::WrongId(
+                        ::socketcan::Frame::can_id(&frame))
+                })
+            }
+        }
+
+        impl #impl_generics ::core::convert::From<#name #ty_generics>
+            for ::socketcan::CanFrame #where_clause
+        {
+            fn from(msg: #name #ty_generics) -> Self {
+                ::socketcan::message::CanMessage::to_frame(&msg)
+            }
+        }
+    })
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syn::parse_quote;
+
+    fn expand_err(input: DeriveInput) -> String {
+        expand(input).expect_err("expected an error").to_string()
+    }
+
+    #[test]
+    fn test_bad_layout() {
+        let input = parse_quote! {
+            #[can_message(id = 0x123, len = 2)]
+            struct Msg {
+                #[can(start = 8, len = 9)]
+                val: u16,
+            }
+        };
+        assert_eq!(
+            "field at bit 8 with length 9 doesn't fit in the 2-byte message",
+            expand_err(input)
+        );
+
+        // Big endian walks down from the start bit, into the next byte
+        let input = parse_quote! {
+            #[can_message(id = 0x123, len = 1)]
+            struct Msg {
+                #[can(start = 7, len = 9, big_endian)]
+                val: u16,
+            }
+        };
+        assert!(expand_err(input).contains("doesn't fit"));
+
+        let input = parse_quote! {
+            #[can_message(id = 0x123, len = 2)]
+            struct Msg {
+                #[can(start = 7, len = 9, big_endian)]
+                val: u16,
+            }
+        };
+        assert!(expand(input).is_ok());
+    }
+
+    #[test]
+    fn test_bad_len() {
+        let input = parse_quote! {
+            #[can_message(id = 0x123)]
+            struct Msg {
+                #[can(start = 0, len = 12)]
+                val: u8,
+            }
+        };
+        assert_eq!(
+            "field length of 12 bits is wider than its 8-bit type",
+            expand_err(input)
+        );
+
+        let input = parse_quote! {
+            #[can_message(id = 0x123)]
+            struct Msg {
+                #[can(start = 0, len = 12)]
+                val: i16,
+            }
+        };
+        assert!(expand(input).is_ok());
+    }
+
+    #[test]
+    fn test_bad_id() {
+        let input = parse_quote! {
+            #[can_message(id = 0x2000_0000, extended)]
+            struct Msg {
+                #[can(start = 0)]
+                on: bool,
+            }
+        };
+        assert_eq!("message ID must fit in 29 bits", expand_err(input));
+    }
+}
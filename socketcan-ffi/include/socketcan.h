@@ -0,0 +1,82 @@
+/*
+ * socketcan-ffi/include/socketcan.h
+ *
+ * C API for the socketcan crate.
+ *
+ * This file is part of the Rust 'socketcan-rs' library.
+ *
+ * Licensed under the MIT license:
+ *   <LICENSE or http://opensource.org/licenses/MIT>
+ * This file may not be copied, modified, or distributed except according
+ * to those terms.
+ *
+ * Frames are passed in the kernel's own layout, struct can_frame and
+ * struct canfd_frame, sized CAN_MTU or CANFD_MTU, as with a raw read() or
+ * write() on the socket. Functions that fail return -1, or NULL, and set
+ * errno.
+ *
+ *     socketcan_socket *sock = socketcan_open("can0", SOCKETCAN_FD);
+ *     struct canfd_frame frame;
+ *     ssize_t n = socketcan_read(sock, &frame, sizeof(frame), 1000);
+ *     if (n == CAN_MTU)
+ *         ... a classic frame ...
+ *     socketcan_close(sock);
+ */
+
+#ifndef SOCKETCAN_H
+#define SOCKETCAN_H
+
+#include <stddef.h>
+#include <sys/types.h>
+#include <linux/can.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Open the socket for classic frames only */
+#define SOCKETCAN_CLASSIC 0
+
+/* Open the socket for both classic and FD frames */
+#define SOCKETCAN_FD 1
+
+/* An open CAN socket */
+typedef struct socketcan_socket socketcan_socket;
+
+/* Gets the version of the library. */
+const char *socketcan_version(void);
+
+/* Opens a socket on the named interface, in SOCKETCAN_CLASSIC or
+ * SOCKETCAN_FD mode. Returns NULL on error. */
+socketcan_socket *socketcan_open(const char *ifname, int mode);
+
+/* Closes a socket. A NULL socket is ignored. */
+void socketcan_close(socketcan_socket *sock);
+
+/* Gets the file descriptor of the socket, for poll() or select(). */
+int socketcan_fd(const socketcan_socket *sock);
+
+/* Reads a frame, waiting up to timeout_ms, or forever if it's negative.
+ * Returns the size of the frame, CAN_MTU or CANFD_MTU, or -1 on error,
+ * with errno set to EAGAIN on a timeout. */
+ssize_t socketcan_read(const socketcan_socket *sock, void *frame, size_t size,
+                       int timeout_ms);
+
+/* Writes a frame of size CAN_MTU or CANFD_MTU. FD frames can only be
+ * written to an FD socket. Returns 0 on success, or -1 on error. */
+int socketcan_write(const socketcan_socket *sock, const void *frame, size_t size);
+
+/* Sets the receive filters, replacing any already set. With no filters,
+ * no frames are received. Returns 0 on success, or -1 on error. */
+int socketcan_set_filters(const socketcan_socket *sock,
+                          const struct can_filter *filters, size_t n);
+
+/* Sets whether the socket receives its own frames. Returns 0 on success,
+ * or -1 on error. */
+int socketcan_set_recv_own_msgs(const socketcan_socket *sock, int enabled);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* SOCKETCAN_H */
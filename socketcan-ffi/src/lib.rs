@@ -0,0 +1,379 @@
+// socketcan-ffi/src/lib.rs
+//
+// C API for the socketcan crate.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! A C API for the `socketcan` crate.
+//!
+//! This builds a shared and a static library that existing C and C++
+//! applications can link against, to move over to the Rust implementation
+//! one piece at a time. The API is declared in `include/socketcan.h`.
+//!
+//! Frames are passed in the kernel's own layout, `struct can_frame` and
+//! `struct canfd_frame` from `<linux/can.h>`, and sized the same way as a
+//! raw `read()` or `write()` on the socket: `CAN_MTU` bytes for a classic
+//! frame, or `CANFD_MTU` for an FD frame. Functions that fail return -1,
+//! or NULL, and set `errno`. A panic in the library never unwinds into
+//! the caller; the function fails with `errno` set to `EIO` instead.
+//!
+//! The API is stable: functions are only ever added to it.
+
+#![deny(missing_docs)]
+
+use libc::{c_char, c_int, c_void, can_filter, can_frame, canfd_frame, size_t, ssize_t};
+use socketcan::{
+    frame::AsPtr, CanAnyFrame, CanFdFrame, CanFdSocket, CanFilter, CanFrame, CanSocket, Socket,
+    SocketOptions,
+};
+use std::{ffi::CStr, io, mem::size_of, os::unix::io::AsRawFd, panic, ptr, time::Duration};
+
+/// The size of a classic frame.
+const CAN_MTU: usize = size_of::<can_frame>();
+
+/// The size of an FD frame.
+const CANFD_MTU: usize = size_of::<canfd_frame>();
+
+/// Open the socket for classic frames only.
+pub const SOCKETCAN_CLASSIC: c_int = 0;
+
+/// Open the socket for both classic and FD frames.
+pub const SOCKETCAN_FD: c_int = 1;
+
+// The version of the library, as a C string
+const VERSION: &str = concat!(env!("CARGO_PKG_VERSION"), "\0");
+
+/// An open CAN socket, opaque to C.
+#[derive(Debug)]
+pub enum SocketcanSocket {
+    /// A socket for classic frames
+    Classic(CanSocket),
+    /// A socket for classic and FD frames
+    Fd(CanFdSocket),
+}
+
+impl SocketcanSocket {
+    fn set_filters(&self, filters: &[CanFilter]) -> io::Result<()> {
+        match (self, filters.is_empty()) {
+            (SocketcanSocket::Classic(sock), true) => sock.set_filter_drop_all(),
+            (SocketcanSocket::Classic(sock), false) => sock.set_filters(filters),
+            (SocketcanSocket::Fd(sock), true) => sock.set_filter_drop_all(),
+            (SocketcanSocket::Fd(sock), false) => sock.set_filters(filters),
+        }
+    }
+
+    fn set_recv_own_msgs(&self, enabled: bool) -> io::Result<()> {
+        match self {
+            SocketcanSocket::Classic(sock) => sock.set_recv_own_msgs(enabled),
+            SocketcanSocket::Fd(sock) => sock.set_recv_own_msgs(enabled),
+        }
+    }
+
+    fn read_frame(&self, timeout: Option<Duration>) -> io::Result<CanAnyFrame> {
+        match (self, timeout) {
+            (SocketcanSocket::Classic(sock), None) => sock.read_frame().map(CanAnyFrame::from),
+            (SocketcanSocket::Classic(sock), Some(t)) => {
+                sock.read_frame_timeout(t).map(CanAnyFrame::from)
+            }
+            (SocketcanSocket::Fd(sock), None) => sock.read_frame(),
+            (SocketcanSocket::Fd(sock), Some(t)) => sock.read_frame_timeout(t),
+        }
+    }
+}
+
+// Sets errno, and returns the error value for the function
+fn fail<T>(err: io::Error, ret: T) -> T {
+    let code = match (err.raw_os_error(), err.kind()) {
+        (Some(code), _) => code,
+        (None, io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock) => libc::EAGAIN,
+        (None, _) => libc::EIO,
+    };
+    // SAFETY: errno is thread-local, and always valid to write
+    unsafe { *libc::__errno_location() = code };
+    ret
+}
+
+fn invalid<T>(ret: T) -> T {
+    fail(io::Error::from_raw_os_error(libc::EINVAL), ret)
+}
+
+// Runs the body of an API function, so that a panic can't unwind into the
+// C caller. A panic sets errno to EIO, and returns the error value.
+fn guard<T, F: FnOnce() -> T>(ret: T, f: F) -> T {
+    panic::catch_unwind(panic::AssertUnwindSafe(f))
+        .unwrap_or_else(|_| fail(io::Error::from_raw_os_error(libc::EIO), ret))
+}
+
+// ===== Library =====
+
+/// Gets the version of the library, as a static, NUL-terminated string.
+#[no_mangle]
+pub extern "C" fn socketcan_version() -> *const c_char {
+    guard(ptr::null(), || VERSION.as_ptr() as *const c_char)
+}
+
+// ===== Sockets =====
+
+/// Opens a socket on the named interface, such as "can0".
+///
+/// The mode is `SOCKETCAN_CLASSIC` or `SOCKETCAN_FD`. Returns NULL on
+/// error.
+///
+/// # Safety
+///
+/// The name must be a valid, NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn socketcan_open(
+    ifname: *const c_char,
+    mode: c_int,
+) -> *mut SocketcanSocket {
+    guard(ptr::null_mut(), || {
+        if ifname.is_null() {
+            return invalid(ptr::null_mut());
+        }
+        let Ok(ifname) = CStr::from_ptr(ifname).to_str() else {
+            return invalid(ptr::null_mut());
+        };
+
+        let sock = match mode {
+            SOCKETCAN_CLASSIC => CanSocket::open(ifname).map(SocketcanSocket::Classic),
+            SOCKETCAN_FD => CanFdSocket::open(ifname).map(SocketcanSocket::Fd),
+            _ => return invalid(ptr::null_mut()),
+        };
+        match sock {
+            Ok(sock) => Box::into_raw(Box::new(sock)),
+            Err(err) => fail(err, ptr::null_mut()),
+        }
+    })
+}
+
+/// Closes a socket. A NULL socket is ignored.
+///
+/// # Safety
+///
+/// The socket must have come from `socketcan_open()`, and not already
+/// been closed.
+#[no_mangle]
+pub unsafe extern "C" fn socketcan_close(sock: *mut SocketcanSocket) {
+    guard((), || {
+        if !sock.is_null() {
+            drop(Box::from_raw(sock));
+        }
+    })
+}
+
+/// Gets the file descriptor of the socket, to wait on it with `poll()` or
+/// `select()`. The socket still owns it.
+///
+/// # Safety
+///
+/// The socket must be open, or NULL.
+#[no_mangle]
+pub unsafe extern "C" fn socketcan_fd(sock: *const SocketcanSocket) -> c_int {
+    guard(-1, || match sock.as_ref() {
+        Some(SocketcanSocket::Classic(sock)) => sock.as_raw_fd(),
+        Some(SocketcanSocket::Fd(sock)) => sock.as_raw_fd(),
+        None => invalid(-1),
+    })
+}
+
+// ===== Frames =====
+
+/// Reads a frame into the buffer, waiting up to the timeout, in
+/// milliseconds, or forever if it's negative.
+///
+/// Returns the size of the frame read, `CAN_MTU` or `CANFD_MTU`, or -1 on
+/// error, with `errno` set to `EAGAIN` on a timeout. The buffer must hold
+/// `CANFD_MTU` bytes on an FD socket, or `CAN_MTU` on a classic one.
+///
+/// # Safety
+///
+/// The socket must be open, and the buffer valid for `size` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn socketcan_read(
+    sock: *const SocketcanSocket,
+    frame: *mut c_void,
+    size: size_t,
+    timeout_ms: c_int,
+) -> ssize_t {
+    guard(-1, || {
+        let Some(sock) = sock.as_ref() else {
+            return invalid(-1);
+        };
+        let mtu = match sock {
+            SocketcanSocket::Classic(_) => CAN_MTU,
+            SocketcanSocket::Fd(_) => CANFD_MTU,
+        };
+        if frame.is_null() || size < mtu {
+            return invalid(-1);
+        }
+
+        let timeout = u64::try_from(timeout_ms).ok().map(Duration::from_millis);
+        match sock.read_frame(timeout) {
+            Ok(rx) => {
+                let bytes = rx.as_bytes();
+                ptr::copy_nonoverlapping(bytes.as_ptr(), frame as *mut u8, bytes.len());
+                bytes.len() as ssize_t
+            }
+            Err(err) => fail(err, -1),
+        }
+    })
+}
+
+/// Writes a frame from the buffer, which holds a `struct can_frame` if
+/// the size is `CAN_MTU`, or a `struct canfd_frame` if it's `CANFD_MTU`.
+///
+/// FD frames can only be written to an FD socket. Returns 0 on success,
+/// or -1 on error.
+///
+/// # Safety
+///
+/// The socket must be open, and the buffer valid for `size` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn socketcan_write(
+    sock: *const SocketcanSocket,
+    frame: *const c_void,
+    size: size_t,
+) -> c_int {
+    guard(-1, || {
+        let Some(sock) = sock.as_ref() else {
+            return invalid(-1);
+        };
+        if frame.is_null() {
+            return invalid(-1);
+        }
+
+        let res = match (sock, size) {
+            (SocketcanSocket::Classic(sock), CAN_MTU) => {
+                let frame = CanFrame::from(ptr::read_unaligned(frame as *const can_frame));
+                sock.write_frame(&frame)
+            }
+            (SocketcanSocket::Fd(sock), CAN_MTU) => {
+                let frame = CanFrame::from(ptr::read_unaligned(frame as *const can_frame));
+                sock.write_frame(&frame)
+            }
+            (SocketcanSocket::Fd(sock), CANFD_MTU) => {
+                let frame = CanFdFrame::from(ptr::read_unaligned(frame as *const canfd_frame));
+                sock.write_frame(&frame)
+            }
+            _ => return invalid(-1),
+        };
+        match res {
+            Ok(()) => 0,
+            Err(err) => fail(err, -1),
+        }
+    })
+}
+
+// ===== Options =====
+
+/// Sets the receive filters of the socket, replacing any already set.
+///
+/// A frame is received if, for any of the filters,
+/// `(can_id & can_mask) == (filter.can_id & filter.can_mask)`. With no
+/// filters, no frames are received. Returns 0 on success, or -1 on error.
+///
+/// # Safety
+///
+/// The socket must be open, and the filters valid for `n` entries.
+#[no_mangle]
+pub unsafe extern "C" fn socketcan_set_filters(
+    sock: *const SocketcanSocket,
+    filters: *const can_filter,
+    n: size_t,
+) -> c_int {
+    guard(-1, || {
+        let Some(sock) = sock.as_ref() else {
+            return invalid(-1);
+        };
+        let filters: Vec<CanFilter> = match n {
+            0 => Vec::new(),
+            _ if filters.is_null() => return invalid(-1),
+            _ => std::slice::from_raw_parts(filters, n)
+                .iter()
+                .map(|f| CanFilter::new(f.can_id, f.can_mask))
+                .collect(),
+        };
+
+        match sock.set_filters(&filters) {
+            Ok(()) => 0,
+            Err(err) => fail(err, -1),
+        }
+    })
+}
+
+/// Sets whether the socket receives the frames it sent itself. Returns 0
+/// on success, or -1 on error.
+///
+/// # Safety
+///
+/// The socket must be open.
+#[no_mangle]
+pub unsafe extern "C" fn socketcan_set_recv_own_msgs(
+    sock: *const SocketcanSocket,
+    enabled: c_int,
+) -> c_int {
+    guard(-1, || {
+        let Some(sock) = sock.as_ref() else {
+            return invalid(-1);
+        };
+        match sock.set_recv_own_msgs(enabled != 0) {
+            Ok(()) => 0,
+            Err(err) => fail(err, -1),
+        }
+    })
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn errno() -> c_int {
+        io::Error::last_os_error().raw_os_error().unwrap()
+    }
+
+    #[test]
+    fn test_version() {
+        let version = unsafe { CStr::from_ptr(socketcan_version()) };
+        assert_eq!(env!("CARGO_PKG_VERSION"), version.to_str().unwrap());
+    }
+
+    #[test]
+    fn test_errors() {
+        unsafe {
+            let sock = socketcan_open(b"nosuchcan9\0".as_ptr() as *const c_char, SOCKETCAN_FD);
+            assert!(sock.is_null());
+            assert_eq!(libc::ENODEV, errno());
+
+            assert!(socketcan_open(ptr::null(), SOCKETCAN_CLASSIC).is_null());
+            assert_eq!(libc::EINVAL, errno());
+
+            let mut buf = [0u8; CANFD_MTU];
+            let n = socketcan_read(ptr::null(), buf.as_mut_ptr() as *mut c_void, buf.len(), 0);
+            assert_eq!(-1, n);
+            assert_eq!(libc::EINVAL, errno());
+
+            socketcan_close(ptr::null_mut());
+        }
+    }
+
+    #[test]
+    fn test_fail_errno() {
+        assert_eq!(-1, fail(io::ErrorKind::TimedOut.into(), -1));
+        assert_eq!(libc::EAGAIN, errno());
+        assert_eq!(-1, fail(io::ErrorKind::WouldBlock.into(), -1));
+        assert_eq!(libc::EAGAIN, errno());
+        assert_eq!(-1, fail(io::ErrorKind::InvalidData.into(), -1));
+        assert_eq!(libc::EIO, errno());
+
+        assert_eq!(-1, guard(-1, || panic!("in the library")));
+        assert_eq!(libc::EIO, errno());
+    }
+}
@@ -11,13 +11,25 @@
 
 //! SocketCAN address type.
 
-use libc::{sa_family_t, sockaddr, sockaddr_can, sockaddr_storage, socklen_t};
+use crate::{alias, CanId};
+use libc::{canid_t, sa_family_t, sockaddr, sockaddr_can, sockaddr_storage, socklen_t};
 use nix::net::if_::if_nametoindex;
 use socket2::SockAddr;
 use std::{fmt, io, mem, mem::size_of, os::raw::c_int};
 
 pub use libc::{AF_CAN, CAN_RAW, PF_CAN};
 
+/// The kernel `sockaddr_can` layout with the transport protocol IDs of
+/// the address union, as used by ISO-TP sockets.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct SockAddrCanTp {
+    can_family: sa_family_t,
+    can_ifindex: c_int,
+    rx_id: canid_t,
+    tx_id: canid_t,
+}
+
 /// CAN socket address.
 ///
 /// This is the address for use with CAN sockets. It is simply an addres to
@@ -42,11 +54,26 @@ impl CanAddr {
     }
 
     /// Try to create an address from an interface name.
+    ///
+    /// The name can be an alias from the [`alias`](crate::alias) registry.
     pub fn from_iface(ifname: &str) -> io::Result<Self> {
-        let ifindex = if_nametoindex(ifname)?;
+        let ifindex = if_nametoindex(&*alias::resolve(ifname))?;
         Ok(Self::new(ifindex))
     }
 
+    /// Sets the receive and transmit IDs of the address, for an ISO-TP
+    /// socket.
+    pub fn with_isotp_ids(mut self, rx_id: CanId, tx_id: CanId) -> Self {
+        // The TP member is a prefix of the address union, which is at least
+        // as large and aligned, so the cast stays within the struct.
+        let tp = (&mut self.0 as *mut sockaddr_can).cast::<SockAddrCanTp>();
+        unsafe {
+            (*tp).rx_id = rx_id.id_word();
+            (*tp).tx_id = tx_id.id_word();
+        }
+        self
+    }
+
     /// Gets the address of the structure as a `sockaddr_can` pointer.
     pub fn as_ptr(&self) -> *const sockaddr_can {
         &self.0
@@ -149,4 +176,17 @@ mod tests {
         assert_eq!(CanAddr::len() as socklen_t, len);
         assert_eq!(as_bytes(&addr), &as_bytes(&sock_addr)[0..len as usize]);
     }
+
+    #[test]
+    fn test_isotp_ids() {
+        let rx_id = crate::StandardId::new(0x7E8).unwrap();
+        let tx_id = crate::ExtendedId::new(0x18DA_F110).unwrap();
+        let addr = CanAddr::new(IDX).with_isotp_ids(rx_id.into(), tx_id.into());
+
+        let tp = unsafe { *addr.as_ptr().cast::<SockAddrCanTp>() };
+        assert_eq!(IDX as c_int, tp.can_ifindex);
+        assert_eq!(0x7E8, tp.rx_id);
+        assert_eq!(0x18DA_F110 | libc::CAN_EFF_FLAG, tp.tx_id);
+        assert!(size_of::<SockAddrCanTp>() <= CanAddr::len());
+    }
 }
@@ -0,0 +1,277 @@
+// socketcan/src/alias.rs
+//
+// Logical names for CAN interfaces.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! Logical names for CAN interfaces.
+//!
+//! The same application often runs on machines where the buses are wired
+//! to different interfaces: the powertrain bus might be `can0` on the
+//! bench, and `can2` in the vehicle. Rather than having the interface
+//! names in the code, the application can use logical names like
+//! "powertrain" and "body", and map them to the interfaces of each
+//! machine in its configuration.
+//!
+//! The process-wide registry is consulted wherever the crate takes an
+//! interface name, such as by [`Socket::open`](crate::Socket::open) and
+//! [`CanAddr::from_iface`](crate::CanAddr::from_iface). A name that isn't
+//! an alias is used as it is. The registry starts out with the aliases in
+//! the `SOCKETCAN_ALIASES` environment variable, if it's set, like:
+//!
+//! ```text
+//! SOCKETCAN_ALIASES="powertrain=can0,body=can1"
+//! ```
+//!
+//! If the variable can't be parsed, a warning is logged the first time the
+//! registry is used, and none of its aliases are loaded. Call
+//! [`AliasMap::from_env`] to get the error itself.
+//!
+//! More can be added from a configuration file, or in code:
+//!
+//! ```no_run
+//! use socketcan::{alias::{self, AliasMap}, CanSocket, Socket};
+//!
+//! let aliases = AliasMap::load("/etc/socketcan/aliases").unwrap();
+//! alias::extend(aliases);
+//! alias::register("diag", "vcan0");
+//!
+//! let sock = CanSocket::open("powertrain").unwrap();
+//! ```
+//!
+//! The file has one `name = interface` pair per line. Blank lines and
+//! everything after a `#` are ignored.
+
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    env, fs, io,
+    path::Path,
+    str::FromStr,
+    sync::{OnceLock, RwLock},
+};
+use thiserror::Error;
+
+/// The environment variable with the initial aliases of the registry.
+pub const ALIAS_ENV: &str = "SOCKETCAN_ALIASES";
+
+/// An error loading aliases.
+#[derive(Error, Debug)]
+pub enum AliasError {
+    /// An entry isn't a `name=interface` pair
+    #[error("Invalid alias: '{0}'")]
+    InvalidAlias(String),
+    /// The aliases couldn't be read
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+// ===== AliasMap =====
+
+/// A map of logical bus names to interface names.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct AliasMap(BTreeMap<String, String>);
+
+impl AliasMap {
+    /// Creates an empty map.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an alias, as a builder.
+    pub fn with_alias(mut self, name: &str, iface: &str) -> Self {
+        self.insert(name, iface);
+        self
+    }
+
+    /// Adds an alias, returning the interface it was mapped to before, if
+    /// any.
+    pub fn insert(&mut self, name: &str, iface: &str) -> Option<String> {
+        self.0.insert(name.into(), iface.into())
+    }
+
+    /// Removes an alias, returning the interface it was mapped to.
+    pub fn remove(&mut self, name: &str) -> Option<String> {
+        self.0.remove(name)
+    }
+
+    /// Gets the interface for an alias.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0.get(name).map(String::as_str)
+    }
+
+    /// Gets the interface for a name: the one it's an alias for, or the
+    /// name itself if it isn't one.
+    pub fn resolve<'a>(&'a self, name: &'a str) -> &'a str {
+        self.get(name).unwrap_or(name)
+    }
+
+    /// Gets the number of aliases.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Determines if there are no aliases.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Gets an iterator over the aliases, as `(name, interface)` pairs,
+    /// ordered by name.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    /// Loads the aliases from the [`ALIAS_ENV`] environment variable. If
+    /// it isn't set, the map is empty.
+    pub fn from_env() -> Result<Self, AliasError> {
+        match env::var(ALIAS_ENV) {
+            Ok(s) => s.parse(),
+            Err(_) => Ok(Self::new()),
+        }
+    }
+
+    /// Loads the aliases from a configuration file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, AliasError> {
+        fs::read_to_string(path)?.parse()
+    }
+}
+
+impl FromStr for AliasMap {
+    type Err = AliasError;
+
+    /// Parses `name=interface` pairs, separated by commas or newlines.
+    /// Blank entries and comments, from a `#` to the end of the line, are
+    /// ignored.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut map = Self::new();
+        let entries = s
+            .lines()
+            .flat_map(|line| line.split('#').next().unwrap_or("").split(','))
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty());
+
+        for entry in entries {
+            match entry.split_once('=') {
+                Some((name, iface)) if !name.trim().is_empty() && !iface.trim().is_empty() => {
+                    map.insert(name.trim(), iface.trim());
+                }
+                _ => return Err(AliasError::InvalidAlias(entry.into())),
+            }
+        }
+        Ok(map)
+    }
+}
+
+impl Extend<(String, String)> for AliasMap {
+    fn extend<I: IntoIterator<Item = (String, String)>>(&mut self, iter: I) {
+        self.0.extend(iter);
+    }
+}
+
+impl IntoIterator for AliasMap {
+    type Item = (String, String);
+    type IntoIter = std::collections::btree_map::IntoIter<String, String>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+// ===== Registry =====
+
+// The process-wide registry, loaded from the environment on first use.
+// If the environment has an invalid alias, a warning is logged and the
+// registry starts out empty.
+fn registry() -> &'static RwLock<AliasMap> {
+    static REGISTRY: OnceLock<RwLock<AliasMap>> = OnceLock::new();
+    REGISTRY.get_or_init(|| {
+        let aliases = AliasMap::from_env().unwrap_or_else(|err| {
+            log::warn!("Ignoring {}: {}", ALIAS_ENV, err);
+            AliasMap::new()
+        });
+        RwLock::new(aliases)
+    })
+}
+
+/// Adds an alias to the process-wide registry.
+pub fn register(name: &str, iface: &str) {
+    registry().write().unwrap().insert(name, iface);
+}
+
+/// Removes an alias from the process-wide registry, returning the
+/// interface it was mapped to.
+pub fn unregister(name: &str) -> Option<String> {
+    registry().write().unwrap().remove(name)
+}
+
+/// Adds all the aliases in the map to the process-wide registry,
+/// replacing any with the same names.
+pub fn extend(aliases: AliasMap) {
+    registry().write().unwrap().extend(aliases);
+}
+
+/// Gets a copy of the aliases in the process-wide registry.
+pub fn aliases() -> AliasMap {
+    registry().read().unwrap().clone()
+}
+
+/// Gets the interface for a name from the process-wide registry: the one
+/// it's an alias for, or the name itself if it isn't one.
+pub fn resolve(name: &str) -> Cow<'_, str> {
+    match registry().read().unwrap().get(name) {
+        Some(iface) => Cow::Owned(iface.into()),
+        None => Cow::Borrowed(name),
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CanAddr;
+
+    #[test]
+    fn test_parse() {
+        let map: AliasMap = "
+            # The buses of the bench
+            powertrain = can0, body=can1
+            diag=vcan0  # for testing
+        "
+        .parse()
+        .unwrap();
+
+        assert_eq!(3, map.len());
+        assert_eq!(Some("can1"), map.get("body"));
+        assert_eq!("vcan0", map.resolve("diag"));
+        assert_eq!("can3", map.resolve("can3"));
+
+        assert!(matches!(
+            "powertrain".parse::<AliasMap>(),
+            Err(AliasError::InvalidAlias(_))
+        ));
+        assert!("=can0".parse::<AliasMap>().is_err());
+        assert!("".parse::<AliasMap>().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_registry() {
+        register("alias-test-loopback", "lo");
+        assert_eq!("lo", resolve("alias-test-loopback"));
+        assert_eq!("can9", resolve("can9"));
+
+        // Names are resolved wherever an interface is looked up
+        let lo = CanAddr::from_iface("lo").unwrap();
+        let aliased = CanAddr::from_iface("alias-test-loopback").unwrap();
+        assert_eq!(lo.as_bytes(), aliased.as_bytes());
+
+        assert_eq!(Some("lo".into()), unregister("alias-test-loopback"));
+        assert!(CanAddr::from_iface("alias-test-loopback").is_err());
+    }
+}
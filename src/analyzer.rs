@@ -0,0 +1,499 @@
+// socketcan/src/analyzer.rs
+//
+// Traffic analysis for CAN bus frames.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! Traffic analysis of CAN frames.
+//!
+//! The [`TrafficAnalyzer`] consumes timestamped frames, either live from a
+//! socket or from a capture log, and keeps per-ID statistics, such as the
+//! frame count, rate, cycle time, and how often the payload changed. This
+//! is the same information that `cansniffer` shows, but exposed as data
+//! that an application can use.
+//!
+//! The expected period of cyclic IDs can also be declared with
+//! [`TrafficAnalyzer::declare_period`]. The analyzer then tracks the
+//! jitter of each cycle against that period, and detects missed cycles, so
+//! that the schedule adherence of an ECU can be validated from a capture.
+//!
+//! ```no_run
+//! use socketcan::{analyzer::TrafficAnalyzer, CanFdSocket, Socket};
+//! use std::time::SystemTime;
+//!
+//! let sock = CanFdSocket::open("vcan0").unwrap();
+//! let mut analyzer = TrafficAnalyzer::new();
+//!
+//! for _ in 0..1000 {
+//!     let frame = sock.read_frame().unwrap();
+//!     let t_us = SystemTime::now()
+//!         .duration_since(SystemTime::UNIX_EPOCH)
+//!         .unwrap()
+//!         .as_micros() as u64;
+//!     analyzer.update(t_us, &frame);
+//! }
+//!
+//! for stats in analyzer.report().ids {
+//!     println!("{:?}: {} frames, {:.1} Hz", stats.id, stats.count, stats.rate());
+//! }
+//! ```
+
+use crate::{capture::Record, frame::CANFD_MAX_DLEN, CanAnyFrame, EmbeddedFrame, Frame, Id};
+use std::collections::BTreeMap;
+
+// ===== JitterHistogram =====
+
+/// A histogram of the deviation of cycle times from a declared period.
+///
+/// The bins are centered on multiples of the bin width, so the middle bin
+/// counts the cycles that were on time (within half a bin). Deviations
+/// past either end are counted in the outermost bins.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JitterHistogram {
+    bin_us: u64,
+    counts: Vec<u64>,
+}
+
+impl JitterHistogram {
+    /// Creates a histogram with `2 * half_bins + 1` bins, each `bin_us`
+    /// wide.
+    pub fn new(bin_us: u64, half_bins: usize) -> Self {
+        Self {
+            bin_us: bin_us.max(1),
+            counts: vec![0; 2 * half_bins + 1],
+        }
+    }
+
+    /// The width of each bin, in microseconds
+    pub fn bin_us(&self) -> u64 {
+        self.bin_us
+    }
+
+    /// Adds a deviation, in microseconds, to the histogram.
+    pub fn record(&mut self, dev_us: i64) {
+        let half = (self.counts.len() / 2) as i64;
+        let idx = (dev_us as f64 / self.bin_us as f64).round() as i64;
+        self.counts[(idx.clamp(-half, half) + half) as usize] += 1;
+    }
+
+    /// Gets the number of samples that fell into the bin that contains
+    /// the deviation.
+    pub fn count(&self, dev_us: i64) -> u64 {
+        let half = (self.counts.len() / 2) as i64;
+        let idx = (dev_us as f64 / self.bin_us as f64).round() as i64;
+        self.counts[(idx.clamp(-half, half) + half) as usize]
+    }
+
+    /// The total number of samples in the histogram
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Iterates over the bins as `(center_us, count)` pairs, from the most
+    /// early to the most late.
+    pub fn bins(&self) -> impl Iterator<Item = (i64, u64)> + '_ {
+        let half = (self.counts.len() / 2) as i64;
+        let bin_us = self.bin_us as i64;
+        self.counts
+            .iter()
+            .enumerate()
+            .map(move |(i, n)| ((i as i64 - half) * bin_us, *n))
+    }
+}
+
+// ===== ScheduleStats =====
+
+/// The number of bins on each side of the center of a jitter histogram.
+const JITTER_HALF_BINS: usize = 10;
+
+/// Statistics on how well an ID keeps to its declared period.
+#[derive(Debug, Clone)]
+pub struct ScheduleStats {
+    /// The declared period, in microseconds
+    pub period_us: u64,
+    /// The number of cycles in which no frame was seen
+    pub missed_cycles: u64,
+    /// The largest deviation from the period, in microseconds
+    pub max_jitter_us: u64,
+    /// The distribution of the deviations from the period.
+    ///
+    /// The bins are 1/20 of the period wide, covering +/-50% of the period.
+    pub histogram: JitterHistogram,
+}
+
+impl ScheduleStats {
+    /// Creates the schedule statistics for a declared period.
+    fn new(period_us: u64) -> Self {
+        let period_us = period_us.max(1);
+        let bin_us = period_us / (2 * JITTER_HALF_BINS as u64);
+        Self {
+            period_us,
+            missed_cycles: 0,
+            max_jitter_us: 0,
+            histogram: JitterHistogram::new(bin_us, JITTER_HALF_BINS),
+        }
+    }
+
+    /// Updates the statistics with the time between two frames.
+    ///
+    /// A gap of about `n` periods counts as `n-1` missed cycles, and the
+    /// jitter is measured against the nearest multiple of the period.
+    fn update(&mut self, cycle_us: u64) {
+        let n = ((cycle_us + self.period_us / 2) / self.period_us).max(1);
+        self.missed_cycles += n - 1;
+
+        let dev_us = cycle_us as i64 - (n * self.period_us) as i64;
+        self.max_jitter_us = self.max_jitter_us.max(dev_us.unsigned_abs());
+        self.histogram.record(dev_us);
+    }
+}
+
+// ===== IdStats =====
+
+/// The traffic statistics for a single CAN ID.
+#[derive(Debug, Clone)]
+pub struct IdStats {
+    /// The CAN ID
+    pub id: Id,
+    /// The number of frames received with this ID
+    pub count: u64,
+    /// The timestamp of the first frame, in microseconds
+    pub first_us: u64,
+    /// The timestamp of the most recent frame, in microseconds
+    pub last_us: u64,
+    /// The shortest time between consecutive frames, in microseconds
+    pub min_cycle_us: Option<u64>,
+    /// The longest time between consecutive frames, in microseconds
+    pub max_cycle_us: Option<u64>,
+    /// The number of frames in which the payload differed from the
+    /// previous frame with the same ID
+    pub payload_changes: u64,
+    /// The number of times each data byte changed between consecutive
+    /// frames with this ID
+    pub byte_changes: Vec<u64>,
+    /// The schedule statistics, if a period was declared for the ID
+    pub schedule: Option<ScheduleStats>,
+    // The sum of all cycle times, for the mean
+    sum_cycle_us: u64,
+    // The most recent payload
+    last_data: [u8; CANFD_MAX_DLEN],
+    last_len: usize,
+}
+
+impl IdStats {
+    /// Creates the statistics for an ID from its first frame.
+    fn new(id: Id, t_us: u64, data: &[u8]) -> Self {
+        let mut last_data = [0u8; CANFD_MAX_DLEN];
+        last_data[..data.len()].copy_from_slice(data);
+        Self {
+            id,
+            count: 1,
+            first_us: t_us,
+            last_us: t_us,
+            min_cycle_us: None,
+            max_cycle_us: None,
+            payload_changes: 0,
+            byte_changes: vec![0; data.len()],
+            schedule: None,
+            sum_cycle_us: 0,
+            last_data,
+            last_len: data.len(),
+        }
+    }
+
+    /// Updates the statistics with a subsequent frame.
+    fn update(&mut self, t_us: u64, data: &[u8]) {
+        let cycle_us = t_us.saturating_sub(self.last_us);
+        self.min_cycle_us = Some(self.min_cycle_us.map_or(cycle_us, |n| n.min(cycle_us)));
+        self.max_cycle_us = Some(self.max_cycle_us.map_or(cycle_us, |n| n.max(cycle_us)));
+        self.sum_cycle_us += cycle_us;
+
+        if let Some(schedule) = self.schedule.as_mut() {
+            schedule.update(cycle_us);
+        }
+
+        if data != self.last_data() {
+            self.payload_changes += 1;
+        }
+
+        if self.byte_changes.len() < data.len() {
+            self.byte_changes.resize(data.len(), 0);
+        }
+        for (i, b) in data.iter().enumerate() {
+            if i >= self.last_len || *b != self.last_data[i] {
+                self.byte_changes[i] += 1;
+            }
+        }
+
+        self.count += 1;
+        self.last_us = t_us;
+        self.last_data[..data.len()].copy_from_slice(data);
+        self.last_len = data.len();
+    }
+
+    /// Gets the most recent payload for the ID.
+    pub fn last_data(&self) -> &[u8] {
+        &self.last_data[..self.last_len]
+    }
+
+    /// The mean time between consecutive frames, in microseconds.
+    ///
+    /// This is `None` until at least two frames have been received.
+    pub fn mean_cycle_us(&self) -> Option<f64> {
+        match self.count {
+            0 | 1 => None,
+            n => Some(self.sum_cycle_us as f64 / (n - 1) as f64),
+        }
+    }
+
+    /// The average rate of the frames, in frames per second.
+    ///
+    /// This is the rate over the time between the first and last frames,
+    /// and is zero until at least two frames have been received.
+    pub fn rate(&self) -> f64 {
+        match self.mean_cycle_us() {
+            Some(cycle_us) if cycle_us > 0.0 => 1.0e6 / cycle_us,
+            _ => 0.0,
+        }
+    }
+}
+
+// ===== TrafficReport =====
+
+/// A snapshot of the traffic statistics for all IDs on the bus.
+#[derive(Debug, Clone, Default)]
+pub struct TrafficReport {
+    /// The statistics for each ID, in order of bus priority
+    pub ids: Vec<IdStats>,
+    /// The total number of frames, including error frames
+    pub total_frames: u64,
+    /// The number of error frames
+    pub error_frames: u64,
+    /// The declared IDs that were never seen
+    pub missing_ids: Vec<Id>,
+    /// The time between the first and last frames, in microseconds
+    pub duration_us: u64,
+}
+
+impl TrafficReport {
+    /// Gets the statistics for a specific ID
+    pub fn get(&self, id: impl Into<Id>) -> Option<&IdStats> {
+        let id = id.into();
+        self.ids.iter().find(|stats| stats.id == id)
+    }
+
+    /// The total number of missed cycles over all of the declared IDs.
+    pub fn missed_cycles(&self) -> u64 {
+        self.ids
+            .iter()
+            .filter_map(|stats| stats.schedule.as_ref())
+            .map(|sched| sched.missed_cycles)
+            .sum()
+    }
+}
+
+// ===== TrafficAnalyzer =====
+
+/// An analyzer that keeps per-ID statistics about bus traffic.
+#[derive(Debug, Clone, Default)]
+pub struct TrafficAnalyzer {
+    ids: BTreeMap<Id, IdStats>,
+    periods: BTreeMap<Id, u64>,
+    total_frames: u64,
+    error_frames: u64,
+    // The earliest and latest timestamps, which aren't necessarily those
+    // of the first and last frames in merged or replayed logs
+    first_us: Option<u64>,
+    last_us: u64,
+}
+
+impl TrafficAnalyzer {
+    /// Creates a new, empty, analyzer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Declares the expected period of a cyclic ID, in microseconds.
+    ///
+    /// Subsequent cycles of the ID are checked against the period for
+    /// jitter and missed cycles.
+    pub fn declare_period(&mut self, id: impl Into<Id>, period_us: u64) {
+        let id = id.into();
+        self.periods.insert(id, period_us);
+        if let Some(stats) = self.ids.get_mut(&id) {
+            stats.schedule = Some(ScheduleStats::new(period_us));
+        }
+    }
+
+    /// Updates the statistics with a timestamped frame.
+    ///
+    /// The frames should be given in time order.
+    pub fn update(&mut self, t_us: u64, frame: &CanAnyFrame) {
+        self.total_frames += 1;
+        self.first_us = Some(self.first_us.map_or(t_us, |t| t.min(t_us)));
+        self.last_us = self.last_us.max(t_us);
+
+        if frame.is_error_frame() {
+            self.error_frames += 1;
+            return;
+        }
+
+        let (id, data) = (frame.id(), frame.data());
+        self.ids
+            .entry(id)
+            .and_modify(|stats| stats.update(t_us, data))
+            .or_insert_with(|| {
+                let mut stats = IdStats::new(id, t_us, data);
+                stats.schedule = self.periods.get(&id).map(|p| ScheduleStats::new(*p));
+                stats
+            });
+    }
+
+    /// Updates the statistics with a record from a capture log.
+    pub fn update_record(&mut self, rec: &Record) {
+        self.update(rec.t_us, &rec.frame)
+    }
+
+    /// Gets the current statistics for a specific ID.
+    pub fn stats(&self, id: impl Into<Id>) -> Option<&IdStats> {
+        self.ids.get(&id.into())
+    }
+
+    /// Gets a snapshot of the statistics for all of the IDs.
+    pub fn report(&self) -> TrafficReport {
+        TrafficReport {
+            ids: self.ids.values().cloned().collect(),
+            total_frames: self.total_frames,
+            error_frames: self.error_frames,
+            missing_ids: self
+                .periods
+                .keys()
+                .filter(|id| !self.ids.contains_key(id))
+                .copied()
+                .collect(),
+            duration_us: self.first_us.map_or(0, |t| self.last_us - t),
+        }
+    }
+
+    /// Clears all the statistics.
+    ///
+    /// The declared periods are kept.
+    pub fn clear(&mut self) {
+        let periods = std::mem::take(&mut self.periods);
+        *self = Self {
+            periods,
+            ..Self::default()
+        };
+    }
+}
+
+impl Extend<Record> for TrafficAnalyzer {
+    fn extend<T: IntoIterator<Item = Record>>(&mut self, iter: T) {
+        for rec in iter {
+            self.update_record(&rec);
+        }
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CanError, CanErrorFrame, CanFrame, Frame, StandardId};
+
+    fn frame(id: u32, data: &[u8]) -> CanAnyFrame {
+        CanFrame::from_raw_id(id, data).unwrap().into()
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut analyzer = TrafficAnalyzer::new();
+
+        analyzer.update(0, &frame(0x100, &[1, 2]));
+        analyzer.update(1000, &frame(0x100, &[1, 2]));
+        analyzer.update(3000, &frame(0x100, &[1, 3]));
+        analyzer.update(3500, &frame(0x200, &[0]));
+        analyzer.update(
+            4000,
+            &CanAnyFrame::Error(CanErrorFrame::from(CanError::BusOff)),
+        );
+
+        let id = StandardId::new(0x100).unwrap();
+        let stats = analyzer.stats(id).unwrap();
+        assert_eq!(3, stats.count);
+        assert_eq!(Some(1000), stats.min_cycle_us);
+        assert_eq!(Some(2000), stats.max_cycle_us);
+        assert_eq!(Some(1500.0), stats.mean_cycle_us());
+        assert_eq!(1, stats.payload_changes);
+        assert_eq!(vec![0, 1], stats.byte_changes);
+        assert_eq!(&[1, 3], stats.last_data());
+
+        let report = analyzer.report();
+        assert_eq!(2, report.ids.len());
+        assert_eq!(5, report.total_frames);
+        assert_eq!(1, report.error_frames);
+        assert_eq!(4000, report.duration_us);
+        assert_eq!(
+            1,
+            report.get(StandardId::new(0x200).unwrap()).unwrap().count
+        );
+        assert_eq!(
+            0.0,
+            report.get(StandardId::new(0x200).unwrap()).unwrap().rate()
+        );
+    }
+
+    #[test]
+    fn test_out_of_order() {
+        let mut analyzer = TrafficAnalyzer::new();
+
+        // As from a merge of two logs that weren't sorted
+        analyzer.update(5000, &frame(0x100, &[1]));
+        analyzer.update(2000, &frame(0x200, &[2]));
+        analyzer.update(1000, &frame(0x100, &[3]));
+        analyzer.update(3000, &frame(0x200, &[4]));
+
+        let report = analyzer.report();
+        assert_eq!(4000, report.duration_us);
+        assert_eq!(4, report.total_frames);
+
+        let stats = analyzer.stats(StandardId::new(0x100).unwrap()).unwrap();
+        assert_eq!(2, stats.count);
+        assert_eq!(Some(0), stats.min_cycle_us);
+    }
+
+    #[test]
+    fn test_schedule() {
+        let id = StandardId::new(0x100).unwrap();
+        let mut analyzer = TrafficAnalyzer::new();
+        analyzer.declare_period(id, 10_000);
+        analyzer.declare_period(StandardId::new(0x300).unwrap(), 100_000);
+
+        // On time, late, early, then two cycles missed, slightly late
+        for t_us in [0, 10_000, 20_500, 29_800, 60_000] {
+            analyzer.update(t_us, &frame(0x100, &[]));
+        }
+
+        let sched = analyzer.stats(id).unwrap().schedule.as_ref().unwrap();
+        assert_eq!(2, sched.missed_cycles);
+        assert_eq!(700, sched.max_jitter_us);
+        assert_eq!(4, sched.histogram.total());
+        assert_eq!(2, sched.histogram.count(0));
+        assert_eq!(1, sched.histogram.count(500));
+        assert_eq!(1, sched.histogram.count(-700));
+        assert_eq!(Some((-5000, 0)), sched.histogram.bins().next());
+
+        let report = analyzer.report();
+        assert_eq!(2, report.missed_cycles());
+        assert_eq!(
+            vec![Id::from(StandardId::new(0x300).unwrap())],
+            report.missing_ids
+        );
+    }
+}
@@ -11,7 +11,8 @@
 
 //! Bindings to async-io for CANbus 2.0 and FD sockets using SocketCAN on Linux.
 
-use crate::{frame::AsPtr, CanAnyFrame, CanFrame, Socket, SocketOptions};
+use crate::{frame::AsPtr, CanAnyFrame, CanEvent, CanFrame, Socket, SocketOptions};
+use futures::stream::{self, Stream};
 use std::{
     io,
     os::unix::io::{AsRawFd, RawFd},
@@ -44,7 +45,7 @@ impl CanSocket {
     /// Writes a frame to the socket asynchronously.
     pub async fn write_frame<F>(&self, frame: &F) -> io::Result<()>
     where
-        F: Into<CanFrame> + AsPtr,
+        F: Into<CanFrame> + AsPtr + Clone,
     {
         self.0.write_with(|fd| fd.write_frame(frame)).await
     }
@@ -53,6 +54,24 @@ impl CanSocket {
     pub async fn read_frame(&self) -> io::Result<CanFrame> {
         self.0.read_with(|fd| fd.read_frame()).await
     }
+
+    /// Reads a frame from the socket asynchronously, with any error frame
+    /// split out as a typed event.
+    pub async fn read_event(&self) -> io::Result<CanEvent<CanFrame>> {
+        self.read_frame().await.map(CanEvent::from)
+    }
+
+    /// Gets a stream of the frames read from the socket, with error frames
+    /// split out as typed events.
+    ///
+    /// Error frames are only received if the error filter of the socket
+    /// is enabled, such as with `set_error_filter_accept_all()`.
+    pub fn events(&self) -> impl Stream<Item = io::Result<CanEvent<CanFrame>>> + '_ {
+        stream::unfold(
+            self,
+            |sock| async move { Some((sock.read_event().await, sock)) },
+        )
+    }
 }
 
 impl SocketOptions for CanSocket {}
@@ -89,7 +108,7 @@ impl CanFdSocket {
     /// Writes a frame to the socket asynchronously.
     pub async fn write_frame<F>(&self, frame: &F) -> io::Result<()>
     where
-        F: Into<CanAnyFrame> + AsPtr,
+        F: Into<CanAnyFrame> + AsPtr + Clone,
     {
         self.0.write_with(|fd| fd.write_frame(frame)).await
     }
@@ -98,6 +117,24 @@ impl CanFdSocket {
     pub async fn read_frame(&self) -> io::Result<CanAnyFrame> {
         self.0.read_with(|fd| fd.read_frame()).await
     }
+
+    /// Reads a frame from the socket asynchronously, with any error frame
+    /// split out as a typed event.
+    pub async fn read_event(&self) -> io::Result<CanEvent<CanAnyFrame>> {
+        self.read_frame().await.map(CanEvent::from)
+    }
+
+    /// Gets a stream of the frames read from the socket, with error frames
+    /// split out as typed events.
+    ///
+    /// Error frames are only received if the error filter of the socket
+    /// is enabled, such as with `set_error_filter_accept_all()`.
+    pub fn events(&self) -> impl Stream<Item = io::Result<CanEvent<CanAnyFrame>>> + '_ {
+        stream::unfold(
+            self,
+            |sock| async move { Some((sock.read_event().await, sock)) },
+        )
+    }
 }
 
 impl SocketOptions for CanFdSocket {}
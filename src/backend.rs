@@ -0,0 +1,192 @@
+// socketcan/src/backend.rs
+//
+// Backend abstraction for transmitting and receiving frames.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! Backend abstraction for transmitting and receiving frames.
+//!
+//! The higher layers of the library, like the
+//! [`Dispatcher`](crate::dispatch::Dispatcher), the
+//! [`Scheduler`](crate::scheduler::Scheduler), the
+//! [`Watchdog`](crate::watchdog::Watchdog), the CANopen and XCP clients,
+//! the [`TxQueue`](crate::txqueue::TxQueue) and the
+//! [`BusScanner`](crate::scanner::BusScanner), only need to send or
+//! receive frames. They're written against the [`CanTransmitter`] and
+//! [`CanReceiver`] traits rather than the full [`Socket`], so that they can
+//! be driven by a mock in tests, or by a backend other than SocketCAN.
+//!
+//! Every RAW socket type implements both traits. With the `tokio` feature,
+//! the [`AsyncCanTransmitter`] and [`AsyncCanReceiver`] traits do the same
+//! for the async sockets.
+//!
+//! ```no_run
+//! use socketcan::{
+//!     backend::{CanReceiver, CanTransmitter},
+//!     CanFrame, CanSocket, IoResult, Socket,
+//! };
+//!
+//! // Works with a socket, or with anything else that can pass frames
+//! fn echo<B>(bus: &B) -> IoResult<()>
+//! where
+//!     B: CanReceiver<Frame = CanFrame> + CanTransmitter<CanFrame>,
+//! {
+//!     let frame = bus.receive()?;
+//!     bus.transmit(&frame)
+//! }
+//!
+//! let sock = CanSocket::open("can0").unwrap();
+//! echo(&sock).unwrap();
+//! ```
+
+use crate::{frame::AsPtr, IoResult, Socket};
+use std::time::Duration;
+
+#[cfg(feature = "tokio")]
+use std::task::{Context, Poll};
+
+/// Something that can send frames of type `F` onto a bus.
+pub trait CanTransmitter<F> {
+    /// Sends a frame, blocking until it's queued for transmission.
+    fn transmit(&self, frame: &F) -> IoResult<()>;
+
+    /// Makes a single attempt to send a frame, which fails with an error
+    /// that [`should_retry`](crate::ShouldRetry::should_retry) if the
+    /// backend can't take it right now, such as on a non-blocking socket
+    /// with a full queue.
+    ///
+    /// By default, this is the same as [`transmit`](Self::transmit).
+    fn try_transmit(&self, frame: &F) -> IoResult<()> {
+        self.transmit(frame)
+    }
+}
+
+/// Something that can receive frames from a bus.
+pub trait CanReceiver {
+    /// The type of the frames received.
+    type Frame;
+
+    /// Receives the next frame, blocking until one arrives.
+    fn receive(&self) -> IoResult<Self::Frame>;
+
+    /// Receives the next frame, waiting at most for the timeout.
+    ///
+    /// Returns an error of kind `TimedOut` if no frame arrives in time.
+    fn receive_timeout(&self, timeout: Duration) -> IoResult<Self::Frame>;
+}
+
+impl<S, F> CanTransmitter<F> for S
+where
+    S: Socket,
+    F: Into<S::FrameType> + AsPtr + Clone,
+{
+    fn transmit(&self, frame: &F) -> IoResult<()> {
+        self.write_frame_insist(frame)
+    }
+
+    fn try_transmit(&self, frame: &F) -> IoResult<()> {
+        self.write_frame(frame)
+    }
+}
+
+impl<S: Socket> CanReceiver for S {
+    type Frame = S::FrameType;
+
+    fn receive(&self) -> IoResult<Self::Frame> {
+        self.read_frame()
+    }
+
+    fn receive_timeout(&self, timeout: Duration) -> IoResult<Self::Frame> {
+        self.read_frame_timeout(timeout)
+    }
+}
+
+// ===== Async =====
+
+/// Something that can send frames of type `F` onto a bus asynchronously.
+#[cfg(feature = "tokio")]
+pub trait AsyncCanTransmitter<F> {
+    /// Attempts to send a frame, registering the task for wakeup if the
+    /// backend isn't ready for it.
+    fn poll_transmit(&self, cx: &mut Context<'_>, frame: &F) -> Poll<IoResult<()>>;
+}
+
+/// Something that can receive frames from a bus asynchronously.
+#[cfg(feature = "tokio")]
+pub trait AsyncCanReceiver {
+    /// The type of the frames received.
+    type Frame;
+
+    /// Attempts to receive the next frame, registering the task for
+    /// wakeup if none is available.
+    fn poll_receive(&self, cx: &mut Context<'_>) -> Poll<IoResult<Self::Frame>>;
+}
+
+/// Sends a frame on an async transmitter.
+#[cfg(feature = "tokio")]
+pub async fn transmit<T, F>(tx: &T, frame: &F) -> IoResult<()>
+where
+    T: AsyncCanTransmitter<F> + ?Sized,
+{
+    futures::future::poll_fn(|cx| tx.poll_transmit(cx, frame)).await
+}
+
+/// Receives the next frame from an async receiver.
+#[cfg(feature = "tokio")]
+pub async fn receive<R>(rx: &R) -> IoResult<R::Frame>
+where
+    R: AsyncCanReceiver + ?Sized,
+{
+    futures::future::poll_fn(|cx| rx.poll_receive(cx)).await
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{dispatch::Dispatcher, CanFrame, EmbeddedFrame, Frame, IoErrorKind};
+    use std::{cell::RefCell, collections::VecDeque};
+
+    // A receiver that plays back a list of frames
+    struct Playback(RefCell<VecDeque<CanFrame>>);
+
+    impl CanReceiver for Playback {
+        type Frame = CanFrame;
+
+        fn receive(&self) -> IoResult<CanFrame> {
+            self.receive_timeout(Duration::ZERO)
+        }
+
+        fn receive_timeout(&self, _timeout: Duration) -> IoResult<CanFrame> {
+            self.0
+                .borrow_mut()
+                .pop_front()
+                .ok_or_else(|| IoErrorKind::TimedOut.into())
+        }
+    }
+
+    #[test]
+    fn test_mock_receiver() {
+        let frames = (0..3)
+            .map(|i| CanFrame::from_raw_id(0x100 + i, &[]).unwrap())
+            .collect();
+        let rx = Playback(RefCell::new(frames));
+
+        let mut ids = vec![];
+        let mut disp = Dispatcher::<CanFrame>::new();
+        disp.on_default(|frame| ids.push(frame.id()));
+        for _ in 0..3 {
+            disp.dispatch_next(&rx).unwrap();
+        }
+        let err = disp.dispatch_next(&rx).unwrap_err();
+        assert_eq!(IoErrorKind::TimedOut, err.kind());
+        drop(disp);
+        assert_eq!(3, ids.len());
+    }
+}
@@ -0,0 +1,330 @@
+// socketcan/src/batch.rs
+//
+// A columnar (struct-of-arrays) container for batches of frames.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! A columnar container for batches of frames.
+//!
+//! A `Vec` of frames stores each one as a whole, padded out to the size of
+//! the largest, so a scan over just the IDs or the timestamps of a large
+//! log drags every payload through the cache along with them. A
+//! [`FrameBatch`] stores the frames by column instead: the ID words, the
+//! lengths, the flags and the timestamps each in their own array, and the
+//! payloads packed end to end in a single arena. Analysis that touches
+//! one or two columns then reads only those, in order, which is what the
+//! cache and the prefetcher are best at.
+//!
+//! A batch can be filled from the frames of a receive batch, or a log,
+//! and the frames can be rebuilt from it when they're needed whole.
+//!
+//! ```
+//! use socketcan::{batch::FrameBatch, CanFrame, EmbeddedFrame, Frame, Timestamped};
+//!
+//! let batch: FrameBatch = (0..1000u32)
+//!     .map(|i| {
+//!         let frame = CanFrame::from_raw_id(0x100 + i % 4, &i.to_le_bytes()).unwrap();
+//!         Timestamped::from_micros(frame.into(), u64::from(i) * 1000)
+//!     })
+//!     .collect();
+//!
+//! // Count the frames with one ID, reading only the ID column
+//! let n = batch.ids().iter().filter(|&&id| id == 0x101).count();
+//! assert_eq!(250, n);
+//!
+//! // Then get the frames back
+//! let frame = batch.get(1).unwrap().frame;
+//! assert_eq!(0x101, frame.raw_id());
+//! assert_eq!(&[1, 0, 0, 0], frame.data());
+//! ```
+
+use crate::{
+    frame::{can_frame_default, canfd_frame_default},
+    CanAnyFrame, CanFdFrame, CanFrame, EmbeddedFrame, Frame, Timestamped,
+};
+use libc::canid_t;
+
+/// The bit in the flags column that marks an FD frame. It's above the bits
+/// of [`FdFlags`](crate::frame::FdFlags), which are kept as they were in the frame.
+pub const FD_FRAME: u8 = 0x80;
+
+// ===== FrameBatch =====
+
+/// A batch of timestamped frames, stored by column.
+///
+/// Row `i` of every column belongs to the `i`th frame pushed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameBatch {
+    /// The ID words, with the EFF, RTR, and ERR flags
+    ids: Vec<canid_t>,
+    /// The data lengths, or the DLC of a remote frame
+    lens: Vec<u8>,
+    /// The FD flags, with `FD_FRAME` marking an FD frame
+    flags: Vec<u8>,
+    /// The timestamps, in microseconds since the Unix epoch
+    t_us: Vec<u64>,
+    /// The start of each frame's data in the arena, plus the end of the
+    /// last one
+    offsets: Vec<usize>,
+    /// The payloads of all the frames, end to end
+    payload: Vec<u8>,
+}
+
+impl Default for FrameBatch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FrameBatch {
+    /// Creates an empty batch.
+    pub fn new() -> Self {
+        Self::with_capacity(0, 0)
+    }
+
+    /// Creates an empty batch with room for a number of frames, and a
+    /// number of bytes of payload in total, without reallocating.
+    pub fn with_capacity(frames: usize, payload: usize) -> Self {
+        let mut offsets = Vec::with_capacity(frames + 1);
+        offsets.push(0);
+        Self {
+            ids: Vec::with_capacity(frames),
+            lens: Vec::with_capacity(frames),
+            flags: Vec::with_capacity(frames),
+            t_us: Vec::with_capacity(frames),
+            offsets,
+            payload: Vec::with_capacity(payload),
+        }
+    }
+
+    /// Gets the number of frames in the batch.
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    /// Determines if the batch is empty.
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// Removes all the frames, keeping the memory for reuse.
+    pub fn clear(&mut self) {
+        self.ids.clear();
+        self.lens.clear();
+        self.flags.clear();
+        self.t_us.clear();
+        self.offsets.truncate(1);
+        self.payload.clear();
+    }
+
+    /// Adds a frame, with its timestamp in microseconds since the Unix
+    /// epoch.
+    pub fn push(&mut self, t_us: u64, frame: &CanAnyFrame) {
+        let (len, flags) = match frame {
+            CanAnyFrame::Fd(frame) => (frame.len(), frame.flags().bits() | FD_FRAME),
+            CanAnyFrame::Remote(frame) => (frame.dlc(), 0),
+            _ => (frame.data().len(), 0),
+        };
+        self.ids.push(frame.id_word());
+        self.lens.push(len as u8);
+        self.flags.push(flags);
+        self.t_us.push(t_us);
+        self.payload.extend_from_slice(frame.data());
+        self.offsets.push(self.payload.len());
+    }
+
+    /// Gets the ID words of the frames, including the EFF, RTR, and ERR
+    /// flags.
+    pub fn ids(&self) -> &[canid_t] {
+        &self.ids
+    }
+
+    /// Gets the data lengths of the frames. For a remote frame, this is
+    /// the requested DLC.
+    pub fn lens(&self) -> &[u8] {
+        &self.lens
+    }
+
+    /// Gets the flags of the frames: the bits of [`FdFlags`](crate::frame::FdFlags), along with
+    /// [`FD_FRAME`] for an FD frame. A classic frame's flags are zero.
+    pub fn flags(&self) -> &[u8] {
+        &self.flags
+    }
+
+    /// Gets the timestamps of the frames, in microseconds since the Unix
+    /// epoch.
+    pub fn timestamps(&self) -> &[u64] {
+        &self.t_us
+    }
+
+    /// Gets the payloads of all the frames, packed end to end.
+    pub fn payload(&self) -> &[u8] {
+        &self.payload
+    }
+
+    /// Gets the data of a frame, or `None` if the index is out of range.
+    pub fn data(&self, i: usize) -> Option<&[u8]> {
+        let (start, end) = (*self.offsets.get(i)?, *self.offsets.get(i + 1)?);
+        Some(&self.payload[start..end])
+    }
+
+    /// Rebuilds a frame from the batch, or `None` if the index is out of
+    /// range.
+    pub fn get(&self, i: usize) -> Option<Timestamped<CanAnyFrame>> {
+        let data = self.data(i)?;
+        let flags = self.flags[i];
+
+        let frame = if flags & FD_FRAME != 0 {
+            let mut raw = canfd_frame_default();
+            raw.can_id = self.ids[i];
+            raw.len = self.lens[i];
+            raw.flags = flags & !FD_FRAME;
+            raw.data[..data.len()].copy_from_slice(data);
+            CanAnyFrame::Fd(CanFdFrame::from(raw))
+        } else {
+            let mut raw = can_frame_default();
+            raw.can_id = self.ids[i];
+            raw.can_dlc = self.lens[i];
+            raw.data[..data.len()].copy_from_slice(data);
+            CanAnyFrame::from(CanFrame::from(raw))
+        };
+        Some(Timestamped::from_micros(frame, self.t_us[i]))
+    }
+
+    /// Gets an iterator over the frames, rebuilt from the batch.
+    pub fn iter(&self) -> Iter<'_> {
+        Iter { batch: self, i: 0 }
+    }
+}
+
+impl<'a> Extend<&'a Timestamped<CanAnyFrame>> for FrameBatch {
+    fn extend<I: IntoIterator<Item = &'a Timestamped<CanAnyFrame>>>(&mut self, iter: I) {
+        for ts in iter {
+            self.push(ts.micros(), &ts.frame);
+        }
+    }
+}
+
+impl Extend<Timestamped<CanAnyFrame>> for FrameBatch {
+    fn extend<I: IntoIterator<Item = Timestamped<CanAnyFrame>>>(&mut self, iter: I) {
+        for ts in iter {
+            self.push(ts.micros(), &ts.frame);
+        }
+    }
+}
+
+impl FromIterator<Timestamped<CanAnyFrame>> for FrameBatch {
+    fn from_iter<I: IntoIterator<Item = Timestamped<CanAnyFrame>>>(iter: I) -> Self {
+        let mut batch = Self::new();
+        batch.extend(iter);
+        batch
+    }
+}
+
+impl From<&[Timestamped<CanAnyFrame>]> for FrameBatch {
+    /// Creates a batch from the frames of a receive batch, such as from
+    /// `CanFdSocket::read_frames_into()`.
+    fn from(frames: &[Timestamped<CanAnyFrame>]) -> Self {
+        let payload = frames.iter().map(|ts| ts.frame.data().len()).sum();
+        let mut batch = Self::with_capacity(frames.len(), payload);
+        batch.extend(frames);
+        batch
+    }
+}
+
+impl<'a> IntoIterator for &'a FrameBatch {
+    type Item = Timestamped<CanAnyFrame>;
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+// ===== Iter =====
+
+/// An iterator over the frames of a [`FrameBatch`].
+#[derive(Debug, Clone)]
+pub struct Iter<'a> {
+    batch: &'a FrameBatch,
+    i: usize,
+}
+
+impl Iterator for Iter<'_> {
+    type Item = Timestamped<CanAnyFrame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let frame = self.batch.get(self.i)?;
+        self.i += 1;
+        Some(frame)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let n = self.batch.len() - self.i;
+        (n, Some(n))
+    }
+}
+
+impl ExactSizeIterator for Iter<'_> {}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{frame::FdFlags, CanErrorFrame, CanRemoteFrame, ExtendedId, StandardId};
+
+    fn frames() -> Vec<Timestamped<CanAnyFrame>> {
+        let fd = CanFdFrame::with_flags(StandardId::new(0x7FF).unwrap(), &[0xAA; 48], FdFlags::BRS)
+            .unwrap();
+        let frames: [CanAnyFrame; 5] = [
+            CanFrame::from_raw_id(0x123, &[1, 2, 3]).unwrap().into(),
+            CanRemoteFrame::new_remote(ExtendedId::new(0x12345).unwrap(), 4)
+                .unwrap()
+                .into(),
+            CanErrorFrame::new_error(0x04, &[0, 1, 2, 3, 4, 5, 6, 7])
+                .unwrap()
+                .into(),
+            fd.into(),
+            CanFrame::from_raw_id(0x456, &[]).unwrap().into(),
+        ];
+        frames
+            .into_iter()
+            .enumerate()
+            .map(|(i, frame)| Timestamped::from_micros(frame, 1_000 * i as u64))
+            .collect()
+    }
+
+    #[test]
+    fn test_columns() {
+        let batch = FrameBatch::from(&frames()[..]);
+        assert_eq!(5, batch.len());
+        assert_eq!(&[3, 4, 8, 48, 0], batch.lens());
+        assert_eq!(&[0, 1000, 2000, 3000, 4000], batch.timestamps());
+        assert_eq!(3 + 8 + 48, batch.payload().len());
+        assert_eq!(Some(&[1u8, 2, 3][..]), batch.data(0));
+        assert_eq!(Some(&[][..]), batch.data(1));
+        assert_eq!(None, batch.data(5));
+
+        assert_eq!(FD_FRAME | FdFlags::BRS.bits(), batch.flags()[3]);
+        assert_eq!(0, batch.flags()[0]);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let frames = frames();
+        let mut batch: FrameBatch = frames.iter().cloned().collect();
+        assert_eq!(frames, batch.iter().collect::<Vec<_>>());
+
+        batch.clear();
+        assert!(batch.is_empty());
+        assert_eq!(None, batch.get(0));
+        batch.extend(&frames[3..]);
+        assert_eq!(&frames[3..], &batch.iter().collect::<Vec<_>>()[..]);
+    }
+}
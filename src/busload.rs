@@ -0,0 +1,265 @@
+// socketcan/src/busload.rs
+//
+// Bus load measurement.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! Bus load measurement.
+//!
+//! The load on a CAN bus is the fraction of time that it's busy carrying
+//! frames. It's estimated from the frames seen, by working out how long
+//! each one occupied the wire with
+//! [`CanAnyFrame::wire_duration()`], from the worst-case number of bits
+//! of the frame, including the bits stuffed in by the controller, and the
+//! bit rates of the bus. Error frames are generated by the controllers
+//! rather than sent, and aren't counted.
+//!
+//! The [`BusLoadMonitor`] keeps a sliding window of the frames seen, and
+//! reports the utilization of the bus over that window, along with the
+//! share of it due to each ID. It can be fed live from a socket, or from a
+//! capture log.
+//!
+//! ```no_run
+//! use socketcan::{busload::BusLoadMonitor, CanFdSocket, Socket};
+//! use std::time::Duration;
+//!
+//! let sock = CanFdSocket::open("can0").unwrap();
+//! let mut monitor = BusLoadMonitor::new(500_000);
+//!
+//! monitor
+//!     .run(&sock, Duration::from_secs(1), |load| {
+//!         println!("Bus load: {:.1}%", load.utilization);
+//!         for id in load.ids.iter().take(3) {
+//!             println!("  {:?}: {:.1}%", id.id, id.utilization);
+//!         }
+//!         true
+//!     })
+//!     .unwrap();
+//! ```
+
+use crate::{
+    capture::Record, CanAnyFrame, EmbeddedFrame, Frame, Id, IoErrorKind, IoResult, Socket,
+};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+/// The default length of the sliding window.
+pub const DEFAULT_WINDOW: Duration = Duration::from_secs(1);
+
+// ===== BusLoad =====
+
+/// The load due to a single ID.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IdLoad {
+    /// The CAN ID
+    pub id: Id,
+    /// The number of frames in the window
+    pub frames: u64,
+    /// The time the bus was busy with the ID, in nanoseconds
+    pub busy_ns: u64,
+    /// The percentage of the window that the bus was busy with the ID
+    pub utilization: f64,
+}
+
+/// The load on the bus over the window.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BusLoad {
+    /// The percentage of the window that the bus was busy
+    pub utilization: f64,
+    /// The number of frames in the window
+    pub frames: u64,
+    /// The time the bus was busy, in nanoseconds
+    pub busy_ns: u64,
+    /// The load due to each ID, from the largest to the smallest
+    pub ids: Vec<IdLoad>,
+}
+
+// ===== BusLoadMonitor =====
+
+/// A monitor of the bus load over a sliding window.
+#[derive(Debug, Clone)]
+pub struct BusLoadMonitor {
+    bitrate: u32,
+    data_bitrate: u32,
+    window_us: u64,
+    frames: VecDeque<(u64, Id, u64)>,
+    ids: BTreeMap<Id, (u64, u64)>,
+    busy_ns: u64,
+}
+
+impl BusLoadMonitor {
+    /// Creates a monitor for a bus at the bit rate, with the default
+    /// window.
+    pub fn new(bitrate: u32) -> Self {
+        Self {
+            bitrate,
+            data_bitrate: bitrate,
+            window_us: DEFAULT_WINDOW.as_micros() as u64,
+            frames: VecDeque::new(),
+            ids: BTreeMap::new(),
+            busy_ns: 0,
+        }
+    }
+
+    /// Sets the data bit rate, for CAN FD frames with bit rate switching.
+    pub fn with_data_bitrate(mut self, data_bitrate: u32) -> Self {
+        self.data_bitrate = data_bitrate;
+        self
+    }
+
+    /// Sets the length of the sliding window.
+    pub fn with_window(mut self, window: Duration) -> Self {
+        self.window_us = (window.as_micros() as u64).max(1);
+        self
+    }
+
+    /// Adds a timestamped frame.
+    ///
+    /// The frames should be given in time order.
+    pub fn update(&mut self, t_us: u64, frame: &CanAnyFrame) {
+        if frame.is_error_frame() {
+            return;
+        }
+        let id = frame.id();
+        let ns = frame
+            .wire_duration(self.bitrate, self.data_bitrate)
+            .as_nanos() as u64;
+
+        self.frames.push_back((t_us, id, ns));
+        let entry = self.ids.entry(id).or_default();
+        entry.0 += 1;
+        entry.1 += ns;
+        self.busy_ns += ns;
+        self.expire(t_us);
+    }
+
+    /// Adds a frame from a capture log.
+    pub fn update_record(&mut self, rec: &Record) {
+        self.update(rec.t_us, &rec.frame)
+    }
+
+    /// Drops the frames that have fallen out of the window ending at the
+    /// time.
+    fn expire(&mut self, now_us: u64) {
+        while let Some(&(t_us, id, ns)) = self.frames.front() {
+            if t_us + self.window_us > now_us {
+                break;
+            }
+            self.frames.pop_front();
+            self.busy_ns -= ns;
+            if let Some(entry) = self.ids.get_mut(&id) {
+                entry.0 -= 1;
+                entry.1 -= ns;
+                if entry.0 == 0 {
+                    self.ids.remove(&id);
+                }
+            }
+        }
+    }
+
+    /// Gets the load over the window ending at the time.
+    pub fn load(&mut self, now_us: u64) -> BusLoad {
+        self.expire(now_us);
+        let window_ns = self.window_us as f64 * 1000.0;
+        let pct = |ns: u64| 100.0 * ns as f64 / window_ns;
+
+        let mut ids: Vec<_> = self
+            .ids
+            .iter()
+            .map(|(&id, &(frames, busy_ns))| IdLoad {
+                id,
+                frames,
+                busy_ns,
+                utilization: pct(busy_ns),
+            })
+            .collect();
+        ids.sort_by_key(|load| std::cmp::Reverse(load.busy_ns));
+
+        BusLoad {
+            utilization: pct(self.busy_ns),
+            frames: self.frames.len() as u64,
+            busy_ns: self.busy_ns,
+            ids,
+        }
+    }
+
+    /// Clears the window.
+    pub fn clear(&mut self) {
+        self.frames.clear();
+        self.ids.clear();
+        self.busy_ns = 0;
+    }
+
+    /// Reads frames from the socket, reporting the load every `interval`
+    /// until the callback returns `false`.
+    ///
+    /// Frames are timestamped on reception.
+    pub fn run<S, F>(&mut self, sock: &S, interval: Duration, mut f: F) -> IoResult<()>
+    where
+        S: Socket,
+        S::FrameType: Into<CanAnyFrame>,
+        F: FnMut(&BusLoad) -> bool,
+    {
+        let start = Instant::now();
+        let mut next = start + interval;
+
+        loop {
+            let remaining = next.saturating_duration_since(Instant::now());
+            match sock.read_frame_timeout(remaining) {
+                Ok(frame) => {
+                    let t_us = start.elapsed().as_micros() as u64;
+                    self.update(t_us, &frame.into());
+                }
+                Err(err) if err.kind() == IoErrorKind::TimedOut => (),
+                Err(err) => return Err(err),
+            }
+            if Instant::now() >= next {
+                let load = self.load(start.elapsed().as_micros() as u64);
+                if !f(&load) {
+                    return Ok(());
+                }
+                next += interval;
+            }
+        }
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CanFrame, Frame};
+
+    #[test]
+    fn test_bus_load() {
+        let mut monitor = BusLoadMonitor::new(500_000).with_window(Duration::from_millis(100));
+        let a = CanFrame::from_raw_id(0x100, &[0x55; 8]).unwrap();
+        let b = CanFrame::from_raw_id(0x200, &[0x55; 2]).unwrap();
+        let bits_a = a.bit_length() as u64;
+
+        for i in 0..10 {
+            monitor.update(i * 10_000, &a.into());
+        }
+        monitor.update(95_000, &b.into());
+
+        let load = monitor.load(99_000);
+        assert_eq!(11, load.frames);
+        assert_eq!(2, load.ids.len());
+        assert_eq!(a.id(), load.ids[0].id);
+        assert_eq!(10 * bits_a * 2000, load.ids[0].busy_ns);
+        assert!(load.utilization > load.ids[0].utilization);
+
+        // Everything but the last frame has left the window
+        let load = monitor.load(190_000);
+        assert_eq!(1, load.frames);
+        assert_eq!(0, monitor.load(200_000).frames);
+    }
+}
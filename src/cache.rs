@@ -0,0 +1,452 @@
+// socketcan/src/cache.rs
+//
+// A cache of the most recent value of each ID on the bus.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! A last-value cache of the state of the bus.
+//!
+//! The [`BusState`] tracks the most recent frame received for each ID, so
+//! that an application can ask for the "current" value of any ID without
+//! waiting for it to be sent again. At any instant, the full state of the
+//! bus can be taken as a [`BusSnapshot`].
+//!
+//! With the `serde` feature, a snapshot can be serialized, such as to JSON
+//! for dashboards and debugging. The frames have the same representation
+//! as anywhere else in the crate:
+//!
+#![cfg_attr(feature = "serde", doc = "```")]
+#![cfg_attr(not(feature = "serde"), doc = "```ignore")]
+//! use socketcan::{cache::BusState, CanFrame, Frame, StandardId};
+//!
+//! let mut state = BusState::new();
+//! state.update(1000, &CanFrame::from_raw_id(0x100, &[1, 2]).unwrap().into());
+//! state.update(2000, &CanFrame::from_raw_id(0x100, &[3, 4]).unwrap().into());
+//!
+//! let id = StandardId::new(0x100).unwrap();
+//! assert_eq!(&[3, 4], state.get(id).unwrap().data());
+//!
+//! let json = serde_json::to_string(&state.snapshot()).unwrap();
+//! assert_eq!(
+//!     concat!(
+//!         r#"{"t_us":2000,"error_frames":0,"values":["#,
+//!         r#"{"t_us":2000,"count":2,"frame":{"Normal":{"id":256,"ext":false,"data":[3,4]}}}"#,
+//!         r#"]}"#
+//!     ),
+//!     json
+//! );
+//! ```
+//!
+//! The [`LastValueCache`] wraps a `BusState` that can be shared between
+//! threads. It can be kept up to date by a background thread that reads
+//! from a socket, and callers can query the current value of any ID or
+//! subscribe to notifications when the payload of an ID changes.
+//!
+//! ```no_run
+//! use socketcan::{cache::LastValueCache, CanSocket, Socket, StandardId};
+//!
+//! let sock = CanSocket::open("vcan0").unwrap();
+//! let cache = LastValueCache::spawn(sock);
+//!
+//! let rx = cache.subscribe_id(StandardId::new(0x100).unwrap());
+//! for val in rx.iter().take(10) {
+//!     println!("{:?}", val.data());
+//! }
+//! ```
+
+use crate::{
+    timestamp::micros_since_epoch, CanAnyFrame, EmbeddedFrame, Frame, Id, IoErrorKind, ShouldRetry,
+    Socket,
+};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, SystemTime},
+};
+
+// ===== CachedValue =====
+
+/// The most recent value of an ID on the bus.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CachedValue {
+    /// The timestamp of the most recent frame, in microseconds
+    pub t_us: u64,
+    /// The number of frames received for the ID
+    pub count: u64,
+    /// The most recent frame
+    pub frame: CanAnyFrame,
+}
+
+impl CachedValue {
+    /// Gets the CAN ID of the value
+    pub fn id(&self) -> Id {
+        self.frame.id()
+    }
+
+    /// Gets the most recent data payload.
+    ///
+    /// This is empty if the most recent frame was a remote frame.
+    pub fn data(&self) -> &[u8] {
+        self.frame.data()
+    }
+}
+
+// ===== BusSnapshot =====
+
+/// The state of the bus at an instant in time.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct BusSnapshot {
+    /// The time of the most recent frame, in microseconds
+    pub t_us: u64,
+    /// The number of error frames received
+    pub error_frames: u64,
+    /// The most recent value of each ID, in order of bus priority
+    pub values: Vec<CachedValue>,
+}
+
+impl BusSnapshot {
+    /// Gets the value of a specific ID.
+    pub fn get(&self, id: impl Into<Id>) -> Option<&CachedValue> {
+        let id = id.into();
+        self.values.iter().find(|val| val.id() == id)
+    }
+}
+
+// ===== BusState =====
+
+/// A cache of the most recent frame for each ID on the bus.
+#[derive(Debug, Clone, Default)]
+pub struct BusState {
+    values: BTreeMap<Id, CachedValue>,
+    error_frames: u64,
+    t_us: u64,
+}
+
+impl BusState {
+    /// Creates a new, empty, cache.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Updates the cache with a timestamped frame.
+    ///
+    /// Error frames are counted, but not cached.
+    pub fn update(&mut self, t_us: u64, frame: &CanAnyFrame) {
+        self.update_changed(t_us, frame);
+    }
+
+    /// Updates the cache, returning the new value if the payload of the ID
+    /// changed, or the ID was new to the cache.
+    fn update_changed(&mut self, t_us: u64, frame: &CanAnyFrame) -> Option<CachedValue> {
+        self.t_us = t_us;
+
+        if frame.is_error_frame() {
+            self.error_frames += 1;
+            return None;
+        }
+        let id = frame.id();
+
+        let val = CachedValue {
+            t_us,
+            count: 1,
+            frame: *frame,
+        };
+
+        match self.values.insert(id, val) {
+            Some(prev) => {
+                let val = self.values.get_mut(&id)?;
+                val.count += prev.count;
+                (prev.data() != val.data()).then_some(*val)
+            }
+            None => Some(val),
+        }
+    }
+
+    /// Gets the most recent value of an ID.
+    pub fn get(&self, id: impl Into<Id>) -> Option<&CachedValue> {
+        self.values.get(&id.into())
+    }
+
+    /// Iterates over the most recent values of all the IDs, in order of bus
+    /// priority.
+    pub fn iter(&self) -> impl Iterator<Item = &CachedValue> {
+        self.values.values()
+    }
+
+    /// The number of IDs in the cache
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Determines if the cache is empty
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Takes a snapshot of the state of the bus.
+    pub fn snapshot(&self) -> BusSnapshot {
+        BusSnapshot {
+            t_us: self.t_us,
+            error_frames: self.error_frames,
+            values: self.values.values().copied().collect(),
+        }
+    }
+
+    /// Removes all the values from the cache.
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+// ===== LastValueCache =====
+
+/// How long the background thread waits for a frame before checking
+/// whether the cache was dropped.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A subscriber to change notifications.
+#[derive(Debug)]
+struct Subscriber {
+    id: Option<Id>,
+    tx: mpsc::Sender<CachedValue>,
+}
+
+/// The state shared by the cache and its background thread.
+#[derive(Debug, Default)]
+struct Shared {
+    state: Mutex<BusState>,
+    subscribers: Mutex<Vec<Subscriber>>,
+    stop: AtomicBool,
+}
+
+impl Shared {
+    /// Updates the state and notifies the subscribers of any change.
+    fn update(&self, t_us: u64, frame: &CanAnyFrame) {
+        let changed = self.state.lock().unwrap().update_changed(t_us, frame);
+
+        if let Some(val) = changed {
+            let id = val.id();
+            // Drop any subscribers that went away
+            self.subscribers.lock().unwrap().retain(|sub| match sub.id {
+                Some(sub_id) if sub_id != id => true,
+                _ => sub.tx.send(val).is_ok(),
+            });
+        }
+    }
+}
+
+/// A last-value cache that can be shared between threads and kept up to
+/// date from a socket.
+///
+/// Cloning the cache gives another handle to the same shared state. If
+/// the cache was created with [`LastValueCache::spawn`], the background
+/// thread is stopped when the last handle is dropped.
+#[derive(Debug, Clone, Default)]
+pub struct LastValueCache {
+    shared: Arc<Shared>,
+    thread: Option<Arc<ReaderThread>>,
+}
+
+/// The background thread that reads frames into the cache.
+#[derive(Debug)]
+struct ReaderThread {
+    shared: Arc<Shared>,
+    handle: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl Drop for ReaderThread {
+    fn drop(&mut self) {
+        self.shared.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.lock().unwrap().take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl LastValueCache {
+    /// Creates a new, empty, cache that is updated manually.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a cache that is continuously updated from the socket by a
+    /// background thread.
+    ///
+    /// Frames are timestamped with the system time when they are read.
+    /// The thread exits when the cache is dropped, or on a socket error.
+    pub fn spawn<S>(sock: S) -> Self
+    where
+        S: Socket + Send + 'static,
+        S::FrameType: Into<CanAnyFrame>,
+    {
+        let shared = Arc::new(Shared::default());
+
+        let thr_shared = Arc::clone(&shared);
+        let handle = thread::spawn(move || {
+            while !thr_shared.stop.load(Ordering::Relaxed) {
+                match sock.read_frame_timeout(POLL_INTERVAL) {
+                    Ok(frame) => {
+                        thr_shared.update(micros_since_epoch(SystemTime::now()), &frame.into())
+                    }
+                    Err(err)
+                        if err.kind() == IoErrorKind::TimedOut
+                            || err.kind() == IoErrorKind::Interrupted
+                            || err.should_retry() => {}
+                    Err(_) => break,
+                }
+            }
+        });
+
+        let thread = ReaderThread {
+            shared: Arc::clone(&shared),
+            handle: Mutex::new(Some(handle)),
+        };
+
+        Self {
+            shared,
+            thread: Some(Arc::new(thread)),
+        }
+    }
+
+    /// Updates the cache with a timestamped frame, notifying any
+    /// subscribers if the payload changed.
+    pub fn update(&self, t_us: u64, frame: &CanAnyFrame) {
+        self.shared.update(t_us, frame);
+    }
+
+    /// Gets the current value of an ID.
+    pub fn get(&self, id: impl Into<Id>) -> Option<CachedValue> {
+        self.shared.state.lock().unwrap().get(id).copied()
+    }
+
+    /// Takes a snapshot of the state of the bus.
+    pub fn snapshot(&self) -> BusSnapshot {
+        self.shared.state.lock().unwrap().snapshot()
+    }
+
+    /// Subscribes to change notifications for all IDs.
+    ///
+    /// The receiver gets the new value whenever an ID is first seen, or
+    /// its payload changes. Frames that repeat the current payload are
+    /// not reported.
+    pub fn subscribe(&self) -> mpsc::Receiver<CachedValue> {
+        self.add_subscriber(None)
+    }
+
+    /// Subscribes to change notifications for a single ID.
+    pub fn subscribe_id(&self, id: impl Into<Id>) -> mpsc::Receiver<CachedValue> {
+        self.add_subscriber(Some(id.into()))
+    }
+
+    fn add_subscriber(&self, id: Option<Id>) -> mpsc::Receiver<CachedValue> {
+        let (tx, rx) = mpsc::channel();
+        self.shared
+            .subscribers
+            .lock()
+            .unwrap()
+            .push(Subscriber { id, tx });
+        rx
+    }
+
+    /// Determines if the background thread is still reading frames.
+    ///
+    /// This is `false` if the cache is updated manually, or if the thread
+    /// stopped due to a socket error.
+    pub fn is_running(&self) -> bool {
+        self.thread.as_ref().is_some_and(|thr| {
+            thr.handle
+                .lock()
+                .unwrap()
+                .as_ref()
+                .is_some_and(|handle| !handle.is_finished())
+        })
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CanError, CanErrorFrame, CanFdFrame, CanFrame, ExtendedId, Frame};
+
+    #[test]
+    fn test_snapshot() {
+        let mut state = BusState::new();
+        assert!(state.is_empty());
+
+        let id = ExtendedId::new(0x1234).unwrap();
+        let frame = CanFdFrame::new(id, &[0xAB; 2]).unwrap();
+        state.update(10, &frame.into());
+        state.update(20, &CanFrame::from_raw_id(0x10, &[1]).unwrap().into());
+        state.update(30, &CanFrame::new_remote(id, 0).unwrap().into());
+        state.update(
+            40,
+            &CanAnyFrame::Error(CanErrorFrame::from(CanError::BusOff)),
+        );
+
+        assert_eq!(2, state.len());
+        let val = state.get(id).unwrap();
+        assert_eq!((30, 2), (val.t_us, val.count));
+        assert!(val.data().is_empty());
+
+        let snap = state.snapshot();
+        assert_eq!(
+            1,
+            snap.get(crate::StandardId::new(0x10).unwrap())
+                .unwrap()
+                .count
+        );
+        assert_eq!((40, 1), (snap.t_us, snap.error_frames));
+
+        let ids: Vec<_> = snap.values.iter().map(CachedValue::id).collect();
+        let std_id = crate::StandardId::new(0x10).unwrap();
+        assert_eq!(vec![Id::from(id), Id::from(std_id)], ids);
+    }
+
+    #[test]
+    fn test_subscribe() {
+        let cache = LastValueCache::new();
+        let all = cache.subscribe();
+        let one = cache.subscribe_id(crate::StandardId::new(0x20).unwrap());
+
+        let frame = |id, data: &[u8]| CanFrame::from_raw_id(id, data).unwrap().into();
+        cache.update(1, &frame(0x10, &[1]));
+        cache.update(2, &frame(0x20, &[1]));
+        cache.update(3, &frame(0x20, &[1]));
+        cache.update(4, &frame(0x20, &[2]));
+
+        let changes: Vec<_> = all.try_iter().map(|val| val.t_us).collect();
+        assert_eq!(vec![1, 2, 4], changes);
+
+        let changes: Vec<_> = one.try_iter().map(|val| val.data()[0]).collect();
+        assert_eq!(vec![1, 2], changes);
+
+        assert_eq!(
+            3,
+            cache
+                .get(crate::StandardId::new(0x20).unwrap())
+                .unwrap()
+                .count
+        );
+        assert!(!cache.is_running());
+
+        // Dropped subscribers are removed on the next change
+        drop(all);
+        cache.update(5, &frame(0x10, &[2]));
+        assert_eq!(1, cache.shared.subscribers.lock().unwrap().len());
+    }
+}
@@ -0,0 +1,366 @@
+// socketcan/src/cannelloni.rs
+//
+// CAN tunneling over UDP, compatible with cannelloni.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! CAN tunneling over UDP, compatible with cannelloni.
+//!
+//! [cannelloni](https://github.com/mguentner/cannelloni) tunnels CAN
+//! traffic between machines, packing frames into UDP datagrams. The
+//! [`encode`] and [`decode`] functions handle its packet format, and a
+//! [`CannelloniEndpoint`] is one end of a tunnel, which can talk to the C
+//! tool or to another endpoint. It implements the [`CanTransmitter`] and
+//! [`CanReceiver`] traits, so a tunneled bus can stand in for a local
+//! socket.
+//!
+//! Only the UDP transport is supported.
+//!
+//! ```no_run
+//! use socketcan::{
+//!     backend::CanReceiver,
+//!     cannelloni::CannelloniEndpoint,
+//!     CanFdSocket, Socket,
+//! };
+//!
+//! // Forward everything from the tunnel onto a local bus
+//! let tunnel = CannelloniEndpoint::bind("0.0.0.0:20000", "192.168.1.20:20000").unwrap();
+//! let sock = CanFdSocket::open("can0").unwrap();
+//!
+//! loop {
+//!     let frame = tunnel.receive().unwrap();
+//!     sock.write_frame(&frame).unwrap();
+//! }
+//! ```
+
+use crate::{
+    backend::{CanReceiver, CanTransmitter},
+    frame::{can_frame_default, canfd_frame_default},
+    CanAnyFrame, EmbeddedFrame, Frame, IoError, IoErrorKind, IoResult,
+};
+use libc::CAN_RTR_FLAG;
+use std::{
+    collections::VecDeque,
+    net::{ToSocketAddrs, UdpSocket},
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+use thiserror::Error;
+
+/// The version of the cannelloni protocol.
+pub const VERSION: u8 = 2;
+
+/// The operation code of a data packet.
+pub const OP_DATA: u8 = 0;
+
+/// The largest packet sent, which keeps clear of IP fragmentation on an
+/// Ethernet link.
+pub const MAX_PACKET_LEN: usize = 1472;
+
+// The size of the packet header
+const HEADER_LEN: usize = 5;
+
+// The flag in the length byte that marks an FD frame
+const CANFD_FRAME: u8 = 0x80;
+
+/// An error decoding a cannelloni packet.
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CannelloniError {
+    /// The packet is from an unsupported version of the protocol
+    #[error("Unsupported cannelloni version: {0}")]
+    Version(u8),
+    /// The packet isn't a data packet
+    #[error("Unsupported cannelloni operation: {0}")]
+    OpCode(u8),
+    /// The packet is cut short, or a frame in it is invalid
+    #[error("Malformed cannelloni packet")]
+    Malformed,
+}
+
+impl From<CannelloniError> for IoError {
+    fn from(err: CannelloniError) -> Self {
+        IoError::new(IoErrorKind::InvalidData, err)
+    }
+}
+
+// ===== Packets =====
+
+/// Gets the number of bytes a frame takes up in a packet.
+pub fn encoded_len(frame: &CanAnyFrame) -> usize {
+    match frame {
+        CanAnyFrame::Fd(frame) => 6 + frame.data().len(),
+        CanAnyFrame::Remote(_) => 5,
+        _ => 5 + frame.data().len(),
+    }
+}
+
+/// Encodes frames into a data packet with the sequence number.
+///
+/// All the frames go into the one packet, so the caller should keep it
+/// under the size of a datagram, such as with [`encoded_len`].
+pub fn encode(seq: u8, frames: &[CanAnyFrame]) -> Vec<u8> {
+    let len = HEADER_LEN + frames.iter().map(encoded_len).sum::<usize>();
+    let mut buf = Vec::with_capacity(len);
+
+    buf.extend_from_slice(&[VERSION, OP_DATA, seq]);
+    buf.extend_from_slice(&(frames.len() as u16).to_be_bytes());
+
+    for frame in frames {
+        buf.extend_from_slice(&frame.id_word().to_be_bytes());
+        match frame {
+            CanAnyFrame::Fd(frame) => {
+                buf.push(frame.data().len() as u8 | CANFD_FRAME);
+                buf.push(frame.flags().bits());
+                buf.extend_from_slice(frame.data());
+            }
+            CanAnyFrame::Remote(frame) => buf.push(frame.dlc() as u8),
+            _ => {
+                buf.push(frame.data().len() as u8);
+                buf.extend_from_slice(frame.data());
+            }
+        }
+    }
+    buf
+}
+
+/// Decodes a data packet, returning its sequence number and frames.
+pub fn decode(packet: &[u8]) -> Result<(u8, Vec<CanAnyFrame>), CannelloniError> {
+    use CannelloniError::*;
+
+    if packet.len() < HEADER_LEN {
+        return Err(Malformed);
+    }
+    if packet[0] != VERSION {
+        return Err(Version(packet[0]));
+    }
+    if packet[1] != OP_DATA {
+        return Err(OpCode(packet[1]));
+    }
+    let seq = packet[2];
+    let count = u16::from_be_bytes([packet[3], packet[4]]) as usize;
+
+    let mut buf = &packet[HEADER_LEN..];
+    // The count comes off the wire, so only reserve what the packet could
+    // hold, at five bytes for the smallest frame
+    let capacity = count.min(buf.len() / 5);
+    let mut take = |n: usize| -> Result<&[u8], CannelloniError> {
+        if buf.len() < n {
+            return Err(Malformed);
+        }
+        let (head, tail) = buf.split_at(n);
+        buf = tail;
+        Ok(head)
+    };
+
+    let mut frames = Vec::with_capacity(capacity);
+    for _ in 0..count {
+        let id = take(4)?;
+        let can_id = u32::from_be_bytes([id[0], id[1], id[2], id[3]]);
+        let len = take(1)?[0];
+
+        let frame = if len & CANFD_FRAME != 0 {
+            let len = (len & !CANFD_FRAME) as usize;
+            let mut frame = canfd_frame_default();
+            if len > frame.data.len() {
+                return Err(Malformed);
+            }
+            frame.flags = take(1)?[0];
+            frame.data[..len].copy_from_slice(take(len)?);
+            frame.can_id = can_id;
+            frame.len = len as u8;
+            CanAnyFrame::from(frame)
+        } else {
+            let len = len as usize;
+            if len > 8 {
+                return Err(Malformed);
+            }
+            let mut frame = can_frame_default();
+            if can_id & CAN_RTR_FLAG == 0 {
+                frame.data[..len].copy_from_slice(take(len)?);
+            }
+            frame.can_id = can_id;
+            frame.can_dlc = len as u8;
+            CanAnyFrame::from(frame)
+        };
+        frames.push(frame);
+    }
+    Ok((seq, frames))
+}
+
+// ===== CannelloniEndpoint =====
+
+/// One end of a cannelloni tunnel.
+#[derive(Debug)]
+pub struct CannelloniEndpoint {
+    sock: UdpSocket,
+    seq: AtomicU8,
+    rx_queue: Mutex<VecDeque<CanAnyFrame>>,
+}
+
+impl CannelloniEndpoint {
+    /// Binds to the local address, to exchange frames with the peer at
+    /// the remote address.
+    pub fn bind<L, R>(local: L, remote: R) -> IoResult<Self>
+    where
+        L: ToSocketAddrs,
+        R: ToSocketAddrs,
+    {
+        let sock = UdpSocket::bind(local)?;
+        sock.connect(remote)?;
+        Ok(Self {
+            sock,
+            seq: AtomicU8::new(0),
+            rx_queue: Mutex::new(VecDeque::new()),
+        })
+    }
+
+    /// Gets the underlying UDP socket.
+    pub fn get_ref(&self) -> &UdpSocket {
+        &self.sock
+    }
+
+    /// Sends frames to the peer, packing as many as fit into each packet.
+    pub fn transmit_batch(&self, frames: &[CanAnyFrame]) -> IoResult<()> {
+        let mut start = 0;
+        let mut len = HEADER_LEN;
+        for (i, frame) in frames.iter().enumerate() {
+            let n = encoded_len(frame);
+            if len + n > MAX_PACKET_LEN {
+                self.send_packet(&frames[start..i])?;
+                start = i;
+                len = HEADER_LEN;
+            }
+            len += n;
+        }
+        if start < frames.len() {
+            self.send_packet(&frames[start..])?;
+        }
+        Ok(())
+    }
+
+    fn send_packet(&self, frames: &[CanAnyFrame]) -> IoResult<()> {
+        let seq = self.seq.fetch_add(1, Ordering::Relaxed);
+        self.sock.send(&encode(seq, frames))?;
+        Ok(())
+    }
+
+    fn receive_with(&self, timeout: Option<Duration>) -> IoResult<CanAnyFrame> {
+        let mut rx_queue = self.rx_queue.lock().unwrap();
+        self.sock.set_read_timeout(timeout)?;
+
+        let mut buf = [0u8; 65536];
+        loop {
+            if let Some(frame) = rx_queue.pop_front() {
+                return Ok(frame);
+            }
+            let n = match self.sock.recv(&mut buf) {
+                Ok(n) => n,
+                Err(err) if err.kind() == IoErrorKind::WouldBlock => {
+                    return Err(IoErrorKind::TimedOut.into())
+                }
+                Err(err) => return Err(err),
+            };
+            match decode(&buf[..n]) {
+                Ok((_, frames)) => rx_queue.extend(frames),
+                Err(err) => log::warn!("Dropping cannelloni packet: {}", err),
+            }
+        }
+    }
+}
+
+impl<F> CanTransmitter<F> for CannelloniEndpoint
+where
+    F: Into<CanAnyFrame> + Clone,
+{
+    fn transmit(&self, frame: &F) -> IoResult<()> {
+        self.send_packet(&[frame.clone().into()])
+    }
+}
+
+impl CanReceiver for CannelloniEndpoint {
+    type Frame = CanAnyFrame;
+
+    fn receive(&self) -> IoResult<CanAnyFrame> {
+        self.receive_with(None)
+    }
+
+    fn receive_timeout(&self, timeout: Duration) -> IoResult<CanAnyFrame> {
+        // A zero timeout means no timeout to the socket
+        self.receive_with(Some(timeout.max(Duration::from_micros(1))))
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CanFdFrame, CanFrame, CanRemoteFrame};
+
+    #[test]
+    fn test_encode_decode() {
+        let frames: Vec<CanAnyFrame> = vec![
+            CanFrame::from_raw_id(0x123, &[1, 2, 3]).unwrap().into(),
+            CanRemoteFrame::remote_from_raw_id(0x1ABCDEF0, 2)
+                .unwrap()
+                .into(),
+            CanFdFrame::from_raw_id(0x456, &[0x55; 12]).unwrap().into(),
+        ];
+        let packet = encode(7, &frames);
+        assert_eq!(
+            &[2, 0, 7, 0, 3, 0x00, 0x00, 0x01, 0x23, 3, 1, 2, 3],
+            &packet[..13]
+        );
+        assert_eq!(&[0xDA, 0xBC, 0xDE, 0xF0, 2], &packet[13..18]);
+        assert_eq!(0x80 | 12, packet[22]);
+        assert_eq!(
+            packet.len(),
+            5 + frames.iter().map(encoded_len).sum::<usize>()
+        );
+
+        let (seq, decoded) = decode(&packet).unwrap();
+        assert_eq!(7, seq);
+        assert_eq!(frames, decoded);
+
+        assert_eq!(Err(CannelloniError::Malformed), decode(&packet[..20]));
+        assert_eq!(Err(CannelloniError::Version(1)), decode(&[1, 0, 0, 0, 0]));
+
+        // A count far past the end of the packet
+        assert_eq!(
+            Err(CannelloniError::Malformed),
+            decode(&[2, 0, 7, 0xFF, 0xFF, 0, 0, 0, 1, 0])
+        );
+    }
+
+    #[test]
+    fn test_endpoint() {
+        let a = UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let b = UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let ep_a = CannelloniEndpoint::bind(a, b).unwrap();
+        let ep_b = CannelloniEndpoint::bind(b, a).unwrap();
+
+        let frames: Vec<CanAnyFrame> = (0..200)
+            .map(|i| CanFrame::from_raw_id(i, &[0; 8]).unwrap().into())
+            .collect();
+        ep_a.transmit_batch(&frames).unwrap();
+
+        for i in 0..200 {
+            let frame = ep_b.receive_timeout(Duration::from_secs(5)).unwrap();
+            assert_eq!(i, frame.raw_id());
+        }
+    }
+}
@@ -0,0 +1,316 @@
+// socketcan/src/canopen/emcy.rs
+//
+// CANopen emergency messages.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! CANopen emergency (EMCY) messages.
+//!
+//! A node sends an emergency message when an internal error occurs, and
+//! again with an error code of zero when the error is cleared. The message
+//! carries a 16-bit error code from the tables of CiA 301 or a device
+//! profile, the node's error register (object 0x1001), and five bytes of
+//! manufacturer-specific data.
+//!
+//! Emergencies can be handled with the [`Dispatcher`], or, with the `tokio`
+//! feature, read as a stream of [`Emergency`] values.
+//!
+//! ```no_run
+//! use socketcan::{dispatch::Dispatcher, CanFrame, CanSocket, Socket};
+//!
+//! let sock = CanSocket::open("can0").unwrap();
+//!
+//! let mut disp = Dispatcher::<CanFrame>::new();
+//! disp.on_emergency(|emcy| println!("{}", emcy));
+//!
+//! loop {
+//!     disp.dispatch_next(&sock).unwrap();
+//! }
+//! ```
+
+use super::FC_SYNC_EMCY;
+use crate::{
+    dispatch::{Dispatcher, HandlerId, Route},
+    frame::write_hex_bytes,
+    CanFrame, CanOpenId, EmbeddedFrame, Frame,
+};
+use bitflags::bitflags;
+use std::fmt;
+
+bitflags! {
+    /// The bits of the error register of a node (object 0x1001).
+    #[derive(Default)]
+    pub struct ErrorRegister: u8 {
+        /// A generic error
+        const GENERIC = 0x01;
+        /// A current error
+        const CURRENT = 0x02;
+        /// A voltage error
+        const VOLTAGE = 0x04;
+        /// A temperature error
+        const TEMPERATURE = 0x08;
+        /// A communication error, such as an overrun or error state
+        const COMMUNICATION = 0x10;
+        /// An error specific to the device profile
+        const DEVICE_PROFILE = 0x20;
+        /// A manufacturer-specific error
+        const MANUFACTURER = 0x80;
+    }
+}
+
+/// The class of an emergency error code, from its upper byte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorClass {
+    /// No error, or the error was reset (0x00xx)
+    NoError,
+    /// Generic error (0x10xx)
+    Generic,
+    /// Current error (0x20xx - 0x23xx)
+    Current,
+    /// Voltage error (0x30xx - 0x33xx)
+    Voltage,
+    /// Temperature error (0x40xx - 0x42xx)
+    Temperature,
+    /// Device hardware error (0x50xx)
+    Hardware,
+    /// Device software error (0x60xx - 0x63xx)
+    Software,
+    /// Error in an additional module (0x70xx)
+    AdditionalModules,
+    /// Monitoring error, including communication and protocol errors
+    /// (0x80xx - 0x82xx)
+    Monitoring,
+    /// External error (0x90xx)
+    External,
+    /// Error in an additional function (0xF0xx)
+    AdditionalFunctions,
+    /// Device specific error (0xFFxx)
+    DeviceSpecific,
+    /// A code that isn't in any of the standard classes
+    Unknown,
+}
+
+impl From<u16> for ErrorClass {
+    fn from(code: u16) -> Self {
+        use ErrorClass::*;
+        match code >> 8 {
+            0x00 => NoError,
+            0x10 => Generic,
+            0x20..=0x23 => Current,
+            0x30..=0x33 => Voltage,
+            0x40..=0x42 => Temperature,
+            0x50 => Hardware,
+            0x60..=0x63 => Software,
+            0x70 => AdditionalModules,
+            0x80..=0x82 => Monitoring,
+            0x90 => External,
+            0xF0 => AdditionalFunctions,
+            0xFF => DeviceSpecific,
+            _ => Unknown,
+        }
+    }
+}
+
+impl fmt::Display for ErrorClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use ErrorClass::*;
+        let s = match *self {
+            NoError => "No error",
+            Generic => "Generic error",
+            Current => "Current error",
+            Voltage => "Voltage error",
+            Temperature => "Temperature error",
+            Hardware => "Device hardware error",
+            Software => "Device software error",
+            AdditionalModules => "Additional modules error",
+            Monitoring => "Monitoring error",
+            External => "External error",
+            AdditionalFunctions => "Additional functions error",
+            DeviceSpecific => "Device specific error",
+            Unknown => "Unknown error",
+        };
+        f.write_str(s)
+    }
+}
+
+// ===== Emergency =====
+
+/// An emergency message from a node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Emergency {
+    /// The ID of the node that sent the message
+    pub node_id: u8,
+    /// The error code
+    pub error_code: u16,
+    /// The error register of the node
+    pub error_register: ErrorRegister,
+    /// The manufacturer-specific error data
+    pub manufacturer: [u8; 5],
+}
+
+impl Emergency {
+    /// Creates an emergency message for the node.
+    pub fn new(node_id: u8, error_code: u16, error_register: ErrorRegister) -> Self {
+        Self {
+            node_id,
+            error_code,
+            error_register,
+            manufacturer: [0; 5],
+        }
+    }
+
+    /// Sets the manufacturer-specific error data.
+    pub fn with_manufacturer(mut self, data: [u8; 5]) -> Self {
+        self.manufacturer = data;
+        self
+    }
+
+    /// Gets the class of the error code.
+    pub fn class(&self) -> ErrorClass {
+        ErrorClass::from(self.error_code)
+    }
+
+    /// Determines if this message reports that the errors were cleared.
+    pub fn is_reset(&self) -> bool {
+        self.error_code == 0
+    }
+
+    /// Gets the route that matches the emergency messages from all nodes.
+    pub fn route() -> Route {
+        Route::Range {
+            ids: 0x081..=0x0FF,
+            extended: false,
+        }
+    }
+
+    /// Creates the emergency frame.
+    pub fn frame(&self) -> CanFrame {
+        let id = CanOpenId::new(FC_SYNC_EMCY, self.node_id);
+        let mut data = [0u8; 8];
+        data[..2].copy_from_slice(&self.error_code.to_le_bytes());
+        data[2] = self.error_register.bits();
+        data[3..].copy_from_slice(&self.manufacturer);
+        CanFrame::new(id.can_id(), &data).unwrap()
+    }
+
+    /// Parses an emergency frame.
+    ///
+    /// SYNC messages share the function code with node ID zero, and are
+    /// not emergencies.
+    pub fn from_frame<F: Frame>(frame: &F) -> Option<Self> {
+        let id = frame.can_id().canopen()?;
+        if id.function_code() != FC_SYNC_EMCY || id.node_id() == 0 || frame.is_remote_frame() {
+            return None;
+        }
+        let data = frame.data();
+        if data.len() != 8 {
+            return None;
+        }
+        let mut manufacturer = [0u8; 5];
+        manufacturer.copy_from_slice(&data[3..]);
+        Some(Self {
+            node_id: id.node_id(),
+            error_code: u16::from_le_bytes([data[0], data[1]]),
+            error_register: ErrorRegister::from_bits_truncate(data[2]),
+            manufacturer,
+        })
+    }
+}
+
+impl fmt::Display for Emergency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Node {} EMCY {:04X} ({}), register {:02X}, data ",
+            self.node_id,
+            self.error_code,
+            self.class(),
+            self.error_register.bits(),
+        )?;
+        write_hex_bytes(f, &self.manufacturer, true, None)
+    }
+}
+
+impl<'a, F: Frame> Dispatcher<'a, F> {
+    /// Adds a handler for the emergency messages from all nodes.
+    pub fn on_emergency<H>(&mut self, mut handler: H) -> HandlerId
+    where
+        H: FnMut(Emergency) + 'a,
+    {
+        self.on(Emergency::route(), move |frame: &F| {
+            if let Some(emcy) = Emergency::from_frame(frame) {
+                handler(emcy)
+            }
+        })
+    }
+}
+
+/// Gets a stream of the emergency messages in a stream of frames, such as
+/// from an async socket.
+///
+/// Other frames are skipped, and errors are passed through.
+#[cfg(feature = "tokio")]
+pub fn emergencies<S, F>(stream: S) -> impl futures::Stream<Item = std::io::Result<Emergency>>
+where
+    S: futures::Stream<Item = std::io::Result<F>>,
+    F: Frame,
+{
+    use futures::StreamExt;
+
+    stream.filter_map(|res| {
+        futures::future::ready(match res {
+            Ok(frame) => Emergency::from_frame(&frame).map(Ok),
+            Err(err) => Some(Err(err)),
+        })
+    })
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    #[test]
+    fn test_emergency() {
+        let emcy = Emergency::new(
+            5,
+            0x8130,
+            ErrorRegister::GENERIC | ErrorRegister::COMMUNICATION,
+        )
+        .with_manufacturer([1, 2, 3, 4, 5]);
+        let frame = emcy.frame();
+        assert_eq!(0x085, frame.raw_id());
+        assert_eq!(&[0x30, 0x81, 0x11, 1, 2, 3, 4, 5], frame.data());
+
+        assert_eq!(Some(emcy), Emergency::from_frame(&frame));
+        assert_eq!(ErrorClass::Monitoring, emcy.class());
+        assert!(!emcy.is_reset());
+
+        // SYNC
+        let sync = CanFrame::new(CanOpenId::new(FC_SYNC_EMCY, 0).can_id(), &[]).unwrap();
+        assert!(Emergency::from_frame(&sync).is_none());
+    }
+
+    #[test]
+    fn test_dispatch() {
+        let seen = RefCell::new(Vec::new());
+        let mut disp = Dispatcher::<CanFrame>::new();
+        disp.on_emergency(|emcy| seen.borrow_mut().push(emcy.node_id));
+
+        let emcy = Emergency::new(9, 0x0000, ErrorRegister::empty());
+        assert_eq!(1, disp.dispatch(&emcy.frame()));
+
+        let sync = CanFrame::new(CanOpenId::new(FC_SYNC_EMCY, 0).can_id(), &[]).unwrap();
+        assert_eq!(0, disp.dispatch(&sync));
+
+        drop(disp);
+        assert_eq!(vec![9], seen.into_inner());
+    }
+}
@@ -0,0 +1,450 @@
+// socketcan/src/canopen/lss.rs
+//
+// CANopen layer setting services.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! CANopen layer setting services (LSS).
+//!
+//! LSS (CiA 305) is used to commission devices that don't yet have a node
+//! ID or the right bit rate for the network. Each device is addressed by
+//! its identity: the vendor ID, product code, revision number and serial
+//! number from object 0x1018. The [`LssMaster`] switches a device into the
+//! configuration mode, then sets its node ID and bit rate, and stores them
+//! in its non-volatile memory.
+//!
+//! The master sends requests with the ID 0x7E5, and the devices reply with
+//! the ID 0x7E4. Only one device should be in the configuration mode when
+//! a service with a response is used. The LSS fastscan is not supported.
+//!
+//! ```no_run
+//! use socketcan::{
+//!     canopen::lss::{LssAddress, LssBitrate, LssMaster, LssMode},
+//!     CanSocket, Socket,
+//! };
+//!
+//! let sock = CanSocket::open("can0").unwrap();
+//! let lss = LssMaster::new(&sock);
+//!
+//! let addr = LssAddress::new(0x0000_0123, 0x0000_0456, 0x0001_0000, 0x1234_5678);
+//! lss.switch_mode_selective(&addr).unwrap();
+//! lss.configure_node_id(5).unwrap();
+//! lss.configure_bitrate(LssBitrate::Kbps500).unwrap();
+//! lss.store_configuration().unwrap();
+//! lss.switch_mode_global(LssMode::Waiting).unwrap();
+//! ```
+
+use crate::{
+    backend::{CanReceiver, CanTransmitter},
+    CanFrame, EmbeddedFrame, Frame, StandardId,
+};
+use std::{
+    io,
+    ops::RangeInclusive,
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+
+/// The ID of the requests from the LSS master
+pub const LSS_MASTER_ID: u16 = 0x7E5;
+/// The ID of the responses from LSS slaves
+pub const LSS_SLAVE_ID: u16 = 0x7E4;
+
+/// The default time to wait for a response from a device.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_millis(100);
+
+// Command specifiers
+const CS_SWITCH_GLOBAL: u8 = 0x04;
+const CS_CONFIGURE_NODE_ID: u8 = 0x11;
+const CS_CONFIGURE_BIT_TIMING: u8 = 0x13;
+const CS_ACTIVATE_BIT_TIMING: u8 = 0x15;
+const CS_STORE_CONFIGURATION: u8 = 0x17;
+const CS_SWITCH_SELECTIVE_VENDOR: u8 = 0x40;
+const CS_SWITCH_SELECTIVE_RESPONSE: u8 = 0x44;
+const CS_IDENTIFY_REMOTE_VENDOR: u8 = 0x46;
+const CS_IDENTIFY_NON_CONFIGURED: u8 = 0x4C;
+const CS_IDENTIFY_RESPONSE: u8 = 0x4F;
+const CS_IDENTIFY_NON_CONFIGURED_RESPONSE: u8 = 0x50;
+const CS_INQUIRE_VENDOR: u8 = 0x5A;
+const CS_INQUIRE_NODE_ID: u8 = 0x5E;
+
+/// An LSS message payload.
+type Msg = [u8; 8];
+
+/// Creates a message with the command specifier and a 32-bit value.
+fn msg(cs: u8, value: u32) -> Msg {
+    let mut m = [0u8; 8];
+    m[0] = cs;
+    m[1..5].copy_from_slice(&value.to_le_bytes());
+    m
+}
+
+/// An error from an LSS service.
+#[derive(Error, Debug)]
+pub enum LssError {
+    /// The device rejected the configuration
+    #[error("LSS configuration rejected, error {code} ({specific})")]
+    Rejected {
+        /// The error code: 1 if the value is out of range, or 255 for an
+        /// implementation-specific error
+        code: u8,
+        /// The implementation-specific error
+        specific: u8,
+    },
+    /// No device responded in time
+    #[error("LSS service timed out")]
+    Timeout,
+    /// An invalid value was given
+    #[error("LSS error: {0}")]
+    InvalidValue(&'static str),
+    /// An I/O error on the socket
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+/// Checks the response to a configuration service.
+fn check_config(resp: &Msg) -> Result<(), LssError> {
+    match resp[1] {
+        0 => Ok(()),
+        code => Err(LssError::Rejected {
+            code,
+            specific: resp[2],
+        }),
+    }
+}
+
+/// The LSS mode of a device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum LssMode {
+    /// The normal mode, in which the device only responds to switch and
+    /// identify services
+    Waiting = 0,
+    /// The mode in which the device can be configured
+    Configuration = 1,
+}
+
+/// The standard bit rates of the CiA 305 bit timing table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum LssBitrate {
+    /// 1 Mbit/s
+    Mbps1 = 0,
+    /// 800 kbit/s
+    Kbps800 = 1,
+    /// 500 kbit/s
+    Kbps500 = 2,
+    /// 250 kbit/s
+    Kbps250 = 3,
+    /// 125 kbit/s
+    Kbps125 = 4,
+    /// 50 kbit/s
+    Kbps50 = 6,
+    /// 20 kbit/s
+    Kbps20 = 7,
+    /// 10 kbit/s
+    Kbps10 = 8,
+    /// Automatic bit rate detection
+    Auto = 9,
+}
+
+impl LssBitrate {
+    /// Gets the bit rate in bits per second, if it's not automatic.
+    pub fn bitrate(&self) -> Option<u32> {
+        use LssBitrate::*;
+        match *self {
+            Mbps1 => Some(1_000_000),
+            Kbps800 => Some(800_000),
+            Kbps500 => Some(500_000),
+            Kbps250 => Some(250_000),
+            Kbps125 => Some(125_000),
+            Kbps50 => Some(50_000),
+            Kbps20 => Some(20_000),
+            Kbps10 => Some(10_000),
+            Auto => None,
+        }
+    }
+}
+
+impl TryFrom<u32> for LssBitrate {
+    type Error = LssError;
+
+    /// Gets the table entry for a bit rate, in bits per second.
+    fn try_from(bitrate: u32) -> Result<Self, Self::Error> {
+        use LssBitrate::*;
+        [
+            Mbps1, Kbps800, Kbps500, Kbps250, Kbps125, Kbps50, Kbps20, Kbps10,
+        ]
+        .into_iter()
+        .find(|b| b.bitrate() == Some(bitrate))
+        .ok_or(LssError::InvalidValue("bit rate not in the LSS table"))
+    }
+}
+
+/// The identity of a device, from its object 0x1018.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LssAddress {
+    /// The vendor ID
+    pub vendor_id: u32,
+    /// The product code
+    pub product_code: u32,
+    /// The revision number
+    pub revision: u32,
+    /// The serial number
+    pub serial: u32,
+}
+
+impl LssAddress {
+    /// Creates an LSS address.
+    pub fn new(vendor_id: u32, product_code: u32, revision: u32, serial: u32) -> Self {
+        Self {
+            vendor_id,
+            product_code,
+            revision,
+            serial,
+        }
+    }
+
+    /// Gets the messages to switch the device into the configuration mode.
+    fn switch_msgs(&self) -> [Msg; 4] {
+        let cs = CS_SWITCH_SELECTIVE_VENDOR;
+        [
+            msg(cs, self.vendor_id),
+            msg(cs + 1, self.product_code),
+            msg(cs + 2, self.revision),
+            msg(cs + 3, self.serial),
+        ]
+    }
+}
+
+/// A range of devices to identify, by their identity.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LssRange {
+    /// The vendor ID
+    pub vendor_id: u32,
+    /// The product code
+    pub product_code: u32,
+    /// The range of revision numbers
+    pub revision: RangeInclusive<u32>,
+    /// The range of serial numbers
+    pub serial: RangeInclusive<u32>,
+}
+
+impl LssRange {
+    /// Gets the identify remote slave messages for the range.
+    fn identify_msgs(&self) -> [Msg; 6] {
+        let cs = CS_IDENTIFY_REMOTE_VENDOR;
+        [
+            msg(cs, self.vendor_id),
+            msg(cs + 1, self.product_code),
+            msg(cs + 2, *self.revision.start()),
+            msg(cs + 3, *self.revision.end()),
+            msg(cs + 4, *self.serial.start()),
+            msg(cs + 5, *self.serial.end()),
+        ]
+    }
+}
+
+// ===== LssMaster =====
+
+/// An LSS master, to commission devices over a socket.
+#[derive(Debug)]
+pub struct LssMaster<'a, S> {
+    sock: &'a S,
+    timeout: Duration,
+}
+
+impl<'a, S> LssMaster<'a, S>
+where
+    S: CanTransmitter<CanFrame> + CanReceiver,
+    S::Frame: Frame,
+{
+    /// Creates an LSS master on the socket.
+    pub fn new(sock: &'a S) -> Self {
+        Self {
+            sock,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Sets the time to wait for a response from a device.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sends a request.
+    fn send(&self, req: &Msg) -> Result<(), LssError> {
+        let id = StandardId::new(LSS_MASTER_ID).unwrap();
+        self.sock.transmit(&CanFrame::new(id, req).unwrap())?;
+        Ok(())
+    }
+
+    /// Waits for a response with the command specifier.
+    fn wait(&self, cs: u8) -> Result<Msg, LssError> {
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(LssError::Timeout);
+            }
+            let frame = match self.sock.receive_timeout(remaining) {
+                Ok(frame) => frame,
+                Err(err) if err.kind() == io::ErrorKind::TimedOut => return Err(LssError::Timeout),
+                Err(err) => return Err(err.into()),
+            };
+            if frame.raw_id() == u32::from(LSS_SLAVE_ID)
+                && !frame.is_extended()
+                && !frame.is_remote_frame()
+            {
+                if let Ok(resp) = Msg::try_from(frame.data()) {
+                    if resp[0] == cs {
+                        return Ok(resp);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Sends a request, and waits for the response.
+    fn request(&self, req: &Msg) -> Result<Msg, LssError> {
+        self.send(req)?;
+        self.wait(req[0])
+    }
+
+    /// Switches the mode of all the devices.
+    pub fn switch_mode_global(&self, mode: LssMode) -> Result<(), LssError> {
+        self.send(&msg(CS_SWITCH_GLOBAL, mode as u32))
+    }
+
+    /// Switches the device with the address into the configuration mode.
+    pub fn switch_mode_selective(&self, addr: &LssAddress) -> Result<(), LssError> {
+        for req in addr.switch_msgs() {
+            self.send(&req)?;
+        }
+        self.wait(CS_SWITCH_SELECTIVE_RESPONSE).map(|_| ())
+    }
+
+    /// Sets the node ID of the device in the configuration mode.
+    ///
+    /// The ID 255 makes the device unconfigured.
+    pub fn configure_node_id(&self, node_id: u8) -> Result<(), LssError> {
+        if node_id == 0 || (node_id > super::MAX_NODE_ID && node_id != 0xFF) {
+            return Err(LssError::InvalidValue("invalid node ID"));
+        }
+        check_config(&self.request(&msg(CS_CONFIGURE_NODE_ID, node_id.into()))?)
+    }
+
+    /// Sets the bit rate of the device in the configuration mode.
+    ///
+    /// The new bit rate is used after it's activated, or the device is
+    /// reset.
+    pub fn configure_bitrate(&self, bitrate: LssBitrate) -> Result<(), LssError> {
+        let mut req = [0u8; 8];
+        req[0] = CS_CONFIGURE_BIT_TIMING;
+        req[2] = bitrate as u8;
+        check_config(&self.request(&req)?)
+    }
+
+    /// Makes all the devices in the configuration mode switch to their
+    /// new bit rate.
+    ///
+    /// The devices stop transmitting for the delay, switch, then wait for
+    /// the delay again before transmitting with the new bit rate.
+    pub fn activate_bitrate(&self, delay: Duration) -> Result<(), LssError> {
+        let delay = u16::try_from(delay.as_millis())
+            .map_err(|_| LssError::InvalidValue("switch delay too long"))?;
+        self.send(&msg(CS_ACTIVATE_BIT_TIMING, delay.into()))
+    }
+
+    /// Stores the configuration of the device in the configuration mode to
+    /// its non-volatile memory.
+    pub fn store_configuration(&self) -> Result<(), LssError> {
+        check_config(&self.request(&msg(CS_STORE_CONFIGURATION, 0))?)
+    }
+
+    /// Reads the identity of the device in the configuration mode.
+    pub fn inquire_identity(&self) -> Result<LssAddress, LssError> {
+        let mut vals = [0u32; 4];
+        for (i, val) in vals.iter_mut().enumerate() {
+            let resp = self.request(&msg(CS_INQUIRE_VENDOR + i as u8, 0))?;
+            *val = u32::from_le_bytes([resp[1], resp[2], resp[3], resp[4]]);
+        }
+        Ok(LssAddress::new(vals[0], vals[1], vals[2], vals[3]))
+    }
+
+    /// Reads the node ID of the device in the configuration mode.
+    ///
+    /// An unconfigured device has the ID 255.
+    pub fn inquire_node_id(&self) -> Result<u8, LssError> {
+        Ok(self.request(&msg(CS_INQUIRE_NODE_ID, 0))?[1])
+    }
+
+    /// Determines if there are any devices in the range of identities.
+    pub fn identify_remote(&self, range: &LssRange) -> Result<bool, LssError> {
+        for req in range.identify_msgs() {
+            self.send(&req)?;
+        }
+        Self::found(self.wait(CS_IDENTIFY_RESPONSE))
+    }
+
+    /// Determines if there are any devices without a node ID.
+    pub fn identify_non_configured(&self) -> Result<bool, LssError> {
+        self.send(&msg(CS_IDENTIFY_NON_CONFIGURED, 0))?;
+        Self::found(self.wait(CS_IDENTIFY_NON_CONFIGURED_RESPONSE))
+    }
+
+    fn found(res: Result<Msg, LssError>) -> Result<bool, LssError> {
+        match res {
+            Ok(_) => Ok(true),
+            Err(LssError::Timeout) => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_msgs() {
+        let addr = LssAddress::new(0x0000_0123, 0x0000_0456, 0x0001_0000, 0x1234_5678);
+        let msgs = addr.switch_msgs();
+        assert_eq!([0x40, 0x23, 0x01, 0, 0, 0, 0, 0], msgs[0]);
+        assert_eq!([0x43, 0x78, 0x56, 0x34, 0x12, 0, 0, 0], msgs[3]);
+
+        let range = LssRange {
+            vendor_id: 0x123,
+            product_code: 0x456,
+            revision: 0..=0xFFFF_FFFF,
+            serial: 0x100..=0x1FF,
+        };
+        let msgs = range.identify_msgs();
+        assert_eq!([0x49, 0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0], msgs[3]);
+        assert_eq!([0x4B, 0xFF, 0x01, 0, 0, 0, 0, 0], msgs[5]);
+
+        assert!(check_config(&[0x11, 0, 0, 0, 0, 0, 0, 0]).is_ok());
+        assert!(matches!(
+            check_config(&[0x11, 0xFF, 0x42, 0, 0, 0, 0, 0]),
+            Err(LssError::Rejected {
+                code: 0xFF,
+                specific: 0x42
+            })
+        ));
+    }
+
+    #[test]
+    fn test_bitrate() {
+        assert_eq!(LssBitrate::Kbps250, LssBitrate::try_from(250_000).unwrap());
+        assert_eq!(Some(50_000), LssBitrate::Kbps50.bitrate());
+        assert!(LssBitrate::try_from(100_000).is_err());
+    }
+}
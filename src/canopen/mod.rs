@@ -0,0 +1,68 @@
+// socketcan/src/canopen/mod.rs
+//
+// CANopen protocol support.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! CANopen
+//!
+//! CANopen (CiA 301) is a higher-layer protocol over classic CAN, widely
+//! used in industrial automation, motion control, and medical devices.
+//! Each device on the bus is a node with a 7-bit node ID, and the 11-bit
+//! CAN ID of each message is made of a 4-bit function code and the node ID,
+//! as represented by a [`CanOpenId`](crate::CanOpenId).
+//!
+//! This module covers the services used to supervise and configure the
+//! devices on a bus, built on the frame types of this crate, so they can
+//! be used with any of the sockets, and with the dispatcher and scheduler.
+//!
+//! - [`nmt`] - Network management commands, and heartbeat production and
+//!   consumption to track the state of each node.
+//! - [`sdo`] - A client to read and write the object dictionary of a node.
+//! - [`pdo`] - Mapping descriptions to decode and create process data.
+//! - [`emcy`] - Emergency messages reporting device errors.
+//! - [`lss`] - Layer setting services, to set the node ID and bit rate of
+//!   unconfigured devices.
+
+pub mod emcy;
+pub mod lss;
+pub mod nmt;
+pub mod pdo;
+pub mod sdo;
+
+/// The function code of NMT commands
+pub const FC_NMT: u8 = 0x0;
+/// The function code of SYNC (node 0) and emergency (EMCY) messages
+pub const FC_SYNC_EMCY: u8 = 0x1;
+/// The function code of TIME messages
+pub const FC_TIME: u8 = 0x2;
+/// The function code of the first transmit PDO
+pub const FC_TPDO1: u8 = 0x3;
+/// The function code of the first receive PDO
+pub const FC_RPDO1: u8 = 0x4;
+/// The function code of the second transmit PDO
+pub const FC_TPDO2: u8 = 0x5;
+/// The function code of the second receive PDO
+pub const FC_RPDO2: u8 = 0x6;
+/// The function code of the third transmit PDO
+pub const FC_TPDO3: u8 = 0x7;
+/// The function code of the third receive PDO
+pub const FC_RPDO3: u8 = 0x8;
+/// The function code of the fourth transmit PDO
+pub const FC_TPDO4: u8 = 0x9;
+/// The function code of the fourth receive PDO
+pub const FC_RPDO4: u8 = 0xA;
+/// The function code of SDO responses, from the server to the client
+pub const FC_SDO_TX: u8 = 0xB;
+/// The function code of SDO requests, from the client to the server
+pub const FC_SDO_RX: u8 = 0xC;
+/// The function code of heartbeat and boot-up messages
+pub const FC_HEARTBEAT: u8 = 0xE;
+
+/// The highest valid node ID
+pub const MAX_NODE_ID: u8 = 127;
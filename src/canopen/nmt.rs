@@ -0,0 +1,470 @@
+// socketcan/src/canopen/nmt.rs
+//
+// CANopen network management and heartbeats.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! CANopen network management (NMT) and heartbeats.
+//!
+//! The NMT master controls the state of the nodes on the bus with
+//! [`NmtCommand`] messages, and each node reports its [`NmtState`] in a
+//! periodic heartbeat. The [`HeartbeatProducer`] generates the heartbeats
+//! for a local node, and the [`NodeMonitor`] consumes them to track the
+//! state of remote nodes, and reports any that stop sending them.
+//!
+//! ```no_run
+//! use socketcan::{
+//!     canopen::nmt::{NmtCommand, NodeEvent, NodeMonitor},
+//!     CanSocket, Socket,
+//! };
+//! use std::time::{Duration, Instant};
+//!
+//! let sock = CanSocket::open("can0").unwrap();
+//! sock.write_frame(&NmtCommand::Start.frame(0)).unwrap();
+//!
+//! let mut mon = NodeMonitor::new();
+//! mon.add_node(5, Duration::from_millis(1500));
+//!
+//! loop {
+//!     if let Ok(frame) = sock.read_frame_timeout(Duration::from_millis(100)) {
+//!         if let Some(ev) = mon.process(&frame, Instant::now()) {
+//!             println!("{:?}", ev);
+//!         }
+//!     }
+//!     for ev in mon.check_timeouts(Instant::now()) {
+//!         println!("{:?}", ev);
+//!     }
+//! }
+//! ```
+
+use super::{FC_HEARTBEAT, FC_NMT};
+use crate::{CanFrame, CanOpenId, EmbeddedFrame, Frame};
+use std::{collections::BTreeMap, fmt, time::Duration, time::Instant};
+
+/// An NMT command from the master to one or all nodes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum NmtCommand {
+    /// Go to the Operational state
+    Start = 0x01,
+    /// Go to the Stopped state
+    Stop = 0x02,
+    /// Go to the Pre-operational state
+    EnterPreOperational = 0x80,
+    /// Reset the application
+    ResetNode = 0x81,
+    /// Reset the communication parameters
+    ResetCommunication = 0x82,
+}
+
+impl NmtCommand {
+    /// Creates the frame to send the command to a node, or to all of the
+    /// nodes if the node ID is zero.
+    pub fn frame(&self, node_id: u8) -> CanFrame {
+        let id = CanOpenId::new(FC_NMT, 0);
+        CanFrame::new(id.can_id(), &[*self as u8, node_id]).unwrap()
+    }
+
+    /// Parses an NMT command frame, returning the command and the node ID
+    /// it's addressed to.
+    pub fn from_frame<F: Frame>(frame: &F) -> Option<(Self, u8)> {
+        if frame.raw_id() != 0 || frame.is_extended() || frame.is_remote_frame() {
+            return None;
+        }
+        match *frame.data() {
+            [cmd, node, ..] => Some((Self::try_from(cmd).ok()?, node)),
+            _ => None,
+        }
+    }
+}
+
+impl TryFrom<u8> for NmtCommand {
+    type Error = u8;
+
+    fn try_from(val: u8) -> Result<Self, Self::Error> {
+        use NmtCommand::*;
+        match val {
+            0x01 => Ok(Start),
+            0x02 => Ok(Stop),
+            0x80 => Ok(EnterPreOperational),
+            0x81 => Ok(ResetNode),
+            0x82 => Ok(ResetCommunication),
+            _ => Err(val),
+        }
+    }
+}
+
+/// The NMT state of a node, as reported in its heartbeat.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum NmtState {
+    /// The node just booted, and is going to Pre-operational
+    BootUp = 0x00,
+    /// Stopped; only NMT and heartbeats are active
+    Stopped = 0x04,
+    /// Operational; all services are active
+    Operational = 0x05,
+    /// Pre-operational; all services but PDOs are active
+    PreOperational = 0x7F,
+}
+
+impl TryFrom<u8> for NmtState {
+    type Error = u8;
+
+    /// Converts the state byte of a heartbeat, ignoring the toggle bit
+    /// used by node guarding.
+    fn try_from(val: u8) -> Result<Self, Self::Error> {
+        use NmtState::*;
+        match val & 0x7F {
+            0x00 => Ok(BootUp),
+            0x04 => Ok(Stopped),
+            0x05 => Ok(Operational),
+            0x7F => Ok(PreOperational),
+            _ => Err(val),
+        }
+    }
+}
+
+impl fmt::Display for NmtState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use NmtState::*;
+        let s = match self {
+            BootUp => "Boot-up",
+            Stopped => "Stopped",
+            Operational => "Operational",
+            PreOperational => "Pre-operational",
+        };
+        f.write_str(s)
+    }
+}
+
+// ===== Heartbeat =====
+
+/// A heartbeat (or boot-up) message from a node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Heartbeat {
+    /// The ID of the node that sent the heartbeat
+    pub node_id: u8,
+    /// The state of the node
+    pub state: NmtState,
+}
+
+impl Heartbeat {
+    /// Creates a heartbeat for the node.
+    pub fn new(node_id: u8, state: NmtState) -> Self {
+        Self { node_id, state }
+    }
+
+    /// Creates the heartbeat frame.
+    pub fn frame(&self) -> CanFrame {
+        let id = CanOpenId::new(FC_HEARTBEAT, self.node_id);
+        CanFrame::new(id.can_id(), &[self.state as u8]).unwrap()
+    }
+
+    /// Parses a heartbeat frame.
+    pub fn from_frame<F: Frame>(frame: &F) -> Option<Self> {
+        let id = frame.can_id().canopen()?;
+        if id.function_code() != FC_HEARTBEAT || id.node_id() == 0 || frame.is_remote_frame() {
+            return None;
+        }
+        match *frame.data() {
+            [state] => Some(Self::new(id.node_id(), NmtState::try_from(state).ok()?)),
+            _ => None,
+        }
+    }
+}
+
+/// The producer of the heartbeats of a local node.
+///
+/// This keeps the state of the node, and the time the next heartbeat is
+/// due. It can be polled from an application loop, or its frames can be
+/// sent by a [`Scheduler`](crate::scheduler::Scheduler).
+#[derive(Debug, Clone, Copy)]
+pub struct HeartbeatProducer {
+    node_id: u8,
+    state: NmtState,
+    period: Duration,
+    next: Option<Instant>,
+}
+
+impl HeartbeatProducer {
+    /// Creates a producer for the node, which starts in the boot-up state.
+    pub fn new(node_id: u8, period: Duration) -> Self {
+        Self {
+            node_id,
+            state: NmtState::BootUp,
+            period,
+            next: None,
+        }
+    }
+
+    /// Gets the current state of the node.
+    pub fn state(&self) -> NmtState {
+        self.state
+    }
+
+    /// Sets the state of the node.
+    pub fn set_state(&mut self, state: NmtState) {
+        self.state = state;
+    }
+
+    /// Gets the heartbeat period.
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+
+    /// Applies an NMT command, if it's addressed to this node.
+    ///
+    /// The reset commands put the node back into the boot-up state, so the
+    /// next heartbeat is a boot-up message.
+    pub fn apply(&mut self, cmd: NmtCommand, node_id: u8) {
+        if node_id != 0 && node_id != self.node_id {
+            return;
+        }
+        self.state = match cmd {
+            NmtCommand::Start => NmtState::Operational,
+            NmtCommand::Stop => NmtState::Stopped,
+            NmtCommand::EnterPreOperational => NmtState::PreOperational,
+            NmtCommand::ResetNode | NmtCommand::ResetCommunication => {
+                self.next = None;
+                NmtState::BootUp
+            }
+        };
+    }
+
+    /// Gets the current heartbeat frame.
+    pub fn frame(&self) -> CanFrame {
+        Heartbeat::new(self.node_id, self.state).frame()
+    }
+
+    /// Gets the heartbeat frame if one is due at `now`.
+    ///
+    /// The boot-up message is sent immediately, and after it the node goes
+    /// to the Pre-operational state, as required by the specification.
+    pub fn poll(&mut self, now: Instant) -> Option<CanFrame> {
+        if self.next.is_some_and(|next| now < next) {
+            return None;
+        }
+        let frame = self.frame();
+        if self.state == NmtState::BootUp {
+            self.state = NmtState::PreOperational;
+        }
+        self.next = Some(now + self.period);
+        Some(frame)
+    }
+}
+
+// ===== NodeMonitor =====
+
+/// A change in a node, reported by the [`NodeMonitor`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NodeEvent {
+    /// The node booted, or was reset
+    BootUp(u8),
+    /// The node reported a new state
+    StateChanged {
+        /// The node ID
+        node_id: u8,
+        /// The previous state, if known
+        from: Option<NmtState>,
+        /// The new state
+        to: NmtState,
+    },
+    /// The node didn't send a heartbeat within its timeout
+    Timeout(u8),
+    /// The node sent a heartbeat after timing out
+    Recovered(u8),
+}
+
+/// The tracking state of a remote node.
+#[derive(Debug, Clone, Copy)]
+struct NodeInfo {
+    state: Option<NmtState>,
+    timeout: Option<Duration>,
+    last_seen: Option<Instant>,
+    timed_out: bool,
+}
+
+/// A heartbeat consumer, tracking the state of the nodes on the bus.
+///
+/// Nodes are tracked when their first heartbeat is received, or when they
+/// are added with a heartbeat timeout. A node with a timeout is reported
+/// once if it misses its heartbeat, and again when it recovers. The
+/// timeout is normally a bit longer than the producer's period.
+#[derive(Debug, Clone, Default)]
+pub struct NodeMonitor {
+    nodes: BTreeMap<u8, NodeInfo>,
+}
+
+impl NodeMonitor {
+    /// Creates a monitor that isn't tracking any nodes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Monitors the node for heartbeat timeouts.
+    ///
+    /// The timer starts with the first heartbeat from the node.
+    pub fn add_node(&mut self, node_id: u8, timeout: Duration) {
+        self.nodes
+            .entry(node_id)
+            .or_insert(NodeInfo {
+                state: None,
+                timeout: None,
+                last_seen: None,
+                timed_out: false,
+            })
+            .timeout = Some(timeout);
+    }
+
+    /// Stops tracking the node.
+    pub fn remove_node(&mut self, node_id: u8) {
+        self.nodes.remove(&node_id);
+    }
+
+    /// Gets the last known state of the node.
+    pub fn state(&self, node_id: u8) -> Option<NmtState> {
+        self.nodes.get(&node_id).and_then(|info| info.state)
+    }
+
+    /// Determines if the node has missed its heartbeat.
+    pub fn is_timed_out(&self, node_id: u8) -> bool {
+        self.nodes.get(&node_id).is_some_and(|info| info.timed_out)
+    }
+
+    /// Gets the IDs and last known states of all the tracked nodes.
+    pub fn nodes(&self) -> impl Iterator<Item = (u8, Option<NmtState>)> + '_ {
+        self.nodes.iter().map(|(id, info)| (*id, info.state))
+    }
+
+    /// Processes a received frame, returning an event if it's a heartbeat
+    /// that changes the state of a node.
+    pub fn process<F: Frame>(&mut self, frame: &F, now: Instant) -> Option<NodeEvent> {
+        let hb = Heartbeat::from_frame(frame)?;
+        let info = self.nodes.entry(hb.node_id).or_insert(NodeInfo {
+            state: None,
+            timeout: None,
+            last_seen: None,
+            timed_out: false,
+        });
+
+        info.last_seen = Some(now);
+        let from = info.state.replace(hb.state);
+
+        if hb.state == NmtState::BootUp {
+            info.timed_out = false;
+            Some(NodeEvent::BootUp(hb.node_id))
+        } else if info.timed_out {
+            info.timed_out = false;
+            Some(NodeEvent::Recovered(hb.node_id))
+        } else if from != Some(hb.state) {
+            Some(NodeEvent::StateChanged {
+                node_id: hb.node_id,
+                from,
+                to: hb.state,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Checks for nodes that missed their heartbeats by `now`.
+    ///
+    /// Each timeout is only reported once, until the node recovers.
+    pub fn check_timeouts(&mut self, now: Instant) -> Vec<NodeEvent> {
+        self.nodes
+            .iter_mut()
+            .filter_map(|(id, info)| {
+                let expired = match (info.timeout, info.last_seen) {
+                    (Some(timeout), Some(last)) => now.saturating_duration_since(last) > timeout,
+                    _ => false,
+                };
+                (expired && !info.timed_out).then(|| {
+                    info.timed_out = true;
+                    NodeEvent::Timeout(*id)
+                })
+            })
+            .collect()
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nmt_frames() {
+        let frame = NmtCommand::ResetNode.frame(5);
+        assert_eq!(0, frame.raw_id());
+        assert_eq!(&[0x81, 5], frame.data());
+        assert_eq!(
+            Some((NmtCommand::ResetNode, 5)),
+            NmtCommand::from_frame(&frame)
+        );
+
+        let frame = Heartbeat::new(5, NmtState::Operational).frame();
+        assert_eq!(0x705, frame.raw_id());
+        assert_eq!(&[0x05], frame.data());
+        assert_eq!(
+            Some(Heartbeat::new(5, NmtState::Operational)),
+            Heartbeat::from_frame(&frame)
+        );
+
+        let mut prod = HeartbeatProducer::new(5, Duration::from_millis(100));
+        let t0 = Instant::now();
+        assert_eq!(&[0x00], prod.poll(t0).unwrap().data());
+        assert_eq!(NmtState::PreOperational, prod.state());
+        assert!(prod.poll(t0 + Duration::from_millis(50)).is_none());
+        prod.apply(NmtCommand::Start, 0);
+        assert_eq!(
+            &[0x05],
+            prod.poll(t0 + Duration::from_millis(100)).unwrap().data()
+        );
+    }
+
+    #[test]
+    fn test_node_monitor() {
+        let t0 = Instant::now();
+        let ms = Duration::from_millis;
+
+        let mut mon = NodeMonitor::new();
+        mon.add_node(5, ms(150));
+
+        let boot = Heartbeat::new(5, NmtState::BootUp).frame();
+        let preop = Heartbeat::new(5, NmtState::PreOperational).frame();
+        let op = Heartbeat::new(5, NmtState::Operational).frame();
+
+        assert_eq!(Some(NodeEvent::BootUp(5)), mon.process(&boot, t0));
+        assert_eq!(
+            Some(NodeEvent::StateChanged {
+                node_id: 5,
+                from: Some(NmtState::BootUp),
+                to: NmtState::PreOperational
+            }),
+            mon.process(&preop, t0 + ms(10))
+        );
+        assert_eq!(None, mon.process(&preop, t0 + ms(110)));
+        assert!(mon.check_timeouts(t0 + ms(200)).is_empty());
+
+        assert_eq!(
+            vec![NodeEvent::Timeout(5)],
+            mon.check_timeouts(t0 + ms(300))
+        );
+        assert!(mon.check_timeouts(t0 + ms(400)).is_empty());
+        assert!(mon.is_timed_out(5));
+
+        assert_eq!(
+            Some(NodeEvent::Recovered(5)),
+            mon.process(&op, t0 + ms(500))
+        );
+        assert_eq!(Some(NmtState::Operational), mon.state(5));
+        assert_eq!(None, mon.process(&NmtCommand::Start.frame(0), t0));
+    }
+}
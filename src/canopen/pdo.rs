@@ -0,0 +1,364 @@
+// socketcan/src/canopen/pdo.rs
+//
+// CANopen PDO mapping and decoding.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! CANopen process data objects (PDO).
+//!
+//! PDOs carry the real-time process data of a node. Each PDO is a frame
+//! with a fixed COB-ID, whose payload is made of the values of objects
+//! from the node's object dictionary, packed back to back in little-endian
+//! order, as described by the PDO's mapping. A node sends its transmit
+//! PDOs (TPDO), and receives its receive PDOs (RPDO).
+//!
+//! A [`Pdo`] describes the communication parameters and mapping of a PDO.
+//! It can decode a received PDO into the values of the mapped objects,
+//! create a PDO frame from object values, and configure the PDO on a node
+//! through an [`SdoClient`]. Each mapped object is a
+//! [`Signal`](crate::signal::Signal) in the payload.
+//!
+//! ```no_run
+//! use socketcan::{canopen::pdo::Pdo, CanSocket, Socket};
+//!
+//! // TPDO1 of node 5 with a 16-bit status word and a 32-bit position
+//! let tpdo = Pdo::tpdo(1, 5).with_mapping(0x6041, 0, 16).with_mapping(0x6064, 0, 32);
+//!
+//! let sock = CanSocket::open("can0").unwrap();
+//! loop {
+//!     let frame = sock.read_frame().unwrap();
+//!     if let Some(values) = tpdo.decode_frame(&frame) {
+//!         println!("Status: {:04X}, Position: {}", values[0].value, values[1].value as i32);
+//!     }
+//! }
+//! ```
+
+use super::sdo::{SdoClient, SdoError};
+use crate::{
+    backend::{CanReceiver, CanTransmitter},
+    signal::{insert_bits, ByteOrder, Signal, SignalError},
+    CanFrame, CanId, EmbeddedFrame, Frame, StandardId,
+};
+use thiserror::Error;
+
+/// The flag in a COB-ID parameter that disables the PDO
+pub const COB_ID_INVALID: u32 = 0x8000_0000;
+
+/// An error describing or encoding a PDO.
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PdoError {
+    /// The mapped objects are longer than the 64-bit payload
+    #[error("PDO mapping is {0} bits, but the maximum is 64")]
+    MappingTooLong(u32),
+    /// The number of values doesn't match the number of mapped objects
+    #[error("expected {expected} PDO values, but got {got}")]
+    ValueCount {
+        /// The number of mapped objects
+        expected: usize,
+        /// The number of values given
+        got: usize,
+    },
+    /// A value couldn't be packed into the payload
+    #[error(transparent)]
+    Signal(#[from] SignalError),
+}
+
+/// The direction of a PDO, from the point of view of the node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PdoKind {
+    /// A transmit PDO, sent by the node
+    Transmit,
+    /// A receive PDO, received by the node
+    Receive,
+}
+
+/// The mapping of an object into a PDO.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PdoMapping {
+    /// The index of the object
+    pub index: u16,
+    /// The sub-index of the object
+    pub subindex: u8,
+    /// The length of the object, in bits
+    pub bits: u8,
+}
+
+impl PdoMapping {
+    /// Creates a mapping for an object.
+    pub fn new(index: u16, subindex: u8, bits: u8) -> Self {
+        Self {
+            index,
+            subindex,
+            bits,
+        }
+    }
+
+    /// Gets a mapping from its 32-bit mapping parameter.
+    pub fn from_raw(raw: u32) -> Self {
+        Self::new((raw >> 16) as u16, (raw >> 8) as u8, raw as u8)
+    }
+
+    /// Gets the 32-bit mapping parameter, as written to the object
+    /// dictionary.
+    pub fn to_raw(&self) -> u32 {
+        (u32::from(self.index) << 16) | (u32::from(self.subindex) << 8) | u32::from(self.bits)
+    }
+}
+
+/// The value of a mapped object, decoded from a PDO.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PdoValue {
+    /// The index of the object
+    pub index: u16,
+    /// The sub-index of the object
+    pub subindex: u8,
+    /// The raw value of the object
+    pub value: u64,
+}
+
+// ===== Pdo =====
+
+/// The description of a PDO: its COB-ID, transmission type, and mapping.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Pdo {
+    /// Whether the PDO is transmitted or received by the node
+    pub kind: PdoKind,
+    /// The PDO number, starting at one
+    pub number: u16,
+    /// The CAN ID of the PDO
+    pub cob_id: CanId,
+    /// The transmission type
+    pub transmission_type: u8,
+    /// The objects mapped into the PDO, in order
+    pub mappings: Vec<PdoMapping>,
+}
+
+impl Pdo {
+    /// Creates a PDO with an explicit COB-ID.
+    ///
+    /// The transmission type defaults to 255, for event-driven PDOs.
+    pub fn new(kind: PdoKind, number: u16, cob_id: CanId) -> Self {
+        Self {
+            kind,
+            number,
+            cob_id,
+            transmission_type: 0xFF,
+            mappings: Vec::new(),
+        }
+    }
+
+    /// Creates one of the first four transmit PDOs of a node, with its
+    /// default COB-ID.
+    ///
+    /// # Panics
+    ///
+    /// If the number is not 1 to 4.
+    pub fn tpdo(number: u16, node_id: u8) -> Self {
+        Self::new(
+            PdoKind::Transmit,
+            number,
+            Self::default_cob_id(0x180, number, node_id),
+        )
+    }
+
+    /// Creates one of the first four receive PDOs of a node, with its
+    /// default COB-ID.
+    ///
+    /// # Panics
+    ///
+    /// If the number is not 1 to 4.
+    pub fn rpdo(number: u16, node_id: u8) -> Self {
+        Self::new(
+            PdoKind::Receive,
+            number,
+            Self::default_cob_id(0x200, number, node_id),
+        )
+    }
+
+    fn default_cob_id(base: u16, number: u16, node_id: u8) -> CanId {
+        assert!((1..=4).contains(&number), "no default COB-ID for the PDO");
+        let id = base + 0x100 * (number - 1) + u16::from(node_id & 0x7F);
+        StandardId::new(id).unwrap().into()
+    }
+
+    /// Sets the transmission type.
+    pub fn with_transmission_type(mut self, transmission_type: u8) -> Self {
+        self.transmission_type = transmission_type;
+        self
+    }
+
+    /// Maps the next object into the PDO.
+    pub fn with_mapping(mut self, index: u16, subindex: u8, bits: u8) -> Self {
+        self.mappings.push(PdoMapping::new(index, subindex, bits));
+        self
+    }
+
+    /// Gets the total length of the mapped objects, in bits.
+    pub fn bit_len(&self) -> u32 {
+        self.mappings.iter().map(|m| u32::from(m.bits)).sum()
+    }
+
+    /// Checks that the mapped objects fit in the payload.
+    pub fn validate(&self) -> Result<(), PdoError> {
+        match self.bit_len() {
+            n if n > 64 => Err(PdoError::MappingTooLong(n)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Gets the signals of the mapped objects in the payload.
+    pub fn signals(&self) -> impl Iterator<Item = (PdoMapping, Signal)> + '_ {
+        self.mappings.iter().scan(0u16, |start, m| {
+            let sig = Signal::new(*start, u16::from(m.bits), ByteOrder::LittleEndian);
+            *start += u16::from(m.bits);
+            Some((*m, sig))
+        })
+    }
+
+    /// Decodes the payload into the raw values of the mapped objects.
+    ///
+    /// This returns `None` if the payload is too short for the mapping.
+    pub fn decode(&self, data: &[u8]) -> Option<Vec<PdoValue>> {
+        self.signals()
+            .map(|(m, sig)| {
+                Some(PdoValue {
+                    index: m.index,
+                    subindex: m.subindex,
+                    value: sig.decode_raw(data)? as u64,
+                })
+            })
+            .collect()
+    }
+
+    /// Decodes a frame, if it's this PDO.
+    pub fn decode_frame<F: Frame>(&self, frame: &F) -> Option<Vec<PdoValue>> {
+        if frame.can_id() != self.cob_id || frame.is_remote_frame() {
+            return None;
+        }
+        self.decode(frame.data())
+    }
+
+    /// Creates the PDO frame from the raw values of the mapped objects, in
+    /// the order of the mapping.
+    ///
+    /// Values are truncated to the length of their objects.
+    pub fn encode(&self, values: &[u64]) -> Result<CanFrame, PdoError> {
+        self.validate()?;
+        if values.len() != self.mappings.len() {
+            return Err(PdoError::ValueCount {
+                expected: self.mappings.len(),
+                got: values.len(),
+            });
+        }
+
+        let mut data = [0u8; 8];
+        let len = ((self.bit_len() + 7) / 8) as usize;
+        for ((_, sig), val) in self.signals().zip(values) {
+            if sig.len > 0 {
+                insert_bits(&mut data[..len], sig.start, sig.len, sig.order, *val)?;
+            }
+        }
+        Ok(CanFrame::new(self.cob_id, &data[..len]).unwrap())
+    }
+
+    /// Gets the index of the communication parameter record of the PDO in
+    /// the object dictionary.
+    pub fn comm_index(&self) -> u16 {
+        let base = match self.kind {
+            PdoKind::Receive => 0x1400,
+            PdoKind::Transmit => 0x1800,
+        };
+        base + self.number - 1
+    }
+
+    /// Gets the index of the mapping parameter record of the PDO in the
+    /// object dictionary.
+    pub fn mapping_index(&self) -> u16 {
+        self.comm_index() + 0x200
+    }
+
+    /// Configures the PDO on the node through its SDO server.
+    ///
+    /// This follows the procedure of CiA 301: the PDO is disabled, the
+    /// transmission type and mapping are written, then the PDO is enabled
+    /// with its COB-ID.
+    pub fn configure<S>(&self, client: &SdoClient<'_, S>) -> Result<(), SdoError>
+    where
+        S: CanTransmitter<CanFrame> + CanReceiver,
+        S::Frame: Frame,
+    {
+        self.validate()
+            .map_err(|_| SdoError::Protocol("PDO mapping is too long"))?;
+
+        let (comm, map) = (self.comm_index(), self.mapping_index());
+        let cob_id = self.cob_id.as_raw()
+            | if self.cob_id.is_extended() {
+                1 << 29
+            } else {
+                0
+            };
+
+        client.download(comm, 1, &(cob_id | COB_ID_INVALID).to_le_bytes())?;
+        client.download(comm, 2, &[self.transmission_type])?;
+        client.download(map, 0, &[0])?;
+        for (i, m) in self.mappings.iter().enumerate() {
+            client.download(map, i as u8 + 1, &m.to_raw().to_le_bytes())?;
+        }
+        client.download(map, 0, &[self.mappings.len() as u8])?;
+        client.download(comm, 1, &cob_id.to_le_bytes())
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mapping() {
+        let m = PdoMapping::from_raw(0x6041_0010);
+        assert_eq!(PdoMapping::new(0x6041, 0, 16), m);
+        assert_eq!(0x6041_0010, m.to_raw());
+    }
+
+    #[test]
+    fn test_pdo() {
+        let pdo = Pdo::tpdo(2, 5)
+            .with_mapping(0x6041, 0, 16)
+            .with_mapping(0x2000, 1, 1)
+            .with_mapping(0x2000, 2, 7)
+            .with_mapping(0x6064, 0, 32);
+        assert_eq!(0x285, pdo.cob_id.as_raw());
+        assert_eq!(0x1801, pdo.comm_index());
+        assert_eq!(0x1A01, pdo.mapping_index());
+
+        let frame = pdo.encode(&[0x1237, 1, 0x55, 0x1234_5678]).unwrap();
+        assert_eq!(&[0x37, 0x12, 0xAB, 0x78, 0x56, 0x34, 0x12], frame.data());
+
+        let values = pdo.decode_frame(&frame).unwrap();
+        let raw: Vec<_> = values.iter().map(|v| v.value).collect();
+        assert_eq!(vec![0x1237, 1, 0x55, 0x1234_5678], raw);
+        assert_eq!((0x2000, 2), (values[2].index, values[2].subindex));
+
+        assert!(pdo.decode(&[0; 4]).is_none());
+        assert_eq!(
+            Err(PdoError::ValueCount {
+                expected: 4,
+                got: 1
+            }),
+            pdo.encode(&[0])
+        );
+
+        let long = Pdo::rpdo(1, 5)
+            .with_mapping(0x6064, 0, 32)
+            .with_mapping(0x6064, 0, 32)
+            .with_mapping(0x6041, 0, 16);
+        assert_eq!(Err(PdoError::MappingTooLong(80)), long.encode(&[0, 0, 0]));
+    }
+}
@@ -0,0 +1,536 @@
+// socketcan/src/canopen/sdo.rs
+//
+// CANopen SDO client.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! CANopen service data objects (SDO).
+//!
+//! SDOs give access to the object dictionary of a node: each entry, at an
+//! index and sub-index, can be read ("uploaded") from the node or written
+//! ("downloaded") to it. The [`SdoClient`] makes these transfers with a
+//! node over a socket, using an expedited transfer for values up to four
+//! bytes, and a segmented transfer for anything larger. Block transfers
+//! are not supported.
+//!
+//! When a node refuses a transfer, it replies with an abort, which is
+//! returned as an [`SdoError::Abort`] with the decoded [`SdoAbortCode`].
+//!
+//! ```no_run
+//! use socketcan::{canopen::sdo::SdoClient, CanSocket, Socket};
+//!
+//! let sock = CanSocket::open("can0").unwrap();
+//! let client = SdoClient::new(&sock, 5);
+//!
+//! // Read the device type, and the device name
+//! let dev_type = client.upload_u32(0x1000, 0).unwrap();
+//! let name = client.upload(0x1008, 0).unwrap();
+//! println!("{:08X}: {}", dev_type, String::from_utf8_lossy(&name));
+//!
+//! // Set the heartbeat producer time to 1000 ms
+//! client.download(0x1017, 0, &1000u16.to_le_bytes()).unwrap();
+//! ```
+
+use super::{FC_SDO_RX, FC_SDO_TX};
+use crate::{
+    backend::{CanReceiver, CanTransmitter},
+    CanFrame, CanOpenId, EmbeddedFrame, Frame,
+};
+use std::{
+    fmt, io,
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+
+/// The default time to wait for each response from the server.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// An SDO abort code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SdoAbortCode(pub u32);
+
+impl SdoAbortCode {
+    /// Toggle bit not alternated
+    pub const TOGGLE_BIT: Self = Self(0x0503_0000);
+    /// SDO protocol timed out
+    pub const TIMEOUT: Self = Self(0x0504_0000);
+    /// Client/server command specifier not valid or unknown
+    pub const INVALID_COMMAND: Self = Self(0x0504_0001);
+    /// General error
+    pub const GENERAL_ERROR: Self = Self(0x0800_0000);
+
+    /// Gets the description of the abort code from CiA 301, if it's a
+    /// standard code.
+    pub fn description(&self) -> Option<&'static str> {
+        let s = match self.0 {
+            0x0503_0000 => "Toggle bit not alternated",
+            0x0504_0000 => "SDO protocol timed out",
+            0x0504_0001 => "Client/server command specifier not valid or unknown",
+            0x0504_0002 => "Invalid block size",
+            0x0504_0003 => "Invalid sequence number",
+            0x0504_0004 => "CRC error",
+            0x0504_0005 => "Out of memory",
+            0x0601_0000 => "Unsupported access to an object",
+            0x0601_0001 => "Attempt to read a write only object",
+            0x0601_0002 => "Attempt to write a read only object",
+            0x0602_0000 => "Object does not exist in the object dictionary",
+            0x0604_0041 => "Object cannot be mapped to the PDO",
+            0x0604_0042 => "The number and length of the objects would exceed the PDO length",
+            0x0604_0043 => "General parameter incompatibility",
+            0x0604_0047 => "General internal incompatibility in the device",
+            0x0606_0000 => "Access failed due to a hardware error",
+            0x0607_0010 => "Data type does not match, length of service parameter does not match",
+            0x0607_0012 => "Data type does not match, length of service parameter too high",
+            0x0607_0013 => "Data type does not match, length of service parameter too low",
+            0x0609_0011 => "Sub-index does not exist",
+            0x0609_0030 => "Invalid value for parameter",
+            0x0609_0031 => "Value of parameter written too high",
+            0x0609_0032 => "Value of parameter written too low",
+            0x0609_0036 => "Maximum value is less than minimum value",
+            0x060A_0023 => "Resource not available: SDO connection",
+            0x0800_0000 => "General error",
+            0x0800_0020 => "Data cannot be transferred or stored to the application",
+            0x0800_0021 => {
+                "Data cannot be transferred or stored to the application because of local control"
+            }
+            0x0800_0022 => {
+                "Data cannot be transferred or stored to the application because of the present device state"
+            }
+            0x0800_0023 => "Object dictionary dynamic generation fails or no object dictionary is present",
+            0x0800_0024 => "No data available",
+            _ => return None,
+        };
+        Some(s)
+    }
+}
+
+impl fmt::Display for SdoAbortCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.description() {
+            Some(s) => write!(f, "{} ({:08X}h)", s, self.0),
+            None => write!(f, "Unknown abort code {:08X}h", self.0),
+        }
+    }
+}
+
+/// An error in an SDO transfer.
+#[derive(Error, Debug)]
+pub enum SdoError {
+    /// The server aborted the transfer
+    #[error("SDO transfer of {index:04X}h:{subindex:02X}h aborted: {code}")]
+    Abort {
+        /// The object index
+        index: u16,
+        /// The object sub-index
+        subindex: u8,
+        /// The reason for the abort
+        code: SdoAbortCode,
+    },
+    /// The server didn't respond in time
+    #[error("SDO transfer timed out")]
+    Timeout,
+    /// The server sent an unexpected response
+    #[error("SDO protocol error: {0}")]
+    Protocol(&'static str),
+    /// An I/O error on the socket
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+// Client command specifiers
+const CCS_DOWNLOAD_SEGMENT: u8 = 0;
+const CCS_INITIATE_DOWNLOAD: u8 = 1;
+const CCS_INITIATE_UPLOAD: u8 = 2;
+const CCS_UPLOAD_SEGMENT: u8 = 3;
+const CS_ABORT: u8 = 4;
+
+// Server command specifiers
+const SCS_UPLOAD_SEGMENT: u8 = 0;
+const SCS_DOWNLOAD_SEGMENT: u8 = 1;
+const SCS_INITIATE_UPLOAD: u8 = 2;
+const SCS_INITIATE_DOWNLOAD: u8 = 3;
+
+/// An SDO message payload.
+type Msg = [u8; 8];
+
+/// The most bytes reserved up front for a segmented upload. The size is
+/// given by the server, so a larger one is only trusted as the data
+/// arrives.
+const MAX_UPLOAD_PREALLOC: usize = 4096;
+
+/// Creates the payload of an initiate or abort message, with the
+/// multiplexer (index and sub-index).
+fn mux_msg(cmd: u8, index: u16, subindex: u8, data: [u8; 4]) -> Msg {
+    let [lo, hi] = index.to_le_bytes();
+    [cmd, lo, hi, subindex, data[0], data[1], data[2], data[3]]
+}
+
+/// Runs the upload protocol with an exchange function, which sends a
+/// request to the server and returns its response.
+fn upload_with<X>(mut exchange: X, index: u16, subindex: u8) -> Result<Vec<u8>, SdoError>
+where
+    X: FnMut(Msg) -> Result<Msg, SdoError>,
+{
+    let req = mux_msg(CCS_INITIATE_UPLOAD << 5, index, subindex, [0; 4]);
+    let resp = check_response(exchange(req)?, index, subindex, SCS_INITIATE_UPLOAD)?;
+
+    let (expedited, sized) = (resp[0] & 0x02 != 0, resp[0] & 0x01 != 0);
+    if expedited {
+        let n = if sized {
+            4 - usize::from((resp[0] >> 2) & 0x03)
+        } else {
+            4
+        };
+        return Ok(resp[4..4 + n].to_vec());
+    }
+
+    let size = sized.then(|| u32::from_le_bytes([resp[4], resp[5], resp[6], resp[7]]) as usize);
+    let mut data = Vec::with_capacity(size.unwrap_or(0).min(MAX_UPLOAD_PREALLOC));
+    let mut toggle = 0;
+
+    loop {
+        let mut req = [0u8; 8];
+        req[0] = (CCS_UPLOAD_SEGMENT << 5) | (toggle << 4);
+        let resp = exchange(req)?;
+        check_abort(&resp, index, subindex)?;
+        if resp[0] >> 5 != SCS_UPLOAD_SEGMENT {
+            return Err(SdoError::Protocol("unexpected upload segment response"));
+        }
+        if (resp[0] >> 4) & 1 != toggle {
+            return Err(SdoError::Protocol("toggle bit not alternated"));
+        }
+        let n = 7 - usize::from((resp[0] >> 1) & 0x07);
+        data.extend_from_slice(&resp[1..1 + n]);
+        if resp[0] & 0x01 != 0 {
+            break;
+        }
+        toggle ^= 1;
+    }
+
+    if size.is_some_and(|size| size != data.len()) {
+        return Err(SdoError::Protocol("upload size mismatch"));
+    }
+    Ok(data)
+}
+
+/// Runs the download protocol with an exchange function, which sends a
+/// request to the server and returns its response.
+fn download_with<X>(mut exchange: X, index: u16, subindex: u8, data: &[u8]) -> Result<(), SdoError>
+where
+    X: FnMut(Msg) -> Result<Msg, SdoError>,
+{
+    if (1..=4).contains(&data.len()) {
+        let n = data.len() as u8;
+        let mut buf = [0u8; 4];
+        buf[..data.len()].copy_from_slice(data);
+        let cmd = (CCS_INITIATE_DOWNLOAD << 5) | ((4 - n) << 2) | 0x03;
+        check_response(
+            exchange(mux_msg(cmd, index, subindex, buf))?,
+            index,
+            subindex,
+            SCS_INITIATE_DOWNLOAD,
+        )?;
+        return Ok(());
+    }
+
+    let size = u32::try_from(data.len())
+        .map_err(|_| SdoError::Protocol("download too large"))?
+        .to_le_bytes();
+    let cmd = (CCS_INITIATE_DOWNLOAD << 5) | 0x01;
+    check_response(
+        exchange(mux_msg(cmd, index, subindex, size))?,
+        index,
+        subindex,
+        SCS_INITIATE_DOWNLOAD,
+    )?;
+
+    let mut segments: Vec<&[u8]> = data.chunks(7).collect();
+    if segments.is_empty() {
+        segments.push(&[]);
+    }
+    let last = segments.len() - 1;
+    let mut toggle = 0;
+
+    for (i, seg) in segments.into_iter().enumerate() {
+        let n = seg.len() as u8;
+        let mut req = [0u8; 8];
+        req[0] = (CCS_DOWNLOAD_SEGMENT << 5) | (toggle << 4) | ((7 - n) << 1) | u8::from(i == last);
+        req[1..1 + seg.len()].copy_from_slice(seg);
+
+        let resp = exchange(req)?;
+        check_abort(&resp, index, subindex)?;
+        if resp[0] >> 5 != SCS_DOWNLOAD_SEGMENT {
+            return Err(SdoError::Protocol("unexpected download segment response"));
+        }
+        if (resp[0] >> 4) & 1 != toggle {
+            return Err(SdoError::Protocol("toggle bit not alternated"));
+        }
+        toggle ^= 1;
+    }
+    Ok(())
+}
+
+/// Checks if the response is an abort.
+fn check_abort(resp: &Msg, index: u16, subindex: u8) -> Result<(), SdoError> {
+    if resp[0] >> 5 == CS_ABORT {
+        let code = SdoAbortCode(u32::from_le_bytes([resp[4], resp[5], resp[6], resp[7]]));
+        return Err(SdoError::Abort {
+            index,
+            subindex,
+            code,
+        });
+    }
+    Ok(())
+}
+
+/// Checks an initiate response for an abort, the expected command
+/// specifier, and a matching multiplexer.
+fn check_response(resp: Msg, index: u16, subindex: u8, scs: u8) -> Result<Msg, SdoError> {
+    check_abort(&resp, index, subindex)?;
+    if resp[0] >> 5 != scs {
+        return Err(SdoError::Protocol("unexpected initiate response"));
+    }
+    if u16::from_le_bytes([resp[1], resp[2]]) != index || resp[3] != subindex {
+        return Err(SdoError::Protocol("response for the wrong object"));
+    }
+    Ok(resp)
+}
+
+// ===== SdoClient =====
+
+/// An SDO client, to access the object dictionary of a node.
+#[derive(Debug)]
+pub struct SdoClient<'a, S> {
+    sock: &'a S,
+    node_id: u8,
+    timeout: Duration,
+}
+
+impl<'a, S> SdoClient<'a, S>
+where
+    S: CanTransmitter<CanFrame> + CanReceiver,
+    S::Frame: Frame,
+{
+    /// Creates a client for the node, using the default SDO channel.
+    pub fn new(sock: &'a S, node_id: u8) -> Self {
+        Self {
+            sock,
+            node_id,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Sets the time to wait for each response from the server.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Gets the ID of the node.
+    pub fn node_id(&self) -> u8 {
+        self.node_id
+    }
+
+    /// Sends a request and waits for the response from the server.
+    fn exchange(&self, req: Msg) -> Result<Msg, SdoError> {
+        let tx_id = CanOpenId::new(FC_SDO_RX, self.node_id).can_id();
+        let rx_id = CanOpenId::new(FC_SDO_TX, self.node_id).can_id();
+
+        self.sock.transmit(&CanFrame::new(tx_id, &req).unwrap())?;
+
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(SdoError::Timeout);
+            }
+            let frame = match self.sock.receive_timeout(remaining) {
+                Ok(frame) => frame,
+                Err(err) if err.kind() == io::ErrorKind::TimedOut => return Err(SdoError::Timeout),
+                Err(err) => return Err(err.into()),
+            };
+            if frame.can_id() == rx_id && !frame.is_remote_frame() {
+                if let Ok(resp) = Msg::try_from(frame.data()) {
+                    return Ok(resp);
+                }
+            }
+        }
+    }
+
+    /// Aborts the transfer with the server, after a local failure.
+    fn abort(&self, index: u16, subindex: u8, err: &SdoError) {
+        let code = match err {
+            SdoError::Abort { .. } | SdoError::Io(_) => return,
+            SdoError::Timeout => SdoAbortCode::TIMEOUT,
+            SdoError::Protocol(_) => SdoAbortCode::INVALID_COMMAND,
+        };
+        let req = mux_msg(CS_ABORT << 5, index, subindex, code.0.to_le_bytes());
+        let tx_id = CanOpenId::new(FC_SDO_RX, self.node_id).can_id();
+        let _ = self.sock.transmit(&CanFrame::new(tx_id, &req).unwrap());
+    }
+
+    /// Reads an object from the node.
+    pub fn upload(&self, index: u16, subindex: u8) -> Result<Vec<u8>, SdoError> {
+        upload_with(|req| self.exchange(req), index, subindex).map_err(|err| {
+            self.abort(index, subindex, &err);
+            err
+        })
+    }
+
+    /// Writes an object to the node.
+    pub fn download(&self, index: u16, subindex: u8, data: &[u8]) -> Result<(), SdoError> {
+        download_with(|req| self.exchange(req), index, subindex, data).map_err(|err| {
+            self.abort(index, subindex, &err);
+            err
+        })
+    }
+
+    /// Reads an unsigned 8-bit object from the node.
+    pub fn upload_u8(&self, index: u16, subindex: u8) -> Result<u8, SdoError> {
+        let data = self.upload(index, subindex)?;
+        Ok(le_value(&data, 1)? as u8)
+    }
+
+    /// Reads an unsigned 16-bit object from the node.
+    pub fn upload_u16(&self, index: u16, subindex: u8) -> Result<u16, SdoError> {
+        let data = self.upload(index, subindex)?;
+        Ok(le_value(&data, 2)? as u16)
+    }
+
+    /// Reads an unsigned 32-bit object from the node.
+    pub fn upload_u32(&self, index: u16, subindex: u8) -> Result<u32, SdoError> {
+        let data = self.upload(index, subindex)?;
+        le_value(&data, 4)
+    }
+}
+
+/// Gets a little-endian value of the expected size.
+fn le_value(data: &[u8], size: usize) -> Result<u32, SdoError> {
+    if data.len() != size {
+        return Err(SdoError::Protocol("unexpected object size"));
+    }
+    Ok(data
+        .iter()
+        .rev()
+        .fold(0u32, |acc, b| (acc << 8) | u32::from(*b)))
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    /// An exchange that checks each request against the expected one, and
+    /// returns the canned response.
+    fn script(steps: Vec<(Msg, Msg)>) -> impl FnMut(Msg) -> Result<Msg, SdoError> {
+        let mut steps = VecDeque::from(steps);
+        move |req| {
+            let (expected, resp) = steps.pop_front().expect("unexpected request");
+            assert_eq!(expected, req);
+            Ok(resp)
+        }
+    }
+
+    #[test]
+    fn test_upload() {
+        // Expedited, 2 bytes
+        let xchg = script(vec![(
+            [0x40, 0x17, 0x10, 0, 0, 0, 0, 0],
+            [0x4B, 0x17, 0x10, 0, 0xE8, 0x03, 0, 0],
+        )]);
+        assert_eq!(vec![0xE8, 0x03], upload_with(xchg, 0x1017, 0).unwrap());
+
+        // Segmented, 10 bytes
+        let xchg = script(vec![
+            (
+                [0x40, 0x08, 0x10, 0, 0, 0, 0, 0],
+                [0x41, 0x08, 0x10, 0, 10, 0, 0, 0],
+            ),
+            (
+                [0x60, 0, 0, 0, 0, 0, 0, 0],
+                [0x00, b'S', b'o', b'c', b'k', b'e', b't', b'C'],
+            ),
+            (
+                [0x70, 0, 0, 0, 0, 0, 0, 0],
+                [0x19, b'A', b'N', b'!', 0, 0, 0, 0],
+            ),
+        ]);
+        assert_eq!(
+            b"SocketCAN!".to_vec(),
+            upload_with(xchg, 0x1008, 0).unwrap()
+        );
+
+        // A bogus size is not preallocated, and is caught at the end
+        let xchg = script(vec![
+            (
+                [0x40, 0x08, 0x10, 0, 0, 0, 0, 0],
+                [0x41, 0x08, 0x10, 0, 0xFF, 0xFF, 0xFF, 0xFF],
+            ),
+            (
+                [0x60, 0, 0, 0, 0, 0, 0, 0],
+                [0x0D, b'S', b'o', b'c', 0, 0, 0, 0],
+            ),
+        ]);
+        assert!(matches!(
+            upload_with(xchg, 0x1008, 0),
+            Err(SdoError::Protocol("upload size mismatch"))
+        ));
+
+        // Aborted
+        let xchg = script(vec![(
+            [0x40, 0x00, 0x20, 1, 0, 0, 0, 0],
+            [0x80, 0x00, 0x20, 1, 0x00, 0x00, 0x02, 0x06],
+        )]);
+        match upload_with(xchg, 0x2000, 1) {
+            Err(SdoError::Abort { code, .. }) => {
+                assert_eq!(SdoAbortCode(0x0602_0000), code);
+                assert_eq!(
+                    Some("Object does not exist in the object dictionary"),
+                    code.description()
+                );
+            }
+            res => panic!("unexpected result: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn test_download() {
+        // Expedited, 2 bytes
+        let xchg = script(vec![(
+            [0x2B, 0x17, 0x10, 0, 0xE8, 0x03, 0, 0],
+            [0x60, 0x17, 0x10, 0, 0, 0, 0, 0],
+        )]);
+        download_with(xchg, 0x1017, 0, &[0xE8, 0x03]).unwrap();
+
+        // Segmented, 9 bytes
+        let xchg = script(vec![
+            (
+                [0x21, 0x00, 0x20, 0, 9, 0, 0, 0],
+                [0x60, 0x00, 0x20, 0, 0, 0, 0, 0],
+            ),
+            ([0x00, 1, 2, 3, 4, 5, 6, 7], [0x20, 0, 0, 0, 0, 0, 0, 0]),
+            ([0x1B, 8, 9, 0, 0, 0, 0, 0], [0x30, 0, 0, 0, 0, 0, 0, 0]),
+        ]);
+        download_with(xchg, 0x2000, 0, &[1, 2, 3, 4, 5, 6, 7, 8, 9]).unwrap();
+
+        // Wrong toggle bit
+        let xchg = script(vec![
+            (
+                [0x21, 0x00, 0x20, 0, 9, 0, 0, 0],
+                [0x60, 0x00, 0x20, 0, 0, 0, 0, 0],
+            ),
+            ([0x00, 1, 2, 3, 4, 5, 6, 7], [0x30, 0, 0, 0, 0, 0, 0, 0]),
+        ]);
+        assert!(matches!(
+            download_with(xchg, 0x2000, 0, &[1, 2, 3, 4, 5, 6, 7, 8, 9]),
+            Err(SdoError::Protocol(_))
+        ));
+    }
+}
@@ -0,0 +1,397 @@
+// socketcan/src/capture.rs
+//
+// Post-processing utilities for captured CAN traffic.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! Post-processing of captured CAN traffic.
+//!
+//! These are utilities to manipulate logs of timestamped frames, such as
+//! those read from a candump file, so that analysis pipelines don't need
+//! to rely on external tools. They can:
+//!
+//! - Merge logs from multiple channels into a single log, ordered by time
+//! - Sort a log by timestamp
+//! - Slice a log to a window of time, or to a set of IDs
+//! - Re-base the timestamps of a log to a new starting point
+//! - Compare two logs, by ID and payload, with [`diff`]
+//!
+//! The merge and slice operations are lazy iterator adapters, so they can
+//! be chained together and run over very large logs without loading them
+//! into memory.
+
+use crate::{CanAnyFrame, EmbeddedFrame, Frame, Id};
+use libc::canid_t;
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BinaryHeap, HashSet},
+};
+
+/// A single timestamped frame from a capture log.
+#[derive(Debug, Clone)]
+pub struct Record {
+    /// The timestamp, in microseconds
+    pub t_us: u64,
+    /// The name of the device (channel) on which the frame was captured
+    pub device: String,
+    /// The captured frame
+    pub frame: CanAnyFrame,
+}
+
+impl Record {
+    /// Creates a new record.
+    pub fn new(t_us: u64, device: impl Into<String>, frame: impl Into<CanAnyFrame>) -> Self {
+        Self {
+            t_us,
+            device: device.into(),
+            frame: frame.into(),
+        }
+    }
+
+    /// Gets the raw numeric CAN ID of the frame (without any flags).
+    pub fn raw_id(&self) -> canid_t {
+        self.frame.raw_id()
+    }
+
+    /// Gets the CAN ID of the frame.
+    ///
+    /// Error frames do not have an ID, so this returns `None` for them.
+    pub fn id(&self) -> Option<Id> {
+        (!self.frame.is_error_frame()).then(|| self.frame.id())
+    }
+
+    /// Gets the data payload of the frame.
+    pub fn data(&self) -> &[u8] {
+        self.frame.data()
+    }
+}
+
+// ===== Merge =====
+
+/// An iterator that merges multiple time-ordered logs into one.
+///
+/// This is created by [`merge`].
+#[derive(Debug)]
+pub struct Merge<I: Iterator<Item = Record>> {
+    logs: Vec<I>,
+    // The next record from each log, ordered by (time, log index)
+    heap: BinaryHeap<Reverse<(u64, usize)>>,
+    pending: Vec<Option<Record>>,
+}
+
+impl<I: Iterator<Item = Record>> Merge<I> {
+    /// Gets the next record from the specified log into the heap.
+    fn advance(&mut self, idx: usize) {
+        if let Some(rec) = self.logs[idx].next() {
+            self.heap.push(Reverse((rec.t_us, idx)));
+            self.pending[idx] = Some(rec);
+        }
+    }
+}
+
+impl<I: Iterator<Item = Record>> Iterator for Merge<I> {
+    type Item = Record;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let Reverse((_, idx)) = self.heap.pop()?;
+        let rec = self.pending[idx].take();
+        self.advance(idx);
+        rec
+    }
+}
+
+/// Merges multiple logs into a single log, ordered by timestamp.
+///
+/// Each of the logs should already be sorted by time. Records with the same
+/// timestamp are returned in the order of the logs given.
+pub fn merge<L, I>(logs: L) -> Merge<I>
+where
+    L: IntoIterator,
+    L::Item: IntoIterator<IntoIter = I>,
+    I: Iterator<Item = Record>,
+{
+    let logs: Vec<I> = logs.into_iter().map(|log| log.into_iter()).collect();
+    let n = logs.len();
+
+    let mut merge = Merge {
+        logs,
+        heap: BinaryHeap::with_capacity(n),
+        pending: vec![None; n],
+    };
+
+    for idx in 0..n {
+        merge.advance(idx);
+    }
+    merge
+}
+
+// ===== Sort, slice, and re-base =====
+
+/// Sorts a log by timestamp.
+///
+/// This is a stable sort, so records with the same timestamp keep their
+/// original order.
+pub fn sort(log: &mut [Record]) {
+    log.sort_by_key(|rec| rec.t_us);
+}
+
+/// Slices a log to the records in the time window, `[start_us, end_us)`.
+pub fn time_window<I>(log: I, start_us: u64, end_us: u64) -> impl Iterator<Item = Record>
+where
+    I: IntoIterator<Item = Record>,
+{
+    log.into_iter()
+        .filter(move |rec| rec.t_us >= start_us && rec.t_us < end_us)
+}
+
+/// Slices a log to the records for frames with any of the IDs.
+///
+/// Standard and extended IDs are distinct, so a standard ID doesn't match
+/// an extended one with the same number. Error frames have no ID, and are
+/// dropped.
+pub fn with_ids<I>(log: I, ids: &[Id]) -> impl Iterator<Item = Record>
+where
+    I: IntoIterator<Item = Record>,
+{
+    let ids: HashSet<Id> = ids.iter().copied().collect();
+    log.into_iter()
+        .filter(move |rec| rec.id().is_some_and(|id| ids.contains(&id)))
+}
+
+/// Slices a log to the records captured on the named device.
+pub fn with_device<'a, I>(log: I, device: &'a str) -> impl Iterator<Item = Record> + 'a
+where
+    I: IntoIterator<Item = Record>,
+    I::IntoIter: 'a,
+{
+    log.into_iter().filter(move |rec| rec.device == device)
+}
+
+/// Re-bases the timestamps of a log so that `t0_us` becomes time zero.
+///
+/// Any records before `t0_us` are clamped to zero.
+pub fn rebase(log: &mut [Record], t0_us: u64) {
+    for rec in log.iter_mut() {
+        rec.t_us = rec.t_us.saturating_sub(t0_us);
+    }
+}
+
+/// Re-bases the timestamps of a log so that the first record is at time
+/// zero.
+pub fn rebase_to_first(log: &mut [Record]) {
+    if let Some(t0_us) = log.iter().map(|rec| rec.t_us).min() {
+        rebase(log, t0_us);
+    }
+}
+
+// ===== Diff =====
+
+/// A difference in the payload of an ID between two logs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// The CAN ID
+    pub id: Id,
+    /// The occurrence of the ID in each log, counting from zero
+    pub index: usize,
+    /// The time of the frame in the first log, relative to its start
+    pub a_us: u64,
+    /// The time of the frame in the second log, relative to its start
+    pub b_us: u64,
+    /// The payload in the first log
+    pub a_data: Vec<u8>,
+    /// The payload in the second log
+    pub b_data: Vec<u8>,
+}
+
+/// The differences between two logs, as reported by [`diff`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CaptureDiff {
+    /// The IDs that appear only in the second log
+    pub added_ids: Vec<Id>,
+    /// The IDs that appear only in the first log
+    pub missing_ids: Vec<Id>,
+    /// The IDs that appear a different number of times in each log, as
+    /// `(id, count_a, count_b)`
+    pub count_mismatches: Vec<(Id, usize, usize)>,
+    /// The frames of common IDs whose payloads differ
+    pub divergences: Vec<Divergence>,
+}
+
+impl CaptureDiff {
+    /// Determines if the logs had the same IDs and payloads.
+    pub fn is_identical(&self) -> bool {
+        self.added_ids.is_empty()
+            && self.missing_ids.is_empty()
+            && self.count_mismatches.is_empty()
+            && self.divergences.is_empty()
+    }
+}
+
+/// Splits a log by ID, with the timestamps relative to the first record.
+fn by_id(log: &[Record]) -> BTreeMap<Id, Vec<(u64, &[u8])>> {
+    let t0_us = log.iter().map(|rec| rec.t_us).min().unwrap_or(0);
+    let mut ids: BTreeMap<Id, Vec<_>> = BTreeMap::new();
+    for rec in log {
+        if let Some(id) = rec.id() {
+            ids.entry(id)
+                .or_default()
+                .push((rec.t_us - t0_us, rec.data()));
+        }
+    }
+    ids
+}
+
+/// Compares two logs by ID and payload.
+///
+/// The frames of each ID are compared in the order in which they occur,
+/// so the n-th frame of an ID in `a` is compared against the n-th frame of
+/// that ID in `b`. The timestamps are not compared, but are reported
+/// relative to the start of each log to help find the divergences in time.
+/// Error frames are ignored.
+///
+/// This is useful for regression testing, such as checking that a new
+/// version of ECU software produces the same traffic as the old one.
+pub fn diff(a: &[Record], b: &[Record]) -> CaptureDiff {
+    let a_ids = by_id(a);
+    let b_ids = by_id(b);
+    let mut diff = CaptureDiff::default();
+
+    for (id, a_frames) in &a_ids {
+        let b_frames = match b_ids.get(id) {
+            Some(frames) => frames,
+            None => {
+                diff.missing_ids.push(*id);
+                continue;
+            }
+        };
+
+        if a_frames.len() != b_frames.len() {
+            diff.count_mismatches
+                .push((*id, a_frames.len(), b_frames.len()));
+        }
+
+        for (index, ((a_us, a_data), (b_us, b_data))) in
+            a_frames.iter().zip(b_frames.iter()).enumerate()
+        {
+            if a_data != b_data {
+                diff.divergences.push(Divergence {
+                    id: *id,
+                    index,
+                    a_us: *a_us,
+                    b_us: *b_us,
+                    a_data: a_data.to_vec(),
+                    b_data: b_data.to_vec(),
+                });
+            }
+        }
+    }
+
+    diff.added_ids = b_ids
+        .keys()
+        .filter(|id| !a_ids.contains_key(id))
+        .copied()
+        .collect();
+    diff
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CanFrame, ExtendedId, StandardId};
+
+    fn rec(t_us: u64, device: &str, id: u32) -> Record {
+        Record::new(t_us, device, CanFrame::from_raw_id(id, &[]).unwrap())
+    }
+
+    #[test]
+    fn test_merge() {
+        let can0 = vec![rec(1, "can0", 1), rec(5, "can0", 2), rec(9, "can0", 3)];
+        let can1 = vec![rec(2, "can1", 4), rec(5, "can1", 5)];
+
+        let log: Vec<_> = merge(vec![can0, can1]).collect();
+        let times: Vec<_> = log.iter().map(|rec| rec.t_us).collect();
+        let ids: Vec<_> = log.iter().map(|rec| rec.raw_id()).collect();
+
+        assert_eq!(vec![1, 2, 5, 5, 9], times);
+        assert_eq!(vec![1, 4, 2, 5, 3], ids);
+    }
+
+    #[test]
+    fn test_sort_slice_rebase() {
+        let mut log = vec![rec(30, "can0", 3), rec(10, "can0", 1), rec(20, "can1", 2)];
+        sort(&mut log);
+        assert_eq!(1, log[0].raw_id());
+
+        let ids: Vec<_> = time_window(log.clone(), 10, 30)
+            .map(|rec| rec.raw_id())
+            .collect();
+        assert_eq!(vec![1, 2], ids);
+
+        let std_id = |id| Id::from(StandardId::new(id).unwrap());
+        let ids: Vec<_> = with_ids(log.clone(), &[std_id(1), std_id(3)])
+            .map(|rec| rec.raw_id())
+            .collect();
+        assert_eq!(vec![1, 3], ids);
+
+        let ext = Record::new(
+            40,
+            "can0",
+            CanFrame::new(ExtendedId::new(1).unwrap(), &[]).unwrap(),
+        );
+        assert_eq!(0, with_ids(vec![ext.clone()], &[std_id(1)]).count());
+        assert_eq!(
+            1,
+            with_ids(vec![ext], &[ExtendedId::new(1).unwrap().into()]).count()
+        );
+
+        assert_eq!(1, with_device(log.clone(), "can1").count());
+
+        rebase_to_first(&mut log);
+        let times: Vec<_> = log.iter().map(|rec| rec.t_us).collect();
+        assert_eq!(vec![0, 10, 20], times);
+    }
+
+    #[test]
+    fn test_diff() {
+        let frame = |t_us, id, data: &[u8]| {
+            Record::new(t_us, "can0", CanFrame::from_raw_id(id, data).unwrap())
+        };
+
+        let a = vec![
+            frame(100, 1, &[1]),
+            frame(110, 2, &[2]),
+            frame(120, 1, &[1]),
+            frame(130, 3, &[3]),
+        ];
+        let b = vec![
+            frame(500, 1, &[1]),
+            frame(510, 2, &[2]),
+            frame(520, 1, &[9]),
+            frame(530, 2, &[2]),
+            frame(540, 4, &[4]),
+        ];
+
+        assert!(diff(&a, &a).is_identical());
+
+        let d = diff(&a, &b);
+        assert!(!d.is_identical());
+
+        let id = |n| Id::from(StandardId::new(n).unwrap());
+        assert_eq!(vec![id(4)], d.added_ids);
+        assert_eq!(vec![id(3)], d.missing_ids);
+        assert_eq!(vec![(id(2), 1, 2)], d.count_mismatches);
+        assert_eq!(1, d.divergences.len());
+
+        let div = &d.divergences[0];
+        assert_eq!((id(1), 1), (div.id, div.index));
+        assert_eq!((20, 20), (div.a_us, div.b_us));
+        assert_eq!((vec![1], vec![9]), (div.a_data.clone(), div.b_data.clone()));
+    }
+}
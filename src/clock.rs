@@ -0,0 +1,192 @@
+// socketcan/src/clock.rs
+//
+// Real and simulated clocks.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! Real and simulated clocks.
+//!
+//! The timing code in the crate, such as the
+//! [`Schedule`](crate::scheduler::Schedule), the
+//! [`Watchdog`](crate::watchdog::Watchdog), and the
+//! [`ReplayHarness`](crate::replay::ReplayHarness), can take its time
+//! from a [`Clock`] rather than straight from the system. Normally that's
+//! the [`SystemClock`], but a test can use a [`SimClock`] instead, which
+//! only moves when it's told to. Since sleeping on a simulated clock just
+//! moves it forward, hours of traffic can be run through in an instant,
+//! with the same result every time.
+//!
+//! ```
+//! use socketcan::{
+//!     clock::{Clock, SimClock},
+//!     scheduler::Schedule,
+//!     CanFrame, Frame,
+//! };
+//! use std::time::Duration;
+//!
+//! let clock = SimClock::new();
+//! let mut sched = Schedule::new();
+//! sched.add(clock.now(), Duration::from_millis(10), Duration::ZERO, || {
+//!     CanFrame::from_raw_id(0x100, &[]).unwrap()
+//! });
+//!
+//! // An hour of a 10ms message, without the wait
+//! let mut n = 0;
+//! let end = clock.now() + Duration::from_secs(3600);
+//! sched
+//!     .run_until(&clock, end, |_| {
+//!         n += 1;
+//!         Ok::<_, ()>(())
+//!     })
+//!     .unwrap();
+//!
+//! // From 0s to 3600s, inclusive
+//! assert_eq!(360_001, n);
+//! assert_eq!(Duration::from_secs(3600), clock.elapsed());
+//! ```
+
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// A source of time.
+pub trait Clock: Send + Sync {
+    /// Gets the current time.
+    fn now(&self) -> Instant;
+
+    /// Waits until the time.
+    fn sleep_until(&self, deadline: Instant);
+
+    /// Waits for the duration.
+    fn sleep(&self, dur: Duration) {
+        self.sleep_until(self.now() + dur);
+    }
+}
+
+impl<C: Clock + ?Sized> Clock for Arc<C> {
+    fn now(&self) -> Instant {
+        (**self).now()
+    }
+
+    fn sleep_until(&self, deadline: Instant) {
+        (**self).sleep_until(deadline)
+    }
+}
+
+/// A shared clock, that can be either real or simulated.
+pub type SharedClock = Arc<dyn Clock>;
+
+// ===== SystemClock =====
+
+/// The real, monotonic system clock.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep_until(&self, deadline: Instant) {
+        thread::sleep(deadline.saturating_duration_since(Instant::now()));
+    }
+}
+
+// ===== SimClock =====
+
+/// A simulated clock, that only moves when it's told to.
+///
+/// The clock is a handle to shared state, so clones of it refer to the
+/// same time. Sleeping on it moves it forward to the end of the sleep,
+/// right away, so a single thread that sleeps between events runs as
+/// fast as it can, in simulated time.
+#[derive(Clone)]
+pub struct SimClock {
+    base: Instant,
+    elapsed: Arc<Mutex<Duration>>,
+}
+
+impl SimClock {
+    /// Creates a simulated clock, starting at the current time.
+    pub fn new() -> Self {
+        Self {
+            base: Instant::now(),
+            elapsed: Arc::new(Mutex::new(Duration::ZERO)),
+        }
+    }
+
+    /// Gets the time since the clock was created.
+    pub fn elapsed(&self) -> Duration {
+        *self.elapsed.lock().unwrap()
+    }
+
+    /// Moves the clock forward.
+    pub fn advance(&self, dur: Duration) {
+        *self.elapsed.lock().unwrap() += dur;
+    }
+
+    /// Sets the time since the clock was created.
+    ///
+    /// The clock never moves backwards, so an earlier time is ignored.
+    pub fn set_elapsed(&self, elapsed: Duration) {
+        let mut cur = self.elapsed.lock().unwrap();
+        *cur = (*cur).max(elapsed);
+    }
+}
+
+impl Default for SimClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for SimClock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SimClock")
+            .field("elapsed", &self.elapsed())
+            .finish()
+    }
+}
+
+impl Clock for SimClock {
+    fn now(&self) -> Instant {
+        self.base + self.elapsed()
+    }
+
+    fn sleep_until(&self, deadline: Instant) {
+        self.set_elapsed(deadline.saturating_duration_since(self.base));
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sim_clock() {
+        let clock = SimClock::new();
+        let other = clock.clone();
+        let t0 = clock.now();
+
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(Duration::from_secs(5), other.now() - t0);
+
+        // Never goes back
+        other.set_elapsed(Duration::from_secs(1));
+        assert_eq!(Duration::from_secs(5), clock.elapsed());
+
+        let shared: SharedClock = Arc::new(clock.clone());
+        shared.sleep(Duration::from_secs(3600));
+        assert_eq!(Duration::from_secs(3605), clock.elapsed());
+    }
+}
@@ -0,0 +1,298 @@
+// socketcan/src/codec.rs
+//
+// Codecs to frame CAN frames over byte streams.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! Codecs to frame CAN frames over byte streams.
+//!
+//! These implement the [`Encoder`] and [`Decoder`] traits from
+//! [tokio-util](https://crates.io/crates/tokio-util), so that frames can
+//! be sent over any `AsyncRead`/`AsyncWrite` transport, such as a TCP
+//! bridge or a serial link, with `FramedRead`, `FramedWrite`, or
+//! `Framed`. There are two formats:
+//!
+//! - [`RawCodec`] sends the frames in the kernel's own memory layout, as
+//!   read from and written to a socket: 16 bytes for a classic frame, or
+//!   72 for an FD frame. It's simple and fast, but the stream carries one
+//!   type of frame, and both ends must share the host byte order.
+//! - [`LengthPrefixedCodec`] sends each frame with a two-byte length
+//!   prefix, and only as many data bytes as it has, in network byte order.
+//!   Classic, remote, error, and FD frames can be mixed in the one stream.
+//!
+//! ```
+//! use futures::{SinkExt, StreamExt};
+//! use socketcan::{codec::LengthPrefixedCodec, CanFrame, Frame};
+//! use tokio_util::codec::{FramedRead, FramedWrite};
+//!
+//! #[tokio::main]
+//! async fn main() -> std::io::Result<()> {
+//!     // Any byte stream will do, such as a TcpStream or a serial port
+//!     let (a, b) = tokio::io::duplex(1024);
+//!     let mut tx = FramedWrite::new(a, LengthPrefixedCodec::new());
+//!     let mut rx = FramedRead::new(b, LengthPrefixedCodec::new());
+//!
+//!     tx.send(CanFrame::from_raw_id(0x123, &[1, 2, 3]).unwrap()).await?;
+//!     let frame = rx.next().await.unwrap()?;
+//!     assert_eq!(0x123, frame.raw_id());
+//!     Ok(())
+//! }
+//! ```
+
+use crate::{
+    frame::{can_frame_default, canfd_frame_default, AsPtr},
+    CanAnyFrame, CanFdFrame, CanFrame, ConstructionError, EmbeddedFrame, Frame, IoError,
+    IoErrorKind,
+};
+use bytes::{Buf, BufMut, BytesMut};
+use libc::CAN_RTR_FLAG;
+use std::{marker::PhantomData, mem::size_of};
+use tokio_util::codec::{Decoder, Encoder};
+
+// The flag in the flags byte that marks an FD frame
+const FD_FRAME: u8 = 0x80;
+
+// The size of the ID, flags, and length fields of a frame
+const HEADER_LEN: usize = 6;
+
+/// The longest encoded frame, after the length prefix.
+pub const MAX_ENCODED_LEN: usize = HEADER_LEN + 64;
+
+// ===== RawCodec =====
+
+mod private {
+    pub trait Sealed {}
+    impl Sealed for crate::CanFrame {}
+    impl Sealed for crate::CanFdFrame {}
+}
+
+/// A frame with a fixed kernel memory layout, which can be sent by the
+/// [`RawCodec`].
+///
+/// This is sealed, as a raw frame is read straight from the bytes in the
+/// stream, which is only sound for plain C structs of integers.
+pub trait RawFrame: AsPtr + private::Sealed + Sized {
+    #[doc(hidden)]
+    fn from_raw_bytes(bytes: &[u8]) -> Result<Self, ConstructionError>;
+}
+
+impl RawFrame for CanFrame {
+    fn from_raw_bytes(bytes: &[u8]) -> Result<Self, ConstructionError> {
+        Self::try_from_bytes(bytes)
+    }
+}
+
+impl RawFrame for CanFdFrame {
+    fn from_raw_bytes(bytes: &[u8]) -> Result<Self, ConstructionError> {
+        Self::try_from_bytes(bytes)
+    }
+}
+
+/// A codec that sends frames in the kernel's memory layout.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RawCodec<F> {
+    _frame: PhantomData<F>,
+}
+
+impl<F: RawFrame> RawCodec<F> {
+    /// Creates a codec for the type of frame.
+    pub fn new() -> Self {
+        Self {
+            _frame: PhantomData,
+        }
+    }
+}
+
+impl<F: RawFrame> Encoder<F> for RawCodec<F> {
+    type Error = IoError;
+
+    fn encode(&mut self, frame: F, dst: &mut BytesMut) -> Result<(), IoError> {
+        dst.extend_from_slice(frame.as_bytes());
+        Ok(())
+    }
+}
+
+impl<F: RawFrame> Decoder for RawCodec<F> {
+    type Item = F;
+    type Error = IoError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<F>, IoError> {
+        let n = size_of::<F::Inner>();
+        if src.len() < n {
+            src.reserve(n - src.len());
+            return Ok(None);
+        }
+        let frame = F::from_raw_bytes(&src[..n]);
+        src.advance(n);
+        frame
+            .map(Some)
+            .map_err(|err| IoError::new(IoErrorKind::InvalidData, err))
+    }
+}
+
+// ===== LengthPrefixedCodec =====
+
+/// A codec that sends each frame with a length prefix.
+///
+/// Each frame is encoded, in network byte order, as:
+///
+/// | Bytes | Field                                                  |
+/// |-------|--------------------------------------------------------|
+/// | 2     | The length of the rest of the frame                    |
+/// | 4     | The CAN ID, with the EFF/RTR/ERR flags                 |
+/// | 1     | 0x80 for an FD frame, ORed with its FD flags; else 0   |
+/// | 1     | The data length, or the DLC of a remote frame          |
+/// | 0-64  | The data, which a remote frame doesn't have            |
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LengthPrefixedCodec;
+
+impl LengthPrefixedCodec {
+    /// Creates a codec.
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+fn invalid_data(msg: &str) -> IoError {
+    IoError::new(IoErrorKind::InvalidData, msg)
+}
+
+impl<F: Into<CanAnyFrame>> Encoder<F> for LengthPrefixedCodec {
+    type Error = IoError;
+
+    fn encode(&mut self, frame: F, dst: &mut BytesMut) -> Result<(), IoError> {
+        let frame = frame.into();
+        let data = frame.data();
+        dst.reserve(2 + HEADER_LEN + data.len());
+        dst.put_u16((HEADER_LEN + data.len()) as u16);
+        dst.put_u32(frame.id_word());
+        match &frame {
+            CanAnyFrame::Fd(fd) => {
+                dst.put_u8(FD_FRAME | fd.flags().bits());
+                dst.put_u8(data.len() as u8);
+            }
+            _ => {
+                dst.put_u8(0);
+                dst.put_u8(frame.dlc() as u8);
+            }
+        }
+        dst.extend_from_slice(data);
+        Ok(())
+    }
+}
+
+impl Decoder for LengthPrefixedCodec {
+    type Item = CanAnyFrame;
+    type Error = IoError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<CanAnyFrame>, IoError> {
+        if src.len() < 2 {
+            return Ok(None);
+        }
+        let len = u16::from_be_bytes([src[0], src[1]]) as usize;
+        if !(HEADER_LEN..=MAX_ENCODED_LEN).contains(&len) {
+            return Err(invalid_data("bad frame length prefix"));
+        }
+        if src.len() < 2 + len {
+            src.reserve(2 + len - src.len());
+            return Ok(None);
+        }
+
+        src.advance(2);
+        let mut buf = src.split_to(len);
+        let can_id = buf.get_u32();
+        let flags = buf.get_u8();
+        let n = buf.get_u8() as usize;
+        let data = &buf[..];
+
+        let frame = if flags & FD_FRAME != 0 {
+            let mut frame = canfd_frame_default();
+            if n != data.len() || n > frame.data.len() {
+                return Err(invalid_data("bad FD frame length"));
+            }
+            frame.can_id = can_id;
+            frame.flags = flags & !FD_FRAME;
+            frame.len = n as u8;
+            frame.data[..n].copy_from_slice(data);
+            CanAnyFrame::from(frame)
+        } else {
+            let mut frame = can_frame_default();
+            let expected = if can_id & CAN_RTR_FLAG != 0 { 0 } else { n };
+            if n > frame.data.len() || data.len() != expected {
+                return Err(invalid_data("bad frame length"));
+            }
+            frame.can_id = can_id;
+            frame.can_dlc = n as u8;
+            frame.data[..data.len()].copy_from_slice(data);
+            CanAnyFrame::from(frame)
+        };
+        Ok(Some(frame))
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CanRemoteFrame;
+
+    #[test]
+    fn test_raw_codec() {
+        let mut codec = RawCodec::<CanFdFrame>::new();
+        let frame = CanFdFrame::from_raw_id(0x123, &[0xAA; 24]).unwrap();
+        let mut buf = BytesMut::new();
+        codec.encode(frame, &mut buf).unwrap();
+        assert_eq!(size_of::<libc::canfd_frame>(), buf.len());
+
+        let mut partial = buf.split_to(40);
+        assert!(codec.decode(&mut partial).unwrap().is_none());
+        partial.unsplit(buf);
+        assert_eq!(Some(frame), codec.decode(&mut partial).unwrap());
+        assert!(partial.is_empty());
+    }
+
+    #[test]
+    fn test_raw_codec_invalid() {
+        // A classic frame claiming 200 bytes of data
+        let mut codec = RawCodec::<CanFrame>::new();
+        let mut buf = BytesMut::from(CanFrame::from_raw_id(0x123, &[1]).unwrap().as_bytes());
+        buf[4] = 200;
+        let err = codec.decode(&mut buf).unwrap_err();
+        assert_eq!(IoErrorKind::InvalidData, err.kind());
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_length_prefixed_codec() {
+        let frames: Vec<CanAnyFrame> = vec![
+            CanFrame::from_raw_id(0x123, &[1, 2, 3]).unwrap().into(),
+            CanRemoteFrame::remote_from_raw_id(0x1ABCDEF0, 4)
+                .unwrap()
+                .into(),
+            CanFdFrame::from_raw_id(0x456, &[0x55; 12]).unwrap().into(),
+        ];
+
+        let mut codec = LengthPrefixedCodec::new();
+        let mut buf = BytesMut::new();
+        for frame in &frames {
+            codec.encode(*frame, &mut buf).unwrap();
+        }
+        assert_eq!(&[0, 9, 0, 0, 1, 0x23, 0, 3, 1, 2, 3], &buf[..11]);
+        assert_eq!(&[0, 6, 0xC0 | 0x1A, 0xBC, 0xDE, 0xF0, 0, 4], &buf[11..19]);
+
+        let mut decoded = Vec::new();
+        while let Some(frame) = codec.decode(&mut buf).unwrap() {
+            decoded.push(frame);
+        }
+        assert_eq!(frames, decoded);
+
+        let mut bad = BytesMut::from(&[0u8, 200][..]);
+        assert!(codec.decode(&mut bad).is_err());
+    }
+}
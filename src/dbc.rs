@@ -0,0 +1,896 @@
+// socketcan/src/dbc.rs
+//
+// Decoding and encoding frames with a DBC database.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! DBC databases, to decode frames into named signals.
+//!
+//! A DBC file describes the messages on a bus, and the signals packed into
+//! each of them. This module parses the parts of the format needed to
+//! decode and encode the signals - the message (`BO_`) and signal (`SG_`)
+//! definitions, including multiplexed signals, and the IEEE float and
+//! double signal types (`SIG_VALTYPE_`) - and ignores the rest.
+//!
+//! The `VECTOR__INDEPENDENT_SIG_MSG` pseudo-message, which holds signals
+//! not assigned to any message, is skipped. With extended multiplexing,
+//! a signal that is both multiplexed and a multiplexor, like `m1M`, is
+//! decoded as a multiplexed signal, but the signals it selects are skipped
+//! with a warning, as only one level of multiplexing is supported.
+//!
+//! Decoding a frame produces a map of signal names to their physical
+//! values, and a map of values can be encoded back into a frame:
+//!
+//! ```
+//! use socketcan::{dbc::Database, CanFrame, EmbeddedFrame, Frame};
+//! use std::collections::HashMap;
+//!
+//! let db = Database::parse(r#"
+//! BO_ 256 Engine: 8 ECU
+//!  SG_ Speed : 0|16@1+ (0.25,0) [0|16383.75] "rpm" Dash
+//!  SG_ Temp : 16|8@1- (1,0) [-128|127] "degC" Dash
+//! "#).unwrap();
+//!
+//! let frame = CanFrame::from_raw_id(0x100, &[0x40, 0x1F, 0xF6, 0, 0, 0, 0, 0]).unwrap();
+//! let values = db.decode(&frame).unwrap();
+//! assert_eq!(2000.0, values["Speed"]);
+//! assert_eq!(-10.0, values["Temp"]);
+//!
+//! let frame = db.encode("Engine", &values).unwrap();
+//! assert_eq!(&[0x40, 0x1F, 0xF6, 0, 0, 0, 0, 0], frame.data());
+//! ```
+
+use crate::{
+    signal::{extract_bits, insert_bits, ByteOrder, Signal, SignalError},
+    CanAnyFrame, CanDataFrame, CanFdFrame, CanFilter, CanId, EmbeddedFrame, Frame, Socket,
+    SocketOptions, Timestamped,
+};
+#[cfg(feature = "tokio")]
+use futures::{future, Stream, StreamExt};
+use libc::CAN_EFF_FLAG;
+#[cfg(feature = "tokio")]
+use std::time::SystemTime;
+use std::{collections::HashMap, fs, io, path::Path};
+use thiserror::Error;
+
+/// The ID of the `VECTOR__INDEPENDENT_SIG_MSG` pseudo-message, which holds
+/// the signals that aren't sent in any message.
+const INDEPENDENT_SIG_MSG_ID: u32 = 0xC000_0000;
+
+/// An error parsing a DBC file, or decoding or encoding with it.
+#[derive(Error, Debug)]
+pub enum DbcError {
+    /// A line of the file couldn't be parsed
+    #[error("DBC parse error on line {line}: {msg}")]
+    Parse {
+        /// The line number, starting at one
+        line: usize,
+        /// A description of the problem
+        msg: String,
+    },
+    /// The message is not in the database
+    #[error("unknown message: {0}")]
+    UnknownMessage(String),
+    /// The signal is not in the database
+    #[error("unknown signal: {0}")]
+    UnknownSignal(String),
+    /// A signal value couldn't be encoded
+    #[error(transparent)]
+    Signal(#[from] SignalError),
+    /// The frame couldn't be created
+    #[error(transparent)]
+    Construction(#[from] crate::ConstructionError),
+    /// An I/O error reading the file
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+/// The multiplexing role of a signal.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Multiplex {
+    /// The signal is always present
+    #[default]
+    None,
+    /// The signal selects which multiplexed signals are present
+    Multiplexor,
+    /// The signal is only present when the multiplexor has this value.
+    ///
+    /// A signal that is also a multiplexor itself, with extended
+    /// multiplexing, is decoded as this.
+    Multiplexed(u64),
+}
+
+/// The type of the raw value of a signal, as set by `SIG_VALTYPE_`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ValueType {
+    /// An integer, signed or unsigned
+    #[default]
+    Integer,
+    /// A 32-bit IEEE float
+    Float,
+    /// A 64-bit IEEE double
+    Double,
+}
+
+impl ValueType {
+    /// Gets the length of the signal, in bits, required for the type, if
+    /// it's fixed.
+    fn len(&self) -> Option<u16> {
+        match self {
+            ValueType::Integer => None,
+            ValueType::Float => Some(32),
+            ValueType::Double => Some(64),
+        }
+    }
+}
+
+/// A signal definition from a DBC file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DbcSignal {
+    /// The name of the signal
+    pub name: String,
+    /// The location and scaling of the signal in the payload
+    pub signal: Signal,
+    /// The type of the raw value
+    pub value_type: ValueType,
+    /// The multiplexing role of the signal
+    pub mux: Multiplex,
+    /// The minimum physical value
+    pub min: f64,
+    /// The maximum physical value
+    pub max: f64,
+    /// The unit of the physical value
+    pub unit: String,
+}
+
+/// A message definition from a DBC file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    /// The CAN ID of the message
+    pub id: CanId,
+    /// The name of the message
+    pub name: String,
+    /// The size of the payload, in bytes
+    pub size: usize,
+    /// The signals in the message
+    pub signals: Vec<DbcSignal>,
+}
+
+impl DbcSignal {
+    /// Gets the physical value of the signal from the payload.
+    ///
+    /// An IEEE float or double raw value is scaled and offset like an
+    /// integer one.
+    pub fn decode(&self, data: &[u8]) -> Option<f64> {
+        let sig = &self.signal;
+        let raw = match self.value_type {
+            ValueType::Integer => return sig.decode(data),
+            ValueType::Float => {
+                let bits = extract_bits(data, sig.start, 32, sig.order)?;
+                f64::from(f32::from_bits(bits as u32))
+            }
+            ValueType::Double => f64::from_bits(extract_bits(data, sig.start, 64, sig.order)?),
+        };
+        Some(raw * sig.scale + sig.offset)
+    }
+
+    /// Inserts the physical value of the signal into the payload.
+    pub fn encode(&self, data: &mut [u8], value: f64) -> Result<(), SignalError> {
+        let sig = &self.signal;
+        let raw = (value - sig.offset) / sig.scale;
+        match self.value_type {
+            ValueType::Integer => sig.encode(data, value),
+            ValueType::Float => insert_bits(
+                data,
+                sig.start,
+                32,
+                sig.order,
+                u64::from((raw as f32).to_bits()),
+            ),
+            ValueType::Double => insert_bits(data, sig.start, 64, sig.order, raw.to_bits()),
+        }
+    }
+}
+
+impl Message {
+    /// Gets a signal by name.
+    pub fn signal(&self, name: &str) -> Option<&DbcSignal> {
+        self.signals.iter().find(|sig| sig.name == name)
+    }
+
+    /// Gets the multiplexor signal, if the message has one.
+    pub fn multiplexor(&self) -> Option<&DbcSignal> {
+        self.signals
+            .iter()
+            .find(|sig| sig.mux == Multiplex::Multiplexor)
+    }
+
+    /// Decodes the signals present in the payload into their physical
+    /// values.
+    ///
+    /// Multiplexed signals are only included if the multiplexor selects
+    /// them, and any signals that don't fit in the payload are skipped.
+    pub fn decode(&self, data: &[u8]) -> HashMap<String, f64> {
+        let mux = self
+            .multiplexor()
+            .and_then(|sig| sig.signal.decode_raw(data))
+            .map(|raw| raw as u64);
+
+        self.signals
+            .iter()
+            .filter(|sig| match sig.mux {
+                Multiplex::Multiplexed(n) => mux == Some(n),
+                _ => true,
+            })
+            .filter_map(|sig| Some((sig.name.clone(), sig.decode(data)?)))
+            .collect()
+    }
+
+    /// Decodes a single signal from the payload into its physical value.
+    ///
+    /// This is `None` if the signal is not in the message, doesn't fit in
+    /// the payload, or is multiplexed and not selected by the multiplexor.
+    pub fn decode_signal(&self, name: &str, data: &[u8]) -> Option<f64> {
+        let sig = self.signal(name)?;
+        if let Multiplex::Multiplexed(n) = sig.mux {
+            let mux = self.multiplexor()?.signal.decode_raw(data)?;
+            if mux as u64 != n {
+                return None;
+            }
+        }
+        sig.decode(data)
+    }
+
+    /// Encodes the signal values into a payload of the message size.
+    ///
+    /// Any signals missing from the map are encoded as zero. Multiplexed
+    /// signals are only encoded if the multiplexor value selects them.
+    pub fn encode_data(&self, values: &HashMap<String, f64>) -> Result<Vec<u8>, DbcError> {
+        let mut data = vec![0u8; self.size];
+
+        let mux = match self.multiplexor() {
+            Some(sig) => {
+                let val = values.get(&sig.name).copied().unwrap_or(0.0);
+                sig.signal.encode(&mut data, val)?;
+                sig.signal.decode_raw(&data).map(|raw| raw as u64)
+            }
+            None => None,
+        };
+
+        for sig in &self.signals {
+            match sig.mux {
+                Multiplex::Multiplexor => continue,
+                Multiplex::Multiplexed(n) if mux != Some(n) => continue,
+                _ => (),
+            }
+            if let Some(val) = values.get(&sig.name) {
+                sig.encode(&mut data, *val)?;
+            }
+        }
+        Ok(data)
+    }
+
+    /// Encodes the signal values into a frame.
+    ///
+    /// This creates a classic data frame if the message is up to 8 bytes,
+    /// and an FD frame otherwise.
+    pub fn encode(&self, values: &HashMap<String, f64>) -> Result<CanAnyFrame, DbcError> {
+        let data = self.encode_data(values)?;
+        let frame = if data.len() <= 8 {
+            CanDataFrame::try_new(self.id, &data)?.into()
+        } else {
+            CanFdFrame::try_new(self.id, &data)?.into()
+        };
+        Ok(frame)
+    }
+}
+
+// ===== Database =====
+
+/// A database of messages parsed from a DBC file.
+#[derive(Debug, Default, Clone)]
+pub struct Database {
+    messages: Vec<Message>,
+    by_id: HashMap<CanId, usize>,
+}
+
+impl Database {
+    /// Parses a database from the text of a DBC file.
+    pub fn parse(text: &str) -> Result<Self, DbcError> {
+        let mut db = Self::default();
+        // Set while in the signals of a skipped message
+        let mut skipping = false;
+
+        for (i, line) in text.lines().enumerate() {
+            let err = |msg: &str| DbcError::Parse {
+                line: i + 1,
+                msg: msg.into(),
+            };
+            let line = line.trim();
+
+            if let Some(rest) = line.strip_prefix("BO_ ") {
+                skipping = is_independent_sig_msg(rest);
+                if skipping {
+                    continue;
+                }
+                let msg = parse_message(rest).ok_or_else(|| err("invalid message"))?;
+                db.by_id.insert(msg.id, db.messages.len());
+                db.messages.push(msg);
+            } else if let Some(rest) = line.strip_prefix("SG_ ") {
+                if skipping {
+                    continue;
+                }
+                let sig = parse_signal(rest).ok_or_else(|| err("invalid signal"))?;
+                db.messages
+                    .last_mut()
+                    .ok_or_else(|| err("signal outside of a message"))?
+                    .signals
+                    .push(sig);
+            } else if let Some(rest) = line.strip_prefix("SG_MUL_VAL_ ") {
+                let (id, name, switch) =
+                    parse_mux_value(rest).ok_or_else(|| err("invalid multiplexor value"))?;
+                db.remove_nested(id, name, switch);
+            } else if let Some(rest) = line.strip_prefix("SIG_VALTYPE_ ") {
+                let (id, name, value_type) =
+                    parse_value_type(rest).ok_or_else(|| err("invalid signal value type"))?;
+                if !db.set_value_type(id, name, value_type) {
+                    return Err(err("signal length doesn't match its value type"));
+                }
+            }
+        }
+        Ok(db)
+    }
+
+    /// Removes a signal that is selected by a multiplexor other than the
+    /// main one of its message, as nested multiplexing isn't supported.
+    fn remove_nested(&mut self, id: CanId, name: &str, switch: &str) {
+        let Some(&i) = self.by_id.get(&id) else {
+            return;
+        };
+        let msg = &mut self.messages[i];
+        if msg.multiplexor().is_some_and(|mux| mux.name == switch) {
+            return;
+        }
+        log::warn!(
+            "Skipping signal {} of {}: nested multiplexing by {} isn't supported",
+            name,
+            msg.name,
+            switch
+        );
+        msg.signals.retain(|sig| sig.name != name);
+    }
+
+    /// Sets the type of the raw value of a signal.
+    ///
+    /// This is false if the signal's length doesn't fit the type. A signal
+    /// that's not in the database is ignored.
+    fn set_value_type(&mut self, id: CanId, name: &str, value_type: ValueType) -> bool {
+        let sig = self.by_id.get(&id).and_then(|&i| {
+            self.messages[i]
+                .signals
+                .iter_mut()
+                .find(|sig| sig.name == name)
+        });
+        match sig {
+            Some(sig) if value_type.len().is_some_and(|len| len != sig.signal.len) => false,
+            Some(sig) => {
+                sig.value_type = value_type;
+                true
+            }
+            None => true,
+        }
+    }
+
+    /// Reads and parses a DBC file.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, DbcError> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Gets all of the messages in the database.
+    pub fn messages(&self) -> &[Message] {
+        &self.messages
+    }
+
+    /// Gets a message by its CAN ID.
+    pub fn message(&self, id: impl Into<CanId>) -> Option<&Message> {
+        self.by_id.get(&id.into()).map(|&i| &self.messages[i])
+    }
+
+    /// Gets a message by name.
+    pub fn message_by_name(&self, name: &str) -> Option<&Message> {
+        self.messages.iter().find(|msg| msg.name == name)
+    }
+
+    /// Finds a signal by name, along with the message that contains it.
+    ///
+    /// If more than one message has a signal with the name, this is the
+    /// first one in the database.
+    pub fn find_signal(&self, name: &str) -> Option<(&Message, &DbcSignal)> {
+        self.messages
+            .iter()
+            .find_map(|msg| msg.signal(name).map(|sig| (msg, sig)))
+    }
+
+    /// Decodes a frame into its signal values.
+    ///
+    /// This returns `None` if the frame's ID is not in the database.
+    pub fn decode<F: Frame>(&self, frame: &F) -> Option<HashMap<String, f64>> {
+        self.message(frame.can_id())
+            .map(|msg| msg.decode(frame.data()))
+    }
+
+    /// Encodes signal values into a frame for the named message.
+    pub fn encode(
+        &self,
+        name: &str,
+        values: &HashMap<String, f64>,
+    ) -> Result<CanAnyFrame, DbcError> {
+        self.message_by_name(name)
+            .ok_or_else(|| DbcError::UnknownMessage(name.into()))?
+            .encode(values)
+    }
+}
+
+// ===== SignalBus =====
+
+/// A socket paired with a database, to receive signals by name.
+///
+/// With a blocking socket, [`SignalBus::subscribe_signal`] gives an
+/// iterator over the values of a signal. With a socket from the `tokio`
+/// module, `subscribe_signal_stream()` gives an async stream of them.
+///
+/// ```no_run
+/// use socketcan::{dbc::{Database, SignalBus}, CanSocket, Socket};
+///
+/// let db = Database::from_file("engine.dbc").unwrap();
+/// let sock = CanSocket::open("can0").unwrap();
+/// let mut bus = SignalBus::new(sock, db);
+///
+/// for val in bus.subscribe_signal("EngineSpeed").unwrap() {
+///     let val = val.unwrap();
+///     println!("{:?}: {} rpm", val.time, val.frame);
+/// }
+/// ```
+#[derive(Debug)]
+pub struct SignalBus<S> {
+    socket: S,
+    db: Database,
+}
+
+impl<S> SignalBus<S> {
+    /// Creates a bus from an open socket and a database.
+    pub fn new(socket: S, db: Database) -> Self {
+        Self { socket, db }
+    }
+
+    /// Gets the database.
+    pub fn database(&self) -> &Database {
+        &self.db
+    }
+
+    /// Gets the underlying socket.
+    pub fn socket(&self) -> &S {
+        &self.socket
+    }
+
+    /// Consumes the bus, returning the socket and the database.
+    pub fn into_parts(self) -> (S, Database) {
+        (self.socket, self.db)
+    }
+}
+
+impl<S: Socket + SocketOptions> SignalBus<S>
+where
+    S::FrameType: Frame,
+{
+    /// Subscribes to a signal by name.
+    ///
+    /// This sets the socket filter to receive only the message containing
+    /// the signal, replacing any filters already on the socket. The
+    /// returned iterator blocks reading frames, and yields the physical
+    /// value of the signal from each one, with the time it was received.
+    /// Frames that don't carry the signal, such as remote frames, or
+    /// multiplexed frames for another multiplexor value, are skipped.
+    pub fn subscribe_signal(&mut self, name: &str) -> Result<SignalSubscription<'_, S>, DbcError> {
+        let (msg, _) = self
+            .db
+            .find_signal(name)
+            .ok_or_else(|| DbcError::UnknownSignal(name.into()))?;
+        self.socket.set_filters(&[CanFilter::from_id(msg.id)])?;
+        Ok(SignalSubscription {
+            socket: &mut self.socket,
+            msg,
+            name: name.into(),
+        })
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<S, F> SignalBus<S>
+where
+    S: Stream<Item = crate::Result<F>> + Unpin + SocketOptions,
+    F: Frame,
+{
+    /// Subscribes to a signal by name, as an async stream.
+    ///
+    /// This is the counterpart of [`SignalBus::subscribe_signal`] for the
+    /// async sockets in the [`tokio`](crate::tokio) module. It sets the
+    /// socket filter in the same way, and the returned stream yields the
+    /// physical value of the signal from each frame, with the time it was
+    /// read from the socket.
+    pub fn subscribe_signal_stream(
+        &mut self,
+        name: &str,
+    ) -> Result<impl Stream<Item = crate::Result<Timestamped<f64>>> + '_, DbcError> {
+        let (msg, _) = self
+            .db
+            .find_signal(name)
+            .ok_or_else(|| DbcError::UnknownSignal(name.into()))?;
+        self.socket.set_filters(&[CanFilter::from_id(msg.id)])?;
+
+        let name = name.to_string();
+        Ok((&mut self.socket).filter_map(move |res| {
+            future::ready(match res {
+                // The filter may pass other frames queued before it was set
+                Ok(frame) if frame.can_id() == msg.id => msg
+                    .decode_signal(&name, frame.data())
+                    .map(|val| Ok(Timestamped::new(val, SystemTime::now()))),
+                Ok(_) => None,
+                Err(err) => Some(Err(err)),
+            })
+        }))
+    }
+}
+
+/// An iterator over the values of a subscribed signal.
+///
+/// This is created by [`SignalBus::subscribe_signal`].
+#[derive(Debug)]
+pub struct SignalSubscription<'a, S> {
+    socket: &'a mut S,
+    msg: &'a Message,
+    name: String,
+}
+
+impl<S: Socket> SignalSubscription<'_, S>
+where
+    S::FrameType: Frame,
+{
+    /// Gets the message carrying the signal.
+    pub fn message(&self) -> &Message {
+        self.msg
+    }
+}
+
+impl<S: Socket> Iterator for SignalSubscription<'_, S>
+where
+    S::FrameType: Frame,
+{
+    type Item = io::Result<Timestamped<f64>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let ts = match self.socket.read_frame_timestamped() {
+                Ok(ts) => ts,
+                Err(err) => return Some(Err(err)),
+            };
+            // The filter may pass other frames queued before it was set
+            if ts.frame.can_id() != self.msg.id {
+                continue;
+            }
+            if let Some(val) = self.msg.decode_signal(&self.name, ts.frame.data()) {
+                return Some(Ok(ts.map(|_| val)));
+            }
+        }
+    }
+}
+
+/// Parses a message definition, after the `BO_` keyword, like:
+///
+/// `256 Engine: 8 ECU`
+fn parse_message(s: &str) -> Option<Message> {
+    let (head, tail) = s.split_once(':')?;
+    let mut head = head.split_whitespace();
+    let id = parse_id(head.next()?)?;
+    let name = head.next()?.to_string();
+    let size = tail.split_whitespace().next()?.parse().ok()?;
+
+    Some(Message {
+        id,
+        name,
+        size,
+        signals: Vec::new(),
+    })
+}
+
+/// Parses a message ID, with the extended flag in the top bit.
+fn parse_id(s: &str) -> Option<CanId> {
+    let raw_id: u32 = s.parse().ok()?;
+    if raw_id & CAN_EFF_FLAG != 0 {
+        CanId::extended(raw_id & !CAN_EFF_FLAG).ok()
+    } else {
+        CanId::standard(u16::try_from(raw_id).ok()?).ok()
+    }
+}
+
+/// Determines if a message definition, after the `BO_` keyword, is the
+/// `VECTOR__INDEPENDENT_SIG_MSG` pseudo-message.
+fn is_independent_sig_msg(s: &str) -> bool {
+    s.split_whitespace()
+        .next()
+        .and_then(|id| id.parse::<u32>().ok())
+        == Some(INDEPENDENT_SIG_MSG_ID)
+}
+
+/// Parses a signal definition, after the `SG_` keyword, like:
+///
+/// `Speed m1 : 0|16@1+ (0.25,0) [0|16383.75] "rpm" Dash`
+fn parse_signal(s: &str) -> Option<DbcSignal> {
+    let (head, tail) = s.split_once(':')?;
+    let mut head = head.split_whitespace();
+    let name = head.next()?.to_string();
+    let mux = match head.next() {
+        None => Multiplex::None,
+        Some("M") => Multiplex::Multiplexor,
+        Some(m) => {
+            // An `m1M` signal is also a multiplexor, for nested signals
+            let m = m.strip_prefix('m')?;
+            Multiplex::Multiplexed(m.strip_suffix('M').unwrap_or(m).parse().ok()?)
+        }
+    };
+
+    let tail = tail.trim();
+    let (layout, tail) = tail.split_once(' ')?;
+    let (start, layout) = layout.split_once('|')?;
+    let (len, layout) = layout.split_once('@')?;
+    let order = match layout.get(..1)? {
+        "1" => ByteOrder::LittleEndian,
+        "0" => ByteOrder::BigEndian,
+        _ => return None,
+    };
+    let signed = match layout.get(1..2)? {
+        "+" => false,
+        "-" => true,
+        _ => return None,
+    };
+
+    let (scaling, tail) = tail.trim().strip_prefix('(')?.split_once(')')?;
+    let (scale, offset) = scaling.split_once(',')?;
+    let (range, tail) = tail.trim().strip_prefix('[')?.split_once(']')?;
+    let (min, max) = range.split_once('|')?;
+    let unit = tail
+        .trim()
+        .strip_prefix('"')
+        .and_then(|s| s.split_once('"'))
+        .map(|(unit, _)| unit.to_string())
+        .unwrap_or_default();
+
+    let mut signal = Signal::new(start.parse().ok()?, len.parse().ok()?, order)
+        .with_scale(scale.trim().parse().ok()?, offset.trim().parse().ok()?);
+    signal.signed = signed;
+
+    Some(DbcSignal {
+        name,
+        signal,
+        value_type: ValueType::Integer,
+        mux,
+        min: min.trim().parse().ok()?,
+        max: max.trim().parse().ok()?,
+        unit,
+    })
+}
+
+/// Parses the multiplexor values of a signal with extended multiplexing,
+/// after the `SG_MUL_VAL_` keyword, like:
+///
+/// `1024 Temp Sensor 1-1, 3-4;`
+///
+/// Returns the message ID, the signal, and its multiplexor.
+fn parse_mux_value(s: &str) -> Option<(CanId, &str, &str)> {
+    let mut fields = s.split_whitespace();
+    let id = parse_id(fields.next()?)?;
+    Some((id, fields.next()?, fields.next()?))
+}
+
+/// Parses the value type of a signal, after the `SIG_VALTYPE_` keyword,
+/// like:
+///
+/// `1024 Temp : 1;`
+///
+/// Returns the message ID, the signal, and its value type.
+fn parse_value_type(s: &str) -> Option<(CanId, &str, ValueType)> {
+    let (head, tail) = s.split_once(':')?;
+    let mut head = head.split_whitespace();
+    let id = parse_id(head.next()?)?;
+    let name = head.next()?;
+    let value_type = match tail.trim().trim_end_matches(';').trim() {
+        "0" => ValueType::Integer,
+        "1" => ValueType::Float,
+        "2" => ValueType::Double,
+        _ => return None,
+    };
+    Some((id, name, value_type))
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EmbeddedFrame, ExtendedId};
+
+    const DBC: &str = r#"
+VERSION ""
+
+BU_: ECU Dash
+
+BO_ 2364540158 EEC1: 8 ECU
+ SG_ EngineSpeed : 24|16@1+ (0.125,0) [0|8031.875] "rpm" Dash
+
+BO_ 512 Diag: 8 ECU
+ SG_ Page M : 7|8@0+ (1,0) [0|255] "" Dash
+ SG_ Volts m0 : 15|16@0+ (0.01,0) [0|655.35] "V" Dash
+ SG_ Amps m1 : 15|16@0- (0.1,0) [-3276.8|3276.7] "A" Dash
+
+CM_ BO_ 512 "Diagnostic pages";
+"#;
+
+    #[test]
+    fn test_parse() {
+        let db = Database::parse(DBC).unwrap();
+        assert_eq!(2, db.messages().len());
+
+        let msg = db.message(ExtendedId::new(0x0CF0_04FE).unwrap()).unwrap();
+        assert_eq!("EEC1", msg.name);
+        assert_eq!("rpm", msg.signal("EngineSpeed").unwrap().unit);
+
+        let msg = db.message_by_name("Diag").unwrap();
+        assert_eq!(Multiplex::Multiplexor, msg.multiplexor().unwrap().mux);
+        assert_eq!(Multiplex::Multiplexed(1), msg.signal("Amps").unwrap().mux);
+        assert!(msg.signal("Amps").unwrap().signal.signed);
+
+        assert!(Database::parse("BO_ 1 Bad\n").is_err());
+    }
+
+    #[test]
+    fn test_multiplexed() {
+        let db = Database::parse(DBC).unwrap();
+
+        let values = HashMap::from([("Page".to_string(), 1.0), ("Amps".to_string(), -2.5)]);
+        let frame = db.encode("Diag", &values).unwrap();
+        assert_eq!(&[0x01, 0xFF, 0xE7, 0, 0, 0, 0, 0], frame.data());
+
+        let decoded = db.decode(&frame).unwrap();
+        assert_eq!(1.0, decoded["Page"]);
+        assert_eq!(-2.5, decoded["Amps"]);
+        assert!(!decoded.contains_key("Volts"));
+
+        let values = HashMap::from([("EngineSpeed".to_string(), 1500.0)]);
+        let frame = db.encode("EEC1", &values).unwrap();
+        assert!(frame.is_extended());
+        assert_eq!(1500.0, db.decode(&frame).unwrap()["EngineSpeed"]);
+        assert!(db.encode("Nope", &values).is_err());
+    }
+
+    // As exported by Vector CANdb++
+    const VECTOR_DBC: &str = r#"VERSION ""
+
+
+NS_ : 
+	NS_DESC_
+	CM_
+	BA_DEF_
+	BA_
+	VAL_
+	SG_MUL_VAL_
+
+BS_:
+
+BU_: Gateway Sensor
+
+
+BO_ 1024 SensorData: 8 Sensor
+ SG_ Status : 56|8@1+ (1,0) [0|255] "" Vector__XXX
+ SG_ Pressure m2 : 16|16@1+ (0.1,0) [0|6553.5] "kPa" Gateway
+ SG_ Humidity m1 : 24|8@1+ (0.5,0) [0|100] "%" Gateway
+ SG_ Temp m4 : 24|8@1- (1,-40) [-168|87] "degC" Gateway
+ SG_ Channel m1M : 16|8@1+ (1,0) [0|255] "" Gateway
+ SG_ Kind M : 0|8@1+ (1,0) [0|255] "" Gateway
+
+BO_ 3221225472 VECTOR__INDEPENDENT_SIG_MSG: 0 Vector__XXX
+ SG_ Spare : 0|8@1+ (1,0) [0|0] "" Vector__XXX
+
+CM_ BO_ 1024 "Sensor readings";
+BA_DEF_ BO_  "GenMsgCycleTime" INT 0 65535;
+BA_DEF_DEF_  "GenMsgCycleTime" 100;
+BA_ "GenMsgCycleTime" BO_ 1024 50;
+VAL_ 1024 Kind 1 "Climate" 2 "Pressure" ;
+SG_MUL_VAL_ 1024 Humidity Channel 0-0;
+SG_MUL_VAL_ 1024 Pressure Kind 2-2;
+SG_MUL_VAL_ 1024 Channel Kind 1-1;
+
+"#;
+
+    #[test]
+    fn test_parse_vector() {
+        let db = Database::parse(VECTOR_DBC).unwrap();
+        assert_eq!(1, db.messages().len());
+        assert!(db
+            .messages()
+            .iter()
+            .all(|msg| msg.signal("Spare").is_none()));
+
+        let msg = db.message_by_name("SensorData").unwrap();
+        assert_eq!("Kind", msg.multiplexor().unwrap().name);
+        assert_eq!(
+            Multiplex::Multiplexed(1),
+            msg.signal("Channel").unwrap().mux
+        );
+        assert_eq!(Multiplex::Multiplexed(4), msg.signal("Temp").unwrap().mux);
+        // Nested under Channel
+        assert!(msg.signal("Humidity").is_none());
+
+        let values = msg.decode(&[2, 0, 0x10, 0x27, 0, 0, 0, 0x01]);
+        assert_eq!(1000.0, values["Pressure"]);
+        assert_eq!(1.0, values["Status"]);
+        assert!(!values.contains_key("Channel"));
+
+        let values = msg.decode(&[1, 0, 3, 0, 0, 0, 0, 0]);
+        assert_eq!(3.0, values["Channel"]);
+    }
+
+    #[test]
+    fn test_decode_signal() {
+        let db = Database::parse(DBC).unwrap();
+
+        let (msg, sig) = db.find_signal("Amps").unwrap();
+        assert_eq!("Diag", msg.name);
+        assert_eq!(Multiplex::Multiplexed(1), sig.mux);
+        assert!(db.find_signal("Nope").is_none());
+
+        let data = [0x01, 0xFF, 0xE7, 0, 0, 0, 0, 0];
+        assert_eq!(Some(-2.5), msg.decode_signal("Amps", &data));
+        assert_eq!(None, msg.decode_signal("Volts", &data));
+        assert_eq!(None, msg.decode_signal("Amps", &[]));
+    }
+
+    #[test]
+    fn test_float_signals() {
+        let db = Database::parse(
+            r#"
+BO_ 768 Sensor: 16 ECU
+ SG_ Temp : 0|32@1- (1,0) [-1000|1000] "degC" Dash
+ SG_ Pressure : 71|64@0- (0.5,0) [0|1E+006] "kPa" Dash
+
+SIG_VALTYPE_ 768 Temp : 1;
+SIG_VALTYPE_ 768 Pressure : 2;
+"#,
+        )
+        .unwrap();
+
+        let msg = db.message_by_name("Sensor").unwrap();
+        assert_eq!(ValueType::Float, msg.signal("Temp").unwrap().value_type);
+        assert_eq!(
+            ValueType::Double,
+            msg.signal("Pressure").unwrap().value_type
+        );
+
+        let mut data = [0u8; 16];
+        data[..4].copy_from_slice(&(-12.5f32).to_le_bytes());
+        data[8..].copy_from_slice(&(2000.25f64).to_be_bytes());
+        let values = msg.decode(&data);
+        assert_eq!(-12.5, values["Temp"]);
+        assert_eq!(1000.125, values["Pressure"]);
+
+        assert_eq!(&data[..], &msg.encode_data(&values).unwrap()[..]);
+
+        let bad = "BO_ 768 Sensor: 8 ECU\n SG_ Temp : 0|16@1- (1,0) [0|0] \"\" Dash\n\
+                   SIG_VALTYPE_ 768 Temp : 1;\n";
+        assert!(Database::parse(bad).is_err());
+    }
+}
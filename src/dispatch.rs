@@ -0,0 +1,296 @@
+// socketcan/src/dispatch.rs
+//
+// Routing of received frames to handlers by ID.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! Routing of frames to handlers by ID.
+//!
+//! A [`Dispatcher`] holds a set of handlers, each registered for a
+//! [`Route`]: a single ID, a range of IDs, or an ID and mask like a socket
+//! filter. Each frame given to the dispatcher is passed to every handler
+//! with a matching route, in the order they were registered. Frames that
+//! no route matches go to the default handler, if there is one.
+//!
+//! ```no_run
+//! use socketcan::{dispatch::Dispatcher, CanAnyFrame, CanFdSocket, CanId, Frame, Socket};
+//!
+//! let sock = CanFdSocket::open("can0").unwrap();
+//!
+//! let mut disp = Dispatcher::<CanAnyFrame>::new();
+//! disp.on_id(CanId::standard(0x100).unwrap(), |frame| println!("Status: {:?}", frame));
+//! disp.on_range(0x700..=0x77F, false, |frame| println!("Heartbeat: {:?}", frame));
+//! disp.on_default(|frame| println!("Unhandled: {:X}", frame.raw_id()));
+//!
+//! loop {
+//!     disp.dispatch_next(&sock).unwrap();
+//! }
+//! ```
+
+use crate::{backend::CanReceiver, CanFilter, CanId, Frame};
+use libc::CAN_ERR_FLAG;
+use std::{fmt, io::Result as IoResult, ops::RangeInclusive};
+
+/// The handle to a registered handler, used to remove it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct HandlerId(usize);
+
+/// The frames that a handler receives.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Route {
+    /// A single ID
+    Id(CanId),
+    /// A range of raw IDs, either standard or extended
+    Range {
+        /// The raw IDs
+        ids: RangeInclusive<u32>,
+        /// Whether the IDs are extended
+        extended: bool,
+    },
+    /// An ID and mask, matched the same way as a socket filter
+    Filter(CanFilter),
+}
+
+impl Route {
+    /// Determines if the route matches the frame.
+    ///
+    /// Error frames only match a filter that selects them.
+    pub fn matches<F: Frame>(&self, frame: &F) -> bool {
+        match self {
+            Self::Filter(filter) => filter.matches(frame.id_word()),
+            _ if frame.id_word() & CAN_ERR_FLAG != 0 => false,
+            Self::Id(id) => frame.can_id() == *id,
+            Self::Range { ids, extended } => {
+                frame.is_extended() == *extended && ids.contains(&frame.raw_id())
+            }
+        }
+    }
+}
+
+impl From<CanId> for Route {
+    fn from(id: CanId) -> Self {
+        Self::Id(id)
+    }
+}
+
+impl From<CanFilter> for Route {
+    fn from(filter: CanFilter) -> Self {
+        Self::Filter(filter)
+    }
+}
+
+/// A frame handler.
+type Handler<'a, F> = Box<dyn FnMut(&F) + 'a>;
+
+// ===== Dispatcher =====
+
+/// A router of frames to handlers by ID.
+pub struct Dispatcher<'a, F> {
+    routes: Vec<(HandlerId, Route, Handler<'a, F>)>,
+    default: Option<Handler<'a, F>>,
+    next_id: usize,
+}
+
+impl<F> fmt::Debug for Dispatcher<'_, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Dispatcher")
+            .field(
+                "routes",
+                &self
+                    .routes
+                    .iter()
+                    .map(|(id, route, _)| (id, route))
+                    .collect::<Vec<_>>(),
+            )
+            .field("default", &self.default.is_some())
+            .finish()
+    }
+}
+
+impl<F> Default for Dispatcher<'_, F> {
+    fn default() -> Self {
+        Self {
+            routes: Vec::new(),
+            default: None,
+            next_id: 0,
+        }
+    }
+}
+
+impl<'a, F: Frame> Dispatcher<'a, F> {
+    /// Creates a dispatcher with no handlers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a handler for a route.
+    pub fn on<R, H>(&mut self, route: R, handler: H) -> HandlerId
+    where
+        R: Into<Route>,
+        H: FnMut(&F) + 'a,
+    {
+        let id = HandlerId(self.next_id);
+        self.next_id += 1;
+        self.routes.push((id, route.into(), Box::new(handler)));
+        id
+    }
+
+    /// Registers a handler for a single ID.
+    pub fn on_id<H>(&mut self, id: CanId, handler: H) -> HandlerId
+    where
+        H: FnMut(&F) + 'a,
+    {
+        self.on(id, handler)
+    }
+
+    /// Registers a handler for a range of standard or extended IDs.
+    pub fn on_range<H>(&mut self, ids: RangeInclusive<u32>, extended: bool, handler: H) -> HandlerId
+    where
+        H: FnMut(&F) + 'a,
+    {
+        self.on(Route::Range { ids, extended }, handler)
+    }
+
+    /// Registers a handler for an ID and mask, matched the same way as a
+    /// socket filter.
+    pub fn on_mask<H>(&mut self, id: u32, mask: u32, handler: H) -> HandlerId
+    where
+        H: FnMut(&F) + 'a,
+    {
+        self.on(CanFilter::new(id, mask), handler)
+    }
+
+    /// Sets the handler for frames that no route matches.
+    pub fn on_default<H>(&mut self, handler: H)
+    where
+        H: FnMut(&F) + 'a,
+    {
+        self.default = Some(Box::new(handler));
+    }
+
+    /// Removes a handler, returning whether it was registered.
+    pub fn remove(&mut self, id: HandlerId) -> bool {
+        let n = self.routes.len();
+        self.routes.retain(|(hid, _, _)| *hid != id);
+        self.routes.len() != n
+    }
+
+    /// Removes the default handler.
+    pub fn clear_default(&mut self) {
+        self.default = None;
+    }
+
+    /// Passes a frame to every handler with a matching route, or to the
+    /// default handler if none match.
+    ///
+    /// Returns the number of routed handlers that received the frame, not
+    /// counting the default handler.
+    pub fn dispatch(&mut self, frame: &F) -> usize {
+        let mut n = 0;
+        for (_, route, handler) in &mut self.routes {
+            if route.matches(frame) {
+                handler(frame);
+                n += 1;
+            }
+        }
+        if n == 0 {
+            if let Some(handler) = &mut self.default {
+                handler(frame);
+            }
+        }
+        n
+    }
+
+    /// Reads the next frame from the socket, or any other receiver, and
+    /// dispatches it.
+    pub fn dispatch_next<S>(&mut self, sock: &S) -> IoResult<usize>
+    where
+        S: CanReceiver<Frame = F>,
+    {
+        let frame = sock.receive()?;
+        Ok(self.dispatch(&frame))
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CanErrorFrame, CanFrame};
+    use std::cell::RefCell;
+
+    #[test]
+    fn test_route() {
+        let std = CanFrame::from_raw_id(0x123, &[]).unwrap();
+        let ext = CanFrame::from_raw_id(0x123 << 11, &[]).unwrap();
+        let err = CanFrame::Error(CanErrorFrame::new_error(0x004, &[]).unwrap());
+
+        let route = Route::from(CanId::standard(0x123).unwrap());
+        assert!(route.matches(&std));
+        assert!(!route.matches(&ext));
+
+        let route = Route::Range {
+            ids: 0x100..=0x1FF,
+            extended: false,
+        };
+        assert!(route.matches(&std));
+        assert!(!route.matches(&ext));
+        assert!(!route.matches(&err));
+
+        let route = Route::from(CanFilter::new(0x120, 0x7F0));
+        assert!(route.matches(&std));
+        assert!(!route.matches(&err));
+        let route = Route::from(CanFilter::new_inverted(0x120, 0x7F0));
+        assert!(!route.matches(&std));
+    }
+
+    #[test]
+    fn test_dispatch() {
+        let log = RefCell::new(Vec::new());
+
+        let mut disp = Dispatcher::new();
+        let a = disp.on_id(CanId::standard(0x100).unwrap(), |f: &CanFrame| {
+            log.borrow_mut().push(("a", f.raw_id()))
+        });
+        disp.on_range(0x100..=0x10F, false, |f| {
+            log.borrow_mut().push(("b", f.raw_id()))
+        });
+        disp.on_default(|f| log.borrow_mut().push(("default", f.raw_id())));
+
+        assert_eq!(
+            2,
+            disp.dispatch(&CanFrame::from_raw_id(0x100, &[]).unwrap())
+        );
+        assert_eq!(
+            1,
+            disp.dispatch(&CanFrame::from_raw_id(0x101, &[]).unwrap())
+        );
+        assert_eq!(
+            0,
+            disp.dispatch(&CanFrame::from_raw_id(0x200, &[]).unwrap())
+        );
+        assert!(disp.remove(a));
+        assert_eq!(
+            1,
+            disp.dispatch(&CanFrame::from_raw_id(0x100, &[]).unwrap())
+        );
+        drop(disp);
+
+        assert_eq!(
+            vec![
+                ("a", 0x100),
+                ("b", 0x100),
+                ("b", 0x101),
+                ("default", 0x200),
+                ("b", 0x100)
+            ],
+            log.into_inner()
+        );
+    }
+}
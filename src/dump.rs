@@ -22,21 +22,57 @@
 //!
 //! Can be parsed by a `Reader` object. The API is inspired by the
 //! [csv](https://crates.io/crates/csv) crate.
+//!
+//! Lines that are already in memory can be parsed with [`parse_line`],
+//! which borrows from the line and doesn't allocate:
+//!
+//! ```
+//! use socketcan::{dump, EmbeddedFrame};
+//!
+//! let rec = dump::parse_line(b"(1469439874.299654) can1 701#7F\n").unwrap();
+//! assert_eq!("can1", rec.device);
+//! assert_eq!(&[0x7F], rec.frame.data());
+//! ```
 
 use crate::{
-    frame::{FdFlags, IdFlags},
-    CanDataFrame, CanFdFrame,
+    frame::{FdFlags, IdFlags, CANFD_MAX_DLEN},
+    hex, CanDataFrame, CanFdFrame,
 };
 use embedded_can::StandardId;
-use hex::FromHex;
 use libc::canid_t;
 use std::{fs, io, path};
 
-// cannot be generic, because from_str_radix is not part of any Trait
-fn parse_raw(bytes: &[u8], radix: u32) -> Option<u64> {
-    ::std::str::from_utf8(bytes)
-        .ok()
-        .and_then(|s| u64::from_str_radix(s, radix).ok())
+// Cuts off a trailing "\n" or "\r\n"
+fn trim_eol(mut line: &[u8]) -> &[u8] {
+    if let Some((b'\n', rest)) = line.split_last() {
+        line = rest;
+    }
+    if let Some((b'\r', rest)) = line.split_last() {
+        line = rest;
+    }
+    line
+}
+
+// Parses a non-empty string of decimal digits
+fn parse_dec(bytes: &[u8]) -> Option<u64> {
+    if bytes.is_empty() {
+        return None;
+    }
+    bytes.iter().try_fold(0u64, |n, &c| {
+        let d = c.wrapping_sub(b'0');
+        (d < 10).then_some(())?;
+        n.checked_mul(10)?.checked_add(u64::from(d))
+    })
+}
+
+// Parses a non-empty string of hex digits
+fn parse_hex(bytes: &[u8]) -> Option<u64> {
+    if bytes.is_empty() || bytes.len() > 16 {
+        return None;
+    }
+    bytes.iter().try_fold(0u64, |n, &c| {
+        Some(n << 4 | u64::from(hex::decode_digit(c)?))
+    })
 }
 
 #[derive(Debug)]
@@ -83,6 +119,12 @@ pub struct CanDumpRecord<'a> {
     pub frame: super::CanAnyFrame,
 }
 
+impl From<CanDumpRecord<'_>> for crate::capture::Record {
+    fn from(rec: CanDumpRecord<'_>) -> Self {
+        Self::new(rec.t_us, rec.device, rec.frame)
+    }
+}
+
 #[derive(Debug)]
 /// candump line parse error
 pub enum ParseError {
@@ -119,6 +161,9 @@ impl<R: io::BufRead> Reader<R> {
     }
 
     /// Advance state, returning next record.
+    ///
+    /// The line buffer is reused from one record to the next, so, once it
+    /// has grown to fit the longest line, this doesn't allocate.
     pub fn next_record(&mut self) -> Result<Option<CanDumpRecord>, ParseError> {
         self.line_buf.clear();
         let bytes_read = self.rdr.read_until(b'\n', &mut self.line_buf)?;
@@ -127,99 +172,106 @@ impl<R: io::BufRead> Reader<R> {
         if bytes_read == 0 {
             return Ok(None);
         }
+        parse_line(&self.line_buf).map(Some)
+    }
+}
 
-        let mut field_iter = self.line_buf.split(|&c| c == b' ');
+/// Parses a single line of a candump log, such as
+/// `(1469439874.299654) can1 701#7F`.
+///
+/// The record borrows the device name from the line, and the frame is
+/// decoded on the stack, so this never allocates. That makes it suitable
+/// for running over very large logs, one line at a time, such as from a
+/// memory-mapped file. A trailing newline is ignored.
+pub fn parse_line(line: &[u8]) -> Result<CanDumpRecord<'_>, ParseError> {
+    let line = trim_eol(line);
+    let mut field_iter = line.split(|&c| c == b' ');
+
+    // parse time field
+    let f = field_iter.next().ok_or(ParseError::UnexpectedEndOfLine)?;
+
+    if f.len() < 3 || f[0] != b'(' || f[f.len() - 1] != b')' {
+        return Err(ParseError::InvalidTimestamp);
+    }
 
-        // parse time field
-        let f = field_iter.next().ok_or(ParseError::UnexpectedEndOfLine)?;
+    let inner = &f[1..f.len() - 1];
 
-        if f.len() < 3 || f[0] != b'(' || f[f.len() - 1] != b')' {
-            return Err(ParseError::InvalidTimestamp);
-        }
+    // split at dot, read both parts
+    let dot = inner
+        .iter()
+        .position(|&c| c == b'.')
+        .ok_or(ParseError::InvalidTimestamp)?;
+
+    let (num, mant) = inner.split_at(dot);
+
+    // parse number and multiply
+    let n_num = parse_dec(num).ok_or(ParseError::InvalidTimestamp)?;
+    let n_mant = parse_dec(&mant[1..]).ok_or(ParseError::InvalidTimestamp)?;
+    let t_us = n_num.saturating_mul(1_000_000).saturating_add(n_mant);
 
-        let inner = &f[1..f.len() - 1];
-
-        // split at dot, read both parts
-        let dot = inner
-            .iter()
-            .position(|&c| c == b'.')
-            .ok_or(ParseError::InvalidTimestamp)?;
-
-        let (num, mant) = inner.split_at(dot);
-
-        // parse number and multiply
-        let n_num: u64 = parse_raw(num, 10).ok_or(ParseError::InvalidTimestamp)?;
-        let n_mant: u64 = parse_raw(&mant[1..], 10).ok_or(ParseError::InvalidTimestamp)?;
-        let t_us = n_num.saturating_mul(1_000_000).saturating_add(n_mant);
-
-        let f = field_iter.next().ok_or(ParseError::UnexpectedEndOfLine)?;
-
-        // device name
-        let device = ::std::str::from_utf8(f).map_err(|_| ParseError::InvalidDeviceName)?;
-
-        // parse packet
-        let can_raw = field_iter.next().ok_or(ParseError::UnexpectedEndOfLine)?;
-
-        let sep_idx = can_raw
-            .iter()
-            .position(|&c| c == b'#')
-            .ok_or(ParseError::InvalidCanFrame)?;
-        let (can_id, mut can_data) = can_raw.split_at(sep_idx);
-
-        // determine frame type (FD or classical) and skip separator(s)
-        let mut fd_flags = FdFlags::empty();
-        let is_fd_frame = if let Some(&b'#') = can_data.get(1) {
-            fd_flags = FdFlags::from_bits_truncate(can_data[2]);
-            can_data = &can_data[3..];
-            true
-        } else {
-            can_data = &can_data[1..];
-            false
-        };
-
-        // cut of linefeed
-        if let Some(&b'\n') = can_data.last() {
-            can_data = &can_data[..can_data.len() - 1];
-        };
-        // cut off \r
-        if let Some(&b'\r') = can_data.last() {
-            can_data = &can_data[..can_data.len() - 1];
-        };
-
-        let mut flags = IdFlags::empty();
-        flags.set(IdFlags::RTR, b"R" == can_data);
-        // TODO: How are error frames saved?
-
-        let data = if flags.contains(IdFlags::RTR) {
-            Vec::new()
-        } else {
-            Vec::from_hex(can_data).map_err(|_| ParseError::InvalidCanFrame)?
-        };
-        let frame: super::CanAnyFrame = if is_fd_frame {
-            CanFdFrame::init(
-                parse_raw(can_id, 16).ok_or(ParseError::InvalidCanFrame)? as canid_t | flags.bits(),
-                &data,
-                fd_flags,
-            )
+    let f = field_iter.next().ok_or(ParseError::UnexpectedEndOfLine)?;
+
+    // device name
+    let device = std::str::from_utf8(f).map_err(|_| ParseError::InvalidDeviceName)?;
+
+    // parse packet
+    let can_raw = field_iter.next().ok_or(ParseError::UnexpectedEndOfLine)?;
+    let frame = parse_frame(can_raw)?;
+
+    Ok(CanDumpRecord {
+        t_us,
+        device,
+        frame,
+    })
+}
+
+/// Parses the frame field of a candump line, like `701#7F` or `701##17F`.
+fn parse_frame(can_raw: &[u8]) -> Result<super::CanAnyFrame, ParseError> {
+    let sep_idx = can_raw
+        .iter()
+        .position(|&c| c == b'#')
+        .ok_or(ParseError::InvalidCanFrame)?;
+    let (can_id, mut can_data) = can_raw.split_at(sep_idx);
+    let can_id = parse_hex(can_id).ok_or(ParseError::InvalidCanFrame)?;
+
+    // determine frame type (FD or classical) and skip separator(s)
+    let mut fd_flags = FdFlags::empty();
+    let is_fd_frame = if let Some(&b'#') = can_data.get(1) {
+        let flags = can_data.get(2).ok_or(ParseError::InvalidCanFrame)?;
+        fd_flags = FdFlags::from_bits_truncate(*flags);
+        can_data = &can_data[3..];
+        true
+    } else {
+        can_data = &can_data[1..];
+        false
+    };
+
+    let mut flags = IdFlags::empty();
+    flags.set(IdFlags::RTR, b"R" == can_data);
+    // TODO: How are error frames saved?
+
+    let mut buf = [0u8; CANFD_MAX_DLEN];
+    let data = if flags.contains(IdFlags::RTR) {
+        &buf[..0]
+    } else {
+        let n = hex::decode_into(can_data, &mut buf).map_err(|_| ParseError::InvalidCanFrame)?;
+        &buf[..n]
+    };
+
+    let frame = if is_fd_frame {
+        CanFdFrame::init(can_id as canid_t | flags.bits(), data, fd_flags)
             .map(super::CanAnyFrame::Fd)
-        } else {
-            // TODO: Check for other frame types?
-            // is extended?
-            let can_id = parse_raw(can_id, 16).ok_or(ParseError::InvalidCanFrame)?;
-            if can_id >= StandardId::MAX.as_raw() as u64 {
-                flags.set(IdFlags::EFF, true);
-            }
-            CanDataFrame::init(can_id as canid_t | flags.bits(), &data)
-                .map(super::CanFrame::Data)
-                .map(|f| f.into())
-        }?;
-
-        Ok(Some(CanDumpRecord {
-            t_us,
-            device,
-            frame,
-        }))
-    }
+    } else {
+        // TODO: Check for other frame types?
+        // is extended?
+        if can_id >= StandardId::MAX.as_raw() as u64 {
+            flags.set(IdFlags::EFF, true);
+        }
+        CanDataFrame::init(can_id as canid_t | flags.bits(), data)
+            .map(super::CanFrame::Data)
+            .map(|f| f.into())
+    }?;
+    Ok(frame)
 }
 
 impl<'a, R: io::Read> Iterator for CanDumpRecords<'a, io::BufReader<R>> {
@@ -259,7 +311,7 @@ mod test {
                 assert!(!frame.is_remote_frame());
                 assert!(!frame.is_error_frame());
                 assert!(!frame.is_extended());
-                assert_eq!(frame.data(), &[]);
+                assert_eq!(frame.data(), &[] as &[u8]);
             } else {
                 panic!("Expected Normal frame, got FD");
             }
@@ -302,7 +354,7 @@ mod test {
                 assert_eq!(frame.is_remote_frame(), false);
                 assert_eq!(frame.is_error_frame(), false);
                 assert_eq!(frame.is_extended(), true);
-                assert_eq!(frame.data(), &[]);
+                assert_eq!(frame.data(), &[] as &[u8]);
             } else {
                 panic!("Expected Normal frame, got FD");
             }
@@ -346,7 +398,7 @@ mod test {
                 assert!(!frame.is_extended());
                 assert!(!frame.is_brs());
                 assert!(!frame.is_esi());
-                assert_eq!(frame.data(), &[]);
+                assert_eq!(frame.data(), &[] as &[u8]);
             } else {
                 panic!("Expected FD frame, got Normal");
             }
@@ -371,4 +423,27 @@ mod test {
 
         assert!(reader.next_record().unwrap().is_none());
     }
+
+    #[test]
+    fn test_parse_line() {
+        let line = "(1469439874.299654) can1 123#DEADbeef\r\n";
+        let rec = parse_line(line.as_bytes()).unwrap();
+        assert_eq!(rec.t_us, 1469439874299654);
+        assert_eq!(rec.device, "can1");
+        assert_eq!(rec.frame.data(), &[0xDE, 0xAD, 0xBE, 0xEF]);
+
+        let rec = parse_line(b"(0.000001) vcan0 7FF#R").unwrap();
+        assert!(rec.frame.data().is_empty());
+
+        for bad in [
+            &b"(0.000001) can0 123#ABC"[..],
+            b"(0.000001) can0 123#XY",
+            b"(0.000001) can0 #00",
+            b"(0.000001) can0 123##",
+            b"(x.000001) can0 123#00",
+            b"(0.000001) can0",
+        ] {
+            assert!(parse_line(bad).is_err());
+        }
+    }
 }
@@ -0,0 +1,426 @@
+// socketcan/src/e2e.rs
+//
+// AUTOSAR End-to-End (E2E) protection.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! AUTOSAR End-to-End (E2E) protection.
+//!
+//! Safety-relevant messages are protected end-to-end with a CRC and an
+//! alive counter in the payload, so that the receiver can detect corrupted,
+//! repeated, lost, or misrouted messages. The CRC also covers a Data ID
+//! that's never sent, but is known to the sender and receiver of each
+//! message.
+//!
+//! This implements the most common profiles for CAN:
+//!
+//! - [`Profile1`]: CRC-8 (SAE J1850) in byte 0, a 4-bit counter (0-14) in
+//!   the low nibble of byte 1, and a 16-bit Data ID.
+//! - [`Profile2`]: CRC-8H2F in byte 0, a 4-bit counter (0-15) in the low
+//!   nibble of byte 1, and a list of 16 Data IDs picked by the counter.
+//! - [`Profile5`]: CRC-16 (CCITT) in bytes 0-1, an 8-bit counter in byte 2,
+//!   and a 16-bit Data ID.
+//!
+//! Each has `protect()` for the sender, which writes the counter and CRC
+//! into a payload, and `check()` for the receiver, which verifies them.
+//!
+//! ```
+//! use socketcan::e2e::{E2eProfile, E2eStatus, Profile5};
+//!
+//! let mut tx = Profile5::new(0x1234);
+//! let mut rx = Profile5::new(0x1234);
+//!
+//! let mut data = [0u8, 0, 0, 0x11, 0x22, 0x33, 0x44, 0x55];
+//! tx.protect(&mut data).unwrap();
+//! assert_eq!(E2eStatus::Initial, rx.check(&data));
+//!
+//! tx.protect(&mut data).unwrap();
+//! assert_eq!(E2eStatus::Ok, rx.check(&data));
+//! ```
+
+use thiserror::Error;
+
+/// An error protecting a payload.
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum E2eError {
+    /// The payload is too short for the E2E header
+    #[error("Payload too short for the E2E header: {0} bytes")]
+    TooShort(usize),
+}
+
+/// The result of checking a received payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum E2eStatus {
+    /// The CRC is correct and the counter is the next one expected
+    Ok,
+    /// The CRC is correct, and this is the first payload checked, so the
+    /// counter couldn't be verified
+    Initial,
+    /// The CRC is correct, but the counter is the same as the last one
+    Repeated,
+    /// The CRC is correct, and the counter shows that some payloads were
+    /// lost, but no more than allowed
+    OkSomeLost(u8),
+    /// The CRC is correct, but the counter jumped by more than allowed
+    WrongSequence,
+    /// The CRC is wrong, or the payload is too short
+    Error,
+}
+
+impl E2eStatus {
+    /// Determines if the payload can be used.
+    pub fn is_valid(&self) -> bool {
+        matches!(self, Self::Ok | Self::Initial | Self::OkSomeLost(_))
+    }
+}
+
+/// An E2E protection profile.
+pub trait E2eProfile {
+    /// Writes the next counter value and the CRC into the payload.
+    fn protect(&mut self, data: &mut [u8]) -> Result<(), E2eError>;
+
+    /// Verifies the CRC and counter of a received payload.
+    fn check(&mut self, data: &[u8]) -> E2eStatus;
+}
+
+// ===== CRCs =====
+
+/// Computes a CRC-8 with the polynomial, MSB first, continuing from the
+/// value.
+fn crc8(poly: u8, init: u8, data: &[u8]) -> u8 {
+    data.iter().fold(init, |mut crc, b| {
+        crc ^= b;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ poly
+            } else {
+                crc << 1
+            };
+        }
+        crc
+    })
+}
+
+/// Computes the CRC-8 SAE J1850 (polynomial 0x1D), continuing from the
+/// value, without a final XOR.
+///
+/// This is also the CRC of the heartbeat
+/// [`Checksum::Crc8`](crate::heartbeat::Checksum::Crc8).
+pub fn crc8_sae_j1850(init: u8, data: &[u8]) -> u8 {
+    crc8(0x1D, init, data)
+}
+
+/// Computes the CRC-8H2F (polynomial 0x2F), continuing from the value,
+/// without a final XOR.
+pub fn crc8_h2f(init: u8, data: &[u8]) -> u8 {
+    crc8(0x2F, init, data)
+}
+
+/// Computes the CRC-16 CCITT (polynomial 0x1021), continuing from the
+/// value, without a final XOR.
+pub fn crc16_ccitt(init: u16, data: &[u8]) -> u16 {
+    data.iter().fold(init, |mut crc, b| {
+        crc ^= u16::from(*b) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+        crc
+    })
+}
+
+// ===== Counter =====
+
+/// The receiver's tracking of the alive counter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct CounterCheck {
+    modulus: u16,
+    max_delta: u8,
+    last: Option<u8>,
+}
+
+impl CounterCheck {
+    fn new(modulus: u16, max_delta: u8) -> Self {
+        Self {
+            modulus,
+            max_delta,
+            last: None,
+        }
+    }
+
+    /// Checks a received counter, after the CRC was verified.
+    fn check(&mut self, counter: u8) -> E2eStatus {
+        let last = match self.last.replace(counter) {
+            Some(last) => last,
+            None => return E2eStatus::Initial,
+        };
+        let delta = (u16::from(counter) + self.modulus - u16::from(last)) % self.modulus;
+        match delta {
+            0 => E2eStatus::Repeated,
+            1 => E2eStatus::Ok,
+            n if n <= u16::from(self.max_delta) => E2eStatus::OkSomeLost(n as u8 - 1),
+            _ => E2eStatus::WrongSequence,
+        }
+    }
+}
+
+// ===== Profile 1 =====
+
+/// E2E Profile 1, with both bytes of the Data ID in the CRC.
+///
+/// The CRC is in byte 0, and the counter in the low nibble of byte 1. The
+/// counter runs from 0 to 14.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Profile1 {
+    data_id: u16,
+    counter: u8,
+    rx: CounterCheck,
+}
+
+impl Profile1 {
+    /// Creates the profile for the Data ID.
+    pub fn new(data_id: u16) -> Self {
+        Self {
+            data_id,
+            counter: 0,
+            rx: CounterCheck::new(15, 1),
+        }
+    }
+
+    /// Sets the largest counter jump that the receiver accepts.
+    pub fn with_max_delta(mut self, max_delta: u8) -> Self {
+        self.rx.max_delta = max_delta;
+        self
+    }
+
+    /// Computes the CRC of a payload.
+    ///
+    /// Fails if the payload is too short for the E2E header.
+    pub fn crc(&self, data: &[u8]) -> Result<u8, E2eError> {
+        if data.len() < 2 {
+            return Err(E2eError::TooShort(data.len()));
+        }
+        let crc = crc8_sae_j1850(0x00, &self.data_id.to_le_bytes());
+        Ok(crc8_sae_j1850(crc, &data[1..]))
+    }
+}
+
+impl E2eProfile for Profile1 {
+    fn protect(&mut self, data: &mut [u8]) -> Result<(), E2eError> {
+        if data.len() < 2 {
+            return Err(E2eError::TooShort(data.len()));
+        }
+        data[1] = (data[1] & 0xF0) | self.counter;
+        data[0] = self.crc(data)?;
+        self.counter = (self.counter + 1) % 15;
+        Ok(())
+    }
+
+    fn check(&mut self, data: &[u8]) -> E2eStatus {
+        if data.len() < 2 || self.crc(data) != Ok(data[0]) || data[1] & 0x0F == 0x0F {
+            return E2eStatus::Error;
+        }
+        self.rx.check(data[1] & 0x0F)
+    }
+}
+
+// ===== Profile 2 =====
+
+/// E2E Profile 2.
+///
+/// The CRC is in byte 0, and the counter in the low nibble of byte 1. The
+/// Data ID in the CRC is picked from the list by the counter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Profile2 {
+    data_ids: [u8; 16],
+    counter: u8,
+    rx: CounterCheck,
+}
+
+impl Profile2 {
+    /// Creates the profile for the list of Data IDs.
+    pub fn new(data_ids: [u8; 16]) -> Self {
+        Self {
+            data_ids,
+            counter: 0,
+            rx: CounterCheck::new(16, 1),
+        }
+    }
+
+    /// Sets the largest counter jump that the receiver accepts.
+    pub fn with_max_delta(mut self, max_delta: u8) -> Self {
+        self.rx.max_delta = max_delta;
+        self
+    }
+
+    /// Computes the CRC of a payload, with the counter already in place.
+    ///
+    /// Fails if the payload is too short for the E2E header.
+    pub fn crc(&self, data: &[u8]) -> Result<u8, E2eError> {
+        if data.len() < 2 {
+            return Err(E2eError::TooShort(data.len()));
+        }
+        let data_id = self.data_ids[usize::from(data[1] & 0x0F)];
+        let crc = crc8_h2f(0xFF, &data[1..]);
+        Ok(crc8_h2f(crc, &[data_id]) ^ 0xFF)
+    }
+}
+
+impl E2eProfile for Profile2 {
+    fn protect(&mut self, data: &mut [u8]) -> Result<(), E2eError> {
+        if data.len() < 2 {
+            return Err(E2eError::TooShort(data.len()));
+        }
+        data[1] = (data[1] & 0xF0) | self.counter;
+        data[0] = self.crc(data)?;
+        self.counter = (self.counter + 1) & 0x0F;
+        Ok(())
+    }
+
+    fn check(&mut self, data: &[u8]) -> E2eStatus {
+        if data.len() < 2 || self.crc(data) != Ok(data[0]) {
+            return E2eStatus::Error;
+        }
+        self.rx.check(data[1] & 0x0F)
+    }
+}
+
+// ===== Profile 5 =====
+
+/// E2E Profile 5.
+///
+/// The CRC is in bytes 0-1, little endian, and the counter in byte 2.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Profile5 {
+    data_id: u16,
+    counter: u8,
+    rx: CounterCheck,
+}
+
+impl Profile5 {
+    /// Creates the profile for the Data ID.
+    pub fn new(data_id: u16) -> Self {
+        Self {
+            data_id,
+            counter: 0,
+            rx: CounterCheck::new(256, 1),
+        }
+    }
+
+    /// Sets the largest counter jump that the receiver accepts.
+    pub fn with_max_delta(mut self, max_delta: u8) -> Self {
+        self.rx.max_delta = max_delta;
+        self
+    }
+
+    /// Computes the CRC of a payload.
+    ///
+    /// Fails if the payload is too short for the E2E header.
+    pub fn crc(&self, data: &[u8]) -> Result<u16, E2eError> {
+        if data.len() < 3 {
+            return Err(E2eError::TooShort(data.len()));
+        }
+        let crc = crc16_ccitt(0xFFFF, &data[2..]);
+        Ok(crc16_ccitt(crc, &self.data_id.to_le_bytes()))
+    }
+}
+
+impl E2eProfile for Profile5 {
+    fn protect(&mut self, data: &mut [u8]) -> Result<(), E2eError> {
+        if data.len() < 3 {
+            return Err(E2eError::TooShort(data.len()));
+        }
+        data[2] = self.counter;
+        let crc = self.crc(data)?;
+        data[..2].copy_from_slice(&crc.to_le_bytes());
+        self.counter = self.counter.wrapping_add(1);
+        Ok(())
+    }
+
+    fn check(&mut self, data: &[u8]) -> E2eStatus {
+        match self.crc(data) {
+            Ok(crc) if data[..2] == crc.to_le_bytes() => (),
+            _ => return E2eStatus::Error,
+        }
+        self.rx.check(data[2])
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crcs() {
+        let data = b"123456789";
+        assert_eq!(0x4B, crc8_sae_j1850(0xFF, data) ^ 0xFF);
+        assert_eq!(0xDF, crc8_h2f(0xFF, data) ^ 0xFF);
+        assert_eq!(0x29B1, crc16_ccitt(0xFFFF, data));
+    }
+
+    #[test]
+    fn test_profiles() {
+        fn run<P: E2eProfile + Clone>(profile: P, len: usize) {
+            let mut tx = profile.clone();
+            let mut rx = profile;
+            let mut data = vec![0x5A; len];
+
+            tx.protect(&mut data).unwrap();
+            assert_eq!(E2eStatus::Initial, rx.check(&data));
+            assert_eq!(E2eStatus::Repeated, rx.check(&data));
+            tx.protect(&mut data).unwrap();
+            assert_eq!(E2eStatus::Ok, rx.check(&data));
+
+            tx.protect(&mut data).unwrap();
+            tx.protect(&mut data).unwrap();
+            assert_eq!(E2eStatus::WrongSequence, rx.check(&data));
+
+            tx.protect(&mut data).unwrap();
+            data[len - 1] ^= 0x01;
+            assert_eq!(E2eStatus::Error, rx.check(&data));
+        }
+
+        run(Profile1::new(0x0123), 8);
+        run(Profile2::new([0x11; 16]), 8);
+        run(Profile5::new(0x0123), 8);
+
+        // A different Data ID fails the CRC
+        let mut data = [0u8; 8];
+        Profile5::new(1).protect(&mut data).unwrap();
+        assert_eq!(E2eStatus::Error, Profile5::new(2).check(&data));
+
+        // Profile 1 counters wrap from 14 to 0
+        let mut tx = Profile1::new(7);
+        let mut rx = Profile1::new(7).with_max_delta(2);
+        for _ in 0..15 {
+            tx.protect(&mut data).unwrap();
+            rx.check(&data);
+        }
+        assert_eq!(14, data[1] & 0x0F);
+        tx.protect(&mut data).unwrap();
+        assert_eq!(0, data[1] & 0x0F);
+        assert_eq!(E2eStatus::Ok, rx.check(&data));
+        tx.protect(&mut data).unwrap();
+        tx.protect(&mut data).unwrap();
+        assert_eq!(E2eStatus::OkSomeLost(1), rx.check(&data));
+    }
+
+    #[test]
+    fn test_short_payload() {
+        assert_eq!(Err(E2eError::TooShort(1)), Profile1::new(1).crc(&[0]));
+        assert_eq!(Err(E2eError::TooShort(0)), Profile2::new([0; 16]).crc(&[]));
+        assert_eq!(Err(E2eError::TooShort(2)), Profile5::new(1).crc(&[0, 0]));
+        assert_eq!(E2eStatus::Error, Profile2::new([0; 16]).check(&[0]));
+        assert!(Profile1::new(1).crc(&[0, 0]).is_ok());
+    }
+}
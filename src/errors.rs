@@ -38,7 +38,9 @@
 //! [linux/can/error.h](https://raw.githubusercontent.com/torvalds/linux/master/include/uapi/linux/can/error.h)
 //!
 
-use crate::{CanErrorFrame, EmbeddedFrame, Frame};
+use crate::{CanErrorFrame, EmbeddedFrame, Frame, ShouldRetry};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 use std::{convert::TryFrom, error, fmt, io};
 use thiserror::Error;
 
@@ -48,7 +50,16 @@ use thiserror::Error;
 ///
 /// This can be any of the underlying errors from this library. The two main
 /// error sources are either CAN errors coming in through received error
-/// frames or from typical system I/O errors.
+/// frames or from typical system I/O errors. Problems decoding an error
+/// frame, or constructing a frame, convert into these, so that any
+/// fallible operation in the library can be propagated with `?` into this
+/// one type: a decoding failure is a [`CanError::DecodingFailure`], and a
+/// construction error is an I/O error of kind `InvalidInput`.
+///
+/// This implements [`std::error::Error`], with the underlying error
+/// available as its `source()`, so it works with the usual error handling
+/// crates like _anyhow_ and _thiserror_. It can also be converted back into
+/// an [`io::Error`], keeping the OS error code for I/O errors.
 #[derive(Error, Debug)]
 pub enum Error {
     /// A CANbus error, usually from an error frmae
@@ -59,6 +70,50 @@ pub enum Error {
     Io(#[from] io::Error),
 }
 
+impl Error {
+    /// Determines if this is an I/O error for an operation that would have
+    /// blocked.
+    ///
+    /// This is the error returned by a non-blocking socket that isn't
+    /// ready, or by a blocking socket when the timeout set with
+    /// `set_read_timeout()` or `set_write_timeout()` expires. The operation
+    /// can be retried.
+    pub fn would_block(&self) -> bool {
+        match self {
+            Error::Io(err) => err.should_retry(),
+            _ => false,
+        }
+    }
+
+    /// Determines if this is a timeout error.
+    ///
+    /// This is true for an I/O timeout, such as from `read_frame_timeout()`,
+    /// or a CAN error frame reporting a transmit timeout by the driver.
+    pub fn is_timeout(&self) -> bool {
+        match self {
+            Error::Io(err) => err.kind() == io::ErrorKind::TimedOut,
+            Error::Can(CanError::TransmitTimeout) => true,
+            _ => false,
+        }
+    }
+
+    /// Gets the raw OS error code (errno), if this is an I/O error that
+    /// came from the OS.
+    pub fn raw_os_error(&self) -> Option<i32> {
+        match self {
+            Error::Io(err) => err.raw_os_error(),
+            _ => None,
+        }
+    }
+}
+
+impl ShouldRetry for Error {
+    /// Determines if the failed operation can be retried.
+    fn should_retry(&self) -> bool {
+        self.would_block()
+    }
+}
+
 impl embedded_can::Error for Error {
     fn kind(&self) -> embedded_can::ErrorKind {
         match *self {
@@ -74,6 +129,34 @@ impl From<CanErrorFrame> for Error {
     }
 }
 
+impl From<CanErrorDecodingFailure> for Error {
+    /// Reports a failure decoding an error frame as a CAN error.
+    fn from(err: CanErrorDecodingFailure) -> Self {
+        Error::Can(CanError::DecodingFailure(err))
+    }
+}
+
+impl From<ConstructionError> for Error {
+    /// Reports a failure constructing a frame as an `InvalidInput` I/O
+    /// error, with the construction error as its source.
+    fn from(err: ConstructionError) -> Self {
+        Error::Io(io::Error::new(io::ErrorKind::InvalidInput, err))
+    }
+}
+
+impl From<Error> for io::Error {
+    /// Converts the error into an I/O error.
+    ///
+    /// I/O errors are returned as-is, preserving any OS error code. The
+    /// other errors are wrapped as the source of a new I/O error.
+    fn from(err: Error) -> Self {
+        match err {
+            Error::Io(err) => err,
+            Error::Can(err) => io::Error::new(io::ErrorKind::Other, err),
+        }
+    }
+}
+
 impl From<io::ErrorKind> for Error {
     /// Creates an Io error straight from an io::ErrorKind
     fn from(ek: io::ErrorKind) -> Self {
@@ -122,6 +205,7 @@ pub type IoResult<T> = io::Result<T>;
 /// (`CAN_ERR_FLAG`) is set. But there are additional types to handle any
 /// problems decoding the error frame.
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum CanError {
     /// TX timeout (by netdevice driver)
     TransmitTimeout,
@@ -138,6 +222,9 @@ pub enum CanError {
         location: Location,
     },
     /// Transceiver Error.
+    ///
+    /// The transceiver status is available from the error frame with
+    /// [`CanErrorFrame::transceiver_errors`].
     TransceiverError,
     /// No ACK received for current CAN frame.
     NoAck,
@@ -153,7 +240,16 @@ pub enum CanError {
     Unknown(u32),
 }
 
-impl error::Error for CanError {}
+impl error::Error for CanError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            CanError::ControllerProblem(err) => Some(err),
+            CanError::ProtocolViolation { vtype, .. } => Some(vtype),
+            CanError::DecodingFailure(err) => Some(err),
+            _ => None,
+        }
+    }
+}
 
 impl fmt::Display for CanError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -188,49 +284,167 @@ impl embedded_can::Error for CanError {
                     _ => embedded_can::ErrorKind::Other,
                 }
             }
+            CanError::ProtocolViolation { vtype, location } => {
+                use ViolationType::*;
+                match vtype {
+                    SingleBitError | UnableToSendDominantBit | UnableToSendRecessiveBit => {
+                        embedded_can::ErrorKind::Bit
+                    }
+                    BitStuffingError => embedded_can::ErrorKind::Stuff,
+                    FrameFormatError => embedded_can::ErrorKind::Form,
+                    _ if location == Location::CrcSequence => embedded_can::ErrorKind::Crc,
+                    _ => embedded_can::ErrorKind::Other,
+                }
+            }
             CanError::NoAck => embedded_can::ErrorKind::Acknowledge,
             _ => embedded_can::ErrorKind::Other,
         }
     }
 }
 
-impl From<CanErrorFrame> for CanError {
-    /// Constructs a CAN error from an error frame.
-    fn from(frame: CanErrorFrame) -> Self {
+impl CanError {
+    /// Decodes all of the errors reported in an error frame.
+    ///
+    /// The kernel may set several error bits in the ID word of a single
+    /// error frame, such as a protocol violation along with a bus error,
+    /// and the data bytes may report several controller problems, such as
+    /// both RX and TX warnings. This decodes every one of them into a
+    /// separate `CanError`, with the most significant first, as:
+    ///
+    /// ```text
+    /// Bus Off, Controller Problem, Protocol Violation, Transceiver Error,
+    /// No ACK, Lost Arbitration, TX Timeout, Restarted, Bus Error
+    /// ```
+    ///
+    /// Any error bits that are not known are reported as
+    /// [`CanError::Unknown`], and any data bytes that can't be decoded are
+    /// reported as [`CanError::DecodingFailure`].
+    pub fn decode_all(frame: &CanErrorFrame) -> Vec<CanError> {
         // Note that the CanErrorFrame is guaranteed to have the full 8-byte
         // data payload.
-        match frame.error_bits() {
-            0x0001 => CanError::TransmitTimeout,
-            0x0002 => CanError::LostArbitration(frame.data()[0]),
-            0x0004 => match ControllerProblem::try_from(frame.data()[1]) {
-                Ok(err) => CanError::ControllerProblem(err),
-                Err(err) => CanError::DecodingFailure(err),
-            },
-            0x0008 => {
-                match (
-                    ViolationType::try_from(frame.data()[2]),
-                    Location::try_from(frame.data()[3]),
+        let bits = frame.error_bits();
+        let data = frame.data();
+        let mut errs = Vec::new();
+
+        for bit in ERROR_BIT_PRIORITY {
+            if bits & bit == 0 {
+                continue;
+            }
+            match bit {
+                CAN_ERR_BUSOFF => errs.push(CanError::BusOff),
+                CAN_ERR_CRTL => match ControllerProblem::from_bits(data[1]) {
+                    Ok(probs) => errs.extend(probs.into_iter().map(CanError::ControllerProblem)),
+                    Err(err) => errs.push(CanError::DecodingFailure(err)),
+                },
+                CAN_ERR_PROT => match (
+                    ViolationType::from_bits(data[2]),
+                    Location::try_from(data[3]),
                 ) {
-                    (Ok(vtype), Ok(location)) => CanError::ProtocolViolation { vtype, location },
-                    (Err(err), _) | (_, Err(err)) => CanError::DecodingFailure(err),
-                }
+                    (Ok(vtypes), Ok(location)) => errs.extend(
+                        vtypes
+                            .into_iter()
+                            .map(|vtype| CanError::ProtocolViolation { vtype, location }),
+                    ),
+                    (Err(err), _) | (_, Err(err)) => errs.push(CanError::DecodingFailure(err)),
+                },
+                CAN_ERR_TRX => errs.push(CanError::TransceiverError),
+                CAN_ERR_ACK => errs.push(CanError::NoAck),
+                CAN_ERR_LOSTARB => errs.push(CanError::LostArbitration(data[0])),
+                CAN_ERR_TX_TIMEOUT => errs.push(CanError::TransmitTimeout),
+                CAN_ERR_RESTARTED => errs.push(CanError::Restarted),
+                _ => errs.push(CanError::BusError),
             }
-            0x0010 => CanError::TransceiverError,
-            0x0020 => CanError::NoAck,
-            0x0040 => CanError::BusOff,
-            0x0080 => CanError::BusError,
-            0x0100 => CanError::Restarted,
-            err => CanError::Unknown(err),
         }
+
+        // The error counter bit just indicates that data[6..8] are valid.
+        let known = ERROR_BIT_PRIORITY
+            .iter()
+            .fold(CAN_ERR_CNT, |acc, b| acc | b);
+        if bits & !known != 0 || errs.is_empty() {
+            errs.push(CanError::Unknown(bits));
+        }
+        errs
+    }
+}
+
+impl From<CanErrorFrame> for CanError {
+    /// Constructs a CAN error from an error frame.
+    ///
+    /// If the frame reports more than one error, this returns the most
+    /// significant one. Use [`CanError::decode_all`] to get all of them.
+    fn from(frame: CanErrorFrame) -> Self {
+        // There is always at least one error, even if it's Unknown
+        CanError::decode_all(&frame).swap_remove(0)
     }
 }
 
+// ===== Error bits =====
+
+/// TX timeout (by netdevice driver)
+pub(crate) const CAN_ERR_TX_TIMEOUT: u32 = 0x0001;
+/// Lost arbitration, in data[0]
+pub(crate) const CAN_ERR_LOSTARB: u32 = 0x0002;
+/// Controller problems, in data[1]
+pub(crate) const CAN_ERR_CRTL: u32 = 0x0004;
+/// Protocol violations, in data[2..3]
+pub(crate) const CAN_ERR_PROT: u32 = 0x0008;
+/// Transceiver status, in data[4]
+pub(crate) const CAN_ERR_TRX: u32 = 0x0010;
+/// Received no ACK on transmission
+pub(crate) const CAN_ERR_ACK: u32 = 0x0020;
+/// Bus off
+pub(crate) const CAN_ERR_BUSOFF: u32 = 0x0040;
+/// Bus error (may flood!)
+pub(crate) const CAN_ERR_BUSERROR: u32 = 0x0080;
+/// Controller restarted
+pub(crate) const CAN_ERR_RESTARTED: u32 = 0x0100;
+/// TX and RX error counters, in data[6..7]
+pub(crate) const CAN_ERR_CNT: u32 = 0x0200;
+
+/// The error bits, in order of significance
+const ERROR_BIT_PRIORITY: [u32; 9] = [
+    CAN_ERR_BUSOFF,
+    CAN_ERR_CRTL,
+    CAN_ERR_PROT,
+    CAN_ERR_TRX,
+    CAN_ERR_ACK,
+    CAN_ERR_LOSTARB,
+    CAN_ERR_TX_TIMEOUT,
+    CAN_ERR_RESTARTED,
+    CAN_ERR_BUSERROR,
+];
+
+/// Decodes a bitmask of flags into a list of values, in the order given.
+///
+/// A zero mask decodes as the `unspecified` value. Any bits not in the
+/// list cause the `err` failure.
+fn decode_flags<T: Copy>(
+    val: u8,
+    flags: &[(u8, T)],
+    unspecified: T,
+    err: CanErrorDecodingFailure,
+) -> std::result::Result<Vec<T>, CanErrorDecodingFailure> {
+    if val == 0 {
+        return Ok(vec![unspecified]);
+    }
+    let known = flags.iter().fold(0, |acc, (bit, _)| acc | bit);
+    if val & !known != 0 {
+        return Err(err);
+    }
+    Ok(flags
+        .iter()
+        .filter(|(bit, _)| val & bit != 0)
+        .map(|(_, v)| *v)
+        .collect())
+}
+
 // ===== ControllerProblem =====
 
 /// Error status of the CAN conroller.
 ///
 /// This is derived from `data[1]` of an error frame
 #[derive(Debug, Clone, Copy, PartialOrd, Ord, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[repr(u8)]
 pub enum ControllerProblem {
     /// unspecified
@@ -251,6 +465,28 @@ pub enum ControllerProblem {
     Active = 0x40,
 }
 
+impl ControllerProblem {
+    /// Decodes all of the problems in the bitmask from `data[1]` of an
+    /// error frame, with the most severe first.
+    pub fn from_bits(val: u8) -> std::result::Result<Vec<Self>, CanErrorDecodingFailure> {
+        use ControllerProblem::*;
+        decode_flags(
+            val,
+            &[
+                (0x20, TransmitErrorPassive),
+                (0x10, ReceiveErrorPassive),
+                (0x08, TransmitErrorWarning),
+                (0x04, ReceiveErrorWarning),
+                (0x02, TransmitBufferOverflow),
+                (0x01, ReceiveBufferOverflow),
+                (0x40, Active),
+            ],
+            Unspecified,
+            CanErrorDecodingFailure::InvalidControllerProblem,
+        )
+    }
+}
+
 impl error::Error for ControllerProblem {}
 
 impl fmt::Display for ControllerProblem {
@@ -294,7 +530,8 @@ impl TryFrom<u8> for ControllerProblem {
 /// The type of protocol violation error.
 ///
 /// This is derived from `data[2]` of an error frame.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[repr(u8)]
 pub enum ViolationType {
     /// Unspecified Violation
@@ -317,6 +554,33 @@ pub enum ViolationType {
     TransmissionError = 0x80,
 }
 
+impl ViolationType {
+    /// Decodes all of the violations in the bitmask from `data[2]` of an
+    /// error frame.
+    ///
+    /// The [`ViolationType::TransmissionError`] bit flags that the
+    /// violation occurred while transmitting. It is reported last, after
+    /// the type(s) of violation.
+    pub fn from_bits(val: u8) -> std::result::Result<Vec<Self>, CanErrorDecodingFailure> {
+        use ViolationType::*;
+        decode_flags(
+            val,
+            &[
+                (0x01, SingleBitError),
+                (0x02, FrameFormatError),
+                (0x04, BitStuffingError),
+                (0x08, UnableToSendDominantBit),
+                (0x10, UnableToSendRecessiveBit),
+                (0x20, BusOverload),
+                (0x40, Active),
+                (0x80, TransmissionError),
+            ],
+            Unspecified,
+            CanErrorDecodingFailure::InvalidViolationType,
+        )
+    }
+}
+
 impl error::Error for ViolationType {}
 
 impl fmt::Display for ViolationType {
@@ -364,6 +628,7 @@ impl TryFrom<u8> for ViolationType {
 ///
 /// This is derived from `data[3]` of an error frame.
 #[derive(Debug, Copy, Clone, PartialOrd, Ord, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[repr(u8)]
 pub enum Location {
     /// Unspecified
@@ -473,6 +738,7 @@ impl TryFrom<u8> for Location {
 ///
 /// This is derived from `data[4]` of an error frame.
 #[derive(Debug, Clone, Copy, PartialOrd, Ord, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[repr(u8)]
 pub enum TransceiverError {
     /// Unsecified
@@ -497,6 +763,45 @@ pub enum TransceiverError {
     CanLowShortToCanHigh = 0x80,
 }
 
+impl TransceiverError {
+    /// Decodes the status of both wires from `data[4]` of an error frame.
+    ///
+    /// The low nibble has the status of the CAN High wire, and the high
+    /// nibble the status of CAN Low, so both can report a problem at the
+    /// same time. The CAN High error is reported first.
+    pub fn from_bits(val: u8) -> std::result::Result<Vec<Self>, CanErrorDecodingFailure> {
+        if val == 0 {
+            return Ok(vec![TransceiverError::Unspecified]);
+        }
+        [val & 0x0F, val & 0xF0]
+            .into_iter()
+            .filter(|v| *v != 0)
+            .map(TransceiverError::try_from)
+            .collect()
+    }
+}
+
+impl error::Error for TransceiverError {}
+
+impl fmt::Display for TransceiverError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use TransceiverError::*;
+        let msg = match *self {
+            Unspecified => "unspecified",
+            CanHighNoWire => "CAN High, no wire",
+            CanHighShortToBat => "CAN High, short to BAT",
+            CanHighShortToVcc => "CAN High, short to VCC",
+            CanHighShortToGnd => "CAN High, short to GND",
+            CanLowNoWire => "CAN Low, no wire",
+            CanLowShortToBat => "CAN Low, short to BAT",
+            CanLowShortToVcc => "CAN Low, short to VCC",
+            CanLowShortToGnd => "CAN Low, short to GND",
+            CanLowShortToCanHigh => "CAN Low, short to CAN High",
+        };
+        write!(f, "{}", msg)
+    }
+}
+
 impl TryFrom<u8> for TransceiverError {
     type Error = CanErrorDecodingFailure;
 
@@ -541,6 +846,7 @@ impl<T: Frame> ControllerSpecificErrorInformation for T {
 
 /// Error decoding a CanError from a CanErrorFrame.
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum CanErrorDecodingFailure {
     /// The supplied CANFrame did not have the error bit set.
     NotAnError,
@@ -582,14 +888,46 @@ impl fmt::Display for CanErrorDecodingFailure {
 // ===== ConstructionError =====
 
 #[derive(Debug, Copy, Clone, PartialOrd, Ord, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 /// Error that occurs when creating CAN packets
 pub enum ConstructionError {
     /// Trying to create a specific frame type from an incompatible type
     WrongFrameType,
+    /// The frame type does not support remote transmission requests
+    RemoteNotSupported,
     /// CAN ID was outside the range of valid IDs
-    IDTooLarge,
+    IDTooLarge {
+        /// The offending ID
+        id: u32,
+        /// Whether an extended ID was requested
+        extended: bool,
+    },
     /// Larger payload reported than can be held in the frame.
-    TooMuchData,
+    TooMuchData {
+        /// The length of the payload, or the DLC, that was given
+        len: usize,
+        /// The maximum length allowed for the frame type
+        max: usize,
+    },
+    /// Smaller payload than the frame type requires.
+    TooLittleData {
+        /// The length of the payload that was given
+        len: usize,
+        /// The minimum length required for the frame type
+        min: usize,
+    },
+    /// A raw buffer doesn't have the size or layout of the frame type.
+    InvalidLayout {
+        /// The size of the buffer that was given
+        size: usize,
+        /// The size of the frame structure
+        expected: usize,
+    },
+    /// The payload length, or DLC, isn't valid for the frame type.
+    InvalidLength {
+        /// The length of the payload, or the DLC, that was given
+        len: usize,
+    },
 }
 
 impl error::Error for ConstructionError {}
@@ -597,12 +935,62 @@ impl error::Error for ConstructionError {}
 impl fmt::Display for ConstructionError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         use ConstructionError::*;
-        let msg = match *self {
-            WrongFrameType => "Incompatible frame type",
-            IDTooLarge => "CAN ID too large",
-            TooMuchData => "Payload is too large",
-        };
-        write!(f, "{}", msg)
+        match *self {
+            WrongFrameType => write!(f, "Incompatible frame type"),
+            RemoteNotSupported => write!(f, "Remote frames not supported by the frame type"),
+            IDTooLarge { id, extended } => write!(
+                f,
+                "CAN ID 0x{:X} too large for {} ID",
+                id,
+                if extended {
+                    "an extended"
+                } else {
+                    "a standard"
+                }
+            ),
+            TooMuchData { len, max } => write!(
+                f,
+                "Payload is too large: {} bytes, with a max of {}",
+                len, max
+            ),
+            TooLittleData { len, min } => write!(
+                f,
+                "Payload is too small: {} bytes, with a min of {}",
+                len, min
+            ),
+            InvalidLayout { size, expected } => write!(
+                f,
+                "Invalid frame layout: {} bytes, expected {}",
+                size, expected
+            ),
+            InvalidLength { len } => write!(f, "Invalid length for the frame type: {}", len),
+        }
+    }
+}
+
+// ===== defmt =====
+
+// The errors are formatted with their text descriptions, the same as
+// Display, so that they read the same in firmware and Linux logs.
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for CanError {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "{}", defmt::Display2Format(self))
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for CanErrorDecodingFailure {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "{}", defmt::Display2Format(self))
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for ConstructionError {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "{}", defmt::Display2Format(self))
     }
 }
 
@@ -610,6 +998,7 @@ impl fmt::Display for ConstructionError {
 
 #[cfg(test)]
 mod tests {
+    use super::*;
     use crate::Error;
     use std::io;
 
@@ -632,5 +1021,119 @@ mod tests {
         } else {
             panic!("Wrong error conversion");
         }
+
+        // Back to an io::Error, keeping the OS error
+        let err = Error::from(io::Error::from_raw_os_error(libc::ENETDOWN));
+        assert_eq!(Some(libc::ENETDOWN), err.raw_os_error());
+        let ioerr = io::Error::from(err);
+        assert_eq!(Some(libc::ENETDOWN), ioerr.raw_os_error());
+    }
+
+    #[test]
+    fn test_retry_helpers() {
+        let err = Error::from(io::Error::from_raw_os_error(libc::EAGAIN));
+        assert!(err.would_block());
+        assert!(err.should_retry());
+        assert!(!err.is_timeout());
+
+        let err = Error::from(io::Error::from_raw_os_error(libc::EINPROGRESS));
+        assert!(err.would_block());
+
+        let err = Error::from(io::ErrorKind::TimedOut);
+        assert!(err.is_timeout());
+        assert!(!err.would_block());
+
+        let err = Error::from(CanError::TransmitTimeout);
+        assert!(err.is_timeout());
+        assert_eq!(None, err.raw_os_error());
+    }
+
+    #[test]
+    fn test_error_sources() {
+        use std::error::Error as _;
+
+        let err = Error::from(CanError::DecodingFailure(
+            CanErrorDecodingFailure::InvalidLocation,
+        ));
+        assert_eq!("decoding failure: not a valid location", err.to_string());
+        assert_eq!("not a valid location", err.source().unwrap().to_string());
+
+        let err = Error::from(ConstructionError::TooMuchData { len: 9, max: 8 });
+        assert!(matches!(err, Error::Io(_)));
+        let ioerr = io::Error::from(err);
+        assert_eq!(io::ErrorKind::InvalidInput, ioerr.kind());
+        assert!(ioerr.get_ref().unwrap().is::<ConstructionError>());
+        assert_eq!(
+            "Payload is too large: 9 bytes, with a max of 8",
+            ioerr.to_string()
+        );
+    }
+
+    #[test]
+    fn test_decode_all() {
+        // A protocol violation reported as a bus error, with error counters
+        let frame = CanErrorFrame::new_error(0x0288, &[0, 0, 0x81, 0x08, 0, 0, 12, 3]).unwrap();
+        let errs = CanError::decode_all(&frame);
+        assert_eq!(3, errs.len());
+        assert!(matches!(
+            errs[0],
+            CanError::ProtocolViolation {
+                vtype: ViolationType::SingleBitError,
+                location: Location::CrcSequence
+            }
+        ));
+        assert!(matches!(
+            errs[1],
+            CanError::ProtocolViolation {
+                vtype: ViolationType::TransmissionError,
+                ..
+            }
+        ));
+        assert!(matches!(errs[2], CanError::BusError));
+        assert!(matches!(
+            CanError::from(frame),
+            CanError::ProtocolViolation { .. }
+        ));
+        assert_eq!(
+            embedded_can::ErrorKind::Bit,
+            embedded_can::Error::kind(&errs[0])
+        );
+
+        // RX and TX warnings at the same time
+        let frame = CanErrorFrame::new_error(0x0004, &[0, 0x0C]).unwrap();
+        let errs = CanError::decode_all(&frame);
+        assert!(matches!(
+            errs[..],
+            [
+                CanError::ControllerProblem(ControllerProblem::TransmitErrorWarning),
+                CanError::ControllerProblem(ControllerProblem::ReceiveErrorWarning)
+            ]
+        ));
+
+        // Bus off takes priority over a transceiver error
+        let frame = CanErrorFrame::new_error(0x0050, &[0, 0, 0, 0, 0x44]).unwrap();
+        let errs = CanError::decode_all(&frame);
+        assert!(matches!(
+            errs[..],
+            [CanError::BusOff, CanError::TransceiverError]
+        ));
+        assert_eq!(
+            vec![
+                TransceiverError::CanHighNoWire,
+                TransceiverError::CanLowNoWire
+            ],
+            frame.transceiver_errors().unwrap().unwrap()
+        );
+
+        // Bad data and unknown bits
+        let frame = CanErrorFrame::new_error(0x0404, &[0, 0x80]).unwrap();
+        let errs = CanError::decode_all(&frame);
+        assert!(matches!(
+            errs[..],
+            [
+                CanError::DecodingFailure(CanErrorDecodingFailure::InvalidControllerProblem),
+                CanError::Unknown(0x0404)
+            ]
+        ));
     }
 }
@@ -0,0 +1,474 @@
+// socketcan/src/fault.rs
+//
+// A backend wrapper that injects faults, for robustness testing.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! A backend wrapper that injects faults, for robustness testing.
+//!
+//! A [`FaultInjector`] wraps any backend that implements the
+//! [`CanTransmitter`] and/or [`CanReceiver`] traits, and randomly
+//! mistreats the frames passing through it. Each frame can be:
+//!
+//! - Dropped, as if it never got on the bus
+//! - Duplicated, as when a sender retransmits a frame it thinks was lost
+//! - Delayed, by a random time up to a limit
+//! - Reordered, by holding it back until after the next frame
+//! - Corrupted, with a single bit of its payload flipped
+//!
+//! Each fault has its own probability, and a filter can limit the faults
+//! to some of the frames, such as those with a particular ID, so that a
+//! protocol layer can be tested against a lossy or noisy bus. Seeding
+//! the injector makes the faults repeatable.
+//!
+//! ```
+//! use socketcan::{
+//!     backend::{CanReceiver, CanTransmitter},
+//!     fault::{Direction, FaultInjector},
+//!     mock::MockCanSocket,
+//!     CanFrame, Frame,
+//! };
+//!
+//! let sock = MockCanSocket::<CanFrame>::new();
+//! let faulty = FaultInjector::new(sock.clone())
+//!     .with_seed(42)
+//!     .with_direction(Direction::Tx)
+//!     .with_drop(0.25)
+//!     .with_duplicate(0.1);
+//!
+//! for i in 0..100 {
+//!     faulty.transmit(&CanFrame::from_raw_id(0x100, &[i]).unwrap()).unwrap();
+//! }
+//! let stats = faulty.stats();
+//! assert_eq!(100 - stats.dropped + stats.duplicated, sock.sent().len() as u64);
+//! ```
+
+use crate::{
+    backend::{CanReceiver, CanTransmitter},
+    generator::Rng,
+    Frame, IoResult,
+};
+use std::{collections::VecDeque, fmt, sync::Mutex, thread, time::Duration};
+
+/// A filter that picks the frames that can be faulted.
+pub type FaultFilter<F> = Box<dyn Fn(&F) -> bool + Send + Sync>;
+
+/// The direction(s) of traffic that are faulted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// Only frames that are transmitted
+    Tx,
+    /// Only frames that are received
+    Rx,
+    /// Frames in both directions
+    Both,
+}
+
+impl Direction {
+    fn has_tx(&self) -> bool {
+        matches!(self, Self::Tx | Self::Both)
+    }
+
+    fn has_rx(&self) -> bool {
+        matches!(self, Self::Rx | Self::Both)
+    }
+}
+
+/// The number of faults injected so far.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FaultStats {
+    /// The number of frames that were dropped
+    pub dropped: u64,
+    /// The number of frames that were duplicated
+    pub duplicated: u64,
+    /// The number of frames that were delayed
+    pub delayed: u64,
+    /// The number of frames that were held back behind the next one
+    pub reordered: u64,
+    /// The number of frames that were corrupted
+    pub corrupted: u64,
+}
+
+/// The probabilities of each of the faults.
+#[derive(Debug, Default, Clone, Copy)]
+struct Probabilities {
+    drop: f64,
+    duplicate: f64,
+    delay: f64,
+    max_delay: Duration,
+    reorder: f64,
+    corrupt: f64,
+}
+
+/// What to do with a frame.
+#[derive(Debug, Default)]
+struct Plan {
+    drop: bool,
+    duplicate: bool,
+    delay: Duration,
+    reorder: bool,
+}
+
+/// The mutable state of the injector.
+#[derive(Debug)]
+struct State<F> {
+    rng: Rng,
+    stats: FaultStats,
+    tx_held: Option<F>,
+    rx_held: Option<F>,
+    rx_pending: VecDeque<F>,
+}
+
+// ===== FaultInjector =====
+
+/// A wrapper around a backend that drops, duplicates, delays, reorders,
+/// or corrupts the frames passing through it.
+///
+/// A delay blocks the caller for that time before the frame is passed
+/// on. A frame that's held back for reordering goes out after the next
+/// one; call [`flush`](Self::flush) to send one that's still held when
+/// the traffic stops.
+pub struct FaultInjector<B, F> {
+    inner: B,
+    dir: Direction,
+    prob: Probabilities,
+    filter: Option<FaultFilter<F>>,
+    state: Mutex<State<F>>,
+}
+
+impl<B, F> FaultInjector<B, F> {
+    /// Wraps the backend, initially without any faults, and seeded from
+    /// the system time.
+    pub fn new(inner: B) -> Self {
+        Self {
+            inner,
+            dir: Direction::Both,
+            prob: Probabilities::default(),
+            filter: None,
+            state: Mutex::new(State {
+                rng: Rng::from_time(),
+                stats: FaultStats::default(),
+                tx_held: None,
+                rx_held: None,
+                rx_pending: VecDeque::new(),
+            }),
+        }
+    }
+
+    /// Seeds the random number generator, to make the faults repeatable.
+    pub fn with_seed(self, seed: u64) -> Self {
+        self.state.lock().unwrap().rng = Rng::new(seed);
+        self
+    }
+
+    /// Sets the direction(s) of traffic to fault.
+    pub fn with_direction(mut self, dir: Direction) -> Self {
+        self.dir = dir;
+        self
+    }
+
+    /// Sets the probability that a frame is dropped.
+    pub fn with_drop(mut self, p: f64) -> Self {
+        self.prob.drop = p;
+        self
+    }
+
+    /// Sets the probability that a frame is passed on twice.
+    pub fn with_duplicate(mut self, p: f64) -> Self {
+        self.prob.duplicate = p;
+        self
+    }
+
+    /// Sets the probability that a frame is delayed, and the longest
+    /// delay. The delay is chosen at random, up to the limit.
+    pub fn with_delay(mut self, p: f64, max_delay: Duration) -> Self {
+        self.prob.delay = p;
+        self.prob.max_delay = max_delay;
+        self
+    }
+
+    /// Sets the probability that a frame is held back, and passed on
+    /// after the next one.
+    pub fn with_reorder(mut self, p: f64) -> Self {
+        self.prob.reorder = p;
+        self
+    }
+
+    /// Sets the probability that a bit in the payload of a frame is
+    /// flipped. Frames without a payload aren't corrupted.
+    pub fn with_corrupt(mut self, p: f64) -> Self {
+        self.prob.corrupt = p;
+        self
+    }
+
+    /// Limits the faults to the frames that pass the filter. The others
+    /// go through untouched.
+    pub fn with_filter<P>(mut self, filter: P) -> Self
+    where
+        P: Fn(&F) -> bool + Send + Sync + 'static,
+    {
+        self.filter = Some(Box::new(filter));
+        self
+    }
+
+    /// Gets the wrapped backend.
+    pub fn get_ref(&self) -> &B {
+        &self.inner
+    }
+
+    /// Unwraps the backend, discarding any held frames.
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+
+    /// Gets the number of faults injected so far.
+    pub fn stats(&self) -> FaultStats {
+        self.state.lock().unwrap().stats
+    }
+}
+
+impl<B, F: Frame + Clone> FaultInjector<B, F> {
+    /// Decides the fate of a frame, corrupting it in place if chosen.
+    fn plan(&self, frame: &mut F) -> Plan {
+        if !self.filter.as_ref().map_or(true, |filter| filter(frame)) {
+            return Plan::default();
+        }
+
+        let mut state = self.state.lock().unwrap();
+        let State { rng, stats, .. } = &mut *state;
+
+        if rng.chance(self.prob.drop) {
+            stats.dropped += 1;
+            return Plan {
+                drop: true,
+                ..Plan::default()
+            };
+        }
+
+        if rng.chance(self.prob.corrupt) && !frame.data().is_empty() {
+            let mut data = frame.data().to_vec();
+            let bit = rng.in_range(0, data.len() as u64 * 8 - 1) as usize;
+            data[bit / 8] ^= 1 << (bit % 8);
+            if frame.set_data(&data).is_ok() {
+                stats.corrupted += 1;
+            }
+        }
+
+        let mut plan = Plan::default();
+        if rng.chance(self.prob.duplicate) {
+            stats.duplicated += 1;
+            plan.duplicate = true;
+        }
+        if rng.chance(self.prob.delay) {
+            let max = self.prob.max_delay.as_micros() as u64;
+            plan.delay = Duration::from_micros(rng.in_range(0, max));
+            stats.delayed += 1;
+        }
+        if rng.chance(self.prob.reorder) {
+            stats.reordered += 1;
+            plan.reorder = true;
+        }
+        plan
+    }
+}
+
+impl<B, F> FaultInjector<B, F>
+where
+    B: CanTransmitter<F>,
+    F: Frame + Clone,
+{
+    /// Sends a frame that's being held back for reordering, if any.
+    pub fn flush(&self) -> IoResult<()> {
+        let held = self.state.lock().unwrap().tx_held.take();
+        match held {
+            Some(frame) => self.inner.transmit(&frame),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<B, F> fmt::Debug for FaultInjector<B, F>
+where
+    B: fmt::Debug,
+    F: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FaultInjector")
+            .field("inner", &self.inner)
+            .field("dir", &self.dir)
+            .field("prob", &self.prob)
+            .field("state", &self.state)
+            .finish()
+    }
+}
+
+impl<B, F> CanTransmitter<F> for FaultInjector<B, F>
+where
+    B: CanTransmitter<F>,
+    F: Frame + Clone,
+{
+    fn transmit(&self, frame: &F) -> IoResult<()> {
+        if !self.dir.has_tx() {
+            return self.inner.transmit(frame);
+        }
+
+        let mut frame = frame.clone();
+        let plan = self.plan(&mut frame);
+        if plan.drop {
+            return Ok(());
+        }
+        if !plan.delay.is_zero() {
+            thread::sleep(plan.delay);
+        }
+
+        // A held frame goes out after this one, unless this one is held
+        // back in turn, in which case they swap places.
+        let held = {
+            let mut state = self.state.lock().unwrap();
+            if plan.reorder {
+                state.tx_held.replace(frame.clone())
+            } else {
+                state.tx_held.take()
+            }
+        };
+        if !plan.reorder {
+            self.inner.transmit(&frame)?;
+            if plan.duplicate {
+                self.inner.transmit(&frame)?;
+            }
+        }
+        if let Some(held) = held {
+            self.inner.transmit(&held)?;
+        }
+        Ok(())
+    }
+}
+
+impl<B, F> FaultInjector<B, F>
+where
+    B: CanReceiver<Frame = F>,
+    F: Frame + Clone,
+{
+    fn receive_with(&self, timeout: Option<Duration>) -> IoResult<F> {
+        loop {
+            if let Some(frame) = self.state.lock().unwrap().rx_pending.pop_front() {
+                return Ok(frame);
+            }
+
+            let mut frame = match timeout {
+                Some(timeout) => self.inner.receive_timeout(timeout)?,
+                None => self.inner.receive()?,
+            };
+            if !self.dir.has_rx() {
+                return Ok(frame);
+            }
+
+            let plan = self.plan(&mut frame);
+            if plan.drop {
+                continue;
+            }
+            if !plan.delay.is_zero() {
+                thread::sleep(plan.delay);
+            }
+
+            let mut state = self.state.lock().unwrap();
+            if plan.reorder {
+                if let Some(held) = state.rx_held.replace(frame) {
+                    state.rx_pending.push_back(held);
+                }
+                continue;
+            }
+            if plan.duplicate {
+                state.rx_pending.push_back(frame.clone());
+            }
+            if let Some(held) = state.rx_held.take() {
+                state.rx_pending.push_back(held);
+            }
+            return Ok(frame);
+        }
+    }
+}
+
+impl<B, F> CanReceiver for FaultInjector<B, F>
+where
+    B: CanReceiver<Frame = F>,
+    F: Frame + Clone,
+{
+    type Frame = F;
+
+    fn receive(&self) -> IoResult<F> {
+        self.receive_with(None)
+    }
+
+    fn receive_timeout(&self, timeout: Duration) -> IoResult<F> {
+        self.receive_with(Some(timeout))
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{mock::MockCanSocket, CanFrame, EmbeddedFrame};
+
+    fn frame(i: u8) -> CanFrame {
+        CanFrame::from_raw_id(0x100 + i as u32, &[i, 0, 0, 0]).unwrap()
+    }
+
+    #[test]
+    fn test_tx_faults() {
+        let sock = MockCanSocket::<CanFrame>::new();
+        let faulty = FaultInjector::new(sock.clone())
+            .with_seed(7)
+            .with_drop(0.2)
+            .with_duplicate(0.2)
+            .with_corrupt(0.2)
+            .with_filter(|frame: &CanFrame| frame.raw_id() != 0x100);
+
+        for i in 0..200 {
+            faulty.transmit(&frame(i)).unwrap();
+        }
+        let stats = faulty.stats();
+        assert!(stats.dropped > 0 && stats.duplicated > 0 && stats.corrupted > 0);
+
+        let mut sent = sock.sent();
+        assert_eq!(200 - stats.dropped + stats.duplicated, sent.len() as u64);
+        // The filtered frame is always untouched
+        assert_eq!(frame(0), sent[0]);
+
+        // A duplicate follows right after the original
+        sent.dedup();
+        let corrupted = sent
+            .iter()
+            .filter(|f| f.data()[1..] != [0, 0, 0] || f.data()[0] as u32 != f.raw_id() - 0x100)
+            .count();
+        assert_eq!(stats.corrupted, corrupted as u64);
+    }
+
+    #[test]
+    fn test_reorder() {
+        let sock = MockCanSocket::<CanFrame>::new();
+        for i in 0..3 {
+            sock.push_rx(frame(i));
+        }
+        let faulty = FaultInjector::new(sock.clone())
+            .with_reorder(1.0)
+            .with_filter(|frame: &CanFrame| frame.raw_id() == 0x100);
+
+        // The first frame is held back behind the second
+        for i in [1, 0, 2] {
+            assert_eq!(frame(i), faulty.receive().unwrap());
+        }
+        for i in 0..3 {
+            faulty.transmit(&frame(i)).unwrap();
+        }
+        assert_eq!(vec![frame(1), frame(0), frame(2)], sock.sent());
+        assert_eq!(2, faulty.stats().reordered);
+    }
+}
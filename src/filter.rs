@@ -0,0 +1,451 @@
+// socketcan/src/filter.rs
+//
+// Building kernel receive filters.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! Building kernel receive filters.
+//!
+//! Tools built on the crate can take their filters in the same syntax as
+//! the `candump` utility from
+//! [can-utils](https://github.com/linux-can/can-utils): a comma-separated
+//! list of
+//!
+//! - `<can_id>:<can_mask>`, which passes a frame when
+//!   `<received_can_id> & mask == can_id & mask`
+//! - `<can_id>~<can_mask>`, which passes a frame when
+//!   `<received_can_id> & mask != can_id & mask`
+//! - `#<error_mask>`, which sets the error classes reported as error
+//!   frames
+//! - `j` or `J`, which joins the filters, so a frame has to pass them all,
+//!   rather than any one
+//!
+//! All the values are in hex. As with candump, an ID of eight digits is an
+//! extended ID, and the mask never selects error frames; those are only
+//! controlled by the error mask.
+//!
+//! ```no_run
+//! use socketcan::{filter::FilterSpec, CanSocket, Socket};
+//!
+//! let spec: FilterSpec = "123:7FF,400~7F0,#20000004".parse().unwrap();
+//!
+//! let sock = CanSocket::open("can0").unwrap();
+//! spec.apply(&sock).unwrap();
+//! ```
+//!
+//! An application that knows which IDs it wants, rather than how to mask
+//! them, can collect them in an [`IdSet`] and have it work out the
+//! filters. Each range of IDs is covered by the fewest ID and mask pairs
+//! that match exactly that range. The kernel only takes so many filters
+//! on a socket, so when there would be too many, the [`FilterPlan`] falls
+//! back to passing every frame through the kernel, and checking the IDs
+//! in userspace instead.
+//!
+//! ```no_run
+//! use socketcan::{filter::IdSet, CanFdSocket, CanId, Frame, Socket};
+//!
+//! let ids = IdSet::new()
+//!     .with_range(0x100..=0x17F, false)
+//!     .with_id(CanId::extended(0x18FEF100).unwrap());
+//! let plan = ids.plan();
+//!
+//! let sock = CanFdSocket::open("can0").unwrap();
+//! plan.apply(&sock).unwrap();
+//!
+//! loop {
+//!     let frame = sock.read_frame().unwrap();
+//!     if plan.accepts(frame.id_word()) {
+//!         println!("{:?}", frame);
+//!     }
+//! }
+//! ```
+
+use crate::{CanFilter, CanId, IoResult, SocketOptions};
+use libc::{canid_t, CAN_EFF_FLAG, CAN_EFF_MASK, CAN_ERR_FLAG, CAN_SFF_MASK};
+use std::{ops::RangeInclusive, str::FromStr};
+use thiserror::Error;
+
+/// The most filters that the kernel accepts on a raw socket.
+pub const CAN_RAW_FILTER_MAX: usize = 512;
+
+/// An error parsing a filter expression.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ParseFilterError {
+    /// An item in the list isn't a filter, an error mask, or a join
+    #[error("Invalid filter: '{0}'")]
+    InvalidFilter(String),
+    /// A value isn't a valid hex number
+    #[error("Invalid hex value: '{0}'")]
+    InvalidHex(String),
+}
+
+// Parses a hex value, for the error
+fn parse_hex(s: &str) -> Result<canid_t, ParseFilterError> {
+    canid_t::from_str_radix(s, 16).map_err(|_| ParseFilterError::InvalidHex(s.into()))
+}
+
+impl FromStr for CanFilter {
+    type Err = ParseFilterError;
+
+    /// Parses a single filter, in the candump syntax, like `123:7FF` or
+    /// `400~7F0`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (id, mask, inverted) = match (s.split_once(':'), s.split_once('~')) {
+            (Some((id, mask)), None) => (id, mask, false),
+            (None, Some((id, mask))) => (id, mask, true),
+            _ => return Err(ParseFilterError::InvalidFilter(s.into())),
+        };
+
+        let mut can_id = parse_hex(id)?;
+        if id.len() == 8 {
+            can_id |= CAN_EFF_FLAG;
+        }
+        let can_mask = parse_hex(mask)? & !CAN_ERR_FLAG;
+
+        Ok(if inverted {
+            CanFilter::new_inverted(can_id, can_mask)
+        } else {
+            CanFilter::new(can_id, can_mask)
+        })
+    }
+}
+
+// ===== FilterSpec =====
+
+/// A full set of receive filter settings for a socket, as given to
+/// candump.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct FilterSpec {
+    /// The ID filters. If there are none, the socket's filters are left
+    /// alone.
+    pub filters: Vec<CanFilter>,
+    /// The error classes to report as error frames, if any
+    pub error_mask: u32,
+    /// Whether a frame has to pass all the filters, rather than any one
+    pub join: bool,
+}
+
+impl FilterSpec {
+    /// Sets the filters on the socket.
+    ///
+    /// As with candump, the ID filters replace the socket's own only if
+    /// there are any, so a spec of just an error mask leaves all the
+    /// frames passing.
+    pub fn apply<S: SocketOptions>(&self, sock: &S) -> IoResult<()> {
+        if self.error_mask != 0 {
+            sock.set_error_filter(self.error_mask)?;
+        }
+        if self.join {
+            sock.set_join_filters(true)?;
+        }
+        if !self.filters.is_empty() {
+            sock.set_filters(&self.filters)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for FilterSpec {
+    type Err = ParseFilterError;
+
+    /// Parses a comma-separated list of filters, in the candump syntax.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut spec = Self::default();
+        for item in s.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            if item.eq_ignore_ascii_case("j") {
+                spec.join = true;
+            } else if let Some(mask) = item.strip_prefix('#') {
+                spec.error_mask |= parse_hex(mask)?;
+            } else {
+                spec.filters.push(item.parse()?);
+            }
+        }
+        Ok(spec)
+    }
+}
+
+// ===== IdSet =====
+
+/// A set of standard and extended IDs, made up of ranges.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct IdSet {
+    standard: Vec<RangeInclusive<u32>>,
+    extended: Vec<RangeInclusive<u32>>,
+}
+
+impl IdSet {
+    /// Creates an empty set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a single ID to the set.
+    pub fn with_id(mut self, id: CanId) -> Self {
+        self.insert_id(id);
+        self
+    }
+
+    /// Adds a range of raw standard or extended IDs to the set.
+    pub fn with_range(mut self, ids: RangeInclusive<u32>, extended: bool) -> Self {
+        self.insert_range(ids, extended);
+        self
+    }
+
+    /// Adds a single ID to the set.
+    pub fn insert_id(&mut self, id: CanId) {
+        let raw = id.as_raw();
+        self.insert_range(raw..=raw, id.is_extended());
+    }
+
+    /// Adds a range of raw standard or extended IDs to the set. The range
+    /// is clipped to the valid IDs of the type.
+    pub fn insert_range(&mut self, ids: RangeInclusive<u32>, extended: bool) {
+        let (ranges, max) = if extended {
+            (&mut self.extended, CAN_EFF_MASK)
+        } else {
+            (&mut self.standard, CAN_SFF_MASK)
+        };
+        let (lo, hi) = (*ids.start(), (*ids.end()).min(max));
+        if lo > hi {
+            return;
+        }
+
+        // Keep the ranges sorted, and merge any that overlap or touch
+        ranges.push(lo..=hi);
+        ranges.sort_by_key(|r| *r.start());
+        let mut merged: Vec<RangeInclusive<u32>> = Vec::with_capacity(ranges.len());
+        for r in ranges.drain(..) {
+            match merged.last_mut() {
+                Some(last) if *r.start() <= last.end().saturating_add(1) => {
+                    *last = *last.start()..=*last.end().max(r.end());
+                }
+                _ => merged.push(r),
+            }
+        }
+        *ranges = merged;
+    }
+
+    /// Determines if the set is empty.
+    pub fn is_empty(&self) -> bool {
+        self.standard.is_empty() && self.extended.is_empty()
+    }
+
+    /// Determines if the set contains the ID.
+    pub fn contains(&self, id: CanId) -> bool {
+        let ranges = if id.is_extended() {
+            &self.extended
+        } else {
+            &self.standard
+        };
+        let raw = id.as_raw();
+        // The ranges are sorted and disjoint
+        let i = ranges.partition_point(|r| *r.end() < raw);
+        ranges.get(i).is_some_and(|r| r.contains(&raw))
+    }
+
+    /// Gets the kernel filters that match exactly the IDs in the set.
+    ///
+    /// Each range is covered by the fewest ID and mask pairs that match
+    /// it, and nothing else, the same way that a range of IP addresses is
+    /// split into CIDR blocks. Remote frames with the IDs pass, but error
+    /// frames don't.
+    pub fn filters(&self) -> Vec<CanFilter> {
+        let mut filters = Vec::new();
+        for r in &self.standard {
+            cover(r, CAN_SFF_MASK, 0, &mut filters);
+        }
+        for r in &self.extended {
+            cover(r, CAN_EFF_MASK, CAN_EFF_FLAG, &mut filters);
+        }
+        filters
+    }
+
+    /// Works out how to filter for the set, within the kernel's limit of
+    /// [`CAN_RAW_FILTER_MAX`] filters.
+    pub fn plan(&self) -> FilterPlan {
+        self.plan_with_limit(CAN_RAW_FILTER_MAX)
+    }
+
+    /// Works out how to filter for the set, with at most `limit` kernel
+    /// filters. If it takes more, every frame is passed by the kernel and
+    /// the set is checked in userspace.
+    pub fn plan_with_limit(&self, limit: usize) -> FilterPlan {
+        let filters = self.filters();
+        if filters.len() <= limit {
+            FilterPlan::Kernel(filters)
+        } else {
+            FilterPlan::Userspace(self.clone())
+        }
+    }
+}
+
+// Covers the range of IDs with aligned, power-of-two blocks, each of
+// which is an ID and a mask.
+fn cover(r: &RangeInclusive<u32>, id_mask: u32, flag: u32, filters: &mut Vec<CanFilter>) {
+    let (mut lo, hi) = (u64::from(*r.start()), u64::from(*r.end()));
+    while lo <= hi {
+        // The largest block that starts at `lo`, and doesn't pass `hi`
+        let mut size = if lo == 0 {
+            1 << 32
+        } else {
+            1u64 << lo.trailing_zeros()
+        };
+        while lo + size - 1 > hi {
+            size >>= 1;
+        }
+        // The flags in the mask keep out the other type of ID, and error
+        // frames, while leaving out RTR lets remote frames through.
+        let mask = id_mask & !((size - 1) as u32) | CAN_EFF_FLAG | CAN_ERR_FLAG;
+        filters.push(CanFilter::new(lo as u32 | flag, mask));
+        lo += size;
+    }
+}
+
+// ===== FilterPlan =====
+
+/// How to filter a socket for an [`IdSet`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FilterPlan {
+    /// The kernel filters match the set exactly
+    Kernel(Vec<CanFilter>),
+    /// The set needs too many filters for the kernel, so the socket passes
+    /// every frame, and the set is checked in userspace
+    Userspace(IdSet),
+}
+
+impl FilterPlan {
+    /// Sets the kernel filters on the socket.
+    ///
+    /// With an empty set, the socket receives no frames.
+    pub fn apply<S: SocketOptions>(&self, sock: &S) -> IoResult<()> {
+        match self {
+            Self::Kernel(filters) if filters.is_empty() => sock.set_filter_drop_all(),
+            Self::Kernel(filters) => sock.set_filters(filters),
+            Self::Userspace(_) => sock.set_filter_accept_all(),
+        }
+    }
+
+    /// Determines if the filtering passes a frame with the ID word, as
+    /// read from a socket that the plan was applied to.
+    pub fn accepts(&self, id_word: canid_t) -> bool {
+        match self {
+            Self::Kernel(filters) => filters.iter().any(|f| f.matches(id_word)),
+            Self::Userspace(_) if id_word & CAN_ERR_FLAG != 0 => false,
+            Self::Userspace(ids) => ids.contains(CanId::from_id_word(id_word)),
+        }
+    }
+
+    /// Determines if the plan needs the IDs checked in userspace.
+    pub fn is_userspace(&self) -> bool {
+        matches!(self, Self::Userspace(_))
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libc::CAN_INV_FILTER;
+
+    #[test]
+    fn test_parse_filter() {
+        let filter: CanFilter = "123:7FF".parse().unwrap();
+        assert_eq!(CanFilter::new(0x123, 0x7FF), filter);
+        assert!(filter.matches(0x123));
+        assert!(!filter.matches(0x124));
+
+        let filter: CanFilter = "400~7F0".parse().unwrap();
+        assert_eq!(CanFilter::new(0x400 | CAN_INV_FILTER, 0x7F0), filter);
+        assert!(filter.matches(0x123));
+        assert!(!filter.matches(0x40F));
+
+        // Eight digits is an extended ID
+        let filter: CanFilter = "00000123:1FFFFFFF".parse().unwrap();
+        assert_eq!(CanFilter::new(0x123 | CAN_EFF_FLAG, 0x1FFFFFFF), filter);
+
+        // The mask never selects error frames
+        let filter: CanFilter = "0:FFFFFFFF".parse().unwrap();
+        assert_eq!(CanFilter::new(0, !CAN_ERR_FLAG), filter);
+
+        for bad in ["123", "123:", "xyz:7FF", "1:2:3", "1:2~3"] {
+            assert!(bad.parse::<CanFilter>().is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_parse_spec() {
+        let spec: FilterSpec = "123:7FF, 400~7F0,#20000004,#1,J".parse().unwrap();
+        assert_eq!(
+            vec![
+                CanFilter::new(0x123, 0x7FF),
+                CanFilter::new_inverted(0x400, 0x7F0)
+            ],
+            spec.filters
+        );
+        assert_eq!(0x20000005, spec.error_mask);
+        assert!(spec.join);
+
+        assert_eq!(FilterSpec::default(), "".parse().unwrap());
+        assert_eq!(
+            Err(ParseFilterError::InvalidHex("x".into())),
+            "123:7FF,#x".parse::<FilterSpec>()
+        );
+    }
+
+    #[test]
+    fn test_id_set_filters() {
+        let ids = IdSet::new()
+            .with_range(0x100..=0x17F, false)
+            .with_range(0x180..=0x182, false)
+            .with_id(CanId::standard(0x7FF).unwrap())
+            .with_range(0..=u32::MAX, true);
+
+        let filters = ids.filters();
+        assert_eq!(
+            vec![
+                CanFilter::new(0x100, 0x780 | CAN_EFF_FLAG | CAN_ERR_FLAG),
+                CanFilter::new(0x180, 0x7FE | CAN_EFF_FLAG | CAN_ERR_FLAG),
+                CanFilter::new(0x182, 0x7FF | CAN_EFF_FLAG | CAN_ERR_FLAG),
+                CanFilter::new(0x7FF, 0x7FF | CAN_EFF_FLAG | CAN_ERR_FLAG),
+                CanFilter::new(CAN_EFF_FLAG, CAN_EFF_FLAG | CAN_ERR_FLAG),
+            ],
+            filters
+        );
+
+        // The filters match exactly the IDs in the set
+        let plan = ids.plan();
+        assert!(!plan.is_userspace());
+        for raw in 0..=CAN_SFF_MASK {
+            let id = CanId::standard(raw as u16).unwrap();
+            assert_eq!(ids.contains(id), plan.accepts(id.id_word()), "{}", id);
+        }
+        assert!(plan.accepts(0x1234 | CAN_EFF_FLAG | libc::CAN_RTR_FLAG));
+        assert!(!plan.accepts(CAN_ERR_FLAG | 0x100));
+    }
+
+    #[test]
+    fn test_id_set_userspace() {
+        // Every other ID takes one filter each
+        let mut ids = IdSet::new();
+        for raw in (0..0x400).step_by(2) {
+            ids.insert_id(CanId::standard(raw).unwrap());
+        }
+        assert_eq!(0x200, ids.filters().len());
+        assert!(!ids.plan().is_userspace());
+
+        ids.insert_id(CanId::standard(0x401).unwrap());
+        let plan = ids.plan();
+        assert!(plan.is_userspace());
+        assert!(plan.accepts(0x3FE));
+        assert!(!plan.accepts(0x3FF));
+        assert!(plan.accepts(0x401));
+        assert!(!plan.accepts(0x3FE | CAN_EFF_FLAG));
+    }
+}
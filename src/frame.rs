@@ -29,21 +29,67 @@
 //!   can not be sent to the bus, but can be converted to standard Rust
 //!   [Error](https://doc.rust-lang.org/std/error/trait.Error.html) types.
 //!
+//! # Text format
+//!
+//! The `Display` implementation for all of the frame types renders them
+//! in the same compact ASCII format used by `candump -L` and `cansend`
+//! from [can-utils](https://github.com/linux-can/can-utils). This format
+//! is considered stable:
+//!
+//! ```text
+//! 123#DEADBEEF        - standard (11-bit) data frame, 3 hex digit ID
+//! 12345678#DEADBEEF   - extended (29-bit) data frame, 8 hex digit ID
+//! 123#R               - remote frame
+//! 123#R4              - remote frame with a non-zero DLC
+//! 20000080#0000000000000000 - error frame (error flag in the ID)
+//! 123##1DEADBEEF      - FD frame, a single hex digit of FD flags
+//! ```
+//!
+//! The alternate form, `{:#}`, separates the data bytes with spaces, which
+//! is easier to read in a console, like `123#DE AD BE EF`.
+//!
+//! # Serde
+//!
+//! With the `serde` feature, the frames can be serialized and deserialized
+//! as structs of their logical fields, rather than as the raw C structs.
+//! In JSON, they look like this:
+//!
+//! ```text
+//! CanDataFrame   - { "id": 291, "ext": false, "data": [1, 2, 3] }
+//! CanRemoteFrame - { "id": 291, "ext": false, "dlc": 4 }
+//! CanErrorFrame  - { "bits": 32, "data": [0, 0, 0, 0, 0, 0, 0, 0] }
+//! CanFdFrame     - { "id": 291, "ext": true, "brs": true, "esi": false, "data": [1, 2, 3] }
+//! ```
+//!
+//! The `id` is the raw numeric ID, without any flags, and `bits` are the
+//! error class bits from the ID word of an error frame. The enums, like
+//! [`CanFrame`] and [`CanAnyFrame`], are externally tagged with the name
+//! of the variant, like `{ "Data": { "id": 291, ... } }`.
+//!
 
-use crate::{CanError, ConstructionError};
+use crate::{
+    errors::{
+        self, CanErrorDecodingFailure, ControllerProblem, Location, TransceiverError, ViolationType,
+    },
+    hex, CanError, CanId, ConstructionError,
+};
 use bitflags::bitflags;
 use embedded_can::{ExtendedId, Frame as EmbeddedFrame, Id, StandardId};
-use itertools::Itertools;
-use libc::{can_frame, canfd_frame, canid_t};
+use libc::{can_frame, canfd_frame, canid_t, canxl_frame};
+#[cfg(feature = "serde")]
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use std::{
     ffi::c_void,
+    hash::{Hash, Hasher},
     mem::size_of,
+    time::Duration,
     {convert::TryFrom, fmt, matches, mem},
 };
 
 pub use libc::{
-    CANFD_BRS, CANFD_ESI, CANFD_MAX_DLEN, CAN_EFF_FLAG, CAN_EFF_MASK, CAN_ERR_FLAG, CAN_ERR_MASK,
-    CAN_MAX_DLEN, CAN_RTR_FLAG, CAN_SFF_MASK,
+    CANFD_BRS, CANFD_ESI, CANFD_FDF, CANFD_MAX_DLEN, CANXL_HDR_SIZE, CANXL_MAX_DLEN,
+    CANXL_MIN_DLEN, CANXL_PRIO_MASK, CANXL_SEC, CANXL_XLF, CAN_EFF_FLAG, CAN_EFF_MASK,
+    CAN_ERR_FLAG, CAN_ERR_MASK, CAN_MAX_DLEN, CAN_RTR_FLAG, CAN_SFF_MASK,
 };
 
 /// An error mask that will cause SocketCAN to report all errors
@@ -64,11 +110,23 @@ bitflags! {
     }
 
     /// Bit flags for the Flexible Data (FD) frames.
+    #[derive(Default)]
     pub struct FdFlags: u8 {
         /// Bit rate switch (second bit rate for payload data)
         const BRS = CANFD_BRS as u8;
         /// Error state indicator of the transmitting node
         const ESI = CANFD_ESI as u8;
+        /// Marks the frame as an FD frame, when reported by the kernel
+        const FDF = CANFD_FDF as u8;
+    }
+
+    /// Bit flags for the CAN XL frames.
+    #[derive(Default)]
+    pub struct XlFlags: u8 {
+        /// Simple extended content (security/segmentation)
+        const SEC = CANXL_SEC as u8;
+        /// Marks the frame as a CAN XL frame. This is always set.
+        const XLF = CANXL_XLF as u8;
     }
 }
 
@@ -93,11 +151,314 @@ pub fn id_is_extended(id: &Id) -> bool {
 /// it is created as an Extened ID. If you require an Extended ID <= 0x7FF,
 /// create it explicitly.
 pub fn id_from_raw(id: u32) -> Option<Id> {
-    let id = match id {
-        n if n <= CAN_SFF_MASK => StandardId::new(n as u16)?.into(),
-        n => ExtendedId::new(n)?.into(),
+    CanId::from_raw(id).ok().map(Id::from)
+}
+
+/// Creates a SocketCAN ID word from the numeric ID and extended flag.
+///
+/// Unlike [`CanId::from_raw`], a standard ID is never promoted to an
+/// extended one, so an ID that's too large for it is an error.
+#[cfg(feature = "serde")]
+fn canid_from_parts(id: u32, ext: bool) -> Result<canid_t, ConstructionError> {
+    let can_id = CanId::from_raw(id)?;
+    if ext {
+        Ok(id | CAN_EFF_FLAG)
+    } else if can_id.is_extended() {
+        Err(ConstructionError::IDTooLarge {
+            id,
+            extended: false,
+        })
+    } else {
+        Ok(can_id.id_word())
+    }
+}
+
+// ===== Bus priority =====
+
+/// Gets the bus arbitration key for a SocketCAN ID word.
+///
+/// The key reproduces the arbitration field as it's sent on the wire,
+/// with dominant bits as zeros, so a frame with a lower key wins
+/// arbitration over one with a higher key. The layout, from the MSB, is:
+///
+/// ```text
+/// base ID (11) | RTR or SRR (1) | IDE (1) | extended ID (18) | RTR (1)
+/// ```
+///
+/// This means that a lower ID wins, a data frame wins over a remote frame
+/// with the same ID, and a standard frame wins over an extended frame
+/// with the same 11-bit base ID.
+pub fn arbitration_key(can_id: canid_t) -> u32 {
+    let rtr = u32::from(can_id & CAN_RTR_FLAG != 0);
+    if can_id & CAN_EFF_FLAG != 0 {
+        let id = can_id & CAN_EFF_MASK;
+        let base = id >> 18;
+        let ext = id & 0x3FFFF;
+        (base << 21) | (1 << 20) | (1 << 19) | (ext << 1) | rtr
+    } else {
+        let base = can_id & CAN_SFF_MASK;
+        (base << 21) | (rtr << 20)
+    }
+}
+
+// ===== Classic raw DLC =====
+
+/// The largest data length code (DLC) that can be sent on the wire.
+///
+/// For classic CAN frames, a DLC of 9-15 still means an 8-byte payload.
+pub const CAN_MAX_RAW_DLC: u8 = 15;
+
+/// Gets the raw, wire DLC of a classic frame, if it's 9-15.
+///
+/// The kernel only reports this for 8-byte frames, and only when the
+/// `CAN_CTRLMODE_CC_LEN8_DLC` mode is enabled on the interface.
+fn len8_dlc(frame: &can_frame) -> Option<u8> {
+    (frame.can_dlc as usize == CAN_MAX_DLEN
+        && frame.len8_dlc as usize > CAN_MAX_DLEN
+        && frame.len8_dlc <= CAN_MAX_RAW_DLC)
+        .then_some(frame.len8_dlc)
+}
+
+/// Sets the raw, wire DLC of an 8-byte classic frame.
+///
+/// A value of 8 or less clears it.
+fn set_len8_dlc(frame: &mut can_frame, dlc: u8) -> Result<(), ConstructionError> {
+    match dlc {
+        n if n as usize <= CAN_MAX_DLEN => frame.len8_dlc = 0,
+        n if n <= CAN_MAX_RAW_DLC && frame.can_dlc as usize == CAN_MAX_DLEN => frame.len8_dlc = n,
+        n if n <= CAN_MAX_RAW_DLC => return Err(ConstructionError::WrongFrameType),
+        n => {
+            return Err(ConstructionError::TooMuchData {
+                len: n as usize,
+                max: CAN_MAX_RAW_DLC as usize,
+            })
+        }
+    }
+    Ok(())
+}
+
+// ===== CAN FD DLC =====
+
+/// The payload length, in bytes, for each of the CAN FD data length
+/// codes (DLC), 0-15.
+pub const CANFD_DLC_TO_LEN: [u8; 16] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 12, 16, 20, 24, 32, 48, 64];
+
+/// Gets the payload length, in bytes, for a CAN FD data length code.
+///
+/// Only the lower 4 bits of the DLC are used.
+pub fn fd_dlc_to_len(dlc: u8) -> usize {
+    CANFD_DLC_TO_LEN[(dlc & 0x0F) as usize] as usize
+}
+
+/// Gets the smallest CAN FD data length code that can hold a payload of
+/// `len` bytes, or `None` if it's larger than an FD frame can hold.
+pub fn fd_len_to_dlc(len: usize) -> Option<u8> {
+    CANFD_DLC_TO_LEN
+        .iter()
+        .position(|&n| len <= n as usize)
+        .map(|dlc| dlc as u8)
+}
+
+/// Gets the smallest legal CAN FD payload length that can hold `len`
+/// bytes, or `None` if it's larger than an FD frame can hold.
+///
+/// The legal lengths are 0-8, 12, 16, 20, 24, 32, 48, and 64 bytes.
+pub fn fd_padded_len(len: usize) -> Option<usize> {
+    fd_len_to_dlc(len).map(fd_dlc_to_len)
+}
+
+/// Determines if `len` is one of the legal CAN FD payload lengths.
+pub fn is_valid_fd_len(len: usize) -> bool {
+    fd_padded_len(len) == Some(len)
+}
+
+/// Creates a default C `canxl_frame`.
+/// This initializes the entire structure to zeros.
+#[inline(always)]
+pub fn canxl_frame_default() -> canxl_frame {
+    unsafe { mem::zeroed() }
+}
+
+// ===== Wire length =====
+
+/// The bits at the end of every frame that are never stuffed: the CRC
+/// delimiter, ACK slot and delimiter, end of frame, and interframe space.
+const FRAME_TRAILER_BITS: usize = 13;
+
+/// Gets the worst-case number of stuff bits for `n` bits that are subject
+/// to bit stuffing.
+///
+/// A stuff bit is inserted after five consecutive bits of the same level,
+/// and since the stuff bit can start a new run, the worst case is one
+/// stuff bit in every four bits after the first.
+fn worst_case_stuff_bits(n: usize) -> usize {
+    n.saturating_sub(1) / 4
+}
+
+/// Gets the worst-case number of bits that a classic CAN 2.0 frame
+/// occupies on the wire, including stuff bits and the interframe space.
+///
+/// The `len` is the number of data bytes sent, which is zero for a
+/// remote frame.
+pub fn classic_bit_length(extended: bool, len: usize) -> usize {
+    // SOF, ID, RTR, IDE, r0, DLC, and CRC for a standard frame, with
+    // SRR and the 18-bit ID extension added for an extended frame.
+    let header = if extended { 54 } else { 34 };
+    let stuffed = header + 8 * len;
+    stuffed + worst_case_stuff_bits(stuffed) + FRAME_TRAILER_BITS
+}
+
+/// Gets the worst-case number of bits that a CAN FD frame occupies on the
+/// wire, split into the bits sent at the nominal (arbitration) bit rate
+/// and those sent in the data phase, which may be at a higher bit rate if
+/// the frame uses bit rate switching (BRS).
+///
+/// The `len` should be a legal FD payload length.
+pub fn fd_bit_lengths(extended: bool, len: usize) -> (usize, usize) {
+    // SOF, ID, RRS, IDE, FDF, res, and BRS for a standard frame, with
+    // SRR and the 18-bit ID extension added for an extended frame.
+    let arb = if extended { 36 } else { 17 };
+    // ESI, DLC, and the data
+    let data = 5 + 8 * len;
+    let stuff = worst_case_stuff_bits(arb + data);
+    let arb_stuff = worst_case_stuff_bits(arb);
+
+    // The stuff count and CRC have fixed stuff bits, one before the stuff
+    // count and after every four bits.
+    let crc = if len > 16 { 21 } else { 17 };
+    let crc_field = 4 + crc + (4 + crc) / 4 + 1;
+
+    (
+        arb + arb_stuff + FRAME_TRAILER_BITS,
+        data + (stuff - arb_stuff) + crc_field,
+    )
+}
+
+/// Gets the worst-case total number of bits that a CAN FD frame occupies
+/// on the wire.
+pub fn fd_bit_length(extended: bool, len: usize) -> usize {
+    let (arb, data) = fd_bit_lengths(extended, len);
+    arb + data
+}
+
+// ===== Text formatting =====
+
+/// The style used to write a frame as text.
+///
+/// All of the styles use the candump layout, and differ in the case of the
+/// hex digits and in what the alternate (`#`) flag does:
+///
+/// - `{}` is uppercase, and `{:#}` separates the data bytes with spaces,
+///   like `123#00 01 02`
+/// - `{:X}` is the same as `{}`, and `{:#X}` adds a "0x" prefix to the ID
+///   and separates the data bytes with dots, like `0x123#00.01.02`
+/// - `{:x}` and `{:#x}` are the lowercase versions of `{:X}` and `{:#X}`
+#[derive(Clone, Copy)]
+struct TextStyle {
+    upper: bool,
+    prefix: bool,
+    sep: Option<u8>,
+}
+
+impl TextStyle {
+    /// The style for `Display`.
+    fn display(f: &fmt::Formatter) -> Self {
+        Self {
+            upper: true,
+            prefix: false,
+            sep: f.alternate().then_some(b' '),
+        }
+    }
+
+    /// The style for `UpperHex` or `LowerHex`.
+    fn hex(f: &fmt::Formatter, upper: bool) -> Self {
+        Self {
+            upper,
+            prefix: f.alternate(),
+            sep: f.alternate().then_some(b'.'),
+        }
+    }
+
+    /// Writes a value as hex digits, zero-padded to the width, which is
+    /// at most 8.
+    fn write_hex(&self, f: &mut fmt::Formatter, val: u32, width: usize) -> fmt::Result {
+        let mut buf = [0u8; 8];
+        for (i, c) in buf[..width].iter_mut().rev().enumerate() {
+            *c = hex::encode_digit((val >> (4 * i)) as u8, self.upper);
+        }
+        f.write_str(ascii_str(&buf[..width]))
+    }
+}
+
+/// Views bytes that are known to be ASCII as a string.
+fn ascii_str(buf: &[u8]) -> &str {
+    debug_assert!(buf.is_ascii());
+    // SAFETY: The bytes are all ASCII hex digits or separators
+    unsafe { std::str::from_utf8_unchecked(buf) }
+}
+
+/// Writes bytes as hex digits, with an optional ASCII separator between
+/// them.
+///
+/// The text is built up in a buffer on the stack and written in large
+/// pieces, rather than formatting each byte, which matters when logging
+/// tens of thousands of frames per second.
+pub(crate) fn write_hex_bytes<W>(
+    w: &mut W,
+    data: &[u8],
+    upper: bool,
+    sep: Option<u8>,
+) -> fmt::Result
+where
+    W: fmt::Write + ?Sized,
+{
+    let mut buf = [0u8; 192];
+    let Some(sep) = sep else {
+        for chunk in data.chunks(buf.len() / 2) {
+            // The buffer always fits the chunk
+            let text = hex::encode_into(chunk, &mut buf, upper).map_err(|_| fmt::Error)?;
+            w.write_str(text)?;
+        }
+        return Ok(());
     };
-    Some(id)
+
+    let mut n = 0;
+    for (i, b) in data.iter().enumerate() {
+        if n + 3 > buf.len() {
+            w.write_str(ascii_str(&buf[..n]))?;
+            n = 0;
+        }
+        if i != 0 {
+            buf[n] = sep;
+            n += 1;
+        }
+        buf[n..n + 2].copy_from_slice(&hex::encode_byte(*b, upper));
+        n += 2;
+    }
+    w.write_str(ascii_str(&buf[..n]))
+}
+
+/// Writes the ID of a frame in the candump format.
+///
+/// Error frames and extended frames use 8 hex digits, while standard
+/// frames use 3 digits.
+fn fmt_candump_id(f: &mut fmt::Formatter, can_id: canid_t, style: TextStyle) -> fmt::Result {
+    if style.prefix {
+        f.write_str("0x")?;
+    }
+    if can_id & CAN_ERR_FLAG != 0 {
+        style.write_hex(f, can_id & (CAN_ERR_MASK | CAN_ERR_FLAG), 8)
+    } else if can_id & CAN_EFF_FLAG != 0 {
+        style.write_hex(f, can_id & CAN_EFF_MASK, 8)
+    } else {
+        style.write_hex(f, can_id & CAN_SFF_MASK, 3)
+    }
+}
+
+/// Writes the data bytes of a frame in the candump format, with the
+/// separator of the style, if any, between them.
+fn fmt_candump_data(f: &mut fmt::Formatter, data: &[u8], style: TextStyle) -> fmt::Result {
+    write_hex_bytes(f, data, style.upper, style.sep)
 }
 
 // ===== can_frame =====
@@ -109,6 +470,56 @@ pub fn can_frame_default() -> can_frame {
     unsafe { mem::zeroed() }
 }
 
+/// A mirror of the C `can_frame` with all public fields, so that one can
+/// be assembled in a const context.
+#[repr(C, align(8))]
+struct ConstCanFrame {
+    can_id: canid_t,
+    len: u8,
+    pad: u8,
+    res0: u8,
+    len8_dlc: u8,
+    data: [u8; CAN_MAX_DLEN],
+}
+
+/// Creates a C `can_frame` from raw parts in a const context.
+///
+/// Panics if the data is longer than 8 bytes, which is a compile-time
+/// error when evaluated for a const item.
+const fn const_can_frame(can_id: canid_t, data: &[u8]) -> can_frame {
+    assert!(data.len() <= CAN_MAX_DLEN, "CAN payload too large");
+
+    let mut buf = [0u8; CAN_MAX_DLEN];
+    let mut i = 0;
+    while i < data.len() {
+        buf[i] = data[i];
+        i += 1;
+    }
+
+    let frame = ConstCanFrame {
+        can_id,
+        len: data.len() as u8,
+        pad: 0,
+        res0: 0,
+        len8_dlc: 0,
+        data: buf,
+    };
+    // SAFETY: The two structures have identical size and layout.
+    unsafe { mem::transmute::<ConstCanFrame, can_frame>(frame) }
+}
+
+/// Converts a raw ID to a SocketCAN ID word in a const context, using a
+/// standard ID if it fits in 11 bits, and an extended one otherwise.
+///
+/// Panics if the ID is larger than 29 bits.
+const fn const_canid(id: u32) -> canid_t {
+    match CanId::from_raw(id) {
+        Ok(can_id) if can_id.is_extended() => id | CAN_EFF_FLAG,
+        Ok(_) => id,
+        Err(_) => panic!("CAN ID too large"),
+    }
+}
+
 /// Creates a default C `can_frame`.
 /// This initializes the entire structure to zeros.
 #[inline(always)]
@@ -116,6 +527,20 @@ pub fn canfd_frame_default() -> canfd_frame {
     unsafe { mem::zeroed() }
 }
 
+/// Copies a raw byte buffer into a C frame structure, checking that it is
+/// exactly the size of the structure.
+fn copy_frame_bytes<T: Sized>(frame: &mut T, buf: &[u8]) -> Result<(), ConstructionError> {
+    let bytes = crate::as_bytes_mut(frame);
+    if buf.len() != bytes.len() {
+        return Err(ConstructionError::InvalidLayout {
+            size: buf.len(),
+            expected: bytes.len(),
+        });
+    }
+    bytes.copy_from_slice(buf);
+    Ok(())
+}
+
 // ===== AsPtr trait =====
 
 /// Trait to get a pointer to an inner type
@@ -160,6 +585,26 @@ pub trait AsPtr {
 /// Shared trait for CAN frames
 #[allow(clippy::len_without_is_empty)]
 pub trait Frame: EmbeddedFrame {
+    /// Creates a new data frame, or reports why it can't be created.
+    ///
+    /// This is the non-panicking, descriptive alternative to
+    /// [`EmbeddedFrame::new`], which just returns `None` on any failure.
+    /// The default reports any failure of `new()` as
+    /// [`ConstructionError::InvalidLength`]; the frame types of this crate
+    /// give a more specific reason.
+    fn try_new(id: impl Into<Id>, data: &[u8]) -> Result<Self, ConstructionError> {
+        Self::new(id, data).ok_or(ConstructionError::InvalidLength { len: data.len() })
+    }
+
+    /// Creates a new remote frame, or reports why it can't be created.
+    ///
+    /// This is the descriptive alternative to [`EmbeddedFrame::new_remote`].
+    /// The default reports any failure of `new_remote()` as
+    /// [`ConstructionError::InvalidLength`].
+    fn try_new_remote(id: impl Into<Id>, dlc: usize) -> Result<Self, ConstructionError> {
+        Self::new_remote(id, dlc).ok_or(ConstructionError::InvalidLength { len: dlc })
+    }
+
     /// Creates a frame using a raw, integer CAN ID.
     ///
     /// If the `id` is <= 0x7FF, it's assumed to be a standard ID, otherwise
@@ -178,6 +623,18 @@ pub trait Frame: EmbeddedFrame {
         Self::new_remote(id_from_raw(id)?, dlc)
     }
 
+    /// Creates a frame using a raw, integer CAN ID, or reports why it
+    /// can't be created.
+    fn try_from_raw_id(id: u32, data: &[u8]) -> Result<Self, ConstructionError> {
+        Self::try_new(CanId::from_raw(id)?, data)
+    }
+
+    /// Creates a remote frame using a raw, integer CAN ID, or reports why
+    /// it can't be created.
+    fn try_remote_from_raw_id(id: u32, dlc: usize) -> Result<Self, ConstructionError> {
+        Self::try_new_remote(CanId::from_raw(id)?, dlc)
+    }
+
     /// Get the composite SocketCAN ID word, with EFF/RTR/ERR flags
     fn id_word(&self) -> canid_t;
 
@@ -191,6 +648,11 @@ pub trait Frame: EmbeddedFrame {
         self.id_word() & mask
     }
 
+    /// Gets the CAN ID of the frame.
+    fn can_id(&self) -> CanId {
+        CanId::from_id_word(self.id_word())
+    }
+
     /// Returns the EFF/RTR/ERR flags from the ID word
     fn id_flags(&self) -> IdFlags {
         IdFlags::from_bits_truncate(self.id_word())
@@ -214,6 +676,16 @@ pub trait Frame: EmbeddedFrame {
         self.dlc()
     }
 
+    /// Gets the bus arbitration priority of the frame.
+    ///
+    /// A lower value has a higher priority, and would win arbitration on
+    /// the bus. This can be used as the sort key for software priority
+    /// queues to schedule frames the way the bus would.
+    /// See [`arbitration_key`] for details.
+    fn priority(&self) -> u32 {
+        arbitration_key(self.id_word())
+    }
+
     /// Check if frame is an error message
     fn is_error_frame(&self) -> bool {
         self.id_flags().contains(IdFlags::ERR)
@@ -222,10 +694,48 @@ pub trait Frame: EmbeddedFrame {
     /// Sets the CAN ID for the frame
     fn set_id(&mut self, id: impl Into<Id>);
 
+    /// Switches the frame between a standard and extended ID, keeping the
+    /// same raw ID value.
+    ///
+    /// This fails if the raw ID is too large to fit into a standard ID.
+    fn set_extended(&mut self, extended: bool) -> Result<(), ConstructionError> {
+        let id = self.raw_id();
+        if extended {
+            if !self.is_extended() {
+                // A standard ID always fits into an extended one
+                self.set_id(ExtendedId::new(id).unwrap());
+            }
+        } else if self.is_extended() {
+            let std_id = StandardId::new(id as u16)
+                .filter(|_| id <= CAN_SFF_MASK)
+                .ok_or(ConstructionError::IDTooLarge {
+                    id,
+                    extended: false,
+                })?;
+            self.set_id(std_id);
+        }
+        Ok(())
+    }
+
     /// Sets the data payload of the frame.
     fn set_data(&mut self, data: &[u8]) -> Result<(), ConstructionError>;
 }
 
+/// Switches a classic C frame between a data and remote frame, in place.
+///
+/// The DLC is kept, so a data frame becomes a request for the same number
+/// of bytes, and a remote frame becomes a data frame of that length with
+/// the payload cleared to zero.
+fn switch_rtr(mut frame: can_frame, rtr: bool) -> can_frame {
+    if rtr {
+        frame.can_id |= CAN_RTR_FLAG;
+    } else {
+        frame.can_id &= !CAN_RTR_FLAG;
+    }
+    frame.data = [0; CAN_MAX_DLEN];
+    frame
+}
+
 // ===== CanAnyFrame =====
 
 /// An FD socket can read a raw classic 2.0 or FD frame.
@@ -251,7 +761,8 @@ impl From<canfd_frame> for CanRawFrame {
 }
 
 /// Any frame type.
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum CanAnyFrame {
     /// A classic CAN 2.0 frame, with up to 8-bytes of data
     Normal(CanDataFrame),
@@ -263,13 +774,35 @@ pub enum CanAnyFrame {
     Fd(CanFdFrame),
 }
 
+impl fmt::Display for CanAnyFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Normal(frame) => fmt::Display::fmt(frame, f),
+            Self::Remote(frame) => fmt::Display::fmt(frame, f),
+            Self::Error(frame) => fmt::Display::fmt(frame, f),
+            Self::Fd(frame) => fmt::Display::fmt(frame, f),
+        }
+    }
+}
+
 impl fmt::UpperHex for CanAnyFrame {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Self::Normal(frame) => frame.fmt(f),
-            Self::Remote(frame) => frame.fmt(f),
-            Self::Error(frame) => frame.fmt(f),
-            Self::Fd(frame) => frame.fmt(f),
+            Self::Normal(frame) => fmt::UpperHex::fmt(frame, f),
+            Self::Remote(frame) => fmt::UpperHex::fmt(frame, f),
+            Self::Error(frame) => fmt::UpperHex::fmt(frame, f),
+            Self::Fd(frame) => fmt::UpperHex::fmt(frame, f),
+        }
+    }
+}
+
+impl fmt::LowerHex for CanAnyFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Normal(frame) => fmt::LowerHex::fmt(frame, f),
+            Self::Remote(frame) => fmt::LowerHex::fmt(frame, f),
+            Self::Error(frame) => fmt::LowerHex::fmt(frame, f),
+            Self::Fd(frame) => fmt::LowerHex::fmt(frame, f),
         }
     }
 }
@@ -285,6 +818,24 @@ impl From<CanFrame> for CanAnyFrame {
     }
 }
 
+impl From<CanDataFrame> for CanAnyFrame {
+    fn from(frame: CanDataFrame) -> Self {
+        Self::Normal(frame)
+    }
+}
+
+impl From<CanRemoteFrame> for CanAnyFrame {
+    fn from(frame: CanRemoteFrame) -> Self {
+        Self::Remote(frame)
+    }
+}
+
+impl From<CanErrorFrame> for CanAnyFrame {
+    fn from(frame: CanErrorFrame) -> Self {
+        Self::Error(frame)
+    }
+}
+
 impl From<can_frame> for CanAnyFrame {
     fn from(frame: can_frame) -> Self {
         let frame = CanFrame::from(frame);
@@ -346,577 +897,744 @@ impl AsPtr for CanAnyFrame {
     }
 }
 
-// ===== CanFrame =====
-
-/// The classic CAN 2.0 frame with up to 8-bytes of data.
-#[derive(Clone, Copy, Debug)]
-pub enum CanFrame {
-    /// A data frame
-    Data(CanDataFrame),
-    /// A remote frame
-    Remote(CanRemoteFrame),
-    /// An error frame
-    Error(CanErrorFrame),
-}
-
-impl AsPtr for CanFrame {
-    type Inner = can_frame;
-
-    /// Gets a pointer to the CAN frame structure that is compatible with
-    /// the Linux C API.
-    fn as_ptr(&self) -> *const Self::Inner {
-        use CanFrame::*;
-        match self {
-            Data(frame) => frame.as_ptr(),
-            Remote(frame) => frame.as_ptr(),
-            Error(frame) => frame.as_ptr(),
-        }
-    }
-
-    /// Gets a mutable pointer to the CAN frame structure that is compatible
-    /// with the Linux C API.
-    fn as_mut_ptr(&mut self) -> *mut Self::Inner {
-        use CanFrame::*;
-        match self {
-            Data(frame) => frame.as_mut_ptr(),
-            Remote(frame) => frame.as_mut_ptr(),
-            Error(frame) => frame.as_mut_ptr(),
-        }
-    }
-}
-
-impl EmbeddedFrame for CanFrame {
-    /// Create a new CAN 2.0 data frame
+impl EmbeddedFrame for CanAnyFrame {
+    /// Create a new data frame.
+    ///
+    /// This is a classic CAN 2.0 frame if the data fits, with up to
+    /// 8 bytes, otherwise it's an FD frame.
     fn new(id: impl Into<Id>, data: &[u8]) -> Option<Self> {
-        CanDataFrame::new(id, data).map(CanFrame::Data)
+        if data.len() <= CAN_MAX_DLEN {
+            CanDataFrame::new(id, data).map(CanAnyFrame::Normal)
+        } else {
+            CanFdFrame::new(id, data).map(CanAnyFrame::Fd)
+        }
     }
 
     /// Create a new remote transmission request frame.
     fn new_remote(id: impl Into<Id>, dlc: usize) -> Option<Self> {
-        CanRemoteFrame::new_remote(id, dlc).map(CanFrame::Remote)
+        CanRemoteFrame::new_remote(id, dlc).map(CanAnyFrame::Remote)
     }
 
     /// Check if frame uses 29-bit extended ID format.
     fn is_extended(&self) -> bool {
-        use CanFrame::*;
         match self {
-            Data(frame) => frame.is_extended(),
-            Remote(frame) => frame.is_extended(),
-            Error(frame) => frame.is_extended(),
+            CanAnyFrame::Normal(frame) => frame.is_extended(),
+            CanAnyFrame::Remote(frame) => frame.is_extended(),
+            CanAnyFrame::Error(frame) => frame.is_extended(),
+            CanAnyFrame::Fd(frame) => frame.is_extended(),
         }
     }
 
     /// Check if frame is a remote transmission request.
     fn is_remote_frame(&self) -> bool {
-        matches!(self, CanFrame::Remote(_))
+        matches!(self, CanAnyFrame::Remote(_))
     }
 
     /// Return the frame identifier.
     fn id(&self) -> Id {
-        use CanFrame::*;
         match self {
-            Data(frame) => frame.id(),
-            Remote(frame) => frame.id(),
-            Error(frame) => frame.id(),
+            CanAnyFrame::Normal(frame) => frame.id(),
+            CanAnyFrame::Remote(frame) => frame.id(),
+            CanAnyFrame::Error(frame) => frame.id(),
+            CanAnyFrame::Fd(frame) => frame.id(),
         }
     }
 
     /// Data length
     fn dlc(&self) -> usize {
-        use CanFrame::*;
         match self {
-            Data(frame) => frame.dlc(),
-            Remote(frame) => frame.dlc(),
-            Error(frame) => frame.dlc(),
+            CanAnyFrame::Normal(frame) => frame.dlc(),
+            CanAnyFrame::Remote(frame) => frame.dlc(),
+            CanAnyFrame::Error(frame) => frame.dlc(),
+            CanAnyFrame::Fd(frame) => frame.dlc(),
         }
     }
 
-    /// A slice into the actual data. Slice will always be <= 8 bytes in length
+    /// A slice into the actual data.
     fn data(&self) -> &[u8] {
-        use CanFrame::*;
         match self {
-            Data(frame) => frame.data(),
-            Remote(frame) => frame.data(),
-            Error(frame) => frame.data(),
+            CanAnyFrame::Normal(frame) => frame.data(),
+            CanAnyFrame::Remote(frame) => frame.data(),
+            CanAnyFrame::Error(frame) => frame.data(),
+            CanAnyFrame::Fd(frame) => frame.data(),
         }
     }
 }
 
-impl Frame for CanFrame {
+impl Frame for CanAnyFrame {
+    /// Create a new data frame.
+    ///
+    /// This is a classic CAN 2.0 frame if the data fits, with up to
+    /// 8 bytes, otherwise it's an FD frame.
+    fn try_new(id: impl Into<Id>, data: &[u8]) -> Result<Self, ConstructionError> {
+        if data.len() <= CAN_MAX_DLEN {
+            CanDataFrame::try_new(id, data).map(CanAnyFrame::Normal)
+        } else {
+            CanFdFrame::try_new(id, data).map(CanAnyFrame::Fd)
+        }
+    }
+
+    /// Create a new remote transmission request frame.
+    fn try_new_remote(id: impl Into<Id>, dlc: usize) -> Result<Self, ConstructionError> {
+        CanRemoteFrame::try_new_remote(id, dlc).map(CanAnyFrame::Remote)
+    }
+
     /// Get the composite SocketCAN ID word, with EFF/RTR/ERR flags
     fn id_word(&self) -> canid_t {
-        use CanFrame::*;
         match self {
-            Data(frame) => frame.id_word(),
-            Remote(frame) => frame.id_word(),
-            Error(frame) => frame.id_word(),
+            CanAnyFrame::Normal(frame) => frame.id_word(),
+            CanAnyFrame::Remote(frame) => frame.id_word(),
+            CanAnyFrame::Error(frame) => frame.id_word(),
+            CanAnyFrame::Fd(frame) => frame.id_word(),
+        }
+    }
+
+    /// Get the number of bytes in the payload.
+    fn len(&self) -> usize {
+        match self {
+            CanAnyFrame::Normal(frame) => frame.len(),
+            CanAnyFrame::Remote(frame) => frame.len(),
+            CanAnyFrame::Error(frame) => frame.len(),
+            CanAnyFrame::Fd(frame) => frame.len(),
         }
     }
 
     /// Sets the CAN ID for the frame
     fn set_id(&mut self, id: impl Into<Id>) {
-        use CanFrame::*;
         match self {
-            Data(frame) => frame.set_id(id),
-            Remote(frame) => frame.set_id(id),
-            Error(frame) => frame.set_id(id),
+            CanAnyFrame::Normal(frame) => frame.set_id(id),
+            CanAnyFrame::Remote(frame) => frame.set_id(id),
+            CanAnyFrame::Error(frame) => frame.set_id(id),
+            CanAnyFrame::Fd(frame) => frame.set_id(id),
         }
     }
 
     /// Sets the data payload of the frame.
+    ///
+    /// This does not change the type of the frame, so a classic data frame
+    /// can't be given more than 8 bytes.
     fn set_data(&mut self, data: &[u8]) -> Result<(), ConstructionError> {
-        use CanFrame::*;
         match self {
-            Data(frame) => frame.set_data(data),
-            Remote(frame) => frame.set_data(data),
-            Error(frame) => frame.set_data(data),
+            CanAnyFrame::Normal(frame) => frame.set_data(data),
+            CanAnyFrame::Remote(frame) => frame.set_data(data),
+            CanAnyFrame::Error(frame) => frame.set_data(data),
+            CanAnyFrame::Fd(frame) => frame.set_data(data),
         }
     }
 }
 
-impl Default for CanFrame {
-    /// The default frame is a default data frame - all fields and data set
-    /// to zero, and all flags off.
-    fn default() -> Self {
-        CanFrame::Data(CanDataFrame::default())
+impl CanAnyFrame {
+    /// Gets the worst-case number of bits that the frame occupies on the
+    /// wire, including stuff bits and the interframe space.
+    pub fn bit_length(&self) -> usize {
+        match self {
+            CanAnyFrame::Normal(frame) => frame.bit_length(),
+            CanAnyFrame::Remote(frame) => frame.bit_length(),
+            CanAnyFrame::Error(frame) => classic_bit_length(frame.is_extended(), frame.len()),
+            CanAnyFrame::Fd(frame) => frame.bit_length(),
+        }
     }
-}
 
-impl fmt::UpperHex for CanFrame {
-    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        use CanFrame::*;
-        match self {
-            Data(frame) => fmt::UpperHex::fmt(&frame, f),
-            Remote(frame) => fmt::UpperHex::fmt(&frame, f),
-            Error(frame) => fmt::UpperHex::fmt(&frame, f),
+    /// Gets the worst-case time that the frame occupies the bus, at the
+    /// nominal bit rate and, for an FD frame with bit rate switching, the
+    /// data bit rate. Both are in bits per second.
+    ///
+    /// A zero bit rate counts the bits sent at that rate as taking no
+    /// time.
+    pub fn wire_duration(&self, bitrate: u32, data_bitrate: u32) -> Duration {
+        let (nominal, data) = match self {
+            CanAnyFrame::Fd(frame) if frame.is_brs() => {
+                fd_bit_lengths(frame.is_extended(), frame.len())
+            }
+            _ => (self.bit_length(), 0),
+        };
+        let ns = |bits: usize, rate: u32| match rate {
+            0 => 0,
+            rate => bits as u64 * 1_000_000_000 / u64::from(rate),
+        };
+        Duration::from_nanos(ns(nominal, bitrate) + ns(data, data_bitrate))
+    }
+
+    /// Switches the frame between a data and remote frame, keeping the ID
+    /// and DLC.
+    ///
+    /// This works like [`CanFrame::set_rtr`]. FD frames can't be made into
+    /// remote frames, since remote frames don't exist in CAN FD.
+    pub fn set_rtr(&mut self, rtr: bool) -> Result<(), ConstructionError> {
+        use CanAnyFrame::*;
+        match *self {
+            Normal(frame) if rtr => *self = Remote(CanRemoteFrame(switch_rtr(frame.0, true))),
+            Remote(frame) if !rtr => *self = Normal(CanDataFrame(switch_rtr(frame.0, false))),
+            Fd(_) if rtr => return Err(ConstructionError::RemoteNotSupported),
+            Error(_) => return Err(ConstructionError::WrongFrameType),
+            _ => (),
         }
+        Ok(())
     }
 }
 
-impl From<can_frame> for CanFrame {
-    /// Create a `CanFrame` from a C `can_frame` struct.
-    fn from(frame: can_frame) -> Self {
-        if frame.can_id & CAN_ERR_FLAG != 0 {
-            CanFrame::Error(CanErrorFrame(frame))
-        } else if frame.can_id & CAN_RTR_FLAG != 0 {
-            CanFrame::Remote(CanRemoteFrame(frame))
-        } else {
-            CanFrame::Data(CanDataFrame(frame))
+// ===== FdConversion =====
+
+/// How frames are converted between classic and FD.
+///
+/// This is the policy of a gateway route, and of the write path of a
+/// socket, set with [`CanSocket::set_fd_conversion`] or
+/// [`CanFdSocket::set_fd_conversion`].
+///
+/// [`CanSocket::set_fd_conversion`]: crate::CanSocket::set_fd_conversion
+/// [`CanFdSocket::set_fd_conversion`]: crate::CanFdSocket::set_fd_conversion
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FdConversion {
+    /// Leave frames unchanged
+    #[default]
+    Keep,
+    /// Convert FD frames to classic frames, for a classic bus.
+    /// FD frames with more than 8 bytes of data can't be converted.
+    ToClassic,
+    /// Convert classic data frames to FD frames, optionally with the bit
+    /// rate switch flag set. Remote frames are left unchanged.
+    ToFd {
+        /// Whether to set the bit rate switch (BRS) flag
+        brs: bool,
+    },
+}
+
+impl FdConversion {
+    /// Converts the frame according to the policy, or returns `None` if it
+    /// can't be converted.
+    pub fn convert(&self, frame: CanAnyFrame) -> Option<CanAnyFrame> {
+        match (self, frame) {
+            (Self::ToClassic, CanAnyFrame::Fd(fd)) => CanDataFrame::try_new(fd.can_id(), fd.data())
+                .ok()
+                .map(CanAnyFrame::from),
+            (Self::ToFd { brs }, CanAnyFrame::Normal(frame)) => {
+                let flags = if *brs { FdFlags::BRS } else { FdFlags::empty() };
+                CanFdFrame::with_flags(frame.can_id(), frame.data(), flags).map(CanAnyFrame::from)
+            }
+            (_, frame) => Some(frame),
         }
     }
 }
 
-impl From<CanDataFrame> for CanFrame {
-    /// Create a `CanFrame` from a data frame
-    fn from(frame: CanDataFrame) -> Self {
-        Self::Data(frame)
-    }
+// ===== CanEvent =====
+
+/// A frame read from the bus, with error frames split out as their own,
+/// typed case.
+///
+/// When the error filter of a socket is enabled, error frames are read
+/// interleaved with the regular traffic. This separates them so that
+/// a consumer can simply match on the item rather than checking each
+/// frame with `is_error_frame()`.
+///
+/// The `Data` variant holds a data or remote frame, and never an error
+/// frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CanEvent<F> {
+    /// A data or remote frame
+    Data(F),
+    /// An error frame, reported by the controller or driver
+    Error(CanErrorFrame),
 }
 
-impl From<CanRemoteFrame> for CanFrame {
-    /// Create a `CanFrame` from a remote frame
-    fn from(frame: CanRemoteFrame) -> Self {
-        Self::Remote(frame)
+impl<F> CanEvent<F> {
+    /// Determines if this is an error frame.
+    pub fn is_error(&self) -> bool {
+        matches!(self, CanEvent::Error(_))
     }
-}
 
-impl From<CanErrorFrame> for CanFrame {
-    /// Create a `CanFrame` from an error frame
-    fn from(frame: CanErrorFrame) -> Self {
-        Self::Error(frame)
+    /// Gets a reference to the data frame, if this is one.
+    pub fn frame(&self) -> Option<&F> {
+        match self {
+            CanEvent::Data(frame) => Some(frame),
+            CanEvent::Error(_) => None,
+        }
     }
-}
 
-impl AsRef<can_frame> for CanFrame {
-    fn as_ref(&self) -> &can_frame {
-        use CanFrame::*;
+    /// Converts the event into the data frame, if it is one.
+    pub fn into_frame(self) -> Option<F> {
         match self {
-            Data(frame) => frame.as_ref(),
-            Remote(frame) => frame.as_ref(),
-            Error(frame) => frame.as_ref(),
+            CanEvent::Data(frame) => Some(frame),
+            CanEvent::Error(_) => None,
         }
     }
-}
 
-impl TryFrom<CanFdFrame> for CanFrame {
-    type Error = ConstructionError;
+    /// Gets a reference to the error frame, if this is one.
+    pub fn error_frame(&self) -> Option<&CanErrorFrame> {
+        match self {
+            CanEvent::Data(_) => None,
+            CanEvent::Error(frame) => Some(frame),
+        }
+    }
 
-    /// Try to convert a CAN FD frame into a classic CAN 2.0 frame.
+    /// Gets all the errors decoded from an error frame.
     ///
-    /// This should work if it's a data frame with 8 or fewer data bytes.
-    fn try_from(frame: CanFdFrame) -> Result<Self, <Self as TryFrom<CanFdFrame>>::Error> {
-        CanDataFrame::try_from(frame).map(CanFrame::Data)
+    /// This is empty if this is a data frame.
+    pub fn errors(&self) -> Vec<CanError> {
+        self.error_frame()
+            .map(CanErrorFrame::errors)
+            .unwrap_or_default()
     }
 }
 
-// ===== CanDataFrame =====
-
-/// The classic CAN 2.0 frame with up to 8-bytes of data.
-///
-/// This is highly compatible with the `can_frame` from libc.
-/// ([ref](https://docs.rs/libc/latest/libc/struct.can_frame.html))
-#[derive(Clone, Copy)]
-pub struct CanDataFrame(can_frame);
+impl From<CanFrame> for CanEvent<CanFrame> {
+    fn from(frame: CanFrame) -> Self {
+        match frame {
+            CanFrame::Error(frame) => CanEvent::Error(frame),
+            frame => CanEvent::Data(frame),
+        }
+    }
+}
 
-impl CanDataFrame {
-    /// Initializes a CAN data frame from raw parts.
-    pub(crate) fn init(can_id: canid_t, data: &[u8]) -> Result<Self, ConstructionError> {
-        match data.len() {
-            n if n <= CAN_MAX_DLEN => {
-                let mut frame = can_frame_default();
-                frame.can_id = can_id;
-                frame.can_dlc = n as u8;
-                frame.data[..n].copy_from_slice(data);
-                Ok(Self(frame))
-            }
-            _ => Err(ConstructionError::TooMuchData),
+impl From<CanAnyFrame> for CanEvent<CanAnyFrame> {
+    fn from(frame: CanAnyFrame) -> Self {
+        match frame {
+            CanAnyFrame::Error(frame) => CanEvent::Error(frame),
+            frame => CanEvent::Data(frame),
         }
     }
 }
 
-impl AsPtr for CanDataFrame {
+// ===== CanFrame =====
+
+/// The classic CAN 2.0 frame with up to 8-bytes of data.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum CanFrame {
+    /// A data frame
+    Data(CanDataFrame),
+    /// A remote frame
+    Remote(CanRemoteFrame),
+    /// An error frame
+    Error(CanErrorFrame),
+}
+
+impl AsPtr for CanFrame {
     type Inner = can_frame;
 
     /// Gets a pointer to the CAN frame structure that is compatible with
     /// the Linux C API.
     fn as_ptr(&self) -> *const Self::Inner {
-        &self.0
+        use CanFrame::*;
+        match self {
+            Data(frame) => frame.as_ptr(),
+            Remote(frame) => frame.as_ptr(),
+            Error(frame) => frame.as_ptr(),
+        }
     }
 
     /// Gets a mutable pointer to the CAN frame structure that is compatible
     /// with the Linux C API.
     fn as_mut_ptr(&mut self) -> *mut Self::Inner {
-        &mut self.0
+        use CanFrame::*;
+        match self {
+            Data(frame) => frame.as_mut_ptr(),
+            Remote(frame) => frame.as_mut_ptr(),
+            Error(frame) => frame.as_mut_ptr(),
+        }
     }
 }
 
-impl EmbeddedFrame for CanDataFrame {
+impl EmbeddedFrame for CanFrame {
     /// Create a new CAN 2.0 data frame
     fn new(id: impl Into<Id>, data: &[u8]) -> Option<Self> {
-        let can_id = id_to_canid_t(id);
-        Self::init(can_id, data).ok()
+        Self::try_new(id, data).ok()
     }
 
     /// Create a new remote transmission request frame.
-    fn new_remote(_id: impl Into<Id>, _dlc: usize) -> Option<Self> {
-        None
+    fn new_remote(id: impl Into<Id>, dlc: usize) -> Option<Self> {
+        Self::try_new_remote(id, dlc).ok()
     }
 
     /// Check if frame uses 29-bit extended ID format.
     fn is_extended(&self) -> bool {
-        self.id_flags().contains(IdFlags::EFF)
+        use CanFrame::*;
+        match self {
+            Data(frame) => frame.is_extended(),
+            Remote(frame) => frame.is_extended(),
+            Error(frame) => frame.is_extended(),
+        }
     }
 
     /// Check if frame is a remote transmission request.
     fn is_remote_frame(&self) -> bool {
-        false
+        matches!(self, CanFrame::Remote(_))
     }
 
     /// Return the frame identifier.
     fn id(&self) -> Id {
-        self.hal_id()
+        use CanFrame::*;
+        match self {
+            Data(frame) => frame.id(),
+            Remote(frame) => frame.id(),
+            Error(frame) => frame.id(),
+        }
     }
 
     /// Data length
     fn dlc(&self) -> usize {
-        self.0.can_dlc as usize
+        use CanFrame::*;
+        match self {
+            Data(frame) => frame.dlc(),
+            Remote(frame) => frame.dlc(),
+            Error(frame) => frame.dlc(),
+        }
     }
 
     /// A slice into the actual data. Slice will always be <= 8 bytes in length
     fn data(&self) -> &[u8] {
-        &self.0.data[..(self.0.can_dlc as usize)]
+        use CanFrame::*;
+        match self {
+            Data(frame) => frame.data(),
+            Remote(frame) => frame.data(),
+            Error(frame) => frame.data(),
+        }
     }
 }
 
-impl Frame for CanDataFrame {
+impl Frame for CanFrame {
+    /// Create a new CAN 2.0 data frame
+    fn try_new(id: impl Into<Id>, data: &[u8]) -> Result<Self, ConstructionError> {
+        CanDataFrame::try_new(id, data).map(CanFrame::Data)
+    }
+
+    /// Create a new remote transmission request frame.
+    fn try_new_remote(id: impl Into<Id>, dlc: usize) -> Result<Self, ConstructionError> {
+        CanRemoteFrame::try_new_remote(id, dlc).map(CanFrame::Remote)
+    }
+
     /// Get the composite SocketCAN ID word, with EFF/RTR/ERR flags
     fn id_word(&self) -> canid_t {
-        self.0.can_id
+        use CanFrame::*;
+        match self {
+            Data(frame) => frame.id_word(),
+            Remote(frame) => frame.id_word(),
+            Error(frame) => frame.id_word(),
+        }
     }
 
     /// Sets the CAN ID for the frame
     fn set_id(&mut self, id: impl Into<Id>) {
-        self.0.can_id = id_to_canid_t(id);
+        use CanFrame::*;
+        match self {
+            Data(frame) => frame.set_id(id),
+            Remote(frame) => frame.set_id(id),
+            Error(frame) => frame.set_id(id),
+        }
     }
 
     /// Sets the data payload of the frame.
     fn set_data(&mut self, data: &[u8]) -> Result<(), ConstructionError> {
-        match data.len() {
-            n if n <= CAN_MAX_DLEN => {
-                self.0.can_dlc = n as u8;
-                self.0.data[..n].copy_from_slice(data);
-                Ok(())
-            }
-            _ => Err(ConstructionError::TooMuchData),
+        use CanFrame::*;
+        match self {
+            Data(frame) => frame.set_data(data),
+            Remote(frame) => frame.set_data(data),
+            Error(frame) => frame.set_data(data),
         }
     }
 }
 
-impl Default for CanDataFrame {
-    /// The default FD frame has all fields and data set to zero, and all flags off.
-    fn default() -> Self {
-        Self(can_frame_default())
+impl CanFrame {
+    /// Gets the worst-case number of bits that the frame occupies on the
+    /// wire, including stuff bits and the interframe space.
+    ///
+    /// This can be used to compute the bus load, or budget transmissions.
+    pub fn bit_length(&self) -> usize {
+        use CanFrame::*;
+        match self {
+            Data(frame) => frame.bit_length(),
+            Remote(frame) => frame.bit_length(),
+            Error(frame) => classic_bit_length(frame.is_extended(), frame.len()),
+        }
     }
-}
 
-impl fmt::Debug for CanDataFrame {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "CanDataFrame {{ ")?;
-        fmt::UpperHex::fmt(self, f)?;
-        write!(f, " }}")
+    /// Switches the frame between a data and remote frame, keeping the ID
+    /// and DLC.
+    ///
+    /// When a data frame becomes a remote frame, it requests the number
+    /// of bytes in the data, and the payload is dropped. When a remote frame
+    /// becomes a data frame, it gets the requested number of bytes, all
+    /// zero. Error frames can't be switched.
+    pub fn set_rtr(&mut self, rtr: bool) -> Result<(), ConstructionError> {
+        use CanFrame::*;
+        match *self {
+            Data(frame) if rtr => *self = Remote(CanRemoteFrame(switch_rtr(frame.0, true))),
+            Remote(frame) if !rtr => *self = Data(CanDataFrame(switch_rtr(frame.0, false))),
+            Error(_) => return Err(ConstructionError::WrongFrameType),
+            _ => (),
+        }
+        Ok(())
     }
-}
 
-impl fmt::UpperHex for CanDataFrame {
-    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        write!(f, "{:X}#", self.0.can_id)?;
-        let mut parts = self.data().iter().map(|v| format!("{:02X}", v));
-        write!(f, "{}", parts.join(" "))
+    /// Creates a data frame in a const context.
+    ///
+    /// This allows static message tables to be kept as const data. The ID
+    /// is standard if it fits into 11 bits, otherwise it's extended. This
+    /// panics if the ID is larger than 29 bits or the data is longer
+    /// than 8 bytes, which fails the build when used for a const item:
+    ///
+    /// ```
+    /// use socketcan::{CanFrame, EmbeddedFrame};
+    /// const HEARTBEAT: CanFrame = CanFrame::new_const(0x700, &[0x05]);
+    /// assert_eq!(HEARTBEAT.data(), &[0x05]);
+    /// ```
+    pub const fn new_const(id: u32, data: &[u8]) -> Self {
+        CanFrame::Data(CanDataFrame::new_const(id, data))
     }
-}
 
-impl TryFrom<can_frame> for CanDataFrame {
-    type Error = ConstructionError;
+    /// Gets the frame as the raw bytes of the C `can_frame` structure.
+    ///
+    /// This is the exact layout used by the kernel, so it can be copied
+    /// directly into a ring buffer, shared memory, or network tunnel.
+    pub fn as_bytes(&self) -> &[u8] {
+        AsPtr::as_bytes(self)
+    }
 
-    /// Try to create a `CanDataFrame` from a C `can_frame`
+    /// Creates a frame from the raw bytes of a C `can_frame` structure.
     ///
-    /// This will succeed as long as the C frame is not marked as an error
-    /// or remote frame.
-    fn try_from(frame: can_frame) -> Result<Self, Self::Error> {
-        if frame.can_id & (CAN_ERR_FLAG | CAN_RTR_FLAG) == 0 {
-            Ok(Self(frame))
-        } else {
-            Err(ConstructionError::WrongFrameType)
+    /// The buffer must be exactly `CAN_MTU` bytes long, and hold a valid
+    /// payload length.
+    pub fn try_from_bytes(buf: &[u8]) -> Result<Self, ConstructionError> {
+        let mut frame = can_frame_default();
+        copy_frame_bytes(&mut frame, buf)?;
+        if frame.can_dlc as usize > CAN_MAX_DLEN {
+            return Err(ConstructionError::TooMuchData {
+                len: frame.can_dlc as usize,
+                max: CAN_MAX_DLEN,
+            });
         }
+        Ok(frame.into())
     }
 }
 
-impl TryFrom<CanFdFrame> for CanDataFrame {
-    type Error = ConstructionError;
+impl Default for CanFrame {
+    /// The default frame is a default data frame - all fields and data set
+    /// to zero, and all flags off.
+    fn default() -> Self {
+        CanFrame::Data(CanDataFrame::default())
+    }
+}
 
-    fn try_from(frame: CanFdFrame) -> Result<Self, Self::Error> {
-        if frame.len() > CAN_MAX_DLEN {
-            return Err(ConstructionError::TooMuchData);
+impl fmt::Display for CanFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use CanFrame::*;
+        match self {
+            Data(frame) => fmt::Display::fmt(frame, f),
+            Remote(frame) => fmt::Display::fmt(frame, f),
+            Error(frame) => fmt::Display::fmt(frame, f),
         }
-
-        CanDataFrame::init(frame.id_word(), &frame.data()[..(frame.0.len as usize)])
     }
 }
 
-impl AsRef<can_frame> for CanDataFrame {
-    fn as_ref(&self) -> &can_frame {
-        &self.0
+impl fmt::UpperHex for CanFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use CanFrame::*;
+        match self {
+            Data(frame) => fmt::UpperHex::fmt(frame, f),
+            Remote(frame) => fmt::UpperHex::fmt(frame, f),
+            Error(frame) => fmt::UpperHex::fmt(frame, f),
+        }
     }
 }
 
-// ===== CanRemoteFrame =====
+impl fmt::LowerHex for CanFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use CanFrame::*;
+        match self {
+            Data(frame) => fmt::LowerHex::fmt(frame, f),
+            Remote(frame) => fmt::LowerHex::fmt(frame, f),
+            Error(frame) => fmt::LowerHex::fmt(frame, f),
+        }
+    }
+}
 
-/// The classic CAN 2.0 remote request frame.
-///
-/// This is is meant to request a transmission by another node on the bus.
-/// It contain no data.
-///
-/// This is highly compatible with the `can_frame` from libc.
-/// ([ref](https://docs.rs/libc/latest/libc/struct.can_frame.html))
-#[derive(Clone, Copy)]
-pub struct CanRemoteFrame(can_frame);
-
-impl CanRemoteFrame {
-    /// Sets the data length code for the frame
-    pub fn set_dlc(&mut self, dlc: usize) -> Result<(), ConstructionError> {
-        if dlc <= CAN_MAX_DLEN {
-            self.0.can_dlc = dlc as u8;
-            Ok(())
+impl From<can_frame> for CanFrame {
+    /// Create a `CanFrame` from a C `can_frame` struct.
+    fn from(frame: can_frame) -> Self {
+        if frame.can_id & CAN_ERR_FLAG != 0 {
+            CanFrame::Error(CanErrorFrame(frame))
+        } else if frame.can_id & CAN_RTR_FLAG != 0 {
+            CanFrame::Remote(CanRemoteFrame(frame))
         } else {
-            Err(ConstructionError::TooMuchData)
+            CanFrame::Data(CanDataFrame(frame))
         }
     }
 }
 
-impl AsPtr for CanRemoteFrame {
-    type Inner = can_frame;
-
-    /// Gets a pointer to the CAN frame structure that is compatible with
-    /// the Linux C API.
-    fn as_ptr(&self) -> *const Self::Inner {
-        &self.0
-    }
-
-    /// Gets a mutable pointer to the CAN frame structure that is compatible
-    /// with the Linux C API.
-    fn as_mut_ptr(&mut self) -> *mut Self::Inner {
-        &mut self.0
+impl From<CanDataFrame> for CanFrame {
+    /// Create a `CanFrame` from a data frame
+    fn from(frame: CanDataFrame) -> Self {
+        Self::Data(frame)
     }
 }
 
-impl EmbeddedFrame for CanRemoteFrame {
-    /// Create a new CAN 2.0 remote frame
-    fn new(id: impl Into<Id>, data: &[u8]) -> Option<Self> {
-        Self::new_remote(id, data.len())
-    }
-
-    /// Create a new remote transmission request frame.
-    ///
-    /// This will set the RTR flag in the CAN ID word.
-    fn new_remote(id: impl Into<Id>, dlc: usize) -> Option<Self> {
-        if dlc <= CAN_MAX_DLEN {
-            let mut frame = can_frame_default();
-            frame.can_id = id_to_canid_t(id) | CAN_RTR_FLAG;
-            frame.can_dlc = dlc as u8;
-            Some(Self(frame))
-        } else {
-            None
-        }
-    }
-
-    /// Check if frame uses 29-bit extended ID format.
-    fn is_extended(&self) -> bool {
-        self.id_flags().contains(IdFlags::EFF)
-    }
-
-    /// Check if frame is a remote transmission request.
-    fn is_remote_frame(&self) -> bool {
-        true
-    }
-
-    /// Return the frame identifier.
-    fn id(&self) -> Id {
-        self.hal_id()
-    }
-
-    /// Data length code
-    fn dlc(&self) -> usize {
-        self.0.can_dlc as usize
-    }
-
-    /// A slice into the actual data. Slice will always be <= 8 bytes in length
-    fn data(&self) -> &[u8] {
-        // TODO: Is this OK, or just an empty slice?
-        &self.0.data[..self.dlc()]
+impl From<CanRemoteFrame> for CanFrame {
+    /// Create a `CanFrame` from a remote frame
+    fn from(frame: CanRemoteFrame) -> Self {
+        Self::Remote(frame)
     }
 }
 
-impl Frame for CanRemoteFrame {
-    /// Get the composite SocketCAN ID word, with EFF/RTR/ERR flags
-    fn id_word(&self) -> canid_t {
-        self.0.can_id
-    }
-
-    /// Sets the CAN ID for the frame.
-    ///
-    /// This will set the RTR flag in the CAN ID word.
-    fn set_id(&mut self, id: impl Into<Id>) {
-        self.0.can_id = id_to_canid_t(id) | CAN_RTR_FLAG;
-    }
-
-    /// Sets the data payload of the frame.
-    /// For the Remote frame, this just updates the DLC to the length of the
-    /// data slice.
-    fn set_data(&mut self, data: &[u8]) -> Result<(), ConstructionError> {
-        self.set_dlc(data.len())
+impl From<CanErrorFrame> for CanFrame {
+    /// Create a `CanFrame` from an error frame
+    fn from(frame: CanErrorFrame) -> Self {
+        Self::Error(frame)
     }
 }
 
-impl Default for CanRemoteFrame {
-    /// The default remote frame has all fields and data set to zero, and all flags off.
-    fn default() -> Self {
-        let mut frame = can_frame_default();
-        frame.can_id |= CAN_RTR_FLAG;
-        Self(frame)
+impl AsRef<can_frame> for CanFrame {
+    fn as_ref(&self) -> &can_frame {
+        use CanFrame::*;
+        match self {
+            Data(frame) => frame.as_ref(),
+            Remote(frame) => frame.as_ref(),
+            Error(frame) => frame.as_ref(),
+        }
     }
 }
 
-impl fmt::Debug for CanRemoteFrame {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "CanRemoteFrame {{ ")?;
-        fmt::UpperHex::fmt(self, f)?;
-        write!(f, " }}")
-    }
-}
+impl TryFrom<CanFdFrame> for CanFrame {
+    type Error = ConstructionError;
 
-impl fmt::UpperHex for CanRemoteFrame {
-    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        write!(f, "{:X}#", self.0.can_id)?;
-        let mut parts = self.data().iter().map(|v| format!("{:02X}", v));
-        write!(f, "{}", parts.join(" "))
+    /// Try to convert a CAN FD frame into a classic CAN 2.0 frame.
+    ///
+    /// This should work if it's a data frame with 8 or fewer data bytes.
+    fn try_from(frame: CanFdFrame) -> Result<Self, <Self as TryFrom<CanFdFrame>>::Error> {
+        CanDataFrame::try_from(frame).map(CanFrame::Data)
     }
 }
 
-impl TryFrom<can_frame> for CanRemoteFrame {
+impl TryFrom<CanAnyFrame> for CanFrame {
     type Error = ConstructionError;
 
-    /// Try to create a `CanRemoteFrame` from a C `can_frame`
+    /// Try to convert any frame into a classic CAN 2.0 frame.
     ///
-    /// This will only succeed the C frame is marked as a remote frame.
-    fn try_from(frame: can_frame) -> Result<Self, Self::Error> {
-        if frame.can_id & CAN_RTR_FLAG != 0 {
-            Ok(Self(frame))
-        } else {
-            Err(ConstructionError::WrongFrameType)
+    /// This works for any classic frame, or for an FD frame with 8 or
+    /// fewer data bytes.
+    fn try_from(frame: CanAnyFrame) -> Result<Self, <Self as TryFrom<CanAnyFrame>>::Error> {
+        match frame {
+            CanAnyFrame::Normal(frame) => Ok(CanFrame::Data(frame)),
+            CanAnyFrame::Remote(frame) => Ok(CanFrame::Remote(frame)),
+            CanAnyFrame::Error(frame) => Ok(CanFrame::Error(frame)),
+            CanAnyFrame::Fd(frame) => CanFrame::try_from(frame),
         }
     }
 }
 
-impl AsRef<can_frame> for CanRemoteFrame {
-    fn as_ref(&self) -> &can_frame {
-        &self.0
-    }
-}
-
-// ===== CanErrorFrame =====
+// ===== CanDataFrame =====
 
-/// A SocketCAN error frame.
-///
-/// This is returned from a read/receive by the OS or interface device
-/// driver when it detects an error, such as a problem on the bus. The
-/// frame encodes detailed information about the error, which can be
-/// managed directly by the application or converted into a Rust error
+/// The classic CAN 2.0 frame with up to 8-bytes of data.
 ///
 /// This is highly compatible with the `can_frame` from libc.
 /// ([ref](https://docs.rs/libc/latest/libc/struct.can_frame.html))
 #[derive(Clone, Copy)]
-pub struct CanErrorFrame(can_frame);
+pub struct CanDataFrame(can_frame);
 
-impl CanErrorFrame {
-    /// Creates a CAN error frame from raw parts.
-    ///
-    /// Note that an application would not normally _ever_ create an error
-    /// frame. This is included mainly to aid in implementing mocks and other
-    /// tests for an application.
+impl CanDataFrame {
+    /// Gets the worst-case number of bits that the frame occupies on the
+    /// wire, including stuff bits and the interframe space.
+    pub fn bit_length(&self) -> usize {
+        classic_bit_length(self.is_extended(), self.len())
+    }
+
+    /// Creates a data frame in a const context.
     ///
-    /// The data byte slice should have the necessary codes for the supplied
-    /// error. They will be zero padded to a full frame of 8 bytes.
+    /// The ID is standard if it fits into 11 bits, otherwise it's extended.
+    /// This panics if the ID is larger than 29 bits or the data is longer
+    /// than 8 bytes, which fails the build when used for a const item:
     ///
-    /// Also note:
-    /// - The error flag is forced on
-    /// - The other, non-error, flags are forced off
-    /// - The frame data is always padded with zero's to 8 bytes,
-    ///   regardless of the length of the `data` parameter provided.
-    pub fn new_error(can_id: canid_t, data: &[u8]) -> Result<Self, ConstructionError> {
+    /// ```
+    /// use socketcan::CanDataFrame;
+    /// const HEARTBEAT: CanDataFrame = CanDataFrame::new_const(0x705, &[0x05]);
+    /// ```
+    pub const fn new_const(id: u32, data: &[u8]) -> Self {
+        Self(const_can_frame(const_canid(id), data))
+    }
+
+    /// Initializes a CAN data frame from raw parts.
+    pub(crate) fn init(can_id: canid_t, data: &[u8]) -> Result<Self, ConstructionError> {
         match data.len() {
             n if n <= CAN_MAX_DLEN => {
                 let mut frame = can_frame_default();
-                frame.can_id = (can_id & CAN_ERR_MASK) | CAN_ERR_FLAG;
-                frame.can_dlc = CAN_MAX_DLEN as u8;
+                frame.can_id = can_id;
+                frame.can_dlc = n as u8;
                 frame.data[..n].copy_from_slice(data);
                 Ok(Self(frame))
             }
-            _ => Err(ConstructionError::TooMuchData),
+            len => Err(ConstructionError::TooMuchData {
+                len,
+                max: CAN_MAX_DLEN,
+            }),
         }
     }
 
-    /// Return the error bits from the ID word of the error frame.
-    pub fn error_bits(&self) -> u32 {
-        self.id_word() & CAN_ERR_MASK
+    /// Gets a mutable slice to the data payload of the frame.
+    ///
+    /// This allows the payload to be updated in place, without changing
+    /// its length.
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        &mut self.0.data[..(self.0.can_dlc as usize)]
     }
 
-    /// Converts this error frame into a `CanError`
-    pub fn into_error(self) -> CanError {
-        CanError::from(self)
+    /// Gets the raw DLC, 9-15, that was received on the wire for this
+    /// 8-byte frame, if any.
+    ///
+    /// This requires the `CAN_CTRLMODE_CC_LEN8_DLC` control mode to be
+    /// enabled on the interface (see `CanCtrlMode::CcLen8Dlc`).
+    pub fn len8_dlc(&self) -> Option<u8> {
+        len8_dlc(&self.0)
+    }
+
+    /// Sets the raw DLC, 9-15, to send on the wire for this 8-byte frame.
+    ///
+    /// A value of 8 or less clears it. It's an error to set a value of
+    /// 9-15 unless the frame is 8 bytes long.
+    /// This requires the `CAN_CTRLMODE_CC_LEN8_DLC` control mode to be
+    /// enabled on the interface for the value to be sent.
+    pub fn set_len8_dlc(&mut self, dlc: u8) -> Result<(), ConstructionError> {
+        set_len8_dlc(&mut self.0, dlc)
+    }
+
+    /// Gets the DLC as sent on the wire, 0-15.
+    ///
+    /// This is the same as the length, unless a raw DLC of 9-15 is set.
+    pub fn raw_dlc(&self) -> u8 {
+        self.len8_dlc().unwrap_or(self.0.can_dlc)
+    }
+
+    /// Shortens the payload to `len` bytes.
+    ///
+    /// This has no effect if `len` is greater than or equal to the current
+    /// length. The bytes removed from the payload are cleared to zero.
+    pub fn truncate(&mut self, len: usize) {
+        let n = self.0.can_dlc as usize;
+        if len < n {
+            self.0.data[len..n].fill(0);
+            self.0.can_dlc = len as u8;
+            self.0.len8_dlc = 0;
+        }
+    }
+
+    /// Extends the payload to `len` bytes, filling the new bytes with
+    /// the `pad` value.
+    ///
+    /// This has no effect if the payload is already at least `len` bytes.
+    pub fn pad_to(&mut self, len: usize, pad: u8) -> Result<(), ConstructionError> {
+        if len > CAN_MAX_DLEN {
+            return Err(ConstructionError::TooMuchData {
+                len,
+                max: CAN_MAX_DLEN,
+            });
+        }
+        let n = self.0.can_dlc as usize;
+        if len > n {
+            self.0.data[n..len].fill(pad);
+            self.0.can_dlc = len as u8;
+        }
+        Ok(())
     }
 }
 
-impl AsPtr for CanErrorFrame {
+impl AsPtr for CanDataFrame {
     type Inner = can_frame;
 
     /// Gets a pointer to the CAN frame structure that is compatible with
@@ -932,21 +1650,13 @@ impl AsPtr for CanErrorFrame {
     }
 }
 
-impl EmbeddedFrame for CanErrorFrame {
-    /// Create an error frame.
-    ///
-    /// Note that an application would not normally _ever_ create an error
-    /// frame. This is included mainly to aid in implementing mocks and other
-    /// tests for an application.
-    ///
-    /// This will set the error bit in the CAN ID word.
+impl EmbeddedFrame for CanDataFrame {
+    /// Create a new CAN 2.0 data frame
     fn new(id: impl Into<Id>, data: &[u8]) -> Option<Self> {
-        let can_id = id_to_canid_t(id);
-        Self::new_error(can_id, data).ok()
+        Self::try_new(id, data).ok()
     }
 
-    /// The application should not create an error frame.
-    /// This will always return None.
+    /// Create a new remote transmission request frame.
     fn new_remote(_id: impl Into<Id>, _dlc: usize) -> Option<Self> {
         None
     }
@@ -961,69 +1671,134 @@ impl EmbeddedFrame for CanErrorFrame {
         false
     }
 
-    /// Check if frame is a data frame.
-    fn is_data_frame(&self) -> bool {
-        false
-    }
-
     /// Return the frame identifier.
     fn id(&self) -> Id {
         self.hal_id()
     }
 
-    /// Data length code
+    /// Data length
     fn dlc(&self) -> usize {
         self.0.can_dlc as usize
     }
 
-    /// A slice into the actual data.
-    /// An error frame can always acess the full 8-byte data payload.
+    /// A slice into the actual data. Slice will always be <= 8 bytes in length
     fn data(&self) -> &[u8] {
-        &self.0.data[..]
+        &self.0.data[..(self.0.can_dlc as usize)]
     }
 }
 
-impl Frame for CanErrorFrame {
+impl Frame for CanDataFrame {
+    /// Create a new CAN 2.0 data frame
+    fn try_new(id: impl Into<Id>, data: &[u8]) -> Result<Self, ConstructionError> {
+        Self::init(id_to_canid_t(id), data)
+    }
+
+    /// A data frame can't be a remote frame.
+    fn try_new_remote(_id: impl Into<Id>, _dlc: usize) -> Result<Self, ConstructionError> {
+        Err(ConstructionError::RemoteNotSupported)
+    }
+
     /// Get the composite SocketCAN ID word, with EFF/RTR/ERR flags
     fn id_word(&self) -> canid_t {
         self.0.can_id
     }
 
     /// Sets the CAN ID for the frame
-    /// This does nothing on an error frame.
-    fn set_id(&mut self, _id: impl Into<Id>) {}
+    fn set_id(&mut self, id: impl Into<Id>) {
+        self.0.can_id = id_to_canid_t(id);
+    }
 
     /// Sets the data payload of the frame.
-    /// This is an error on an error frame.
-    fn set_data(&mut self, _data: &[u8]) -> Result<(), ConstructionError> {
-        Err(ConstructionError::WrongFrameType)
+    fn set_data(&mut self, data: &[u8]) -> Result<(), ConstructionError> {
+        match data.len() {
+            n if n <= CAN_MAX_DLEN => {
+                if n < CAN_MAX_DLEN {
+                    self.0.len8_dlc = 0;
+                }
+                self.0.can_dlc = n as u8;
+                self.0.data[..n].copy_from_slice(data);
+                Ok(())
+            }
+            len => Err(ConstructionError::TooMuchData {
+                len,
+                max: CAN_MAX_DLEN,
+            }),
+        }
     }
 }
 
-impl fmt::Debug for CanErrorFrame {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "CanErrorFrame {{ ")?;
-        fmt::UpperHex::fmt(self, f)?;
-        write!(f, " }}")
+impl PartialEq for CanDataFrame {
+    /// Data frames are equal if they have the same ID word, DLC, and
+    /// payload. Any unused bytes past the payload are ignored.
+    fn eq(&self, other: &Self) -> bool {
+        self.0.can_id == other.0.can_id
+            && self.raw_dlc() == other.raw_dlc()
+            && self.data() == other.data()
     }
 }
 
-impl fmt::UpperHex for CanErrorFrame {
-    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        write!(f, "{:X}#", self.0.can_id)?;
-        let mut parts = self.data().iter().map(|v| format!("{:02X}", v));
-        write!(f, "{}", parts.join(" "))
+impl Eq for CanDataFrame {}
+
+impl Hash for CanDataFrame {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.can_id.hash(state);
+        self.raw_dlc().hash(state);
+        self.data().hash(state);
     }
 }
 
-impl TryFrom<can_frame> for CanErrorFrame {
-    type Error = ConstructionError;
-
-    /// Try to create a `CanErrorFrame` from a C `can_frame`
+impl Default for CanDataFrame {
+    /// The default FD frame has all fields and data set to zero, and all flags off.
+    fn default() -> Self {
+        Self(can_frame_default())
+    }
+}
+
+impl fmt::Debug for CanDataFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "CanDataFrame {{ ")?;
+        fmt::Display::fmt(self, f)?;
+        write!(f, " }}")
+    }
+}
+
+impl CanDataFrame {
+    /// Writes the frame as text in the candump format, with the style.
+    fn fmt_text(&self, f: &mut fmt::Formatter, style: TextStyle) -> fmt::Result {
+        fmt_candump_id(f, self.0.can_id, style)?;
+        f.write_str("#")?;
+        fmt_candump_data(f, self.data(), style)
+    }
+}
+
+impl fmt::Display for CanDataFrame {
+    /// Formats the frame in the candump format, like `123#DEADBEEF`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_text(f, TextStyle::display(f))
+    }
+}
+
+impl fmt::UpperHex for CanDataFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_text(f, TextStyle::hex(f, true))
+    }
+}
+
+impl fmt::LowerHex for CanDataFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_text(f, TextStyle::hex(f, false))
+    }
+}
+
+impl TryFrom<can_frame> for CanDataFrame {
+    type Error = ConstructionError;
+
+    /// Try to create a `CanDataFrame` from a C `can_frame`
     ///
-    /// This will only succeed the C frame is marked as an error frame.
+    /// This will succeed as long as the C frame is not marked as an error
+    /// or remote frame.
     fn try_from(frame: can_frame) -> Result<Self, Self::Error> {
-        if frame.can_id & CAN_ERR_FLAG != 0 {
+        if frame.can_id & (CAN_ERR_FLAG | CAN_RTR_FLAG) == 0 {
             Ok(Self(frame))
         } else {
             Err(ConstructionError::WrongFrameType)
@@ -1031,242 +1806,1857 @@ impl TryFrom<can_frame> for CanErrorFrame {
     }
 }
 
-impl From<CanError> for CanErrorFrame {
-    fn from(err: CanError) -> Self {
-        use CanError::*;
+impl TryFrom<CanFdFrame> for CanDataFrame {
+    type Error = ConstructionError;
 
-        let mut data = [0u8; CAN_MAX_DLEN];
-        let id: canid_t = match err {
-            TransmitTimeout => 0x0001,
-            LostArbitration(bit) => {
-                data[0] = bit;
-                0x0002
-            }
-            ControllerProblem(prob) => {
-                data[1] = prob as u8;
-                0x0004
-            }
-            ProtocolViolation { vtype, location } => {
-                data[2] = vtype as u8;
-                data[3] = location as u8;
-                0x0008
-            }
-            TransceiverError => 0x0010,
-            NoAck => 0x0020,
-            BusOff => 0x0040,
-            BusError => 0x0080,
-            Restarted => 0x0100,
-            DecodingFailure(_failure) => 0,
-            Unknown(e) => e,
-        };
-        Self::new_error(id, &data).unwrap()
+    fn try_from(frame: CanFdFrame) -> Result<Self, Self::Error> {
+        if frame.len() > CAN_MAX_DLEN {
+            return Err(ConstructionError::TooMuchData {
+                len: frame.len(),
+                max: CAN_MAX_DLEN,
+            });
+        }
+
+        CanDataFrame::init(frame.id_word(), &frame.data()[..(frame.0.len as usize)])
     }
 }
 
-impl AsRef<can_frame> for CanErrorFrame {
+impl AsRef<can_frame> for CanDataFrame {
     fn as_ref(&self) -> &can_frame {
         &self.0
     }
 }
 
-// ===== CanFdFrame =====
+// ===== CanRemoteFrame =====
 
-/// The CAN flexible data rate frame with up to 64-bytes of data.
+/// The classic CAN 2.0 remote request frame.
 ///
-/// This is highly compatible with the `canfd_frame` from libc.
-/// ([ref](https://docs.rs/libc/latest/libc/struct.canfd_frame.html))
+/// This is is meant to request a transmission by another node on the bus.
+/// It contain no data.
+///
+/// This is highly compatible with the `can_frame` from libc.
+/// ([ref](https://docs.rs/libc/latest/libc/struct.can_frame.html))
 #[derive(Clone, Copy)]
-pub struct CanFdFrame(canfd_frame);
+pub struct CanRemoteFrame(can_frame);
 
-impl CanFdFrame {
-    /// Create a new FD frame with FD flags
-    pub fn with_flags(id: impl Into<Id>, data: &[u8], flags: FdFlags) -> Option<Self> {
-        let can_id = id_to_canid_t(id);
-        Self::init(can_id, data, flags).ok()
+impl CanRemoteFrame {
+    /// Gets the worst-case number of bits that the frame occupies on the
+    /// wire, including stuff bits and the interframe space.
+    ///
+    /// A remote frame doesn't carry any data, regardless of its DLC.
+    pub fn bit_length(&self) -> usize {
+        classic_bit_length(self.is_extended(), 0)
     }
 
-    /// Initialize a FD frame from the raw components.
-    pub(crate) fn init(
-        can_id: u32,
-        data: &[u8],
-        fd_flags: FdFlags,
-    ) -> Result<Self, ConstructionError> {
-        match data.len() {
-            n if n <= CANFD_MAX_DLEN => {
-                let mut frame = canfd_frame_default();
-                frame.can_id = can_id;
-                frame.len = n as u8;
-                frame.flags = fd_flags.bits();
-                frame.data[..n].copy_from_slice(data);
-                Ok(Self(frame))
-            }
-            _ => Err(ConstructionError::TooMuchData),
+    /// Creates a remote frame requesting the specified raw DLC, 0-15.
+    ///
+    /// A DLC of 9-15 requests 8 bytes of data, but is sent on the wire as
+    /// the given value. This requires the `CAN_CTRLMODE_CC_LEN8_DLC`
+    /// control mode to be enabled on the interface.
+    pub fn with_raw_dlc(id: impl Into<Id>, dlc: u8) -> Result<Self, ConstructionError> {
+        let mut frame = Self::try_new_remote(id, (dlc as usize).min(CAN_MAX_DLEN))?;
+        frame.set_len8_dlc(dlc)?;
+        Ok(frame)
+    }
+
+    /// Gets the number of data bytes being requested by the remote frame.
+    ///
+    /// This is the DLC of the frame, 0-8.
+    pub fn requested_len(&self) -> usize {
+        self.0.can_dlc as usize
+    }
+
+    /// Sets the data length code for the frame
+    ///
+    /// This clears any raw DLC.
+    pub fn set_dlc(&mut self, dlc: usize) -> Result<(), ConstructionError> {
+        if dlc <= CAN_MAX_DLEN {
+            self.0.can_dlc = dlc as u8;
+            self.0.len8_dlc = 0;
+            Ok(())
+        } else {
+            Err(ConstructionError::TooMuchData {
+                len: dlc,
+                max: CAN_MAX_DLEN,
+            })
         }
     }
 
-    /// Gets the flags for the FD frame.
+    /// Gets the raw DLC, 9-15, that was received on the wire for this
+    /// 8-byte request, if any.
     ///
-    /// These are the bits from the separate FD frame flags, not the flags
-    /// in the composite ID word.
-    pub fn flags(&self) -> FdFlags {
-        FdFlags::from_bits_truncate(self.0.flags)
+    /// This requires the `CAN_CTRLMODE_CC_LEN8_DLC` control mode to be
+    /// enabled on the interface (see `CanCtrlMode::CcLen8Dlc`).
+    pub fn len8_dlc(&self) -> Option<u8> {
+        len8_dlc(&self.0)
     }
 
-    /// Whether the frame uses a bit rate switch (second bit rate for
-    /// payload data).
-    pub fn is_brs(&self) -> bool {
-        self.flags().contains(FdFlags::BRS)
+    /// Sets the raw DLC, 9-15, to send on the wire for this 8-byte request.
+    ///
+    /// A value of 8 or less clears it. It's an error to set a value of
+    /// 9-15 unless the DLC is 8.
+    /// This requires the `CAN_CTRLMODE_CC_LEN8_DLC` control mode to be
+    /// enabled on the interface for the value to be sent.
+    pub fn set_len8_dlc(&mut self, dlc: u8) -> Result<(), ConstructionError> {
+        set_len8_dlc(&mut self.0, dlc)
     }
 
-    /// Sets whether the frame uses a bit rate switch.
-    pub fn set_brs(&mut self, on: bool) {
-        if on {
-            self.0.flags |= CANFD_BRS as u8;
-        } else {
-            self.0.flags &= !(CANFD_BRS as u8);
-        }
+    /// Gets the DLC as sent on the wire, 0-15.
+    ///
+    /// This is the same as the length, unless a raw DLC of 9-15 is set.
+    pub fn raw_dlc(&self) -> u8 {
+        self.len8_dlc().unwrap_or(self.0.can_dlc)
     }
+}
 
-    /// Gets the error state indicator of the transmitting node
-    pub fn is_esi(&self) -> bool {
-        self.flags().contains(FdFlags::ESI)
+impl AsPtr for CanRemoteFrame {
+    type Inner = can_frame;
+
+    /// Gets a pointer to the CAN frame structure that is compatible with
+    /// the Linux C API.
+    fn as_ptr(&self) -> *const Self::Inner {
+        &self.0
     }
 
-    /// Sets the error state indicator of the transmitting node
-    pub fn set_esi(&mut self, on: bool) {
-        if on {
-            self.0.flags |= CANFD_ESI as u8;
-        } else {
-            self.0.flags &= !CANFD_ESI as u8;
+    /// Gets a mutable pointer to the CAN frame structure that is compatible
+    /// with the Linux C API.
+    fn as_mut_ptr(&mut self) -> *mut Self::Inner {
+        &mut self.0
+    }
+}
+
+impl EmbeddedFrame for CanRemoteFrame {
+    /// Create a new CAN 2.0 remote frame
+    fn new(id: impl Into<Id>, data: &[u8]) -> Option<Self> {
+        Self::new_remote(id, data.len())
+    }
+
+    /// Create a new remote transmission request frame.
+    ///
+    /// This will set the RTR flag in the CAN ID word.
+    fn new_remote(id: impl Into<Id>, dlc: usize) -> Option<Self> {
+        Self::try_new_remote(id, dlc).ok()
+    }
+
+    /// Check if frame uses 29-bit extended ID format.
+    fn is_extended(&self) -> bool {
+        self.id_flags().contains(IdFlags::EFF)
+    }
+
+    /// Check if frame is a remote transmission request.
+    fn is_remote_frame(&self) -> bool {
+        true
+    }
+
+    /// Return the frame identifier.
+    fn id(&self) -> Id {
+        self.hal_id()
+    }
+
+    /// Data length code
+    fn dlc(&self) -> usize {
+        self.0.can_dlc as usize
+    }
+
+    /// A slice into the actual data. Slice will always be <= 8 bytes in length
+    ///
+    /// A remote frame carries no data, so this is always an empty slice.
+    /// The length of the data being requested is the DLC; see
+    /// [`CanRemoteFrame::requested_len`].
+    fn data(&self) -> &[u8] {
+        &[]
+    }
+}
+
+impl Frame for CanRemoteFrame {
+    /// Create a new CAN 2.0 remote frame, with the DLC taken from the
+    /// length of the data.
+    fn try_new(id: impl Into<Id>, data: &[u8]) -> Result<Self, ConstructionError> {
+        Self::try_new_remote(id, data.len())
+    }
+
+    /// Create a new remote transmission request frame.
+    ///
+    /// This will set the RTR flag in the CAN ID word.
+    fn try_new_remote(id: impl Into<Id>, dlc: usize) -> Result<Self, ConstructionError> {
+        let mut frame = Self::default();
+        frame.0.can_id = id_to_canid_t(id) | CAN_RTR_FLAG;
+        frame.set_dlc(dlc)?;
+        Ok(frame)
+    }
+
+    /// Get the composite SocketCAN ID word, with EFF/RTR/ERR flags
+    fn id_word(&self) -> canid_t {
+        self.0.can_id
+    }
+
+    /// Sets the CAN ID for the frame.
+    ///
+    /// This will set the RTR flag in the CAN ID word.
+    fn set_id(&mut self, id: impl Into<Id>) {
+        self.0.can_id = id_to_canid_t(id) | CAN_RTR_FLAG;
+    }
+
+    /// Sets the data payload of the frame.
+    /// For the Remote frame, this just updates the DLC to the length of the
+    /// data slice.
+    fn set_data(&mut self, data: &[u8]) -> Result<(), ConstructionError> {
+        self.set_dlc(data.len())
+    }
+}
+
+impl PartialEq for CanRemoteFrame {
+    /// Remote frames are equal if they have the same ID word and DLC.
+    fn eq(&self, other: &Self) -> bool {
+        self.0.can_id == other.0.can_id && self.raw_dlc() == other.raw_dlc()
+    }
+}
+
+impl Eq for CanRemoteFrame {}
+
+impl Hash for CanRemoteFrame {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.can_id.hash(state);
+        self.raw_dlc().hash(state);
+    }
+}
+
+impl Default for CanRemoteFrame {
+    /// The default remote frame has all fields and data set to zero, and all flags off.
+    fn default() -> Self {
+        let mut frame = can_frame_default();
+        frame.can_id |= CAN_RTR_FLAG;
+        Self(frame)
+    }
+}
+
+impl fmt::Debug for CanRemoteFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "CanRemoteFrame {{ ")?;
+        fmt::Display::fmt(self, f)?;
+        write!(f, " }}")
+    }
+}
+
+impl CanRemoteFrame {
+    /// Writes the frame as text in the candump format, with the style.
+    fn fmt_text(&self, f: &mut fmt::Formatter, style: TextStyle) -> fmt::Result {
+        fmt_candump_id(f, self.0.can_id, style)?;
+        f.write_str("#R")?;
+        match self.dlc() {
+            0 => Ok(()),
+            n => style.write_hex(f, n as u32, 1),
         }
     }
 }
 
-impl AsPtr for CanFdFrame {
-    type Inner = canfd_frame;
+impl fmt::Display for CanRemoteFrame {
+    /// Formats the frame in the candump format, like `123#R`.
+    ///
+    /// A non-zero DLC is appended as a single digit, like `123#R4`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_text(f, TextStyle::display(f))
+    }
+}
+
+impl fmt::UpperHex for CanRemoteFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_text(f, TextStyle::hex(f, true))
+    }
+}
+
+impl fmt::LowerHex for CanRemoteFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_text(f, TextStyle::hex(f, false))
+    }
+}
+
+impl TryFrom<can_frame> for CanRemoteFrame {
+    type Error = ConstructionError;
+
+    /// Try to create a `CanRemoteFrame` from a C `can_frame`
+    ///
+    /// This will only succeed the C frame is marked as a remote frame.
+    fn try_from(frame: can_frame) -> Result<Self, Self::Error> {
+        if frame.can_id & CAN_RTR_FLAG != 0 {
+            Ok(Self(frame))
+        } else {
+            Err(ConstructionError::WrongFrameType)
+        }
+    }
+}
+
+impl AsRef<can_frame> for CanRemoteFrame {
+    fn as_ref(&self) -> &can_frame {
+        &self.0
+    }
+}
+
+// ===== CanErrorFrame =====
+
+/// A SocketCAN error frame.
+///
+/// This is returned from a read/receive by the OS or interface device
+/// driver when it detects an error, such as a problem on the bus. The
+/// frame encodes detailed information about the error, which can be
+/// managed directly by the application or converted into a Rust error
+///
+/// This is highly compatible with the `can_frame` from libc.
+/// ([ref](https://docs.rs/libc/latest/libc/struct.can_frame.html))
+#[derive(Clone, Copy)]
+pub struct CanErrorFrame(can_frame);
+
+impl CanErrorFrame {
+    /// Creates a CAN error frame from raw parts.
+    ///
+    /// Note that an application would not normally _ever_ create an error
+    /// frame. This is included mainly to aid in implementing mocks and other
+    /// tests for an application.
+    ///
+    /// The data byte slice should have the necessary codes for the supplied
+    /// error. They will be zero padded to a full frame of 8 bytes.
+    ///
+    /// Also note:
+    /// - The error flag is forced on
+    /// - The other, non-error, flags are forced off
+    /// - The frame data is always padded with zero's to 8 bytes,
+    ///   regardless of the length of the `data` parameter provided.
+    pub fn new_error(can_id: canid_t, data: &[u8]) -> Result<Self, ConstructionError> {
+        match data.len() {
+            n if n <= CAN_MAX_DLEN => {
+                let mut frame = can_frame_default();
+                frame.can_id = (can_id & CAN_ERR_MASK) | CAN_ERR_FLAG;
+                frame.can_dlc = CAN_MAX_DLEN as u8;
+                frame.data[..n].copy_from_slice(data);
+                Ok(Self(frame))
+            }
+            len => Err(ConstructionError::TooMuchData {
+                len,
+                max: CAN_MAX_DLEN,
+            }),
+        }
+    }
+
+    /// Creates an error frame that reports the specified error.
+    ///
+    /// This is the inverse of [`CanErrorFrame::into_error`], and allows a
+    /// simulator to inject synthetic error conditions into consumers. A
+    /// `DecodingFailure` can't be encoded, and creates a frame with no error
+    /// bits set.
+    pub fn new(err: CanError) -> Self {
+        use CanError::*;
+
+        let mut data = [0u8; CAN_MAX_DLEN];
+        let id: canid_t = match err {
+            TransmitTimeout => 0x0001,
+            LostArbitration(bit) => {
+                data[0] = bit;
+                0x0002
+            }
+            ControllerProblem(prob) => {
+                data[1] = prob as u8;
+                0x0004
+            }
+            ProtocolViolation { vtype, location } => {
+                data[2] = vtype as u8;
+                data[3] = location as u8;
+                0x0008
+            }
+            TransceiverError => 0x0010,
+            NoAck => 0x0020,
+            BusOff => 0x0040,
+            BusError => 0x0080,
+            Restarted => 0x0100,
+            DecodingFailure(_failure) => 0,
+            Unknown(e) => e,
+        };
+        // Safe unwrap: the data fits in a classic frame
+        Self::new_error(id, &data).unwrap()
+    }
+
+    /// Return the error bits from the ID word of the error frame.
+    pub fn error_bits(&self) -> u32 {
+        self.id_word() & CAN_ERR_MASK
+    }
+
+    /// Converts this error frame into a `CanError`
+    pub fn into_error(self) -> CanError {
+        CanError::from(self)
+    }
+
+    /// Decodes all of the errors reported in this frame, most significant
+    /// first.
+    ///
+    /// See [`CanError::decode_all`].
+    pub fn errors(&self) -> Vec<CanError> {
+        CanError::decode_all(self)
+    }
+
+    /// Determines if the specified error bit(s) are set in the ID word.
+    fn has_error_bits(&self, bits: u32) -> bool {
+        self.error_bits() & bits != 0
+    }
+
+    /// Gets the bit position at which arbitration was lost, if this frame
+    /// reports lost arbitration.
+    ///
+    /// A value of zero means that the position is unspecified.
+    pub fn lost_arbitration_bit(&self) -> Option<u8> {
+        self.has_error_bits(errors::CAN_ERR_LOSTARB)
+            .then_some(self.0.data[0])
+    }
+
+    /// Gets the controller problems, if this frame reports any.
+    pub fn controller_problems(
+        &self,
+    ) -> Option<Result<Vec<ControllerProblem>, CanErrorDecodingFailure>> {
+        self.has_error_bits(errors::CAN_ERR_CRTL)
+            .then(|| ControllerProblem::from_bits(self.0.data[1]))
+    }
+
+    /// Gets the types and location of a protocol violation, if this frame
+    /// reports one.
+    #[allow(clippy::type_complexity)]
+    pub fn protocol_violation(
+        &self,
+    ) -> Option<Result<(Vec<ViolationType>, Location), CanErrorDecodingFailure>> {
+        self.has_error_bits(errors::CAN_ERR_PROT).then(|| {
+            Ok((
+                ViolationType::from_bits(self.0.data[2])?,
+                Location::try_from(self.0.data[3])?,
+            ))
+        })
+    }
+
+    /// Gets the transceiver status, if this frame reports a transceiver
+    /// error.
+    pub fn transceiver_errors(
+        &self,
+    ) -> Option<Result<Vec<TransceiverError>, CanErrorDecodingFailure>> {
+        self.has_error_bits(errors::CAN_ERR_TRX)
+            .then(|| TransceiverError::from_bits(self.0.data[4]))
+    }
+
+    /// Determines if this frame reports that no ACK was received.
+    pub fn is_no_ack(&self) -> bool {
+        self.has_error_bits(errors::CAN_ERR_ACK)
+    }
+
+    /// Determines if this frame reports that the controller went bus-off.
+    pub fn is_bus_off(&self) -> bool {
+        self.has_error_bits(errors::CAN_ERR_BUSOFF)
+    }
+
+    /// Determines if this frame reports a bus error.
+    pub fn is_bus_error(&self) -> bool {
+        self.has_error_bits(errors::CAN_ERR_BUSERROR)
+    }
+
+    /// Determines if this frame reports that the controller restarted.
+    pub fn is_restarted(&self) -> bool {
+        self.has_error_bits(errors::CAN_ERR_RESTARTED)
+    }
+
+    /// Gets the controller-specific error information from `data[5]`.
+    pub fn controller_specific(&self) -> u8 {
+        self.0.data[5]
+    }
+
+    /// Gets the controller's TX and RX error counters, as `(tx, rx)`.
+    ///
+    /// These are carried in `data[6]` and `data[7]`, but are only valid if
+    /// the driver set the error counter bit (`CAN_ERR_CNT`) in the ID word.
+    pub fn error_counters(&self) -> Option<(u8, u8)> {
+        self.has_error_bits(errors::CAN_ERR_CNT)
+            .then_some((self.0.data[6], self.0.data[7]))
+    }
+
+    /// Gets the controller's TX error counter, if it was reported.
+    pub fn tx_error_counter(&self) -> Option<u8> {
+        self.error_counters().map(|(tx, _)| tx)
+    }
+
+    /// Gets the controller's RX error counter, if it was reported.
+    pub fn rx_error_counter(&self) -> Option<u8> {
+        self.error_counters().map(|(_, rx)| rx)
+    }
+}
+
+impl AsPtr for CanErrorFrame {
+    type Inner = can_frame;
+
+    /// Gets a pointer to the CAN frame structure that is compatible with
+    /// the Linux C API.
+    fn as_ptr(&self) -> *const Self::Inner {
+        &self.0
+    }
+
+    /// Gets a mutable pointer to the CAN frame structure that is compatible
+    /// with the Linux C API.
+    fn as_mut_ptr(&mut self) -> *mut Self::Inner {
+        &mut self.0
+    }
+}
+
+impl EmbeddedFrame for CanErrorFrame {
+    /// Create an error frame.
+    ///
+    /// Note that an application would not normally _ever_ create an error
+    /// frame. This is included mainly to aid in implementing mocks and other
+    /// tests for an application.
+    ///
+    /// This will set the error bit in the CAN ID word.
+    fn new(id: impl Into<Id>, data: &[u8]) -> Option<Self> {
+        Self::try_new(id, data).ok()
+    }
+
+    /// The application should not create an error frame.
+    /// This will always return None.
+    fn new_remote(_id: impl Into<Id>, _dlc: usize) -> Option<Self> {
+        None
+    }
+
+    /// Check if frame uses 29-bit extended ID format.
+    fn is_extended(&self) -> bool {
+        self.id_flags().contains(IdFlags::EFF)
+    }
+
+    /// Check if frame is a remote transmission request.
+    fn is_remote_frame(&self) -> bool {
+        false
+    }
+
+    /// Check if frame is a data frame.
+    fn is_data_frame(&self) -> bool {
+        false
+    }
+
+    /// Return the frame identifier.
+    fn id(&self) -> Id {
+        self.hal_id()
+    }
+
+    /// Data length code
+    fn dlc(&self) -> usize {
+        self.0.can_dlc as usize
+    }
+
+    /// A slice into the actual data.
+    /// An error frame can always acess the full 8-byte data payload.
+    fn data(&self) -> &[u8] {
+        &self.0.data[..]
+    }
+}
+
+impl Frame for CanErrorFrame {
+    /// Create a new error frame using the supplied error code as the ID.
+    ///
+    /// This will set the error bit in the CAN ID word.
+    fn try_new(id: impl Into<Id>, data: &[u8]) -> Result<Self, ConstructionError> {
+        Self::new_error(id_to_canid_t(id), data)
+    }
+
+    /// An error frame can't be a remote frame.
+    fn try_new_remote(_id: impl Into<Id>, _dlc: usize) -> Result<Self, ConstructionError> {
+        Err(ConstructionError::RemoteNotSupported)
+    }
+
+    /// Get the composite SocketCAN ID word, with EFF/RTR/ERR flags
+    fn id_word(&self) -> canid_t {
+        self.0.can_id
+    }
+
+    /// Sets the CAN ID for the frame
+    /// This does nothing on an error frame.
+    fn set_id(&mut self, _id: impl Into<Id>) {}
+
+    /// Sets the data payload of the frame.
+    /// This is an error on an error frame.
+    fn set_data(&mut self, _data: &[u8]) -> Result<(), ConstructionError> {
+        Err(ConstructionError::WrongFrameType)
+    }
+}
+
+impl PartialEq for CanErrorFrame {
+    /// Error frames are equal if they have the same error bits and data.
+    fn eq(&self, other: &Self) -> bool {
+        self.0.can_id == other.0.can_id && self.0.data == other.0.data
+    }
+}
+
+impl Eq for CanErrorFrame {}
+
+impl Hash for CanErrorFrame {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.can_id.hash(state);
+        self.0.data.hash(state);
+    }
+}
+
+impl fmt::Debug for CanErrorFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "CanErrorFrame {{ ")?;
+        fmt::Display::fmt(self, f)?;
+        write!(f, " }}")
+    }
+}
+
+impl CanErrorFrame {
+    /// Writes the frame as text in the candump format, with the style.
+    fn fmt_text(&self, f: &mut fmt::Formatter, style: TextStyle) -> fmt::Result {
+        fmt_candump_id(f, self.0.can_id, style)?;
+        f.write_str("#")?;
+        fmt_candump_data(f, self.data(), style)
+    }
+}
+
+impl fmt::Display for CanErrorFrame {
+    /// Formats the frame in the candump format, like
+    /// `20000004#0004000000000000`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_text(f, TextStyle::display(f))
+    }
+}
+
+impl fmt::UpperHex for CanErrorFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_text(f, TextStyle::hex(f, true))
+    }
+}
+
+impl fmt::LowerHex for CanErrorFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_text(f, TextStyle::hex(f, false))
+    }
+}
+
+impl From<CanError> for CanFrame {
+    /// Creates an error frame reporting the error.
+    fn from(err: CanError) -> Self {
+        CanFrame::Error(CanErrorFrame::new(err))
+    }
+}
+
+impl TryFrom<can_frame> for CanErrorFrame {
+    type Error = ConstructionError;
+
+    /// Try to create a `CanErrorFrame` from a C `can_frame`
+    ///
+    /// This will only succeed the C frame is marked as an error frame.
+    fn try_from(frame: can_frame) -> Result<Self, Self::Error> {
+        if frame.can_id & CAN_ERR_FLAG != 0 {
+            Ok(Self(frame))
+        } else {
+            Err(ConstructionError::WrongFrameType)
+        }
+    }
+}
+
+impl From<CanError> for CanErrorFrame {
+    fn from(err: CanError) -> Self {
+        Self::new(err)
+    }
+}
+
+impl AsRef<can_frame> for CanErrorFrame {
+    fn as_ref(&self) -> &can_frame {
+        &self.0
+    }
+}
+
+// ===== CanFdFrame =====
+
+/// The CAN flexible data rate frame with up to 64-bytes of data.
+///
+/// This is highly compatible with the `canfd_frame` from libc.
+/// ([ref](https://docs.rs/libc/latest/libc/struct.canfd_frame.html))
+#[derive(Clone, Copy)]
+pub struct CanFdFrame(canfd_frame);
+
+impl CanFdFrame {
+    /// Gets the worst-case number of bits that the frame occupies on the
+    /// wire, including stuff bits and the interframe space.
+    ///
+    /// If the frame uses bit rate switching, use [`fd_bit_lengths`] to
+    /// get the bits at each rate.
+    pub fn bit_length(&self) -> usize {
+        fd_bit_length(self.is_extended(), self.len())
+    }
+
+    /// Gets the frame as the raw bytes of the C `canfd_frame` structure.
+    ///
+    /// This is the exact layout used by the kernel, so it can be copied
+    /// directly into a ring buffer, shared memory, or network tunnel.
+    pub fn as_bytes(&self) -> &[u8] {
+        AsPtr::as_bytes(self)
+    }
+
+    /// Creates a frame from the raw bytes of a C `canfd_frame` structure.
+    ///
+    /// The buffer must be exactly `CANFD_MTU` bytes long, and hold a valid
+    /// payload length: one of the lengths that an FD DLC can encode.
+    pub fn try_from_bytes(buf: &[u8]) -> Result<Self, ConstructionError> {
+        let mut frame = canfd_frame_default();
+        copy_frame_bytes(&mut frame, buf)?;
+        let len = frame.len as usize;
+        if len > CANFD_MAX_DLEN {
+            return Err(ConstructionError::TooMuchData {
+                len,
+                max: CANFD_MAX_DLEN,
+            });
+        }
+        if !is_valid_fd_len(len) {
+            return Err(ConstructionError::InvalidLength { len });
+        }
+        Ok(frame.into())
+    }
+
+    /// Create a new FD frame with FD flags
+    pub fn with_flags(id: impl Into<Id>, data: &[u8], flags: FdFlags) -> Option<Self> {
+        Self::try_with_flags(id, data, flags).ok()
+    }
+
+    /// Create a new FD frame with FD flags, or report why it can't be
+    /// created.
+    pub fn try_with_flags(
+        id: impl Into<Id>,
+        data: &[u8],
+        flags: FdFlags,
+    ) -> Result<Self, ConstructionError> {
+        Self::init(id_to_canid_t(id), data, flags)
+    }
+
+    /// Gets a mutable slice to the data payload of the frame.
+    ///
+    /// This allows the payload to be updated in place, without changing
+    /// its length.
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        &mut self.0.data[..(self.0.len as usize)]
+    }
+
+    /// Shortens the payload to `len` bytes.
+    ///
+    /// This has no effect if `len` is greater than or equal to the current
+    /// length. The bytes removed from the payload are cleared to zero.
+    ///
+    /// If `len` is not a legal FD payload length, the frame keeps the
+    /// next larger legal length, with the bytes after `len` cleared.
+    pub fn truncate(&mut self, len: usize) {
+        let n = self.0.len as usize;
+        if len < n {
+            self.0.data[len..n].fill(0);
+            self.0.len = fd_padded_len(len).unwrap_or(len) as u8;
+        }
+    }
+
+    /// Extends the payload to `len` bytes, filling the new bytes with
+    /// the `pad` value.
+    ///
+    /// If `len` is not a legal FD payload length, the payload is padded
+    /// up to the next larger legal length.
+    /// This has no effect if the payload is already at least `len` bytes.
+    pub fn pad_to(&mut self, len: usize, pad: u8) -> Result<(), ConstructionError> {
+        let len = fd_padded_len(len).ok_or(ConstructionError::TooMuchData {
+            len,
+            max: CANFD_MAX_DLEN,
+        })?;
+        let n = self.0.len as usize;
+        if len > n {
+            self.0.data[n..len].fill(pad);
+            self.0.len = len as u8;
+        }
+        Ok(())
+    }
+
+    /// Initialize a FD frame from the raw components.
+    pub(crate) fn init(
+        can_id: u32,
+        data: &[u8],
+        fd_flags: FdFlags,
+    ) -> Result<Self, ConstructionError> {
+        let mut frame = Self(canfd_frame_default());
+        frame.0.can_id = can_id;
+        frame.0.flags = fd_flags.bits();
+        frame.set_data(data)?;
+        Ok(frame)
+    }
+
+    /// Gets the flags for the FD frame.
+    ///
+    /// These are the bits from the separate FD frame flags, not the flags
+    /// in the composite ID word.
+    pub fn flags(&self) -> FdFlags {
+        FdFlags::from_bits_truncate(self.0.flags)
+    }
+
+    /// Whether the frame uses a bit rate switch (second bit rate for
+    /// payload data).
+    pub fn is_brs(&self) -> bool {
+        self.flags().contains(FdFlags::BRS)
+    }
+
+    /// Sets whether the frame uses a bit rate switch.
+    pub fn set_brs(&mut self, on: bool) {
+        if on {
+            self.0.flags |= CANFD_BRS as u8;
+        } else {
+            self.0.flags &= !(CANFD_BRS as u8);
+        }
+    }
+
+    /// Gets the error state indicator of the transmitting node
+    pub fn is_esi(&self) -> bool {
+        self.flags().contains(FdFlags::ESI)
+    }
+
+    /// Sets the error state indicator of the transmitting node
+    pub fn set_esi(&mut self, on: bool) {
+        if on {
+            self.0.flags |= CANFD_ESI as u8;
+        } else {
+            self.0.flags &= !CANFD_ESI as u8;
+        }
+    }
+
+    /// Whether the frame has the FD frame (FDF) flag set.
+    ///
+    /// Newer kernels set this on all FD frames they receive, to distinguish
+    /// them from classic frames in the same structure. It's optional when
+    /// sending.
+    pub fn is_fdf(&self) -> bool {
+        self.flags().contains(FdFlags::FDF)
+    }
+
+    /// Sets the FD frame (FDF) flag.
+    pub fn set_fdf(&mut self, on: bool) {
+        if on {
+            self.0.flags |= CANFD_FDF as u8;
+        } else {
+            self.0.flags &= !(CANFD_FDF as u8);
+        }
+    }
+}
+
+impl AsPtr for CanFdFrame {
+    type Inner = canfd_frame;
+
+    /// Gets a pointer to the CAN frame structure that is compatible with
+    /// the Linux C API.
+    fn as_ptr(&self) -> *const Self::Inner {
+        &self.0
+    }
+
+    /// Gets a mutable pointer to the CAN frame structure that is compatible
+    /// with the Linux C API.
+    fn as_mut_ptr(&mut self) -> *mut Self::Inner {
+        &mut self.0
+    }
+}
+
+impl EmbeddedFrame for CanFdFrame {
+    /// Create a new FD frame
+    fn new(id: impl Into<Id>, data: &[u8]) -> Option<Self> {
+        Self::try_new(id, data).ok()
+    }
+
+    /// CAN FD frames don't support remote
+    fn new_remote(_id: impl Into<Id>, _dlc: usize) -> Option<Self> {
+        None
+    }
+
+    /// Check if frame uses 29-bit extended ID format.
+    fn is_extended(&self) -> bool {
+        self.id_flags().contains(IdFlags::EFF)
+    }
+
+    /// The FD frames don't support remote request
+    fn is_remote_frame(&self) -> bool {
+        false
+    }
+
+    /// Return the frame identifier.
+    fn id(&self) -> Id {
+        self.hal_id()
+    }
+
+    /// Data length code
+    ///
+    /// For FD frames, this is the DLC code, 0-15, and not the number of
+    /// bytes in the payload. Use `len()` to get the byte count.
+    fn dlc(&self) -> usize {
+        fd_len_to_dlc(self.0.len as usize).unwrap_or(15) as usize
+    }
+
+    /// A slice into the actual data.
+    ///
+    /// For normal CAN frames the slice will always be <= 8 bytes in length.
+    fn data(&self) -> &[u8] {
+        &self.0.data[..(self.0.len as usize)]
+    }
+}
+
+impl Frame for CanFdFrame {
+    /// Create a new FD frame
+    fn try_new(id: impl Into<Id>, data: &[u8]) -> Result<Self, ConstructionError> {
+        Self::try_with_flags(id, data, FdFlags::empty())
+    }
+
+    /// CAN FD frames don't support remote
+    fn try_new_remote(_id: impl Into<Id>, _dlc: usize) -> Result<Self, ConstructionError> {
+        Err(ConstructionError::RemoteNotSupported)
+    }
+
+    /// Get the composite SocketCAN ID word, with EFF/RTR/ERR flags
+    fn id_word(&self) -> canid_t {
+        self.0.can_id
+    }
+
+    /// Sets the CAN ID for the frame
+    fn set_id(&mut self, id: impl Into<Id>) {
+        self.0.can_id = id_to_canid_t(id);
+    }
+
+    /// Get the number of bytes in the payload.
+    fn len(&self) -> usize {
+        self.0.len as usize
+    }
+
+    /// Sets the data payload of the frame.
+    ///
+    /// If the length of the data is not one of the legal FD payload
+    /// lengths, it is padded with zeros up to the next larger one.
+    fn set_data(&mut self, data: &[u8]) -> Result<(), ConstructionError> {
+        let n = data.len();
+        let len = fd_padded_len(n).ok_or(ConstructionError::TooMuchData {
+            len: n,
+            max: CANFD_MAX_DLEN,
+        })?;
+        self.0.data[..n].copy_from_slice(data);
+        self.0.data[n..len].fill(0);
+        self.0.len = len as u8;
+        Ok(())
+    }
+}
+
+impl PartialEq for CanFdFrame {
+    /// FD frames are equal if they have the same ID word, FD flags, and
+    /// payload. Any unused bytes past the payload are ignored.
+    fn eq(&self, other: &Self) -> bool {
+        self.0.can_id == other.0.can_id
+            && self.0.flags == other.0.flags
+            && self.data() == other.data()
+    }
+}
+
+impl Eq for CanFdFrame {}
+
+impl Hash for CanFdFrame {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.can_id.hash(state);
+        self.0.flags.hash(state);
+        self.data().hash(state);
+    }
+}
+
+impl Default for CanFdFrame {
+    /// The default FD frame has all fields and data set to zero, and all flags off.
+    fn default() -> Self {
+        Self(canfd_frame_default())
+    }
+}
+
+impl fmt::Debug for CanFdFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "CanFdFrame {{ ")?;
+        fmt::Display::fmt(self, f)?;
+        write!(f, " }}")
+    }
+}
+
+impl CanFdFrame {
+    /// Writes the frame as text in the candump format, with the style.
+    fn fmt_text(&self, f: &mut fmt::Formatter, style: TextStyle) -> fmt::Result {
+        fmt_candump_id(f, self.0.can_id, style)?;
+        f.write_str("##")?;
+        style.write_hex(f, u32::from(self.0.flags & 0x0F), 1)?;
+        fmt_candump_data(f, self.data(), style)
+    }
+}
+
+impl fmt::Display for CanFdFrame {
+    /// Formats the frame in the candump format, like `123##1DEADBEEF`,
+    /// where the digit after the double separator holds the FD flags.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_text(f, TextStyle::display(f))
+    }
+}
+
+impl fmt::UpperHex for CanFdFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_text(f, TextStyle::hex(f, true))
+    }
+}
+
+impl fmt::LowerHex for CanFdFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_text(f, TextStyle::hex(f, false))
+    }
+}
+
+impl From<CanDataFrame> for CanFdFrame {
+    fn from(frame: CanDataFrame) -> Self {
+        let n = frame.dlc();
+
+        let mut fdframe = canfd_frame_default();
+        fdframe.can_id = frame.id_word();
+        fdframe.len = n as u8;
+        fdframe.data[..n].copy_from_slice(&frame.data()[..n]);
+        Self(fdframe)
+    }
+}
+
+impl TryFrom<CanAnyFrame> for CanFdFrame {
+    type Error = ConstructionError;
+
+    /// Try to convert any frame into an FD frame.
+    ///
+    /// This works for FD frames and classic data frames. Remote and error
+    /// frames can't be represented as FD frames.
+    fn try_from(frame: CanAnyFrame) -> Result<Self, Self::Error> {
+        match frame {
+            CanAnyFrame::Normal(frame) => Ok(frame.into()),
+            CanAnyFrame::Fd(frame) => Ok(frame),
+            CanAnyFrame::Remote(_) | CanAnyFrame::Error(_) => {
+                Err(ConstructionError::WrongFrameType)
+            }
+        }
+    }
+}
+
+impl From<canfd_frame> for CanFdFrame {
+    fn from(frame: canfd_frame) -> Self {
+        Self(frame)
+    }
+}
+
+impl AsRef<canfd_frame> for CanFdFrame {
+    fn as_ref(&self) -> &canfd_frame {
+        &self.0
+    }
+}
+
+// ===== CanXlFrame =====
+
+/// The bit position of the virtual CAN network ID (VCID) in the priority
+/// field of an XL frame.
+const CANXL_VCID_OFFSET: u32 = 16;
+
+/// A CAN XL frame, with up to 2048 bytes of data.
+///
+/// Rather than an ID, an XL frame has an 11-bit priority that is used for
+/// bus arbitration, along with a service data unit type (SDT), and a 32-bit
+/// acceptance field (AF) which can carry a higher-layer address.
+///
+/// This is highly compatible with the `canxl_frame` from libc.
+/// Note that this is a large type, and so it is not `Copy`.
+#[derive(Clone)]
+pub struct CanXlFrame(canxl_frame);
+
+impl CanXlFrame {
+    /// Creates a new XL frame.
+    ///
+    /// The `prio` is the 11-bit priority, and the data must be 1-2048
+    /// bytes long.
+    pub fn new(prio: u16, sdt: u8, af: u32, data: &[u8]) -> Result<Self, ConstructionError> {
+        let mut frame = Self(canxl_frame_default());
+        frame.set_priority(prio)?;
+        frame.0.flags = CANXL_XLF as u8;
+        frame.0.sdt = sdt;
+        frame.0.af = af;
+        frame.set_data(data)?;
+        Ok(frame)
+    }
+
+    /// Gets the 11-bit priority of the frame, which is used for bus
+    /// arbitration, like the ID of a classic frame.
+    pub fn priority(&self) -> u16 {
+        (self.0.prio & CANXL_PRIO_MASK) as u16
+    }
+
+    /// Sets the 11-bit priority of the frame.
+    pub fn set_priority(&mut self, prio: u16) -> Result<(), ConstructionError> {
+        let id = u32::from(prio);
+        if id > CANXL_PRIO_MASK {
+            return Err(ConstructionError::IDTooLarge {
+                id,
+                extended: false,
+            });
+        }
+        self.0.prio = (self.0.prio & !CANXL_PRIO_MASK) | id;
+        Ok(())
+    }
+
+    /// Gets the virtual CAN network ID (VCID).
+    ///
+    /// This is only sent or received on sockets with the VCID options
+    /// enabled, otherwise it's zero.
+    pub fn vcid(&self) -> u8 {
+        (self.0.prio >> CANXL_VCID_OFFSET) as u8
+    }
+
+    /// Sets the virtual CAN network ID (VCID).
+    pub fn set_vcid(&mut self, vcid: u8) {
+        self.0.prio = (self.0.prio & CANXL_PRIO_MASK) | (u32::from(vcid) << CANXL_VCID_OFFSET);
+    }
+
+    /// Gets the flags for the XL frame.
+    pub fn flags(&self) -> XlFlags {
+        XlFlags::from_bits_truncate(self.0.flags)
+    }
+
+    /// Whether the frame has the simple extended content (SEC) flag set.
+    pub fn is_sec(&self) -> bool {
+        self.flags().contains(XlFlags::SEC)
+    }
+
+    /// Sets the simple extended content (SEC) flag.
+    pub fn set_sec(&mut self, on: bool) {
+        if on {
+            self.0.flags |= CANXL_SEC as u8;
+        } else {
+            self.0.flags &= !(CANXL_SEC as u8);
+        }
+    }
+
+    /// Gets the service data unit (SDU) type.
+    pub fn sdt(&self) -> u8 {
+        self.0.sdt
+    }
+
+    /// Sets the service data unit (SDU) type.
+    pub fn set_sdt(&mut self, sdt: u8) {
+        self.0.sdt = sdt;
+    }
+
+    /// Gets the acceptance field.
+    pub fn af(&self) -> u32 {
+        self.0.af
+    }
+
+    /// Sets the acceptance field.
+    pub fn set_af(&mut self, af: u32) {
+        self.0.af = af;
+    }
+
+    /// Gets the number of bytes in the payload.
+    pub fn len(&self) -> usize {
+        self.0.len as usize
+    }
+
+    /// Determines if the payload is empty.
+    ///
+    /// A valid XL frame always has at least one byte of data, so this
+    /// is only true for a default, uninitialized frame.
+    pub fn is_empty(&self) -> bool {
+        self.0.len == 0
+    }
+
+    /// Gets the data payload of the frame.
+    pub fn data(&self) -> &[u8] {
+        &self.0.data[..self.len()]
+    }
+
+    /// Sets the data payload of the frame.
+    ///
+    /// The data must be 1-2048 bytes long.
+    pub fn set_data(&mut self, data: &[u8]) -> Result<(), ConstructionError> {
+        match data.len() {
+            n if n < CANXL_MIN_DLEN => Err(ConstructionError::TooLittleData {
+                len: n,
+                min: CANXL_MIN_DLEN,
+            }),
+            n if n <= CANXL_MAX_DLEN => {
+                self.0.data[..n].copy_from_slice(data);
+                self.0.len = n as u16;
+                Ok(())
+            }
+            len => Err(ConstructionError::TooMuchData {
+                len,
+                max: CANXL_MAX_DLEN,
+            }),
+        }
+    }
+}
+
+impl Default for CanXlFrame {
+    /// The default XL frame has all fields and data set to zero, other
+    /// than the XLF flag.
+    fn default() -> Self {
+        let mut frame = canxl_frame_default();
+        frame.flags = CANXL_XLF as u8;
+        Self(frame)
+    }
+}
+
+impl AsPtr for CanXlFrame {
+    type Inner = canxl_frame;
+
+    /// Gets a pointer to the CAN frame structure that is compatible with
+    /// the Linux C API.
+    fn as_ptr(&self) -> *const Self::Inner {
+        &self.0
+    }
+
+    /// Gets a mutable pointer to the CAN frame structure that is compatible
+    /// with the Linux C API.
+    fn as_mut_ptr(&mut self) -> *mut Self::Inner {
+        &mut self.0
+    }
+
+    /// The size of the frame on the wire.
+    ///
+    /// The kernel expects an XL frame to be written as the header followed
+    /// by just the used part of the data.
+    fn size(&self) -> usize {
+        CANXL_HDR_SIZE + self.len()
+    }
+}
+
+impl PartialEq for CanXlFrame {
+    /// XL frames are equal if all the header fields and payload are equal.
+    fn eq(&self, other: &Self) -> bool {
+        self.0.prio == other.0.prio
+            && self.0.flags == other.0.flags
+            && self.0.sdt == other.0.sdt
+            && self.0.af == other.0.af
+            && self.data() == other.data()
+    }
+}
+
+impl Eq for CanXlFrame {}
+
+impl Hash for CanXlFrame {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.prio.hash(state);
+        self.0.flags.hash(state);
+        self.0.sdt.hash(state);
+        self.0.af.hash(state);
+        self.data().hash(state);
+    }
+}
+
+impl fmt::Debug for CanXlFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "CanXlFrame {{ ")?;
+        fmt::Display::fmt(self, f)?;
+        write!(f, " }}")
+    }
+}
+
+impl fmt::Display for CanXlFrame {
+    /// Formats the frame in the candump XL format, like
+    /// `242#81:00:12345678#1122334455`, with the VCID (if any) and the
+    /// priority, followed by the flags, SDT, and AF, then the data.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.vcid() != 0 {
+            write!(f, "{:02X}", self.vcid())?;
+        }
+        write!(
+            f,
+            "{:03X}#{:02X}:{:02X}:{:08X}#",
+            self.priority(),
+            self.0.flags,
+            self.0.sdt,
+            self.0.af
+        )?;
+        write_hex_bytes(f, self.data(), true, None)
+    }
+}
+
+impl TryFrom<canxl_frame> for CanXlFrame {
+    type Error = ConstructionError;
+
+    /// Takes a C `canxl_frame`, such as one read from a socket, checking
+    /// that its length is valid for an XL frame.
+    fn try_from(frame: canxl_frame) -> Result<Self, Self::Error> {
+        match usize::from(frame.len) {
+            len if len < CANXL_MIN_DLEN => Err(ConstructionError::TooLittleData {
+                len,
+                min: CANXL_MIN_DLEN,
+            }),
+            len if len > CANXL_MAX_DLEN => Err(ConstructionError::TooMuchData {
+                len,
+                max: CANXL_MAX_DLEN,
+            }),
+            _ => Ok(Self(frame)),
+        }
+    }
+}
+
+impl AsRef<canxl_frame> for CanXlFrame {
+    fn as_ref(&self) -> &canxl_frame {
+        &self.0
+    }
+}
+
+/// Any frame that can be read from an XL socket.
+///
+/// The XL frame is boxed to keep this type small, since classic and FD
+/// frames are usually more common on the bus.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum CanXlAnyFrame {
+    /// A classic CAN 2.0 or FD frame
+    Can(CanAnyFrame),
+    /// A CAN XL frame
+    Xl(Box<CanXlFrame>),
+}
+
+impl fmt::Display for CanXlAnyFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Can(frame) => fmt::Display::fmt(frame, f),
+            Self::Xl(frame) => fmt::Display::fmt(frame, f),
+        }
+    }
+}
+
+impl<T: Into<CanAnyFrame>> From<T> for CanXlAnyFrame {
+    fn from(frame: T) -> Self {
+        Self::Can(frame.into())
+    }
+}
+
+impl From<CanXlFrame> for CanXlAnyFrame {
+    fn from(frame: CanXlFrame) -> Self {
+        Self::Xl(Box::new(frame))
+    }
+}
+
+impl AsPtr for CanXlAnyFrame {
+    type Inner = c_void;
+
+    fn as_ptr(&self) -> *const Self::Inner {
+        match self {
+            Self::Can(frame) => frame.as_ptr(),
+            Self::Xl(frame) => frame.as_ptr() as *const Self::Inner,
+        }
+    }
+
+    fn as_mut_ptr(&mut self) -> *mut Self::Inner {
+        match self {
+            Self::Can(frame) => frame.as_mut_ptr(),
+            Self::Xl(frame) => frame.as_mut_ptr() as *mut Self::Inner,
+        }
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            Self::Can(frame) => frame.size(),
+            Self::Xl(frame) => frame.size(),
+        }
+    }
+}
+
+// ===== Builders =====
+
+/// The data payload held by a builder, in a fixed buffer so that building
+/// a frame doesn't allocate.
+#[derive(Debug, Clone, Copy)]
+struct BuilderData {
+    buf: [u8; CANFD_MAX_DLEN],
+    len: usize,
+}
+
+impl BuilderData {
+    /// Copies in the payload, remembering its full length so that a
+    /// payload that's too large can be reported when the frame is built.
+    fn set(&mut self, data: &[u8]) {
+        let n = data.len().min(CANFD_MAX_DLEN);
+        self.buf[..n].copy_from_slice(&data[..n]);
+        self.len = data.len();
+    }
+
+    /// Gets the payload, if it fits into the buffer.
+    fn get(&self) -> Result<&[u8], ConstructionError> {
+        self.buf
+            .get(..self.len)
+            .ok_or(ConstructionError::TooMuchData {
+                len: self.len,
+                max: CANFD_MAX_DLEN,
+            })
+    }
+}
+
+impl Default for BuilderData {
+    fn default() -> Self {
+        Self {
+            buf: [0; CANFD_MAX_DLEN],
+            len: 0,
+        }
+    }
+}
+
+/// A fluent builder for classic CAN 2.0 frames.
+///
+/// ```
+/// use socketcan::{CanFrame, EmbeddedFrame};
+///
+/// let frame = CanFrame::builder()
+///     .id(0x123)
+///     .extended()
+///     .data(&[1, 2, 3])
+///     .build()
+///     .unwrap();
+/// assert!(frame.is_extended());
+/// ```
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CanFrameBuilder {
+    id: u32,
+    extended: bool,
+    rtr: bool,
+    dlc: usize,
+    data: BuilderData,
+}
+
+impl CanFrameBuilder {
+    /// Sets the raw, numeric ID of the frame.
+    ///
+    /// IDs larger than 0x7FF are always created as extended IDs.
+    pub fn id(mut self, id: u32) -> Self {
+        self.id = id;
+        self
+    }
+
+    /// Makes the frame use a 29-bit extended ID, even if the ID would fit
+    /// into a standard one.
+    pub fn extended(mut self) -> Self {
+        self.extended = true;
+        self
+    }
+
+    /// Sets the data payload of the frame.
+    pub fn data(mut self, data: &[u8]) -> Self {
+        self.data.set(data);
+        self
+    }
+
+    /// Makes the frame a remote transmission request.
+    ///
+    /// A remote frame carries no data, so it can't also be given a
+    /// payload. The requested length is set with [`dlc()`](Self::dlc).
+    pub fn rtr(mut self) -> Self {
+        self.rtr = true;
+        self
+    }
+
+    /// Sets the data length code requested by a remote frame.
+    pub fn dlc(mut self, dlc: usize) -> Self {
+        self.dlc = dlc;
+        self
+    }
+
+    /// Creates the frame.
+    ///
+    /// This fails if a remote frame was also given a data payload.
+    pub fn build(self) -> Result<CanFrame, ConstructionError> {
+        let can_id = if self.extended {
+            CanId::extended(self.id)?
+        } else {
+            CanId::from_raw(self.id)?
+        }
+        .id_word();
+        let data = self.data.get()?;
+        if self.rtr {
+            if !data.is_empty() {
+                return Err(ConstructionError::TooMuchData {
+                    len: data.len(),
+                    max: 0,
+                });
+            }
+            let mut frame = CanRemoteFrame::default();
+            frame.0.can_id = can_id | CAN_RTR_FLAG;
+            frame.set_dlc(self.dlc)?;
+            Ok(CanFrame::Remote(frame))
+        } else {
+            CanDataFrame::init(can_id, data).map(CanFrame::Data)
+        }
+    }
+}
+
+impl CanFrame {
+    /// Gets a builder to create a classic CAN 2.0 frame.
+    pub fn builder() -> CanFrameBuilder {
+        CanFrameBuilder::default()
+    }
+}
+
+/// A fluent builder for CAN FD frames.
+///
+/// ```
+/// use socketcan::CanFdFrame;
+///
+/// let frame = CanFdFrame::builder()
+///     .id(0x123)
+///     .data(&[0xAA; 16])
+///     .brs()
+///     .build()
+///     .unwrap();
+/// assert!(frame.is_brs());
+/// ```
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CanFdFrameBuilder {
+    id: u32,
+    extended: bool,
+    flags: FdFlags,
+    data: BuilderData,
+}
+
+impl CanFdFrameBuilder {
+    /// Sets the raw, numeric ID of the frame.
+    ///
+    /// IDs larger than 0x7FF are always created as extended IDs.
+    pub fn id(mut self, id: u32) -> Self {
+        self.id = id;
+        self
+    }
+
+    /// Makes the frame use a 29-bit extended ID, even if the ID would fit
+    /// into a standard one.
+    pub fn extended(mut self) -> Self {
+        self.extended = true;
+        self
+    }
+
+    /// Sets the data payload of the frame.
+    ///
+    /// It is padded with zeros to the next legal FD length, if necessary.
+    pub fn data(mut self, data: &[u8]) -> Self {
+        self.data.set(data);
+        self
+    }
+
+    /// Sets the bit rate switch flag, to send the payload at the higher
+    /// data bit rate.
+    pub fn brs(mut self) -> Self {
+        self.flags |= FdFlags::BRS;
+        self
+    }
+
+    /// Sets the error state indicator flag.
+    pub fn esi(mut self) -> Self {
+        self.flags |= FdFlags::ESI;
+        self
+    }
+
+    /// Sets the FD frame flag.
+    pub fn fdf(mut self) -> Self {
+        self.flags |= FdFlags::FDF;
+        self
+    }
+
+    /// Creates the frame.
+    pub fn build(self) -> Result<CanFdFrame, ConstructionError> {
+        let can_id = if self.extended {
+            CanId::extended(self.id)?
+        } else {
+            CanId::from_raw(self.id)?
+        }
+        .id_word();
+        CanFdFrame::init(can_id, self.data.get()?, self.flags)
+    }
+}
+
+impl CanFdFrame {
+    /// Gets a builder to create a CAN FD frame.
+    pub fn builder() -> CanFdFrameBuilder {
+        CanFdFrameBuilder::default()
+    }
+}
+
+// ===== serde =====
+
+/// The serde representation of a data frame.
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "CanDataFrame")]
+struct DataFrameRepr<D> {
+    id: u32,
+    ext: bool,
+    data: D,
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for CanDataFrame {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        DataFrameRepr {
+            id: self.raw_id(),
+            ext: self.is_extended(),
+            data: self.data(),
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for CanDataFrame {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = DataFrameRepr::<Vec<u8>>::deserialize(deserializer)?;
+        let can_id = canid_from_parts(repr.id, repr.ext).map_err(de::Error::custom)?;
+        Self::init(can_id, &repr.data).map_err(de::Error::custom)
+    }
+}
+
+/// The serde representation of a remote frame.
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "CanRemoteFrame")]
+struct RemoteFrameRepr {
+    id: u32,
+    ext: bool,
+    dlc: u8,
+}
 
-    /// Gets a pointer to the CAN frame structure that is compatible with
-    /// the Linux C API.
-    fn as_ptr(&self) -> *const Self::Inner {
-        &self.0
+#[cfg(feature = "serde")]
+impl Serialize for CanRemoteFrame {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        RemoteFrameRepr {
+            id: self.raw_id(),
+            ext: self.is_extended(),
+            dlc: self.0.can_dlc,
+        }
+        .serialize(serializer)
     }
+}
 
-    /// Gets a mutable pointer to the CAN frame structure that is compatible
-    /// with the Linux C API.
-    fn as_mut_ptr(&mut self) -> *mut Self::Inner {
-        &mut self.0
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for CanRemoteFrame {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = RemoteFrameRepr::deserialize(deserializer)?;
+        let can_id = canid_from_parts(repr.id, repr.ext).map_err(de::Error::custom)?;
+        let mut frame = Self::default();
+        frame.0.can_id = can_id | CAN_RTR_FLAG;
+        frame
+            .set_dlc(repr.dlc as usize)
+            .map_err(de::Error::custom)?;
+        Ok(frame)
     }
 }
 
-impl EmbeddedFrame for CanFdFrame {
-    /// Create a new FD frame
-    fn new(id: impl Into<Id>, data: &[u8]) -> Option<Self> {
-        let can_id = id_to_canid_t(id);
-        Self::init(can_id, data, FdFlags::empty()).ok()
-    }
+/// The serde representation of an error frame.
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "CanErrorFrame")]
+struct ErrorFrameRepr {
+    bits: u32,
+    data: [u8; CAN_MAX_DLEN],
+}
 
-    /// CAN FD frames don't support remote
-    fn new_remote(_id: impl Into<Id>, _dlc: usize) -> Option<Self> {
-        None
+#[cfg(feature = "serde")]
+impl Serialize for CanErrorFrame {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        ErrorFrameRepr {
+            bits: self.error_bits(),
+            data: self.0.data,
+        }
+        .serialize(serializer)
     }
+}
 
-    /// Check if frame uses 29-bit extended ID format.
-    fn is_extended(&self) -> bool {
-        self.id_flags().contains(IdFlags::EFF)
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for CanErrorFrame {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = ErrorFrameRepr::deserialize(deserializer)?;
+        Self::new_error(repr.bits, &repr.data).map_err(de::Error::custom)
     }
+}
 
-    /// The FD frames don't support remote request
-    fn is_remote_frame(&self) -> bool {
-        false
+/// The serde representation of an FD frame.
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "CanFdFrame")]
+struct FdFrameRepr<D> {
+    id: u32,
+    ext: bool,
+    brs: bool,
+    esi: bool,
+    #[serde(default)]
+    fdf: bool,
+    data: D,
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for CanFdFrame {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        FdFrameRepr {
+            id: self.raw_id(),
+            ext: self.is_extended(),
+            brs: self.is_brs(),
+            esi: self.is_esi(),
+            fdf: self.is_fdf(),
+            data: self.data(),
+        }
+        .serialize(serializer)
     }
+}
 
-    /// Return the frame identifier.
-    fn id(&self) -> Id {
-        self.hal_id()
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for CanFdFrame {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = FdFrameRepr::<Vec<u8>>::deserialize(deserializer)?;
+        let can_id = canid_from_parts(repr.id, repr.ext).map_err(de::Error::custom)?;
+        let mut flags = FdFlags::empty();
+        flags.set(FdFlags::BRS, repr.brs);
+        flags.set(FdFlags::ESI, repr.esi);
+        flags.set(FdFlags::FDF, repr.fdf);
+        Self::init(can_id, &repr.data, flags).map_err(de::Error::custom)
     }
+}
 
-    /// Data length code
-    fn dlc(&self) -> usize {
-        self.0.len as usize
+// ===== defmt =====
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for CanDataFrame {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(
+            f,
+            "CanDataFrame {{ id: {=u32:X}, ext: {=bool}, data: {=[u8]:02X} }}",
+            self.raw_id(),
+            self.is_extended(),
+            self.data()
+        )
     }
+}
 
-    /// A slice into the actual data.
-    ///
-    /// For normal CAN frames the slice will always be <= 8 bytes in length.
-    fn data(&self) -> &[u8] {
-        &self.0.data[..(self.0.len as usize)]
+#[cfg(feature = "defmt")]
+impl defmt::Format for CanRemoteFrame {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(
+            f,
+            "CanRemoteFrame {{ id: {=u32:X}, ext: {=bool}, dlc: {=usize} }}",
+            self.raw_id(),
+            self.is_extended(),
+            self.dlc()
+        )
     }
 }
 
-impl Frame for CanFdFrame {
-    /// Get the composite SocketCAN ID word, with EFF/RTR/ERR flags
-    fn id_word(&self) -> canid_t {
-        self.0.can_id
+#[cfg(feature = "defmt")]
+impl defmt::Format for CanErrorFrame {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(
+            f,
+            "CanErrorFrame {{ bits: {=u32:X}, data: {=[u8]:02X} }}",
+            self.error_bits(),
+            self.data()
+        )
     }
+}
 
-    /// Sets the CAN ID for the frame
-    fn set_id(&mut self, id: impl Into<Id>) {
-        self.0.can_id = id_to_canid_t(id);
+#[cfg(feature = "defmt")]
+impl defmt::Format for CanFdFrame {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(
+            f,
+            "CanFdFrame {{ id: {=u32:X}, ext: {=bool}, flags: {=u8:02X}, data: {=[u8]:02X} }}",
+            self.raw_id(),
+            self.is_extended(),
+            self.flags().bits(),
+            self.data()
+        )
     }
+}
 
-    /// Sets the data payload of the frame.
-    fn set_data(&mut self, data: &[u8]) -> Result<(), ConstructionError> {
-        match data.len() {
-            n if n <= CANFD_MAX_DLEN => {
-                self.0.len = n as u8;
-                self.0.data[..n].copy_from_slice(data);
-                Ok(())
-            }
-            _ => Err(ConstructionError::TooMuchData),
+#[cfg(feature = "defmt")]
+impl defmt::Format for CanFrame {
+    fn format(&self, f: defmt::Formatter) {
+        use CanFrame::*;
+        match self {
+            Data(frame) => frame.format(f),
+            Remote(frame) => frame.format(f),
+            Error(frame) => frame.format(f),
         }
     }
 }
 
-impl Default for CanFdFrame {
-    /// The default FD frame has all fields and data set to zero, and all flags off.
-    fn default() -> Self {
-        Self(canfd_frame_default())
+#[cfg(feature = "defmt")]
+impl defmt::Format for CanAnyFrame {
+    fn format(&self, f: defmt::Formatter) {
+        use CanAnyFrame::*;
+        match self {
+            Normal(frame) => frame.format(f),
+            Remote(frame) => frame.format(f),
+            Error(frame) => frame.format(f),
+            Fd(frame) => frame.format(f),
+        }
     }
 }
 
-impl fmt::Debug for CanFdFrame {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "CanFdFrame {{ ")?;
-        fmt::UpperHex::fmt(self, f)?;
-        write!(f, " }}")
+// ===== arbitrary =====
+
+// The generated frames always respect the invariants of each type, so
+// that they are frames that could actually be read from the bus.
+
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for CanDataFrame {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        let id = CanId::arbitrary(u)?;
+        let len = u.int_in_range(0..=CAN_MAX_DLEN)?;
+        let data = u.bytes(len)?;
+        Ok(Self::init(id.id_word(), data).unwrap())
     }
 }
 
-impl fmt::UpperHex for CanFdFrame {
-    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        write!(f, "{:X}##", self.0.can_id)?;
-        write!(f, "{} ", self.0.flags)?;
-        let mut parts = self.data().iter().map(|v| format!("{:02X}", v));
-        write!(f, "{}", parts.join(" "))
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for CanRemoteFrame {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        let id = CanId::arbitrary(u)?;
+        let dlc = u.int_in_range(0..=CAN_MAX_DLEN)?;
+        Ok(Self::try_new_remote(id, dlc).unwrap())
     }
 }
 
-impl From<CanDataFrame> for CanFdFrame {
-    fn from(frame: CanDataFrame) -> Self {
-        let n = frame.dlc();
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for CanErrorFrame {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        let bits = u32::arbitrary(u)? & CAN_ERR_MASK;
+        let data = <[u8; CAN_MAX_DLEN]>::arbitrary(u)?;
+        Ok(Self::new_error(bits, &data).unwrap())
+    }
+}
 
-        let mut fdframe = canfd_frame_default();
-        fdframe.can_id = frame.id_word();
-        fdframe.len = n as u8;
-        fdframe.data[..n].copy_from_slice(&frame.data()[..n]);
-        Self(fdframe)
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for CanFdFrame {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        let id = CanId::arbitrary(u)?;
+        let len = fd_dlc_to_len(u.int_in_range(0..=15)?);
+        let data = u.bytes(len)?;
+        let mut flags = FdFlags::empty();
+        flags.set(FdFlags::BRS, u.arbitrary()?);
+        flags.set(FdFlags::ESI, u.arbitrary()?);
+        Ok(Self::init(id.id_word(), data, flags).unwrap())
     }
 }
 
-impl From<canfd_frame> for CanFdFrame {
-    fn from(frame: canfd_frame) -> Self {
-        Self(frame)
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for CanFrame {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(match u.int_in_range(0..=2)? {
+            0 => CanFrame::Data(u.arbitrary()?),
+            1 => CanFrame::Remote(u.arbitrary()?),
+            _ => CanFrame::Error(u.arbitrary()?),
+        })
     }
 }
 
-impl AsRef<canfd_frame> for CanFdFrame {
-    fn as_ref(&self) -> &canfd_frame {
-        &self.0
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for CanAnyFrame {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(match u.int_in_range(0..=3)? {
+            0 => CanAnyFrame::Normal(u.arbitrary()?),
+            1 => CanAnyFrame::Remote(u.arbitrary()?),
+            2 => CanAnyFrame::Error(u.arbitrary()?),
+            _ => CanAnyFrame::Fd(u.arbitrary()?),
+        })
     }
 }
 
@@ -1275,7 +3665,6 @@ impl AsRef<canfd_frame> for CanFdFrame {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::errors;
 
     const STD_ID: Id = Id::Standard(StandardId::MAX);
     const EXT_ID: Id = Id::Extended(ExtendedId::MAX);
@@ -1286,7 +3675,6 @@ mod tests {
     const DATA_LEN: usize = DATA.len();
 
     const EMPTY_DATA: &[u8] = &[];
-    const ZERO_DATA: &[u8] = &[0u8; DATA_LEN];
 
     fn id_to_raw(id: Id) -> u32 {
         match id {
@@ -1381,6 +3769,27 @@ mod tests {
         assert!(frame.is_extended());
     }
 
+    #[test]
+    fn test_data_mutation() {
+        let mut frame = CanDataFrame::new(STD_ID, DATA).unwrap();
+        frame.data_mut()[0] = 0xFF;
+        assert_eq!(&[0xFF, 1, 2, 3], frame.data());
+
+        frame.truncate(2);
+        assert_eq!(&[0xFF, 1], frame.data());
+
+        frame.pad_to(4, 0xCC).unwrap();
+        assert_eq!(&[0xFF, 1, 0xCC, 0xCC], frame.data());
+        assert!(frame.pad_to(9, 0).is_err());
+
+        let mut frame = CanFdFrame::new(STD_ID, DATA).unwrap();
+        frame.pad_to(12, 0xAA).unwrap();
+        assert_eq!(12, frame.len());
+        frame.set_data(&[1; 20]).unwrap();
+        frame.truncate(16);
+        assert_eq!(&[1; 16], frame.data());
+    }
+
     #[test]
     fn test_remote_frame() {
         let frame = CanRemoteFrame::default();
@@ -1403,7 +3812,8 @@ mod tests {
         assert!(!frame.is_error_frame());
         assert_eq!(DATA_LEN, frame.dlc());
         assert_eq!(DATA_LEN, frame.len());
-        assert_eq!(ZERO_DATA, frame.data());
+        assert_eq!(EMPTY_DATA, frame.data());
+        assert_eq!(DATA_LEN, frame.requested_len());
 
         assert!(frame.id_flags().contains(IdFlags::RTR));
         assert_eq!(CAN_RTR_FLAG, frame.id_word() & CAN_RTR_FLAG);
@@ -1416,7 +3826,8 @@ mod tests {
         assert!(!frame.is_data_frame());
         assert!(frame.is_remote_frame());
         assert!(!frame.is_error_frame());
-        assert_eq!(ZERO_DATA, frame.data());
+        assert_eq!(EMPTY_DATA, frame.data());
+        assert_eq!(DATA_LEN, frame.dlc());
 
         assert!(matches!(frame, CanFrame::Remote(_)));
         assert!(frame.id_flags().contains(IdFlags::RTR));
@@ -1430,7 +3841,8 @@ mod tests {
         assert!(!frame.is_data_frame());
         assert!(frame.is_remote_frame());
         assert!(!frame.is_error_frame());
-        assert_eq!(ZERO_DATA, frame.data());
+        assert_eq!(EMPTY_DATA, frame.data());
+        assert_eq!(DATA_LEN, frame.dlc());
 
         assert!(matches!(frame, CanFrame::Remote(_)));
         assert!(frame.id_flags().contains(IdFlags::RTR));
@@ -1440,6 +3852,191 @@ mod tests {
         assert!(frame.is_none());
     }
 
+    #[test]
+    fn test_try_new() {
+        let frame = CanDataFrame::try_new(STD_ID, DATA).unwrap();
+        assert_eq!(DATA, frame.data());
+
+        let err = CanDataFrame::try_new(STD_ID, &[0; 9]).unwrap_err();
+        assert_eq!(ConstructionError::TooMuchData { len: 9, max: 8 }, err);
+
+        let err = CanFdFrame::try_new(STD_ID, &[0; 65]).unwrap_err();
+        assert_eq!(ConstructionError::TooMuchData { len: 65, max: 64 }, err);
+
+        let err = CanFrame::try_new_remote(STD_ID, 9).unwrap_err();
+        assert_eq!(ConstructionError::TooMuchData { len: 9, max: 8 }, err);
+
+        let err = CanFdFrame::try_new_remote(STD_ID, 0).unwrap_err();
+        assert_eq!(ConstructionError::RemoteNotSupported, err);
+
+        let err = CanFrame::try_from_raw_id(0x2000_0000, DATA).unwrap_err();
+        assert_eq!(
+            ConstructionError::IDTooLarge {
+                id: 0x2000_0000,
+                extended: true
+            },
+            err
+        );
+    }
+
+    #[test]
+    fn test_try_new_default() {
+        // A frame type from outside the crate, which only has `new()`
+        #[derive(Debug)]
+        struct Wrapped(CanDataFrame);
+
+        impl EmbeddedFrame for Wrapped {
+            fn new(id: impl Into<Id>, data: &[u8]) -> Option<Self> {
+                CanDataFrame::new(id, data).map(Self)
+            }
+            fn new_remote(_id: impl Into<Id>, _dlc: usize) -> Option<Self> {
+                None
+            }
+            fn is_extended(&self) -> bool {
+                self.0.is_extended()
+            }
+            fn is_remote_frame(&self) -> bool {
+                false
+            }
+            fn id(&self) -> Id {
+                self.0.id()
+            }
+            fn dlc(&self) -> usize {
+                self.0.dlc()
+            }
+            fn data(&self) -> &[u8] {
+                self.0.data()
+            }
+        }
+
+        impl Frame for Wrapped {
+            fn id_word(&self) -> canid_t {
+                self.0.id_word()
+            }
+            fn set_id(&mut self, id: impl Into<Id>) {
+                self.0.set_id(id)
+            }
+            fn set_data(&mut self, data: &[u8]) -> Result<(), ConstructionError> {
+                self.0.set_data(data)
+            }
+        }
+
+        assert_eq!(DATA, Wrapped::try_new(STD_ID, DATA).unwrap().data());
+        assert_eq!(
+            ConstructionError::InvalidLength { len: 9 },
+            Wrapped::try_new(STD_ID, &[0; 9]).unwrap_err()
+        );
+        assert_eq!(
+            ConstructionError::InvalidLength { len: 2 },
+            Wrapped::try_new_remote(STD_ID, 2).unwrap_err()
+        );
+    }
+
+    #[test]
+    fn test_any_frame_embedded() {
+        let frame = <CanAnyFrame as EmbeddedFrame>::new(STD_ID, DATA).unwrap();
+        assert!(matches!(frame, CanAnyFrame::Normal(_)));
+        assert_eq!(STD_ID, frame.id());
+        assert_eq!(DATA, frame.data());
+
+        let frame = <CanAnyFrame as EmbeddedFrame>::new(EXT_ID, &[0xAA; 12]).unwrap();
+        assert!(matches!(frame, CanAnyFrame::Fd(_)));
+        assert!(frame.is_extended());
+        assert_eq!(9, frame.dlc());
+
+        let frame = CanAnyFrame::new_remote(STD_ID, 2).unwrap();
+        assert!(frame.is_remote_frame());
+    }
+
+    #[test]
+    fn test_any_frame_conversions() {
+        let frame = CanAnyFrame::try_new(STD_ID, DATA).unwrap();
+        assert_eq!(DATA_LEN, frame.len());
+        assert_eq!(id_to_raw(STD_ID), frame.raw_id());
+        assert!(CanFrame::try_from(frame).is_ok());
+        assert!(CanFdFrame::try_from(frame).is_ok());
+
+        let mut frame = CanAnyFrame::try_new(STD_ID, &[0; 20]).unwrap();
+        assert!(CanFrame::try_from(frame).is_err());
+        frame.set_id(EXT_ID);
+        assert!(frame.is_extended());
+        frame.set_data(DATA).unwrap();
+        assert!(matches!(CanFrame::try_from(frame), Ok(CanFrame::Data(_))));
+
+        let frame = CanAnyFrame::try_new_remote(STD_ID, 2).unwrap();
+        assert_eq!(
+            Err(ConstructionError::WrongFrameType),
+            CanFdFrame::try_from(frame)
+        );
+    }
+
+    #[test]
+    fn test_conversion() {
+        let fd = CanAnyFrame::from(CanFdFrame::from_raw_id(0x123, &[1, 2, 3]).unwrap());
+        let classic = FdConversion::ToClassic.convert(fd).unwrap();
+        assert!(matches!(classic, CanAnyFrame::Normal(_)));
+        assert_eq!(&[1, 2, 3], classic.data());
+
+        let long = CanAnyFrame::from(CanFdFrame::from_raw_id(0x123, &[0; 12]).unwrap());
+        assert!(FdConversion::ToClassic.convert(long).is_none());
+
+        let promoted = FdConversion::ToFd { brs: true }.convert(classic).unwrap();
+        match promoted {
+            CanAnyFrame::Fd(frame) => assert!(frame.is_brs()),
+            _ => panic!("expected an FD frame"),
+        }
+        assert_eq!(Some(fd), FdConversion::Keep.convert(fd));
+    }
+
+    #[test]
+    fn test_event() {
+        let frame = CanFrame::new(STD_ID, DATA).unwrap();
+        let event = CanEvent::from(frame);
+        assert!(!event.is_error());
+        assert_eq!(DATA, event.frame().unwrap().data());
+        assert!(event.errors().is_empty());
+
+        let frame = CanAnyFrame::Error(CanErrorFrame::from(CanError::BusOff));
+        let event = CanEvent::from(frame);
+        assert!(event.is_error());
+        assert!(event.into_frame().is_none());
+        assert!(matches!(event.errors()[..], [CanError::BusOff]));
+    }
+
+    #[test]
+    fn test_error_frame_accessors() {
+        let frame =
+            CanErrorFrame::new_error(0x02CC, &[0, 0x30, 0x04, 0x0A, 0, 0x5A, 130, 96]).unwrap();
+
+        assert_eq!(None, frame.lost_arbitration_bit());
+        assert_eq!(
+            vec![
+                ControllerProblem::TransmitErrorPassive,
+                ControllerProblem::ReceiveErrorPassive
+            ],
+            frame.controller_problems().unwrap().unwrap()
+        );
+        assert_eq!(
+            (vec![ViolationType::BitStuffingError], Location::DataSection),
+            frame.protocol_violation().unwrap().unwrap()
+        );
+        assert!(frame.transceiver_errors().is_none());
+        assert!(frame.is_bus_off());
+        assert!(frame.is_bus_error());
+        assert!(!frame.is_no_ack());
+        assert!(!frame.is_restarted());
+        assert_eq!(0x5A, frame.controller_specific());
+        assert_eq!(Some((130, 96)), frame.error_counters());
+        assert_eq!(Some(130), frame.tx_error_counter());
+        assert_eq!(Some(96), frame.rx_error_counter());
+        assert_eq!(5, frame.errors().len());
+
+        // Without CAN_ERR_CNT, the counters aren't valid
+        let frame = CanErrorFrame::new_error(0x0002, &[7, 0, 0, 0, 0, 0, 1, 2]).unwrap();
+        assert_eq!(Some(7), frame.lost_arbitration_bit());
+        assert_eq!(None, frame.error_counters());
+    }
+
     #[test]
     fn test_error_frame() {
         // Create an error frame indicating transciever error
@@ -1451,7 +4048,7 @@ mod tests {
         assert!(matches!(err, CanError::TransceiverError));
 
         let id = StandardId::new(0x0010).unwrap();
-        let frame = CanErrorFrame::new(id, &[]).unwrap();
+        let frame = <CanErrorFrame as EmbeddedFrame>::new(id, &[]).unwrap();
         assert!(!frame.is_data_frame());
         assert!(!frame.is_remote_frame());
         assert!(frame.is_error_frame());
@@ -1460,7 +4057,7 @@ mod tests {
         assert!(matches!(err, CanError::TransceiverError));
 
         let id = ExtendedId::new(0x0020).unwrap();
-        let frame = CanErrorFrame::new(id, &[]).unwrap();
+        let frame = <CanErrorFrame as EmbeddedFrame>::new(id, &[]).unwrap();
         assert!(!frame.is_data_frame());
         assert!(!frame.is_remote_frame());
         assert!(frame.is_error_frame());
@@ -1479,8 +4076,8 @@ mod tests {
         assert!(matches!(err, CanError::TransmitTimeout));
 
         let err = CanError::ProtocolViolation {
-            vtype: errors::ViolationType::BitStuffingError,
-            location: errors::Location::Id0400,
+            vtype: ViolationType::BitStuffingError,
+            location: Location::Id0400,
         };
         let frame = CanErrorFrame::from(err);
         assert!(!frame.is_data_frame());
@@ -1490,8 +4087,8 @@ mod tests {
         let err = frame.into_error();
         match err {
             CanError::ProtocolViolation { vtype, location } => {
-                assert_eq!(vtype, errors::ViolationType::BitStuffingError);
-                assert_eq!(location, errors::Location::Id0400);
+                assert_eq!(vtype, ViolationType::BitStuffingError);
+                assert_eq!(location, Location::Id0400);
             }
             _ => {
                 assert!(false);
@@ -1499,6 +4096,87 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_display() {
+        let frame = CanDataFrame::new(StandardId::new(0x123).unwrap(), DATA).unwrap();
+        assert_eq!("123#00010203", format!("{}", frame));
+        assert_eq!("123#00 01 02 03", format!("{:#}", frame));
+        assert_eq!("123#00010203", format!("{}", CanFrame::from(frame)));
+
+        let frame = CanDataFrame::new(ExtendedId::new(0x12345).unwrap(), EMPTY_DATA).unwrap();
+        assert_eq!("00012345#", format!("{}", frame));
+
+        let frame = CanRemoteFrame::new_remote(StandardId::new(0x42).unwrap(), 0).unwrap();
+        assert_eq!("042#R", format!("{}", frame));
+
+        let frame = CanRemoteFrame::new_remote(StandardId::new(0x42).unwrap(), 4).unwrap();
+        assert_eq!("042#R4", format!("{}", frame));
+
+        let frame = CanErrorFrame::from(CanError::NoAck);
+        assert_eq!("20000020#0000000000000000", format!("{}", frame));
+
+        let frame =
+            CanFdFrame::with_flags(StandardId::new(0x123).unwrap(), DATA, FdFlags::BRS).unwrap();
+        assert_eq!("123##100010203", format!("{}", frame));
+        assert_eq!("123##100 01 02 03", format!("{:#}", frame));
+        assert_eq!("123##100010203", format!("{}", CanAnyFrame::from(frame)));
+    }
+
+    #[test]
+    fn test_hex_format() {
+        let frame = CanFrame::from_raw_id(0xAB, &[0xDE, 0xAD, 0xBE, 0xEF]).unwrap();
+        assert_eq!("0AB#DEADBEEF", format!("{:X}", frame));
+        assert_eq!("0x0AB#DE.AD.BE.EF", format!("{:#X}", frame));
+        assert_eq!("0ab#deadbeef", format!("{:x}", frame));
+        assert_eq!("0x0ab#de.ad.be.ef", format!("{:#x}", frame));
+
+        let frame = CanRemoteFrame::new_remote(ExtendedId::new(0xABCDE).unwrap(), 8).unwrap();
+        assert_eq!("000abcde#R8", format!("{:x}", frame));
+
+        let frame = CanFdFrame::with_flags(
+            StandardId::new(0xAB).unwrap(),
+            &[0xAA],
+            FdFlags::BRS | FdFlags::ESI,
+        )
+        .unwrap();
+        assert_eq!("0x0ab##3aa", format!("{:#x}", CanAnyFrame::from(frame)));
+    }
+
+    #[test]
+    fn test_write_hex_bytes() {
+        // Longer than the stack buffer, to write in more than one piece
+        let data: Vec<u8> = (0..=255).collect();
+        let mut s = String::new();
+        write_hex_bytes(&mut s, &data, false, Some(b' ')).unwrap();
+        let expected: Vec<_> = data.iter().map(|b| format!("{:02x}", b)).collect();
+        assert_eq!(expected.join(" "), s);
+
+        s.clear();
+        write_hex_bytes(&mut s, &[], true, Some(b' ')).unwrap();
+        assert!(s.is_empty());
+    }
+
+    #[cfg(feature = "arbitrary")]
+    #[test]
+    fn test_arbitrary() {
+        use arbitrary::{Arbitrary, Unstructured};
+
+        let buf: Vec<u8> = (0..4096u32)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8)
+            .collect();
+        let mut u = Unstructured::new(&buf);
+        while let Ok(frame) = CanAnyFrame::arbitrary(&mut u) {
+            match frame {
+                CanAnyFrame::Fd(frame) => assert!(is_valid_fd_len(frame.len())),
+                CanAnyFrame::Error(frame) => assert!(frame.is_error_frame()),
+                frame => assert!(frame.dlc() <= CAN_MAX_DLEN),
+            }
+            if u.is_empty() {
+                break;
+            }
+        }
+    }
+
     #[test]
     fn test_fd_frame() {
         let frame = CanFdFrame::new(STD_ID, DATA).unwrap();
@@ -1528,6 +4206,325 @@ mod tests {
         assert!(frame.is_extended());
     }
 
+    #[test]
+    fn test_eq_hash() {
+        use std::collections::HashSet;
+
+        let a = CanFrame::new(STD_ID, DATA).unwrap();
+        let mut b = CanFrame::new(STD_ID, &[0, 1, 2, 3, 4, 5]).unwrap();
+        assert_ne!(a, b);
+
+        // Stale bytes past the payload are ignored
+        b.set_data(DATA).unwrap();
+        assert_eq!(a, b);
+
+        let mut set = HashSet::new();
+        set.insert(CanAnyFrame::from(a));
+        set.insert(CanAnyFrame::from(b));
+        set.insert(CanAnyFrame::from(CanFdFrame::from(
+            CanDataFrame::new(STD_ID, DATA).unwrap(),
+        )));
+        assert_eq!(2, set.len());
+
+        let mut fd = CanFdFrame::new(STD_ID, DATA).unwrap();
+        assert_ne!(CanAnyFrame::Fd(fd), CanAnyFrame::from(a));
+        let plain = fd;
+        fd.set_brs(true);
+        assert_ne!(plain, fd);
+    }
+
+    #[test]
+    fn test_priority() {
+        let std_data = CanDataFrame::from_raw_id(0x100, DATA).unwrap();
+        let std_remote = CanRemoteFrame::remote_from_raw_id(0x100, 0).unwrap();
+        let ext_data = CanDataFrame::new(ExtendedId::new(0x100 << 18).unwrap(), DATA).unwrap();
+        let ext_remote =
+            CanRemoteFrame::new_remote(ExtendedId::new(0x100 << 18).unwrap(), 0).unwrap();
+        let lower = CanDataFrame::new(ExtendedId::new(0x0FF << 18).unwrap(), DATA).unwrap();
+
+        let mut order = vec![
+            ext_remote.priority(),
+            std_remote.priority(),
+            ext_data.priority(),
+            std_data.priority(),
+            lower.priority(),
+        ];
+        order.sort();
+        assert_eq!(
+            vec![
+                lower.priority(),
+                std_data.priority(),
+                std_remote.priority(),
+                ext_data.priority(),
+                ext_remote.priority()
+            ],
+            order
+        );
+    }
+
+    #[test]
+    fn test_builders() {
+        let frame = CanFrame::builder().id(0x123).data(DATA).build().unwrap();
+        assert!(frame.is_standard());
+        assert_eq!(0x123, frame.raw_id());
+        assert_eq!(DATA, frame.data());
+
+        let frame = CanFrame::builder().id(0x123).rtr().dlc(4).build().unwrap();
+        assert!(frame.is_remote_frame());
+        assert_eq!(4, frame.dlc());
+
+        let frame = CanFrame::builder().id(0x1234).build().unwrap();
+        assert!(frame.is_extended());
+
+        let err = CanFrame::builder().id(0x2000_0000).build().unwrap_err();
+        assert!(matches!(err, ConstructionError::IDTooLarge { .. }));
+
+        let err = CanFrame::builder().id(0x123).rtr().data(DATA).build();
+        assert_eq!(Err(ConstructionError::TooMuchData { len: 4, max: 0 }), err);
+
+        let mut frame = CanFdFrame::builder()
+            .id(0x100)
+            .extended()
+            .data(&[1; 10])
+            .brs()
+            .esi()
+            .build()
+            .unwrap();
+        assert!(frame.is_extended());
+        assert!(frame.is_brs() && frame.is_esi());
+        assert!(!frame.is_fdf());
+        frame.set_fdf(true);
+        assert!(frame.is_fdf() && frame.flags().contains(FdFlags::FDF));
+        assert_eq!(12, frame.len());
+
+        let err = CanFdFrame::builder().id(0x100).data(&[0; 65]).build();
+        assert_eq!(
+            Err(ConstructionError::TooMuchData { len: 65, max: 64 }),
+            err
+        );
+    }
+
+    #[test]
+    fn test_len8_dlc() {
+        let mut frame = CanDataFrame::new(STD_ID, &[0xFF; 8]).unwrap();
+        assert_eq!(None, frame.len8_dlc());
+        assert_eq!(8, frame.raw_dlc());
+
+        frame.set_len8_dlc(12).unwrap();
+        assert_eq!(Some(12), frame.len8_dlc());
+        assert_eq!(12, frame.raw_dlc());
+        assert_eq!(8, frame.dlc());
+        assert!(frame.set_len8_dlc(16).is_err());
+
+        frame.truncate(4);
+        assert_eq!(None, frame.len8_dlc());
+        assert!(frame.set_len8_dlc(9).is_err());
+
+        let frame = CanRemoteFrame::with_raw_dlc(STD_ID, 15).unwrap();
+        assert_eq!(8, frame.dlc());
+        assert_eq!(15, frame.raw_dlc());
+
+        let frame = CanRemoteFrame::with_raw_dlc(STD_ID, 3).unwrap();
+        assert_eq!(3, frame.raw_dlc());
+    }
+
+    #[test]
+    fn test_fd_dlc() {
+        for (dlc, &len) in CANFD_DLC_TO_LEN.iter().enumerate() {
+            assert_eq!(len as usize, fd_dlc_to_len(dlc as u8));
+            assert_eq!(Some(dlc as u8), fd_len_to_dlc(len as usize));
+        }
+        assert_eq!(Some(10), fd_len_to_dlc(13));
+        assert_eq!(None, fd_len_to_dlc(65));
+        assert!(is_valid_fd_len(48));
+        assert!(!is_valid_fd_len(9));
+
+        // Payloads are padded to a legal length
+        let frame = CanFdFrame::new(STD_ID, &[0xFF; 9]).unwrap();
+        assert_eq!(12, frame.len());
+        assert_eq!(9, frame.dlc());
+        assert_eq!(&[0xFF, 0, 0, 0], &frame.data()[8..]);
+
+        let mut frame = CanFdFrame::new(STD_ID, &[0xFF; 64]).unwrap();
+        assert_eq!(15, frame.dlc());
+        frame.truncate(30);
+        assert_eq!(32, frame.len());
+        assert_eq!(&[0xFF, 0, 0], &frame.data()[29..]);
+    }
+
+    #[test]
+    fn test_error_frame_from_error() {
+        use crate::errors::{ControllerProblem, Location, ViolationType};
+
+        let errs = [
+            CanError::TransmitTimeout,
+            CanError::LostArbitration(7),
+            CanError::ControllerProblem(ControllerProblem::TransmitErrorPassive),
+            CanError::ProtocolViolation {
+                vtype: ViolationType::BitStuffingError,
+                location: Location::DataSection,
+            },
+            CanError::NoAck,
+            CanError::BusOff,
+            CanError::Restarted,
+        ];
+        for err in errs {
+            let frame = CanErrorFrame::from(err);
+            assert!(frame.is_error_frame());
+            assert_eq!(err.to_string(), frame.into_error().to_string());
+        }
+
+        let frame = CanErrorFrame::new(CanError::BusError);
+        assert_eq!(0x0080, frame.error_bits());
+        assert!(matches!(frame.into_error(), CanError::BusError));
+
+        match CanFrame::from(CanError::BusOff) {
+            CanFrame::Error(frame) => assert!(matches!(frame.into_error(), CanError::BusOff)),
+            _ => panic!("Wrong frame type"),
+        }
+    }
+
+    #[test]
+    fn test_bit_length() {
+        // The well-known worst cases for classic frames
+        assert_eq!(135, classic_bit_length(false, 8));
+        assert_eq!(160, classic_bit_length(true, 8));
+        assert_eq!(55, classic_bit_length(false, 0));
+
+        let frame = CanFrame::from_raw_id(0x100, &[0; 8]).unwrap();
+        assert_eq!(135, frame.bit_length());
+        let frame = CanFrame::remote_from_raw_id(0x100, 8).unwrap();
+        assert_eq!(55, frame.bit_length());
+
+        let (arb, data) = fd_bit_lengths(false, 64);
+        assert_eq!(fd_bit_length(false, 64), arb + data);
+        assert!(data > 8 * 64);
+        assert!(fd_bit_length(true, 8) > fd_bit_length(false, 8));
+
+        let frame = CanFdFrame::new(StandardId::ZERO, &[0; 64]).unwrap();
+        assert_eq!(fd_bit_length(false, 64), frame.bit_length());
+    }
+
+    #[test]
+    fn test_wire_duration() {
+        // 135 bits at 500 kbps
+        let frame = CanAnyFrame::from(CanFrame::from_raw_id(0x100, &[0; 8]).unwrap());
+        assert_eq!(
+            Duration::from_nanos(270_000),
+            frame.wire_duration(500_000, 0)
+        );
+
+        // With BRS, the data phase is at the data bit rate
+        let mut fd = CanFdFrame::new(StandardId::ZERO, &[0; 64]).unwrap();
+        let slow = CanAnyFrame::from(fd).wire_duration(500_000, 2_000_000);
+        fd.set_brs(true);
+        let (arb, data) = fd_bit_lengths(false, 64);
+        let fast = CanAnyFrame::from(fd).wire_duration(500_000, 2_000_000);
+        assert_eq!(
+            Duration::from_nanos(arb as u64 * 2000 + data as u64 * 500),
+            fast
+        );
+        assert!(fast < slow);
+    }
+
+    #[test]
+    fn test_mutators() {
+        let mut frame = CanFrame::from_raw_id(0x123, DATA).unwrap();
+        frame.set_extended(true).unwrap();
+        assert!(frame.is_extended());
+        assert_eq!(0x123, frame.raw_id());
+        frame.set_extended(false).unwrap();
+        assert!(!frame.is_extended());
+
+        frame.set_id(ExtendedId::new(0x1234).unwrap());
+        assert!(frame.set_extended(false).is_err());
+        assert!(frame.is_extended());
+
+        frame.set_rtr(true).unwrap();
+        assert!(frame.is_remote_frame());
+        assert_eq!(DATA.len(), frame.dlc());
+        assert_eq!(0x1234, frame.raw_id());
+        frame.set_rtr(false).unwrap();
+        assert!(frame.is_data_frame());
+        assert_eq!(&[0; 4], frame.data());
+
+        let mut frame = CanAnyFrame::from(CanFdFrame::new(StandardId::ZERO, DATA).unwrap());
+        assert!(frame.set_rtr(true).is_err());
+    }
+
+    #[test]
+    fn test_new_const() {
+        const STD: CanFrame = CanFrame::new_const(0x700, &[0x05]);
+        const EXT: CanFrame = CanFrame::new_const(0x1234_5678, DATA);
+
+        assert_eq!(STD, CanFrame::from_raw_id(0x700, &[0x05]).unwrap());
+        assert!(!STD.is_extended());
+        assert_eq!(EXT, CanFrame::from_raw_id(0x1234_5678, DATA).unwrap());
+        assert!(EXT.is_extended());
+    }
+
+    #[test]
+    fn test_bytes() {
+        let frame = CanFrame::from_raw_id(0x123, DATA).unwrap();
+        let bytes = frame.as_bytes().to_vec();
+        assert_eq!(size_of::<can_frame>(), bytes.len());
+        assert_eq!(frame, CanFrame::try_from_bytes(&bytes).unwrap());
+        assert!(matches!(
+            CanFrame::try_from_bytes(&bytes[..4]),
+            Err(ConstructionError::InvalidLayout { size: 4, .. })
+        ));
+
+        let frame = CanFdFrame::new(StandardId::new(0x123).unwrap(), &[0xAA; 24]).unwrap();
+        let mut bytes = frame.as_bytes().to_vec();
+        assert_eq!(size_of::<canfd_frame>(), bytes.len());
+        assert_eq!(frame, CanFdFrame::try_from_bytes(&bytes).unwrap());
+        bytes[4] = 65;
+        assert!(CanFdFrame::try_from_bytes(&bytes).is_err());
+        bytes[4] = 13;
+        assert_eq!(
+            Err(ConstructionError::InvalidLength { len: 13 }),
+            CanFdFrame::try_from_bytes(&bytes)
+        );
+    }
+
+    #[test]
+    fn test_xl_frame() {
+        let mut frame = CanXlFrame::new(0x242, 0x03, 0x12345678, &[0x11; 100]).unwrap();
+        assert_eq!(0x242, frame.priority());
+        assert_eq!(0x03, frame.sdt());
+        assert_eq!(0x12345678, frame.af());
+        assert_eq!(100, frame.len());
+        assert_eq!(CANXL_HDR_SIZE + 100, frame.size());
+        assert!(frame.flags().contains(XlFlags::XLF));
+
+        frame.set_vcid(0x45);
+        assert_eq!(0x45, frame.vcid());
+        assert_eq!(0x242, frame.priority());
+        frame.set_sec(true);
+        frame.set_data(&[0x11, 0x22]).unwrap();
+        assert_eq!("45242#81:03:12345678#1122", frame.to_string());
+
+        assert!(matches!(
+            CanXlFrame::new(0x800, 0, 0, DATA),
+            Err(ConstructionError::IDTooLarge { .. })
+        ));
+        assert!(matches!(
+            frame.set_data(&[]),
+            Err(ConstructionError::TooLittleData { len: 0, min: 1 })
+        ));
+        assert!(frame.set_data(&[0; 2049]).is_err());
+
+        let mut raw = *frame.as_ref();
+        assert_eq!(frame, CanXlFrame::try_from(raw).unwrap());
+        raw.len = CANXL_MAX_DLEN as u16 + 1;
+        assert!(matches!(
+            CanXlFrame::try_from(raw),
+            Err(ConstructionError::TooMuchData { len: 2049, .. })
+        ));
+        raw.len = 0;
+        assert!(CanXlFrame::try_from(raw).is_err());
+    }
+
     #[test]
     fn test_frame_to_fd() {
         let frame = CanDataFrame::new(STD_ID, DATA).unwrap();
@@ -0,0 +1,426 @@
+// socketcan/src/gateway.rs
+//
+// Userspace forwarding of frames between buses.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! A userspace gateway between buses.
+//!
+//! The kernel `can-gw` module can forward frames between interfaces, but
+//! it's not always available, and can't be extended. The [`Gateway`] does
+//! the same in userspace over two or more FD sockets. Each [`GatewayRoute`]
+//! forwards frames from one socket to another, and can have:
+//!
+//! - filters, to select the frames that it forwards,
+//! - an ID translation, to map the IDs from one bus to the other,
+//! - an [`FdConversion`] policy, to convert between classic and FD frames,
+//! - a rate limit, to cap the number of frames it forwards.
+//!
+//! ```no_run
+//! use socketcan::{
+//!     gateway::{FdConversion, Gateway, GatewayRoute},
+//!     CanFdSocket, CanFilter, CanId, Socket,
+//! };
+//! use std::time::Duration;
+//!
+//! let mut gw = Gateway::new();
+//! let can0 = gw.add_socket(CanFdSocket::open("can0").unwrap());
+//! let can1 = gw.add_socket(CanFdSocket::open("can1").unwrap());
+//!
+//! gw.add_route(
+//!     GatewayRoute::new(can0, can1)
+//!         .with_filters(&[CanFilter::new(0x100, 0x700)])
+//!         .with_translation(|id| CanId::standard(id.as_raw() as u16 | 0x400).unwrap())
+//!         .with_conversion(FdConversion::ToClassic)
+//!         .with_rate_limit(100, Duration::from_secs(1)),
+//! );
+//! gw.add_route(GatewayRoute::new(can1, can0));
+//!
+//! gw.run().unwrap();
+//! ```
+//!
+//! Error frames are never forwarded.
+
+use crate::{CanAnyFrame, CanFdSocket, CanFilter, CanId, Frame, Socket};
+use nix::poll::{poll, PollFd, PollFlags};
+use std::{
+    fmt,
+    io::{ErrorKind, Result as IoResult},
+    os::unix::io::AsRawFd,
+    time::{Duration, Instant},
+};
+
+pub use crate::frame::FdConversion;
+
+/// The index of a socket in the gateway.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PortId(usize);
+
+/// A token bucket limiting the rate of frames.
+#[derive(Debug, Clone, Copy)]
+struct RateLimit {
+    max: u32,
+    per: Duration,
+    tokens: f64,
+    last: Option<Instant>,
+}
+
+impl RateLimit {
+    fn new(max: u32, per: Duration) -> Self {
+        Self {
+            max,
+            per,
+            tokens: f64::from(max),
+            last: None,
+        }
+    }
+
+    /// Takes a token, if one is available at `now`.
+    fn take(&mut self, now: Instant) -> bool {
+        if let Some(last) = self.last {
+            let refill = f64::from(self.max) * now.saturating_duration_since(last).as_secs_f64()
+                / self.per.as_secs_f64();
+            self.tokens = (self.tokens + refill).min(f64::from(self.max));
+        }
+        self.last = Some(now);
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// The counts of frames handled by a route.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RouteStats {
+    /// The number of frames forwarded
+    pub forwarded: u64,
+    /// The number of frames dropped by the rate limit
+    pub rate_limited: u64,
+    /// The number of frames dropped because they couldn't be converted
+    pub unconvertible: u64,
+}
+
+/// An ID translation.
+type Translation = Box<dyn FnMut(CanId) -> CanId + Send>;
+
+// ===== GatewayRoute =====
+
+/// A route forwarding frames from one socket to another.
+pub struct GatewayRoute {
+    src: PortId,
+    dst: PortId,
+    filters: Vec<CanFilter>,
+    translation: Option<Translation>,
+    conversion: FdConversion,
+    rate_limit: Option<RateLimit>,
+    stats: RouteStats,
+}
+
+impl fmt::Debug for GatewayRoute {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GatewayRoute")
+            .field("src", &self.src)
+            .field("dst", &self.dst)
+            .field("filters", &self.filters)
+            .field("translation", &self.translation.is_some())
+            .field("conversion", &self.conversion)
+            .field("rate_limit", &self.rate_limit)
+            .field("stats", &self.stats)
+            .finish()
+    }
+}
+
+impl GatewayRoute {
+    /// Creates a route forwarding all frames from `src` to `dst`,
+    /// unchanged.
+    pub fn new(src: PortId, dst: PortId) -> Self {
+        Self {
+            src,
+            dst,
+            filters: Vec::new(),
+            translation: None,
+            conversion: FdConversion::default(),
+            rate_limit: None,
+            stats: RouteStats::default(),
+        }
+    }
+
+    /// Only forwards the frames that match any of the filters.
+    ///
+    /// The filters are matched the same way as socket filters.
+    pub fn with_filters(mut self, filters: &[CanFilter]) -> Self {
+        self.filters = filters.to_vec();
+        self
+    }
+
+    /// Translates the ID of each forwarded frame.
+    pub fn with_translation<T>(mut self, translation: T) -> Self
+    where
+        T: FnMut(CanId) -> CanId + Send + 'static,
+    {
+        self.translation = Some(Box::new(translation));
+        self
+    }
+
+    /// Sets the classic/FD conversion policy.
+    pub fn with_conversion(mut self, conversion: FdConversion) -> Self {
+        self.conversion = conversion;
+        self
+    }
+
+    /// Limits the route to forwarding at most `max` frames in each `per`
+    /// interval, allowing bursts of up to `max` frames.
+    pub fn with_rate_limit(mut self, max: u32, per: Duration) -> Self {
+        self.rate_limit = Some(RateLimit::new(max, per));
+        self
+    }
+
+    /// Gets the counts of frames handled by the route.
+    pub fn stats(&self) -> RouteStats {
+        self.stats
+    }
+
+    /// Processes a frame received from the source socket at `now`,
+    /// returning the frame to forward, if any.
+    fn process(&mut self, frame: &CanAnyFrame, now: Instant) -> Option<CanAnyFrame> {
+        if matches!(frame, CanAnyFrame::Error(_))
+            || !(self.filters.is_empty() || self.filters.iter().any(|f| f.matches(frame.id_word())))
+        {
+            return None;
+        }
+
+        let mut frame = match self.conversion.convert(*frame) {
+            Some(frame) => frame,
+            None => {
+                self.stats.unconvertible += 1;
+                return None;
+            }
+        };
+
+        if let Some(translation) = &mut self.translation {
+            let id = translation(frame.can_id());
+            frame.set_id(id);
+        }
+
+        if let Some(limit) = &mut self.rate_limit {
+            if !limit.take(now) {
+                self.stats.rate_limited += 1;
+                return None;
+            }
+        }
+
+        self.stats.forwarded += 1;
+        Some(frame)
+    }
+}
+
+// ===== Gateway =====
+
+/// A forwarding engine between sockets.
+#[derive(Debug, Default)]
+pub struct Gateway {
+    sockets: Vec<CanFdSocket>,
+    routes: Vec<GatewayRoute>,
+}
+
+impl Gateway {
+    /// Creates a gateway with no sockets or routes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a socket to the gateway, returning its port for use in routes.
+    pub fn add_socket(&mut self, sock: CanFdSocket) -> PortId {
+        self.sockets.push(sock);
+        PortId(self.sockets.len() - 1)
+    }
+
+    /// Adds a route.
+    ///
+    /// # Panics
+    ///
+    /// If the source or destination port is not in the gateway.
+    pub fn add_route(&mut self, route: GatewayRoute) {
+        assert!(
+            route.src.0 < self.sockets.len() && route.dst.0 < self.sockets.len(),
+            "route port not in the gateway"
+        );
+        self.routes.push(route);
+    }
+
+    /// Gets the routes, with their statistics.
+    pub fn routes(&self) -> &[GatewayRoute] {
+        &self.routes
+    }
+
+    /// Routes a frame received on a port, returning the frames to send and
+    /// the ports to send them on.
+    pub fn route_frame(&mut self, src: PortId, frame: &CanAnyFrame) -> Vec<(PortId, CanAnyFrame)> {
+        let now = Instant::now();
+        self.routes
+            .iter_mut()
+            .filter(|route| route.src == src)
+            .filter_map(|route| Some((route.dst, route.process(frame, now)?)))
+            .collect()
+    }
+
+    /// Waits up to the timeout for frames on any socket, and forwards them.
+    ///
+    /// A `None` timeout waits forever. Returns the number of frames sent.
+    pub fn run_once(&mut self, timeout: Option<Duration>) -> IoResult<usize> {
+        let mut fds: Vec<_> = self
+            .sockets
+            .iter()
+            .map(|sock| PollFd::new(sock.as_raw_fd(), PollFlags::POLLIN))
+            .collect();
+
+        let timeout = timeout.map_or(-1, |t| t.as_millis().min(i32::MAX as u128) as i32);
+        match poll(&mut fds, timeout) {
+            Ok(_) => (),
+            Err(nix::errno::Errno::EINTR) => return Ok(0),
+            Err(err) => return Err(err.into()),
+        }
+
+        let ready: Vec<_> = fds
+            .iter()
+            .enumerate()
+            .filter(|(_, fd)| {
+                fd.revents()
+                    .is_some_and(|ev| ev.contains(PollFlags::POLLIN))
+            })
+            .map(|(i, _)| PortId(i))
+            .collect();
+
+        let mut n = 0;
+        for src in ready {
+            let frame = match self.sockets[src.0].read_frame() {
+                Ok(frame) => frame,
+                Err(err) if err.kind() == ErrorKind::WouldBlock => continue,
+                Err(err) => return Err(err),
+            };
+            for (dst, frame) in self.route_frame(src, &frame) {
+                self.sockets[dst.0].write_frame_insist(&frame)?;
+                n += 1;
+            }
+        }
+        Ok(n)
+    }
+
+    /// Forwards frames until there's an error.
+    pub fn run(&mut self) -> IoResult<()> {
+        loop {
+            self.run_once(None)?;
+        }
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CanError, CanErrorFrame, CanFdFrame, CanFrame, EmbeddedFrame, StandardId};
+
+    #[test]
+    fn test_route() {
+        let (a, b) = (PortId(0), PortId(1));
+        let mut route = GatewayRoute::new(a, b)
+            .with_filters(&[CanFilter::new(0x100, 0x700)])
+            .with_translation(|id| CanId::standard(id.as_raw() as u16 + 0x400).unwrap())
+            .with_rate_limit(2, Duration::from_secs(1));
+
+        let now = Instant::now();
+        let frame = CanAnyFrame::from(CanFrame::from_raw_id(0x123, &[1]).unwrap());
+        let fwd = route.process(&frame, now).unwrap();
+        assert_eq!(0x523, fwd.raw_id());
+        assert_eq!(&[1], fwd.data());
+
+        let other = CanAnyFrame::from(CanFrame::from_raw_id(0x223, &[1]).unwrap());
+        assert!(route.process(&other, now).is_none());
+
+        assert!(route.process(&frame, now).is_some());
+        assert!(route.process(&frame, now).is_none());
+        assert!(route
+            .process(&frame, now + Duration::from_millis(500))
+            .is_some());
+
+        let stats = route.stats();
+        assert_eq!(3, stats.forwarded);
+        assert_eq!(1, stats.rate_limited);
+    }
+
+    #[test]
+    fn test_filter_rejection() {
+        let mut route = GatewayRoute::new(PortId(0), PortId(1))
+            .with_filters(&[CanFilter::new(0x100, 0x7FF), CanFilter::new(0x200, 0x7FF)]);
+
+        let now = Instant::now();
+        let frame = |id| CanAnyFrame::from(CanFrame::from_raw_id(id, &[1]).unwrap());
+        assert!(route.process(&frame(0x100), now).is_some());
+        assert!(route.process(&frame(0x200), now).is_some());
+        assert!(route.process(&frame(0x101), now).is_none());
+        assert!(route.process(&frame(0x300), now).is_none());
+
+        // Error frames are dropped, even by a route with no filters
+        let err = CanAnyFrame::Error(CanErrorFrame::from(CanError::BusOff));
+        assert!(route.process(&err, now).is_none());
+        let mut open = GatewayRoute::new(PortId(0), PortId(1));
+        assert!(open.process(&err, now).is_none());
+
+        // Rejected frames aren't counted against the route
+        assert_eq!(
+            RouteStats {
+                forwarded: 2,
+                ..RouteStats::default()
+            },
+            route.stats()
+        );
+    }
+
+    #[test]
+    fn test_route_conversion() {
+        let now = Instant::now();
+        let id = StandardId::new(0x100).unwrap();
+
+        let mut route =
+            GatewayRoute::new(PortId(0), PortId(1)).with_conversion(FdConversion::ToClassic);
+        let short = CanAnyFrame::from(CanFdFrame::new(id, &[1, 2, 3]).unwrap());
+        let fwd = route.process(&short, now).unwrap();
+        assert!(matches!(fwd, CanAnyFrame::Normal(_)));
+        assert_eq!(&[1, 2, 3], fwd.data());
+
+        // An FD frame with more than 8 bytes can't go to a classic bus
+        let long = CanAnyFrame::from(CanFdFrame::new(id, &[0; 12]).unwrap());
+        assert!(route.process(&long, now).is_none());
+        assert_eq!(1, route.stats().unconvertible);
+        assert_eq!(1, route.stats().forwarded);
+
+        let mut route = GatewayRoute::new(PortId(0), PortId(1))
+            .with_conversion(FdConversion::ToFd { brs: true });
+        let classic = CanAnyFrame::from(CanFrame::new(id, &[4, 5]).unwrap());
+        match route.process(&classic, now).unwrap() {
+            CanAnyFrame::Fd(fd) => {
+                assert!(fd.is_brs());
+                assert_eq!(&[4, 5], fd.data());
+            }
+            frame => panic!("unexpected frame: {:?}", frame),
+        }
+
+        // Remote frames have no FD form, and are forwarded as they are
+        let remote = CanAnyFrame::from(CanFrame::new_remote(id, 2).unwrap());
+        assert!(matches!(
+            route.process(&remote, now),
+            Some(CanAnyFrame::Remote(_))
+        ));
+        assert_eq!(0, route.stats().unconvertible);
+    }
+}
@@ -0,0 +1,398 @@
+// socketcan/src/generator.rs
+//
+// Generation of frames for load and soak testing.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! Frame generation for load and soak testing.
+//!
+//! The [`FrameGenerator`] produces a stream of frames much like the
+//! `cangen` utility from can-utils. The IDs and lengths can each be fixed,
+//! random, or incrementing over a range, and the payload can be random,
+//! an incrementing counter, or a fixed pattern. The generator is an
+//! iterator of frames, and can also send them to a socket with a gap
+//! between each one.
+//!
+//! ```no_run
+//! use socketcan::{
+//!     generator::{FrameGenerator, Pattern, Payload},
+//!     CanFdSocket, Socket,
+//! };
+//! use std::time::Duration;
+//!
+//! let sock = CanFdSocket::open("vcan0").unwrap();
+//!
+//! let mut gen = FrameGenerator::new()
+//!     .with_ids(0x100..=0x1FF, Pattern::Random)
+//!     .with_lengths(0..=8, Pattern::Increment)
+//!     .with_payload(Payload::Increment)
+//!     .with_gap(Duration::from_millis(1));
+//!
+//! gen.send(&sock, Some(1000)).unwrap();
+//! ```
+//!
+//! The random values come from a simple, fast, pseudo-random generator
+//! which is not suitable for cryptographic use. Giving the generator a
+//! seed makes the sequence of frames repeatable.
+
+use crate::{
+    frame::{fd_padded_len, AsPtr},
+    CanAnyFrame, CanDataFrame, CanFdFrame, CanId, Frame, Socket,
+};
+use std::{
+    io::Result as IoResult,
+    ops::RangeInclusive,
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+/// How a value is chosen from its range for each frame.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Pattern {
+    /// Always the start of the range
+    #[default]
+    Fixed,
+    /// A random value in the range
+    Random,
+    /// Counts up through the range, wrapping back to the start
+    Increment,
+}
+
+/// The payload data of the generated frames.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub enum Payload {
+    /// Random bytes
+    #[default]
+    Random,
+    /// A counter that increments with each frame, in little endian order,
+    /// like `cangen`
+    Increment,
+    /// A fixed pattern, repeated or truncated to fill the frame
+    Fixed(Vec<u8>),
+}
+
+/// A xorshift64* pseudo-random number generator.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Rng(u64);
+
+impl Rng {
+    pub(crate) fn new(seed: u64) -> Self {
+        // The state must never be zero
+        Self(seed | 1)
+    }
+
+    /// Creates a generator seeded from the system time.
+    pub(crate) fn from_time() -> Self {
+        let seed = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or_default();
+        Self::new(seed)
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// Gets a value in the inclusive range.
+    pub(crate) fn in_range(&mut self, lo: u64, hi: u64) -> u64 {
+        match (hi - lo).checked_add(1) {
+            Some(n) => lo + self.next_u64() % n,
+            None => self.next_u64(),
+        }
+    }
+
+    /// Returns true with the probability, from 0.0 to 1.0.
+    pub(crate) fn chance(&mut self, p: f64) -> bool {
+        // The top 53 bits make a uniform value in [0, 1)
+        p > 0.0 && ((self.next_u64() >> 11) as f64) < p * (1u64 << 53) as f64
+    }
+}
+
+/// A value chosen from a range according to a pattern.
+#[derive(Debug, Clone)]
+struct Sequence {
+    range: RangeInclusive<u64>,
+    pattern: Pattern,
+    next: u64,
+}
+
+impl Sequence {
+    fn new(range: RangeInclusive<u64>, pattern: Pattern) -> Self {
+        let next = *range.start();
+        Self {
+            range,
+            pattern,
+            next,
+        }
+    }
+
+    fn next(&mut self, rng: &mut Rng) -> u64 {
+        let (lo, hi) = (*self.range.start(), *self.range.end());
+        match self.pattern {
+            Pattern::Fixed => lo,
+            Pattern::Random => rng.in_range(lo, hi),
+            Pattern::Increment => {
+                let val = self.next;
+                self.next = if val >= hi { lo } else { val + 1 };
+                val
+            }
+        }
+    }
+}
+
+// ===== FrameGenerator =====
+
+/// A generator of frames, for load and soak testing.
+///
+/// By default this generates classic data frames with the standard ID
+/// 0x000 and eight random data bytes, with no gap between them.
+#[derive(Debug, Clone)]
+pub struct FrameGenerator {
+    ids: Sequence,
+    lens: Sequence,
+    payload: Payload,
+    extended: bool,
+    fd: bool,
+    gap: Duration,
+    count: u64,
+    rng: Rng,
+}
+
+impl Default for FrameGenerator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FrameGenerator {
+    /// Creates a generator with the default settings, seeded from the
+    /// system time.
+    pub fn new() -> Self {
+        Self {
+            ids: Sequence::new(0..=0, Pattern::Fixed),
+            lens: Sequence::new(8..=8, Pattern::Fixed),
+            payload: Payload::default(),
+            extended: false,
+            fd: false,
+            gap: Duration::ZERO,
+            count: 0,
+            rng: Rng::from_time(),
+        }
+    }
+
+    /// Sets the range of IDs and how they're chosen.
+    ///
+    /// The range is clamped to the IDs that are valid for the frame type.
+    pub fn with_ids(mut self, ids: RangeInclusive<u32>, pattern: Pattern) -> Self {
+        self.ids = Sequence::new(u64::from(*ids.start())..=u64::from(*ids.end()), pattern);
+        self
+    }
+
+    /// Uses a single ID for all the frames.
+    pub fn with_id(self, id: u32) -> Self {
+        self.with_ids(id..=id, Pattern::Fixed)
+    }
+
+    /// Sets whether the frames have extended IDs.
+    pub fn extended(mut self, on: bool) -> Self {
+        self.extended = on;
+        self
+    }
+
+    /// Sets whether to generate FD frames.
+    ///
+    /// FD frames can carry up to 64 bytes. Lengths that are not valid for
+    /// an FD frame are rounded up to the next valid length.
+    pub fn fd(mut self, on: bool) -> Self {
+        self.fd = on;
+        self
+    }
+
+    /// Sets the range of payload lengths, in bytes, and how they're chosen.
+    ///
+    /// The range is clamped to the lengths that are valid for the frame
+    /// type.
+    pub fn with_lengths(mut self, lens: RangeInclusive<usize>, pattern: Pattern) -> Self {
+        self.lens = Sequence::new(*lens.start() as u64..=*lens.end() as u64, pattern);
+        self
+    }
+
+    /// Uses a single payload length for all the frames.
+    pub fn with_len(self, len: usize) -> Self {
+        self.with_lengths(len..=len, Pattern::Fixed)
+    }
+
+    /// Sets the payload data.
+    pub fn with_payload(mut self, payload: Payload) -> Self {
+        self.payload = payload;
+        self
+    }
+
+    /// Sets the gap between frames when sending them.
+    pub fn with_gap(mut self, gap: Duration) -> Self {
+        self.gap = gap;
+        self
+    }
+
+    /// Seeds the random generator, to make the frames repeatable.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = Rng::new(seed);
+        self
+    }
+
+    /// Gets the gap between frames.
+    pub fn gap(&self) -> Duration {
+        self.gap
+    }
+
+    /// Gets the number of frames generated so far.
+    pub fn generated(&self) -> u64 {
+        self.count
+    }
+
+    /// Generates the next frame.
+    pub fn next_frame(&mut self) -> CanAnyFrame {
+        let max_id = if self.extended { 0x1FFF_FFFF } else { 0x7FF };
+        let raw = self.ids.next(&mut self.rng).min(max_id) as u32;
+        let id = if self.extended {
+            CanId::extended(raw)
+        } else {
+            CanId::standard(raw as u16)
+        }
+        .expect("ID clamped to valid range");
+
+        let max_len = if self.fd { 64 } else { 8 };
+        let mut len = self.lens.next(&mut self.rng).min(max_len) as usize;
+        if self.fd {
+            len = fd_padded_len(len).unwrap_or(64);
+        }
+
+        let mut data = [0u8; 64];
+        let data = &mut data[..len];
+        match &self.payload {
+            Payload::Random => data.chunks_mut(8).for_each(|chunk| {
+                let n = chunk.len();
+                chunk.copy_from_slice(&self.rng.next_u64().to_le_bytes()[..n]);
+            }),
+            Payload::Increment => {
+                let n = len.min(8);
+                data[..n].copy_from_slice(&self.count.to_le_bytes()[..n]);
+            }
+            Payload::Fixed(pat) if !pat.is_empty() => data
+                .iter_mut()
+                .zip(pat.iter().cycle())
+                .for_each(|(b, p)| *b = *p),
+            Payload::Fixed(_) => (),
+        }
+        self.count += 1;
+
+        if self.fd {
+            CanFdFrame::try_new(id, data)
+                .expect("FD length clamped to valid range")
+                .into()
+        } else {
+            CanDataFrame::try_new(id, data)
+                .expect("length clamped to valid range")
+                .into()
+        }
+    }
+
+    /// Sends frames to the socket, waiting the gap between each one.
+    ///
+    /// This sends `count` frames, or sends forever if the count is `None`.
+    /// The gap is measured from the start of one frame to the start of the
+    /// next, so the rate doesn't drift with the time taken to send.
+    pub fn send<S>(&mut self, sock: &S, count: Option<usize>) -> IoResult<()>
+    where
+        S: Socket,
+        CanAnyFrame: Into<S::FrameType> + AsPtr,
+    {
+        let mut deadline = Instant::now();
+        let mut n = 0;
+
+        while count.map_or(true, |count| n < count) {
+            sock.write_frame_insist(&self.next_frame())?;
+            n += 1;
+
+            if !self.gap.is_zero() {
+                deadline += self.gap;
+                let now = Instant::now();
+                if deadline > now {
+                    thread::sleep(deadline - now);
+                } else {
+                    deadline = now;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Iterator for FrameGenerator {
+    type Item = CanAnyFrame;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.next_frame())
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EmbeddedFrame;
+
+    #[test]
+    fn test_increment() {
+        let frames: Vec<_> = FrameGenerator::new()
+            .with_ids(0x7FE..=0x900, Pattern::Increment)
+            .with_lengths(2..=3, Pattern::Increment)
+            .with_payload(Payload::Increment)
+            .take(3)
+            .collect();
+
+        let ids: Vec<_> = frames.iter().map(|f| f.raw_id()).collect();
+        assert_eq!(vec![0x7FE, 0x7FF, 0x7FF], ids);
+        assert_eq!(&[0, 0], frames[0].data());
+        assert_eq!(&[1, 0, 0], frames[1].data());
+        assert_eq!(&[2, 0], frames[2].data());
+
+        let frame = FrameGenerator::new()
+            .fd(true)
+            .with_len(9)
+            .with_payload(Payload::Fixed(vec![0xAA, 0x55]))
+            .next_frame();
+        assert!(matches!(frame, CanAnyFrame::Fd(_)));
+        assert_eq!(12, frame.len());
+        assert_eq!(&[0xAA, 0x55, 0xAA], &frame.data()[..3]);
+    }
+
+    #[test]
+    fn test_random() {
+        let gen = FrameGenerator::new()
+            .extended(true)
+            .with_ids(0x1000..=0x1FFF, Pattern::Random)
+            .with_lengths(0..=8, Pattern::Random)
+            .with_seed(42);
+
+        let a: Vec<_> = gen.clone().take(100).collect();
+        let b: Vec<_> = gen.take(100).collect();
+        assert_eq!(a, b);
+
+        for frame in a {
+            assert!(frame.is_extended());
+            assert!((0x1000..=0x1FFF).contains(&frame.raw_id()));
+            assert!(frame.len() <= 8);
+        }
+    }
+}
@@ -0,0 +1,243 @@
+// socketcan/src/heartbeat.rs
+//
+// Periodic alive messages.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! Periodic alive messages.
+//!
+//! Many ECUs expect to see a cyclic "alive" message from their peers, and
+//! drop into a limp-home mode when it stops or stops changing. An alive
+//! message usually carries a rolling counter, and often a checksum over the
+//! payload. The [`Heartbeat`] describes such a message and produces each
+//! frame in turn, and [`Heartbeat::start`] sends it from a background
+//! [`Scheduler`] that can be paused and resumed.
+//!
+//! ```no_run
+//! use socketcan::{
+//!     heartbeat::{Checksum, Heartbeat},
+//!     CanFrame, CanSocket, Frame, Socket,
+//! };
+//! use std::time::Duration;
+//!
+//! let sock = CanSocket::open("can0").unwrap();
+//! let frame = CanFrame::from_raw_id(0x510, &[0; 8]).unwrap();
+//!
+//! let tx = Heartbeat::new(frame, Duration::from_millis(100))
+//!     .with_counter(6, 0x0F)
+//!     .with_checksum(7, Checksum::Crc8)
+//!     .start(sock)
+//!     .unwrap();
+//!
+//! std::thread::sleep(Duration::from_secs(10));
+//! tx.stop().unwrap();
+//! ```
+
+use crate::{
+    backend::CanTransmitter,
+    e2e::crc8_sae_j1850,
+    scheduler::{Scheduler, TaskId},
+    CanFrame, EmbeddedFrame, IoResult,
+};
+use std::time::Duration;
+
+/// A checksum over the bytes of a message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Checksum {
+    /// The XOR of the bytes
+    Xor,
+    /// The sum of the bytes, modulo 256
+    Sum,
+    /// The SAE J1850 CRC-8 (polynomial 0x1D, initial value and final XOR
+    /// of 0xFF)
+    Crc8,
+}
+
+impl Checksum {
+    /// Computes the checksum of the bytes.
+    pub fn compute(&self, data: &[u8]) -> u8 {
+        use Checksum::*;
+        match self {
+            Xor => data.iter().fold(0, |acc, b| acc ^ b),
+            Sum => data.iter().fold(0, |acc: u8, b| acc.wrapping_add(*b)),
+            Crc8 => crc8_sae_j1850(0xFF, data) ^ 0xFF,
+        }
+    }
+}
+
+// ===== Heartbeat =====
+
+/// A periodic alive message with an optional rolling counter and
+/// checksum.
+#[allow(missing_copy_implementations)]
+#[derive(Debug, Clone)]
+pub struct Heartbeat {
+    frame: CanFrame,
+    period: Duration,
+    counter: Option<(usize, u8)>,
+    checksum: Option<(usize, Checksum)>,
+    count: u8,
+}
+
+impl Heartbeat {
+    /// Creates a heartbeat that sends the frame at the period.
+    ///
+    /// The frame is the template for each one sent; the counter and
+    /// checksum are written into a copy of its payload.
+    pub fn new(frame: CanFrame, period: Duration) -> Self {
+        Self {
+            frame,
+            period,
+            counter: None,
+            checksum: None,
+            count: 0,
+        }
+    }
+
+    /// Adds a rolling counter in the bits of the mask in a byte of the
+    /// payload. The counter counts up through the values that fit in the
+    /// mask, then wraps around to zero.
+    ///
+    /// # Panics
+    ///
+    /// If the byte isn't in the payload, or the mask isn't a contiguous
+    /// run of bits.
+    pub fn with_counter(mut self, byte: usize, mask: u8) -> Self {
+        assert!(byte < self.frame.data().len(), "counter byte out of range");
+        let shifted = mask.checked_shr(mask.trailing_zeros()).unwrap_or(0);
+        assert!(
+            shifted != 0 && shifted & shifted.wrapping_add(1) == 0,
+            "the counter mask must be a contiguous run of bits"
+        );
+        self.counter = Some((byte, mask));
+        self
+    }
+
+    /// Adds a checksum in a byte of the payload, computed over all the
+    /// other bytes after the counter is updated.
+    ///
+    /// # Panics
+    ///
+    /// If the byte isn't in the payload.
+    pub fn with_checksum(mut self, byte: usize, checksum: Checksum) -> Self {
+        assert!(byte < self.frame.data().len(), "checksum byte out of range");
+        self.checksum = Some((byte, checksum));
+        self
+    }
+
+    /// Gets the period.
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+
+    /// Produces the next frame to send, and advances the counter.
+    pub fn next_frame(&mut self) -> CanFrame {
+        let mut data = self.frame.data().to_vec();
+
+        if let Some((byte, mask)) = self.counter {
+            let shift = mask.trailing_zeros();
+            data[byte] = (data[byte] & !mask) | ((self.count << shift) & mask);
+            self.count = self.count.wrapping_add(1) & (mask >> shift);
+        }
+        if let Some((byte, checksum)) = self.checksum {
+            let others: Vec<u8> = data
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| *i != byte)
+                .map(|(_, b)| *b)
+                .collect();
+            data[byte] = checksum.compute(&others);
+        }
+
+        // The template already holds a valid ID and payload length
+        CanFrame::new(self.frame.id(), &data).unwrap_or(self.frame)
+    }
+
+    /// Adds the heartbeat to a running scheduler.
+    pub fn add_to(mut self, sched: &Scheduler<CanFrame>) -> TaskId {
+        sched.add(self.period, Duration::ZERO, move || self.next_frame())
+    }
+
+    /// Starts sending the heartbeat on the socket, from a scheduler
+    /// thread of its own.
+    pub fn start<S>(self, sock: S) -> IoResult<HeartbeatTx>
+    where
+        S: CanTransmitter<CanFrame> + Send + 'static,
+    {
+        let sched = Scheduler::start(sock)?;
+        let task = self.add_to(&sched);
+        Ok(HeartbeatTx { sched, task })
+    }
+}
+
+// ===== HeartbeatTx =====
+
+/// A heartbeat being sent in the background.
+///
+/// The heartbeat is stopped when this is dropped.
+#[derive(Debug)]
+pub struct HeartbeatTx {
+    sched: Scheduler<CanFrame>,
+    task: TaskId,
+}
+
+impl HeartbeatTx {
+    /// Pauses sending the heartbeat.
+    pub fn pause(&self) {
+        self.sched.disable(self.task);
+    }
+
+    /// Resumes sending the heartbeat, starting with a frame right away.
+    ///
+    /// The counter carries on from where it was paused.
+    pub fn resume(&self) {
+        self.sched.enable(self.task);
+    }
+
+    /// Determines if the heartbeat is being sent.
+    pub fn is_running(&self) -> bool {
+        self.sched.is_enabled(self.task).unwrap_or(false)
+    }
+
+    /// Stops the heartbeat, returning any error that stopped it early,
+    /// such as a failure writing to the socket.
+    pub fn stop(self) -> IoResult<()> {
+        self.sched.stop()
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Frame;
+
+    #[test]
+    fn test_checksum() {
+        let data = b"123456789";
+        assert_eq!(0x4B, Checksum::Crc8.compute(data));
+        assert_eq!(0x31, Checksum::Xor.compute(data));
+        assert_eq!(0xDD, Checksum::Sum.compute(data));
+    }
+
+    #[test]
+    fn test_heartbeat() {
+        let frame = CanFrame::from_raw_id(0x510, &[0xAA, 0xF0, 0]).unwrap();
+        let mut hb = Heartbeat::new(frame, Duration::from_millis(100))
+            .with_counter(1, 0x0C)
+            .with_checksum(2, Checksum::Xor);
+
+        let counters: Vec<u8> = (0..5).map(|_| hb.next_frame().data()[1]).collect();
+        assert_eq!(vec![0xF0, 0xF4, 0xF8, 0xFC, 0xF0], counters);
+
+        let frame = hb.next_frame();
+        assert_eq!(0x510, frame.raw_id());
+        assert_eq!(&[0xAA, 0xF4, 0xAA ^ 0xF4], frame.data());
+    }
+}
@@ -0,0 +1,342 @@
+// socketcan/src/hex.rs
+//
+// Fast hex encoding and decoding for the logging and parsing paths.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! Fast hex encoding and decoding.
+//!
+//! Writing or parsing a candump log of a busy FD bus spends most of its
+//! time turning payload bytes into hex digits and back. These functions
+//! work on caller-supplied buffers, so they never allocate, and pick the
+//! fastest implementation for the CPU at runtime: SSSE3 on x86-64
+//! processors that have it, converting 16 bytes at a time, or otherwise a
+//! lookup table, converting a byte at a time without any branches.
+//!
+//! The frame formatting and the candump parser use these internally.
+//!
+//! ```
+//! use socketcan::hex;
+//!
+//! let mut text = [0u8; 8];
+//! let s = hex::encode_into(&[0xDE, 0xAD, 0xBE, 0xEF], &mut text, true).unwrap();
+//! assert_eq!("DEADBEEF", s);
+//!
+//! let mut data = [0u8; 4];
+//! let n = hex::decode_into(b"deadBEEF", &mut data).unwrap();
+//! assert_eq!(&[0xDE, 0xAD, 0xBE, 0xEF], &data[..n]);
+//! ```
+
+use std::sync::OnceLock;
+use thiserror::Error;
+
+/// An error converting hex digits.
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HexError {
+    /// The text has an odd number of digits
+    #[error("Odd number of hex digits")]
+    OddLength,
+    /// The text has something other than a hex digit, at the index
+    #[error("Invalid hex digit at index {0}")]
+    InvalidDigit(usize),
+    /// The output buffer is too small for the result
+    #[error("Buffer too small for the hex conversion")]
+    BufferTooSmall,
+}
+
+/// The implementation used for the conversions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Backend {
+    /// A lookup table, a byte at a time
+    Table,
+    /// SSSE3 vector instructions, 16 bytes at a time
+    Ssse3,
+}
+
+/// Gets the implementation chosen for this CPU.
+pub fn backend() -> Backend {
+    static BACKEND: OnceLock<Backend> = OnceLock::new();
+    *BACKEND.get_or_init(|| {
+        #[cfg(target_arch = "x86_64")]
+        if is_x86_feature_detected!("ssse3") {
+            return Backend::Ssse3;
+        }
+        Backend::Table
+    })
+}
+
+// ===== Tables =====
+
+const UPPER: &[u8; 16] = b"0123456789ABCDEF";
+const LOWER: &[u8; 16] = b"0123456789abcdef";
+
+// The two digits for each byte value
+const fn encode_table(digits: &[u8; 16]) -> [[u8; 2]; 256] {
+    let mut table = [[0u8; 2]; 256];
+    let mut i = 0;
+    while i < 256 {
+        table[i] = [digits[i >> 4], digits[i & 0xF]];
+        i += 1;
+    }
+    table
+}
+
+static ENCODE_UPPER: [[u8; 2]; 256] = encode_table(UPPER);
+static ENCODE_LOWER: [[u8; 2]; 256] = encode_table(LOWER);
+
+// The value of each character as a digit, or 0xFF if it isn't one
+const fn decode_table() -> [u8; 256] {
+    let mut table = [0xFFu8; 256];
+    let mut i = 0;
+    while i < 16 {
+        table[UPPER[i] as usize] = i as u8;
+        table[LOWER[i] as usize] = i as u8;
+        i += 1;
+    }
+    table
+}
+
+static DECODE: [u8; 256] = decode_table();
+
+// ===== Encoding =====
+
+/// Encodes the bytes as hex digits into the buffer, which must hold two
+/// digits per byte, and returns the digits as a string.
+pub fn encode_into<'a>(src: &[u8], dst: &'a mut [u8], upper: bool) -> Result<&'a str, HexError> {
+    let dst = dst
+        .get_mut(..2 * src.len())
+        .ok_or(HexError::BufferTooSmall)?;
+
+    let done = match backend() {
+        #[cfg(target_arch = "x86_64")]
+        // SAFETY: The CPU was checked for SSSE3 when it was selected
+        Backend::Ssse3 => unsafe { x86::encode(src, dst, upper) },
+        _ => 0,
+    };
+    encode_table_into(&src[done..], &mut dst[2 * done..], upper);
+
+    // SAFETY: All the bytes are ASCII hex digits
+    Ok(unsafe { std::str::from_utf8_unchecked(dst) })
+}
+
+/// Gets the hex digit for the low four bits of the value.
+pub(crate) fn encode_digit(val: u8, upper: bool) -> u8 {
+    let digits = if upper { UPPER } else { LOWER };
+    digits[usize::from(val & 0xF)]
+}
+
+/// Gets the two hex digits for a byte.
+pub(crate) fn encode_byte(b: u8, upper: bool) -> [u8; 2] {
+    let table = if upper { &ENCODE_UPPER } else { &ENCODE_LOWER };
+    table[usize::from(b)]
+}
+
+fn encode_table_into(src: &[u8], dst: &mut [u8], upper: bool) {
+    let table = if upper { &ENCODE_UPPER } else { &ENCODE_LOWER };
+    for (b, out) in src.iter().zip(dst.chunks_exact_mut(2)) {
+        out.copy_from_slice(&table[usize::from(*b)]);
+    }
+}
+
+// ===== Decoding =====
+
+/// Decodes pairs of hex digits, in either case, into the buffer, and
+/// returns the number of bytes written.
+pub fn decode_into(src: &[u8], dst: &mut [u8]) -> Result<usize, HexError> {
+    if src.len() % 2 != 0 {
+        return Err(HexError::OddLength);
+    }
+    let n = src.len() / 2;
+    let dst = dst.get_mut(..n).ok_or(HexError::BufferTooSmall)?;
+
+    let done = match backend() {
+        #[cfg(target_arch = "x86_64")]
+        // SAFETY: The CPU was checked for SSSE3 when it was selected
+        Backend::Ssse3 => unsafe { x86::decode(src, dst) },
+        _ => 0,
+    };
+    // The vector code stops at the first chunk with a bad digit, and
+    // leaves it to the table to find it.
+    decode_table_into(&src[2 * done..], &mut dst[done..])
+        .map_err(|i| HexError::InvalidDigit(2 * done + i))?;
+    Ok(n)
+}
+
+/// Gets the value of a single hex digit, in either case.
+pub(crate) fn decode_digit(c: u8) -> Option<u8> {
+    let val = DECODE[usize::from(c)];
+    (val <= 0xF).then_some(val)
+}
+
+// Decodes with the table, or gets the index of the first bad digit
+fn decode_table_into(src: &[u8], dst: &mut [u8]) -> Result<(), usize> {
+    for (i, (pair, out)) in src.chunks_exact(2).zip(dst.iter_mut()).enumerate() {
+        let (hi, lo) = (DECODE[usize::from(pair[0])], DECODE[usize::from(pair[1])]);
+        if (hi | lo) & 0xF0 != 0 {
+            return Err(2 * i + usize::from(hi <= 0xF));
+        }
+        *out = hi << 4 | lo;
+    }
+    Ok(())
+}
+
+// ===== x86 =====
+
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    use std::arch::x86_64::*;
+
+    /// Encodes whole blocks of 16 bytes, returning the number of bytes
+    /// done.
+    #[target_feature(enable = "ssse3")]
+    pub(super) unsafe fn encode(src: &[u8], dst: &mut [u8], upper: bool) -> usize {
+        debug_assert!(dst.len() >= 2 * src.len());
+        let digits = if upper { super::UPPER } else { super::LOWER };
+        let mut i = 0;
+        // SAFETY: The loads and stores are unaligned, and all within the
+        // slices: 16 bytes from the source, and 32 to the destination,
+        // which holds two digits per source byte.
+        unsafe {
+            let lut = _mm_loadu_si128(digits.as_ptr() as *const __m128i);
+            let mask = _mm_set1_epi8(0x0F);
+            while i + 16 <= src.len() {
+                let v = _mm_loadu_si128(src.as_ptr().add(i) as *const __m128i);
+                let hi = _mm_shuffle_epi8(lut, _mm_and_si128(_mm_srli_epi16(v, 4), mask));
+                let lo = _mm_shuffle_epi8(lut, _mm_and_si128(v, mask));
+                let out = dst.as_mut_ptr().add(2 * i) as *mut __m128i;
+                _mm_storeu_si128(out, _mm_unpacklo_epi8(hi, lo));
+                _mm_storeu_si128(out.add(1), _mm_unpackhi_epi8(hi, lo));
+                i += 16;
+            }
+        }
+        i
+    }
+
+    /// Decodes whole blocks of 16 digits, returning the number of bytes
+    /// done. This stops early at a block with anything but hex digits.
+    #[target_feature(enable = "ssse3")]
+    pub(super) unsafe fn decode(src: &[u8], dst: &mut [u8]) -> usize {
+        debug_assert!(2 * dst.len() >= src.len());
+        let mut i = 0;
+        // SAFETY: The loads and stores are unaligned, and all within the
+        // slices: 16 digits from the source, and 8 bytes to the
+        // destination, which holds one byte per two digits.
+        unsafe {
+            while 2 * i + 16 <= src.len() {
+                let c = _mm_loadu_si128(src.as_ptr().add(2 * i) as *const __m128i);
+
+                // Each of these wraps around to a large value for a character
+                // outside its range
+                let d = _mm_sub_epi8(c, _mm_set1_epi8(b'0' as i8));
+                let is_digit = _mm_cmpeq_epi8(_mm_min_epu8(d, _mm_set1_epi8(9)), d);
+                let l = _mm_sub_epi8(
+                    _mm_or_si128(c, _mm_set1_epi8(0x20)),
+                    _mm_set1_epi8(b'a' as i8),
+                );
+                let is_letter = _mm_cmpeq_epi8(_mm_min_epu8(l, _mm_set1_epi8(5)), l);
+                if _mm_movemask_epi8(_mm_or_si128(is_digit, is_letter)) != 0xFFFF {
+                    break;
+                }
+
+                let val = _mm_or_si128(
+                    _mm_and_si128(is_digit, d),
+                    _mm_and_si128(is_letter, _mm_add_epi8(l, _mm_set1_epi8(10))),
+                );
+                // Each pair of digits becomes (16 * hi + lo), as a 16-bit value
+                let pairs = _mm_maddubs_epi16(val, _mm_set1_epi16(0x0110));
+                let bytes = _mm_packus_epi16(pairs, pairs);
+                _mm_storel_epi64(dst.as_mut_ptr().add(i) as *mut __m128i, bytes);
+                i += 8;
+            }
+        }
+        i
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data(n: usize) -> Vec<u8> {
+        (0..n).map(|i| (i * 151 + 7) as u8).collect()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut text = [0u8; 600];
+        let mut bytes = [0u8; 300];
+        for n in 0..300 {
+            let src = data(n);
+            for upper in [true, false] {
+                let s = encode_into(&src, &mut text, upper).unwrap();
+                let expected: String = src
+                    .iter()
+                    .map(|b| match upper {
+                        true => format!("{:02X}", b),
+                        false => format!("{:02x}", b),
+                    })
+                    .collect();
+                assert_eq!(expected, s);
+
+                assert_eq!(Ok(n), decode_into(s.as_bytes(), &mut bytes));
+                assert_eq!(&src[..], &bytes[..n]);
+
+                // And the table on its own, whatever the CPU
+                let mut table_text = vec![0u8; 2 * n];
+                encode_table_into(&src, &mut table_text, upper);
+                assert_eq!(s.as_bytes(), &table_text[..]);
+                bytes.fill(0);
+                decode_table_into(&table_text, &mut bytes).unwrap();
+                assert_eq!(&src[..], &bytes[..n]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_digits() {
+        assert_eq!(b'C', encode_digit(0xAC, true));
+        assert_eq!(b'c', encode_digit(0x0C, false));
+        assert_eq!(*b"A5", encode_byte(0xA5, true));
+        assert_eq!(*b"a5", encode_byte(0xA5, false));
+
+        assert_eq!(Some(0xA), decode_digit(b'a'));
+        assert_eq!(Some(0xF), decode_digit(b'F'));
+        assert_eq!(Some(9), decode_digit(b'9'));
+        assert_eq!(None, decode_digit(b'g'));
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut bytes = [0u8; 64];
+        assert_eq!(Err(HexError::OddLength), decode_into(b"ABC", &mut bytes));
+        assert_eq!(
+            Err(HexError::BufferTooSmall),
+            decode_into(b"0011", &mut bytes[..1])
+        );
+        assert_eq!(
+            Err(HexError::BufferTooSmall),
+            encode_into(&[0, 1], &mut [0u8; 3], true).map(|_| ())
+        );
+
+        // A bad character at every position, in and out of vector blocks
+        let mut text = [0u8; 80];
+        encode_into(&data(40), &mut text, false).unwrap();
+        for i in 0..text.len() {
+            for bad in [b'g', b'G', b'/', b':', b'@', b'`', b' ', 0x80, 0xE1] {
+                let mut src = text;
+                src[i] = bad;
+                assert_eq!(
+                    Err(HexError::InvalidDigit(i)),
+                    decode_into(&src, &mut bytes)
+                );
+            }
+        }
+    }
+}
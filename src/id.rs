@@ -0,0 +1,452 @@
+// socketcan/src/id.rs
+//
+// A unified CAN identifier type.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! A unified CAN identifier.
+//!
+//! The [`CanId`] wraps the standard (11-bit) and extended (29-bit) IDs from
+//! _embedded_can_ into a single type with the helpers that are commonly
+//! needed when working with SocketCAN, like converting to and from the
+//! composite ID word used by the kernel, and parsing from hex strings.
+//!
+//! Since it converts into an [`Id`], a `CanId` can be used anywhere in the
+//! crate that takes `impl Into<Id>`, such as creating or updating frames.
+//!
+//! ```
+//! use socketcan::{CanId, CanFrame, Frame};
+//!
+//! let id: CanId = "18FEF100".parse().unwrap();
+//! assert!(id.is_extended());
+//!
+//! let frame = CanFrame::try_new(id, &[1, 2, 3]).unwrap();
+//! assert_eq!(id, frame.can_id());
+//! ```
+//!
+//! An ID can also be decomposed into the fields used by the common higher
+//! layer protocols, with [`CanId::j1939`] for 29-bit SAE J1939 IDs and
+//! [`CanId::canopen`] for 11-bit CANopen IDs:
+//!
+//! ```
+//! use socketcan::CanId;
+//!
+//! let id: CanId = "18FEF100".parse().unwrap();
+//! let j1939 = id.j1939().unwrap();
+//! assert_eq!(6, j1939.priority());
+//! assert_eq!(0xFEF1, j1939.pgn());
+//! assert_eq!(0x00, j1939.source());
+//! ```
+
+use crate::{
+    frame::{id_to_canid_t, CAN_EFF_FLAG, CAN_EFF_MASK, CAN_SFF_MASK},
+    ConstructionError,
+};
+use embedded_can::{ExtendedId, Id, StandardId};
+use libc::canid_t;
+use std::{fmt, num::ParseIntError, str::FromStr};
+use thiserror::Error;
+
+/// An error parsing a CAN ID from a string.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ParseIdError {
+    /// The string is not a valid hex number
+    #[error("invalid hex CAN ID: {0}")]
+    InvalidHex(#[from] ParseIntError),
+    /// The value is too large for the type of ID
+    #[error(transparent)]
+    TooLarge(#[from] ConstructionError),
+}
+
+/// A CAN identifier, either a standard 11-bit or an extended 29-bit ID.
+///
+/// The ordering of IDs is by bus priority, the same as [`Id`], so the ID
+/// that would win arbitration on the bus sorts first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CanId(Id);
+
+impl CanId {
+    /// Creates a standard, 11-bit ID.
+    pub const fn standard(id: u16) -> Result<Self, ConstructionError> {
+        match StandardId::new(id) {
+            Some(id) => Ok(Self(Id::Standard(id))),
+            None => Err(ConstructionError::IDTooLarge {
+                id: id as u32,
+                extended: false,
+            }),
+        }
+    }
+
+    /// Creates an extended, 29-bit ID.
+    pub const fn extended(id: u32) -> Result<Self, ConstructionError> {
+        match ExtendedId::new(id) {
+            Some(id) => Ok(Self(Id::Extended(id))),
+            None => Err(ConstructionError::IDTooLarge { id, extended: true }),
+        }
+    }
+
+    /// Creates an ID from a raw integer value.
+    ///
+    /// If the `id` is <= 0x7FF, it's assumed to be a standard ID, otherwise
+    /// it is created as an extended ID.
+    ///
+    /// This is the one place that the crate resolves a raw ID, and it can
+    /// be used in a const context.
+    pub const fn from_raw(id: u32) -> Result<Self, ConstructionError> {
+        if id <= CAN_SFF_MASK {
+            Self::standard(id as u16)
+        } else {
+            Self::extended(id)
+        }
+    }
+
+    /// Creates an ID from the composite SocketCAN ID word.
+    ///
+    /// This uses the EFF flag to determine the type of ID, and ignores the
+    /// RTR and ERR flags.
+    pub fn from_id_word(word: canid_t) -> Self {
+        if word & CAN_EFF_FLAG != 0 {
+            Self(ExtendedId::new(word & CAN_EFF_MASK).unwrap().into())
+        } else {
+            Self(
+                StandardId::new((word & CAN_SFF_MASK) as u16)
+                    .unwrap()
+                    .into(),
+            )
+        }
+    }
+
+    /// Gets the composite SocketCAN ID word, with the EFF flag set for
+    /// an extended ID.
+    pub fn id_word(&self) -> canid_t {
+        id_to_canid_t(self.0)
+    }
+
+    /// Gets the raw integer value of the ID, without any flags.
+    pub fn as_raw(&self) -> u32 {
+        match self.0 {
+            Id::Standard(id) => id.as_raw().into(),
+            Id::Extended(id) => id.as_raw(),
+        }
+    }
+
+    /// Determines if this is an extended, 29-bit ID.
+    pub const fn is_extended(&self) -> bool {
+        matches!(self.0, Id::Extended(_))
+    }
+
+    /// Determines if this is a standard, 11-bit ID.
+    pub fn is_standard(&self) -> bool {
+        !self.is_extended()
+    }
+
+    /// Gets the mask of the valid bits for this type of ID.
+    pub fn mask(&self) -> u32 {
+        if self.is_extended() {
+            CAN_EFF_MASK
+        } else {
+            CAN_SFF_MASK
+        }
+    }
+
+    /// Gets the ID as the embedded HAL Id type.
+    pub fn as_id(&self) -> Id {
+        self.0
+    }
+
+    /// Decomposes an extended ID into its SAE J1939 fields.
+    ///
+    /// This is `None` for a standard ID.
+    pub fn j1939(&self) -> Option<J1939Id> {
+        match self.0 {
+            Id::Extended(id) => Some(J1939Id(id.as_raw())),
+            Id::Standard(_) => None,
+        }
+    }
+
+    /// Decomposes a standard ID into its CANopen function code and node ID.
+    ///
+    /// This is `None` for an extended ID.
+    pub fn canopen(&self) -> Option<CanOpenId> {
+        match self.0 {
+            Id::Standard(id) => Some(CanOpenId(id.as_raw())),
+            Id::Extended(_) => None,
+        }
+    }
+}
+
+// ===== J1939Id =====
+
+/// The PDU format values at or above this are broadcast (PDU2) messages.
+const J1939_PDU2_MIN: u8 = 240;
+
+/// A view of a 29-bit ID as an SAE J1939 identifier.
+///
+/// The ID is laid out as a 3-bit priority, an 18-bit parameter group number
+/// (PGN), and an 8-bit source address. For PDU1 (peer-to-peer) messages,
+/// the low byte of the PGN is the destination address, and is reported as
+/// zero in the PGN.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct J1939Id(u32);
+
+impl J1939Id {
+    /// Creates a J1939 ID from its parts.
+    ///
+    /// For a PDU1 PGN, the destination address should be in the low byte of
+    /// the PGN. Any bits out of range for each field are ignored.
+    pub fn new(priority: u8, pgn: u32, source: u8) -> Self {
+        Self((u32::from(priority & 0x07) << 26) | ((pgn & 0x3FFFF) << 8) | u32::from(source))
+    }
+
+    /// Gets the 3-bit priority, where 0 is the highest.
+    pub fn priority(&self) -> u8 {
+        ((self.0 >> 26) & 0x07) as u8
+    }
+
+    /// Gets the PDU format (PF) byte.
+    pub fn pdu_format(&self) -> u8 {
+        (self.0 >> 16) as u8
+    }
+
+    /// Gets the PDU specific (PS) byte, which is the destination address
+    /// for a PDU1 message, or the group extension for PDU2.
+    pub fn pdu_specific(&self) -> u8 {
+        (self.0 >> 8) as u8
+    }
+
+    /// Determines if this is a broadcast, PDU2, message.
+    pub fn is_pdu2(&self) -> bool {
+        self.pdu_format() >= J1939_PDU2_MIN
+    }
+
+    /// Gets the parameter group number (PGN).
+    ///
+    /// For a PDU1 message, the destination address is masked off.
+    pub fn pgn(&self) -> u32 {
+        let pgn = (self.0 >> 8) & 0x3FFFF;
+        if self.is_pdu2() {
+            pgn
+        } else {
+            pgn & !0xFF
+        }
+    }
+
+    /// Gets the destination address of a PDU1 message.
+    ///
+    /// This is `None` for a broadcast, PDU2, message.
+    pub fn destination(&self) -> Option<u8> {
+        (!self.is_pdu2()).then_some(self.pdu_specific())
+    }
+
+    /// Gets the source address.
+    pub fn source(&self) -> u8 {
+        self.0 as u8
+    }
+
+    /// Gets the full CAN ID.
+    pub fn can_id(&self) -> CanId {
+        CanId(ExtendedId::new(self.0).unwrap().into())
+    }
+}
+
+impl From<J1939Id> for CanId {
+    fn from(id: J1939Id) -> Self {
+        id.can_id()
+    }
+}
+
+// ===== CanOpenId =====
+
+/// A view of an 11-bit ID as a CANopen communication object ID.
+///
+/// The ID is laid out as a 4-bit function code and a 7-bit node ID.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CanOpenId(u16);
+
+impl CanOpenId {
+    /// Creates a CANopen ID from a function code and node ID.
+    ///
+    /// Any bits out of range for each field are ignored.
+    pub fn new(function_code: u8, node_id: u8) -> Self {
+        Self((u16::from(function_code & 0x0F) << 7) | u16::from(node_id & 0x7F))
+    }
+
+    /// Gets the 4-bit function code.
+    pub fn function_code(&self) -> u8 {
+        (self.0 >> 7) as u8
+    }
+
+    /// Gets the 7-bit node ID.
+    pub fn node_id(&self) -> u8 {
+        (self.0 & 0x7F) as u8
+    }
+
+    /// Gets the full CAN ID.
+    pub fn can_id(&self) -> CanId {
+        CanId(StandardId::new(self.0).unwrap().into())
+    }
+}
+
+impl From<CanOpenId> for CanId {
+    fn from(id: CanOpenId) -> Self {
+        id.can_id()
+    }
+}
+
+impl From<Id> for CanId {
+    fn from(id: Id) -> Self {
+        Self(id)
+    }
+}
+
+impl From<StandardId> for CanId {
+    fn from(id: StandardId) -> Self {
+        Self(id.into())
+    }
+}
+
+impl From<ExtendedId> for CanId {
+    fn from(id: ExtendedId) -> Self {
+        Self(id.into())
+    }
+}
+
+impl From<CanId> for Id {
+    fn from(id: CanId) -> Self {
+        id.0
+    }
+}
+
+impl TryFrom<u32> for CanId {
+    type Error = ConstructionError;
+
+    /// Creates an ID from a raw integer, like [`CanId::from_raw`].
+    fn try_from(id: u32) -> Result<Self, Self::Error> {
+        Self::from_raw(id)
+    }
+}
+
+impl fmt::Display for CanId {
+    /// Formats the ID in hex, the way candump does: three digits for a
+    /// standard ID, and eight for an extended one.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Id::Standard(id) => write!(f, "{:03X}", id.as_raw()),
+            Id::Extended(id) => write!(f, "{:08X}", id.as_raw()),
+        }
+    }
+}
+
+impl FromStr for CanId {
+    type Err = ParseIdError;
+
+    /// Parses an ID from a hex string, with an optional "0x" prefix.
+    ///
+    /// As with candump and cansend, a string of more than three digits is
+    /// an extended ID, even if the value would fit in 11 bits.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let digits = s
+            .strip_prefix("0x")
+            .or_else(|| s.strip_prefix("0X"))
+            .unwrap_or(s);
+        let id = u32::from_str_radix(digits, 16)?;
+        let id = if digits.len() > 3 {
+            Self::extended(id)?
+        } else {
+            Self::standard(id as u16)?
+        };
+        Ok(id)
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for CanId {
+    fn format(&self, f: defmt::Formatter) {
+        match self.0 {
+            Id::Standard(id) => defmt::write!(f, "{=u16:03X}", id.as_raw()),
+            Id::Extended(id) => defmt::write!(f, "{=u32:08X}", id.as_raw()),
+        }
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for CanId {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        let id = if u.arbitrary()? {
+            Self::extended(u.int_in_range(0..=CAN_EFF_MASK)?)
+        } else {
+            Self::standard(u.int_in_range(0..=CAN_SFF_MASK as u16)?)
+        };
+        Ok(id.unwrap())
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_can_id() {
+        let id = CanId::from_raw(0x123).unwrap();
+        assert!(id.is_standard());
+        assert_eq!(0x123, id.as_raw());
+        assert_eq!(0x123, id.id_word());
+        assert_eq!(CAN_SFF_MASK, id.mask());
+        assert_eq!("123", id.to_string());
+
+        let id = CanId::from_raw(0x1234).unwrap();
+        assert!(id.is_extended());
+        assert_eq!(0x1234 | CAN_EFF_FLAG, id.id_word());
+        assert_eq!(id, CanId::from_id_word(id.id_word()));
+        assert_eq!("00001234", id.to_string());
+
+        assert!(CanId::standard(0x800).is_err());
+        assert!(CanId::from_raw(0x2000_0000).is_err());
+        assert!(CanId::extended(0x100 << 18).unwrap() > CanId::standard(0x100).unwrap());
+    }
+
+    #[test]
+    fn test_j1939() {
+        // PDU2 broadcast: EEC1 from the engine
+        let id = CanId::extended(0x0CF0_0400).unwrap().j1939().unwrap();
+        assert_eq!(3, id.priority());
+        assert_eq!(0xF004, id.pgn());
+        assert_eq!(0x00, id.source());
+        assert_eq!(None, id.destination());
+
+        // PDU1 peer-to-peer: request to address 0x17 from 0xF9
+        let id = CanId::extended(0x18EA_17F9).unwrap().j1939().unwrap();
+        assert_eq!(0xEA00, id.pgn());
+        assert_eq!(Some(0x17), id.destination());
+        assert_eq!(0xF9, id.source());
+        assert_eq!(0x18EA_17F9, J1939Id::new(6, 0xEA17, 0xF9).can_id().as_raw());
+
+        assert!(CanId::standard(0x100).unwrap().j1939().is_none());
+    }
+
+    #[test]
+    fn test_canopen() {
+        let id = CanId::standard(0x185).unwrap().canopen().unwrap();
+        assert_eq!(0x3, id.function_code());
+        assert_eq!(0x05, id.node_id());
+        assert_eq!(0x185, CanOpenId::new(0x3, 0x05).can_id().as_raw());
+        assert!(CanId::extended(0x185).unwrap().canopen().is_none());
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(CanId::standard(0x7FF).unwrap(), "7FF".parse().unwrap());
+        assert_eq!(CanId::standard(0x10).unwrap(), "0x10".parse().unwrap());
+        assert_eq!(CanId::extended(0x10).unwrap(), "00000010".parse().unwrap());
+        assert!("800".parse::<CanId>().is_err());
+        assert!("xyz".parse::<CanId>().is_err());
+    }
+}
@@ -0,0 +1,403 @@
+// socketcan/src/ipc.rs
+//
+// Implements a compact binary encoding of timestamped CAN frames.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! Compact binary encoding of timestamped frames for IPC.
+//!
+//! This is a stable, versioned wire format that allows multiple processes,
+//! such as a logger, a decoder, and a UI, to exchange CAN traffic over Unix
+//! sockets, pipes, or files without each inventing their own format.
+//!
+//! Each record is a fixed 16-byte header followed by the frame's data
+//! bytes. All multi-byte values are little-endian:
+//!
+//! ```text
+//! Offset  Size  Field
+//!   0      1    Format version (currently 1)
+//!   1      1    Kind: 0 = classic CAN 2.0, 1 = FD
+//!   2      1    FD flags (BRS, ESI), zero for classic frames
+//!   3      1    Length: data length, or the DLC for a remote frame
+//!   4      4    SocketCAN ID word, with the EFF/RTR/ERR flags
+//!   8      8    Timestamp, in microseconds
+//!  16      n    Data bytes (none for remote frames)
+//! ```
+//!
+//! The records are read and written as [`Timestamped`] frames, with the
+//! time stored as the number of microseconds since the UNIX epoch, as with
+//! the candump log format. The channel is not part of the record.
+//!
+//! For datagram sockets, each record can be sent as a single message using
+//! [`encode`] and [`decode`]. For stream sockets, the [`RecordWriter`] and
+//! [`RecordReader`] types write and read consecutive records.
+
+use crate::{
+    frame::{can_frame_default, canfd_frame_default, CANFD_MAX_DLEN, CAN_MAX_DLEN, CAN_RTR_FLAG},
+    timestamp::{micros_since_epoch, Timestamped},
+    CanAnyFrame, CanFdFrame, CanFrame, EmbeddedFrame, Frame,
+};
+use std::io;
+use thiserror::Error;
+
+/// The current version of the binary format.
+pub const VERSION: u8 = 1;
+
+/// The size of the fixed header at the start of every record.
+pub const HEADER_LEN: usize = 16;
+
+/// The maximum size of a single encoded record.
+pub const MAX_RECORD_LEN: usize = HEADER_LEN + CANFD_MAX_DLEN;
+
+/// Record kind for a classic CAN 2.0 frame.
+const KIND_CLASSIC: u8 = 0;
+
+/// Record kind for an FD frame.
+const KIND_FD: u8 = 1;
+
+/// An error decoding a binary record.
+#[derive(Error, Debug)]
+pub enum DecodeError {
+    /// The buffer was smaller than the record
+    #[error("truncated record")]
+    Truncated,
+    /// The record was written with an unknown version of the format
+    #[error("unsupported format version: {0}")]
+    UnsupportedVersion(u8),
+    /// The record kind was not recognized
+    #[error("invalid record kind: {0}")]
+    InvalidKind(u8),
+    /// The length is not valid for the kind of frame
+    #[error("invalid frame length: {0}")]
+    InvalidLength(u8),
+    /// An FD record had the remote (RTR) flag set in its ID
+    #[error("remote flag set in an FD record")]
+    RemoteFd,
+    /// An I/O error reading the record
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+/// Gets the parts of a frame needed to encode it.
+///
+/// Returns the kind, FD flags, length field, ID word, and the data bytes
+/// to write.
+fn frame_parts(frame: &CanAnyFrame) -> (u8, u8, u8, u32, &[u8]) {
+    match frame {
+        CanAnyFrame::Normal(frame) => (
+            KIND_CLASSIC,
+            0,
+            frame.dlc() as u8,
+            frame.id_word(),
+            frame.data(),
+        ),
+        CanAnyFrame::Remote(frame) => (KIND_CLASSIC, 0, frame.dlc() as u8, frame.id_word(), &[]),
+        CanAnyFrame::Error(frame) => (
+            KIND_CLASSIC,
+            0,
+            frame.data().len() as u8,
+            frame.id_word(),
+            frame.data(),
+        ),
+        CanAnyFrame::Fd(frame) => (
+            KIND_FD,
+            frame.flags().bits(),
+            frame.data().len() as u8,
+            frame.id_word(),
+            frame.data(),
+        ),
+    }
+}
+
+/// Gets the encoded size of the record for the frame.
+pub fn encoded_len(frame: &CanAnyFrame) -> usize {
+    HEADER_LEN + frame_parts(frame).4.len()
+}
+
+/// Encodes a timestamped frame into the buffer.
+///
+/// On success, returns the number of bytes written into the buffer. If the
+/// buffer is too small, nothing is written and `None` is returned. A buffer
+/// of [`MAX_RECORD_LEN`] bytes will hold any frame.
+pub fn encode(rec: &Timestamped<CanAnyFrame>, buf: &mut [u8]) -> Option<usize> {
+    let (kind, flags, len, can_id, data) = frame_parts(&rec.frame);
+    let t_us = micros_since_epoch(rec.time);
+    let n = HEADER_LEN + data.len();
+
+    if buf.len() < n {
+        return None;
+    }
+
+    buf[0] = VERSION;
+    buf[1] = kind;
+    buf[2] = flags;
+    buf[3] = len;
+    buf[4..8].copy_from_slice(&can_id.to_le_bytes());
+    buf[8..16].copy_from_slice(&t_us.to_le_bytes());
+    buf[HEADER_LEN..n].copy_from_slice(data);
+    Some(n)
+}
+
+/// Encodes a timestamped frame into a new vector.
+pub fn encode_to_vec(rec: &Timestamped<CanAnyFrame>) -> Vec<u8> {
+    let mut buf = vec![0u8; encoded_len(&rec.frame)];
+    // Safe unwrap: the buffer was sized to the record
+    encode(rec, &mut buf).unwrap();
+    buf
+}
+
+/// Gets the number of data bytes that follow the header, validating the
+/// header fields.
+fn data_len(hdr: &[u8]) -> Result<usize, DecodeError> {
+    if hdr[0] != VERSION {
+        return Err(DecodeError::UnsupportedVersion(hdr[0]));
+    }
+
+    let len = hdr[3];
+    let can_id = u32::from_le_bytes([hdr[4], hdr[5], hdr[6], hdr[7]]);
+
+    match hdr[1] {
+        KIND_CLASSIC if len as usize > CAN_MAX_DLEN => Err(DecodeError::InvalidLength(len)),
+        KIND_CLASSIC if can_id & CAN_RTR_FLAG != 0 => Ok(0),
+        KIND_CLASSIC => Ok(len as usize),
+        KIND_FD if !matches!(len, 0..=8 | 12 | 16 | 20 | 24 | 32 | 48 | 64) => {
+            Err(DecodeError::InvalidLength(len))
+        }
+        KIND_FD if can_id & CAN_RTR_FLAG != 0 => Err(DecodeError::RemoteFd),
+        KIND_FD => Ok(len as usize),
+        kind => Err(DecodeError::InvalidKind(kind)),
+    }
+}
+
+/// Creates a frame from a validated header and its data bytes.
+fn frame_from_parts(hdr: &[u8], data: &[u8]) -> Timestamped<CanAnyFrame> {
+    let can_id = u32::from_le_bytes([hdr[4], hdr[5], hdr[6], hdr[7]]);
+    let mut ts = [0u8; 8];
+    ts.copy_from_slice(&hdr[8..16]);
+    let t_us = u64::from_le_bytes(ts);
+
+    let frame = if hdr[1] == KIND_FD {
+        let mut frame = canfd_frame_default();
+        frame.can_id = can_id;
+        frame.len = hdr[3];
+        frame.flags = hdr[2];
+        frame.data[..data.len()].copy_from_slice(data);
+        CanAnyFrame::from(CanFdFrame::from(frame))
+    } else {
+        let mut frame = can_frame_default();
+        frame.can_id = can_id;
+        frame.can_dlc = hdr[3];
+        frame.data[..data.len()].copy_from_slice(data);
+        CanAnyFrame::from(CanFrame::from(frame))
+    };
+    Timestamped::from_micros(frame, t_us)
+}
+
+/// Decodes a timestamped frame from the front of the buffer.
+///
+/// On success this returns the timestamped frame and the number of bytes
+/// consumed from the buffer.
+pub fn decode(buf: &[u8]) -> Result<(Timestamped<CanAnyFrame>, usize), DecodeError> {
+    if buf.len() < HEADER_LEN {
+        return Err(DecodeError::Truncated);
+    }
+
+    let n = HEADER_LEN + data_len(buf)?;
+    if buf.len() < n {
+        return Err(DecodeError::Truncated);
+    }
+
+    let rec = frame_from_parts(&buf[..HEADER_LEN], &buf[HEADER_LEN..n]);
+    Ok((rec, n))
+}
+
+// ===== RecordWriter =====
+
+/// Writes binary frame records to a stream.
+#[derive(Debug)]
+pub struct RecordWriter<W> {
+    wtr: W,
+    buf: [u8; MAX_RECORD_LEN],
+}
+
+impl<W: io::Write> RecordWriter<W> {
+    /// Creates a record writer on top of the I/O writer.
+    pub fn new(wtr: W) -> Self {
+        Self {
+            wtr,
+            buf: [0u8; MAX_RECORD_LEN],
+        }
+    }
+
+    /// Writes a single timestamped frame to the stream.
+    pub fn write_record(&mut self, rec: &Timestamped<CanAnyFrame>) -> io::Result<()> {
+        // Safe unwrap: the buffer can hold the largest record
+        let n = encode(rec, &mut self.buf).unwrap();
+        self.wtr.write_all(&self.buf[..n])
+    }
+
+    /// Flushes the underlying writer.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.wtr.flush()
+    }
+
+    /// Gets the underlying writer back.
+    pub fn into_inner(self) -> W {
+        self.wtr
+    }
+}
+
+// ===== RecordReader =====
+
+/// Reads binary frame records from a stream.
+#[derive(Debug)]
+pub struct RecordReader<R> {
+    rdr: R,
+}
+
+impl<R: io::Read> RecordReader<R> {
+    /// Creates a record reader on top of the I/O reader.
+    pub fn new(rdr: R) -> Self {
+        Self { rdr }
+    }
+
+    /// Reads the next timestamped frame from the stream.
+    ///
+    /// Returns `None` if the stream ended cleanly at a record boundary.
+    pub fn read_record(&mut self) -> Result<Option<Timestamped<CanAnyFrame>>, DecodeError> {
+        let mut hdr = [0u8; HEADER_LEN];
+
+        // Distinguish a clean EOF from a truncated header
+        let mut n = 0;
+        while n < HEADER_LEN {
+            match self.rdr.read(&mut hdr[n..]) {
+                Ok(0) if n == 0 => return Ok(None),
+                Ok(0) => return Err(DecodeError::Truncated),
+                Ok(k) => n += k,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => (),
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        let mut data = [0u8; CANFD_MAX_DLEN];
+        let len = data_len(&hdr)?;
+        self.rdr.read_exact(&mut data[..len]).map_err(|err| {
+            if err.kind() == io::ErrorKind::UnexpectedEof {
+                DecodeError::Truncated
+            } else {
+                err.into()
+            }
+        })?;
+
+        Ok(Some(frame_from_parts(&hdr, &data[..len])))
+    }
+
+    /// Gets the underlying reader back.
+    pub fn into_inner(self) -> R {
+        self.rdr
+    }
+}
+
+impl<R: io::Read> Iterator for RecordReader<R> {
+    type Item = Result<Timestamped<CanAnyFrame>, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record().transpose()
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{frame::FdFlags, CanError, CanErrorFrame, StandardId};
+
+    #[test]
+    fn test_round_trip() {
+        let frames = [
+            CanAnyFrame::from(CanFrame::from_raw_id(0x123, &[1, 2, 3]).unwrap()),
+            CanAnyFrame::from(CanFrame::remote_from_raw_id(0x1234567, 4).unwrap()),
+            CanAnyFrame::Error(CanErrorFrame::from(CanError::BusOff)),
+            CanAnyFrame::from(
+                CanFdFrame::with_flags(StandardId::new(0x42).unwrap(), &[0xAA; 48], FdFlags::BRS)
+                    .unwrap(),
+            ),
+        ];
+
+        for (i, frame) in frames.iter().enumerate() {
+            let rec = Timestamped::from_micros(*frame, i as u64);
+            let buf = encode_to_vec(&rec);
+            assert_eq!(encoded_len(frame), buf.len());
+
+            let (decoded, n) = decode(&buf).unwrap();
+            assert_eq!(i as u64, decoded.micros());
+            assert_eq!(buf.len(), n);
+            assert_eq!(frame.to_string(), decoded.frame.to_string());
+        }
+    }
+
+    #[test]
+    fn test_stream() {
+        let frame = CanAnyFrame::from(CanFrame::from_raw_id(0x100, &[0xFF]).unwrap());
+
+        let mut wtr = RecordWriter::new(Vec::new());
+        wtr.write_record(&Timestamped::from_micros(frame, 1))
+            .unwrap();
+        wtr.write_record(&Timestamped::from_micros(frame, 2))
+            .unwrap();
+        let buf = wtr.into_inner();
+
+        let rdr = RecordReader::new(&buf[..]);
+        let recs: Vec<_> = rdr.map(|rec| rec.unwrap()).collect();
+        assert_eq!(2, recs.len());
+        assert_eq!(2, recs[1].micros());
+        assert_eq!(None, recs[1].channel());
+
+        let mut rdr = RecordReader::new(&buf[..buf.len() - 1]);
+        assert!(rdr.read_record().unwrap().is_some());
+        assert!(matches!(rdr.read_record(), Err(DecodeError::Truncated)));
+    }
+
+    #[test]
+    fn test_bad_header() {
+        let frame = CanAnyFrame::from(CanFrame::from_raw_id(0x100, &[]).unwrap());
+        let mut buf = encode_to_vec(&Timestamped::from_micros(frame, 0));
+
+        assert!(matches!(decode(&buf[..4]), Err(DecodeError::Truncated)));
+
+        buf[0] = 99;
+        assert!(matches!(
+            decode(&buf),
+            Err(DecodeError::UnsupportedVersion(99))
+        ));
+
+        buf[0] = VERSION;
+        buf[3] = 9;
+        assert!(matches!(decode(&buf), Err(DecodeError::InvalidLength(9))));
+    }
+
+    #[test]
+    fn test_bad_fd_header() {
+        let frame =
+            CanAnyFrame::from(CanFdFrame::new(StandardId::new(0x42).unwrap(), &[0; 12]).unwrap());
+        let buf = encode_to_vec(&Timestamped::from_micros(frame, 0));
+        assert!(decode(&buf).is_ok());
+
+        let mut bad = buf.clone();
+        bad[3] = 11;
+        assert!(matches!(decode(&bad), Err(DecodeError::InvalidLength(11))));
+
+        bad[3] = 65;
+        assert!(matches!(decode(&bad), Err(DecodeError::InvalidLength(65))));
+
+        let mut bad = buf;
+        bad[4..8].copy_from_slice(&(0x42 | CAN_RTR_FLAG).to_le_bytes());
+        assert!(matches!(decode(&bad), Err(DecodeError::RemoteFd)));
+    }
+}
@@ -0,0 +1,215 @@
+// socketcan/src/isotp.rs
+//
+// ISO-TP sockets.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! ISO-TP (ISO 15765-2) sockets.
+//!
+//! ISO-TP carries messages of up to 4095 bytes over classic CAN, or more
+//! with FD frames or an escaped first frame, splitting them into a first
+//! frame and consecutive frames, paced by flow control frames from the
+//! receiver. It's the transport of diagnostic protocols like UDS.
+//!
+//! The Linux kernel implements the protocol in the `can-isotp` module,
+//! mainline since 5.10. An [`IsoTpSocket`] is bound to a pair of CAN IDs,
+//! and each read or write is a whole message; the segmentation, flow
+//! control and timing are handled by the kernel.
+//!
+//! ```no_run
+//! use socketcan::{isotp::IsoTpSocket, StandardId};
+//!
+//! let rx_id = StandardId::new(0x7E8).unwrap();
+//! let tx_id = StandardId::new(0x7E0).unwrap();
+//! let sock = IsoTpSocket::open("can0", rx_id, tx_id).unwrap();
+//!
+//! sock.write(&[0x22, 0xF1, 0x90]).unwrap();
+//! let resp = sock.read().unwrap();
+//! println!("{:02X?}", resp);
+//! ```
+
+use crate::{CanAddr, CanId, IoErrorKind, IoResult};
+use libc::{c_int, c_void, socklen_t, AF_CAN};
+use std::{
+    io::Write,
+    mem::size_of,
+    os::unix::io::{AsRawFd, RawFd},
+    time::Duration,
+};
+
+/// The ISO-TP protocol number
+const CAN_ISOTP: c_int = 6;
+/// The socket option level for ISO-TP
+const SOL_CAN_ISOTP: c_int = libc::SOL_CAN_BASE + CAN_ISOTP;
+/// The general ISO-TP options
+const CAN_ISOTP_OPTS: c_int = 1;
+/// The flow control options for receiving
+const CAN_ISOTP_RECV_FC: c_int = 2;
+
+/// Pad the transmitted frames
+const CAN_ISOTP_TX_PADDING: u32 = 0x004;
+
+/// The largest classic ISO-TP message.
+pub const MAX_MSG_LEN: usize = 4095;
+
+/// The kernel `can_isotp_options` struct.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+struct IsoTpOptions {
+    flags: u32,
+    frame_txtime: u32,
+    ext_address: u8,
+    txpad_content: u8,
+    rxpad_content: u8,
+    rx_ext_address: u8,
+}
+
+/// The kernel `can_isotp_fc_options` struct.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+struct IsoTpFcOptions {
+    bs: u8,
+    stmin: u8,
+    wftmax: u8,
+}
+
+/// A socket for ISO-TP messages between a pair of CAN IDs.
+#[derive(Debug)]
+pub struct IsoTpSocket(socket2::Socket);
+
+impl IsoTpSocket {
+    /// Opens a socket on the named interface, receiving messages on
+    /// `rx_id` and sending them on `tx_id`.
+    pub fn open<R, T>(ifname: &str, rx_id: R, tx_id: T) -> IoResult<Self>
+    where
+        R: Into<CanId>,
+        T: Into<CanId>,
+    {
+        let addr = CanAddr::from_iface(ifname)?.with_isotp_ids(rx_id.into(), tx_id.into());
+        Self::open_addr(&addr)
+    }
+
+    /// Opens a socket on an address with the ISO-TP IDs.
+    pub fn open_addr(addr: &CanAddr) -> IoResult<Self> {
+        let sock = socket2::Socket::new(
+            socket2::Domain::from(AF_CAN),
+            socket2::Type::DGRAM,
+            Some(socket2::Protocol::from(CAN_ISOTP)),
+        )?;
+        sock.bind(&addr.into_sock_addr())?;
+        Ok(Self(sock))
+    }
+
+    /// Sets an ISO-TP option on the socket.
+    fn set_option<T>(&self, name: c_int, val: &T) -> IoResult<()> {
+        let ret = unsafe {
+            libc::setsockopt(
+                self.as_raw_fd(),
+                SOL_CAN_ISOTP,
+                name,
+                val as *const _ as *const c_void,
+                size_of::<T>() as socklen_t,
+            )
+        };
+        match ret {
+            0 => Ok(()),
+            _ => Err(std::io::Error::last_os_error()),
+        }
+    }
+
+    /// Sets the byte used to pad transmitted frames to eight bytes, or
+    /// `None` to send frames of the minimum length.
+    ///
+    /// This must be set before the socket is used.
+    pub fn set_tx_padding(&self, padding: Option<u8>) -> IoResult<()> {
+        let opts = IsoTpOptions {
+            flags: if padding.is_some() {
+                CAN_ISOTP_TX_PADDING
+            } else {
+                0
+            },
+            txpad_content: padding.unwrap_or(0),
+            ..IsoTpOptions::default()
+        };
+        self.set_option(CAN_ISOTP_OPTS, &opts)
+    }
+
+    /// Sets the flow control parameters sent to the peer when receiving:
+    /// the block size, and the minimum separation time (STmin) as encoded
+    /// in the frame.
+    ///
+    /// This must be set before the socket is used.
+    pub fn set_flow_control(&self, block_size: u8, st_min: u8) -> IoResult<()> {
+        let opts = IsoTpFcOptions {
+            bs: block_size,
+            stmin: st_min,
+            wftmax: 0,
+        };
+        self.set_option(CAN_ISOTP_RECV_FC, &opts)
+    }
+
+    /// Change socket to non-blocking mode or back to blocking mode.
+    pub fn set_nonblocking(&self, nonblocking: bool) -> IoResult<()> {
+        self.0.set_nonblocking(nonblocking)
+    }
+
+    /// Receives into the buffer with the flags, returning the length the
+    /// kernel reports.
+    fn recv(&self, buf: &mut [u8], flags: c_int) -> IoResult<usize> {
+        let ret = unsafe {
+            libc::recv(
+                self.as_raw_fd(),
+                buf.as_mut_ptr() as *mut c_void,
+                buf.len(),
+                flags,
+            )
+        };
+        match ret {
+            n if n < 0 => Err(std::io::Error::last_os_error()),
+            n => Ok(n as usize),
+        }
+    }
+
+    /// Reads a message.
+    ///
+    /// The length of the message is peeked first, so messages longer than
+    /// [`MAX_MSG_LEN`], as sent with FD frames or an escaped first frame,
+    /// are read whole.
+    pub fn read(&self) -> IoResult<Vec<u8>> {
+        let len = self.recv(&mut [], libc::MSG_PEEK | libc::MSG_TRUNC)?;
+        let mut buf = vec![0u8; len];
+        let n = self.recv(&mut buf, 0)?;
+        buf.truncate(n);
+        Ok(buf)
+    }
+
+    /// Reads a message, waiting at most for the timeout.
+    pub fn read_timeout(&self, timeout: Duration) -> IoResult<Vec<u8>> {
+        use nix::poll::{poll, PollFd, PollFlags};
+        let pollfd = PollFd::new(self.as_raw_fd(), PollFlags::POLLIN);
+
+        match poll(&mut [pollfd], timeout.as_millis() as c_int)? {
+            0 => Err(IoErrorKind::TimedOut.into()),
+            _ => self.read(),
+        }
+    }
+
+    /// Writes a message.
+    ///
+    /// This returns once the message is queued; any failure in the
+    /// transfer is reported on the next call.
+    pub fn write(&self, msg: &[u8]) -> IoResult<()> {
+        (&self.0).write_all(msg)
+    }
+}
+
+impl AsRawFd for IsoTpSocket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
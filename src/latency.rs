@@ -0,0 +1,339 @@
+// socketcan/src/latency.rs
+//
+// Latency measurement with probe frames.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! Latency measurement with probe frames.
+//!
+//! A [`LatencyProbe`] sends numbered probe frames, one at a time, and
+//! times how long it takes for each to come back, to quantify the
+//! latency of an adapter, driver, or bus. The probes can come back in
+//! one of two ways:
+//!
+//! - Loopback: a second socket on the same bus receives the probes
+//!   directly, so each sample is the one-way latency from the sender to
+//!   the receiver.
+//! - Echo: a peer on the bus, such as another machine running [`echo`],
+//!   sends each probe back, usually with a different ID, so each sample
+//!   is the round trip time.
+//!
+//! The results are gathered into [`LatencyStats`] with the minimum,
+//! maximum, mean, and percentiles of the samples.
+//!
+//! ```no_run
+//! use socketcan::{latency::LatencyProbe, CanFrame, CanSocket, Socket};
+//! use std::time::Duration;
+//!
+//! let tx = CanSocket::open("can0").unwrap();
+//! let rx = CanSocket::open("can0").unwrap();
+//!
+//! // The peer echoes 0x7F0 back as 0x7F1
+//! let report = LatencyProbe::<CanFrame>::new(0x7F0)
+//!     .with_reply_id(0x7F1)
+//!     .with_count(1000)
+//!     .with_interval(Duration::from_millis(1))
+//!     .run(&tx, &rx)
+//!     .unwrap();
+//!
+//! println!("lost {} of {}", report.lost(), report.sent);
+//! println!("RTT p50 {:?}, p99 {:?}", report.stats.percentile(50.0), report.stats.percentile(99.0));
+//! ```
+
+use crate::{
+    backend::{CanReceiver, CanTransmitter},
+    Frame, IoErrorKind, IoResult,
+};
+use std::{
+    marker::PhantomData,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// The default time to wait for a probe to come back.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_millis(100);
+
+// ===== LatencyStats =====
+
+/// Statistics over a set of latency samples.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct LatencyStats {
+    // The samples, in ascending order
+    samples: Vec<Duration>,
+}
+
+impl LatencyStats {
+    /// Creates the statistics from the samples.
+    pub fn from_samples(mut samples: Vec<Duration>) -> Self {
+        samples.sort_unstable();
+        Self { samples }
+    }
+
+    /// Gets the number of samples.
+    pub fn count(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// Gets the samples, in ascending order.
+    pub fn samples(&self) -> &[Duration] {
+        &self.samples
+    }
+
+    /// Gets the smallest sample.
+    pub fn min(&self) -> Option<Duration> {
+        self.samples.first().copied()
+    }
+
+    /// Gets the largest sample.
+    pub fn max(&self) -> Option<Duration> {
+        self.samples.last().copied()
+    }
+
+    /// Gets the mean of the samples.
+    pub fn mean(&self) -> Option<Duration> {
+        let n = self.samples.len();
+        if n == 0 {
+            return None;
+        }
+        let total: Duration = self.samples.iter().sum();
+        Some(total / n as u32)
+    }
+
+    /// Gets a percentile of the samples, from 0.0 to 100.0, using the
+    /// nearest rank.
+    pub fn percentile(&self, p: f64) -> Option<Duration> {
+        let n = self.samples.len();
+        if n == 0 {
+            return None;
+        }
+        let rank = (p.clamp(0.0, 100.0) / 100.0 * n as f64).ceil() as usize;
+        Some(self.samples[rank.saturating_sub(1).min(n - 1)])
+    }
+
+    /// Gets the statistics with every sample halved, which estimates the
+    /// one-way latency from round trip times.
+    pub fn halved(&self) -> Self {
+        Self {
+            samples: self.samples.iter().map(|d| *d / 2).collect(),
+        }
+    }
+}
+
+/// The results of a latency measurement.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LatencyReport {
+    /// The number of probes sent
+    pub sent: u32,
+    /// The latency of the probes that came back
+    pub stats: LatencyStats,
+}
+
+impl LatencyReport {
+    /// Gets the number of probes that didn't come back in time.
+    pub fn lost(&self) -> u32 {
+        self.sent - self.stats.count() as u32
+    }
+}
+
+// ===== LatencyProbe =====
+
+/// A latency measurement, sending probe frames and timing their return.
+///
+/// Each probe carries a sequence number in the first four bytes of its
+/// payload, so a late reply to an earlier probe isn't mistaken for the
+/// reply to the current one.
+#[derive(Debug, Clone)]
+pub struct LatencyProbe<F> {
+    id: u32,
+    reply_id: u32,
+    count: u32,
+    interval: Duration,
+    timeout: Duration,
+    len: usize,
+    _frame: PhantomData<F>,
+}
+
+impl<F: Frame> LatencyProbe<F> {
+    /// Creates a measurement of 100 probes with the ID, which come back
+    /// with the same ID.
+    pub fn new(id: u32) -> Self {
+        Self {
+            id,
+            reply_id: id,
+            count: 100,
+            interval: Duration::ZERO,
+            timeout: DEFAULT_TIMEOUT,
+            len: 8,
+            _frame: PhantomData,
+        }
+    }
+
+    /// Sets the ID of the replies, for an echoing peer that answers with
+    /// a different ID.
+    pub fn with_reply_id(mut self, id: u32) -> Self {
+        self.reply_id = id;
+        self
+    }
+
+    /// Sets the number of probes to send.
+    pub fn with_count(mut self, count: u32) -> Self {
+        self.count = count;
+        self
+    }
+
+    /// Sets the gap between a reply and the next probe.
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Sets how long to wait for each probe to come back before counting
+    /// it as lost.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets the length of the probe payload, from 4 to 8 bytes, as the
+    /// time on the wire adds to the latency.
+    pub fn with_len(mut self, len: usize) -> Self {
+        self.len = len.clamp(4, 8);
+        self
+    }
+
+    /// Runs the measurement, sending the probes with one backend and
+    /// receiving them with the other.
+    ///
+    /// The two can be the same, for an echoing peer.
+    pub fn run<T, R>(&self, tx: &T, rx: &R) -> IoResult<LatencyReport>
+    where
+        T: CanTransmitter<F>,
+        R: CanReceiver<Frame = F>,
+    {
+        let mut samples = Vec::with_capacity(self.count as usize);
+        let mut payload = [0u8; 8];
+
+        for seq in 0..self.count {
+            payload[..4].copy_from_slice(&seq.to_be_bytes());
+            let probe =
+                F::from_raw_id(self.id, &payload[..self.len]).ok_or(IoErrorKind::InvalidInput)?;
+
+            let start = Instant::now();
+            tx.transmit(&probe)?;
+            if let Some(t) = self.wait_reply(rx, seq, start)? {
+                samples.push(t);
+            }
+            if !self.interval.is_zero() {
+                thread::sleep(self.interval);
+            }
+        }
+
+        Ok(LatencyReport {
+            sent: self.count,
+            stats: LatencyStats::from_samples(samples),
+        })
+    }
+
+    /// Waits for the reply to a probe, returning the time it took, or
+    /// `None` if it timed out.
+    fn wait_reply<R>(&self, rx: &R, seq: u32, start: Instant) -> IoResult<Option<Duration>>
+    where
+        R: CanReceiver<Frame = F>,
+    {
+        loop {
+            let Some(remaining) = self.timeout.checked_sub(start.elapsed()) else {
+                return Ok(None);
+            };
+            let frame = match rx.receive_timeout(remaining) {
+                Ok(frame) => frame,
+                Err(err) if is_timeout(err.kind()) => return Ok(None),
+                Err(err) => return Err(err),
+            };
+            let elapsed = start.elapsed();
+            if frame.raw_id() == self.reply_id && probe_seq(frame.data()) == Some(seq) {
+                return Ok(Some(elapsed));
+            }
+        }
+    }
+}
+
+/// Echoes probes back to a [`LatencyProbe`], with the reply ID, until
+/// none arrive for the timeout. Returns the number of probes echoed.
+pub fn echo<B, F>(bus: &B, id: u32, reply_id: u32, timeout: Duration) -> IoResult<u64>
+where
+    B: CanTransmitter<F> + CanReceiver<Frame = F>,
+    F: Frame,
+{
+    let mut n = 0;
+    loop {
+        let frame = match bus.receive_timeout(timeout) {
+            Ok(frame) => frame,
+            Err(err) if is_timeout(err.kind()) => return Ok(n),
+            Err(err) => return Err(err),
+        };
+        if frame.raw_id() == id {
+            let reply = F::from_raw_id(reply_id, frame.data()).ok_or(IoErrorKind::InvalidInput)?;
+            bus.transmit(&reply)?;
+            n += 1;
+        }
+    }
+}
+
+// Sockets report a timeout as WouldBlock
+fn is_timeout(kind: IoErrorKind) -> bool {
+    matches!(kind, IoErrorKind::TimedOut | IoErrorKind::WouldBlock)
+}
+
+// Gets the sequence number from a probe payload
+fn probe_seq(data: &[u8]) -> Option<u32> {
+    let bytes = data.get(..4)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{vbus::VirtualBus, CanAnyFrame};
+
+    #[test]
+    fn test_stats() {
+        let ms = Duration::from_millis;
+        let stats = LatencyStats::from_samples((1..=100).rev().map(ms).collect());
+        assert_eq!(Some(ms(1)), stats.min());
+        assert_eq!(Some(ms(100)), stats.max());
+        assert_eq!(Some(Duration::from_micros(50_500)), stats.mean());
+        assert_eq!(Some(ms(50)), stats.percentile(50.0));
+        assert_eq!(Some(ms(99)), stats.percentile(99.0));
+        assert_eq!(Some(ms(1)), stats.percentile(0.0));
+        assert_eq!(Some(ms(25)), stats.halved().percentile(50.0));
+        assert_eq!(None, LatencyStats::default().mean());
+    }
+
+    #[test]
+    fn test_echo() {
+        let bus = VirtualBus::new().with_latency(Duration::from_millis(2));
+        let (a, b) = (bus.endpoint(), bus.endpoint());
+
+        let peer = thread::spawn(move || {
+            echo::<_, CanAnyFrame>(&b, 0x7F0, 0x7F1, Duration::from_millis(500))
+        });
+
+        let report = LatencyProbe::<CanAnyFrame>::new(0x7F0)
+            .with_reply_id(0x7F1)
+            .with_count(10)
+            .with_timeout(Duration::from_secs(1))
+            .run(&a, &a)
+            .unwrap();
+
+        assert_eq!(10, peer.join().unwrap().unwrap());
+        assert_eq!(0, report.lost());
+        assert!(report.stats.min().unwrap() >= Duration::from_millis(4));
+    }
+}
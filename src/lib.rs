@@ -52,6 +52,17 @@
 //! is available through the `AsRawFd`, `IntoRawFd` and `FromRawFd`
 //! implementations.
 //!
+//! # Embedded HAL
+//!
+//! The frame types and sockets implement the traits from the
+//! [embedded-can](https://crates.io/crates/embedded-can) crate, which
+//! replaced the deprecated `embedded_hal::can` module. The [`Frame`] trait
+//! in this crate extends [`embedded_can::Frame`] (re-exported here as
+//! [`EmbeddedFrame`]) with the SocketCAN-specific parts, like the composite
+//! ID word, so any frame can be passed to a driver that is generic over the
+//! HAL traits. The crate itself is also re-exported, so that applications
+//! can rely on finding the same version used here.
+//!
 //! # Crate Features
 //!
 //! ### Default
@@ -89,6 +100,45 @@
 //!   with a submodule aliased for [smol](https://crates.io/crates/smol) and examples
 //!   for that runtime.
 //!
+//! * **serde** -
+//!   Implement `Serialize` and `Deserialize` from [serde](https://serde.rs/)
+//!   for the frame types, filters, and CAN errors.
+//!
+//! * **defmt** -
+//!   Implement `Format` from [defmt](https://defmt.ferrous-systems.com/)
+//!   for the frame types, IDs, and errors, so frames are logged the same way
+//!   on embedded targets and Linux.
+//!
+//! * **arbitrary** -
+//!   Implement `Arbitrary` from [arbitrary](https://crates.io/crates/arbitrary)
+//!   for the frame types, IDs, and filters, so that protocol code can be
+//!   fuzzed and property-tested with valid CAN inputs.
+//!
+//! * **dbc** -
+//!   Parse DBC databases to decode frames into named signal values, and
+//!   encode them back into frames.
+//!
+//! * **derive** -
+//!   Derive the `CanMessage` trait for structs, mapping their fields to bit
+//!   fields in a frame payload.
+//!
+//! * **codec** -
+//!   Encoders and decoders from [tokio-util](https://crates.io/crates/tokio-util)
+//!   to send frames over any async byte stream, such as TCP or a serial link.
+//!
+//! * **websocket** -
+//!   A WebSocket server that streams frames to browsers and remote scripts
+//!   as JSON, and accepts frames from them to transmit.
+//!
+//! * **metrics** -
+//!   Prometheus counters and gauges for sockets, bus monitors, and the
+//!   gateway, with a minimal HTTP server to scrape them from.
+//!
+//! * **pcan** -
+//!   A backend for PEAK-System adapters through the PCAN-Basic library,
+//!   which is loaded at run time, for PEAK drivers built without the
+//!   SocketCAN interface.
+//!
 
 // clippy: do not warn about things like "SocketCAN" inside the docs
 #![allow(clippy::doc_markdown)]
@@ -105,6 +155,10 @@
 
 use std::{io::ErrorKind, mem::size_of};
 
+// Allows the paths generated by the derive macro to resolve in our tests.
+#[cfg(all(test, feature = "derive"))]
+extern crate self as socketcan;
+
 // Re-export the embedded_can crate so that applications can rely on
 // finding the same version we use.
 pub use embedded_can::{
@@ -121,17 +175,128 @@ pub use errors::{
 pub mod addr;
 pub use addr::CanAddr;
 
+pub mod alias;
+
+pub mod id;
+pub use id::{CanId, CanOpenId, J1939Id};
+
 pub mod frame;
 pub use frame::{
-    CanAnyFrame, CanDataFrame, CanErrorFrame, CanFdFrame, CanFrame, CanRawFrame, CanRemoteFrame,
-    Frame,
+    CanAnyFrame, CanDataFrame, CanErrorFrame, CanEvent, CanFdFrame, CanFdFrameBuilder, CanFrame,
+    CanFrameBuilder, CanRawFrame, CanRemoteFrame, CanXlAnyFrame, CanXlFrame, FdConversion, Frame,
 };
 
+pub mod hex;
+
 #[cfg(feature = "dump")]
 pub mod dump;
 
+pub mod ipc;
+
+pub mod capture;
+
+pub mod analyzer;
+
+pub mod backend;
+
+pub mod busload;
+
+pub mod cache;
+
+pub mod cannelloni;
+
+pub mod timestamp;
+pub use timestamp::Timestamped;
+
+pub mod monitor;
+
+pub mod watchdog;
+
+pub mod sniffer;
+
+pub mod scanner;
+
+pub mod sequence;
+
+pub mod generator;
+
+pub mod scheduler;
+
+pub mod txqueue;
+
+pub mod txmux;
+
+pub mod pacing;
+
+pub mod heartbeat;
+
+pub mod e2e;
+
+pub mod secoc;
+
+pub mod segment;
+
+pub mod slcan;
+
+pub mod socketcand;
+
+pub mod vbus;
+
+pub mod mock;
+
+pub mod fault;
+
+pub mod latency;
+
+pub mod replay;
+
+pub mod clock;
+
+pub mod dispatch;
+
+pub mod ring;
+
+pub mod pool;
+
+pub mod batch;
+
+pub mod filter;
+
+pub mod rxfilter;
+
+pub mod gateway;
+
+pub mod canopen;
+
+pub mod isotp;
+
+pub mod uds;
+
+pub mod xcp;
+
+pub mod signal;
+
+pub mod message;
+
+#[cfg(feature = "dbc")]
+pub mod dbc;
+
+#[cfg(feature = "codec")]
+pub mod codec;
+
+#[cfg(feature = "websocket")]
+pub mod websocket;
+
+#[cfg(feature = "metrics")]
+pub mod metrics;
+
+#[cfg(feature = "pcan")]
+pub mod pcan;
+
 pub mod socket;
-pub use socket::{CanFdSocket, CanFilter, CanSocket, ShouldRetry, Socket, SocketOptions};
+pub use socket::{
+    CanFdSocket, CanFilter, CanSocket, CanXlSocket, ShouldRetry, Socket, SocketOptions,
+};
 
 #[cfg(feature = "netlink")]
 pub mod nl;
@@ -244,3 +409,57 @@ impl embedded_can::nb::Can for CanSocket {
         }
     }
 }
+
+impl embedded_can::blocking::Can for CanFdSocket {
+    type Frame = CanAnyFrame;
+    type Error = Error;
+
+    /// Blocking call to receive the next frame from the bus.
+    ///
+    /// This blocks and waits for the next frame to be received from the bus.
+    /// If an error frame is received, it will be converted to a `CanError`
+    /// and returned as an error.
+    fn receive(&mut self) -> Result<Self::Frame> {
+        match self.read_frame()? {
+            CanAnyFrame::Error(frame) => Err(frame.into_error().into()),
+            frame => Ok(frame),
+        }
+    }
+
+    /// Blocking transmit of a frame to the bus.
+    fn transmit(&mut self, frame: &Self::Frame) -> Result<()> {
+        self.write_frame_insist(frame).map_err(|err| err.into())
+    }
+}
+
+impl embedded_can::nb::Can for CanFdSocket {
+    type Frame = CanAnyFrame;
+    type Error = Error;
+
+    /// Non-blocking call to receive the next frame from the bus.
+    ///
+    /// If an error frame is received, it will be converted to a `CanError`
+    /// and returned as an error.
+    /// If no frame is available, it returns a `WouldBlock` error.
+    fn receive(&mut self) -> nb::Result<Self::Frame, Self::Error> {
+        match self.read_frame() {
+            Ok(CanAnyFrame::Error(frame)) => Err(Error::from(frame.into_error()).into()),
+            Ok(frame) => Ok(frame),
+            Err(err) => Err(match err.kind() {
+                ErrorKind::WouldBlock => nb::Error::WouldBlock,
+                _ => Error::from(err).into(),
+            }),
+        }
+    }
+
+    /// Non-blocking transmit of a frame to the bus.
+    fn transmit(&mut self, frame: &Self::Frame) -> nb::Result<Option<Self::Frame>, Self::Error> {
+        match self.write_frame(frame) {
+            Ok(_) => Ok(None),
+            Err(err) => match err.kind() {
+                ErrorKind::WouldBlock => Err(nb::Error::WouldBlock),
+                _ => Err(Error::from(err).into()),
+            },
+        }
+    }
+}
@@ -0,0 +1,182 @@
+// socketcan/src/message.rs
+//
+// Typed messages mapped to and from frames.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! Typed messages.
+//!
+//! The [`CanMessage`] trait maps a user type to the payload of a frame
+//! with a fixed ID. It's a lightweight alternative to a DBC database for
+//! in-house protocols, where the message layout is known at compile time.
+//!
+//! With the `derive` feature, the trait can be derived for a struct,
+//! giving each field a bit layout, which also generates conversions to
+//! and from a [`CanFrame`]. Converting a frame with another ID fails with
+//! a [`MessageError`]:
+//!
+#![cfg_attr(feature = "derive", doc = "```")]
+#![cfg_attr(not(feature = "derive"), doc = "```ignore")]
+//! use socketcan::{message::CanMessage, CanFrame, EmbeddedFrame, Frame};
+//!
+//! #[derive(Debug, Default, PartialEq, CanMessage)]
+//! #[can_message(id = 0x123)]
+//! struct Status {
+//!     #[can(start = 0, len = 16)]
+//!     rpm: u16,
+//!     #[can(start = 16, len = 8, scale = 0.5, offset = -40)]
+//!     temp: f32,
+//!     #[can(start = 24)]
+//!     running: bool,
+//! }
+//!
+//! let frame = CanFrame::from(Status { rpm: 1500, temp: 20.5, running: true });
+//! assert_eq!(Some(Status { rpm: 1500, temp: 20.5, running: true }),
+//!            Status::from_frame(&frame));
+//!
+//! let other = CanFrame::from_raw_id(0x124, frame.data()).unwrap();
+//! assert!(Status::try_from(other).is_err());
+//! ```
+//!
+//! The struct attribute takes the message `id`, and optionally `extended`
+//! to force an extended ID, and the payload `len` in bytes (default 8).
+//! Each field takes the `start` bit and `len` (default 1 for a `bool`,
+//! otherwise the size of the type), and optionally `big_endian`, and the
+//! `scale` and `offset` for floating point fields. The bit numbering is
+//! the same as for a [`Signal`](crate::signal::Signal). Integer values
+//! are truncated to the width of the field, and physical values that
+//! can't be represented are encoded as zero.
+//!
+//! The ID and the field layouts are checked when the macro expands, so a
+//! field that doesn't fit in the payload, or is wider than its integer
+//! type, is a compile error.
+
+use crate::{
+    frame::{can_frame_default, CAN_MAX_DLEN},
+    CanFrame, CanId, Frame,
+};
+use thiserror::Error;
+
+#[cfg(feature = "derive")]
+pub use socketcan_derive::CanMessage;
+
+/// An error converting a frame into a typed message.
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageError {
+    /// The frame doesn't have the ID of the message
+    #[error("frame ID {0} doesn't match the message")]
+    WrongId(CanId),
+}
+
+/// A typed message with a fixed ID, mapped to and from a frame payload.
+pub trait CanMessage: Sized {
+    /// The raw ID of the message
+    const ID: u32;
+    /// Whether the ID is extended, even if it fits in 11 bits
+    const EXTENDED: bool = false;
+    /// The length of the payload, in bytes
+    const LEN: usize = 8;
+
+    /// Decodes the message from a payload.
+    ///
+    /// Any fields that don't fit in the payload are set to zero.
+    fn decode(data: &[u8]) -> Self;
+
+    /// Encodes the message into a payload.
+    ///
+    /// Any fields that don't fit in the payload are left out.
+    fn encode(&self, data: &mut [u8]);
+
+    /// The ID of the message.
+    ///
+    /// This is evaluated at compile time, so an ID that doesn't fit in 29
+    /// bits fails to build. The derive macro also rejects it with an error
+    /// on the attribute.
+    const CAN_ID: CanId = {
+        let id = if Self::EXTENDED || Self::ID > 0x7FF {
+            CanId::extended(Self::ID)
+        } else {
+            CanId::standard(Self::ID as u16)
+        };
+        match id {
+            Ok(id) => id,
+            Err(_) => panic!("message ID must fit in 29 bits"),
+        }
+    };
+
+    /// Gets the ID of the message.
+    fn id() -> CanId {
+        Self::CAN_ID
+    }
+
+    /// Determines if the frame carries this message.
+    fn matches<F: Frame>(frame: &F) -> bool {
+        frame.can_id() == Self::id()
+    }
+
+    /// Decodes the message from a frame, if the frame has its ID.
+    fn from_frame<F: Frame>(frame: &F) -> Option<Self> {
+        Self::matches(frame).then(|| Self::decode(frame.data()))
+    }
+
+    /// Encodes the message into a data frame.
+    fn to_frame(&self) -> CanFrame {
+        let len = Self::LEN.min(CAN_MAX_DLEN);
+        let mut frame = can_frame_default();
+        frame.can_id = Self::id().id_word();
+        frame.can_dlc = len as u8;
+        self.encode(&mut frame.data[..len]);
+        CanFrame::from(frame)
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(all(test, feature = "derive"))]
+mod tests {
+    use super::*;
+    use crate::EmbeddedFrame;
+
+    #[derive(Debug, Default, PartialEq, CanMessage)]
+    #[can_message(id = 0x123, len = 4)]
+    struct Status {
+        #[can(start = 0, len = 16)]
+        rpm: u16,
+        #[can(start = 16, len = 8, scale = 0.5, offset = -40)]
+        temp: f32,
+        #[can(start = 24)]
+        running: bool,
+        #[can(start = 31, len = 4, big_endian)]
+        mode: i8,
+    }
+
+    #[test]
+    fn test_derive() {
+        let status = Status {
+            rpm: 1500,
+            temp: 20.5,
+            running: true,
+            mode: -2,
+        };
+
+        let frame = CanFrame::from(status);
+        assert_eq!(CanId::standard(0x123).unwrap(), frame.can_id());
+        assert_eq!(&[0xDC, 0x05, 0x79, 0xE1], frame.data());
+
+        let status = Status::try_from(frame).unwrap();
+        assert_eq!(-2, status.mode);
+        assert_eq!(Some(status), Status::from_frame(&frame));
+
+        let other = CanFrame::from_raw_id(0x124, &[0; 4]).unwrap();
+        assert_eq!(None, Status::from_frame(&other));
+        assert_eq!(
+            Err(MessageError::WrongId(CanId::standard(0x124).unwrap())),
+            Status::try_from(other)
+        );
+    }
+}
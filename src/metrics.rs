@@ -0,0 +1,700 @@
+// socketcan/src/metrics.rs
+//
+// Prometheus metrics for sockets, monitors, and the gateway.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! Prometheus metrics for sockets, monitors, and the gateway.
+//!
+//! A [`Registry`] holds a set of counters and gauges, and renders them in
+//! the Prometheus text exposition format, to be scraped from a
+//! [`MetricsServer`] or served by whatever HTTP stack the application
+//! already has.
+//!
+//! The metrics for an interface are kept in a [`BusMetrics`]:
+//!
+//! - `socketcan_frames_received_total` and
+//!   `socketcan_frames_transmitted_total`, counted by a [`MeteredBackend`]
+//!   wrapped around any socket or other backend.
+//! - `socketcan_io_errors_total`, by direction.
+//! - `socketcan_bus_errors_total`, by [`ErrorClass`], from error frames.
+//! - `socketcan_frames_dropped_total`
+//! - `socketcan_queue_depth`, such as the length of a
+//!   [`TxQueue`](crate::txqueue::TxQueue).
+//! - `socketcan_bus_load_percent`, from a
+//!   [`BusLoadMonitor`](crate::busload::BusLoadMonitor).
+//! - `socketcan_bus_state`, from a [`BusMonitor`](crate::monitor::BusMonitor),
+//!   as 0 for error active, up to 3 for bus off.
+//!
+//! The route statistics of a [`Gateway`] are exported with
+//! [`record_gateway`].
+//!
+//! ```no_run
+//! use socketcan::{
+//!     backend::CanReceiver,
+//!     metrics::{BusMetrics, MeteredBackend, MetricsServer, Registry},
+//!     CanSocket, Socket,
+//! };
+//!
+//! let registry = Registry::new();
+//! let _server = MetricsServer::bind("0.0.0.0:9100", registry.clone()).unwrap();
+//!
+//! let sock = CanSocket::open("can0").unwrap();
+//! let sock = MeteredBackend::new(sock, BusMetrics::new(&registry, "can0"));
+//!
+//! loop {
+//!     let frame = sock.receive().unwrap();
+//!     println!("{:?}", frame);
+//! }
+//! ```
+
+use crate::{
+    backend::{CanReceiver, CanTransmitter},
+    busload::BusLoad,
+    gateway::Gateway,
+    monitor::{BusHealth, ErrorClass},
+    CanAnyFrame, CanError, CanErrorFrame, Frame, IoErrorKind, IoResult,
+};
+use std::{
+    fmt::{self, Write as _},
+    io::{Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// The content type of the text exposition format.
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+// The longest HTTP request the server reads
+const MAX_REQUEST_LEN: usize = 8 * 1024;
+
+// ===== Counter & Gauge =====
+
+/// A counter, which only goes up.
+///
+/// Clones refer to the same value.
+#[derive(Debug, Clone, Default)]
+pub struct Counter(Arc<AtomicU64>);
+
+impl Counter {
+    /// Adds one to the counter.
+    pub fn inc(&self) {
+        self.inc_by(1);
+    }
+
+    /// Adds to the counter.
+    pub fn inc_by(&self, n: u64) {
+        self.0.fetch_add(n, Ordering::Relaxed);
+    }
+
+    /// Raises the counter to a total kept elsewhere, such as in the
+    /// statistics of a gateway route. A lower total is ignored.
+    pub fn observe_total(&self, total: u64) {
+        self.0.fetch_max(total, Ordering::Relaxed);
+    }
+
+    /// Gets the value of the counter.
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// A gauge, which can go up and down.
+///
+/// Clones refer to the same value.
+#[derive(Debug, Clone, Default)]
+pub struct Gauge(Arc<AtomicU64>);
+
+impl Gauge {
+    /// Sets the value of the gauge.
+    pub fn set(&self, val: f64) {
+        self.0.store(val.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Gets the value of the gauge.
+    pub fn get(&self) -> f64 {
+        f64::from_bits(self.0.load(Ordering::Relaxed))
+    }
+}
+
+// ===== Registry =====
+
+/// The type of a metric.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Counter,
+    Gauge,
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Kind::Counter => write!(f, "counter"),
+            Kind::Gauge => write!(f, "gauge"),
+        }
+    }
+}
+
+type Labels = Vec<(String, String)>;
+
+/// A metric and all of its labelled series.
+#[derive(Debug)]
+struct Family {
+    name: String,
+    help: String,
+    kind: Kind,
+    series: Vec<(Labels, Arc<AtomicU64>)>,
+}
+
+/// A set of metrics, to be rendered for Prometheus.
+///
+/// Clones refer to the same set.
+#[derive(Debug, Clone, Default)]
+pub struct Registry {
+    families: Arc<Mutex<Vec<Family>>>,
+}
+
+impl Registry {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Gets the counter with the name and labels, creating it if it
+    /// doesn't exist yet.
+    ///
+    /// # Panics
+    ///
+    /// If the name is already registered as a gauge.
+    pub fn counter(&self, name: &str, help: &str, labels: &[(&str, &str)]) -> Counter {
+        Counter(self.series(name, help, Kind::Counter, labels))
+    }
+
+    /// Gets the gauge with the name and labels, creating it if it doesn't
+    /// exist yet.
+    ///
+    /// # Panics
+    ///
+    /// If the name is already registered as a counter.
+    pub fn gauge(&self, name: &str, help: &str, labels: &[(&str, &str)]) -> Gauge {
+        Gauge(self.series(name, help, Kind::Gauge, labels))
+    }
+
+    fn series(
+        &self,
+        name: &str,
+        help: &str,
+        kind: Kind,
+        labels: &[(&str, &str)],
+    ) -> Arc<AtomicU64> {
+        let labels: Labels = labels
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+
+        let mut families = self.families.lock().unwrap();
+        let idx = match families.iter().position(|fam| fam.name == name) {
+            Some(idx) => idx,
+            None => {
+                families.push(Family {
+                    name: name.into(),
+                    help: help.into(),
+                    kind,
+                    series: Vec::new(),
+                });
+                families.len() - 1
+            }
+        };
+        let fam = &mut families[idx];
+        assert_eq!(
+            fam.kind, kind,
+            "metric '{}' registered as a {}",
+            name, fam.kind
+        );
+
+        if let Some((_, val)) = fam.series.iter().find(|(l, _)| *l == labels) {
+            return Arc::clone(val);
+        }
+        let val = Arc::new(AtomicU64::new(match kind {
+            Kind::Counter => 0,
+            Kind::Gauge => 0f64.to_bits(),
+        }));
+        fam.series.push((labels, Arc::clone(&val)));
+        val
+    }
+
+    /// Renders all the metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let families = self.families.lock().unwrap();
+        let mut out = String::new();
+        for fam in families.iter() {
+            let _ = writeln!(out, "# HELP {} {}", fam.name, escape(&fam.help, false));
+            let _ = writeln!(out, "# TYPE {} {}", fam.name, fam.kind);
+            for (labels, val) in &fam.series {
+                out.push_str(&fam.name);
+                if !labels.is_empty() {
+                    out.push('{');
+                    for (i, (k, v)) in labels.iter().enumerate() {
+                        if i > 0 {
+                            out.push(',');
+                        }
+                        let _ = write!(out, "{}=\"{}\"", k, escape(v, true));
+                    }
+                    out.push('}');
+                }
+                let val = val.load(Ordering::Relaxed);
+                let _ = match fam.kind {
+                    Kind::Counter => writeln!(out, " {}", val),
+                    Kind::Gauge => writeln!(out, " {}", format_float(f64::from_bits(val))),
+                };
+            }
+        }
+        out
+    }
+}
+
+// Escapes help text or a label value
+fn escape(s: &str, quotes: bool) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '"' if quotes => out.push_str("\\\""),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+// Formats a sample value the way Prometheus spells the special values
+fn format_float(val: f64) -> String {
+    if val.is_nan() {
+        "NaN".into()
+    } else if val.is_infinite() {
+        if val > 0.0 { "+Inf" } else { "-Inf" }.into()
+    } else {
+        val.to_string()
+    }
+}
+
+// ===== BusMetrics =====
+
+/// Gets the label for a class of error.
+///
+/// Protocol violations are all counted together, whatever their type.
+pub fn error_class_label(class: ErrorClass) -> &'static str {
+    match class {
+        ErrorClass::TransmitTimeout => "transmit_timeout",
+        ErrorClass::LostArbitration => "lost_arbitration",
+        ErrorClass::Controller => "controller",
+        ErrorClass::Protocol(_) => "protocol",
+        ErrorClass::Transceiver => "transceiver",
+        ErrorClass::NoAck => "no_ack",
+        ErrorClass::BusOff => "bus_off",
+        ErrorClass::BusError => "bus_error",
+        ErrorClass::Restarted => "restarted",
+        ErrorClass::Other => "other",
+    }
+}
+
+/// The metrics of one interface, labelled with its name.
+#[derive(Debug, Clone)]
+pub struct BusMetrics {
+    registry: Registry,
+    iface: String,
+    rx_frames: Counter,
+    tx_frames: Counter,
+    rx_errors: Counter,
+    tx_errors: Counter,
+    dropped: Counter,
+    queue_depth: Gauge,
+    bus_load: Gauge,
+    bus_state: Gauge,
+}
+
+impl BusMetrics {
+    /// Creates the metrics for the interface in the registry.
+    pub fn new(registry: &Registry, iface: &str) -> Self {
+        let io_errors = |dir| {
+            registry.counter(
+                "socketcan_io_errors_total",
+                "Failed reads and writes on the interface.",
+                &[("iface", iface), ("direction", dir)],
+            )
+        };
+        Self {
+            registry: registry.clone(),
+            iface: iface.into(),
+            rx_frames: registry.counter(
+                "socketcan_frames_received_total",
+                "Frames received from the interface.",
+                &[("iface", iface)],
+            ),
+            tx_frames: registry.counter(
+                "socketcan_frames_transmitted_total",
+                "Frames transmitted on the interface.",
+                &[("iface", iface)],
+            ),
+            rx_errors: io_errors("rx"),
+            tx_errors: io_errors("tx"),
+            dropped: registry.counter(
+                "socketcan_frames_dropped_total",
+                "Frames dropped before they could be handled or sent.",
+                &[("iface", iface)],
+            ),
+            queue_depth: registry.gauge(
+                "socketcan_queue_depth",
+                "Frames waiting in the transmit queue.",
+                &[("iface", iface)],
+            ),
+            bus_load: registry.gauge(
+                "socketcan_bus_load_percent",
+                "The percentage of time the bus was busy.",
+                &[("iface", iface)],
+            ),
+            bus_state: registry.gauge(
+                "socketcan_bus_state",
+                "The fault confinement state: 0 active, 1 warning, 2 passive, 3 bus off.",
+                &[("iface", iface)],
+            ),
+        }
+    }
+
+    /// Gets the name of the interface.
+    pub fn iface(&self) -> &str {
+        &self.iface
+    }
+
+    /// Counts a received frame.
+    pub fn record_rx(&self) {
+        self.rx_frames.inc();
+    }
+
+    /// Counts a transmitted frame.
+    pub fn record_tx(&self) {
+        self.tx_frames.inc();
+    }
+
+    /// Counts a failed read from the interface.
+    pub fn record_rx_error(&self) {
+        self.rx_errors.inc();
+    }
+
+    /// Counts a failed write to the interface.
+    pub fn record_tx_error(&self) {
+        self.tx_errors.inc();
+    }
+
+    /// Counts dropped frames.
+    pub fn record_drops(&self, n: u64) {
+        self.dropped.inc_by(n);
+    }
+
+    /// Counts a bus error, by its class.
+    pub fn record_error(&self, err: &CanError) {
+        self.registry
+            .counter(
+                "socketcan_bus_errors_total",
+                "Errors reported by the controller, by class.",
+                &[
+                    ("iface", &self.iface),
+                    ("class", error_class_label(ErrorClass::from(err))),
+                ],
+            )
+            .inc();
+    }
+
+    /// Counts each of the errors reported in an error frame.
+    pub fn record_error_frame(&self, frame: &CanErrorFrame) {
+        for err in frame.errors() {
+            self.record_error(&err);
+        }
+    }
+
+    /// Sets the number of frames waiting to be sent.
+    pub fn set_queue_depth(&self, len: usize) {
+        self.queue_depth.set(len as f64);
+    }
+
+    /// Sets the bus load from a measurement.
+    pub fn set_bus_load(&self, load: &BusLoad) {
+        self.bus_load.set(load.utilization);
+    }
+
+    /// Sets the fault confinement state of the controller.
+    pub fn set_health(&self, health: BusHealth) {
+        let state = match health {
+            BusHealth::Active => 0.0,
+            BusHealth::Warning => 1.0,
+            BusHealth::Passive => 2.0,
+            BusHealth::BusOff => 3.0,
+        };
+        self.bus_state.set(state);
+    }
+}
+
+/// Exports the statistics of each of the gateway's routes, labelled with
+/// the name of the gateway and the index of the route.
+///
+/// This can be called as often as the metrics are scraped.
+pub fn record_gateway(registry: &Registry, name: &str, gw: &Gateway) {
+    for (i, route) in gw.routes().iter().enumerate() {
+        let i = i.to_string();
+        let stats = route.stats();
+        registry
+            .counter(
+                "socketcan_gateway_forwarded_total",
+                "Frames forwarded by a gateway route.",
+                &[("gateway", name), ("route", &i)],
+            )
+            .observe_total(stats.forwarded);
+        for (reason, n) in [
+            ("rate_limited", stats.rate_limited),
+            ("unconvertible", stats.unconvertible),
+        ] {
+            registry
+                .counter(
+                    "socketcan_gateway_dropped_total",
+                    "Frames dropped by a gateway route, by reason.",
+                    &[("gateway", name), ("route", &i), ("reason", reason)],
+                )
+                .observe_total(n);
+        }
+    }
+}
+
+// ===== MeteredBackend =====
+
+/// A backend wrapper that counts the frames and errors going through it.
+///
+/// Received error frames are also counted by class. A receive that
+/// times out isn't an error.
+#[derive(Debug)]
+pub struct MeteredBackend<B> {
+    inner: B,
+    metrics: BusMetrics,
+}
+
+impl<B> MeteredBackend<B> {
+    /// Wraps the backend.
+    pub fn new(inner: B, metrics: BusMetrics) -> Self {
+        Self { inner, metrics }
+    }
+
+    /// Gets the metrics.
+    pub fn metrics(&self) -> &BusMetrics {
+        &self.metrics
+    }
+
+    /// Gets a reference to the wrapped backend.
+    pub fn get_ref(&self) -> &B {
+        &self.inner
+    }
+
+    /// Unwraps the backend.
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+
+    fn count_rx<F>(&self, res: IoResult<F>) -> IoResult<F>
+    where
+        F: Frame + Clone + Into<CanAnyFrame>,
+    {
+        match &res {
+            Ok(frame) => {
+                self.metrics.record_rx();
+                if frame.is_error_frame() {
+                    if let CanAnyFrame::Error(frame) = frame.clone().into() {
+                        self.metrics.record_error_frame(&frame);
+                    }
+                }
+            }
+            Err(err) if matches!(err.kind(), IoErrorKind::WouldBlock | IoErrorKind::TimedOut) => {}
+            Err(_) => self.metrics.record_rx_error(),
+        }
+        res
+    }
+}
+
+impl<B, F> CanTransmitter<F> for MeteredBackend<B>
+where
+    B: CanTransmitter<F>,
+{
+    fn transmit(&self, frame: &F) -> IoResult<()> {
+        let res = self.inner.transmit(frame);
+        match res {
+            Ok(()) => self.metrics.record_tx(),
+            Err(_) => self.metrics.record_tx_error(),
+        }
+        res
+    }
+}
+
+impl<B> CanReceiver for MeteredBackend<B>
+where
+    B: CanReceiver,
+    B::Frame: Frame + Clone + Into<CanAnyFrame>,
+{
+    type Frame = B::Frame;
+
+    fn receive(&self) -> IoResult<Self::Frame> {
+        self.count_rx(self.inner.receive())
+    }
+
+    fn receive_timeout(&self, timeout: Duration) -> IoResult<Self::Frame> {
+        self.count_rx(self.inner.receive_timeout(timeout))
+    }
+}
+
+// ===== MetricsServer =====
+
+/// A minimal HTTP server that answers every request with the rendered
+/// metrics, for Prometheus to scrape.
+///
+/// The server is stopped when this is dropped.
+#[derive(Debug)]
+pub struct MetricsServer {
+    addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MetricsServer {
+    /// Starts serving the registry on the address.
+    pub fn bind<A: ToSocketAddrs>(addr: A, registry: Registry) -> IoResult<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let addr = listener.local_addr()?;
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let thr_stop = Arc::clone(&stop);
+        let thread = thread::Builder::new()
+            .name("metrics-server".into())
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    if thr_stop.load(Ordering::Acquire) {
+                        break;
+                    }
+                    if let Ok(stream) = stream {
+                        let _ = Self::respond(stream, &registry);
+                    }
+                }
+            })?;
+
+        Ok(Self {
+            addr,
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    /// Gets the address the server is listening on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    fn respond(mut stream: TcpStream, registry: &Registry) -> IoResult<()> {
+        stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+
+        // Read up to the end of the headers; the request itself doesn't matter
+        let mut req = Vec::new();
+        let mut buf = [0u8; 1024];
+        while !req.windows(4).any(|w| w == b"\r\n\r\n") && req.len() < MAX_REQUEST_LEN {
+            let n = stream.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            req.extend_from_slice(&buf[..n]);
+        }
+
+        let body = registry.render();
+        write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            CONTENT_TYPE,
+            body.len()
+        )?;
+        stream.write_all(body.as_bytes())?;
+        stream.flush()
+    }
+}
+
+impl Drop for MetricsServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+        // Wake the accept loop, so it sees the stop flag
+        let _ = TcpStream::connect(self.addr);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{vbus::VirtualBus, CanFrame};
+
+    #[test]
+    fn test_render() {
+        let registry = Registry::new();
+        let metrics = BusMetrics::new(&registry, "can0");
+        metrics.record_rx();
+        metrics.record_rx();
+        metrics.record_error(&CanError::NoAck);
+        metrics.set_queue_depth(3);
+        metrics.set_health(BusHealth::Passive);
+
+        let text = registry.render();
+        assert!(text.contains("# TYPE socketcan_frames_received_total counter\n"));
+        assert!(text.contains("socketcan_frames_received_total{iface=\"can0\"} 2\n"));
+        assert!(text.contains("socketcan_bus_errors_total{iface=\"can0\",class=\"no_ack\"} 1\n"));
+        assert!(text.contains("socketcan_queue_depth{iface=\"can0\"} 3\n"));
+        assert!(text.contains("socketcan_bus_state{iface=\"can0\"} 2\n"));
+
+        // The same series is shared, not duplicated
+        registry
+            .counter("socketcan_frames_received_total", "", &[("iface", "can0")])
+            .inc();
+        assert_eq!(3, metrics.rx_frames.get());
+        assert_eq!("a\\\"b\\\\", escape("a\"b\\", true));
+    }
+
+    #[test]
+    fn test_metered_backend_and_server() {
+        let bus = VirtualBus::new();
+        let registry = Registry::new();
+        let a = MeteredBackend::new(bus.endpoint(), BusMetrics::new(&registry, "vcan0"));
+        let b = bus.endpoint();
+
+        let frame = CanFrame::from_raw_id(0x123, &[1]).unwrap();
+        a.transmit(&CanAnyFrame::from(frame)).unwrap();
+        b.transmit(&CanAnyFrame::from(frame)).unwrap();
+        a.receive_timeout(Duration::from_secs(1)).unwrap();
+        assert!(a.receive_timeout(Duration::ZERO).is_err());
+
+        assert_eq!(1, a.metrics().tx_frames.get());
+        assert_eq!(1, a.metrics().rx_frames.get());
+        assert_eq!(0, a.metrics().rx_errors.get());
+
+        let server = MetricsServer::bind("127.0.0.1:0", registry).unwrap();
+        let mut stream = TcpStream::connect(server.local_addr()).unwrap();
+        stream.write_all(b"GET /metrics HTTP/1.1\r\n\r\n").unwrap();
+        let mut resp = String::new();
+        stream.read_to_string(&mut resp).unwrap();
+        assert!(resp.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(resp.contains("socketcan_frames_transmitted_total{iface=\"vcan0\"} 1\n"));
+    }
+}
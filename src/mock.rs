@@ -0,0 +1,320 @@
+// socketcan/src/mock.rs
+//
+// A scriptable mock socket for unit tests.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! A scriptable mock socket for unit tests.
+//!
+//! A [`MockCanSocket`] implements the [`CanTransmitter`] and
+//! [`CanReceiver`] traits without any hardware or kernel support. The
+//! frames it receives are scripted ahead of time, optionally with delays
+//! and errors, and the frames sent to it are recorded along with the time
+//! they were sent, so a test can check both what a driver sent and when.
+//!
+//! The mock is a handle to shared state, so a clone can be handed to the
+//! code under test, such as a [`Scheduler`](crate::scheduler::Scheduler)
+//! that takes ownership of its socket, while the test keeps the original.
+//!
+//! ```
+//! use socketcan::{
+//!     backend::{CanReceiver, CanTransmitter},
+//!     mock::MockCanSocket,
+//!     CanFrame, EmbeddedFrame, Frame,
+//! };
+//!
+//! let sock = MockCanSocket::<CanFrame>::new();
+//! sock.push_rx(CanFrame::from_raw_id(0x7E8, &[0x02, 0x50, 0x01]).unwrap());
+//!
+//! // The code under test
+//! let req = sock.receive().unwrap();
+//! sock.transmit(&CanFrame::from_raw_id(0x7E0, req.data()).unwrap()).unwrap();
+//!
+//! assert_eq!(0x7E0, sock.sent()[0].raw_id());
+//! ```
+
+use crate::{
+    backend::{CanReceiver, CanTransmitter},
+    IoError, IoErrorKind, IoResult,
+};
+use std::{
+    collections::VecDeque,
+    fmt,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// A scripted event for the receive side.
+#[derive(Debug, Clone)]
+enum RxEvent<F> {
+    Frame(Duration, F),
+    Error(IoErrorKind),
+}
+
+/// The shared state of the mock.
+#[derive(Debug)]
+struct State<F> {
+    rx: VecDeque<RxEvent<F>>,
+    tx: Vec<(Instant, F)>,
+    tx_errors: VecDeque<IoErrorKind>,
+}
+
+/// A mock socket with scripted receive frames and recorded transmit
+/// frames.
+///
+/// Once the script runs out, [`receive`](CanReceiver::receive) fails with
+/// `UnexpectedEof`, rather than blocking forever, and
+/// [`receive_timeout`](CanReceiver::receive_timeout) times out.
+pub struct MockCanSocket<F> {
+    state: Arc<Mutex<State<F>>>,
+    epoch: Instant,
+}
+
+impl<F> MockCanSocket<F> {
+    /// Creates a mock with an empty script.
+    pub fn new() -> Self {
+        Self {
+            state: Arc::new(Mutex::new(State {
+                rx: VecDeque::new(),
+                tx: Vec::new(),
+                tx_errors: VecDeque::new(),
+            })),
+            epoch: Instant::now(),
+        }
+    }
+
+    /// Adds a frame to be received, right away.
+    pub fn push_rx(&self, frame: impl Into<F>) {
+        self.push_rx_after(Duration::ZERO, frame);
+    }
+
+    /// Adds a frame to be received after a delay, counted from when the
+    /// receiver starts waiting for it.
+    pub fn push_rx_after(&self, delay: Duration, frame: impl Into<F>) {
+        let ev = RxEvent::Frame(delay, frame.into());
+        self.state.lock().unwrap().rx.push_back(ev);
+    }
+
+    /// Adds an error to be returned by the receiver, in turn.
+    pub fn push_rx_error(&self, kind: IoErrorKind) {
+        self.state
+            .lock()
+            .unwrap()
+            .rx
+            .push_back(RxEvent::Error(kind));
+    }
+
+    /// Makes the next transmit fail with the error, without recording the
+    /// frame. Several errors are returned in turn.
+    pub fn fail_tx(&self, kind: IoErrorKind) {
+        self.state.lock().unwrap().tx_errors.push_back(kind);
+    }
+
+    /// Gets the number of scripted events that haven't been received.
+    pub fn rx_remaining(&self) -> usize {
+        self.state.lock().unwrap().rx.len()
+    }
+
+    /// Gets the time since the mock was created, which is the base of the
+    /// transmit times.
+    pub fn elapsed(&self) -> Duration {
+        self.epoch.elapsed()
+    }
+}
+
+impl<F: Clone> MockCanSocket<F> {
+    /// Gets the frames sent so far.
+    pub fn sent(&self) -> Vec<F> {
+        let state = self.state.lock().unwrap();
+        state.tx.iter().map(|(_, frame)| frame.clone()).collect()
+    }
+
+    /// Gets the frames sent so far, each with the time it was sent,
+    /// relative to the creation of the mock.
+    pub fn sent_with_times(&self) -> Vec<(Duration, F)> {
+        let state = self.state.lock().unwrap();
+        state
+            .tx
+            .iter()
+            .map(|(t, frame)| (t.duration_since(self.epoch), frame.clone()))
+            .collect()
+    }
+
+    /// Takes the frames sent so far, clearing the record.
+    pub fn take_sent(&self) -> Vec<F> {
+        let mut state = self.state.lock().unwrap();
+        state.tx.drain(..).map(|(_, frame)| frame).collect()
+    }
+
+    /// Gets the intervals between consecutive sent frames.
+    pub fn tx_intervals(&self) -> Vec<Duration> {
+        let state = self.state.lock().unwrap();
+        state
+            .tx
+            .windows(2)
+            .map(|w| w[1].0.duration_since(w[0].0))
+            .collect()
+    }
+
+    /// Asserts that the frames were sent at the period, give or take the
+    /// tolerance.
+    ///
+    /// # Panics
+    ///
+    /// If fewer than two frames were sent, or any interval between them
+    /// is out of tolerance.
+    #[track_caller]
+    pub fn assert_tx_period(&self, period: Duration, tolerance: Duration) {
+        let intervals = self.tx_intervals();
+        assert!(!intervals.is_empty(), "fewer than two frames were sent");
+        for (i, dt) in intervals.iter().enumerate() {
+            let err = if *dt > period {
+                *dt - period
+            } else {
+                period - *dt
+            };
+            assert!(
+                err <= tolerance,
+                "interval {} was {:?}, expected {:?} ± {:?}",
+                i,
+                dt,
+                period,
+                tolerance
+            );
+        }
+    }
+}
+
+impl<F> MockCanSocket<F> {
+    fn receive_with(&self, timeout: Option<Duration>) -> IoResult<F> {
+        let ev = self.state.lock().unwrap().rx.pop_front();
+        match ev {
+            Some(RxEvent::Frame(delay, frame)) => match timeout {
+                Some(timeout) if delay > timeout => {
+                    // Put the rest of the wait back at the front
+                    thread::sleep(timeout);
+                    let ev = RxEvent::Frame(delay - timeout, frame);
+                    self.state.lock().unwrap().rx.push_front(ev);
+                    Err(IoErrorKind::TimedOut.into())
+                }
+                _ => {
+                    thread::sleep(delay);
+                    Ok(frame)
+                }
+            },
+            Some(RxEvent::Error(kind)) => Err(kind.into()),
+            None => match timeout {
+                Some(timeout) => {
+                    thread::sleep(timeout);
+                    Err(IoErrorKind::TimedOut.into())
+                }
+                None => Err(IoError::new(
+                    IoErrorKind::UnexpectedEof,
+                    "mock receive script exhausted",
+                )),
+            },
+        }
+    }
+}
+
+impl<F> Default for MockCanSocket<F> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<F> Clone for MockCanSocket<F> {
+    fn clone(&self) -> Self {
+        Self {
+            state: Arc::clone(&self.state),
+            epoch: self.epoch,
+        }
+    }
+}
+
+impl<F: fmt::Debug> fmt::Debug for MockCanSocket<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MockCanSocket")
+            .field("state", &self.state)
+            .finish()
+    }
+}
+
+impl<F, G> CanTransmitter<G> for MockCanSocket<F>
+where
+    G: Into<F> + Clone,
+{
+    fn transmit(&self, frame: &G) -> IoResult<()> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(kind) = state.tx_errors.pop_front() {
+            return Err(kind.into());
+        }
+        state.tx.push((Instant::now(), frame.clone().into()));
+        Ok(())
+    }
+}
+
+impl<F> CanReceiver for MockCanSocket<F> {
+    type Frame = F;
+
+    fn receive(&self) -> IoResult<F> {
+        self.receive_with(None)
+    }
+
+    fn receive_timeout(&self, timeout: Duration) -> IoResult<F> {
+        self.receive_with(Some(timeout))
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{scheduler::Scheduler, CanAnyFrame, CanFrame, Frame};
+
+    #[test]
+    fn test_script() {
+        let sock = MockCanSocket::<CanAnyFrame>::new();
+        let frame = CanFrame::from_raw_id(0x100, &[1]).unwrap();
+        sock.push_rx(frame);
+        sock.push_rx_error(IoErrorKind::Interrupted);
+        sock.push_rx_after(Duration::from_millis(30), frame);
+
+        assert_eq!(0x100, sock.receive().unwrap().raw_id());
+        assert_eq!(IoErrorKind::Interrupted, sock.receive().unwrap_err().kind());
+        let err = sock.receive_timeout(Duration::from_millis(10)).unwrap_err();
+        assert_eq!(IoErrorKind::TimedOut, err.kind());
+        assert!(sock.receive_timeout(Duration::from_millis(50)).is_ok());
+        assert_eq!(
+            IoErrorKind::UnexpectedEof,
+            sock.receive().unwrap_err().kind()
+        );
+
+        sock.fail_tx(IoErrorKind::WouldBlock);
+        assert!(sock.transmit(&frame).is_err());
+        sock.transmit(&frame).unwrap();
+        assert_eq!(1, sock.take_sent().len());
+        assert!(sock.sent().is_empty());
+    }
+
+    #[test]
+    fn test_scheduler_timing() {
+        let sock = MockCanSocket::<CanFrame>::new();
+        let sched = Scheduler::start(sock.clone()).unwrap();
+        let frame = CanFrame::from_raw_id(0x123, &[]).unwrap();
+        sched.add(Duration::from_millis(20), Duration::ZERO, move || frame);
+
+        thread::sleep(Duration::from_millis(110));
+        sched.stop().unwrap();
+
+        assert!(sock.sent().len() >= 4);
+        sock.assert_tx_period(Duration::from_millis(20), Duration::from_millis(15));
+    }
+}
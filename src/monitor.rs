@@ -0,0 +1,562 @@
+// socketcan/src/monitor.rs
+//
+// Bus health tracking from error frames and error counters.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! Bus health tracking.
+//!
+//! A CAN controller moves through a set of fault confinement states as its
+//! transmit and receive error counters rise and fall:
+//!
+//! ```text
+//! Error Active  (counters < 96)
+//!   => Error Warning  (counters >= 96)
+//!     => Error Passive  (counters >= 128)
+//!       => Bus Off  (TX counter >= 256)
+//! ```
+//!
+//! The [`BusMonitor`] follows these transitions from the error frames
+//! received on a socket, and/or from polling the error counters of the
+//! interface, and gives the application one clear signal of the health of
+//! the bus. Applications can get notified of transitions with a callback or
+//! through a channel.
+//!
+//! To receive the error frames, the socket must have an error filter that
+//! includes at least the controller problems, bus-off, and restarted errors.
+//!
+//! ```no_run
+//! use socketcan::{monitor::BusMonitor, CanAnyFrame, CanFdSocket, Socket, SocketOptions};
+//!
+//! let sock = CanFdSocket::open("can0").unwrap();
+//! sock.set_error_filter_accept_all().unwrap();
+//!
+//! let mut monitor = BusMonitor::new();
+//! monitor.on_transition(|tr| println!("Bus went from {} to {}", tr.from, tr.to));
+//!
+//! loop {
+//!     if let CanAnyFrame::Error(frame) = sock.read_frame().unwrap() {
+//!         monitor.process_error_frame(&frame);
+//!     }
+//! }
+//! ```
+//!
+//! The [`ErrorStats`] aggregator counts the error frames by class over a
+//! sliding time window, and reports the rate of each, such as the number
+//! of bit stuffing errors per second. A steady trickle of protocol errors
+//! is often the first sign of marginal wiring or bad termination.
+
+use crate::{
+    errors::{ControllerProblem, ViolationType},
+    CanError, CanErrorFrame,
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    sync::mpsc,
+    time::Duration,
+};
+
+/// The error counter level at which the controller goes to warning
+pub const WARNING_LIMIT: u16 = 96;
+
+/// The error counter level at which the controller goes error passive
+pub const PASSIVE_LIMIT: u16 = 128;
+
+/// The TX error counter level at which the controller goes bus-off
+pub const BUS_OFF_LIMIT: u16 = 256;
+
+// ===== BusHealth =====
+
+/// The fault confinement state of the CAN controller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum BusHealth {
+    /// Error active; normal operation
+    #[default]
+    Active,
+    /// Error warning; the error counters are getting high
+    Warning,
+    /// Error passive; the controller can no longer signal errors actively
+    Passive,
+    /// Bus off; the controller has disconnected from the bus
+    BusOff,
+}
+
+impl BusHealth {
+    /// Determines the state from the TX and RX error counters.
+    pub fn from_counters(tx: u16, rx: u16) -> Self {
+        let max = tx.max(rx);
+        if tx >= BUS_OFF_LIMIT {
+            BusHealth::BusOff
+        } else if max >= PASSIVE_LIMIT {
+            BusHealth::Passive
+        } else if max >= WARNING_LIMIT {
+            BusHealth::Warning
+        } else {
+            BusHealth::Active
+        }
+    }
+}
+
+impl fmt::Display for BusHealth {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let msg = match *self {
+            BusHealth::Active => "error active",
+            BusHealth::Warning => "error warning",
+            BusHealth::Passive => "error passive",
+            BusHealth::BusOff => "bus off",
+        };
+        write!(f, "{}", msg)
+    }
+}
+
+#[cfg(feature = "netlink")]
+impl From<crate::nl::CanState> for BusHealth {
+    /// Converts the state of an interface, as reported by netlink.
+    ///
+    /// A stopped or sleeping interface is reported as bus-off, since it
+    /// can't communicate.
+    fn from(state: crate::nl::CanState) -> Self {
+        use crate::nl::CanState::*;
+        match state {
+            ErrorActive => BusHealth::Active,
+            ErrorWarning => BusHealth::Warning,
+            ErrorPassive => BusHealth::Passive,
+            BusOff | Stopped | Sleeping => BusHealth::BusOff,
+        }
+    }
+}
+
+// ===== Transition =====
+
+/// A change in the health of the bus.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Transition {
+    /// The previous state
+    pub from: BusHealth,
+    /// The new state
+    pub to: BusHealth,
+}
+
+impl Transition {
+    /// Determines if the bus health got worse.
+    pub fn is_degraded(&self) -> bool {
+        self.to > self.from
+    }
+}
+
+// ===== BusMonitor =====
+
+/// A callback for bus health transitions
+type TransitionCallback = Box<dyn FnMut(&Transition) + Send>;
+
+/// A tracker for the health of the bus.
+///
+/// This maintains the fault confinement state of the controller from the
+/// error frames and error counters that are given to it.
+#[derive(Default)]
+pub struct BusMonitor {
+    state: BusHealth,
+    counters: Option<(u16, u16)>,
+    callbacks: Vec<TransitionCallback>,
+    subscribers: Vec<mpsc::Sender<Transition>>,
+}
+
+impl BusMonitor {
+    /// Creates a new monitor, which assumes the bus is error active.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Gets the current health of the bus.
+    pub fn state(&self) -> BusHealth {
+        self.state
+    }
+
+    /// Gets the most recent TX and RX error counters, as `(tx, rx)`, if any
+    /// have been reported.
+    pub fn counters(&self) -> Option<(u16, u16)> {
+        self.counters
+    }
+
+    /// Registers a callback that is called on each state transition.
+    pub fn on_transition<F>(&mut self, f: F)
+    where
+        F: FnMut(&Transition) + Send + 'static,
+    {
+        self.callbacks.push(Box::new(f));
+    }
+
+    /// Subscribes to state transitions through a channel.
+    pub fn subscribe(&mut self) -> mpsc::Receiver<Transition> {
+        let (tx, rx) = mpsc::channel();
+        self.subscribers.push(tx);
+        rx
+    }
+
+    /// Updates the state from an error frame.
+    ///
+    /// Explicit state changes reported in the frame (bus-off, restarted,
+    /// or the controller warning/passive/active problems) take precedence.
+    /// Otherwise, if the frame carries the error counters, the state is
+    /// derived from them. Returns the transition, if the state changed.
+    pub fn process_error_frame(&mut self, frame: &CanErrorFrame) -> Option<Transition> {
+        let counters = frame
+            .error_counters()
+            .map(|(tx, rx)| (u16::from(tx), u16::from(rx)));
+        if counters.is_some() {
+            self.counters = counters;
+        }
+
+        let state = if frame.is_bus_off() {
+            Some(BusHealth::BusOff)
+        } else if frame.is_restarted() {
+            Some(BusHealth::Active)
+        } else {
+            frame
+                .controller_problems()
+                .and_then(|probs| probs.ok())
+                .and_then(|probs| probs.into_iter().filter_map(problem_state).max())
+                .or_else(|| counters.map(|(tx, rx)| BusHealth::from_counters(tx, rx)))
+        };
+
+        state.and_then(|state| self.set_state(state))
+    }
+
+    /// Updates the state from a poll of the TX and RX error counters, such
+    /// as from the `berr_counter` of the interface.
+    ///
+    /// Returns the transition, if the state changed.
+    pub fn update_counters(&mut self, tx: u16, rx: u16) -> Option<Transition> {
+        self.counters = Some((tx, rx));
+        self.set_state(BusHealth::from_counters(tx, rx))
+    }
+
+    /// Polls the interface for its state and error counters.
+    ///
+    /// The state reported by the interface is used, if available, as it
+    /// includes bus-off, which can't always be seen in the counters.
+    /// Returns the transition, if the state changed.
+    #[cfg(feature = "netlink")]
+    pub fn poll_interface(
+        &mut self,
+        iface: &crate::nl::CanInterface,
+    ) -> Result<
+        Option<Transition>,
+        neli::err::NlError<neli::consts::rtnl::Rtm, neli::rtnl::Ifinfomsg>,
+    > {
+        let details = iface.details()?;
+        if let Some(berr) = details.can.berr_counter {
+            self.counters = Some((berr.txerr, berr.rxerr));
+        }
+        let state = match (details.can.state, self.counters) {
+            (Some(state), _) => BusHealth::from(state),
+            (None, Some((tx, rx))) => BusHealth::from_counters(tx, rx),
+            (None, None) => return Ok(None),
+        };
+        Ok(self.set_state(state))
+    }
+
+    /// Sets the state, notifying any listeners if it changed.
+    fn set_state(&mut self, state: BusHealth) -> Option<Transition> {
+        if state == self.state {
+            return None;
+        }
+
+        let tr = Transition {
+            from: self.state,
+            to: state,
+        };
+        self.state = state;
+
+        for cb in self.callbacks.iter_mut() {
+            cb(&tr);
+        }
+        self.subscribers.retain(|tx| tx.send(tr).is_ok());
+        Some(tr)
+    }
+}
+
+impl fmt::Debug for BusMonitor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BusMonitor")
+            .field("state", &self.state)
+            .field("counters", &self.counters)
+            .field("callbacks", &self.callbacks.len())
+            .field("subscribers", &self.subscribers.len())
+            .finish()
+    }
+}
+
+// ===== ErrorClass =====
+
+/// The class of an error reported in an error frame, for statistics.
+///
+/// This is a [`CanError`] without the details that would keep otherwise
+/// similar errors from being counted together, like the location of a
+/// protocol violation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorClass {
+    /// TX timeout
+    TransmitTimeout,
+    /// Lost arbitration
+    LostArbitration,
+    /// Controller problem
+    Controller,
+    /// Protocol violation of the specified type
+    Protocol(ViolationType),
+    /// Transceiver error
+    Transceiver,
+    /// No ACK received
+    NoAck,
+    /// Bus off
+    BusOff,
+    /// Bus error
+    BusError,
+    /// The bus was restarted
+    Restarted,
+    /// An unknown error, or one that couldn't be decoded
+    Other,
+}
+
+impl From<&CanError> for ErrorClass {
+    fn from(err: &CanError) -> Self {
+        use CanError::*;
+        match *err {
+            TransmitTimeout => ErrorClass::TransmitTimeout,
+            LostArbitration(_) => ErrorClass::LostArbitration,
+            ControllerProblem(_) => ErrorClass::Controller,
+            ProtocolViolation { vtype, .. } => ErrorClass::Protocol(vtype),
+            TransceiverError => ErrorClass::Transceiver,
+            NoAck => ErrorClass::NoAck,
+            BusOff => ErrorClass::BusOff,
+            BusError => ErrorClass::BusError,
+            Restarted => ErrorClass::Restarted,
+            DecodingFailure(_) | Unknown(_) => ErrorClass::Other,
+        }
+    }
+}
+
+impl fmt::Display for ErrorClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ErrorClass::TransmitTimeout => write!(f, "transmit timeout"),
+            ErrorClass::LostArbitration => write!(f, "lost arbitration"),
+            ErrorClass::Controller => write!(f, "controller problem"),
+            ErrorClass::Protocol(vtype) => write!(f, "protocol violation: {}", vtype),
+            ErrorClass::Transceiver => write!(f, "transceiver error"),
+            ErrorClass::NoAck => write!(f, "no ack"),
+            ErrorClass::BusOff => write!(f, "bus off"),
+            ErrorClass::BusError => write!(f, "bus error"),
+            ErrorClass::Restarted => write!(f, "restarted"),
+            ErrorClass::Other => write!(f, "other error"),
+        }
+    }
+}
+
+// ===== ErrorStats =====
+
+/// The counts of error frames by class, over a sliding time window.
+///
+/// Frames are recorded with a timestamp in microseconds, from any
+/// monotonic source, such as the time of a capture [`Record`]. Events
+/// that fall out of the window are dropped as newer ones are recorded.
+///
+/// [`Record`]: crate::capture::Record
+#[derive(Debug, Clone)]
+pub struct ErrorStats {
+    window_us: u64,
+    last_us: u64,
+    frames: u64,
+    events: HashMap<ErrorClass, VecDeque<u64>>,
+    totals: HashMap<ErrorClass, u64>,
+}
+
+impl ErrorStats {
+    /// Creates a new aggregator with the specified window.
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window_us: (window.as_micros() as u64).max(1),
+            last_us: 0,
+            frames: 0,
+            events: HashMap::new(),
+            totals: HashMap::new(),
+        }
+    }
+
+    /// Gets the length of the window.
+    pub fn window(&self) -> Duration {
+        Duration::from_micros(self.window_us)
+    }
+
+    /// Records an error frame received at the specified time.
+    ///
+    /// Each error reported in the frame is counted separately, so a frame
+    /// with a protocol violation and a bus error counts once for each.
+    pub fn record(&mut self, t_us: u64, frame: &CanErrorFrame) {
+        self.frames += 1;
+        for err in frame.errors() {
+            self.record_error(t_us, &err);
+        }
+    }
+
+    /// Records a single, decoded error at the specified time.
+    pub fn record_error(&mut self, t_us: u64, err: &CanError) {
+        let class = ErrorClass::from(err);
+        self.events.entry(class).or_default().push_back(t_us);
+        *self.totals.entry(class).or_default() += 1;
+        self.advance(t_us);
+    }
+
+    /// Moves the window forward to the specified time, dropping any events
+    /// that fall out of it.
+    ///
+    /// This is done automatically as errors are recorded, but can be
+    /// called periodically so that the rates decay when the errors stop.
+    pub fn advance(&mut self, t_us: u64) {
+        self.last_us = self.last_us.max(t_us);
+        let start = self.last_us.saturating_sub(self.window_us);
+        self.events.retain(|_, times| {
+            while times.front().is_some_and(|&t| t < start) {
+                times.pop_front();
+            }
+            !times.is_empty()
+        });
+    }
+
+    /// Gets the number of errors of the class in the current window.
+    pub fn count(&self, class: ErrorClass) -> usize {
+        self.events.get(&class).map_or(0, |times| times.len())
+    }
+
+    /// Gets the total number of errors of the class ever recorded.
+    pub fn total(&self, class: ErrorClass) -> u64 {
+        self.totals.get(&class).copied().unwrap_or(0)
+    }
+
+    /// Gets the total number of error frames ever recorded.
+    pub fn total_frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Gets the rate of errors of the class over the current window,
+    /// in errors per second.
+    pub fn rate(&self, class: ErrorClass) -> f64 {
+        self.count(class) as f64 * 1.0e6 / self.window_us as f64
+    }
+
+    /// Gets the rates, in errors per second, of all the classes that have
+    /// errors in the current window, with the highest rate first.
+    pub fn rates(&self) -> Vec<(ErrorClass, f64)> {
+        let mut rates: Vec<_> = self
+            .events
+            .keys()
+            .map(|&class| (class, self.rate(class)))
+            .collect();
+        rates.sort_by(|a, b| b.1.total_cmp(&a.1));
+        rates
+    }
+
+    /// Clears all the counts.
+    pub fn clear(&mut self) {
+        self.last_us = 0;
+        self.frames = 0;
+        self.events.clear();
+        self.totals.clear();
+    }
+}
+
+/// Gets the bus state implied by a controller problem, if any.
+fn problem_state(prob: ControllerProblem) -> Option<BusHealth> {
+    use ControllerProblem::*;
+    match prob {
+        ReceiveErrorWarning | TransmitErrorWarning => Some(BusHealth::Warning),
+        ReceiveErrorPassive | TransmitErrorPassive => Some(BusHealth::Passive),
+        Active => Some(BusHealth::Active),
+        _ => None,
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_transitions() {
+        let mut monitor = BusMonitor::new();
+        let rx = monitor.subscribe();
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let cb_seen = Arc::clone(&seen);
+        monitor.on_transition(move |tr| cb_seen.lock().unwrap().push(tr.to));
+
+        // RX warning, with counters
+        let frame = CanErrorFrame::new_error(0x0204, &[0, 0x04, 0, 0, 0, 0, 10, 100]).unwrap();
+        let tr = monitor.process_error_frame(&frame).unwrap();
+        assert!(tr.is_degraded());
+        assert_eq!(BusHealth::Warning, monitor.state());
+        assert_eq!(Some((10, 100)), monitor.counters());
+
+        // Repeated state is not a transition
+        assert!(monitor.process_error_frame(&frame).is_none());
+
+        // Counters only
+        let frame = CanErrorFrame::new_error(0x0200, &[0, 0, 0, 0, 0, 0, 130, 0]).unwrap();
+        monitor.process_error_frame(&frame);
+        assert_eq!(BusHealth::Passive, monitor.state());
+
+        // Bus off, then restarted
+        let frame = CanErrorFrame::new_error(0x0040, &[]).unwrap();
+        monitor.process_error_frame(&frame);
+        let frame = CanErrorFrame::new_error(0x0100, &[]).unwrap();
+        let tr = monitor.process_error_frame(&frame).unwrap();
+        assert!(!tr.is_degraded());
+
+        // From a counter poll
+        monitor.update_counters(0, 97);
+
+        let expected = vec![
+            BusHealth::Warning,
+            BusHealth::Passive,
+            BusHealth::BusOff,
+            BusHealth::Active,
+            BusHealth::Warning,
+        ];
+        assert_eq!(expected, *seen.lock().unwrap());
+        assert_eq!(expected, rx.try_iter().map(|tr| tr.to).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_error_stats() {
+        const STUFF: ErrorClass = ErrorClass::Protocol(ViolationType::BitStuffingError);
+
+        let mut stats = ErrorStats::new(Duration::from_secs(1));
+
+        // Stuff error + bus error
+        let frame = CanErrorFrame::new_error(0x0088, &[0, 0, 0x04, 0, 0, 0, 0, 0]).unwrap();
+        for i in 0..4 {
+            stats.record(i * 200_000, &frame);
+        }
+        assert_eq!(4, stats.total_frames());
+        assert_eq!(4, stats.count(STUFF));
+        assert_eq!(4, stats.count(ErrorClass::BusError));
+        assert_eq!(4.0, stats.rate(STUFF));
+        assert_eq!(2, stats.rates().len());
+
+        stats.advance(1_500_000);
+        assert_eq!(1, stats.count(STUFF));
+        assert_eq!(4, stats.total(STUFF));
+
+        stats.advance(3_000_000);
+        assert_eq!(0.0, stats.rate(STUFF));
+        assert!(stats.rates().is_empty());
+    }
+}
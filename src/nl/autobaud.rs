@@ -0,0 +1,331 @@
+// socketcan/src/nl/autobaud.rs
+//
+// Bit rate detection for CAN interfaces.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! Bit rate auto-detection.
+//!
+//! When connecting to an unknown bus, the bit rate can be found by trying
+//! each of the likely candidates in turn. The [`BitrateProbe`] configures
+//! the interface for a candidate rate in listen-only mode, so that it
+//! never acknowledges or disturbs the traffic, and listens for a while.
+//! At the wrong rate the controller sees only bus errors; at the right one
+//! it receives frames without error. Candidates that the controller
+//! rejects are skipped, and when the probe is done the interface is put
+//! back the way it was found.
+//!
+//! ```no_run
+//! use socketcan::nl::autobaud::BitrateProbe;
+//! use std::time::Duration;
+//!
+//! let report = BitrateProbe::new("can0")
+//!     .with_listen_time(Duration::from_millis(500))
+//!     .probe()
+//!     .unwrap();
+//!
+//! match report.detected() {
+//!     Some(bitrate) => println!("Detected {} bps", bitrate),
+//!     None => println!("No traffic detected"),
+//! }
+//! ```
+//!
+//! PRIVILEGED: This requires root privilege, and the interface can not be
+//! in use by other applications while it is being reconfigured.
+
+use super::{CanCtrlMode, CanInterface};
+use crate::{CanFrame, CanSocket, IoErrorKind, Socket, SocketOptions};
+use neli::{consts::rtnl::Rtm, err::NlError, rtnl::Ifinfomsg};
+use std::{
+    io,
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+
+/// The standard bit rates, from fastest to slowest.
+pub const STANDARD_BITRATES: &[u32] = &[
+    1_000_000, 800_000, 500_000, 250_000, 125_000, 100_000, 83_333, 50_000, 20_000, 10_000,
+];
+
+/// The default time to listen at each candidate bit rate.
+pub const DEFAULT_LISTEN_TIME: Duration = Duration::from_millis(250);
+
+/// An error probing the bit rate.
+#[derive(Error, Debug)]
+pub enum AutoBaudError {
+    /// The interface couldn't be found
+    #[error("Interface not found: {0}")]
+    Interface(#[from] nix::Error),
+    /// The interface couldn't be configured
+    #[error(transparent)]
+    Netlink(#[from] NlError),
+    /// The settings of the interface couldn't be read
+    #[error(transparent)]
+    Query(#[from] NlError<Rtm, Ifinfomsg>),
+    /// An error reading from the interface
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+// ===== ProbeResult =====
+
+/// The result of listening at one candidate bit rate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProbeResult {
+    /// The bit rate, in bps
+    pub bitrate: u32,
+    /// The number of frames received without error
+    pub frames: usize,
+    /// The number of error frames received
+    pub errors: usize,
+}
+
+impl ProbeResult {
+    /// Determines if the bus appears to be running at this bit rate:
+    /// at least `min_frames` were received, with no errors.
+    pub fn is_match(&self, min_frames: usize) -> bool {
+        self.errors == 0 && self.frames >= min_frames.max(1)
+    }
+}
+
+// ===== ProbeReport =====
+
+/// The results of a bit rate probe.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProbeReport {
+    /// The results for each candidate tried, in order
+    pub results: Vec<ProbeResult>,
+    /// The candidates that the interface couldn't be configured for
+    pub rejected: Vec<u32>,
+    min_frames: usize,
+}
+
+impl ProbeReport {
+    /// The detected bit rate, if any.
+    pub fn detected(&self) -> Option<u32> {
+        self.results
+            .iter()
+            .find(|res| res.is_match(self.min_frames))
+            .map(|res| res.bitrate)
+    }
+}
+
+// ===== BitrateProbe =====
+
+/// A probe that finds the bit rate of the bus connected to an interface.
+#[derive(Debug, Clone)]
+pub struct BitrateProbe {
+    ifname: String,
+    candidates: Vec<u32>,
+    listen_time: Duration,
+    min_frames: usize,
+    sample_point: Option<u32>,
+}
+
+impl BitrateProbe {
+    /// Creates a probe for the named interface, which tries the standard
+    /// bit rates.
+    pub fn new(ifname: &str) -> Self {
+        Self {
+            ifname: ifname.into(),
+            candidates: STANDARD_BITRATES.to_vec(),
+            listen_time: DEFAULT_LISTEN_TIME,
+            min_frames: 1,
+            sample_point: None,
+        }
+    }
+
+    /// Sets the candidate bit rates, in the order to try them.
+    pub fn with_candidates(mut self, candidates: &[u32]) -> Self {
+        self.candidates = candidates.to_vec();
+        self
+    }
+
+    /// Sets the time to listen at each candidate.
+    ///
+    /// This should be long enough to see traffic from the slowest
+    /// periodic message expected on the bus.
+    pub fn with_listen_time(mut self, listen_time: Duration) -> Self {
+        self.listen_time = listen_time;
+        self
+    }
+
+    /// Sets the number of error-free frames needed to accept a candidate.
+    pub fn with_min_frames(mut self, min_frames: usize) -> Self {
+        self.min_frames = min_frames;
+        self
+    }
+
+    /// Sets the sample point, in tenths of a percent, used with each
+    /// candidate. By default the driver picks one.
+    pub fn with_sample_point(mut self, sample_point: u32) -> Self {
+        self.sample_point = Some(sample_point);
+        self
+    }
+
+    /// Runs the probe, stopping at the first candidate that matches.
+    ///
+    /// A candidate that the interface can't be configured for, or can't
+    /// listen at, is recorded as rejected and skipped. An error is only
+    /// returned if every candidate was rejected.
+    ///
+    /// However the probe ends, the bit rate, listen-only mode, and up or
+    /// down state of the interface are restored. The detected rate can
+    /// then be applied with [`CanInterface::set_bitrate()`].
+    pub fn probe(&self) -> Result<ProbeReport, AutoBaudError> {
+        let iface = CanInterface::open(&self.ifname)?;
+        let _restore = RestoreGuard::new(&iface)?;
+
+        let mut report = ProbeReport {
+            results: Vec::with_capacity(self.candidates.len()),
+            rejected: Vec::new(),
+            min_frames: self.min_frames,
+        };
+        let mut last_err = None;
+
+        for &bitrate in &self.candidates {
+            match self.try_bitrate(&iface, bitrate) {
+                Ok(res) => {
+                    report.results.push(res);
+                    if res.is_match(self.min_frames) {
+                        break;
+                    }
+                }
+                Err(err) => {
+                    log::warn!("Skipping bit rate {} on {}: {}", bitrate, self.ifname, err);
+                    report.rejected.push(bitrate);
+                    last_err = Some(err);
+                }
+            }
+        }
+
+        match last_err {
+            Some(err) if report.results.is_empty() => Err(err),
+            _ => Ok(report),
+        }
+    }
+
+    /// Configures the interface for a bit rate in listen-only mode, and
+    /// counts the frames and errors received.
+    fn try_bitrate(
+        &self,
+        iface: &CanInterface,
+        bitrate: u32,
+    ) -> Result<ProbeResult, AutoBaudError> {
+        iface.bring_down()?;
+        iface.set_bitrate(bitrate, self.sample_point)?;
+        iface.set_ctrlmode(CanCtrlMode::ListenOnly, true)?;
+        iface.bring_up()?;
+
+        let sock = CanSocket::open(&self.ifname)?;
+        sock.set_error_filter_accept_all()?;
+
+        let mut res = ProbeResult {
+            bitrate,
+            frames: 0,
+            errors: 0,
+        };
+        let deadline = Instant::now() + self.listen_time;
+
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break;
+            }
+            match sock.read_frame_timeout(remaining) {
+                Ok(CanFrame::Error(_)) => res.errors += 1,
+                Ok(_) => res.frames += 1,
+                Err(err) if err.kind() == IoErrorKind::TimedOut => break,
+                Err(err) => return Err(err.into()),
+            }
+        }
+        Ok(res)
+    }
+}
+
+// ===== RestoreGuard =====
+
+/// Puts an interface back the way it was when the guard was created,
+/// when the guard is dropped.
+struct RestoreGuard<'a> {
+    iface: &'a CanInterface,
+    bitrate: Option<(u32, u32)>,
+    listen_only: bool,
+    is_up: bool,
+}
+
+impl<'a> RestoreGuard<'a> {
+    /// Saves the settings of the interface.
+    fn new(iface: &'a CanInterface) -> Result<Self, AutoBaudError> {
+        let details = iface.details()?;
+        Ok(Self {
+            iface,
+            bitrate: details
+                .can
+                .bit_timing
+                .filter(|bt| bt.bitrate != 0)
+                .map(|bt| (bt.bitrate, bt.sample_point)),
+            listen_only: details.can.ctrl_mode.has_mode(CanCtrlMode::ListenOnly),
+            is_up: details.is_up,
+        })
+    }
+
+    /// Restores the saved settings, stopping at the first failure.
+    fn restore(&self) -> Result<(), NlError> {
+        self.iface.bring_down()?;
+        if let Some((bitrate, sample_point)) = self.bitrate {
+            self.iface.set_bitrate(bitrate, sample_point)?;
+        }
+        self.iface
+            .set_ctrlmode(CanCtrlMode::ListenOnly, self.listen_only)?;
+        if self.is_up {
+            self.iface.bring_up()?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for RestoreGuard<'_> {
+    fn drop(&mut self) {
+        if let Err(err) = self.restore() {
+            log::warn!("Failed to restore the CAN interface settings: {}", err);
+        }
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_detected() {
+        let res = |bitrate, frames, errors| ProbeResult {
+            bitrate,
+            frames,
+            errors,
+        };
+        let mut report = ProbeReport {
+            results: vec![
+                res(1_000_000, 0, 12),
+                res(500_000, 3, 1),
+                res(250_000, 0, 0),
+            ],
+            rejected: vec![800_000],
+            min_frames: 2,
+        };
+        assert_eq!(None, report.detected());
+
+        report.results.push(res(125_000, 5, 0));
+        assert_eq!(Some(125_000), report.detected());
+
+        // No traffic is never a match
+        assert!(!res(250_000, 0, 0).is_match(0));
+    }
+}
@@ -78,6 +78,10 @@ use std::{
 /// Low-level Netlink CAN struct bindings.
 mod rt;
 
+pub mod autobaud;
+
+pub mod vcan;
+
 use rt::can_ctrlmode;
 pub use rt::CanState;
 
@@ -288,6 +292,11 @@ impl CanCtrlModes {
         }
     }
 
+    /// Determines if the mode is set on in the collection.
+    pub fn has_mode(&self, mode: CanCtrlMode) -> bool {
+        self.0.flags & mode.mask() != 0
+    }
+
     /// Clears all of the mode flags in the collection
     pub fn clear(&mut self) {
         self.0 = can_ctrlmode::default();
@@ -349,9 +358,10 @@ impl CanInterface {
     /// Open a CAN interface by name.
     ///
     /// Similar to `open_iface`, but looks up the device by name instead of
-    /// the interface index.
+    /// the interface index. The name can be an alias from the
+    /// [`alias`](crate::alias) registry.
     pub fn open(ifname: &str) -> Result<Self, nix::Error> {
-        let if_index = if_nametoindex(ifname)?;
+        let if_index = if_nametoindex(&*crate::alias::resolve(ifname))?;
         Ok(Self::open_iface(if_index))
     }
 
@@ -815,6 +825,33 @@ impl CanInterface {
         self.set_ctrlmodes(CanCtrlModes::from_mode(mode, on))
     }
 
+    /// Gets the control modes that are set on the interface.
+    pub fn ctrlmodes(&self) -> Result<Option<CanCtrlModes>, NlInfoError> {
+        Ok(self
+            .can_param::<can_ctrlmode>(IflaCan::CtrlMode)?
+            .map(CanCtrlModes::from))
+    }
+
+    /// Determines if the interface is running CAN FD in the non-ISO mode,
+    /// which is the original Bosch protocol, without the stuff bit count
+    /// in the CRC.
+    pub fn is_fd_non_iso(&self) -> Result<Option<bool>, NlInfoError> {
+        Ok(self
+            .ctrlmodes()?
+            .map(|modes| modes.has_mode(CanCtrlMode::NonIso)))
+    }
+
+    /// Set or clear the non-ISO CAN FD mode.
+    ///
+    /// This is needed to talk to hardware that implements the original,
+    /// pre-ISO, version of CAN FD. The interface must be down to change it.
+    ///
+    /// PRIVILEGED: This requires root privilege.
+    ///
+    pub fn set_fd_non_iso(&self, on: bool) -> NlResult<()> {
+        self.set_ctrlmode(CanCtrlMode::NonIso, on)
+    }
+
     /// Gets the automatic CANbus restart time for the interface, in milliseconds.
     pub fn restart_ms(&self) -> Result<Option<u32>, NlInfoError> {
         self.can_param::<u32>(IflaCan::RestartMs)
@@ -0,0 +1,197 @@
+// socketcan/src/nl/vcan.rs
+//
+// Temporary vcan interfaces for integration tests.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! Temporary vcan interfaces for integration tests.
+//!
+//! A [`VcanFixture`] creates a virtual CAN interface with a name that's
+//! unique to the process and the fixture, brings it up, and deletes it
+//! again when dropped. Since no two fixtures share an interface, tests
+//! that use them can run in parallel.
+//!
+//! ```no_run
+//! use socketcan::{nl::vcan::VcanFixture, CanFrame, EmbeddedFrame, Socket, StandardId};
+//!
+//! let vcan = match VcanFixture::new() {
+//!     Ok(vcan) => vcan,
+//!     Err(err) if err.is_unavailable() => {
+//!         eprintln!("Skipping test: {}", err);
+//!         return;
+//!     }
+//!     Err(err) => panic!("{}", err),
+//! };
+//!
+//! let tx = vcan.socket().unwrap();
+//! let rx = vcan.socket().unwrap();
+//! let frame = CanFrame::new(StandardId::new(0x123).unwrap(), &[1, 2]).unwrap();
+//! tx.write_frame(&frame).unwrap();
+//! assert_eq!(frame, rx.read_frame().unwrap());
+//! ```
+//!
+//! PRIVILEGED: Creating an interface requires the `CAP_NET_ADMIN`
+//! capability, and the `vcan` kernel module.
+
+use super::{CanInterface, Mtu};
+use crate::{CanFdSocket, CanSocket, IoResult, Socket};
+use neli::err::NlError;
+use std::{
+    ops::Deref,
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use thiserror::Error;
+
+/// The default prefix of the names of the fixture interfaces.
+pub const DEFAULT_PREFIX: &str = "vct";
+
+// The longest interface name, without the terminating nul
+const MAX_NAME_LEN: usize = libc::IFNAMSIZ - 1;
+
+// The number of fixtures created by the process
+static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// An error setting up a vcan fixture.
+#[derive(Error, Debug)]
+pub enum VcanError {
+    /// The process isn't allowed to create interfaces
+    #[error(
+        "Creating a vcan interface requires CAP_NET_ADMIN (run as root, or grant the capability)"
+    )]
+    PermissionDenied,
+    /// The kernel doesn't support vcan interfaces
+    #[error("vcan interfaces aren't supported (is the vcan module loaded? try 'modprobe vcan')")]
+    NotSupported,
+    /// Any other error creating or configuring the interface
+    #[error(transparent)]
+    Netlink(#[from] NlError),
+}
+
+impl VcanError {
+    /// Determines if the error is down to the environment, rather than
+    /// the test, such as missing privileges, so that the test can be
+    /// skipped.
+    pub fn is_unavailable(&self) -> bool {
+        matches!(self, Self::PermissionDenied | Self::NotSupported)
+    }
+
+    fn from_nl(err: NlError) -> Self {
+        match &err {
+            NlError::Nlmsgerr(msg) => match -msg.error {
+                libc::EPERM | libc::EACCES => Self::PermissionDenied,
+                libc::EOPNOTSUPP => Self::NotSupported,
+                _ => Self::Netlink(err),
+            },
+            _ => Self::Netlink(err),
+        }
+    }
+}
+
+/// Makes a name for the next interface, unique to the process.
+fn unique_name(prefix: &str) -> String {
+    let n = COUNT.fetch_add(1, Ordering::Relaxed);
+    let suffix = format!("{:x}x{:x}", process::id(), n);
+    let len = MAX_NAME_LEN.saturating_sub(suffix.len()).min(prefix.len());
+    let prefix: String = prefix.chars().take(len).collect();
+    let mut name = prefix + &suffix;
+    name.truncate(MAX_NAME_LEN);
+    name
+}
+
+// ===== VcanFixture =====
+
+/// A vcan interface that lives for the duration of a test.
+///
+/// The interface is deleted when this is dropped.
+#[derive(Debug)]
+pub struct VcanFixture {
+    name: String,
+    iface: CanInterface,
+}
+
+impl VcanFixture {
+    /// Creates a uniquely named vcan interface and brings it up.
+    pub fn new() -> Result<Self, VcanError> {
+        Self::with_prefix(DEFAULT_PREFIX)
+    }
+
+    /// Creates a vcan interface with a unique name that starts with the
+    /// prefix, and brings it up.
+    ///
+    /// The prefix is shortened as needed to fit the limit on the length
+    /// of interface names.
+    pub fn with_prefix(prefix: &str) -> Result<Self, VcanError> {
+        let name = unique_name(prefix);
+        let iface = CanInterface::create_vcan(&name, None).map_err(VcanError::from_nl)?;
+        let fixture = Self { name, iface };
+        fixture.iface.bring_up().map_err(VcanError::from_nl)?;
+        Ok(fixture)
+    }
+
+    /// Creates a uniquely named vcan interface for CAN FD frames, and
+    /// brings it up.
+    pub fn new_fd() -> Result<Self, VcanError> {
+        let fixture = Self::new()?;
+        fixture.iface.bring_down().map_err(VcanError::from_nl)?;
+        fixture.iface.set_mtu(Mtu::Fd).map_err(VcanError::from_nl)?;
+        fixture.iface.bring_up().map_err(VcanError::from_nl)?;
+        Ok(fixture)
+    }
+
+    /// Gets the name of the interface.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Opens a classic CAN socket on the interface.
+    pub fn socket(&self) -> IoResult<CanSocket> {
+        CanSocket::open(&self.name)
+    }
+
+    /// Opens a CAN FD socket on the interface.
+    pub fn fd_socket(&self) -> IoResult<CanFdSocket> {
+        CanFdSocket::open(&self.name)
+    }
+}
+
+impl Deref for VcanFixture {
+    type Target = CanInterface;
+
+    fn deref(&self) -> &Self::Target {
+        &self.iface
+    }
+}
+
+impl Drop for VcanFixture {
+    fn drop(&mut self) {
+        let iface = CanInterface::open_iface(self.iface.if_index);
+        if let Err((_, err)) = iface.delete() {
+            log::warn!("Unable to delete vcan fixture {}: {}", self.name, err);
+        }
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unique_name() {
+        let a = unique_name(DEFAULT_PREFIX);
+        let b = unique_name(DEFAULT_PREFIX);
+        assert_ne!(a, b);
+        assert!(a.starts_with(DEFAULT_PREFIX));
+
+        let long = unique_name("a_very_long_test_name");
+        assert!(long.len() <= MAX_NAME_LEN);
+        assert!(long.starts_with("a_"));
+    }
+}
@@ -0,0 +1,244 @@
+// socketcan/src/pacing.rs
+//
+// Minimum gaps between transmitted frames.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! Minimum gaps between transmitted frames.
+//!
+//! Linux sends frames back-to-back as fast as the bus allows. Some slow
+//! ECUs can't keep up with that, and drop frames that arrive too close
+//! together. A [`Pacer`] enforces a minimum gap between consecutive
+//! frames, either between any two frames, or between frames to the same
+//! ID, and a [`PacedSocket`] applies it to every frame written to a
+//! socket, by waiting before the write when needed.
+//!
+//! ```no_run
+//! use socketcan::{
+//!     pacing::{PacedSocket, Pacer},
+//!     CanFrame, CanId, CanSocket, Frame, Socket,
+//! };
+//! use std::time::Duration;
+//!
+//! let sock = CanSocket::open("can0").unwrap();
+//! let pacer = Pacer::new(Duration::from_micros(500))
+//!     .with_id_gap(CanId::standard(0x7E0).unwrap(), Duration::from_millis(5));
+//! let sock = PacedSocket::new(sock, pacer);
+//!
+//! for i in 0..16 {
+//!     let frame = CanFrame::from_raw_id(0x7E0, &[i]).unwrap();
+//!     sock.write_frame(&frame).unwrap();
+//! }
+//! ```
+
+use crate::{
+    backend::{CanReceiver, CanTransmitter},
+    frame::AsPtr,
+    CanId, Frame, IoResult, Socket,
+};
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
+};
+
+// ===== Pacer =====
+
+/// The timing of transmitted frames, to keep a minimum gap between them.
+#[derive(Debug, Clone, Default)]
+pub struct Pacer {
+    gap: Duration,
+    id_gaps: HashMap<CanId, Duration>,
+    last: Option<Instant>,
+    last_by_id: HashMap<CanId, Instant>,
+}
+
+impl Pacer {
+    /// Creates a pacer with a minimum gap between any two frames.
+    ///
+    /// This can be zero, to only pace the IDs given with
+    /// [`with_id_gap`](Self::with_id_gap).
+    pub fn new(gap: Duration) -> Self {
+        Self {
+            gap,
+            ..Self::default()
+        }
+    }
+
+    /// Sets a minimum gap between consecutive frames with the ID.
+    pub fn with_id_gap(mut self, id: CanId, gap: Duration) -> Self {
+        self.id_gaps.insert(id, gap);
+        self
+    }
+
+    /// Gets the time to wait before a frame with the ID can be sent.
+    pub fn delay(&self, now: Instant, id: CanId) -> Duration {
+        let ready = |last: Option<&Instant>, gap: Duration| {
+            last.map_or(Duration::ZERO, |last| {
+                (*last + gap).saturating_duration_since(now)
+            })
+        };
+
+        let delay = ready(self.last.as_ref(), self.gap);
+        match self.id_gaps.get(&id) {
+            Some(gap) => delay.max(ready(self.last_by_id.get(&id), *gap)),
+            None => delay,
+        }
+    }
+
+    /// Records that a frame with the ID was sent at the time.
+    pub fn sent(&mut self, now: Instant, id: CanId) {
+        self.last = Some(now);
+        if self.id_gaps.contains_key(&id) {
+            self.last_by_id.insert(id, now);
+        }
+    }
+
+    /// Waits until a frame with the ID can be sent, then records it as
+    /// sent.
+    pub fn wait(&mut self, id: CanId) {
+        let delay = self.delay(Instant::now(), id);
+        if !delay.is_zero() {
+            thread::sleep(delay);
+        }
+        self.sent(Instant::now(), id);
+    }
+}
+
+// ===== PacedSocket =====
+
+/// A socket that keeps a minimum gap between the frames written to it.
+#[derive(Debug)]
+pub struct PacedSocket<S> {
+    sock: S,
+    pacer: Mutex<Pacer>,
+}
+
+impl<S> PacedSocket<S> {
+    /// Wraps the socket, or any other transmitter, with the pacer.
+    pub fn new(sock: S, pacer: Pacer) -> Self {
+        Self {
+            sock,
+            pacer: Mutex::new(pacer),
+        }
+    }
+
+    /// Gets a reference to the underlying socket.
+    ///
+    /// Frames written directly to it aren't paced.
+    pub fn get_ref(&self) -> &S {
+        &self.sock
+    }
+
+    /// Unwraps the underlying socket.
+    pub fn into_inner(self) -> S {
+        self.sock
+    }
+}
+
+impl<S: Socket> PacedSocket<S> {
+    /// Writes a frame, after waiting for its gap to pass.
+    pub fn write_frame<F>(&self, frame: &F) -> IoResult<()>
+    where
+        F: Into<S::FrameType> + AsPtr + Clone + Frame,
+    {
+        let mut pacer = self.pacer.lock().unwrap();
+        pacer.wait(frame.can_id());
+        self.sock.write_frame(frame)
+    }
+
+    /// Writes a frame, after waiting for its gap to pass, retrying until
+    /// it's sent or fails.
+    pub fn write_frame_insist<F>(&self, frame: &F) -> IoResult<()>
+    where
+        F: Into<S::FrameType> + AsPtr + Clone + Frame,
+    {
+        let mut pacer = self.pacer.lock().unwrap();
+        pacer.wait(frame.can_id());
+        self.sock.write_frame_insist(frame)
+    }
+
+    /// Reads a frame. Reads aren't paced.
+    pub fn read_frame(&self) -> IoResult<S::FrameType> {
+        self.sock.read_frame()
+    }
+}
+
+impl<S, F> CanTransmitter<F> for PacedSocket<S>
+where
+    S: CanTransmitter<F>,
+    F: Frame,
+{
+    /// Sends a frame, after waiting for its gap to pass.
+    fn transmit(&self, frame: &F) -> IoResult<()> {
+        let mut pacer = self.pacer.lock().unwrap();
+        pacer.wait(frame.can_id());
+        self.sock.transmit(frame)
+    }
+
+    /// Makes a single attempt to send a frame, after waiting for its gap
+    /// to pass.
+    fn try_transmit(&self, frame: &F) -> IoResult<()> {
+        let mut pacer = self.pacer.lock().unwrap();
+        pacer.wait(frame.can_id());
+        self.sock.try_transmit(frame)
+    }
+}
+
+impl<S: CanReceiver> CanReceiver for PacedSocket<S> {
+    type Frame = S::Frame;
+
+    fn receive(&self) -> IoResult<S::Frame> {
+        self.sock.receive()
+    }
+
+    fn receive_timeout(&self, timeout: Duration) -> IoResult<S::Frame> {
+        self.sock.receive_timeout(timeout)
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pacer() {
+        let a = CanId::standard(0x100).unwrap();
+        let b = CanId::standard(0x200).unwrap();
+        let ms = Duration::from_millis;
+
+        let mut pacer = Pacer::new(ms(1)).with_id_gap(a, ms(10));
+        let t0 = Instant::now();
+        assert_eq!(Duration::ZERO, pacer.delay(t0, a));
+
+        pacer.sent(t0, a);
+        assert_eq!(ms(1), pacer.delay(t0, b));
+        assert_eq!(ms(10), pacer.delay(t0, a));
+
+        pacer.sent(t0 + ms(2), b);
+        assert_eq!(ms(1), pacer.delay(t0 + ms(2), b));
+        assert_eq!(ms(4), pacer.delay(t0 + ms(6), a));
+        assert_eq!(Duration::ZERO, pacer.delay(t0 + ms(10), a));
+    }
+
+    #[test]
+    fn test_paced_transmitter() {
+        use crate::{mock::MockCanSocket, CanFrame};
+
+        let gap = Duration::from_millis(20);
+        let sock = PacedSocket::new(MockCanSocket::<CanFrame>::new(), Pacer::new(gap));
+        let frame = CanFrame::from_raw_id(0x100, &[1]).unwrap();
+        for _ in 0..3 {
+            sock.transmit(&frame).unwrap();
+        }
+        assert!(sock.get_ref().tx_intervals().iter().all(|&dt| dt >= gap));
+    }
+}
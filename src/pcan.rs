@@ -0,0 +1,498 @@
+// socketcan/src/pcan.rs
+//
+// A backend for PEAK-System adapters through the PCAN-Basic API.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! A backend for PEAK-System adapters through the PCAN-Basic API.
+//!
+//! PCAN-Basic is the vendor API for PEAK's USB, PCI, and other CAN
+//! adapters, provided on Linux by `libpcanbasic`. A [`PcanSocket`] gives
+//! tools a transport on hosts where the PEAK driver is built in its
+//! character device mode, without the SocketCAN network interface, or
+//! where the interface can't be configured by the application. It
+//! implements the
+//! [`CanTransmitter`] and [`CanReceiver`] traits, so the higher layers of
+//! the library, such as the protocols and the monitors, work over it
+//! unchanged.
+//!
+//! The library is loaded when the first channel is opened, rather than
+//! linked in, so an application built with this feature still runs on
+//! hosts without it, and only fails to open a PCAN channel.
+//!
+//! Only classic data and remote frames are supported. Status messages
+//! from the adapter are skipped. A receive waits on the receive event of
+//! the channel, so it doesn't poll the adapter.
+//!
+//! ```no_run
+//! use socketcan::{
+//!     backend::{CanReceiver, CanTransmitter},
+//!     pcan::{self, PcanSocket},
+//!     CanFrame, Frame,
+//! };
+//!
+//! let sock = PcanSocket::open(pcan::usb_bus(1).unwrap(), 500_000).unwrap();
+//!
+//! let frame = CanFrame::from_raw_id(0x123, &[1, 2, 3]).unwrap();
+//! sock.transmit(&frame).unwrap();
+//!
+//! loop {
+//!     let frame = sock.receive().unwrap();
+//!     println!("{:?}", frame);
+//! }
+//! ```
+
+use crate::{
+    backend::{CanReceiver, CanTransmitter},
+    CanError, CanFrame, CanRemoteFrame, EmbeddedFrame, ExtendedId, Frame, Id, IoError, IoErrorKind,
+    IoResult, StandardId,
+};
+use libc::{c_char, c_int, c_void};
+use std::{
+    ffi::{CStr, CString},
+    mem::{self, size_of},
+    sync::OnceLock,
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+
+/// The names the PCAN-Basic library is loaded by, in order.
+pub const LIBRARY_NAMES: &[&str] = &["libpcanbasic.so", "libpcanbasic.so.4"];
+
+/// The first PCAN-USB channel.
+pub const PCAN_USBBUS1: u16 = 0x51;
+
+/// The first PCAN-PCI channel.
+pub const PCAN_PCIBUS1: u16 = 0x41;
+
+// The status codes
+const PCAN_ERROR_OK: u32 = 0x00000;
+const PCAN_ERROR_XMTFULL: u32 = 0x00001;
+const PCAN_ERROR_QRCVEMPTY: u32 = 0x00020;
+const PCAN_ERROR_QXMTFULL: u32 = 0x00080;
+
+// The parameter for the receive event, which is a file descriptor on Linux
+const PCAN_RECEIVE_EVENT: u8 = 0x03;
+
+// The message types
+const PCAN_MESSAGE_RTR: u8 = 0x01;
+const PCAN_MESSAGE_EXTENDED: u8 = 0x02;
+const PCAN_MESSAGE_ERRFRAME: u8 = 0x40;
+const PCAN_MESSAGE_STATUS: u8 = 0x80;
+
+/// The CAN bitrates supported by a [`PcanSocket`], with their BTR0/BTR1
+/// register values.
+pub const PCAN_BITRATES: [(u32, u16); 10] = [
+    (5_000, 0x7F7F),
+    (10_000, 0x672F),
+    (20_000, 0x532F),
+    (50_000, 0x472F),
+    (100_000, 0x432F),
+    (125_000, 0x031C),
+    (250_000, 0x011C),
+    (500_000, 0x001C),
+    (800_000, 0x0016),
+    (1_000_000, 0x0014),
+];
+
+/// Gets the handle of a PCAN-USB channel, from 1 to 16.
+pub fn usb_bus(n: u8) -> Option<u16> {
+    match n {
+        1..=8 => Some(PCAN_USBBUS1 + n as u16 - 1),
+        9..=16 => Some(0x509 + n as u16 - 9),
+        _ => None,
+    }
+}
+
+/// An error from the PCAN-Basic backend.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum PcanError {
+    /// The PCAN-Basic library couldn't be loaded
+    #[error("PCAN-Basic library not available: {0}")]
+    Load(String),
+    /// The CAN bitrate isn't supported
+    #[error("Unsupported CAN bitrate: {0}")]
+    Bitrate(u32),
+    /// The library returned an error status
+    #[error("PCAN-Basic error 0x{code:X}: {text}")]
+    Status {
+        /// The status code
+        code: u32,
+        /// The description of the status, from the library
+        text: String,
+    },
+}
+
+impl From<PcanError> for IoError {
+    fn from(err: PcanError) -> Self {
+        let kind = match err {
+            PcanError::Load(_) => IoErrorKind::NotFound,
+            PcanError::Bitrate(_) => IoErrorKind::InvalidInput,
+            PcanError::Status { code, .. }
+                if code & (PCAN_ERROR_XMTFULL | PCAN_ERROR_QXMTFULL) != 0 =>
+            {
+                IoErrorKind::WouldBlock
+            }
+            PcanError::Status { .. } => IoErrorKind::Other,
+        };
+        IoError::new(kind, err)
+    }
+}
+
+// ===== Messages =====
+
+/// A CAN message, in the PCAN-Basic layout (`TPCANMsg`).
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PcanMsg {
+    /// The CAN ID
+    pub id: u32,
+    /// The type of message, as `PCAN_MESSAGE_*` flags
+    pub msg_type: u8,
+    /// The data length
+    pub len: u8,
+    /// The data
+    pub data: [u8; 8],
+}
+
+/// A receive timestamp, in the PCAN-Basic layout (`TPCANTimestamp`).
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct PcanTimestamp {
+    millis: u32,
+    millis_overflow: u16,
+    micros: u16,
+}
+
+impl PcanMsg {
+    /// Converts a frame to a message.
+    ///
+    /// Error frames can't be sent through PCAN-Basic, and give `None`.
+    pub fn from_frame(frame: &CanFrame) -> Option<Self> {
+        if frame.is_error_frame() {
+            return None;
+        }
+        let mut msg = Self {
+            id: frame.raw_id(),
+            len: frame.dlc() as u8,
+            ..Self::default()
+        };
+        if frame.is_extended() {
+            msg.msg_type |= PCAN_MESSAGE_EXTENDED;
+        }
+        if frame.is_remote_frame() {
+            msg.msg_type |= PCAN_MESSAGE_RTR;
+        } else {
+            msg.data[..frame.data().len()].copy_from_slice(frame.data());
+        }
+        Some(msg)
+    }
+
+    /// Converts the message to a frame.
+    ///
+    /// An error frame reported by the adapter gives a bus error frame.
+    /// Status messages from the adapter, and malformed messages, give
+    /// `None`.
+    pub fn to_frame(&self) -> Option<CanFrame> {
+        if self.msg_type & PCAN_MESSAGE_STATUS != 0 || self.len > 8 {
+            return None;
+        }
+        if self.msg_type & PCAN_MESSAGE_ERRFRAME != 0 {
+            return Some(CanFrame::from(CanError::BusError));
+        }
+        let id: Id = if self.msg_type & PCAN_MESSAGE_EXTENDED != 0 {
+            ExtendedId::new(self.id)?.into()
+        } else {
+            StandardId::new(u16::try_from(self.id).ok()?)?.into()
+        };
+        if self.msg_type & PCAN_MESSAGE_RTR != 0 {
+            CanRemoteFrame::new_remote(id, self.len as usize).map(CanFrame::from)
+        } else {
+            CanFrame::new(id, &self.data[..self.len as usize])
+        }
+    }
+}
+
+// ===== Library =====
+
+type InitializeFn = unsafe extern "C" fn(u16, u16, u8, u32, u16) -> u32;
+type UninitializeFn = unsafe extern "C" fn(u16) -> u32;
+type ReadFn = unsafe extern "C" fn(u16, *mut PcanMsg, *mut PcanTimestamp) -> u32;
+type WriteFn = unsafe extern "C" fn(u16, *mut PcanMsg) -> u32;
+type GetErrorTextFn = unsafe extern "C" fn(u32, u16, *mut c_char) -> u32;
+type GetValueFn = unsafe extern "C" fn(u16, u8, *mut c_void, u32) -> u32;
+
+/// The entry points of the loaded library.
+#[derive(Debug)]
+struct Library {
+    initialize: InitializeFn,
+    uninitialize: UninitializeFn,
+    read: ReadFn,
+    write: WriteFn,
+    get_error_text: GetErrorTextFn,
+    get_value: GetValueFn,
+}
+
+impl Library {
+    /// Gets the library, loading it the first time.
+    fn get() -> Result<&'static Library, PcanError> {
+        static LIBRARY: OnceLock<Result<Library, PcanError>> = OnceLock::new();
+        LIBRARY
+            .get_or_init(Self::load)
+            .as_ref()
+            .map_err(Clone::clone)
+    }
+
+    fn load() -> Result<Library, PcanError> {
+        let handle = LIBRARY_NAMES
+            .iter()
+            .find_map(|name| {
+                let name = CString::new(*name).ok()?;
+                // SAFETY: The name is a valid C string
+                let handle = unsafe { libc::dlopen(name.as_ptr(), libc::RTLD_NOW) };
+                (!handle.is_null()).then_some(handle)
+            })
+            .ok_or_else(|| PcanError::Load(LIBRARY_NAMES.join(", ")))?;
+
+        let sym = |name: &str| -> Result<*mut c_void, PcanError> {
+            let cname = CString::new(name).unwrap();
+            // SAFETY: The handle is open, and is never closed
+            let sym = unsafe { libc::dlsym(handle, cname.as_ptr()) };
+            match sym.is_null() {
+                true => Err(PcanError::Load(format!("missing symbol {}", name))),
+                false => Ok(sym),
+            }
+        };
+
+        // SAFETY: The symbols have these signatures in the PCAN-Basic API
+        unsafe {
+            Ok(Library {
+                initialize: mem::transmute::<*mut c_void, InitializeFn>(sym("CAN_Initialize")?),
+                uninitialize: mem::transmute::<*mut c_void, UninitializeFn>(sym(
+                    "CAN_Uninitialize",
+                )?),
+                read: mem::transmute::<*mut c_void, ReadFn>(sym("CAN_Read")?),
+                write: mem::transmute::<*mut c_void, WriteFn>(sym("CAN_Write")?),
+                get_error_text: mem::transmute::<*mut c_void, GetErrorTextFn>(sym(
+                    "CAN_GetErrorText",
+                )?),
+                get_value: mem::transmute::<*mut c_void, GetValueFn>(sym("CAN_GetValue")?),
+            })
+        }
+    }
+
+    /// Converts a status to a result.
+    fn check(&self, code: u32) -> Result<(), PcanError> {
+        if code == PCAN_ERROR_OK {
+            return Ok(());
+        }
+        let mut buf = [0 as c_char; 256];
+        // SAFETY: The buffer is the 256 bytes the API asks for. Language 0
+        // is the system default.
+        let text = match unsafe { (self.get_error_text)(code, 0, buf.as_mut_ptr()) } {
+            PCAN_ERROR_OK => unsafe { CStr::from_ptr(buf.as_ptr()) }
+                .to_string_lossy()
+                .into_owned(),
+            _ => String::from("unknown error"),
+        };
+        Err(PcanError::Status { code, text })
+    }
+}
+
+// ===== PcanSocket =====
+
+/// A CAN channel on a PEAK adapter, through PCAN-Basic.
+///
+/// The channel is closed when this is dropped.
+#[derive(Debug)]
+pub struct PcanSocket {
+    lib: &'static Library,
+    channel: u16,
+    event_fd: c_int,
+}
+
+impl PcanSocket {
+    /// Opens the channel, such as [`PCAN_USBBUS1`], at the CAN bitrate.
+    pub fn open(channel: u16, bitrate: u32) -> IoResult<Self> {
+        let btr = PCAN_BITRATES
+            .iter()
+            .find(|(rate, _)| *rate == bitrate)
+            .map(|(_, btr)| *btr)
+            .ok_or(PcanError::Bitrate(bitrate))?;
+
+        let lib = Library::get()?;
+        // SAFETY: The hardware type, port, and interrupt only apply to
+        // non-plug-and-play channels, and are ignored for the others.
+        lib.check(unsafe { (lib.initialize)(channel, btr, 0, 0, 0) })?;
+        let mut sock = Self {
+            lib,
+            channel,
+            event_fd: -1,
+        };
+
+        let mut fd: c_int = -1;
+        // SAFETY: The buffer is an int, of the size given
+        lib.check(unsafe {
+            (lib.get_value)(
+                channel,
+                PCAN_RECEIVE_EVENT,
+                &mut fd as *mut c_int as *mut c_void,
+                size_of::<c_int>() as u32,
+            )
+        })?;
+        sock.event_fd = fd;
+        Ok(sock)
+    }
+
+    /// Gets the handle of the channel.
+    pub fn channel(&self) -> u16 {
+        self.channel
+    }
+
+    /// Reads a frame from the receive queue, if there is one.
+    fn try_read(&self) -> IoResult<Option<CanFrame>> {
+        loop {
+            let mut msg = PcanMsg::default();
+            let mut ts = PcanTimestamp::default();
+            // SAFETY: The message and timestamp are valid to write
+            match unsafe { (self.lib.read)(self.channel, &mut msg, &mut ts) } {
+                PCAN_ERROR_QRCVEMPTY => return Ok(None),
+                code => self.lib.check(code)?,
+            }
+            if let Some(frame) = msg.to_frame() {
+                return Ok(Some(frame));
+            }
+        }
+    }
+
+    /// Reads the receive queue until a frame arrives, or until the
+    /// deadline passes, waiting on the receive event while it's empty.
+    fn read_until(&self, deadline: Option<Instant>) -> IoResult<CanFrame> {
+        loop {
+            if let Some(frame) = self.try_read()? {
+                return Ok(frame);
+            }
+            let timeout = match deadline {
+                Some(deadline) => {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    if remaining.is_zero() {
+                        return Err(IoErrorKind::TimedOut.into());
+                    }
+                    // Round up, so as not to wake just before the deadline
+                    let millis = (remaining.as_nanos() + 999_999) / 1_000_000;
+                    c_int::try_from(millis).unwrap_or(c_int::MAX)
+                }
+                None => -1,
+            };
+            self.wait_event(timeout)?;
+        }
+    }
+
+    /// Waits for the receive event, for up to the timeout in milliseconds,
+    /// or forever if it's negative.
+    fn wait_event(&self, timeout: c_int) -> IoResult<()> {
+        let mut pfd = libc::pollfd {
+            fd: self.event_fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // SAFETY: The poll descriptor is valid for the call
+        match unsafe { libc::poll(&mut pfd, 1, timeout) } {
+            n if n < 0 => {
+                let err = IoError::last_os_error();
+                match err.kind() {
+                    IoErrorKind::Interrupted => Ok(()),
+                    _ => Err(err),
+                }
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Drop for PcanSocket {
+    fn drop(&mut self) {
+        // SAFETY: The channel was initialized when it was opened
+        let _ = unsafe { (self.lib.uninitialize)(self.channel) };
+    }
+}
+
+impl<F> CanTransmitter<F> for PcanSocket
+where
+    F: Into<CanFrame> + Clone,
+{
+    fn transmit(&self, frame: &F) -> IoResult<()> {
+        let frame: CanFrame = frame.clone().into();
+        let mut msg = PcanMsg::from_frame(&frame)
+            .ok_or_else(|| IoError::new(IoErrorKind::InvalidInput, "error frames can't be sent"))?;
+        // SAFETY: The message is valid, and only read by the library
+        let code = unsafe { (self.lib.write)(self.channel, &mut msg) };
+        Ok(self.lib.check(code)?)
+    }
+}
+
+impl CanReceiver for PcanSocket {
+    type Frame = CanFrame;
+
+    fn receive(&self) -> IoResult<CanFrame> {
+        self.read_until(None)
+    }
+
+    fn receive_timeout(&self, timeout: Duration) -> IoResult<CanFrame> {
+        self.read_until(Some(Instant::now() + timeout))
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_msg_conversion() {
+        let frame = CanFrame::from_raw_id(0x123, &[1, 2, 3]).unwrap();
+        let msg = PcanMsg::from_frame(&frame).unwrap();
+        assert_eq!((0x123, 0, 3), (msg.id, msg.msg_type, msg.len));
+        assert_eq!(&[1, 2, 3], &msg.data[..3]);
+        assert_eq!(Some(frame), msg.to_frame());
+
+        let frame = CanFrame::remote_from_raw_id(0x1ABCDEF0, 4).unwrap();
+        let msg = PcanMsg::from_frame(&frame).unwrap();
+        assert_eq!(PCAN_MESSAGE_EXTENDED | PCAN_MESSAGE_RTR, msg.msg_type);
+        assert_eq!(Some(frame), msg.to_frame());
+
+        let status = PcanMsg {
+            msg_type: PCAN_MESSAGE_STATUS,
+            ..msg
+        };
+        assert_eq!(None, status.to_frame());
+
+        let err = PcanMsg {
+            msg_type: PCAN_MESSAGE_ERRFRAME,
+            ..msg
+        };
+        match err.to_frame() {
+            Some(CanFrame::Error(frame)) => {
+                assert!(matches!(frame.into_error(), CanError::BusError))
+            }
+            _ => panic!("Wrong frame type"),
+        }
+    }
+
+    #[test]
+    fn test_channels() {
+        assert_eq!(Some(PCAN_USBBUS1), usb_bus(1));
+        assert_eq!(Some(0x58), usb_bus(8));
+        assert_eq!(Some(0x50A), usb_bus(10));
+        assert_eq!(None, usb_bus(0));
+
+        let err = PcanSocket::open(PCAN_USBBUS1, 123_456).unwrap_err();
+        assert_eq!(IoErrorKind::InvalidInput, err.kind());
+    }
+}
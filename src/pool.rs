@@ -0,0 +1,277 @@
+// socketcan/src/pool.rs
+//
+// A pool of reusable, shareable frame buffers.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! A pool of reusable, shareable frame buffers.
+//!
+//! At tens of thousands of frames per second, allocating a buffer for each
+//! frame, and copying it again for every consumer it's handed to, adds up.
+//! A [`FramePool`] keeps the buffers of frames that are no longer in use,
+//! and hands them out again for the next ones. Each buffer comes in a
+//! [`Pooled`] handle, which goes back to the pool on its own when the last
+//! handle to it is dropped.
+//!
+//! Cloning a handle doesn't copy the frame, it shares it, so the same
+//! frame can be fanned out to any number of threads, over a channel or a
+//! [`ring`](crate::ring), for the cost of a reference count.
+//!
+//! ```no_run
+//! use socketcan::{pool::FramePool, CanFdSocket, Socket};
+//! use std::{sync::mpsc, thread};
+//!
+//! let sock = CanFdSocket::open("can0").unwrap();
+//! let pool = FramePool::new(1024);
+//!
+//! let (tx_log, rx_log) = mpsc::channel();
+//! let (tx_app, rx_app) = mpsc::channel();
+//! thread::spawn(move || rx_log.iter().for_each(|frame| println!("{:?}", frame)));
+//! thread::spawn(move || rx_app.iter().for_each(|frame| { /* ... */ drop(frame) }));
+//!
+//! let mut frames = Vec::new();
+//! loop {
+//!     sock.read_frames_pooled(&pool, &mut frames).unwrap();
+//!     for frame in frames.drain(..) {
+//!         tx_log.send(frame.clone()).unwrap();
+//!         tx_app.send(frame).unwrap();
+//!     }
+//! }
+//! ```
+
+use std::{
+    fmt,
+    ops::Deref,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+/// Counts of the buffers handed out by a pool.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PoolStats {
+    /// The number of buffers that had to be allocated
+    pub allocated: u64,
+    /// The number of buffers that were reused from the pool
+    pub reused: u64,
+    /// The number of buffers currently idle in the pool
+    pub idle: usize,
+}
+
+#[derive(Debug)]
+struct Inner<T> {
+    idle: Mutex<Vec<Arc<T>>>,
+    max_idle: usize,
+    allocated: AtomicU64,
+    reused: AtomicU64,
+}
+
+impl<T> Inner<T> {
+    // Takes back a buffer that no handle refers to any more
+    fn release(&self, buf: Arc<T>) {
+        let mut idle = self.idle.lock().unwrap();
+        if idle.len() < self.max_idle {
+            idle.push(buf);
+        }
+    }
+}
+
+// ===== FramePool =====
+
+/// A pool of reusable buffers for frames, or anything else.
+///
+/// The pool is cheap to clone, and the clones share the same buffers.
+#[derive(Debug)]
+pub struct FramePool<T> {
+    inner: Arc<Inner<T>>,
+}
+
+impl<T> Clone for FramePool<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+impl<T> FramePool<T> {
+    /// Creates a pool that keeps up to `max_idle` buffers for reuse.
+    ///
+    /// Buffers released while the pool is full are freed.
+    pub fn new(max_idle: usize) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                idle: Mutex::new(Vec::with_capacity(max_idle)),
+                max_idle,
+                allocated: AtomicU64::new(0),
+                reused: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// Puts the value in a buffer from the pool, or a new one if the pool
+    /// is empty.
+    pub fn alloc(&self, value: T) -> Pooled<T> {
+        let buf = self.inner.idle.lock().unwrap().pop();
+        let buf = match buf {
+            Some(mut buf) => {
+                self.inner.reused.fetch_add(1, Ordering::Relaxed);
+                // Nothing else refers to an idle buffer
+                *Arc::get_mut(&mut buf).expect("shared idle buffer") = value;
+                buf
+            }
+            None => {
+                self.inner.allocated.fetch_add(1, Ordering::Relaxed);
+                Arc::new(value)
+            }
+        };
+        Pooled {
+            buf: Some(buf),
+            pool: Arc::clone(&self.inner),
+        }
+    }
+
+    /// Gets the counts of buffers handed out by the pool.
+    pub fn stats(&self) -> PoolStats {
+        PoolStats {
+            allocated: self.inner.allocated.load(Ordering::Relaxed),
+            reused: self.inner.reused.load(Ordering::Relaxed),
+            idle: self.inner.idle.lock().unwrap().len(),
+        }
+    }
+}
+
+// ===== Pooled =====
+
+/// A handle to a buffer from a [`FramePool`].
+///
+/// Cloning the handle shares the buffer, rather than copying it. When the
+/// last handle to a buffer is dropped, the buffer goes back to the pool.
+/// If two handles to a buffer are dropped at the same moment on different
+/// threads, it can occasionally be freed instead.
+pub struct Pooled<T> {
+    // Only `None` while being dropped
+    buf: Option<Arc<T>>,
+    pool: Arc<Inner<T>>,
+}
+
+impl<T> Pooled<T> {
+    fn buf(&self) -> &Arc<T> {
+        self.buf.as_ref().expect("pooled buffer")
+    }
+
+    /// Gets a mutable reference to the value, if this is the only handle
+    /// to it.
+    pub fn get_mut(&mut self) -> Option<&mut T> {
+        self.buf.as_mut().and_then(Arc::get_mut)
+    }
+
+    /// Determines if there are other handles to the buffer.
+    pub fn is_shared(&self) -> bool {
+        Arc::strong_count(self.buf()) > 1
+    }
+}
+
+impl<T> Clone for Pooled<T> {
+    fn clone(&self) -> Self {
+        Self {
+            buf: self.buf.clone(),
+            pool: Arc::clone(&self.pool),
+        }
+    }
+}
+
+impl<T> Deref for Pooled<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.buf()
+    }
+}
+
+impl<T> AsRef<T> for Pooled<T> {
+    fn as_ref(&self) -> &T {
+        self.buf()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Pooled<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T> Drop for Pooled<T> {
+    fn drop(&mut self) {
+        if let Some(mut buf) = self.buf.take() {
+            if Arc::get_mut(&mut buf).is_some() {
+                self.pool.release(buf);
+            }
+        }
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CanFdFrame, EmbeddedFrame, Frame, Timestamped};
+    use std::time::SystemTime;
+
+    fn frame(id: u32) -> Timestamped<CanFdFrame> {
+        let frame = CanFdFrame::from_raw_id(id, &[0xAA; 64]).unwrap();
+        Timestamped::new(frame, SystemTime::now())
+    }
+
+    #[test]
+    fn test_reuse() {
+        let pool = FramePool::new(2);
+
+        let a = pool.alloc(frame(0x100));
+        let b = pool.alloc(frame(0x101));
+        let c = pool.alloc(frame(0x102));
+        let addr = &*a as *const _;
+        drop((a, b, c));
+
+        // Only two buffers are kept
+        assert_eq!(2, pool.stats().idle);
+
+        let d = pool.alloc(frame(0x103));
+        let e = pool.alloc(frame(0x104));
+        assert_eq!(0x104, e.frame.raw_id());
+        assert!(std::ptr::eq(addr, &*d) || std::ptr::eq(addr, &*e));
+
+        let stats = pool.stats();
+        assert_eq!(3, stats.allocated);
+        assert_eq!(2, stats.reused);
+        assert_eq!(0, stats.idle);
+    }
+
+    #[test]
+    fn test_shared() {
+        let pool = FramePool::new(4);
+
+        let mut a = pool.alloc(frame(0x100));
+        assert!(a.get_mut().is_some());
+
+        let b = a.clone();
+        assert!(a.is_shared());
+        assert!(a.get_mut().is_none());
+        assert!(std::ptr::eq(&*a, &*b));
+        assert_eq!(&[0xAA; 64], b.frame.data());
+
+        // The buffer only goes back once the last handle is dropped
+        drop(a);
+        assert_eq!(0, pool.stats().idle);
+        let handle = std::thread::spawn(move || drop(b));
+        handle.join().unwrap();
+        assert_eq!(1, pool.stats().idle);
+    }
+}
@@ -0,0 +1,369 @@
+// socketcan/src/replay.rs
+//
+// A record/replay harness for golden-trace regression tests.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! A record/replay harness for golden-trace regression tests.
+//!
+//! A [`ReplayHarness`] plays a capture, such as one read from a candump
+//! log, onto a [`VirtualBus`], one frame at a time, into the code under
+//! test. The code under test talks to the bus through an ordinary
+//! [`VirtualEndpoint`], and every frame it sends is recorded as a
+//! [`Timestamped`] frame, stamped with the time of the capture at that
+//! point. The recording can then be compared against a "golden" trace
+//! from a known good run.
+//!
+//! The capture can be given as anything that converts into a
+//! [`Timestamped`] frame, such as the records of a candump log or the
+//! [`Record`]s of the capture utilities.
+//!
+//! Replay runs on the time of the capture rather than the wall clock, so
+//! it never sleeps: an hour-long log replays as fast as the code under
+//! test can keep up, and gives the same result every time. The harness
+//! keeps time with a [`SimClock`], which code under test that uses a
+//! [`Clock`](crate::clock::Clock) can share to run at the time of the
+//! capture.
+//!
+//! ```
+//! use socketcan::{
+//!     backend::{CanReceiver, CanTransmitter},
+//!     replay::ReplayHarness,
+//!     timestamp::Timestamped,
+//!     CanAnyFrame, CanFrame, EmbeddedFrame, Frame,
+//! };
+//! use std::time::Duration;
+//!
+//! let frame = |id, data: &[u8]| CanAnyFrame::from(CanFrame::from_raw_id(id, data).unwrap());
+//!
+//! let capture = vec![
+//!     Timestamped::from_micros(frame(0x100, &[1]), 1_000).with_channel("can0"),
+//!     Timestamped::from_micros(frame(0x100, &[2]), 2_000).with_channel("can0"),
+//! ];
+//!
+//! let mut harness = ReplayHarness::new(capture);
+//! let dut = harness.endpoint();
+//!
+//! // The code under test answers 0x100 with 0x200
+//! harness.run(|_| {
+//!     while let Ok(frame) = dut.receive_timeout(Duration::ZERO) {
+//!         let reply = CanFrame::from_raw_id(0x200, frame.data()).unwrap();
+//!         dut.transmit(&reply).unwrap();
+//!     }
+//! });
+//!
+//! let golden = vec![
+//!     Timestamped::from_micros(frame(0x200, &[1]), 0),
+//!     Timestamped::from_micros(frame(0x200, &[2]), 1_000),
+//! ];
+//! harness.assert_golden(&golden);
+//! ```
+
+use crate::{
+    backend::{CanReceiver, CanTransmitter},
+    capture::{self, CaptureDiff, Record},
+    clock::SimClock,
+    timestamp::Timestamped,
+    vbus::{VirtualBus, VirtualEndpoint},
+    CanAnyFrame,
+};
+use std::{
+    collections::VecDeque,
+    time::{Duration, SystemTime},
+};
+
+#[cfg(feature = "dump")]
+use crate::dump::{ParseError, Reader};
+#[cfg(feature = "dump")]
+use std::io::BufRead;
+
+/// The device name given to the frames sent by the code under test.
+pub const DUT_DEVICE: &str = "dut";
+
+/// Reads all the frames from a candump log, with the interface names as
+/// their channels.
+#[cfg(feature = "dump")]
+pub fn read_log<R: BufRead>(
+    rdr: &mut Reader<R>,
+) -> Result<Vec<Timestamped<CanAnyFrame>>, ParseError> {
+    let mut log = Vec::new();
+    while let Some(rec) = rdr.next_record()? {
+        log.push(Timestamped::from(rec));
+    }
+    Ok(log)
+}
+
+// ===== ReplayHarness =====
+
+/// A harness that replays a capture into the code under test, and records
+/// what it sends back.
+///
+/// The frames of the recording are stamped with the time of the capture,
+/// which is the time of its first frame plus the elapsed time of the
+/// harness's clock, and have [`DUT_DEVICE`] as their channel.
+#[derive(Debug)]
+pub struct ReplayHarness {
+    log: VecDeque<Timestamped<CanAnyFrame>>,
+    bus: VirtualBus,
+    player: VirtualEndpoint,
+    start: SystemTime,
+    clock: SimClock,
+    sent: Vec<Timestamped<CanAnyFrame>>,
+}
+
+impl ReplayHarness {
+    /// Creates a harness to replay the capture, in order of time.
+    pub fn new<I>(log: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<Timestamped<CanAnyFrame>>,
+    {
+        let mut log: Vec<Timestamped<CanAnyFrame>> = log.into_iter().map(Into::into).collect();
+        // A stable sort, so frames with the same time keep their order
+        log.sort_by_key(|rec| rec.time);
+
+        let bus = VirtualBus::new();
+        let player = bus.endpoint();
+        Self {
+            start: log.first().map_or(SystemTime::UNIX_EPOCH, |rec| rec.time),
+            log: log.into(),
+            bus,
+            player,
+            clock: SimClock::new(),
+            sent: Vec::new(),
+        }
+    }
+
+    /// Creates a harness to replay a candump log.
+    #[cfg(feature = "dump")]
+    pub fn from_candump<R: BufRead>(rdr: &mut Reader<R>) -> Result<Self, ParseError> {
+        Ok(Self::new(read_log(rdr)?))
+    }
+
+    /// Keeps only the records that pass the filter, such as those from
+    /// one channel, or those that the code under test doesn't produce
+    /// itself.
+    pub fn with_filter<P>(mut self, filter: P) -> Self
+    where
+        P: Fn(&Timestamped<CanAnyFrame>) -> bool,
+    {
+        self.log.retain(|rec| filter(rec));
+        self
+    }
+
+    /// Keeps the time of the replay with the simulated clock, which can
+    /// be shared with the code under test.
+    ///
+    /// The elapsed time of the clock is moved to the time of each frame,
+    /// relative to the start of the capture, as it's put onto the bus. The
+    /// code under test may also advance the clock between frames, such as
+    /// by sleeping on it, and its frames are then stamped at the later
+    /// time. The clock is never moved backwards.
+    pub fn with_clock(mut self, clock: SimClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Gets the simulated clock that keeps the time of the replay.
+    pub fn clock(&self) -> &SimClock {
+        &self.clock
+    }
+
+    /// Creates an endpoint on the bus for the code under test.
+    pub fn endpoint(&self) -> VirtualEndpoint {
+        self.bus.endpoint()
+    }
+
+    /// Gets the bus that the capture is replayed onto.
+    pub fn bus(&self) -> &VirtualBus {
+        &self.bus
+    }
+
+    /// Gets the time of the replay, relative to the start of the capture.
+    pub fn now(&self) -> Duration {
+        self.clock.elapsed()
+    }
+
+    /// Gets the number of records still to be replayed.
+    pub fn remaining(&self) -> usize {
+        self.log.len()
+    }
+
+    /// Puts the next frame of the capture onto the bus, and returns it.
+    ///
+    /// Any frames sent by the code under test since the last step are
+    /// recorded first, at the current time of the clock.
+    pub fn step(&mut self) -> Option<Timestamped<CanAnyFrame>> {
+        self.collect();
+        let rec = self.log.pop_front()?;
+        let elapsed = rec.time.duration_since(self.start).unwrap_or_default();
+        if elapsed > self.clock.elapsed() {
+            self.clock.set_elapsed(elapsed);
+        }
+        // Sending to a virtual bus can't fail
+        let _ = self.player.transmit(&rec.frame);
+        Some(rec)
+    }
+
+    /// Replays the whole capture, calling the closure after each frame
+    /// is put onto the bus, to let the code under test handle it.
+    pub fn run<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&Timestamped<CanAnyFrame>),
+    {
+        while let Some(rec) = self.step() {
+            f(&rec);
+        }
+        self.collect();
+    }
+
+    /// Gets the frames sent by the code under test so far.
+    pub fn sent(&mut self) -> &[Timestamped<CanAnyFrame>] {
+        self.collect();
+        &self.sent
+    }
+
+    /// Compares the frames sent by the code under test against a golden
+    /// trace, by ID and payload.
+    ///
+    /// In the result, the golden trace is the first log and the recording
+    /// is the second.
+    pub fn diff(&mut self, golden: &[Timestamped<CanAnyFrame>]) -> CaptureDiff {
+        self.collect();
+        let records = |log: &[Timestamped<CanAnyFrame>]| -> Vec<Record> {
+            log.iter().cloned().map(Record::from).collect()
+        };
+        capture::diff(&records(golden), &records(&self.sent))
+    }
+
+    /// Asserts that the code under test sent the same frames as the
+    /// golden trace.
+    ///
+    /// # Panics
+    ///
+    /// If the recording differs from the golden trace, with the
+    /// differences in the message.
+    #[track_caller]
+    pub fn assert_golden(&mut self, golden: &[Timestamped<CanAnyFrame>]) {
+        let diff = self.diff(golden);
+        assert!(
+            diff.is_identical(),
+            "replay differs from the golden trace: {:#?}",
+            diff
+        );
+    }
+
+    /// Records the frames that the code under test has sent.
+    fn collect(&mut self) {
+        while let Ok(frame) = self.player.receive_timeout(Duration::ZERO) {
+            let time = self.start + self.clock.elapsed();
+            self.sent
+                .push(Timestamped::new(frame, time).with_channel(DUT_DEVICE));
+        }
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CanFrame, EmbeddedFrame, Frame};
+
+    fn rec(t_us: u64, id: u32) -> Record {
+        Record::new(t_us, "can0", CanFrame::from_raw_id(id, &[]).unwrap())
+    }
+
+    #[test]
+    fn test_empty() {
+        let mut harness = ReplayHarness::new(Vec::<Record>::new());
+        assert_eq!(0, harness.remaining());
+        assert!(harness.step().is_none());
+
+        let mut calls = 0;
+        harness.run(|_| calls += 1);
+        assert_eq!(0, calls);
+        assert_eq!(Duration::ZERO, harness.now());
+        harness.assert_golden(&[]);
+    }
+
+    #[test]
+    fn test_timing() {
+        // Out of order, with an hour-long gap, which replays without delay
+        let capture = vec![
+            rec(3_605_000_000, 0x300),
+            rec(5_000_250, 0x200),
+            rec(5_000_000, 0x100),
+        ];
+        let clock = SimClock::new();
+        let mut harness = ReplayHarness::new(capture).with_clock(clock.clone());
+        let dut = harness.endpoint();
+
+        let mut steps = Vec::new();
+        harness.run(|rec| {
+            steps.push((rec.frame.raw_id(), clock.elapsed().as_micros() as u64));
+            let _ = dut.receive_timeout(Duration::ZERO).unwrap();
+            dut.transmit(&CanFrame::from_raw_id(0x7FF, &[]).unwrap())
+                .unwrap();
+        });
+        assert_eq!(
+            vec![(0x100, 0), (0x200, 250), (0x300, 3_600_000_000)],
+            steps
+        );
+        assert_eq!(Duration::from_secs(3600), harness.now());
+
+        let times: Vec<_> = harness.sent().iter().map(|rec| rec.micros()).collect();
+        assert_eq!(vec![5_000_000, 5_000_250, 3_605_000_000], times);
+        assert!(harness
+            .sent()
+            .iter()
+            .all(|rec| rec.channel() == Some(DUT_DEVICE)));
+    }
+
+    #[test]
+    #[cfg(feature = "dump")]
+    fn test_replay_candump() {
+        let input: &[u8] = b"(100.000000) can0 100#01\n\
+                             (100.010000) can0 7E8#AA\n\
+                             (100.020000) can0 100#02\n";
+        let mut rdr = Reader::from_reader(input);
+        let harness = ReplayHarness::from_candump(&mut rdr)
+            .unwrap()
+            .with_filter(|rec| rec.frame.raw_id() == 0x100);
+        assert_eq!(2, harness.remaining());
+
+        let clock = SimClock::new();
+        let mut harness = harness.with_clock(clock.clone());
+        let dut = harness.endpoint();
+        let mut times = Vec::new();
+        harness.run(|rec| {
+            assert_eq!(Some("can0"), rec.channel());
+            times.push(rec.micros());
+            assert_eq!(
+                rec.micros() - 100_000_000,
+                clock.elapsed().as_micros() as u64
+            );
+            let frame = dut.receive_timeout(Duration::ZERO).unwrap();
+            let reply = CanFrame::from_raw_id(0x7E8, frame.data()).unwrap();
+            dut.transmit(&reply).unwrap();
+        });
+        assert_eq!(vec![100_000_000, 100_020_000], times);
+
+        let sent = harness.sent();
+        assert_eq!(2, sent.len());
+        assert_eq!(100_020_000, sent[1].micros());
+
+        let golden: Vec<Timestamped<CanAnyFrame>> = vec![
+            Record::new(0, DUT_DEVICE, CanFrame::from_raw_id(0x7E8, &[1]).unwrap()).into(),
+            Record::new(0, DUT_DEVICE, CanFrame::from_raw_id(0x7E8, &[3]).unwrap()).into(),
+        ];
+        let diff = harness.diff(&golden);
+        assert_eq!(1, diff.divergences.len());
+        assert_eq!(vec![2], diff.divergences[0].b_data);
+    }
+}
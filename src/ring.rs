@@ -0,0 +1,580 @@
+// socketcan/src/ring.rs
+//
+// A lock-free ring buffer between a receive thread and a consumer.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! A lock-free ring buffer between a receive thread and a consumer.
+//!
+//! A bus can deliver a burst of frames faster than an application can
+//! handle them, and if the application falls behind, the socket's receive
+//! queue overflows and the kernel drops frames, without saying which. An
+//! [`RxRing`] decouples the two: a dedicated thread reads from the socket
+//! as fast as it can, and pushes the frames into a fixed-capacity ring
+//! that the consumer reads at its own pace. When the ring fills up, the
+//! [`OverflowPolicy`] decides whether the oldest or the newest frames are
+//! dropped, and the drops are counted, so the application knows what it
+//! missed.
+//!
+//! The ring itself is a lock-free single-producer, single-consumer queue,
+//! which can also be used on its own with [`channel`].
+//!
+//! ```no_run
+//! use socketcan::{
+//!     backend::CanReceiver,
+//!     ring::{OverflowPolicy, RxRing},
+//!     CanSocket, Socket,
+//! };
+//!
+//! let sock = CanSocket::open("can0").unwrap();
+//! let rx = RxRing::spawn(sock, 4096, OverflowPolicy::DropOldest).unwrap();
+//!
+//! loop {
+//!     let frame = rx.receive().unwrap();
+//!     // ...slow processing...
+//!     println!("{:?} (dropped so far: {})", frame, rx.stats().dropped);
+//! }
+//! ```
+
+use crate::{backend::CanReceiver, IoError, IoErrorKind, IoResult};
+use std::{
+    cell::UnsafeCell,
+    fmt,
+    mem::MaybeUninit,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle, Thread},
+    time::{Duration, Instant},
+};
+
+/// How often the reader thread of an [`RxRing`] checks whether it should
+/// stop.
+pub const STOP_POLL: Duration = Duration::from_millis(100);
+
+/// What to do with a new item when the ring is full.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OverflowPolicy {
+    /// Drop the oldest item in the ring, to make room for the new one
+    #[default]
+    DropOldest,
+    /// Drop the new item, keeping the ones already in the ring
+    DropNewest,
+}
+
+/// Counts of the items that went through a ring.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RingStats {
+    /// The number of items pushed into the ring
+    pub pushed: u64,
+    /// The number of items taken out by the consumer
+    pub popped: u64,
+    /// The number of items dropped because the ring was full
+    pub dropped: u64,
+}
+
+// ===== Ring =====
+
+struct Slot<T> {
+    // The position that the slot is ready for: equal to the position to
+    // write it, or one past the position to read it.
+    seq: AtomicUsize,
+    val: UnsafeCell<MaybeUninit<T>>,
+}
+
+/// The shared state of the ring.
+///
+/// This is a bounded queue with a sequence number in each slot, so that
+/// the head and tail can each be claimed with a compare-and-swap. The
+/// producer may also pop from the head, to drop the oldest item, so the
+/// head is claimed the same way by both ends.
+struct Ring<T> {
+    slots: Box<[Slot<T>]>,
+    mask: usize,
+    policy: OverflowPolicy,
+    head: AtomicUsize,
+    tail: AtomicUsize,
+    pushed: AtomicU64,
+    popped: AtomicU64,
+    dropped: AtomicU64,
+    closed: AtomicBool,
+    waiting: AtomicBool,
+    waiter: Mutex<Option<Thread>>,
+}
+
+// SAFETY: The slots are only accessed by whoever has claimed them through
+// the sequence numbers, so items move between threads, but are never
+// shared.
+unsafe impl<T: Send> Send for Ring<T> {}
+unsafe impl<T: Send> Sync for Ring<T> {}
+
+impl<T> Ring<T> {
+    fn new(capacity: usize, policy: OverflowPolicy) -> Self {
+        let capacity = capacity.max(2).next_power_of_two();
+        let slots = (0..capacity)
+            .map(|i| Slot {
+                seq: AtomicUsize::new(i),
+                val: UnsafeCell::new(MaybeUninit::uninit()),
+            })
+            .collect();
+        Self {
+            slots,
+            mask: capacity - 1,
+            policy,
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            pushed: AtomicU64::new(0),
+            popped: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            closed: AtomicBool::new(false),
+            waiting: AtomicBool::new(false),
+            waiter: Mutex::new(None),
+        }
+    }
+
+    fn try_push(&self, item: T) -> Result<(), T> {
+        let mut pos = self.tail.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[pos & self.mask];
+            let seq = slot.seq.load(Ordering::Acquire);
+            match (seq as isize).wrapping_sub(pos as isize) {
+                0 => match self.tail.compare_exchange_weak(
+                    pos,
+                    pos.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        // SAFETY: The slot was claimed by the CAS
+                        unsafe { (*slot.val.get()).write(item) };
+                        slot.seq.store(pos.wrapping_add(1), Ordering::Release);
+                        return Ok(());
+                    }
+                    Err(cur) => pos = cur,
+                },
+                dif if dif < 0 => return Err(item),
+                _ => pos = self.tail.load(Ordering::Relaxed),
+            }
+        }
+    }
+
+    fn try_pop(&self) -> Option<T> {
+        let mut pos = self.head.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[pos & self.mask];
+            let seq = slot.seq.load(Ordering::Acquire);
+            match (seq as isize).wrapping_sub(pos.wrapping_add(1) as isize) {
+                0 => match self.head.compare_exchange_weak(
+                    pos,
+                    pos.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        // SAFETY: The slot was claimed by the CAS, and was
+                        // written before its sequence was released.
+                        let item = unsafe { (*slot.val.get()).assume_init_read() };
+                        slot.seq
+                            .store(pos.wrapping_add(self.mask + 1), Ordering::Release);
+                        return Some(item);
+                    }
+                    Err(cur) => pos = cur,
+                },
+                dif if dif < 0 => return None,
+                _ => pos = self.head.load(Ordering::Relaxed),
+            }
+        }
+    }
+
+    /// Pushes an item, applying the overflow policy, and returns the item
+    /// that was dropped, if any.
+    fn push(&self, mut item: T) -> Option<T> {
+        let mut dropped = None;
+        loop {
+            match self.try_push(item) {
+                Ok(()) => break,
+                Err(it) if self.policy == OverflowPolicy::DropNewest => {
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                    return Some(it);
+                }
+                Err(it) => {
+                    item = it;
+                    if let Some(old) = self.try_pop() {
+                        self.dropped.fetch_add(1, Ordering::Relaxed);
+                        dropped = Some(old);
+                    }
+                }
+            }
+        }
+        self.pushed.fetch_add(1, Ordering::Relaxed);
+        self.wake();
+        dropped
+    }
+
+    fn pop(&self) -> Option<T> {
+        let item = self.try_pop()?;
+        self.popped.fetch_add(1, Ordering::Relaxed);
+        Some(item)
+    }
+
+    /// Wakes the consumer, if it's waiting.
+    fn wake(&self) {
+        if self.waiting.swap(false, Ordering::SeqCst) {
+            if let Some(thr) = self.waiter.lock().unwrap().as_ref() {
+                thr.unpark();
+            }
+        }
+    }
+
+    /// Waits for an item, until the deadline, if any. Returns `None` if
+    /// it timed out, or if the ring is empty and closed.
+    fn pop_wait(&self, deadline: Option<Instant>) -> Option<T> {
+        loop {
+            if let Some(item) = self.pop() {
+                return Some(item);
+            }
+            if self.closed.load(Ordering::Acquire) {
+                return self.pop();
+            }
+
+            *self.waiter.lock().unwrap() = Some(thread::current());
+            self.waiting.store(true, Ordering::SeqCst);
+            // Check again, in case an item arrived before the flag was set
+            if let Some(item) = self.pop() {
+                self.waiting.store(false, Ordering::SeqCst);
+                return Some(item);
+            }
+            match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        self.waiting.store(false, Ordering::SeqCst);
+                        return None;
+                    }
+                    thread::park_timeout(deadline - now);
+                }
+                None => thread::park(),
+            }
+        }
+    }
+
+    fn len(&self) -> usize {
+        let tail = self.tail.load(Ordering::Acquire);
+        let head = self.head.load(Ordering::Acquire);
+        tail.wrapping_sub(head).min(self.mask + 1)
+    }
+
+    fn stats(&self) -> RingStats {
+        RingStats {
+            pushed: self.pushed.load(Ordering::Relaxed),
+            popped: self.popped.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+        }
+    }
+
+    fn close(&self) {
+        self.closed.store(true, Ordering::Release);
+        self.waiting.store(true, Ordering::SeqCst);
+        self.wake();
+    }
+}
+
+impl<T> Drop for Ring<T> {
+    fn drop(&mut self) {
+        while self.try_pop().is_some() {}
+    }
+}
+
+// ===== Producer & Consumer =====
+
+/// Creates a ring with room for at least `capacity` items, rounded up to
+/// a power of two, returning its two ends.
+pub fn channel<T: Send>(capacity: usize, policy: OverflowPolicy) -> (Producer<T>, Consumer<T>) {
+    let ring = Arc::new(Ring::new(capacity, policy));
+    (
+        Producer {
+            ring: Arc::clone(&ring),
+        },
+        Consumer { ring },
+    )
+}
+
+/// The end of a ring that pushes items into it.
+///
+/// The consumer sees the ring as closed when this is dropped.
+pub struct Producer<T> {
+    ring: Arc<Ring<T>>,
+}
+
+impl<T: Send> Producer<T> {
+    /// Pushes an item into the ring, without blocking.
+    ///
+    /// If the ring is full, the overflow policy drops either the oldest
+    /// item or this one, which is returned.
+    pub fn push(&self, item: T) -> Option<T> {
+        self.ring.push(item)
+    }
+
+    /// Gets the counts of items through the ring.
+    pub fn stats(&self) -> RingStats {
+        self.ring.stats()
+    }
+}
+
+impl<T> Drop for Producer<T> {
+    fn drop(&mut self) {
+        self.ring.close();
+    }
+}
+
+impl<T> fmt::Debug for Producer<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Producer")
+            .field("len", &self.ring.len())
+            .field("stats", &self.ring.stats())
+            .finish()
+    }
+}
+
+/// The end of a ring that takes items out of it.
+pub struct Consumer<T> {
+    ring: Arc<Ring<T>>,
+}
+
+impl<T: Send> Consumer<T> {
+    /// Takes the oldest item from the ring, if there is one.
+    pub fn pop(&self) -> Option<T> {
+        self.ring.pop()
+    }
+
+    /// Waits for an item, up to the timeout. Returns `None` if none
+    /// arrived, or if the producer is gone and the ring is empty.
+    pub fn pop_timeout(&self, timeout: Duration) -> Option<T> {
+        self.ring.pop_wait(Some(Instant::now() + timeout))
+    }
+
+    /// Waits for an item. Returns `None` if the producer is gone and the
+    /// ring is empty.
+    pub fn pop_blocking(&self) -> Option<T> {
+        self.ring.pop_wait(None)
+    }
+
+    /// Gets the number of items in the ring.
+    pub fn len(&self) -> usize {
+        self.ring.len()
+    }
+
+    /// Determines if the ring is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Gets the number of items the ring can hold.
+    pub fn capacity(&self) -> usize {
+        self.ring.mask + 1
+    }
+
+    /// Determines if the producer is gone.
+    pub fn is_closed(&self) -> bool {
+        self.ring.closed.load(Ordering::Acquire)
+    }
+
+    /// Gets the counts of items through the ring.
+    pub fn stats(&self) -> RingStats {
+        self.ring.stats()
+    }
+}
+
+impl<T> fmt::Debug for Consumer<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Consumer")
+            .field("len", &self.ring.len())
+            .field("stats", &self.ring.stats())
+            .finish()
+    }
+}
+
+// ===== RxRing =====
+
+/// A receiver that reads frames on a dedicated thread into a ring, for
+/// the application to take at its own pace.
+///
+/// This is itself a [`CanReceiver`], so it can stand in for the socket.
+/// If the socket fails, the thread stops, and the error is returned once
+/// the frames already in the ring have been taken. The thread is stopped
+/// when this is dropped.
+#[derive(Debug)]
+pub struct RxRing<F> {
+    consumer: Consumer<F>,
+    stop: Arc<AtomicBool>,
+    error: Arc<Mutex<Option<IoError>>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl<F: Send + 'static> RxRing<F> {
+    /// Starts a thread reading frames from the receiver into a ring with
+    /// room for at least `capacity` frames.
+    pub fn spawn<R>(rx: R, capacity: usize, policy: OverflowPolicy) -> IoResult<Self>
+    where
+        R: CanReceiver<Frame = F> + Send + 'static,
+    {
+        let (producer, consumer) = channel(capacity, policy);
+        let stop = Arc::new(AtomicBool::new(false));
+        let error = Arc::new(Mutex::new(None));
+
+        let thr_stop = Arc::clone(&stop);
+        let thr_error = Arc::clone(&error);
+        let thread = thread::Builder::new()
+            .name("rx-ring".into())
+            .spawn(move || {
+                while !thr_stop.load(Ordering::Acquire) {
+                    match rx.receive_timeout(STOP_POLL) {
+                        Ok(frame) => {
+                            producer.push(frame);
+                        }
+                        Err(err)
+                            if matches!(
+                                err.kind(),
+                                IoErrorKind::WouldBlock | IoErrorKind::TimedOut
+                            ) => {}
+                        Err(err) => {
+                            *thr_error.lock().unwrap() = Some(err);
+                            break;
+                        }
+                    }
+                }
+            })?;
+
+        Ok(Self {
+            consumer,
+            stop,
+            error,
+            thread: Some(thread),
+        })
+    }
+
+    /// Gets the number of frames waiting in the ring.
+    pub fn len(&self) -> usize {
+        self.consumer.len()
+    }
+
+    /// Determines if the ring is empty.
+    pub fn is_empty(&self) -> bool {
+        self.consumer.is_empty()
+    }
+
+    /// Gets the counts of frames through the ring, including those
+    /// dropped on overflow.
+    pub fn stats(&self) -> RingStats {
+        self.consumer.stats()
+    }
+
+    /// Takes the oldest frame from the ring, if there is one, without
+    /// waiting.
+    pub fn try_receive(&self) -> Option<F> {
+        self.consumer.pop()
+    }
+
+    // Gets the result when no frame is available
+    fn no_frame(&self) -> IoError {
+        match self.error.lock().unwrap().take() {
+            Some(err) => err,
+            None if self.consumer.is_closed() => IoErrorKind::BrokenPipe.into(),
+            None => IoErrorKind::TimedOut.into(),
+        }
+    }
+}
+
+impl<F: Send + 'static> CanReceiver for RxRing<F> {
+    type Frame = F;
+
+    fn receive(&self) -> IoResult<F> {
+        self.consumer.pop_blocking().ok_or_else(|| self.no_frame())
+    }
+
+    fn receive_timeout(&self, timeout: Duration) -> IoResult<F> {
+        self.consumer
+            .pop_timeout(timeout)
+            .ok_or_else(|| self.no_frame())
+    }
+}
+
+impl<F> Drop for RxRing<F> {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        backend::CanTransmitter, vbus::VirtualBus, CanAnyFrame, CanFrame, EmbeddedFrame, Frame,
+    };
+
+    #[test]
+    fn test_overflow_policies() {
+        let (tx, rx) = channel(4, OverflowPolicy::DropOldest);
+        for i in 0..6 {
+            tx.push(i);
+        }
+        assert_eq!(4, rx.len());
+        assert_eq!(
+            vec![2, 3, 4, 5],
+            std::iter::from_fn(|| rx.pop()).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            RingStats {
+                pushed: 6,
+                popped: 4,
+                dropped: 2
+            },
+            rx.stats()
+        );
+
+        let (tx, rx) = channel(4, OverflowPolicy::DropNewest);
+        for i in 0..6 {
+            tx.push(i);
+        }
+        assert_eq!(Some(9), tx.push(9));
+        assert_eq!(
+            vec![0, 1, 2, 3],
+            std::iter::from_fn(|| rx.pop()).collect::<Vec<_>>()
+        );
+        assert_eq!(3, rx.stats().dropped);
+
+        drop(tx);
+        assert!(rx.is_closed());
+        assert_eq!(None, rx.pop_blocking());
+    }
+
+    #[test]
+    fn test_rx_ring() {
+        let bus = VirtualBus::new();
+        let tx = bus.endpoint();
+        let rx = RxRing::spawn(bus.endpoint(), 1024, OverflowPolicy::DropNewest).unwrap();
+
+        for i in 0..100u32 {
+            let frame = CanFrame::from_raw_id(0x100, &i.to_be_bytes()).unwrap();
+            tx.transmit(&CanAnyFrame::from(frame)).unwrap();
+        }
+        for i in 0..100u32 {
+            let frame = rx.receive_timeout(Duration::from_secs(1)).unwrap();
+            assert_eq!(0x100, frame.raw_id());
+            assert_eq!(i.to_be_bytes(), frame.data());
+        }
+        assert!(rx.receive_timeout(Duration::from_millis(10)).is_err());
+        assert_eq!(0, rx.stats().dropped);
+    }
+}
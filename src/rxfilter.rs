@@ -0,0 +1,406 @@
+// socketcan/src/rxfilter.rs
+//
+// Filters to thin out received frames.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! Filters to thin out received frames.
+//!
+//! Kernel filters select frames by ID. The filters here work on the
+//! traffic over time, to cut down what an application has to process:
+//!
+//! - [`Dedup`] suppresses frames that repeat the last payload of their ID.
+//! - [`RateLimit`] passes at most N frames per second of chatty IDs.
+//! - [`Debounce`] holds back a change in a signal until it has been
+//!   stable for a while, so that a toggling value doesn't get through.
+//!
+//! Each is an [`RxFilter`], and filters are combined with
+//! [`RxFilter::and`]. A filter can be put in front of a [`Dispatcher`]
+//! handler with [`filtered()`], or applied to an async stream of frames
+//! with [`filter_stream()`].
+//!
+//! ```no_run
+//! use socketcan::{
+//!     dispatch::Dispatcher,
+//!     rxfilter::{filtered, Dedup, RateLimit, RxFilter},
+//!     CanFdSocket, CanAnyFrame, CanId, Socket,
+//! };
+//!
+//! let sock = CanFdSocket::open("can0").unwrap();
+//! let filter = Dedup::new().and(RateLimit::new(10.0));
+//!
+//! let mut disp = Dispatcher::<CanAnyFrame>::new();
+//! disp.on_id(
+//!     CanId::standard(0x100).unwrap(),
+//!     filtered(filter, |frame| println!("{:?}", frame)),
+//! );
+//!
+//! loop {
+//!     disp.dispatch_next(&sock).unwrap();
+//! }
+//! ```
+//!
+//! [`Dispatcher`]: crate::dispatch::Dispatcher
+
+use crate::{signal::Signal, CanId, Frame};
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::{Duration, Instant},
+};
+
+/// A filter on received frames that can depend on the traffic seen
+/// before.
+pub trait RxFilter {
+    /// Determines if a frame with the ID and payload, received at the time
+    /// (in microseconds), should be passed on.
+    ///
+    /// The frames should be given in time order.
+    fn accept_data(&mut self, t_us: u64, id: CanId, data: &[u8]) -> bool;
+
+    /// Determines if the frame, received at the time (in microseconds),
+    /// should be passed on.
+    fn accept<F: Frame>(&mut self, t_us: u64, frame: &F) -> bool
+    where
+        Self: Sized,
+    {
+        self.accept_data(t_us, frame.can_id(), frame.data())
+    }
+
+    /// Chains this filter with another. A frame is passed on if both
+    /// filters accept it; the second filter only sees the frames that the
+    /// first accepted.
+    fn and<G>(self, other: G) -> Chain<Self, G>
+    where
+        Self: Sized,
+        G: RxFilter,
+    {
+        Chain(self, other)
+    }
+}
+
+impl<C> RxFilter for C
+where
+    C: FnMut(u64, CanId, &[u8]) -> bool,
+{
+    fn accept_data(&mut self, t_us: u64, id: CanId, data: &[u8]) -> bool {
+        self(t_us, id, data)
+    }
+}
+
+/// Two filters applied in turn.
+#[derive(Debug, Clone)]
+pub struct Chain<A, B>(A, B);
+
+impl<A, B> RxFilter for Chain<A, B>
+where
+    A: RxFilter,
+    B: RxFilter,
+{
+    fn accept_data(&mut self, t_us: u64, id: CanId, data: &[u8]) -> bool {
+        self.0.accept_data(t_us, id, data) && self.1.accept_data(t_us, id, data)
+    }
+}
+
+// ===== Dedup =====
+
+/// A filter that suppresses frames with the same payload as the previous
+/// frame of their ID.
+#[derive(Debug, Clone, Default)]
+pub struct Dedup {
+    last: HashMap<CanId, (u64, Vec<u8>)>,
+    refresh_us: Option<u64>,
+}
+
+impl Dedup {
+    /// Creates a filter that suppresses all unchanged repeats.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Passes an unchanged frame anyway if none of its ID has been passed
+    /// for the time, so that a receiver can tell that the sender is still
+    /// alive.
+    pub fn with_refresh(mut self, refresh: Duration) -> Self {
+        self.refresh_us = Some(refresh.as_micros() as u64);
+        self
+    }
+
+    /// Forgets the last payloads, so the next frame of each ID is passed.
+    pub fn clear(&mut self) {
+        self.last.clear();
+    }
+}
+
+impl RxFilter for Dedup {
+    fn accept_data(&mut self, t_us: u64, id: CanId, data: &[u8]) -> bool {
+        match self.last.get_mut(&id) {
+            Some((last_us, last)) => {
+                let stale = self
+                    .refresh_us
+                    .is_some_and(|refresh| t_us.saturating_sub(*last_us) >= refresh);
+                if last.as_slice() == data && !stale {
+                    return false;
+                }
+                *last_us = t_us;
+                last.clear();
+                last.extend_from_slice(data);
+            }
+            None => {
+                self.last.insert(id, (t_us, data.to_vec()));
+            }
+        }
+        true
+    }
+}
+
+// ===== RateLimit =====
+
+/// A filter that passes at most a number of frames per second for each
+/// ID, dropping the rest.
+#[derive(Debug, Clone)]
+pub struct RateLimit {
+    default_us: Option<u64>,
+    limits: BTreeMap<CanId, u64>,
+    last: HashMap<CanId, u64>,
+}
+
+/// Converts a rate, in Hz, to the minimum interval between frames.
+fn interval_us(hz: f64) -> u64 {
+    if hz > 0.0 {
+        (1_000_000.0 / hz) as u64
+    } else {
+        u64::MAX
+    }
+}
+
+impl RateLimit {
+    /// Creates a filter that limits every ID to the rate, in Hz.
+    pub fn new(hz: f64) -> Self {
+        Self {
+            default_us: Some(interval_us(hz)),
+            limits: BTreeMap::new(),
+            last: HashMap::new(),
+        }
+    }
+
+    /// Creates a filter that only limits the IDs given with
+    /// [`with_limit`](Self::with_limit).
+    pub fn unlimited() -> Self {
+        Self {
+            default_us: None,
+            limits: BTreeMap::new(),
+            last: HashMap::new(),
+        }
+    }
+
+    /// Sets the rate limit, in Hz, of a specific ID.
+    pub fn with_limit(mut self, id: CanId, hz: f64) -> Self {
+        self.limits.insert(id, interval_us(hz));
+        self
+    }
+}
+
+impl RxFilter for RateLimit {
+    fn accept_data(&mut self, t_us: u64, id: CanId, _data: &[u8]) -> bool {
+        let interval = match self.limits.get(&id).copied().or(self.default_us) {
+            Some(interval) => interval,
+            None => return true,
+        };
+        match self.last.get_mut(&id) {
+            Some(last_us) if t_us.saturating_sub(*last_us) < interval => false,
+            Some(last_us) => {
+                *last_us = t_us;
+                true
+            }
+            None => {
+                self.last.insert(id, t_us);
+                true
+            }
+        }
+    }
+}
+
+// ===== Debounce =====
+
+/// A filter that holds back frames of an ID while a signal in them
+/// changes, until the new value has been stable for a time.
+///
+/// Frames carrying the last accepted value of the signal are passed, as
+/// are frames of other IDs. Frames with a different value are dropped
+/// until the value has held for the debounce time; the frame at which
+/// that happens is passed, and its value becomes the accepted one. So a
+/// signal that toggles faster than the debounce time never gets through.
+#[derive(Debug, Clone, Copy)]
+pub struct Debounce {
+    id: CanId,
+    signal: Signal,
+    hold_us: u64,
+    stable: Option<i64>,
+    pending: Option<(i64, u64)>,
+}
+
+impl Debounce {
+    /// Creates a filter that debounces a signal in the frames of an ID.
+    pub fn new(id: CanId, signal: Signal, hold: Duration) -> Self {
+        Self {
+            id,
+            signal,
+            hold_us: hold.as_micros() as u64,
+            stable: None,
+            pending: None,
+        }
+    }
+
+    /// Gets the last accepted value of the signal.
+    pub fn value(&self) -> Option<i64> {
+        self.stable
+    }
+}
+
+impl RxFilter for Debounce {
+    fn accept_data(&mut self, t_us: u64, id: CanId, data: &[u8]) -> bool {
+        if id != self.id {
+            return true;
+        }
+        let val = match self.signal.decode_raw(data) {
+            Some(val) => val,
+            None => return false,
+        };
+
+        match self.stable {
+            None => {
+                self.stable = Some(val);
+                true
+            }
+            Some(stable) if stable == val => {
+                self.pending = None;
+                true
+            }
+            Some(_) => match self.pending {
+                Some((pending, since)) if pending == val => {
+                    if t_us.saturating_sub(since) >= self.hold_us {
+                        self.stable = Some(val);
+                        self.pending = None;
+                        true
+                    } else {
+                        false
+                    }
+                }
+                _ if self.hold_us == 0 => {
+                    self.stable = Some(val);
+                    self.pending = None;
+                    true
+                }
+                _ => {
+                    self.pending = Some((val, t_us));
+                    false
+                }
+            },
+        }
+    }
+}
+
+// ===== Pipeline adapters =====
+
+/// Wraps a frame handler, such as one for a
+/// [`Dispatcher`](crate::dispatch::Dispatcher), so that it only receives
+/// the frames the filter accepts.
+///
+/// Frames are timestamped when the handler is called.
+pub fn filtered<F, R, H>(mut filter: R, mut handler: H) -> impl FnMut(&F)
+where
+    F: Frame,
+    R: RxFilter,
+    H: FnMut(&F),
+{
+    let start = Instant::now();
+    move |frame| {
+        let t_us = start.elapsed().as_micros() as u64;
+        if filter.accept(t_us, frame) {
+            handler(frame)
+        }
+    }
+}
+
+/// Applies a filter to an async stream of frames, such as an
+/// [`AsyncCanSocket`](crate::tokio::AsyncCanSocket).
+///
+/// Frames are timestamped as they come out of the stream. Errors are
+/// passed through.
+#[cfg(feature = "tokio")]
+pub fn filter_stream<S, F, R>(stream: S, mut filter: R) -> impl futures::Stream<Item = S::Item>
+where
+    S: futures::Stream<Item = std::io::Result<F>>,
+    F: Frame,
+    R: RxFilter,
+{
+    use futures::StreamExt;
+
+    let start = Instant::now();
+    stream.filter(move |res| {
+        let pass = match res {
+            Ok(frame) => filter.accept(start.elapsed().as_micros() as u64, frame),
+            Err(_) => true,
+        };
+        futures::future::ready(pass)
+    })
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{signal::ByteOrder, CanFrame};
+
+    #[test]
+    fn test_dedup_rate_limit() {
+        let a1 = CanFrame::from_raw_id(0x100, &[1]).unwrap();
+        let a2 = CanFrame::from_raw_id(0x100, &[2]).unwrap();
+        let b = CanFrame::from_raw_id(0x200, &[1]).unwrap();
+
+        let mut dedup = Dedup::new().with_refresh(Duration::from_millis(100));
+        assert!(dedup.accept(0, &a1));
+        assert!(!dedup.accept(10_000, &a1));
+        assert!(dedup.accept(10_000, &b));
+        assert!(dedup.accept(20_000, &a2));
+        assert!(!dedup.accept(30_000, &a2));
+        assert!(dedup.accept(120_000, &a2));
+
+        let mut limit = RateLimit::unlimited().with_limit(CanId::standard(0x100).unwrap(), 10.0);
+        assert!(limit.accept(0, &a1));
+        assert!(!limit.accept(50_000, &a2));
+        assert!(limit.accept(50_000, &b));
+        assert!(limit.accept(100_000, &a1));
+
+        let mut chain = Dedup::new().and(RateLimit::new(10.0));
+        assert!(chain.accept(0, &a1));
+        assert!(!chain.accept(10_000, &a2));
+        // The deduplicator already saw a2, so it's dropped as a repeat
+        assert!(!chain.accept(200_000, &a2));
+    }
+
+    #[test]
+    fn test_debounce() {
+        let id = CanId::standard(0x100).unwrap();
+        let sig = Signal::new(0, 1, ByteOrder::LittleEndian);
+        let mut deb = Debounce::new(id, sig, Duration::from_millis(50));
+        let on = CanFrame::from_raw_id(0x100, &[1]).unwrap();
+        let off = CanFrame::from_raw_id(0x100, &[0]).unwrap();
+
+        assert!(deb.accept(0, &off));
+        // Toggling faster than the debounce time never gets through
+        assert!(!deb.accept(10_000, &on));
+        assert!(deb.accept(20_000, &off));
+        assert!(!deb.accept(30_000, &on));
+        assert!(!deb.accept(60_000, &on));
+        assert!(deb.accept(80_000, &on));
+        assert_eq!(Some(1), deb.value());
+        assert!(deb.accept(90_000, &on));
+    }
+}
@@ -0,0 +1,305 @@
+// socketcan/src/scanner.rs
+//
+// An inventory of the IDs active on a bus.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! A scanner of the IDs active on a bus.
+//!
+//! The first step in reverse-engineering an unknown bus is to find out
+//! what's on it. The [`BusScanner`] listens to a socket for a window of
+//! time, and returns an [`Inventory`] of every ID it saw, with the number
+//! of frames, the period between them, and the lengths of the payloads.
+//! The statistics of each ID are those of the
+//! [`TrafficAnalyzer`](crate::analyzer::TrafficAnalyzer), with the rate
+//! and the last payload among them.
+//!
+//! ```no_run
+//! use socketcan::{scanner::BusScanner, CanFdSocket, Socket};
+//! use std::time::Duration;
+//!
+//! let sock = CanFdSocket::open("can0").unwrap();
+//! let inventory = BusScanner::new(Duration::from_secs(5)).scan(&sock).unwrap();
+//!
+//! for entry in &inventory.ids {
+//!     println!(
+//!         "{:?}: {} frames, every {:.1} ms, lengths {:?}",
+//!         entry.id(),
+//!         entry.count(),
+//!         entry.mean_period_us().unwrap_or(0.0) / 1000.0,
+//!         entry.lengths
+//!     );
+//! }
+//! ```
+//!
+//! The scanner can also be fed frames from a capture log, to take the
+//! inventory of a recording.
+
+use crate::{
+    analyzer::{IdStats, TrafficAnalyzer},
+    backend::CanReceiver,
+    capture::Record,
+    CanAnyFrame, EmbeddedFrame, Frame, Id, IoErrorKind, IoResult,
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    time::{Duration, Instant},
+};
+
+// ===== ScannedId =====
+
+/// An ID seen on the bus.
+#[derive(Debug, Clone)]
+pub struct ScannedId {
+    /// The traffic statistics of the ID, such as the frame count, the
+    /// cycle times, and the last payload
+    pub stats: IdStats,
+    /// The number of remote frames with the ID
+    pub remote_count: u64,
+    /// Whether any of the frames were CAN FD frames
+    pub is_fd: bool,
+    /// The distinct payload lengths seen
+    pub lengths: BTreeSet<usize>,
+}
+
+impl ScannedId {
+    /// Gets the CAN ID.
+    pub fn id(&self) -> Id {
+        self.stats.id
+    }
+
+    /// Gets the number of frames with the ID.
+    pub fn count(&self) -> u64 {
+        self.stats.count
+    }
+
+    /// The mean time between consecutive frames, in microseconds.
+    ///
+    /// This is `None` until at least two frames were seen.
+    pub fn mean_period_us(&self) -> Option<f64> {
+        self.stats.mean_cycle_us()
+    }
+
+    /// Determines if the ID appears to be cyclic: sent at least three times
+    /// with the longest period within the tolerance (as a fraction) of the
+    /// shortest one.
+    pub fn is_cyclic(&self, tolerance: f64) -> bool {
+        let stats = &self.stats;
+        match (stats.count, stats.min_cycle_us, stats.max_cycle_us) {
+            (n, Some(min), Some(max)) if n >= 3 && min > 0 => {
+                (max - min) as f64 <= min as f64 * tolerance
+            }
+            _ => false,
+        }
+    }
+}
+
+// ===== Inventory =====
+
+/// The IDs seen on a bus during a scan.
+#[derive(Debug, Clone, Default)]
+pub struct Inventory {
+    /// The IDs, in order of bus priority
+    pub ids: Vec<ScannedId>,
+    /// The number of error frames
+    pub error_frames: u64,
+    /// The length of the scan, in microseconds
+    pub duration_us: u64,
+}
+
+impl Inventory {
+    /// Gets the entry for an ID.
+    pub fn get(&self, id: impl Into<Id>) -> Option<&ScannedId> {
+        let id = id.into();
+        self.ids.iter().find(|entry| entry.id() == id)
+    }
+
+    /// The total number of frames seen, excluding error frames.
+    pub fn total_frames(&self) -> u64 {
+        self.ids.iter().map(ScannedId::count).sum()
+    }
+}
+
+// ===== BusScanner =====
+
+/// What the scanner tracks about an ID beyond the traffic statistics.
+#[derive(Debug, Clone, Default)]
+struct FrameKinds {
+    remote_count: u64,
+    is_fd: bool,
+    lengths: BTreeSet<usize>,
+}
+
+/// A scanner that takes an inventory of the IDs on a bus.
+///
+/// The traffic statistics of each ID are kept by a [`TrafficAnalyzer`].
+#[derive(Debug, Clone)]
+pub struct BusScanner {
+    window: Duration,
+    analyzer: TrafficAnalyzer,
+    kinds: BTreeMap<Id, FrameKinds>,
+    // The length of the scan, when listening to a socket
+    scan_us: Option<u64>,
+}
+
+impl BusScanner {
+    /// Creates a scanner that listens for the window of time.
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            analyzer: TrafficAnalyzer::new(),
+            kinds: BTreeMap::new(),
+            scan_us: None,
+        }
+    }
+
+    /// Gets the time the scanner listens to a socket.
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// Gets the analyzer with the traffic statistics recorded so far.
+    pub fn analyzer(&self) -> &TrafficAnalyzer {
+        &self.analyzer
+    }
+
+    /// Records a timestamped frame.
+    ///
+    /// The frames should be given in time order.
+    pub fn update(&mut self, t_us: u64, frame: &CanAnyFrame) {
+        self.analyzer.update(t_us, frame);
+
+        if frame.is_error_frame() {
+            return;
+        }
+
+        let kinds = self.kinds.entry(frame.id()).or_default();
+        kinds.is_fd |= matches!(frame, CanAnyFrame::Fd(_));
+        if frame.is_remote_frame() {
+            kinds.remote_count += 1;
+            kinds.lengths.insert(frame.dlc());
+        } else {
+            kinds.lengths.insert(frame.data().len());
+        }
+    }
+
+    /// Records a frame from a capture log.
+    pub fn update_record(&mut self, rec: &Record) {
+        self.update(rec.t_us, &rec.frame)
+    }
+
+    /// Gets the inventory of the frames recorded so far.
+    pub fn inventory(&self) -> Inventory {
+        let report = self.analyzer.report();
+        let ids = report
+            .ids
+            .into_iter()
+            .map(|stats| {
+                let kinds = self.kinds.get(&stats.id).cloned().unwrap_or_default();
+                ScannedId {
+                    stats,
+                    remote_count: kinds.remote_count,
+                    is_fd: kinds.is_fd,
+                    lengths: kinds.lengths,
+                }
+            })
+            .collect();
+
+        Inventory {
+            ids,
+            error_frames: report.error_frames,
+            duration_us: self.scan_us.unwrap_or(report.duration_us),
+        }
+    }
+
+    /// Listens to the socket, or other receiver, for the window of time,
+    /// and returns the inventory of the frames seen.
+    ///
+    /// Frames are timestamped on reception, relative to the start of the
+    /// scan.
+    pub fn scan<S>(mut self, sock: &S) -> IoResult<Inventory>
+    where
+        S: CanReceiver + ?Sized,
+        S::Frame: Into<CanAnyFrame>,
+    {
+        let start = Instant::now();
+        let deadline = start + self.window;
+
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break;
+            }
+            match sock.receive_timeout(remaining) {
+                Ok(frame) => {
+                    let t_us = start.elapsed().as_micros() as u64;
+                    self.update(t_us, &frame.into());
+                }
+                Err(err) if err.kind() == IoErrorKind::TimedOut => break,
+                Err(err) => return Err(err),
+            }
+        }
+        self.scan_us = Some(self.window.as_micros() as u64);
+        Ok(self.inventory())
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CanFrame, CanRemoteFrame, Frame, StandardId};
+
+    #[test]
+    fn test_scanner() {
+        let mut frames: Vec<(u64, CanAnyFrame)> = (0..5u64)
+            .map(|i| {
+                let frame = CanFrame::from_raw_id(0x100, &[i as u8; 8]).unwrap();
+                (i * 10_000, frame.into())
+            })
+            .collect();
+        frames.push((15_000, CanFrame::from_raw_id(0x200, &[1]).unwrap().into()));
+        frames.push((
+            25_000,
+            CanFrame::from_raw_id(0x200, &[1, 2]).unwrap().into(),
+        ));
+        let rtr = CanRemoteFrame::new_remote(StandardId::new(0x300).unwrap(), 4).unwrap();
+        frames.push((30_000, CanAnyFrame::Remote(rtr)));
+        frames.sort_by_key(|(t_us, _)| *t_us);
+
+        let mut scanner = BusScanner::new(Duration::from_secs(1));
+        for (t_us, frame) in &frames {
+            scanner.update(*t_us, frame);
+        }
+
+        let inv = scanner.inventory();
+        assert_eq!(3, inv.ids.len());
+        assert_eq!(8, inv.total_frames());
+        assert_eq!(40_000, inv.duration_us);
+
+        let entry = inv.get(StandardId::new(0x100).unwrap()).unwrap();
+        assert_eq!(5, entry.count());
+        assert_eq!(Some(10_000.0), entry.mean_period_us());
+        assert_eq!(100.0, entry.stats.rate());
+        assert_eq!(&[4; 8], entry.stats.last_data());
+        assert!(entry.is_cyclic(0.1));
+        assert_eq!(vec![8], entry.lengths.iter().copied().collect::<Vec<_>>());
+
+        let entry = inv.get(StandardId::new(0x200).unwrap()).unwrap();
+        assert!(!entry.is_cyclic(0.1));
+        assert_eq!(
+            vec![1, 2],
+            entry.lengths.iter().copied().collect::<Vec<_>>()
+        );
+
+        let entry = inv.get(StandardId::new(0x300).unwrap()).unwrap();
+        assert_eq!(1, entry.remote_count);
+        assert_eq!(None, entry.mean_period_us());
+    }
+}
@@ -0,0 +1,649 @@
+// socketcan/src/scheduler.rs
+//
+// Userspace periodic transmit scheduler.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! Periodic transmission of many cyclic messages over one socket.
+//!
+//! The kernel's broadcast manager (BCM) can send cyclic messages, but it's
+//! not always available, and it can only send fixed payloads. The
+//! [`Scheduler`] does the same in userspace: it runs a background thread
+//! driven by a `timerfd` that sends each message at its period, after an
+//! initial offset, with the payload supplied by a callback each time it's
+//! sent. Messages can be added, removed, enabled, disabled, and have their
+//! payloads updated while the scheduler is running.
+//!
+//! ```no_run
+//! use socketcan::{scheduler::Scheduler, CanFrame, CanSocket, EmbeddedFrame, Socket, StandardId};
+//! use std::time::Duration;
+//!
+//! let sock = CanSocket::open("can0").unwrap();
+//! let sched = Scheduler::start(sock).unwrap();
+//!
+//! let id = StandardId::new(0x100).unwrap();
+//! let mut counter = 0u8;
+//! let task = sched.add(Duration::from_millis(10), Duration::ZERO, move || {
+//!     counter = counter.wrapping_add(1);
+//!     CanFrame::new(id, &[counter]).unwrap()
+//! });
+//!
+//! std::thread::sleep(Duration::from_secs(1));
+//! sched.disable(task);
+//! ```
+//!
+//! If the scheduler falls behind, such as when the socket blocks, a message
+//! skips the periods that it missed rather than sending a burst of frames
+//! to catch up. A frame that fails to send, such as when the bus is off,
+//! is logged and counted by [`Scheduler::tx_errors`], and the scheduler
+//! carries on.
+//!
+//! The scheduler thread runs on the system clock. To run a [`Schedule`]
+//! on a simulated clock instead, such as in a test, drive it with
+//! [`Schedule::run_until`].
+
+use crate::{backend::CanTransmitter, clock::Clock};
+use nix::sys::{
+    time::TimeSpec,
+    timer::Expiration,
+    timerfd::{ClockId, TimerFd, TimerFlags, TimerSetTimeFlags},
+};
+use std::{
+    convert::Infallible,
+    fmt,
+    io::{self, Result as IoResult},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// Converts a number of nanoseconds to a duration, saturating at the
+/// largest one.
+fn duration_from_nanos(nanos: u128) -> Duration {
+    const NANOS_PER_SEC: u128 = 1_000_000_000;
+    let secs = u64::try_from(nanos / NANOS_PER_SEC).unwrap_or(u64::MAX);
+    Duration::new(secs, (nanos % NANOS_PER_SEC) as u32)
+}
+
+/// The handle to a message in the scheduler.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TaskId(usize);
+
+/// A provider of the frame to send each period, or `None` to skip it.
+type Provider<F> = Box<dyn FnMut() -> Option<F> + Send>;
+
+/// A cyclic message.
+struct Task<F> {
+    id: TaskId,
+    period: Duration,
+    offset: Duration,
+    next: Instant,
+    enabled: bool,
+    provider: Provider<F>,
+}
+
+impl<F> fmt::Debug for Task<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Task")
+            .field("id", &self.id)
+            .field("period", &self.period)
+            .field("offset", &self.offset)
+            .field("next", &self.next)
+            .field("enabled", &self.enabled)
+            .finish_non_exhaustive()
+    }
+}
+
+// ===== Schedule =====
+
+/// The set of cyclic messages and when each is next due.
+///
+/// This is the timing logic of the [`Scheduler`], separate from the
+/// thread and socket, so it can be driven by any timer.
+#[derive(Debug)]
+pub struct Schedule<F> {
+    tasks: Vec<Task<F>>,
+    next_id: usize,
+}
+
+impl<F> Default for Schedule<F> {
+    fn default() -> Self {
+        Self {
+            tasks: Vec::new(),
+            next_id: 0,
+        }
+    }
+}
+
+impl<F> Schedule<F> {
+    /// Creates an empty schedule.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an enabled message, first due at `now + offset`.
+    ///
+    /// # Panics
+    ///
+    /// If the period is zero.
+    pub fn add<P>(
+        &mut self,
+        now: Instant,
+        period: Duration,
+        offset: Duration,
+        mut provider: P,
+    ) -> TaskId
+    where
+        P: FnMut() -> F + Send + 'static,
+    {
+        self.add_optional(now, period, offset, move || Some(provider()))
+    }
+
+    /// Adds an enabled message, like [`add`](Self::add), with a provider
+    /// that can skip a period by returning `None`.
+    ///
+    /// # Panics
+    ///
+    /// If the period is zero.
+    pub fn add_optional<P>(
+        &mut self,
+        now: Instant,
+        period: Duration,
+        offset: Duration,
+        provider: P,
+    ) -> TaskId
+    where
+        P: FnMut() -> Option<F> + Send + 'static,
+    {
+        assert!(!period.is_zero(), "the period must be non-zero");
+        let id = TaskId(self.next_id);
+        self.next_id += 1;
+        self.tasks.push(Task {
+            id,
+            period,
+            offset,
+            next: now + offset,
+            enabled: true,
+            provider: Box::new(provider),
+        });
+        id
+    }
+
+    /// Removes a message, returning whether it was in the schedule.
+    pub fn remove(&mut self, id: TaskId) -> bool {
+        let n = self.tasks.len();
+        self.tasks.retain(|task| task.id != id);
+        self.tasks.len() != n
+    }
+
+    fn task(&mut self, id: TaskId) -> Option<&mut Task<F>> {
+        self.tasks.iter_mut().find(|task| task.id == id)
+    }
+
+    /// Enables a message, restarting its timing at `now + offset`.
+    pub fn enable(&mut self, id: TaskId, now: Instant) -> bool {
+        self.task(id)
+            .map(|task| {
+                if !task.enabled {
+                    task.enabled = true;
+                    task.next = now + task.offset;
+                }
+            })
+            .is_some()
+    }
+
+    /// Disables a message, without removing it.
+    pub fn disable(&mut self, id: TaskId) -> bool {
+        self.task(id).map(|task| task.enabled = false).is_some()
+    }
+
+    /// Determines if the message is enabled.
+    pub fn is_enabled(&self, id: TaskId) -> Option<bool> {
+        self.tasks
+            .iter()
+            .find(|task| task.id == id)
+            .map(|task| task.enabled)
+    }
+
+    /// Replaces the payload provider of a message.
+    pub fn set_provider<P>(&mut self, id: TaskId, mut provider: P) -> bool
+    where
+        P: FnMut() -> F + Send + 'static,
+    {
+        self.set_optional_provider(id, move || Some(provider()))
+    }
+
+    /// Replaces the payload provider of a message with one that can skip
+    /// a period by returning `None`.
+    pub fn set_optional_provider<P>(&mut self, id: TaskId, provider: P) -> bool
+    where
+        P: FnMut() -> Option<F> + Send + 'static,
+    {
+        self.task(id)
+            .map(|task| task.provider = Box::new(provider))
+            .is_some()
+    }
+
+    /// Changes the period of a message, taking effect after its next send.
+    ///
+    /// # Panics
+    ///
+    /// If the period is zero.
+    pub fn set_period(&mut self, id: TaskId, period: Duration) -> bool {
+        assert!(!period.is_zero(), "the period must be non-zero");
+        self.task(id).map(|task| task.period = period).is_some()
+    }
+
+    /// Gets the time the next message is due, if any are enabled.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.tasks
+            .iter()
+            .filter(|task| task.enabled)
+            .map(|task| task.next)
+            .min()
+    }
+
+    /// Gets the frames for all the messages due at `now`, and advances
+    /// each of them to its next period.
+    pub fn poll<E, W>(&mut self, now: Instant, mut send: W) -> Result<(), E>
+    where
+        W: FnMut(F) -> Result<(), E>,
+    {
+        for task in self.tasks.iter_mut().filter(|t| t.enabled && t.next <= now) {
+            if let Some(frame) = (task.provider)() {
+                send(frame)?;
+            }
+            task.next += task.period;
+            if task.next <= now {
+                // Fell behind; skip the missed periods
+                let period = task.period.as_nanos();
+                let periods = (now - task.next).as_nanos() / period + 1;
+                task.next += duration_from_nanos(period.saturating_mul(periods));
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs the schedule on the clock until the time, sleeping between
+    /// the messages that are due.
+    ///
+    /// On a [`SimClock`](crate::clock::SimClock), this returns right away,
+    /// with the clock moved on to the end time, having sent every message
+    /// due in between.
+    pub fn run_until<C, E, W>(&mut self, clock: &C, until: Instant, mut send: W) -> Result<(), E>
+    where
+        C: Clock + ?Sized,
+        W: FnMut(F) -> Result<(), E>,
+    {
+        loop {
+            match self.next_deadline() {
+                Some(next) if next <= until => {
+                    clock.sleep_until(next);
+                    self.poll(clock.now(), &mut send)?;
+                }
+                _ => {
+                    clock.sleep_until(until);
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+// ===== Scheduler =====
+
+/// The state shared with the scheduler thread.
+#[derive(Debug)]
+struct Shared<F> {
+    schedule: Mutex<Schedule<F>>,
+    timer: TimerFd,
+    stop: AtomicBool,
+    tx_errors: AtomicU64,
+}
+
+impl<F> Shared<F> {
+    /// Wakes the thread to recompute the next deadline.
+    fn wake(&self) {
+        // A zero expiration would disarm the timer
+        let _ = self.timer.set(
+            Expiration::OneShot(TimeSpec::from(Duration::from_nanos(1))),
+            TimerSetTimeFlags::empty(),
+        );
+    }
+}
+
+/// A userspace scheduler of cyclic messages over one socket.
+///
+/// The scheduler is stopped when dropped.
+#[derive(Debug)]
+pub struct Scheduler<F> {
+    shared: Arc<Shared<F>>,
+    thread: Option<JoinHandle<IoResult<()>>>,
+}
+
+impl<F: Send + 'static> Scheduler<F> {
+    /// Starts the scheduler thread, sending on the socket, or any other
+    /// transmitter.
+    pub fn start<S>(sock: S) -> IoResult<Self>
+    where
+        S: CanTransmitter<F> + Send + 'static,
+    {
+        let timer = TimerFd::new(ClockId::CLOCK_MONOTONIC, TimerFlags::TFD_CLOEXEC)?;
+        let shared = Arc::new(Shared {
+            schedule: Mutex::new(Schedule::new()),
+            timer,
+            stop: AtomicBool::new(false),
+            tx_errors: AtomicU64::new(0),
+        });
+
+        let thr_shared = Arc::clone(&shared);
+        let thread = thread::Builder::new()
+            .name("can-scheduler".into())
+            .spawn(move || Self::run(&thr_shared, &sock))?;
+
+        Ok(Self {
+            shared,
+            thread: Some(thread),
+        })
+    }
+
+    /// The scheduler thread.
+    ///
+    /// The due frames are collected under the lock, and sent after it's
+    /// released, so that a blocked socket doesn't hold up the callers.
+    fn run<S>(shared: &Shared<F>, sock: &S) -> IoResult<()>
+    where
+        S: CanTransmitter<F>,
+    {
+        let mut due = Vec::new();
+        while !shared.stop.load(Ordering::Acquire) {
+            let now = Instant::now();
+            let next = {
+                let mut sched = shared.schedule.lock().unwrap();
+                sched
+                    .poll(now, |frame| {
+                        due.push(frame);
+                        Ok::<_, Infallible>(())
+                    })
+                    .unwrap_or_else(|never| match never {});
+                sched.next_deadline()
+            };
+
+            for frame in due.drain(..) {
+                if let Err(err) = sock.transmit(&frame) {
+                    log::warn!("Scheduler failed to send a frame: {}", err);
+                    shared.tx_errors.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+
+            match next {
+                Some(next) => {
+                    let wait = next
+                        .saturating_duration_since(Instant::now())
+                        .max(Duration::from_nanos(1));
+                    shared.timer.set(
+                        Expiration::OneShot(TimeSpec::from(wait)),
+                        TimerSetTimeFlags::empty(),
+                    )?;
+                }
+                None => shared.timer.unset()?,
+            }
+            shared.timer.wait()?;
+        }
+        Ok(())
+    }
+
+    fn schedule(&self) -> MutexGuard<'_, Schedule<F>> {
+        self.shared.schedule.lock().unwrap()
+    }
+
+    /// Gets the number of frames that failed to send.
+    pub fn tx_errors(&self) -> u64 {
+        self.shared.tx_errors.load(Ordering::Relaxed)
+    }
+
+    /// Adds a message, sent every `period` starting after `offset`, with
+    /// the frame supplied by the provider each time.
+    ///
+    /// # Panics
+    ///
+    /// If the period is zero.
+    pub fn add<P>(&self, period: Duration, offset: Duration, provider: P) -> TaskId
+    where
+        P: FnMut() -> F + Send + 'static,
+    {
+        let id = self
+            .schedule()
+            .add(Instant::now(), period, offset, provider);
+        self.shared.wake();
+        id
+    }
+
+    /// Adds a message, like [`add`](Self::add), with a provider that can
+    /// skip a period by returning `None`.
+    ///
+    /// # Panics
+    ///
+    /// If the period is zero.
+    pub fn add_optional<P>(&self, period: Duration, offset: Duration, provider: P) -> TaskId
+    where
+        P: FnMut() -> Option<F> + Send + 'static,
+    {
+        let id = self
+            .schedule()
+            .add_optional(Instant::now(), period, offset, provider);
+        self.shared.wake();
+        id
+    }
+
+    /// Adds a message with a fixed frame.
+    ///
+    /// The frame can be changed later with [`set_frame`](Self::set_frame).
+    pub fn add_frame(&self, period: Duration, offset: Duration, frame: F) -> TaskId
+    where
+        F: Clone + Send,
+    {
+        self.add(period, offset, move || frame.clone())
+    }
+
+    /// Removes a message, returning whether it was in the scheduler.
+    pub fn remove(&self, id: TaskId) -> bool {
+        self.schedule().remove(id)
+    }
+
+    /// Enables a message, restarting its timing from its offset.
+    pub fn enable(&self, id: TaskId) -> bool {
+        let found = self.schedule().enable(id, Instant::now());
+        self.shared.wake();
+        found
+    }
+
+    /// Disables a message, without removing it.
+    pub fn disable(&self, id: TaskId) -> bool {
+        self.schedule().disable(id)
+    }
+
+    /// Determines if the message is enabled.
+    pub fn is_enabled(&self, id: TaskId) -> Option<bool> {
+        self.schedule().is_enabled(id)
+    }
+
+    /// Replaces the payload provider of a message.
+    pub fn set_provider<P>(&self, id: TaskId, provider: P) -> bool
+    where
+        P: FnMut() -> F + Send + 'static,
+    {
+        self.schedule().set_provider(id, provider)
+    }
+
+    /// Replaces the payload provider of a message with one that can skip
+    /// a period by returning `None`.
+    pub fn set_optional_provider<P>(&self, id: TaskId, provider: P) -> bool
+    where
+        P: FnMut() -> Option<F> + Send + 'static,
+    {
+        self.schedule().set_optional_provider(id, provider)
+    }
+
+    /// Replaces the frame sent by a message with a fixed one.
+    pub fn set_frame(&self, id: TaskId, frame: F) -> bool
+    where
+        F: Clone + Send,
+    {
+        self.set_provider(id, move || frame.clone())
+    }
+
+    /// Changes the period of a message.
+    ///
+    /// # Panics
+    ///
+    /// If the period is zero.
+    pub fn set_period(&self, id: TaskId, period: Duration) -> bool {
+        self.schedule().set_period(id, period)
+    }
+
+    /// Stops the scheduler, returning any error that stopped the thread
+    /// early, such as a failure of its timer.
+    pub fn stop(mut self) -> IoResult<()> {
+        self.shutdown()
+    }
+}
+
+impl<F> Scheduler<F> {
+    fn shutdown(&mut self) -> IoResult<()> {
+        self.shared.stop.store(true, Ordering::Release);
+        self.shared.wake();
+        match self.thread.take() {
+            Some(thread) => thread
+                .join()
+                .map_err(|_| io::Error::new(io::ErrorKind::Other, "scheduler thread panicked"))?,
+            None => Ok(()),
+        }
+    }
+}
+
+impl<F> Drop for Scheduler<F> {
+    fn drop(&mut self) {
+        let _ = self.shutdown();
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn poll_all(sched: &mut Schedule<u32>, now: Instant) -> Vec<u32> {
+        let mut sent = Vec::new();
+        sched
+            .poll(now, |frame| {
+                sent.push(frame);
+                Ok::<_, ()>(())
+            })
+            .unwrap();
+        sent
+    }
+
+    #[test]
+    fn test_schedule() {
+        let t0 = Instant::now();
+        let ms = Duration::from_millis;
+
+        let mut sched = Schedule::new();
+        let a = sched.add(t0, ms(10), ms(0), || 1);
+        let mut n = 100;
+        let b = sched.add(t0, ms(25), ms(5), move || {
+            n += 1;
+            n
+        });
+
+        assert_eq!(Some(t0), sched.next_deadline());
+        assert_eq!(vec![1], poll_all(&mut sched, t0));
+        assert_eq!(Some(t0 + ms(5)), sched.next_deadline());
+        assert_eq!(vec![101], poll_all(&mut sched, t0 + ms(5)));
+        assert_eq!(vec![1], poll_all(&mut sched, t0 + ms(10)));
+
+        // Falling behind skips the missed periods
+        assert_eq!(vec![1, 102], poll_all(&mut sched, t0 + ms(61)));
+        assert_eq!(Some(t0 + ms(70)), sched.next_deadline());
+
+        assert!(sched.disable(a));
+        assert_eq!(Some(false), sched.is_enabled(a));
+        assert_eq!(Some(t0 + ms(80)), sched.next_deadline());
+        assert!(sched.set_provider(b, || 7));
+        assert_eq!(vec![7], poll_all(&mut sched, t0 + ms(80)));
+        assert!(sched.set_optional_provider(b, || None));
+        assert!(poll_all(&mut sched, t0 + ms(105)).is_empty());
+        assert_eq!(Some(t0 + ms(130)), sched.next_deadline());
+
+        assert!(sched.enable(a, t0 + ms(85)));
+        assert_eq!(Some(t0 + ms(85)), sched.next_deadline());
+        assert!(sched.remove(b));
+        assert!(!sched.remove(b));
+    }
+
+    #[test]
+    fn test_skip_many_periods() {
+        let t0 = Instant::now();
+        let ns = Duration::from_nanos;
+
+        // More missed periods than fit in a u32
+        let mut sched = Schedule::new();
+        sched.add(t0, ns(1), ns(0), || 1);
+        let now = t0 + Duration::from_secs(10);
+        assert_eq!(vec![1], poll_all(&mut sched, now));
+        assert_eq!(Some(now + ns(1)), sched.next_deadline());
+    }
+
+    #[test]
+    fn test_scheduler_tx_error() {
+        use crate::mock::MockCanSocket;
+
+        let sock = MockCanSocket::<u32>::new();
+        sock.fail_tx(io::ErrorKind::Other);
+        let sched = Scheduler::start(sock.clone()).unwrap();
+        sched.add(Duration::from_millis(1), Duration::ZERO, || 1u32);
+
+        // The thread runs on the system clock, so poll for the failed send
+        // and the next good one, up to a generous deadline.
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while (sched.tx_errors() == 0 || sock.sent().is_empty()) && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(1, sched.tx_errors());
+        sched.stop().unwrap();
+        assert!(!sock.sent().is_empty());
+    }
+
+    #[test]
+    fn test_run_until() {
+        use crate::clock::SimClock;
+
+        let clock = SimClock::new();
+        let t0 = clock.now();
+        let mut sched = Schedule::new();
+        sched.add(t0, Duration::from_secs(1), Duration::ZERO, || 1);
+        sched.add(t0, Duration::from_secs(60), Duration::from_secs(30), || 2);
+
+        let mut sent = Vec::new();
+        sched
+            .run_until(&clock, t0 + Duration::from_secs(3600), |frame| {
+                sent.push((clock.elapsed().as_secs(), frame));
+                Ok::<_, ()>(())
+            })
+            .unwrap();
+
+        assert_eq!(3601 + 60, sent.len());
+        assert_eq!((30, 2), sent[31]);
+        assert_eq!(Duration::from_secs(3600), clock.elapsed());
+    }
+}
@@ -0,0 +1,439 @@
+// socketcan/src/segment.rs
+//
+// Pluggable segmentation and reassembly of multi-frame messages.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! Pluggable segmentation and reassembly of multi-frame messages.
+//!
+//! Messages longer than a single frame have to be split into segments on
+//! the way out and put back together on the way in. The kernel does this
+//! for ISO-TP with the [`IsoTpSocket`](crate::IsoTpSocket), but plenty of
+//! devices use a framing of their own. The [`Segmenter`] and
+//! [`Reassembler`] traits describe such a framing, so that it can be
+//! plugged into the rest of the library:
+//!
+//! - [`send_segmented()`] writes a message to any socket as a series of
+//!   frames.
+//! - [`reassembled()`] wraps a message handler into a frame handler, such
+//!   as one for a [`Dispatcher`](crate::dispatch::Dispatcher).
+//! - `reassemble_stream()` turns an async stream of frames into a stream
+//!   of messages (with the `tokio` feature).
+//!
+//! The [`IsoTpSegmenter`] and [`IsoTpReassembler`] are a reference
+//! implementation, using the ISO-TP (ISO 15765-2) frame layout in user
+//! space, without flow control.
+//!
+//! ```no_run
+//! use socketcan::{
+//!     dispatch::Dispatcher,
+//!     segment::{reassembled, send_segmented, IsoTpReassembler, IsoTpSegmenter},
+//!     CanFrame, CanId, CanSocket, Socket,
+//! };
+//!
+//! let sock = CanSocket::open("can0").unwrap();
+//! let tx_id = CanId::standard(0x600).unwrap();
+//! let rx_id = CanId::standard(0x680).unwrap();
+//!
+//! let mut seg = IsoTpSegmenter::new().with_padding(0xCC);
+//! send_segmented::<_, CanFrame, _>(&sock, tx_id, &mut seg, &[0x42; 20]).unwrap();
+//!
+//! let mut disp = Dispatcher::<CanFrame>::new();
+//! disp.on_id(
+//!     rx_id,
+//!     reassembled(
+//!         IsoTpReassembler::new(),
+//!         |msg: &[u8]| println!("{:02X?}", msg),
+//!         |err| eprintln!("{}", err),
+//!     ),
+//! );
+//! loop {
+//!     disp.dispatch_next(&sock).unwrap();
+//! }
+//! ```
+
+use crate::{backend::CanTransmitter, CanId, ConstructionError, Frame};
+use std::io;
+use thiserror::Error;
+
+/// An error segmenting or reassembling a message.
+#[derive(Error, Debug)]
+pub enum SegmentError {
+    /// The message is too long for the framing
+    #[error("Message too long: {0} bytes")]
+    TooLong(usize),
+    /// A received frame doesn't follow the framing
+    #[error("Malformed segment")]
+    Malformed,
+    /// A segment arrived out of order
+    #[error("Segment out of sequence: expected {expected}, got {got}")]
+    Sequence {
+        /// The sequence number expected
+        expected: u8,
+        /// The sequence number received
+        got: u8,
+    },
+    /// A segment arrived with no message in progress
+    #[error("Unexpected segment")]
+    Unexpected,
+    /// A frame couldn't be built from a segment
+    #[error(transparent)]
+    Construction(#[from] ConstructionError),
+    /// An I/O error on the socket
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+/// The result of a segmentation or reassembly operation.
+pub type Result<T> = std::result::Result<T, SegmentError>;
+
+/// A framing that splits a message into the payloads of a series of
+/// frames.
+pub trait Segmenter {
+    /// Splits the message into frame payloads, in the order they are sent.
+    fn segment(&mut self, msg: &[u8]) -> Result<Vec<Vec<u8>>>;
+}
+
+/// A framing that puts a message back together from the payloads of a
+/// series of frames.
+pub trait Reassembler {
+    /// Feeds in the payload of the next frame.
+    ///
+    /// Returns the message when its last segment arrives. On an error,
+    /// the message in progress is dropped.
+    fn push(&mut self, data: &[u8]) -> Result<Option<Vec<u8>>>;
+
+    /// Drops the message in progress, if any.
+    fn reset(&mut self);
+}
+
+/// Writes a message to a socket, or other transmitter, as a series of frames with the ID.
+pub fn send_segmented<S, F, G>(sock: &S, id: CanId, seg: &mut G, msg: &[u8]) -> Result<()>
+where
+    S: CanTransmitter<F> + ?Sized,
+    F: Frame,
+    G: Segmenter + ?Sized,
+{
+    for data in seg.segment(msg)? {
+        let frame = F::try_new(id, &data)?;
+        sock.transmit(&frame)?;
+    }
+    Ok(())
+}
+
+/// Wraps a message handler into a frame handler, such as one for a
+/// [`Dispatcher`](crate::dispatch::Dispatcher), that feeds the frames to
+/// the reassembler. The `on_error` callback gets the reassembly errors.
+///
+/// The handler should only get the frames of a single sender, such as by
+/// registering it for one ID.
+pub fn reassembled<F, R, H, E>(
+    mut reassembler: R,
+    mut handler: H,
+    mut on_error: E,
+) -> impl FnMut(&F)
+where
+    F: Frame,
+    R: Reassembler,
+    H: FnMut(&[u8]),
+    E: FnMut(SegmentError),
+{
+    move |frame| match reassembler.push(frame.data()) {
+        Ok(Some(msg)) => handler(&msg),
+        Ok(None) => (),
+        Err(err) => on_error(err),
+    }
+}
+
+/// Turns an async stream of frames, such as an
+/// [`AsyncCanSocket`](crate::tokio::AsyncCanSocket), into a stream of the
+/// messages reassembled from the frames with the ID.
+///
+/// Frames with other IDs are skipped. Errors from the stream and from
+/// reassembly are passed through.
+#[cfg(feature = "tokio")]
+pub fn reassemble_stream<S, F, R>(
+    stream: S,
+    id: CanId,
+    mut reassembler: R,
+) -> impl futures::Stream<Item = Result<Vec<u8>>>
+where
+    S: futures::Stream<Item = io::Result<F>>,
+    F: Frame,
+    R: Reassembler,
+{
+    use futures::StreamExt;
+
+    stream.filter_map(move |res| {
+        let item = match res {
+            Ok(frame) if frame.can_id() == id => reassembler.push(frame.data()).transpose(),
+            Ok(_) => None,
+            Err(err) => Some(Err(err.into())),
+        };
+        futures::future::ready(item)
+    })
+}
+
+// ===== ISO-TP =====
+
+/// The largest message that fits in ISO-TP with a 12-bit length.
+pub const ISOTP_MAX_LEN: usize = 4095;
+
+// The frame types, in the high nibble of the first byte
+const SINGLE: u8 = 0x00;
+const FIRST: u8 = 0x10;
+const CONSECUTIVE: u8 = 0x20;
+const FLOW_CONTROL: u8 = 0x30;
+
+/// A segmenter using the ISO-TP frame layout.
+///
+/// This only does the framing. It doesn't wait for flow control from the
+/// receiver, so the receiver must be able to take the consecutive frames
+/// back-to-back.
+#[derive(Debug, Clone, Copy)]
+pub struct IsoTpSegmenter {
+    frame_len: usize,
+    padding: Option<u8>,
+}
+
+impl Default for IsoTpSegmenter {
+    fn default() -> Self {
+        Self {
+            frame_len: 8,
+            padding: None,
+        }
+    }
+}
+
+impl IsoTpSegmenter {
+    /// Creates a segmenter for classic 8-byte frames, without padding.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the payload length of the frames, such as 64 for CAN FD.
+    ///
+    /// # Panics
+    ///
+    /// If the length is less than 8 bytes.
+    pub fn with_frame_len(mut self, frame_len: usize) -> Self {
+        assert!(frame_len >= 8, "ISO-TP frames hold at least 8 bytes");
+        self.frame_len = frame_len;
+        self
+    }
+
+    /// Pads every frame out to the full length with the byte.
+    pub fn with_padding(mut self, padding: u8) -> Self {
+        self.padding = Some(padding);
+        self
+    }
+
+    fn pad(&self, mut data: Vec<u8>) -> Vec<u8> {
+        if let Some(padding) = self.padding {
+            data.resize(self.frame_len, padding);
+        }
+        data
+    }
+}
+
+impl Segmenter for IsoTpSegmenter {
+    fn segment(&mut self, msg: &[u8]) -> Result<Vec<Vec<u8>>> {
+        let len = msg.len();
+        if len > ISOTP_MAX_LEN {
+            return Err(SegmentError::TooLong(len));
+        }
+
+        // A single frame, with the length escaped for long FD frames
+        if len < 8 {
+            let mut data = vec![SINGLE | len as u8];
+            data.extend_from_slice(msg);
+            return Ok(vec![self.pad(data)]);
+        }
+        if len <= self.frame_len - 2 {
+            let mut data = vec![SINGLE, len as u8];
+            data.extend_from_slice(msg);
+            return Ok(vec![self.pad(data)]);
+        }
+
+        let (first, rest) = msg.split_at(self.frame_len - 2);
+        let mut data = vec![FIRST | (len >> 8) as u8, len as u8];
+        data.extend_from_slice(first);
+
+        let mut segs = vec![data];
+        for (i, chunk) in rest.chunks(self.frame_len - 1).enumerate() {
+            let mut data = vec![CONSECUTIVE | ((i + 1) % 16) as u8];
+            data.extend_from_slice(chunk);
+            segs.push(self.pad(data));
+        }
+        Ok(segs)
+    }
+}
+
+/// A reassembler using the ISO-TP frame layout.
+///
+/// Flow control frames are ignored, so this can listen in on a transfer
+/// between two other nodes.
+#[derive(Debug, Clone, Default)]
+pub struct IsoTpReassembler {
+    buf: Vec<u8>,
+    len: usize,
+    seq: u8,
+}
+
+impl IsoTpReassembler {
+    /// Creates a reassembler with no message in progress.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Determines if a message is in progress.
+    pub fn in_progress(&self) -> bool {
+        self.len != 0
+    }
+}
+
+impl Reassembler for IsoTpReassembler {
+    fn push(&mut self, data: &[u8]) -> Result<Option<Vec<u8>>> {
+        let pci = *data.first().ok_or(SegmentError::Malformed)?;
+
+        match pci & 0xF0 {
+            SINGLE => {
+                self.reset();
+                let (len, payload) = match pci & 0x0F {
+                    0 => (
+                        *data.get(1).ok_or(SegmentError::Malformed)? as usize,
+                        &data[2..],
+                    ),
+                    n => (n as usize, &data[1..]),
+                };
+                if len == 0 || len > payload.len() {
+                    return Err(SegmentError::Malformed);
+                }
+                Ok(Some(payload[..len].to_vec()))
+            }
+            FIRST => {
+                self.reset();
+                let len = ((pci as usize & 0x0F) << 8)
+                    | *data.get(1).ok_or(SegmentError::Malformed)? as usize;
+                if len < 8 {
+                    return Err(SegmentError::Malformed);
+                }
+                self.buf.extend_from_slice(&data[2..]);
+                self.len = len;
+                self.seq = 1;
+                Ok(None)
+            }
+            CONSECUTIVE => {
+                if !self.in_progress() {
+                    return Err(SegmentError::Unexpected);
+                }
+                let got = pci & 0x0F;
+                if got != self.seq {
+                    let expected = self.seq;
+                    self.reset();
+                    return Err(SegmentError::Sequence { expected, got });
+                }
+                self.seq = (self.seq + 1) % 16;
+
+                let n = (self.len - self.buf.len()).min(data.len() - 1);
+                self.buf.extend_from_slice(&data[1..=n]);
+                if self.buf.len() < self.len {
+                    return Ok(None);
+                }
+                let msg = std::mem::take(&mut self.buf);
+                self.reset();
+                Ok(Some(msg))
+            }
+            FLOW_CONTROL => Ok(None),
+            _ => Err(SegmentError::Malformed),
+        }
+    }
+
+    fn reset(&mut self) {
+        self.buf.clear();
+        self.len = 0;
+        self.seq = 0;
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_isotp_round_trip() {
+        let mut seg = IsoTpSegmenter::new().with_padding(0xCC);
+        let mut rsm = IsoTpReassembler::new();
+
+        let msg: Vec<u8> = (0..40).collect();
+        let segs = seg.segment(&msg).unwrap();
+        assert_eq!(6, segs.len());
+        assert_eq!(&[0x10, 40, 0, 1, 2, 3, 4, 5], segs[0].as_slice());
+        assert_eq!(&[0x21, 6, 7, 8, 9, 10, 11, 12], segs[1].as_slice());
+        assert_eq!(&[0x25, 34, 35, 36, 37, 38, 39, 0xCC], segs[5].as_slice());
+
+        let (last, segs) = segs.split_last().unwrap();
+        for data in segs {
+            assert!(rsm.push(data).unwrap().is_none());
+        }
+        assert_eq!(Some(msg), rsm.push(last).unwrap());
+        assert!(!rsm.in_progress());
+
+        let segs = seg.segment(&[1, 2, 3]).unwrap();
+        assert_eq!(vec![vec![0x03, 1, 2, 3, 0xCC, 0xCC, 0xCC, 0xCC]], segs);
+        assert_eq!(Some(vec![1, 2, 3]), rsm.push(&segs[0]).unwrap());
+
+        let mut seg = IsoTpSegmenter::new().with_frame_len(64);
+        let segs = seg.segment(&[7; 20]).unwrap();
+        assert_eq!(1, segs.len());
+        assert_eq!(Some(vec![7; 20]), rsm.push(&segs[0]).unwrap());
+    }
+
+    #[test]
+    fn test_isotp_errors() {
+        let mut rsm = IsoTpReassembler::new();
+        assert!(matches!(
+            rsm.push(&[0x21, 0]),
+            Err(SegmentError::Unexpected)
+        ));
+
+        rsm.push(&[0x10, 20, 0, 1, 2, 3, 4, 5]).unwrap();
+        assert!(rsm.push(&[0x30, 0, 0]).unwrap().is_none());
+        assert!(matches!(
+            rsm.push(&[0x22, 0]),
+            Err(SegmentError::Sequence {
+                expected: 1,
+                got: 2
+            })
+        ));
+        assert!(!rsm.in_progress());
+
+        let mut seg = IsoTpSegmenter::new();
+        assert!(matches!(
+            seg.segment(&[0; 5000]),
+            Err(SegmentError::TooLong(5000))
+        ));
+    }
+
+    #[test]
+    fn test_send_segmented() {
+        use crate::{mock::MockCanSocket, CanFrame, EmbeddedFrame, StandardId};
+
+        let sock = MockCanSocket::<CanFrame>::new();
+        let id = CanId::from(StandardId::new(0x7E0).unwrap());
+        let mut seg = IsoTpSegmenter::new();
+
+        send_segmented::<_, CanFrame, _>(&sock, id, &mut seg, &[0; 20]).unwrap();
+        let sent = sock.sent();
+        assert_eq!(3, sent.len());
+        assert!(sent.iter().all(|frame| frame.can_id() == id));
+        assert_eq!(&[0x10, 20], &sent[0].data()[..2]);
+    }
+}
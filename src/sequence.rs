@@ -0,0 +1,251 @@
+// socketcan/src/sequence.rs
+//
+// Detection of gaps in rolling counters.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! Detection of gaps in rolling counters.
+//!
+//! Many messages carry a rolling counter that the sender increments with
+//! each frame. Following the counter on the receiving side shows whether
+//! every frame arrived: a jump means frames were lost, and a repeat means
+//! a frame was duplicated. That's a quick way to validate that a logger
+//! captured everything, or that a gateway forwarded everything.
+//!
+//! The [`SequenceChecker`] follows the counters of a set of IDs, each at
+//! its own position in the payload, and reports the gaps and duplicates.
+//!
+//! ```no_run
+//! use socketcan::{
+//!     sequence::SequenceChecker, signal::ByteOrder, CanFdSocket, CanId, Socket,
+//! };
+//!
+//! let sock = CanFdSocket::open("can0").unwrap();
+//! let mut checker = SequenceChecker::new()
+//!     .with_counter(CanId::standard(0x100).unwrap(), 8, 4, ByteOrder::LittleEndian);
+//!
+//! loop {
+//!     let frame = sock.read_frame().unwrap();
+//!     if let Some(event) = checker.update(&frame) {
+//!         println!("{:?}", event);
+//!     }
+//! }
+//! ```
+
+use crate::{capture::Record, signal, signal::ByteOrder, CanId, Frame};
+use std::collections::BTreeMap;
+
+/// A break in the sequence of a counter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SequenceEvent {
+    /// The counter skipped values, so frames were lost
+    Lost {
+        /// The ID of the frame
+        id: CanId,
+        /// The counter value expected
+        expected: u64,
+        /// The counter value received
+        received: u64,
+        /// The number of frames lost
+        count: u64,
+    },
+    /// The counter repeated the last value, so a frame was duplicated
+    Duplicate {
+        /// The ID of the frame
+        id: CanId,
+        /// The counter value repeated
+        value: u64,
+    },
+}
+
+/// The statistics of the counter of one ID.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SequenceStats {
+    /// The number of frames received
+    pub received: u64,
+    /// The number of frames lost, from the gaps in the counter
+    pub lost: u64,
+    /// The number of duplicated frames
+    pub duplicates: u64,
+    /// The number of gaps in the sequence
+    pub gaps: u64,
+    /// The last counter value
+    pub last: Option<u64>,
+}
+
+/// The counter of one ID.
+#[derive(Debug, Clone, Copy)]
+struct Counter {
+    start: u16,
+    len: u16,
+    order: ByteOrder,
+    modulus: u64,
+    stats: SequenceStats,
+}
+
+// ===== SequenceChecker =====
+
+/// A tracker of the rolling counters of a set of IDs.
+#[derive(Debug, Clone, Default)]
+pub struct SequenceChecker {
+    counters: BTreeMap<CanId, Counter>,
+}
+
+impl SequenceChecker {
+    /// Creates a checker with no counters.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Tracks a counter in the frames with the ID, at the bit position and
+    /// length, as for a [`Signal`](crate::signal::Signal). The counter
+    /// wraps from its largest value back to zero.
+    pub fn with_counter(self, id: CanId, start: u16, len: u16, order: ByteOrder) -> Self {
+        let modulus = 1u64.checked_shl(len.into()).unwrap_or(0);
+        self.with_counter_modulus(id, start, len, order, modulus)
+    }
+
+    /// Tracks a counter that wraps back to zero after `modulus - 1`, such
+    /// as a 4-bit counter that only runs from 0 to 14.
+    pub fn with_counter_modulus(
+        mut self,
+        id: CanId,
+        start: u16,
+        len: u16,
+        order: ByteOrder,
+        modulus: u64,
+    ) -> Self {
+        self.counters.insert(
+            id,
+            Counter {
+                start,
+                len,
+                order,
+                modulus,
+                stats: SequenceStats::default(),
+            },
+        );
+        self
+    }
+
+    /// Checks the counter in a frame.
+    ///
+    /// Returns the break in the sequence, if any. Frames of IDs without a
+    /// counter, or too short to hold it, are ignored.
+    pub fn update<F: Frame>(&mut self, frame: &F) -> Option<SequenceEvent> {
+        let id = frame.can_id();
+        let ctr = self.counters.get_mut(&id)?;
+        let value = signal::extract_bits(frame.data(), ctr.start, ctr.len, ctr.order)?;
+
+        let stats = &mut ctr.stats;
+        stats.received += 1;
+        let last = stats.last.replace(value)?;
+
+        let delta = match ctr.modulus {
+            0 => value.wrapping_sub(last),
+            m => (value % m + m - last % m) % m,
+        };
+        match delta {
+            0 => {
+                stats.duplicates += 1;
+                Some(SequenceEvent::Duplicate { id, value })
+            }
+            1 => None,
+            n => {
+                let count = n - 1;
+                stats.lost += count;
+                stats.gaps += 1;
+                let expected = match ctr.modulus {
+                    0 => last.wrapping_add(1),
+                    m => (last + 1) % m,
+                };
+                Some(SequenceEvent::Lost {
+                    id,
+                    expected,
+                    received: value,
+                    count,
+                })
+            }
+        }
+    }
+
+    /// Checks the counter in a frame from a capture log, such as to verify
+    /// that the log holds every frame that was sent.
+    pub fn update_record(&mut self, rec: &Record) -> Option<SequenceEvent> {
+        self.update(&rec.frame)
+    }
+
+    /// Gets the statistics of the counter of an ID.
+    pub fn stats(&self, id: CanId) -> Option<&SequenceStats> {
+        self.counters.get(&id).map(|ctr| &ctr.stats)
+    }
+
+    /// Iterates over the statistics of all the counters, in order of bus
+    /// priority.
+    pub fn iter(&self) -> impl Iterator<Item = (CanId, &SequenceStats)> {
+        self.counters.iter().map(|(id, ctr)| (*id, &ctr.stats))
+    }
+
+    /// The total number of frames lost on all the IDs.
+    pub fn total_lost(&self) -> u64 {
+        self.counters.values().map(|ctr| ctr.stats.lost).sum()
+    }
+
+    /// Clears the statistics, so that each counter starts over.
+    pub fn reset(&mut self) {
+        for ctr in self.counters.values_mut() {
+            ctr.stats = SequenceStats::default();
+        }
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CanFrame;
+
+    #[test]
+    fn test_sequence() {
+        let id = CanId::standard(0x100).unwrap();
+        let mut checker = SequenceChecker::new().with_counter(id, 8, 4, ByteOrder::LittleEndian);
+        let frame = |ctr: u8| CanFrame::from_raw_id(0x100, &[0xFF, 0xF0 | ctr]).unwrap();
+
+        assert_eq!(None, checker.update(&frame(14)));
+        assert_eq!(None, checker.update(&frame(15)));
+        assert_eq!(None, checker.update(&frame(0)));
+        assert_eq!(
+            Some(SequenceEvent::Duplicate { id, value: 0 }),
+            checker.update(&frame(0))
+        );
+        assert_eq!(
+            Some(SequenceEvent::Lost {
+                id,
+                expected: 1,
+                received: 4,
+                count: 3
+            }),
+            checker.update(&frame(4))
+        );
+        // Other IDs are ignored
+        let other = CanFrame::from_raw_id(0x200, &[0]).unwrap();
+        assert_eq!(None, checker.update(&other));
+
+        let stats = checker.stats(id).unwrap();
+        assert_eq!(5, stats.received);
+        assert_eq!(3, stats.lost);
+        assert_eq!(1, stats.duplicates);
+        assert_eq!(Some(4), stats.last);
+
+        let mut checker =
+            SequenceChecker::new().with_counter_modulus(id, 8, 4, ByteOrder::LittleEndian, 15);
+        checker.update(&frame(14));
+        assert_eq!(None, checker.update(&frame(0)));
+    }
+}
@@ -0,0 +1,329 @@
+// socketcan/src/signal.rs
+//
+// Bit-level signal extraction and insertion for frame payloads.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! Bit-level signals in frame payloads.
+//!
+//! A [`Signal`] describes a value packed into an arbitrary bit field of a
+//! frame's data: its start bit, length, byte order, signedness, and the
+//! linear scale and offset that convert the raw integer into a physical
+//! value. This is the same model used by DBC files, and the bit numbering
+//! follows the DBC conventions:
+//!
+//! - For little-endian (Intel) signals, the start bit is the least
+//!   significant bit, and bit `n` is bit `n % 8` of byte `n / 8`.
+//! - For big-endian (Motorola) signals, the start bit is the most
+//!   significant bit, with the same numbering of the bits in the payload,
+//!   so the signal runs from the start bit down to bit 0 of each byte,
+//!   then continues from bit 7 of the next byte.
+//!
+//! ```
+//! use socketcan::signal::{ByteOrder, Signal};
+//!
+//! // A 16-bit engine speed, in 0.25 rpm per bit
+//! let rpm = Signal::new(24, 16, ByteOrder::LittleEndian).with_scale(0.25, 0.0);
+//!
+//! let mut data = [0u8; 8];
+//! rpm.encode(&mut data, 1800.0).unwrap();
+//! assert_eq!(Some(1800.0), rpm.decode(&data));
+//! ```
+
+use thiserror::Error;
+
+/// An error inserting a signal value into a payload.
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignalError {
+    /// The signal doesn't fit inside the payload
+    #[error("signal at bit {start} with length {len} doesn't fit in a {size}-byte payload")]
+    OutOfBounds {
+        /// The start bit of the signal
+        start: u16,
+        /// The length of the signal, in bits
+        len: u16,
+        /// The size of the payload, in bytes
+        size: usize,
+    },
+    /// The signal length is zero, or longer than 64 bits
+    #[error("invalid signal length: {0}")]
+    InvalidLength(u16),
+    /// The value can't be represented in the signal
+    #[error("value out of range for the signal")]
+    ValueOutOfRange,
+}
+
+/// The byte order of a signal in the payload.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ByteOrder {
+    /// Little endian, also known as Intel byte order
+    #[default]
+    LittleEndian,
+    /// Big endian, also known as Motorola byte order
+    BigEndian,
+}
+
+/// Gets the payload bit positions of a signal, from its least significant
+/// bit to its most significant.
+///
+/// This is `None` if the signal doesn't fit in a payload of `size` bytes.
+fn bit_positions(
+    start: u16,
+    len: u16,
+    order: ByteOrder,
+    size: usize,
+) -> Option<impl Iterator<Item = usize>> {
+    let nbits = 8 * size;
+    let start = start as usize;
+    let len = len as usize;
+
+    // The position of the least significant bit, and the step to each
+    // more significant bit, which depends on the byte order.
+    let lsb = match order {
+        ByteOrder::LittleEndian => start,
+        ByteOrder::BigEndian => {
+            // Walk from the MSB down to the LSB in the sawtooth numbering,
+            // which in a linear, MSB-first, numbering is a simple count.
+            let msb_lin = (start / 8) * 8 + (7 - start % 8);
+            let lsb_lin = msb_lin + len.checked_sub(1)?;
+            (lsb_lin / 8) * 8 + (7 - lsb_lin % 8)
+        }
+    };
+
+    let positions = (0..len).map(move |i| match order {
+        ByteOrder::LittleEndian => lsb + i,
+        ByteOrder::BigEndian => {
+            let lsb_lin = (lsb / 8) * 8 + (7 - lsb % 8);
+            let lin = lsb_lin - i;
+            (lin / 8) * 8 + (7 - lin % 8)
+        }
+    });
+
+    let last = match order {
+        ByteOrder::LittleEndian => start + len.checked_sub(1)?,
+        ByteOrder::BigEndian => lsb,
+    };
+    (len > 0 && start < nbits && last < nbits).then_some(positions)
+}
+
+/// Extracts the raw, unsigned value of a bit field from a payload.
+///
+/// This returns `None` if the field is longer than 64 bits or doesn't fit
+/// inside the payload.
+pub fn extract_bits(data: &[u8], start: u16, len: u16, order: ByteOrder) -> Option<u64> {
+    if len > 64 {
+        return None;
+    }
+    let val = bit_positions(start, len, order, data.len())?
+        .enumerate()
+        .fold(0u64, |acc, (i, pos)| {
+            acc | (u64::from((data[pos / 8] >> (pos % 8)) & 1) << i)
+        });
+    Some(val)
+}
+
+/// Inserts the raw value into a bit field of a payload.
+///
+/// Only the low `len` bits of the value are used. The other bits in the
+/// payload are left untouched.
+pub fn insert_bits(
+    data: &mut [u8],
+    start: u16,
+    len: u16,
+    order: ByteOrder,
+    val: u64,
+) -> Result<(), SignalError> {
+    if len == 0 || len > 64 {
+        return Err(SignalError::InvalidLength(len));
+    }
+    let size = data.len();
+    let positions = bit_positions(start, len, order, size).ok_or(SignalError::OutOfBounds {
+        start,
+        len,
+        size,
+    })?;
+
+    for (i, pos) in positions.enumerate() {
+        let mask = 1u8 << (pos % 8);
+        if (val >> i) & 1 != 0 {
+            data[pos / 8] |= mask;
+        } else {
+            data[pos / 8] &= !mask;
+        }
+    }
+    Ok(())
+}
+
+// ===== Signal =====
+
+/// The definition of a signal packed into a frame payload.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Signal {
+    /// The start bit, in the DBC numbering for the byte order
+    pub start: u16,
+    /// The length, in bits (1-64)
+    pub len: u16,
+    /// The byte order
+    pub order: ByteOrder,
+    /// Whether the raw value is a two's complement signed integer
+    pub signed: bool,
+    /// The scale factor to convert the raw value to a physical one
+    pub scale: f64,
+    /// The offset added to the scaled raw value to get the physical one
+    pub offset: f64,
+}
+
+impl Signal {
+    /// Creates a new, unsigned signal with a scale of 1 and no offset.
+    pub fn new(start: u16, len: u16, order: ByteOrder) -> Self {
+        Self {
+            start,
+            len,
+            order,
+            signed: false,
+            scale: 1.0,
+            offset: 0.0,
+        }
+    }
+
+    /// Makes the signal a two's complement signed value.
+    pub fn signed(mut self) -> Self {
+        self.signed = true;
+        self
+    }
+
+    /// Sets the scale and offset to convert the raw value to a physical
+    /// value, as `physical = raw * scale + offset`.
+    pub fn with_scale(mut self, scale: f64, offset: f64) -> Self {
+        self.scale = scale;
+        self.offset = offset;
+        self
+    }
+
+    /// Gets the raw value of the signal, sign-extended if it's signed.
+    pub fn decode_raw(&self, data: &[u8]) -> Option<i64> {
+        let val = extract_bits(data, self.start, self.len, self.order)?;
+        let shift = 64 - u32::from(self.len);
+        Some(if self.signed {
+            ((val << shift) as i64) >> shift
+        } else {
+            val as i64
+        })
+    }
+
+    /// Gets the physical value of the signal.
+    ///
+    /// Note that an unsigned 64-bit raw value is too large for an `i64`, so
+    /// is converted through its bits.
+    pub fn decode(&self, data: &[u8]) -> Option<f64> {
+        let raw = self.decode_raw(data)?;
+        let raw = if self.signed || self.len < 64 {
+            raw as f64
+        } else {
+            raw as u64 as f64
+        };
+        Some(raw * self.scale + self.offset)
+    }
+
+    /// Inserts a raw value into the payload.
+    ///
+    /// This fails if the value can't be represented in the signal.
+    pub fn encode_raw(&self, data: &mut [u8], raw: i64) -> Result<(), SignalError> {
+        let (min, max) = self.raw_range();
+        if (raw as i128) < min || (raw as i128) > max {
+            return Err(SignalError::ValueOutOfRange);
+        }
+        insert_bits(data, self.start, self.len, self.order, raw as u64)
+    }
+
+    /// Inserts a physical value into the payload, rounded to the nearest
+    /// raw value.
+    ///
+    /// This fails if the value can't be represented in the signal.
+    pub fn encode(&self, data: &mut [u8], value: f64) -> Result<(), SignalError> {
+        let raw = ((value - self.offset) / self.scale).round();
+        let (min, max) = self.raw_range();
+        if !raw.is_finite() || raw < min as f64 || raw > max as f64 {
+            return Err(SignalError::ValueOutOfRange);
+        }
+        insert_bits(data, self.start, self.len, self.order, raw as i128 as u64)
+    }
+
+    /// Gets the range of raw values that fit in the signal.
+    fn raw_range(&self) -> (i128, i128) {
+        let len = u32::from(self.len.clamp(1, 64));
+        if self.signed {
+            (-(1i128 << (len - 1)), (1i128 << (len - 1)) - 1)
+        } else {
+            (0, (1i128 << len) - 1)
+        }
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bits() {
+        let data = [0x12, 0x34, 0x56];
+
+        assert_eq!(
+            Some(0x3412),
+            extract_bits(&data, 0, 16, ByteOrder::LittleEndian)
+        );
+        assert_eq!(
+            Some(0x1234),
+            extract_bits(&data, 7, 16, ByteOrder::BigEndian)
+        );
+        assert_eq!(
+            Some(0x123),
+            extract_bits(&data, 7, 12, ByteOrder::BigEndian)
+        );
+        assert_eq!(
+            Some(0x2),
+            extract_bits(&data, 0, 4, ByteOrder::LittleEndian)
+        );
+        assert_eq!(
+            Some(0x456),
+            extract_bits(&data, 11, 12, ByteOrder::BigEndian)
+        );
+        assert_eq!(None, extract_bits(&data, 16, 9, ByteOrder::LittleEndian));
+        assert_eq!(None, extract_bits(&data, 23, 9, ByteOrder::BigEndian));
+
+        let mut buf = [0xFFu8; 3];
+        insert_bits(&mut buf, 7, 12, ByteOrder::BigEndian, 0x123).unwrap();
+        assert_eq!([0x12, 0x3F, 0xFF], buf);
+        insert_bits(&mut buf, 4, 8, ByteOrder::LittleEndian, 0).unwrap();
+        assert_eq!([0x02, 0x30, 0xFF], buf);
+        assert!(insert_bits(&mut buf, 20, 8, ByteOrder::LittleEndian, 0).is_err());
+    }
+
+    #[test]
+    fn test_signal() {
+        let temp = Signal::new(8, 8, ByteOrder::LittleEndian)
+            .signed()
+            .with_scale(0.5, -10.0);
+
+        let mut data = [0u8; 4];
+        temp.encode(&mut data, -20.0).unwrap();
+        assert_eq!(0xEC, data[1]);
+        assert_eq!(Some(-20), temp.decode_raw(&data));
+        assert_eq!(Some(-20.0), temp.decode(&data));
+        assert_eq!(
+            Err(SignalError::ValueOutOfRange),
+            temp.encode(&mut data, 100.0)
+        );
+
+        let big = Signal::new(7, 64, ByteOrder::BigEndian);
+        let data = [0xFF; 8];
+        assert_eq!(Some(u64::MAX as f64), big.decode(&data));
+    }
+}
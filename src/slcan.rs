@@ -0,0 +1,432 @@
+// socketcan/src/slcan.rs
+//
+// A backend for SLCAN serial adapters.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! A backend for SLCAN serial adapters.
+//!
+//! Many cheap USB-CAN adapters speak SLCAN, the LAWICEL ASCII protocol,
+//! over a serial port. Linux can put these behind a kernel network
+//! interface with `slcand`, but that isn't always available. The
+//! [`SlcanSocket`] talks to such an adapter directly over its serial
+//! device, and implements the [`CanTransmitter`] and [`CanReceiver`]
+//! traits, so it can stand in for a socket in the higher layers of the
+//! library.
+//!
+//! Only classic data and remote frames are supported. The adapter
+//! doesn't report bus errors as frames.
+//!
+//! ```no_run
+//! use socketcan::{
+//!     backend::{CanReceiver, CanTransmitter},
+//!     slcan::{SlcanConfig, SlcanSocket},
+//!     CanFrame, Frame,
+//! };
+//!
+//! let sock = SlcanSocket::open("/dev/ttyACM0", &SlcanConfig::new(500_000)).unwrap();
+//!
+//! let frame = CanFrame::from_raw_id(0x123, &[1, 2, 3]).unwrap();
+//! sock.transmit(&frame).unwrap();
+//!
+//! loop {
+//!     let frame = sock.receive().unwrap();
+//!     println!("{:?}", frame);
+//! }
+//! ```
+
+use crate::{
+    backend::{CanReceiver, CanTransmitter},
+    frame::write_hex_bytes,
+    CanFrame, CanRemoteFrame, EmbeddedFrame, ExtendedId, Frame, Id, IoError, IoErrorKind, IoResult,
+    StandardId,
+};
+use nix::{
+    poll::{poll, PollFd, PollFlags},
+    sys::termios::{self, BaudRate, SetArg},
+};
+use std::{
+    fmt::Write as _,
+    fs::{File, OpenOptions},
+    io::{Read, Write},
+    os::unix::{fs::OpenOptionsExt, io::AsRawFd},
+    path::Path,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+
+/// An error in the SLCAN protocol.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum SlcanError {
+    /// The CAN bitrate has no SLCAN setup command
+    #[error("Unsupported CAN bitrate: {0}")]
+    Bitrate(u32),
+    /// The serial baud rate isn't supported
+    #[error("Unsupported serial baud rate: {0}")]
+    Baud(u32),
+    /// The adapter rejected a command
+    #[error("Command rejected by the adapter")]
+    Rejected,
+    /// A line from the adapter couldn't be parsed
+    #[error("Malformed SLCAN message: {0:?}")]
+    Malformed(String),
+}
+
+impl From<SlcanError> for IoError {
+    fn from(err: SlcanError) -> Self {
+        IoError::new(IoErrorKind::InvalidData, err)
+    }
+}
+
+/// The CAN bitrates with an SLCAN setup command, `S0` through `S8`.
+pub const SLCAN_BITRATES: [u32; 9] = [
+    10_000, 20_000, 50_000, 100_000, 125_000, 250_000, 500_000, 800_000, 1_000_000,
+];
+
+// The command terminator, and the error response
+const CR: u8 = b'\r';
+const BEL: u8 = 0x07;
+
+// ===== Encoding =====
+
+/// Encodes a frame as an SLCAN transmit command, including the
+/// terminating carriage return.
+///
+/// Error frames can't be sent over SLCAN, and give `None`.
+pub fn encode(frame: &CanFrame) -> Option<String> {
+    if frame.is_error_frame() {
+        return None;
+    }
+    let (cmd, id) = match (frame.is_remote_frame(), frame.id()) {
+        (false, Id::Standard(id)) => ('t', format!("{:03X}", id.as_raw())),
+        (false, Id::Extended(id)) => ('T', format!("{:08X}", id.as_raw())),
+        (true, Id::Standard(id)) => ('r', format!("{:03X}", id.as_raw())),
+        (true, Id::Extended(id)) => ('R', format!("{:08X}", id.as_raw())),
+    };
+
+    let mut s = String::with_capacity(1 + id.len() + 1 + 16 + 1);
+    let _ = write!(s, "{}{}{}", cmd, id, frame.dlc());
+    if !frame.is_remote_frame() {
+        let _ = write_hex_bytes(&mut s, frame.data(), true, None);
+    }
+    s.push('\r');
+    Some(s)
+}
+
+/// Decodes a received frame from an SLCAN line, without the terminating
+/// carriage return.
+///
+/// A trailing timestamp, if the adapter adds one, is ignored. Lines with
+/// anything but ASCII, such as noise on the serial line, are malformed.
+pub fn decode(line: &str) -> Result<CanFrame, SlcanError> {
+    let malformed = || SlcanError::Malformed(line.into());
+    if !line.is_ascii() {
+        return Err(malformed());
+    }
+    let num = |s: &str| u32::from_str_radix(s, 16).map_err(|_| malformed());
+
+    let (remote, id_len) = match line.as_bytes().first() {
+        Some(b't') => (false, 3),
+        Some(b'T') => (false, 8),
+        Some(b'r') => (true, 3),
+        Some(b'R') => (true, 8),
+        _ => return Err(malformed()),
+    };
+    let raw_id = num(line.get(1..1 + id_len).ok_or_else(malformed)?)?;
+    let id: Id = match id_len {
+        3 => StandardId::new(raw_id as u16).map(Id::from),
+        _ => ExtendedId::new(raw_id).map(Id::from),
+    }
+    .ok_or_else(malformed)?;
+
+    let dlc_pos = 1 + id_len;
+    let dlc = num(line.get(dlc_pos..dlc_pos + 1).ok_or_else(malformed)?)? as usize;
+    if dlc > 8 {
+        return Err(malformed());
+    }
+
+    if remote {
+        return CanRemoteFrame::new_remote(id, dlc)
+            .map(CanFrame::from)
+            .ok_or_else(malformed);
+    }
+
+    let digits = line
+        .get(dlc_pos + 1..dlc_pos + 1 + 2 * dlc)
+        .ok_or_else(malformed)?;
+    let mut data = [0u8; 8];
+    for (i, b) in data[..dlc].iter_mut().enumerate() {
+        *b = num(&digits[2 * i..2 * i + 2])? as u8;
+    }
+    CanFrame::new(id, &data[..dlc]).ok_or_else(malformed)
+}
+
+// ===== SlcanConfig =====
+
+/// The setup of an SLCAN adapter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlcanConfig {
+    bitrate: u32,
+    baud: u32,
+    listen_only: bool,
+}
+
+impl SlcanConfig {
+    /// Creates a setup for the CAN bitrate, which must be one of
+    /// [`SLCAN_BITRATES`], with a serial port at 115200 baud.
+    pub fn new(bitrate: u32) -> Self {
+        Self {
+            bitrate,
+            baud: 115_200,
+            listen_only: false,
+        }
+    }
+
+    /// Sets the baud rate of the serial port.
+    ///
+    /// This is ignored by most USB adapters, which run at full USB speed.
+    pub fn with_baud(mut self, baud: u32) -> Self {
+        self.baud = baud;
+        self
+    }
+
+    /// Opens the channel in listen-only mode, so that the adapter never
+    /// transmits, not even acknowledgements.
+    pub fn with_listen_only(mut self) -> Self {
+        self.listen_only = true;
+        self
+    }
+
+    fn bitrate_command(&self) -> Result<String, SlcanError> {
+        SLCAN_BITRATES
+            .iter()
+            .position(|&b| b == self.bitrate)
+            .map(|n| format!("S{}\r", n))
+            .ok_or(SlcanError::Bitrate(self.bitrate))
+    }
+
+    fn baud_rate(&self) -> Result<BaudRate, SlcanError> {
+        use BaudRate::*;
+        Ok(match self.baud {
+            9600 => B9600,
+            19200 => B19200,
+            38400 => B38400,
+            57600 => B57600,
+            115_200 => B115200,
+            230_400 => B230400,
+            460_800 => B460800,
+            500_000 => B500000,
+            921_600 => B921600,
+            1_000_000 => B1000000,
+            2_000_000 => B2000000,
+            3_000_000 => B3000000,
+            baud => return Err(SlcanError::Baud(baud)),
+        })
+    }
+}
+
+// ===== SlcanSocket =====
+
+/// A CAN channel on an SLCAN adapter.
+///
+/// The channel is closed when this is dropped.
+#[derive(Debug)]
+pub struct SlcanSocket {
+    port: File,
+    rx_buf: Mutex<Vec<u8>>,
+}
+
+impl SlcanSocket {
+    /// Opens the serial device, sets up the adapter, and opens the CAN
+    /// channel.
+    pub fn open<P: AsRef<Path>>(path: P, config: &SlcanConfig) -> IoResult<Self> {
+        let bitrate_cmd = config.bitrate_command()?;
+        let baud = config.baud_rate()?;
+
+        let port = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NOCTTY)
+            .open(path)?;
+
+        let mut tios = termios::tcgetattr(port.as_raw_fd())?;
+        termios::cfmakeraw(&mut tios);
+        termios::cfsetspeed(&mut tios, baud)?;
+        termios::tcsetattr(port.as_raw_fd(), SetArg::TCSANOW, &tios)?;
+
+        let sock = Self {
+            port,
+            rx_buf: Mutex::new(Vec::new()),
+        };
+
+        // Close the channel in case it was left open, and flush out any
+        // partial command with an empty one.
+        sock.send(b"\r\rC\r")?;
+        sock.drain_responses()?;
+        for cmd in [
+            bitrate_cmd.as_bytes(),
+            if config.listen_only { b"L\r" } else { b"O\r" },
+        ] {
+            sock.send(cmd)?;
+            if sock.drain_responses()? {
+                return Err(SlcanError::Rejected.into());
+            }
+        }
+        Ok(sock)
+    }
+
+    /// Closes the CAN channel. The adapter stops sending and receiving
+    /// frames until it's opened again.
+    pub fn close(self) -> IoResult<()> {
+        self.send(b"C\r")
+    }
+
+    fn send(&self, cmd: &[u8]) -> IoResult<()> {
+        (&self.port).write_all(cmd)
+    }
+
+    // Discards the responses to a setup command, giving the adapter a
+    // moment to send them. Returns whether the command was rejected.
+    fn drain_responses(&self) -> IoResult<bool> {
+        let mut buf = [0u8; 64];
+        let mut rejected = false;
+        let fd = self.port.as_raw_fd();
+        while poll(&mut [PollFd::new(fd, PollFlags::POLLIN)], 50)? > 0 {
+            match (&self.port).read(&mut buf)? {
+                0 => break,
+                n => rejected |= buf[..n].contains(&BEL),
+            }
+        }
+        Ok(rejected)
+    }
+
+    /// Reads lines from the adapter until a frame arrives, or until the
+    /// deadline passes. Malformed lines are logged and skipped.
+    fn read_until(&self, deadline: Option<Instant>) -> IoResult<CanFrame> {
+        let mut rx_buf = self.rx_buf.lock().unwrap();
+        loop {
+            while let Some(pos) = rx_buf.iter().position(|&b| b == CR || b == BEL) {
+                let line: Vec<u8> = rx_buf.drain(..=pos).collect();
+                match line.first() {
+                    // Acknowledgements, of commands and of transmitted frames
+                    Some(&CR) | Some(b'z') | Some(b'Z') => continue,
+                    Some(&BEL) => {
+                        log::warn!("SLCAN command rejected by the adapter");
+                        continue;
+                    }
+                    _ => {
+                        let line = String::from_utf8_lossy(&line[..line.len() - 1]);
+                        match decode(&line) {
+                            Ok(frame) => return Ok(frame),
+                            Err(err) => log::warn!("Skipping SLCAN line: {}", err),
+                        }
+                    }
+                }
+            }
+
+            let timeout = match deadline {
+                Some(deadline) => {
+                    let left = deadline.saturating_duration_since(Instant::now());
+                    left.as_millis().min(i32::MAX as u128) as i32
+                }
+                None => -1,
+            };
+            let pollfd = PollFd::new(self.port.as_raw_fd(), PollFlags::POLLIN);
+            if poll(&mut [pollfd], timeout)? == 0 {
+                return Err(IoErrorKind::TimedOut.into());
+            }
+
+            let mut buf = [0u8; 256];
+            match (&self.port).read(&mut buf)? {
+                0 => return Err(IoErrorKind::UnexpectedEof.into()),
+                n => rx_buf.extend_from_slice(&buf[..n]),
+            }
+        }
+    }
+}
+
+impl Drop for SlcanSocket {
+    fn drop(&mut self) {
+        let _ = self.send(b"C\r");
+    }
+}
+
+impl AsRawFd for SlcanSocket {
+    fn as_raw_fd(&self) -> std::os::unix::io::RawFd {
+        self.port.as_raw_fd()
+    }
+}
+
+impl<F> CanTransmitter<F> for SlcanSocket
+where
+    F: Into<CanFrame> + Clone,
+{
+    fn transmit(&self, frame: &F) -> IoResult<()> {
+        let frame: CanFrame = frame.clone().into();
+        let cmd = encode(&frame)
+            .ok_or_else(|| IoError::new(IoErrorKind::InvalidInput, "error frames can't be sent"))?;
+        self.send(cmd.as_bytes())
+    }
+}
+
+impl CanReceiver for SlcanSocket {
+    type Frame = CanFrame;
+
+    fn receive(&self) -> IoResult<CanFrame> {
+        self.read_until(None)
+    }
+
+    fn receive_timeout(&self, timeout: Duration) -> IoResult<CanFrame> {
+        self.read_until(Some(Instant::now() + timeout))
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_decode() {
+        let frame = CanFrame::from_raw_id(0x123, &[0x11, 0x22, 0xAB]).unwrap();
+        assert_eq!("t12331122AB\r", encode(&frame).unwrap());
+        assert_eq!(frame, decode("t12331122AB").unwrap());
+
+        let frame = CanFrame::from_raw_id(0x1ABCDEF0, &[]).unwrap();
+        assert_eq!("T1ABCDEF00\r", encode(&frame).unwrap());
+        assert_eq!(frame, decode("T1ABCDEF00").unwrap());
+
+        let frame = CanFrame::remote_from_raw_id(0x7FF, 4).unwrap();
+        assert_eq!("r7FF4\r", encode(&frame).unwrap());
+        assert_eq!(frame, decode("r7FF4").unwrap());
+
+        // With a timestamp
+        assert_eq!(0x100, decode("t10010012EA6").unwrap().raw_id());
+
+        assert!(decode("t1233112").is_err());
+        assert!(decode("t8001").is_err());
+        assert!(decode("x").is_err());
+        assert!(decode("t1232A\u{FFFD}").is_err());
+        assert!(decode("t12320\u{e9}").is_err());
+    }
+
+    #[test]
+    fn test_config() {
+        assert_eq!("S6\r", SlcanConfig::new(500_000).bitrate_command().unwrap());
+        assert_eq!(
+            Err(SlcanError::Bitrate(33_333)),
+            SlcanConfig::new(33_333).bitrate_command()
+        );
+        assert!(SlcanConfig::new(500_000)
+            .with_baud(1234)
+            .baud_rate()
+            .is_err());
+    }
+}
@@ -0,0 +1,369 @@
+// socketcan/src/sniffer.rs
+//
+// A cansniffer-style monitor of payload changes.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! A monitor of the payload changes of each ID, like `cansniffer`.
+//!
+//! The [`DeltaMonitor`] keeps the most recent payload of each ID, and for
+//! each frame reports which bytes and bits changed since the previous frame
+//! with the same ID, as a [`Delta`]. It also remembers when each byte last
+//! changed, so a display can highlight the changes and let them fade out
+//! over the decay time, and drops the IDs that go quiet.
+//!
+//! Bits that toggle all the time, like counters and checksums, hide the
+//! interesting changes. They can be masked out per ID, like the "notch"
+//! of `cansniffer`, so that changes to them aren't reported.
+//!
+//! ```no_run
+//! use socketcan::{sniffer::DeltaMonitor, CanFdSocket, Socket};
+//! use std::time::{Duration, SystemTime};
+//!
+//! let sock = CanFdSocket::open("vcan0").unwrap();
+//! let mut mon = DeltaMonitor::new().with_decay(Duration::from_secs(2));
+//!
+//! loop {
+//!     let frame = sock.read_frame().unwrap();
+//!     let t_us = SystemTime::now()
+//!         .duration_since(SystemTime::UNIX_EPOCH)
+//!         .unwrap()
+//!         .as_micros() as u64;
+//!     if let Some(delta) = mon.update(t_us, &frame) {
+//!         println!("{:?}: bytes {:?} changed", delta.id, delta.changed_bytes().collect::<Vec<_>>());
+//!     }
+//! }
+//! ```
+
+use crate::{capture::Record, frame::CANFD_MAX_DLEN, CanAnyFrame, EmbeddedFrame, Frame, Id};
+use std::{collections::BTreeMap, time::Duration};
+
+/// The default time for a change highlight to fade out
+pub const DEFAULT_DECAY: Duration = Duration::from_secs(1);
+
+// ===== Delta =====
+
+/// The changes in the payload of an ID from one frame to the next.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Delta {
+    /// The CAN ID
+    pub id: Id,
+    /// The timestamp of the frame, in microseconds
+    pub t_us: u64,
+    /// The bits that changed in each byte of the new payload. A byte past
+    /// the end of the previous payload has all its bits set.
+    pub changed: Vec<u8>,
+    /// Whether this is the first frame with the ID
+    pub is_new: bool,
+    /// Whether the length of the payload changed
+    pub len_changed: bool,
+}
+
+impl Delta {
+    /// Iterates over the indexes of the bytes that changed.
+    pub fn changed_bytes(&self) -> impl Iterator<Item = usize> + '_ {
+        self.changed
+            .iter()
+            .enumerate()
+            .filter(|(_, bits)| **bits != 0)
+            .map(|(i, _)| i)
+    }
+
+    /// Determines if a bit changed, counting the bits from the LSB of the
+    /// first byte.
+    pub fn is_bit_changed(&self, bit: usize) -> bool {
+        self.changed
+            .get(bit / 8)
+            .is_some_and(|b| b & (1 << (bit % 8)) != 0)
+    }
+}
+
+// ===== SniffedId =====
+
+/// The state of an ID in the monitor.
+#[derive(Debug, Clone)]
+pub struct SniffedId {
+    /// The CAN ID
+    pub id: Id,
+    /// The number of frames received with the ID
+    pub count: u64,
+    /// The timestamp of the most recent frame, in microseconds
+    pub last_us: u64,
+    /// The time between the two most recent frames, in microseconds
+    pub cycle_us: Option<u64>,
+    data: [u8; CANFD_MAX_DLEN],
+    len: usize,
+    changed_us: [Option<u64>; CANFD_MAX_DLEN],
+    changed_bits: [u8; CANFD_MAX_DLEN],
+}
+
+impl SniffedId {
+    /// Gets the most recent payload.
+    pub fn data(&self) -> &[u8] {
+        &self.data[..self.len]
+    }
+
+    /// Gets the time that a byte last changed, in microseconds.
+    pub fn changed_us(&self, byte: usize) -> Option<u64> {
+        self.changed_us.get(byte).copied().flatten()
+    }
+
+    /// Gets the bits of a byte that changed the last time it changed.
+    pub fn changed_bits(&self, byte: usize) -> u8 {
+        self.changed_bits.get(byte).copied().unwrap_or(0)
+    }
+
+    /// Gets the highlight of a byte at a time: 1.0 when it just changed,
+    /// fading linearly to zero over the decay time.
+    pub fn highlight(&self, byte: usize, now_us: u64, decay: Duration) -> f32 {
+        let decay_us = decay.as_micros() as u64;
+        match self.changed_us(byte) {
+            Some(t) if decay_us > 0 => {
+                let age = now_us.saturating_sub(t);
+                1.0 - (age.min(decay_us) as f32 / decay_us as f32)
+            }
+            _ => 0.0,
+        }
+    }
+}
+
+// ===== DeltaMonitor =====
+
+/// A monitor of the changes in the payload of each ID on the bus.
+#[derive(Debug, Clone)]
+pub struct DeltaMonitor {
+    ids: BTreeMap<Id, SniffedId>,
+    masks: BTreeMap<Id, Vec<u8>>,
+    decay: Duration,
+    timeout: Option<Duration>,
+}
+
+impl Default for DeltaMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DeltaMonitor {
+    /// Creates a new, empty, monitor.
+    pub fn new() -> Self {
+        Self {
+            ids: BTreeMap::new(),
+            masks: BTreeMap::new(),
+            decay: DEFAULT_DECAY,
+            timeout: None,
+        }
+    }
+
+    /// Sets the time for a change highlight to fade out.
+    pub fn with_decay(mut self, decay: Duration) -> Self {
+        self.decay = decay;
+        self
+    }
+
+    /// Sets the time after which an ID that hasn't been seen is dropped by
+    /// [`expire`](Self::expire).
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Gets the time for a change highlight to fade out.
+    pub fn decay(&self) -> Duration {
+        self.decay
+    }
+
+    /// Masks out bits of an ID, so that their changes are ignored. Each set
+    /// bit in the mask is ignored.
+    pub fn set_mask(&mut self, id: impl Into<Id>, mask: &[u8]) {
+        self.masks.insert(id.into(), mask.to_vec());
+    }
+
+    /// Removes the mask of an ID.
+    pub fn clear_mask(&mut self, id: impl Into<Id>) {
+        self.masks.remove(&id.into());
+    }
+
+    /// Updates the monitor with a frame, returning the changes in its
+    /// payload, if any.
+    ///
+    /// Error frames are ignored.
+    pub fn update(&mut self, t_us: u64, frame: &CanAnyFrame) -> Option<Delta> {
+        if frame.is_error_frame() {
+            return None;
+        }
+        let (id, data) = (frame.id(), frame.data());
+        let mask = self.masks.get(&id).map(Vec::as_slice).unwrap_or(&[]);
+
+        let entry = self.ids.entry(id).or_insert_with(|| SniffedId {
+            id,
+            count: 0,
+            last_us: t_us,
+            cycle_us: None,
+            data: [0; CANFD_MAX_DLEN],
+            len: 0,
+            changed_us: [None; CANFD_MAX_DLEN],
+            changed_bits: [0; CANFD_MAX_DLEN],
+        });
+
+        let is_new = entry.count == 0;
+        if !is_new {
+            entry.cycle_us = Some(t_us.saturating_sub(entry.last_us));
+        }
+        entry.count += 1;
+        entry.last_us = t_us;
+
+        let changed: Vec<u8> = data
+            .iter()
+            .enumerate()
+            .map(|(i, b)| {
+                let bits = if i < entry.len {
+                    b ^ entry.data[i]
+                } else {
+                    0xFF
+                };
+                bits & !mask.get(i).copied().unwrap_or(0)
+            })
+            .collect();
+
+        for (i, bits) in changed.iter().enumerate() {
+            if *bits != 0 {
+                entry.changed_us[i] = Some(t_us);
+                entry.changed_bits[i] = *bits;
+            }
+        }
+
+        let len_changed = !is_new && data.len() != entry.len;
+        entry.data[..data.len()].copy_from_slice(data);
+        entry.len = data.len();
+
+        (is_new || len_changed || changed.iter().any(|b| *b != 0)).then_some(Delta {
+            id,
+            t_us,
+            changed,
+            is_new,
+            len_changed,
+        })
+    }
+
+    /// Updates the monitor with a record from a capture log.
+    pub fn update_record(&mut self, rec: &Record) -> Option<Delta> {
+        self.update(rec.t_us, &rec.frame)
+    }
+
+    /// Gets the state of an ID.
+    pub fn get(&self, id: impl Into<Id>) -> Option<&SniffedId> {
+        self.ids.get(&id.into())
+    }
+
+    /// Iterates over the state of all the IDs, in order of bus priority.
+    pub fn iter(&self) -> impl Iterator<Item = &SniffedId> {
+        self.ids.values()
+    }
+
+    /// Gets the highlight of each byte of an ID at a time, from 1.0 when
+    /// it just changed, fading to zero over the decay time.
+    pub fn highlights(&self, id: impl Into<Id>, now_us: u64) -> Vec<f32> {
+        match self.get(id) {
+            Some(entry) => (0..entry.len)
+                .map(|i| entry.highlight(i, now_us, self.decay))
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Removes the IDs that haven't been seen within the timeout, returning
+    /// them.
+    pub fn expire(&mut self, now_us: u64) -> Vec<Id> {
+        let timeout_us = match self.timeout {
+            Some(timeout) => timeout.as_micros() as u64,
+            None => return Vec::new(),
+        };
+        let expired: Vec<Id> = self
+            .ids
+            .values()
+            .filter(|e| now_us.saturating_sub(e.last_us) > timeout_us)
+            .map(|e| e.id)
+            .collect();
+        for id in &expired {
+            self.ids.remove(id);
+        }
+        expired
+    }
+
+    /// Removes all the IDs from the monitor.
+    ///
+    /// The masks are kept.
+    pub fn clear(&mut self) {
+        self.ids.clear();
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CanFrame, Frame, StandardId};
+
+    fn frame(id: u32, data: &[u8]) -> CanAnyFrame {
+        CanFrame::from_raw_id(id, data).unwrap().into()
+    }
+
+    #[test]
+    fn test_delta() {
+        let mut mon = DeltaMonitor::new().with_decay(Duration::from_millis(100));
+        let id = StandardId::new(0x100).unwrap();
+
+        let delta = mon.update(0, &frame(0x100, &[1, 2, 3])).unwrap();
+        assert!(delta.is_new);
+        assert_eq!(vec![0, 1, 2], delta.changed_bytes().collect::<Vec<_>>());
+
+        assert!(mon.update(10_000, &frame(0x100, &[1, 2, 3])).is_none());
+
+        let delta = mon.update(20_000, &frame(0x100, &[1, 0x82, 3])).unwrap();
+        assert!(!delta.is_new && !delta.len_changed);
+        assert_eq!(vec![0, 0x80, 0], delta.changed);
+        assert!(delta.is_bit_changed(15));
+        assert!(!delta.is_bit_changed(14));
+
+        let entry = mon.get(id).unwrap();
+        assert_eq!(3, entry.count);
+        assert_eq!(Some(10_000), entry.cycle_us);
+        assert_eq!(0x80, entry.changed_bits(1));
+
+        let hl = mon.highlights(id, 70_000);
+        assert_eq!(3, hl.len());
+        assert!((hl[1] - 0.5).abs() < 1e-6);
+        assert!((hl[0] - 0.3).abs() < 1e-6);
+        assert_eq!(vec![0.0; 3], mon.highlights(id, 120_000));
+
+        let delta = mon.update(30_000, &frame(0x100, &[1, 0x82])).unwrap();
+        assert!(delta.len_changed);
+    }
+
+    #[test]
+    fn test_mask_expire() {
+        let mut mon = DeltaMonitor::new().with_timeout(Duration::from_millis(50));
+        mon.set_mask(StandardId::new(0x200).unwrap(), &[0x0F]);
+
+        mon.update(0, &frame(0x200, &[0x00, 0]));
+        assert!(mon.update(1000, &frame(0x200, &[0x0A, 0])).is_none());
+        assert_eq!(
+            vec![0x10, 0],
+            mon.update(2000, &frame(0x200, &[0x1A, 0])).unwrap().changed
+        );
+
+        mon.update(40_000, &frame(0x300, &[]));
+        assert_eq!(
+            vec![Id::from(StandardId::new(0x200).unwrap())],
+            mon.expire(60_000)
+        );
+        assert_eq!(1, mon.iter().count());
+    }
+}
@@ -13,10 +13,19 @@
 
 use crate::{
     as_bytes, as_bytes_mut,
-    frame::{can_frame_default, canfd_frame_default, AsPtr, CAN_ERR_MASK},
-    CanAddr, CanAnyFrame, CanFdFrame, CanFrame, CanRawFrame, IoError, IoErrorKind, IoResult,
+    filter::CAN_RAW_FILTER_MAX,
+    frame::{
+        can_frame_default, canfd_frame_default, canxl_frame_default, AsPtr, FdConversion,
+        CAN_EFF_FLAG, CAN_ERR_MASK,
+    },
+    pool::{FramePool, Pooled},
+    timestamp::Timestamped,
+    CanAddr, CanAnyFrame, CanFdFrame, CanFrame, CanId, CanRawFrame, CanXlAnyFrame, CanXlFrame,
+    IoError, IoErrorKind, IoResult,
 };
-use libc::{canid_t, socklen_t, AF_CAN, EINPROGRESS};
+use libc::{canid_t, socklen_t, AF_CAN, CANXL_HDR_SIZE, CANXL_XLF, EINPROGRESS};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 use socket2::SockAddr;
 use std::{
     fmt,
@@ -27,14 +36,44 @@ use std::{
         unix::io::{AsFd, AsRawFd, BorrowedFd, IntoRawFd, OwnedFd, RawFd},
     },
     ptr,
-    time::Duration,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::{Duration, SystemTime},
 };
 
 pub use libc::{
-    CANFD_MTU, CAN_MTU, CAN_RAW, CAN_RAW_ERR_FILTER, CAN_RAW_FD_FRAMES, CAN_RAW_FILTER,
-    CAN_RAW_JOIN_FILTERS, CAN_RAW_LOOPBACK, CAN_RAW_RECV_OWN_MSGS, SOL_CAN_BASE, SOL_CAN_RAW,
+    CANFD_MTU, CANXL_MTU, CAN_MTU, CAN_RAW, CAN_RAW_ERR_FILTER, CAN_RAW_FD_FRAMES, CAN_RAW_FILTER,
+    CAN_RAW_JOIN_FILTERS, CAN_RAW_LOOPBACK, CAN_RAW_RECV_OWN_MSGS, CAN_RAW_XL_FRAMES, SOL_CAN_BASE,
+    SOL_CAN_RAW,
 };
 
+/// The ioctl to get the kernel receive timestamp of the last frame read,
+/// as a `timespec`. This isn't exported by libc.
+const SIOCGSTAMPNS: libc::c_ulong = 0x8907;
+
+/// The socket option for the busy-poll time on receive, in microseconds.
+/// This isn't exported by libc for all Linux targets.
+const SO_BUSY_POLL: c_int = 46;
+
+/// The environment variable with the interface opened by
+/// [`Socket::open_default`].
+pub const CAN_INTERFACE_ENV: &str = "CAN_INTERFACE";
+
+/// The interface opened by [`Socket::open_default`] when the environment
+/// doesn't name one.
+pub const DEFAULT_INTERFACE: &str = "can0";
+
+/// The most frames read in one batch by
+/// [`CanFdSocket::read_frames_into`].
+pub const RECV_BATCH: usize = 32;
+
+// Control message space for a receive timestamp, aligned for a cmsghdr
+#[repr(C, align(8))]
+#[derive(Clone, Copy)]
+struct CmsgBuf([u8; 64]);
+
 /// Check an error return value for timeouts.
 ///
 /// Due to the fact that timeouts are reported as errors, calling `read_frame`
@@ -55,11 +94,9 @@ impl ShouldRetry for IoError {
             // returned when a timeout occurs. the stdlib already maps EAGAIN
             // and EWOULDBLOCK os WouldBlock
             IoErrorKind::WouldBlock => true,
-            // however, EINPROGRESS is also valid
-            IoErrorKind::Other => {
-                matches!(self.raw_os_error(), Some(errno) if errno == EINPROGRESS)
-            }
-            _ => false,
+            // however, EINPROGRESS is also valid. Check the errno directly,
+            // since the kind it maps to depends on the Rust version.
+            _ => matches!(self.raw_os_error(), Some(errno) if errno == EINPROGRESS),
         }
     }
 }
@@ -75,6 +112,63 @@ impl<E: fmt::Debug> ShouldRetry for IoResult<E> {
 
 // ===== Private local helper functions =====
 
+/// Creates the error for a read that returned an unexpected number of bytes.
+///
+/// The read itself succeeded, so there is no OS error to report.
+fn frame_size_error(n: usize) -> IoError {
+    IoError::new(
+        IoErrorKind::InvalidData,
+        format!("unexpected CAN frame size: {} bytes", n),
+    )
+}
+
+/// Converts `n` bytes read into an FD frame buffer to either type of frame.
+fn any_frame_from_raw(fdframe: &libc::canfd_frame, n: usize) -> IoResult<CanAnyFrame> {
+    match n {
+        // If we only get 'can_frame' number of bytes, then the return is,
+        // by definition, a can_frame, so we just copy the bytes into the
+        // proper type.
+        CAN_MTU => {
+            let mut frame = can_frame_default();
+            as_bytes_mut(&mut frame)[..CAN_MTU].copy_from_slice(&as_bytes(fdframe)[..CAN_MTU]);
+            Ok(CanFrame::from(frame).into())
+        }
+        CANFD_MTU => Ok(CanFdFrame::from(*fdframe).into()),
+        n => Err(frame_size_error(n)),
+    }
+}
+
+/// Converts a frame for writing by the policy, failing if it can't be.
+fn convert_for_write(conv: FdConversion, frame: CanAnyFrame) -> IoResult<CanAnyFrame> {
+    conv.convert(frame).ok_or_else(|| {
+        IoError::new(
+            IoErrorKind::InvalidInput,
+            "frame can't be converted by the socket's FD conversion policy",
+        )
+    })
+}
+
+/// Gets the receive timestamp from the control messages of a received
+/// message, if it has one.
+fn cmsg_timestamp(hdr: &libc::msghdr) -> Option<SystemTime> {
+    // SAFETY: The header and its control buffer were filled in by the
+    // kernel, and the macros stay within the buffer.
+    unsafe {
+        let mut cmsg = libc::CMSG_FIRSTHDR(hdr);
+        while !cmsg.is_null() {
+            if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_TIMESTAMPNS
+            {
+                let ts = ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const libc::timespec);
+                return Some(
+                    SystemTime::UNIX_EPOCH + Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32),
+                );
+            }
+            cmsg = libc::CMSG_NXTHDR(hdr, cmsg);
+        }
+    }
+    None
+}
+
 /// Tries to open the CAN socket by the interface number.
 fn raw_open_socket(addr: &CanAddr) -> IoResult<socket2::Socket> {
     let af_can = socket2::Domain::from(AF_CAN);
@@ -151,6 +245,38 @@ pub fn set_socket_option_mult<T>(
     }
 }
 
+/// Gets the names of the CAN interfaces on the system, sorted, from sysfs.
+fn can_interfaces() -> Vec<String> {
+    let mut names: Vec<String> = std::fs::read_dir("/sys/class/net")
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|entry| {
+            std::fs::read_to_string(entry.path().join("type"))
+                .is_ok_and(|t| t.trim() == libc::ARPHRD_CAN.to_string())
+        })
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .collect();
+    names.sort();
+    names
+}
+
+/// Gets the name of the interface for [`Socket::open_default`], and
+/// whether it came from the environment.
+fn default_interface() -> (String, bool) {
+    match std::env::var(CAN_INTERFACE_ENV) {
+        Ok(name) if !name.trim().is_empty() => (name.trim().into(), true),
+        _ => {
+            let names = can_interfaces();
+            let name = match names.iter().any(|name| name == DEFAULT_INTERFACE) {
+                true => DEFAULT_INTERFACE,
+                false => names.first().map_or(DEFAULT_INTERFACE, String::as_str),
+            };
+            (name.into(), false)
+        }
+    }
+}
+
 // ===== Common 'Socket' trait =====
 
 /// Common trait for SocketCAN sockets.
@@ -170,6 +296,40 @@ pub trait Socket: AsRawFd {
         Self::open_addr(&addr)
     }
 
+    /// Open the default CAN device.
+    ///
+    /// This opens the interface named by the `CAN_INTERFACE` environment
+    /// variable, which can also be an [alias](crate::alias). If it isn't
+    /// set, this opens "can0", or if there's no such interface, the first
+    /// CAN interface on the system. This saves examples, tests, and small
+    /// tools from having to take the interface as an argument.
+    ///
+    /// If the interface can't be opened, the error lists the CAN
+    /// interfaces that are available.
+    fn open_default() -> IoResult<Self>
+    where
+        Self: Sized,
+    {
+        let (ifname, from_env) = default_interface();
+        Self::open(&ifname).map_err(|err| {
+            let available = match can_interfaces() {
+                names if names.is_empty() => "none".to_string(),
+                names => names.join(", "),
+            };
+            let source = match from_env {
+                true => format!(" (from {})", CAN_INTERFACE_ENV),
+                false => String::new(),
+            };
+            IoError::new(
+                err.kind(),
+                format!(
+                    "can't open CAN interface '{}'{}: {}; available interfaces: {}",
+                    ifname, source, err, available
+                ),
+            )
+        })
+    }
+
     /// Open CAN device by interface number.
     ///
     /// Opens a CAN device by kernel interface number.
@@ -246,6 +406,16 @@ pub trait Socket: AsRawFd {
     /// Blocking read a single can frame.
     fn read_frame(&self) -> IoResult<Self::FrameType>;
 
+    /// Blocking read of the next frame straight into the buffer, in the
+    /// kernel's memory layout, returning the number of bytes read.
+    ///
+    /// This doesn't allocate or copy, for latency-sensitive loops that
+    /// decode the frame in place. As with any datagram, if the buffer is
+    /// too small for the frame, the rest of it is discarded.
+    fn read_raw(&self, buf: &mut [u8]) -> IoResult<usize> {
+        self.as_raw_socket().read(buf)
+    }
+
     /// Blocking read a single can frame with timeout.
     fn read_frame_timeout(&self, timeout: Duration) -> IoResult<Self::FrameType> {
         use nix::poll::{poll, PollFd, PollFlags};
@@ -257,6 +427,28 @@ pub trait Socket: AsRawFd {
         }
     }
 
+    /// Blocking read a single can frame, along with the time that the
+    /// kernel received it.
+    ///
+    /// Note that reading a frame and retrieving the timestamp requires two
+    /// consecutive syscalls. To avoid race conditions, exclusive access
+    /// to the socket is enforced by requiring a `&mut self`.
+    fn read_frame_timestamped(&mut self) -> IoResult<Timestamped<Self::FrameType>> {
+        let frame = self.read_frame()?;
+
+        let mut ts = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        let ret = unsafe { libc::ioctl(self.as_raw_fd(), SIOCGSTAMPNS as _, &mut ts) };
+        if ret == -1 {
+            return Err(IoError::last_os_error());
+        }
+
+        let time = SystemTime::UNIX_EPOCH + Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32);
+        Ok(Timestamped::new(frame, time))
+    }
+
     /// Write a single can frame.
     ///
     /// Note that this function can fail with an `EAGAIN` error or similar.
@@ -267,13 +459,13 @@ pub trait Socket: AsRawFd {
     /// Writes a normal CAN 2.0 frame to the socket.
     fn write_frame<F>(&self, frame: &F) -> IoResult<()>
     where
-        F: Into<Self::FrameType> + AsPtr;
+        F: Into<Self::FrameType> + AsPtr + Clone;
 
     /// Blocking write a single can frame, retrying until it gets sent
     /// successfully.
     fn write_frame_insist<F>(&self, frame: &F) -> IoResult<()>
     where
-        F: Into<Self::FrameType> + AsPtr,
+        F: Into<Self::FrameType> + AsPtr + Clone,
     {
         loop {
             match self.write_frame(frame) {
@@ -438,35 +630,160 @@ pub trait SocketOptions: AsRawFd {
         let join_filters = c_int::from(enabled);
         self.set_socket_option(SOL_CAN_RAW, CAN_RAW_JOIN_FILTERS, &join_filters)
     }
-}
 
-// TODO: We need to restore this, but preferably with TIMESTAMPING
+    /// Sets the time to busy-poll the device on a blocking receive, when
+    /// no frame is waiting, or zero to disable it (the default).
+    ///
+    /// Rather than going to sleep and waiting for the interrupt and the
+    /// scheduler to wake the thread, the kernel polls the driver's receive
+    /// queue for up to this long, which can shave tens of microseconds off
+    /// the latency of each frame. The cost is a CPU core spinning for the
+    /// whole time, and it only has an effect on drivers that support NAPI
+    /// busy polling. Otherwise the option is accepted, and ignored.
+    ///
+    /// The time is set in whole microseconds. Raising it above the
+    /// system-wide `net.core.busy_read` value requires `CAP_NET_ADMIN`.
+    fn set_busy_poll(&self, timeout: Duration) -> IoResult<()> {
+        let usecs = c_int::try_from(timeout.as_micros()).unwrap_or(c_int::MAX);
+        self.set_socket_option(libc::SOL_SOCKET, SO_BUSY_POLL, &usecs)
+    }
 
-/*
-impl CanSocket {
+    /// Gets the time to busy-poll the device on a blocking receive.
+    fn busy_poll(&self) -> IoResult<Duration> {
+        let mut usecs: c_int = 0;
+        let mut len = size_of::<c_int>() as socklen_t;
+        let ret = unsafe {
+            libc::getsockopt(
+                self.as_raw_fd(),
+                libc::SOL_SOCKET,
+                SO_BUSY_POLL,
+                &mut usecs as *mut _ as *mut c_void,
+                &mut len,
+            )
+        };
+
+        match ret {
+            0 => Ok(Duration::from_micros(usecs as u64)),
+            _ => Err(IoError::last_os_error()),
+        }
+    }
 
-    /// Blocking read a single can frame with timestamp
+    /// Gets the CAN ID filters currently set on the socket.
     ///
-    /// Note that reading a frame and retrieving the timestamp requires two
-    /// consecutive syscalls. To avoid race conditions, exclusive access
-    /// to the socket is enforce through requiring a `mut &self`.
-    pub fn read_frame_with_timestamp(&mut self) -> IoResult<(CanFrame, time::SystemTime)> {
-        let frame = self.read_frame()?;
+    /// A new socket has a single filter that accepts all frames.
+    fn filters(&self) -> IoResult<Vec<CanFilter>> {
+        let mut filters = vec![
+            libc::can_filter {
+                can_id: 0,
+                can_mask: 0
+            };
+            CAN_RAW_FILTER_MAX
+        ];
+        let mut len = size_of_val(&filters[..]) as socklen_t;
+        let ret = unsafe {
+            libc::getsockopt(
+                self.as_raw_fd(),
+                SOL_CAN_RAW,
+                CAN_RAW_FILTER,
+                filters.as_mut_ptr() as *mut c_void,
+                &mut len,
+            )
+        };
+
+        match ret {
+            0 => {
+                filters.truncate(len as usize / size_of::<libc::can_filter>());
+                Ok(filters.into_iter().map(CanFilter::from).collect())
+            }
+            _ => Err(IoError::last_os_error()),
+        }
+    }
 
-        let mut ts = timespec { tv_sec: 0, tv_nsec: 0 };
+    /// Gets the error mask currently set on the socket.
+    fn error_filter(&self) -> IoResult<u32> {
+        let mut mask: u32 = 0;
+        let mut len = size_of::<u32>() as socklen_t;
         let ret = unsafe {
-            libc::ioctl(self.fd, SIOCGSTAMPNS as c_ulong, &mut ts as *mut timespec)
+            libc::getsockopt(
+                self.as_raw_fd(),
+                SOL_CAN_RAW,
+                CAN_RAW_ERR_FILTER,
+                &mut mask as *mut _ as *mut c_void,
+                &mut len,
+            )
         };
 
-        if ret == -1 {
-            return Err(IoError::last_os_error());
+        match ret {
+            0 => Ok(mask),
+            _ => Err(IoError::last_os_error()),
         }
+    }
+
+    /// Stops the socket receiving frames, until the returned guard is
+    /// resumed or dropped.
+    ///
+    /// This swaps in a filter that drops all frames, and an empty error
+    /// mask, and the guard puts back the filters and error mask that were
+    /// set before, so an application can silence reception through a
+    /// critical section without having to rebuild its filters afterwards.
+    /// Frames that were already queued on the socket can still be read
+    /// while it's paused.
+    fn pause(&self) -> IoResult<Paused<'_, Self>>
+    where
+        Self: Sized,
+    {
+        let filters = self.filters()?;
+        let error_mask = self.error_filter()?;
+        self.set_filter_drop_all()?;
+        self.set_error_filter_drop_all()?;
+        Ok(Paused {
+            sock: self,
+            filters,
+            error_mask,
+            resumed: false,
+        })
+    }
+}
+
+/// A guard that keeps a socket paused, from [`SocketOptions::pause`].
+///
+/// The socket's filters are restored when the guard is resumed, or
+/// dropped.
+#[derive(Debug)]
+#[must_use = "the socket resumes as soon as the guard is dropped"]
+pub struct Paused<'a, S: SocketOptions> {
+    sock: &'a S,
+    filters: Vec<CanFilter>,
+    error_mask: u32,
+    resumed: bool,
+}
 
-        Ok((frame, system_time_from_timespec(ts)))
+impl<S: SocketOptions> Paused<'_, S> {
+    /// Resumes reception, restoring the socket's filters and error mask.
+    ///
+    /// Unlike dropping the guard, this reports any error doing so.
+    pub fn resume(mut self) -> IoResult<()> {
+        self.resumed = true;
+        self.restore()
+    }
+
+    fn restore(&self) -> IoResult<()> {
+        if self.filters.is_empty() {
+            self.sock.set_filter_drop_all()?;
+        } else {
+            self.sock.set_filters(&self.filters)?;
+        }
+        self.sock.set_error_filter(self.error_mask)
     }
+}
 
+impl<S: SocketOptions> Drop for Paused<'_, S> {
+    fn drop(&mut self) {
+        if !self.resumed {
+            let _ = self.restore();
+        }
+    }
 }
-*/
 
 // ===== CanSocket =====
 
@@ -481,15 +798,61 @@ impl CanSocket {
 /// (file) descriptor.
 #[allow(missing_copy_implementations)]
 #[derive(Debug)]
-pub struct CanSocket(socket2::Socket);
+pub struct CanSocket {
+    sock: socket2::Socket,
+    fd_conversion: FdConversion,
+}
 
 impl CanSocket {
+    /// Sets the policy for writing FD frames with [`write_any_frame`].
+    ///
+    /// With [`FdConversion::ToClassic`], FD frames with up to 8 bytes of
+    /// data are sent as classic frames. Otherwise they are rejected, as
+    /// they can't be sent on a classic socket.
+    ///
+    /// [`write_any_frame`]: CanSocket::write_any_frame
+    pub fn set_fd_conversion(&mut self, conv: FdConversion) {
+        self.fd_conversion = conv;
+    }
+
+    /// Sets the policy for writing FD frames, as a builder.
+    pub fn with_fd_conversion(mut self, conv: FdConversion) -> Self {
+        self.set_fd_conversion(conv);
+        self
+    }
+
+    /// Gets the policy for writing FD frames.
+    pub fn fd_conversion(&self) -> FdConversion {
+        self.fd_conversion
+    }
+
+    /// Writes either type of frame to the socket, converting an FD frame
+    /// to a classic one by the socket's [`FdConversion`] policy.
+    ///
+    /// This fails with `InvalidInput` for an FD frame that isn't, or
+    /// can't be, converted.
+    pub fn write_any_frame(&self, frame: &CanAnyFrame) -> IoResult<()> {
+        match convert_for_write(self.fd_conversion, *frame)? {
+            CanAnyFrame::Fd(_) => Err(IoError::new(
+                IoErrorKind::InvalidInput,
+                "FD frame can't be written to a classic socket",
+            )),
+            frame => self.as_raw_socket().write_all(frame.as_bytes()),
+        }
+    }
+
     /// Reads a low-level libc `can_frame` from the socket.
     pub fn read_raw_frame(&self) -> IoResult<libc::can_frame> {
         let mut frame = can_frame_default();
-        self.as_raw_socket().read_exact(as_bytes_mut(&mut frame))?;
+        self.read_raw_frame_into(&mut frame)?;
         Ok(frame)
     }
+
+    /// Reads a low-level libc `can_frame` from the socket, straight into
+    /// the caller's frame, with no intermediate copy.
+    pub fn read_raw_frame_into(&self, frame: &mut libc::can_frame) -> IoResult<()> {
+        self.as_raw_socket().read_exact(as_bytes_mut(frame))
+    }
 }
 
 impl Socket for CanSocket {
@@ -499,23 +862,26 @@ impl Socket for CanSocket {
     /// Opens the socket by interface index.
     fn open_addr(addr: &CanAddr) -> IoResult<Self> {
         let sock = raw_open_socket(addr)?;
-        Ok(Self(sock))
+        Ok(Self {
+            sock,
+            fd_conversion: FdConversion::default(),
+        })
     }
 
     /// Gets a shared reference to the underlying socket object
     fn as_raw_socket(&self) -> &socket2::Socket {
-        &self.0
+        &self.sock
     }
 
     /// Gets a mutable reference to the underlying socket object
     fn as_raw_socket_mut(&mut self) -> &mut socket2::Socket {
-        &mut self.0
+        &mut self.sock
     }
 
     /// Writes a normal CAN 2.0 frame to the socket.
     fn write_frame<F>(&self, frame: &F) -> IoResult<()>
     where
-        F: Into<CanFrame> + AsPtr,
+        F: Into<CanFrame> + AsPtr + Clone,
     {
         self.as_raw_socket().write_all(frame.as_bytes())
     }
@@ -532,41 +898,44 @@ impl SocketOptions for CanSocket {}
 // Has no effect: #[deprecated(since = "3.1", note = "Use AsFd::as_fd() instead.")]
 impl AsRawFd for CanSocket {
     fn as_raw_fd(&self) -> RawFd {
-        self.0.as_raw_fd()
+        self.sock.as_raw_fd()
     }
 }
 
 impl From<OwnedFd> for CanSocket {
     fn from(fd: OwnedFd) -> Self {
-        Self(socket2::Socket::from(fd))
+        Self {
+            sock: socket2::Socket::from(fd),
+            fd_conversion: FdConversion::default(),
+        }
     }
 }
 
 impl IntoRawFd for CanSocket {
     fn into_raw_fd(self) -> RawFd {
-        self.0.into_raw_fd()
+        self.sock.into_raw_fd()
     }
 }
 
 impl AsFd for CanSocket {
     fn as_fd(&self) -> BorrowedFd<'_> {
-        self.0.as_fd()
+        self.sock.as_fd()
     }
 }
 
 impl Read for CanSocket {
     fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
-        self.0.read(buf)
+        self.sock.read(buf)
     }
 }
 
 impl Write for CanSocket {
     fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
-        self.0.write(buf)
+        self.sock.write(buf)
     }
 
     fn flush(&mut self) -> IoResult<()> {
-        self.0.flush()
+        self.sock.flush()
     }
 }
 
@@ -578,9 +947,45 @@ impl Write for CanSocket {
 /// or CAN Flexible Data (FD) frames with up to 64-bytes of data.
 #[allow(missing_copy_implementations)]
 #[derive(Debug)]
-pub struct CanFdSocket(socket2::Socket);
+pub struct CanFdSocket {
+    sock: socket2::Socket,
+    fd_conversion: FdConversion,
+    batch: BatchState,
+}
+
+/// The state kept from one batch read of a socket to the next.
+#[derive(Debug, Default)]
+struct BatchState {
+    // Whether receive timestamps have been turned on
+    timestamps: AtomicBool,
+    // An error from a batch that also had good frames, for the next read
+    pending: Mutex<Option<IoError>>,
+}
 
 impl CanFdSocket {
+    /// Sets the policy for converting frames as they're written.
+    ///
+    /// By default frames are written as they are. With
+    /// [`FdConversion::ToFd`], classic data frames are promoted to FD
+    /// frames, with or without the bit rate switch. With
+    /// [`FdConversion::ToClassic`], FD frames with up to 8 bytes of data
+    /// are sent as classic frames, and longer ones are rejected.
+    pub fn set_fd_conversion(&mut self, conv: FdConversion) {
+        self.fd_conversion = conv;
+    }
+
+    /// Sets the policy for converting frames as they're written, as a
+    /// builder.
+    pub fn with_fd_conversion(mut self, conv: FdConversion) -> Self {
+        self.set_fd_conversion(conv);
+        self
+    }
+
+    /// Gets the policy for converting frames as they're written.
+    pub fn fd_conversion(&self) -> FdConversion {
+        self.fd_conversion
+    }
+
     // Enable or disable FD mode on a socket.
     fn set_fd_mode(sock: socket2::Socket, enable: bool) -> IoResult<socket2::Socket> {
         let enable = enable as c_int;
@@ -618,7 +1023,150 @@ impl CanFdSocket {
                 Ok(frame.into())
             }
             CANFD_MTU => Ok(fdframe.into()),
-            _ => Err(IoError::last_os_error()),
+            n => Err(frame_size_error(n)),
+        }
+    }
+}
+
+impl CanFdSocket {
+    /// Reads a batch of frames, along with the times that the kernel
+    /// received them, into a reusable buffer.
+    ///
+    /// The buffer is cleared, then filled with up to [`RECV_BATCH`]
+    /// frames from a single `recvmmsg()` call, blocking until at least one
+    /// is available. Returns the number of frames read. Once the buffer
+    /// has grown to hold a full batch, reading doesn't allocate, which
+    /// keeps a logging loop allocation-free in the steady state.
+    ///
+    /// Receive timestamps are turned on for the socket before the first
+    /// read. Frames that were already queued without one are stamped with
+    /// the current time.
+    ///
+    /// A malformed frame in a batch is skipped, and the rest of the batch
+    /// is returned. The error is then returned by the next read.
+    pub fn read_frames_into(&self, frames: &mut Vec<Timestamped<CanAnyFrame>>) -> IoResult<usize> {
+        frames.clear();
+        frames.reserve(RECV_BATCH);
+        self.recv_batch(|frame| frames.push(frame))?;
+        Ok(frames.len())
+    }
+
+    /// Reads a batch of frames, like [`read_frames_into`], but into
+    /// buffers from a pool.
+    ///
+    /// The handles can be passed on to other threads, and cloned to share
+    /// the frames between them, without copying the frames or allocating
+    /// once the pool is warmed up.
+    ///
+    /// [`read_frames_into`]: CanFdSocket::read_frames_into
+    pub fn read_frames_pooled(
+        &self,
+        pool: &FramePool<Timestamped<CanAnyFrame>>,
+        frames: &mut Vec<Pooled<Timestamped<CanAnyFrame>>>,
+    ) -> IoResult<usize> {
+        frames.clear();
+        frames.reserve(RECV_BATCH);
+        self.recv_batch(|frame| frames.push(pool.alloc(frame)))?;
+        Ok(frames.len())
+    }
+
+    // Reads up to a batch of frames with a single `recvmmsg()` call,
+    // passing each one on as it's decoded.
+    fn recv_batch<P>(&self, mut push: P) -> IoResult<()>
+    where
+        P: FnMut(Timestamped<CanAnyFrame>),
+    {
+        if let Some(err) = self.batch.pending.lock().unwrap().take() {
+            return Err(err);
+        }
+        if !self.batch.timestamps.load(Ordering::Relaxed) {
+            self.set_socket_option(libc::SOL_SOCKET, libc::SO_TIMESTAMPNS, &(1 as c_int))?;
+            self.batch.timestamps.store(true, Ordering::Relaxed);
+        }
+
+        let mut bufs = [canfd_frame_default(); RECV_BATCH];
+        let mut cmsgs = [CmsgBuf([0; 64]); RECV_BATCH];
+        // SAFETY: These are plain C structs, for which all zeros is valid
+        let mut iovs: [libc::iovec; RECV_BATCH] = unsafe { std::mem::zeroed() };
+        let mut msgs: [libc::mmsghdr; RECV_BATCH] = unsafe { std::mem::zeroed() };
+
+        for i in 0..RECV_BATCH {
+            iovs[i].iov_base = &mut bufs[i] as *mut _ as *mut c_void;
+            iovs[i].iov_len = CANFD_MTU;
+            let hdr = &mut msgs[i].msg_hdr;
+            hdr.msg_iov = &mut iovs[i];
+            hdr.msg_iovlen = 1;
+            hdr.msg_control = cmsgs[i].0.as_mut_ptr() as *mut c_void;
+            hdr.msg_controllen = size_of::<CmsgBuf>() as _;
+        }
+
+        // SAFETY: Each message points to its own buffers, which outlive
+        // the call.
+        let n = unsafe {
+            libc::recvmmsg(
+                self.as_raw_fd(),
+                msgs.as_mut_ptr(),
+                RECV_BATCH as _,
+                libc::MSG_WAITFORONE as _,
+                ptr::null_mut(),
+            )
+        };
+        if n < 0 {
+            return Err(IoError::last_os_error());
+        }
+
+        let mut decoded = 0;
+        let mut error = None;
+        for (msg, fdframe) in msgs.iter().zip(&bufs).take(n as usize) {
+            match any_frame_from_raw(fdframe, msg.msg_len as usize) {
+                Ok(frame) => {
+                    let time = cmsg_timestamp(&msg.msg_hdr).unwrap_or_else(SystemTime::now);
+                    push(Timestamped::new(frame, time));
+                    decoded += 1;
+                }
+                Err(err) => {
+                    error.get_or_insert(err);
+                }
+            }
+        }
+
+        match error {
+            Some(err) if decoded == 0 => Err(err),
+            Some(err) => {
+                *self.batch.pending.lock().unwrap() = Some(err);
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Reads either type of CAN frame from the socket straight into the
+    /// caller's FD frame, with no intermediate copy.
+    ///
+    /// Returns the number of bytes read: `CANFD_MTU` for an FD frame, or
+    /// `CAN_MTU` for a classic frame. As with the kernel API, a classic
+    /// frame is left in the FD frame's layout, with its DLC as the length,
+    /// and only its first eight data bytes written, so the frame can be
+    /// reused from one read to the next without being cleared.
+    ///
+    /// FD frames can't be remote frames, so a classic remote frame is
+    /// consumed from the socket and reported as an `InvalidData` error,
+    /// with the remote flag cleared from the caller's frame.
+    pub fn read_frame_into(&self, frame: &mut CanFdFrame) -> IoResult<usize> {
+        // SAFETY: The pointer is to the frame's own `canfd_frame`, which is
+        // all integers, so any bytes that the kernel writes are valid.
+        let raw = unsafe { &mut *frame.as_mut_ptr() };
+        match self.read_raw(as_bytes_mut(raw))? {
+            CAN_MTU if raw.can_id & libc::CAN_RTR_FLAG != 0 => {
+                // Leave the caller's frame a valid FD frame
+                raw.can_id &= !libc::CAN_RTR_FLAG;
+                Err(IoError::new(
+                    IoErrorKind::InvalidData,
+                    "remote frame can't be read into an FD frame",
+                ))
+            }
+            n @ (CAN_MTU | CANFD_MTU) => Ok(n),
+            n => Err(frame_size_error(n)),
         }
     }
 }
@@ -631,6 +1179,135 @@ impl Socket for CanFdSocket {
     fn open_addr(addr: &CanAddr) -> IoResult<Self> {
         raw_open_socket(addr)
             .and_then(|sock| Self::set_fd_mode(sock, true))
+            .map(|sock| Self {
+                sock,
+                fd_conversion: FdConversion::default(),
+                batch: BatchState::default(),
+            })
+    }
+
+    /// Gets a shared reference to the underlying socket object
+    fn as_raw_socket(&self) -> &socket2::Socket {
+        &self.sock
+    }
+
+    /// Gets a mutable reference to the underlying socket object
+    fn as_raw_socket_mut(&mut self) -> &mut socket2::Socket {
+        &mut self.sock
+    }
+
+    /// Writes any type of CAN frame to the socket, converted by the
+    /// socket's [`FdConversion`] policy.
+    fn write_frame<F>(&self, frame: &F) -> IoResult<()>
+    where
+        F: Into<Self::FrameType> + AsPtr + Clone,
+    {
+        if self.fd_conversion == FdConversion::Keep {
+            return self.as_raw_socket().write_all(frame.as_bytes());
+        }
+        let frame = convert_for_write(self.fd_conversion, frame.clone().into())?;
+        self.as_raw_socket().write_all(frame.as_bytes())
+    }
+
+    /// Reads either type of CAN frame from the socket.
+    fn read_frame(&self) -> IoResult<CanAnyFrame> {
+        let mut fdframe = canfd_frame_default();
+        let n = self.as_raw_socket().read(as_bytes_mut(&mut fdframe))?;
+        any_frame_from_raw(&fdframe, n)
+    }
+}
+
+impl SocketOptions for CanFdSocket {}
+
+// Has no effect: #[deprecated(since = "3.1", note = "Use AsFd::as_fd() instead.")]
+impl AsRawFd for CanFdSocket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.sock.as_raw_fd()
+    }
+}
+
+impl From<OwnedFd> for CanFdSocket {
+    fn from(fd: OwnedFd) -> CanFdSocket {
+        Self {
+            sock: socket2::Socket::from(fd),
+            fd_conversion: FdConversion::default(),
+            batch: BatchState::default(),
+        }
+    }
+}
+
+impl IntoRawFd for CanFdSocket {
+    fn into_raw_fd(self) -> RawFd {
+        self.sock.into_raw_fd()
+    }
+}
+
+impl AsFd for CanFdSocket {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.sock.as_fd()
+    }
+}
+
+impl Read for CanFdSocket {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        self.sock.read(buf)
+    }
+}
+
+impl Write for CanFdSocket {
+    fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+        self.sock.write(buf)
+    }
+
+    fn flush(&mut self) -> IoResult<()> {
+        self.sock.flush()
+    }
+}
+
+// ===== CanXlSocket =====
+
+/// A socket for CAN XL devices.
+///
+/// This can transmit and receive CAN 2.0 frames, CAN FD frames, and
+/// CAN XL frames with up to 2048 bytes of data. It requires a kernel and
+/// interface that support CAN XL; opening it on anything else will fail.
+#[allow(missing_copy_implementations)]
+#[derive(Debug)]
+pub struct CanXlSocket(socket2::Socket);
+
+impl CanXlSocket {
+    // Enable or disable XL mode on a socket.
+    fn set_xl_mode(sock: socket2::Socket, enable: bool) -> IoResult<socket2::Socket> {
+        let enable = enable as c_int;
+
+        let ret = unsafe {
+            libc::setsockopt(
+                sock.as_raw_fd(),
+                SOL_CAN_RAW,
+                CAN_RAW_XL_FRAMES,
+                &enable as *const _ as *const c_void,
+                size_of::<c_int>() as u32,
+            )
+        };
+
+        match ret {
+            0 => Ok(sock),
+            _ => Err(IoError::last_os_error()),
+        }
+    }
+}
+
+impl Socket for CanXlSocket {
+    /// CanXlSocket can read/write classic CAN 2.0, FD, or XL frames.
+    type FrameType = CanXlAnyFrame;
+
+    /// Opens the XL socket by interface index.
+    ///
+    /// This enables both FD and XL frames on the socket.
+    fn open_addr(addr: &CanAddr) -> IoResult<Self> {
+        raw_open_socket(addr)
+            .and_then(|sock| CanFdSocket::set_fd_mode(sock, true))
+            .and_then(|sock| Self::set_xl_mode(sock, true))
             .map(Self)
     }
 
@@ -647,64 +1324,78 @@ impl Socket for CanFdSocket {
     /// Writes any type of CAN frame to the socket.
     fn write_frame<F>(&self, frame: &F) -> IoResult<()>
     where
-        F: Into<Self::FrameType> + AsPtr,
+        F: Into<Self::FrameType> + AsPtr + Clone,
     {
         self.as_raw_socket().write_all(frame.as_bytes())
     }
 
-    /// Reads either type of CAN frame from the socket.
-    fn read_frame(&self) -> IoResult<CanAnyFrame> {
-        let mut fdframe = canfd_frame_default();
+    /// Reads any type of CAN frame from the socket.
+    fn read_frame(&self) -> IoResult<CanXlAnyFrame> {
+        let mut xlframe = canxl_frame_default();
+        let n = self.as_raw_socket().read(as_bytes_mut(&mut xlframe))?;
+
+        // The XLF flag sits where the length byte would be in a classic
+        // or FD frame, and is never set for those.
+        if n >= CANXL_HDR_SIZE && xlframe.flags & (CANXL_XLF as u8) != 0 {
+            // The kernel sends just the header and the used part of the data
+            if n != CANXL_HDR_SIZE + usize::from(xlframe.len) {
+                return Err(frame_size_error(n));
+            }
+            return CanXlFrame::try_from(xlframe)
+                .map(CanXlAnyFrame::from)
+                .map_err(|_| frame_size_error(n));
+        }
 
-        match self.as_raw_socket().read(as_bytes_mut(&mut fdframe))? {
-            // If we only get 'can_frame' number of bytes, then the return is,
-            // by definition, a can_frame, so we just copy the bytes into the
-            // proper type.
+        let buf = as_bytes(&xlframe);
+        match n {
             CAN_MTU => {
                 let mut frame = can_frame_default();
-                as_bytes_mut(&mut frame)[..CAN_MTU].copy_from_slice(&as_bytes(&fdframe)[..CAN_MTU]);
+                as_bytes_mut(&mut frame).copy_from_slice(&buf[..CAN_MTU]);
                 Ok(CanFrame::from(frame).into())
             }
-            CANFD_MTU => Ok(CanFdFrame::from(fdframe).into()),
-            _ => Err(IoError::last_os_error()),
+            CANFD_MTU => {
+                let mut frame = canfd_frame_default();
+                as_bytes_mut(&mut frame).copy_from_slice(&buf[..CANFD_MTU]);
+                Ok(CanFdFrame::from(frame).into())
+            }
+            n => Err(frame_size_error(n)),
         }
     }
 }
 
-impl SocketOptions for CanFdSocket {}
+impl SocketOptions for CanXlSocket {}
 
-// Has no effect: #[deprecated(since = "3.1", note = "Use AsFd::as_fd() instead.")]
-impl AsRawFd for CanFdSocket {
+impl AsRawFd for CanXlSocket {
     fn as_raw_fd(&self) -> RawFd {
         self.0.as_raw_fd()
     }
 }
 
-impl From<OwnedFd> for CanFdSocket {
-    fn from(fd: OwnedFd) -> CanFdSocket {
+impl From<OwnedFd> for CanXlSocket {
+    fn from(fd: OwnedFd) -> CanXlSocket {
         Self(socket2::Socket::from(fd))
     }
 }
 
-impl IntoRawFd for CanFdSocket {
+impl IntoRawFd for CanXlSocket {
     fn into_raw_fd(self) -> RawFd {
         self.0.into_raw_fd()
     }
 }
 
-impl AsFd for CanFdSocket {
+impl AsFd for CanXlSocket {
     fn as_fd(&self) -> BorrowedFd<'_> {
         self.0.as_fd()
     }
 }
 
-impl Read for CanFdSocket {
+impl Read for CanXlSocket {
     fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
         self.0.read(buf)
     }
 }
 
-impl Write for CanFdSocket {
+impl Write for CanXlSocket {
     fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
         self.0.write(buf)
     }
@@ -724,7 +1415,16 @@ impl Write for CanFdSocket {
 ///
 /// A socket can be given multiple filters, and each one can be inverted
 /// ([ref](https://docs.kernel.org/networking/can.html#raw-protocol-sockets-with-can-filters-sock-raw))
+///
+/// With the `serde` feature, a filter is serialized as a struct of the raw
+/// `id` and `mask` values, like `{ "id": 291, "mask": 2047 }`. An inverted
+/// filter has the `CAN_INV_FILTER` bit set in the `id`.
 #[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(from = "CanFilterRepr", into = "CanFilterRepr")
+)]
 pub struct CanFilter(libc::can_filter);
 
 impl CanFilter {
@@ -740,6 +1440,41 @@ impl CanFilter {
     pub fn new_inverted(id: canid_t, mask: canid_t) -> Self {
         Self::new(id | libc::CAN_INV_FILTER, mask)
     }
+
+    /// Construct a filter that matches a single CAN ID exactly.
+    ///
+    /// This includes the EFF flag in the mask, so a standard ID won't
+    /// match an extended ID with the same value, or vice versa.
+    pub fn from_id(id: CanId) -> Self {
+        Self::new(id.id_word(), id.mask() | CAN_EFF_FLAG)
+    }
+
+    /// Determines if the filter accepts a frame with the ID word, using
+    /// the same rule as the kernel.
+    ///
+    /// The ID word includes the EFF, RTR, and ERR flags, as they're
+    /// matched by the mask.
+    pub fn matches(&self, id_word: canid_t) -> bool {
+        let f = &self.0;
+        let inverted = f.can_id & libc::CAN_INV_FILTER != 0;
+        let hit = (id_word & f.can_mask) == (f.can_id & !libc::CAN_INV_FILTER & f.can_mask);
+        hit != inverted
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for CanFilter {
+    /// Generates a filter for an arbitrary ID, with a mask that covers
+    /// the ID and the EFF/RTR flags.
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        let id = CanId::arbitrary(u)?;
+        let mask = u32::arbitrary(u)? & (id.mask() | CAN_EFF_FLAG | libc::CAN_RTR_FLAG);
+        Ok(if u.arbitrary()? {
+            Self::new_inverted(id.id_word(), mask)
+        } else {
+            Self::new(id.id_word(), mask)
+        })
+    }
 }
 
 impl From<libc::can_filter> for CanFilter {
@@ -759,3 +1494,29 @@ impl AsRef<libc::can_filter> for CanFilter {
         &self.0
     }
 }
+
+/// The serde representation of a CAN filter.
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "CanFilter")]
+struct CanFilterRepr {
+    id: canid_t,
+    mask: canid_t,
+}
+
+#[cfg(feature = "serde")]
+impl From<CanFilter> for CanFilterRepr {
+    fn from(filt: CanFilter) -> Self {
+        Self {
+            id: filt.0.can_id,
+            mask: filt.0.can_mask,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<CanFilterRepr> for CanFilter {
+    fn from(repr: CanFilterRepr) -> Self {
+        CanFilter::new(repr.id, repr.mask)
+    }
+}
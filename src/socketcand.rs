@@ -0,0 +1,379 @@
+// socketcan/src/socketcand.rs
+//
+// A client for remote CAN buses served by socketcand.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! A client for remote CAN buses served by socketcand.
+//!
+//! The [socketcand](https://github.com/linux-can/socketcand) daemon
+//! exposes the CAN interfaces of a machine over TCP, with a simple ASCII
+//! protocol. The [`SocketcandClient`] connects to a bus on such a daemon,
+//! and implements the [`CanTransmitter`] and [`CanReceiver`] traits, so a
+//! remote bus can stand in for a local socket in the higher layers of the
+//! library.
+//!
+//! The connection starts out in BCM mode, in which the daemon does the
+//! cyclic transmission and the receive filtering, as set up with
+//! [`add_cyclic`](SocketcandClient::add_cyclic) and
+//! [`subscribe`](SocketcandClient::subscribe). In raw mode, selected with
+//! [`rawmode`](SocketcandClient::rawmode), every frame on the bus is
+//! received.
+//!
+//! Only classic data frames are supported.
+//!
+//! ```no_run
+//! use socketcan::{
+//!     backend::{CanReceiver, CanTransmitter},
+//!     socketcand::SocketcandClient,
+//!     CanFrame, Frame,
+//! };
+//!
+//! let client = SocketcandClient::connect("192.168.1.10:29536", "can0").unwrap();
+//! client.rawmode().unwrap();
+//!
+//! let frame = CanFrame::from_raw_id(0x123, &[1, 2, 3]).unwrap();
+//! client.transmit(&frame).unwrap();
+//!
+//! loop {
+//!     let frame = client.receive().unwrap();
+//!     println!("{:?}", frame);
+//! }
+//! ```
+
+use crate::{
+    backend::{CanReceiver, CanTransmitter},
+    frame::{write_hex_bytes, CAN_MAX_DLEN},
+    hex, CanFrame, CanId, EmbeddedFrame, ExtendedId, Id, IoError, IoErrorKind, IoResult,
+    StandardId, Timestamped,
+};
+use std::{
+    io::{Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime},
+};
+use thiserror::Error;
+
+/// The TCP port that socketcand listens on by default.
+pub const DEFAULT_PORT: u16 = 29536;
+
+/// An error in the socketcand protocol.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum SocketcandError {
+    /// The daemon reported an error
+    #[error("socketcand error: {0}")]
+    Server(String),
+    /// The daemon sent an unexpected response
+    #[error("Unexpected socketcand response: {0:?}")]
+    Unexpected(String),
+    /// A message from the daemon couldn't be parsed
+    #[error("Malformed socketcand message: {0:?}")]
+    Malformed(String),
+}
+
+impl From<SocketcandError> for IoError {
+    fn from(err: SocketcandError) -> Self {
+        IoError::new(IoErrorKind::InvalidData, err)
+    }
+}
+
+// ===== Messages =====
+
+/// Formats an ID as socketcand expects it: three hex digits for a standard
+/// ID, and eight for an extended one.
+fn format_id(id: Id) -> String {
+    match id {
+        Id::Standard(id) => format!("{:03X}", id.as_raw()),
+        Id::Extended(id) => format!("{:08X}", id.as_raw()),
+    }
+}
+
+/// Formats the ID, length and data of a frame, as used in the `send`,
+/// `add` and `update` commands.
+fn format_frame(frame: &CanFrame) -> IoResult<String> {
+    if !frame.is_data_frame() {
+        return Err(IoError::new(
+            IoErrorKind::InvalidInput,
+            "socketcand only sends data frames",
+        ));
+    }
+    let mut s = format!("{} {}", format_id(frame.id()), frame.data().len());
+    if !frame.data().is_empty() {
+        s.push(' ');
+        let _ = write_hex_bytes(&mut s, frame.data(), true, Some(b' '));
+    }
+    Ok(s)
+}
+
+/// Parses a `< frame id seconds.useconds data >` message, without the
+/// angle brackets.
+pub fn parse_frame(msg: &str) -> Result<Timestamped<CanFrame>, SocketcandError> {
+    let malformed = || SocketcandError::Malformed(msg.into());
+    let mut fields = msg.split_whitespace();
+    if fields.next() != Some("frame") {
+        return Err(malformed());
+    }
+
+    let id_str = fields.next().ok_or_else(malformed)?;
+    let raw_id = u32::from_str_radix(id_str, 16).map_err(|_| malformed())?;
+    let id: Id = match id_str.len() {
+        0..=3 => StandardId::new(raw_id as u16).map(Id::from),
+        _ => ExtendedId::new(raw_id).map(Id::from),
+    }
+    .ok_or_else(malformed)?;
+
+    let (secs, usecs) = fields
+        .next()
+        .and_then(|ts| ts.split_once('.'))
+        .ok_or_else(malformed)?;
+    let secs: u64 = secs.parse().map_err(|_| malformed())?;
+    let usecs: u32 = usecs.parse().map_err(|_| malformed())?;
+    let time = SystemTime::UNIX_EPOCH + Duration::new(secs, usecs.saturating_mul(1000));
+
+    // The data is a run of hex digits, which some versions split up
+    let mut data = [0u8; CAN_MAX_DLEN];
+    let mut len = 0;
+    for digits in fields {
+        len += hex::decode_into(digits.as_bytes(), &mut data[len..]).map_err(|_| malformed())?;
+    }
+
+    let frame = CanFrame::new(id, &data[..len]).ok_or_else(malformed)?;
+    Ok(Timestamped::new(frame, time))
+}
+
+// ===== SocketcandClient =====
+
+/// A connection to a CAN bus on a socketcand daemon.
+#[derive(Debug)]
+pub struct SocketcandClient {
+    stream: TcpStream,
+    rx_buf: Mutex<Vec<u8>>,
+}
+
+impl SocketcandClient {
+    /// Connects to the daemon and opens the bus, in BCM mode.
+    pub fn connect<A: ToSocketAddrs>(addr: A, bus: &str) -> IoResult<Self> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+        let client = Self {
+            stream,
+            rx_buf: Mutex::new(Vec::new()),
+        };
+
+        client.expect("hi")?;
+        client.command(&format!("open {}", bus))?;
+        Ok(client)
+    }
+
+    /// Switches to raw mode, to receive every frame on the bus.
+    pub fn rawmode(&self) -> IoResult<()> {
+        self.command("rawmode")
+    }
+
+    /// Switches back to BCM mode, to receive only subscribed frames.
+    pub fn bcmmode(&self) -> IoResult<()> {
+        self.command("bcmmode")
+    }
+
+    /// Has the daemon send the frame at the period, until it's deleted.
+    pub fn add_cyclic(&self, period: Duration, frame: &CanFrame) -> IoResult<()> {
+        self.send(&format!(
+            "add {} {} {}",
+            period.as_secs(),
+            period.subsec_micros(),
+            format_frame(frame)?
+        ))
+    }
+
+    /// Changes the payload of a cyclic frame, without changing its
+    /// timing.
+    pub fn update(&self, frame: &CanFrame) -> IoResult<()> {
+        self.send(&format!("update 0 0 {}", format_frame(frame)?))
+    }
+
+    /// Stops sending a cyclic frame.
+    pub fn delete(&self, id: CanId) -> IoResult<()> {
+        self.send(&format!("delete {}", format_id(id.into())))
+    }
+
+    /// Subscribes to the frames with the ID, in BCM mode.
+    ///
+    /// A non-zero interval throttles the frames to at most one per
+    /// interval.
+    pub fn subscribe(&self, id: CanId, interval: Duration) -> IoResult<()> {
+        self.send(&format!(
+            "subscribe {} {} {}",
+            interval.as_secs(),
+            interval.subsec_micros(),
+            format_id(id.into())
+        ))
+    }
+
+    /// Unsubscribes from the frames with the ID.
+    pub fn unsubscribe(&self, id: CanId) -> IoResult<()> {
+        self.send(&format!("unsubscribe {}", format_id(id.into())))
+    }
+
+    /// Receives the next frame, along with the time the daemon received
+    /// it.
+    pub fn receive_timestamped(&self) -> IoResult<Timestamped<CanFrame>> {
+        self.receive_until(None)
+    }
+
+    /// Sends a command, without waiting for a response.
+    fn send(&self, cmd: &str) -> IoResult<()> {
+        (&self.stream).write_all(format!("< {} >", cmd).as_bytes())
+    }
+
+    /// Sends a command and waits for the daemon to accept it.
+    fn command(&self, cmd: &str) -> IoResult<()> {
+        self.send(cmd)?;
+        self.expect("ok")
+    }
+
+    /// Waits for a reply message with the keyword.
+    fn expect(&self, keyword: &str) -> IoResult<()> {
+        let msg = self.next_message(None)?;
+        let mut fields = msg.split_whitespace();
+        match fields.next() {
+            Some(k) if k == keyword => Ok(()),
+            Some("error") => {
+                Err(SocketcandError::Server(fields.collect::<Vec<_>>().join(" ")).into())
+            }
+            _ => Err(SocketcandError::Unexpected(msg).into()),
+        }
+    }
+
+    /// Reads messages until a frame arrives, or until the deadline passes.
+    fn receive_until(&self, deadline: Option<Instant>) -> IoResult<Timestamped<CanFrame>> {
+        loop {
+            let msg = self.next_message(deadline)?;
+            match msg.split_whitespace().next() {
+                Some("frame") => return Ok(parse_frame(&msg)?),
+                Some("error") => {
+                    return Err(SocketcandError::Server(msg["error".len()..].trim().into()).into())
+                }
+                // Acknowledgements and status messages
+                _ => continue,
+            }
+        }
+    }
+
+    /// Reads the next message, without the angle brackets.
+    fn next_message(&self, deadline: Option<Instant>) -> IoResult<String> {
+        let mut rx_buf = self.rx_buf.lock().unwrap();
+        loop {
+            if let Some(end) = rx_buf.iter().position(|&b| b == b'>') {
+                let msg: Vec<u8> = rx_buf.drain(..=end).collect();
+                let msg = String::from_utf8_lossy(&msg[..end]);
+                let msg = msg.trim().trim_start_matches('<').trim();
+                return Ok(msg.into());
+            }
+
+            let timeout = match deadline {
+                Some(deadline) => {
+                    let left = deadline.saturating_duration_since(Instant::now());
+                    if left.is_zero() {
+                        return Err(IoErrorKind::TimedOut.into());
+                    }
+                    Some(left)
+                }
+                None => None,
+            };
+            self.stream.set_read_timeout(timeout)?;
+
+            let mut buf = [0u8; 1024];
+            match (&self.stream).read(&mut buf) {
+                Ok(0) => return Err(IoErrorKind::UnexpectedEof.into()),
+                Ok(n) => rx_buf.extend_from_slice(&buf[..n]),
+                Err(err) if err.kind() == IoErrorKind::WouldBlock => {
+                    return Err(IoErrorKind::TimedOut.into())
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+impl<F> CanTransmitter<F> for SocketcandClient
+where
+    F: Into<CanFrame> + Clone,
+{
+    fn transmit(&self, frame: &F) -> IoResult<()> {
+        let frame: CanFrame = frame.clone().into();
+        self.send(&format!("send {}", format_frame(&frame)?))
+    }
+}
+
+impl CanReceiver for SocketcandClient {
+    type Frame = CanFrame;
+
+    fn receive(&self) -> IoResult<CanFrame> {
+        self.receive_until(None).map(|ts| ts.frame)
+    }
+
+    fn receive_timeout(&self, timeout: Duration) -> IoResult<CanFrame> {
+        self.receive_until(Some(Instant::now() + timeout))
+            .map(|ts| ts.frame)
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Frame;
+    use std::{net::TcpListener, thread};
+
+    #[test]
+    fn test_messages() {
+        let frame = CanFrame::from_raw_id(0x123, &[0x11, 0x22, 0xAB]).unwrap();
+        assert_eq!("123 3 11 22 AB", format_frame(&frame).unwrap());
+
+        let ts = parse_frame("frame 123 1700000000.000250 1122AB").unwrap();
+        assert_eq!(frame, ts.frame);
+        assert_eq!(
+            SystemTime::UNIX_EPOCH + Duration::new(1_700_000_000, 250_000),
+            ts.time
+        );
+
+        let ts = parse_frame("frame 123 1700000000.000250 11 22 AB").unwrap();
+        assert_eq!(frame, ts.frame);
+
+        let ts = parse_frame("frame 1ABCDEF0 1.5").unwrap();
+        assert!(ts.frame.is_extended());
+        assert!(ts.frame.data().is_empty());
+
+        assert!(parse_frame("frame 123 1.0 1").is_err());
+        assert!(parse_frame("frame 123 1.0 0011223344556677 88").is_err());
+        assert!(parse_frame("ok").is_err());
+    }
+
+    #[test]
+    fn test_client() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = thread::spawn(move || {
+            let (mut conn, _) = listener.accept().unwrap();
+            conn.write_all(b"< hi >").unwrap();
+            let mut buf = [0u8; 64];
+            let n = conn.read(&mut buf).unwrap();
+            assert_eq!(b"< open vcan0 >", &buf[..n]);
+            conn.write_all(b"< ok >< frame 7FF 0.000001 00 >").unwrap();
+        });
+
+        let client = SocketcandClient::connect(addr, "vcan0").unwrap();
+        let frame = client.receive_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(0x7FF, frame.raw_id());
+        assert_eq!(&[0], frame.data());
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,146 @@
+// socketcan/src/timestamp.rs
+//
+// A frame with timing metadata.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! Frames with timing metadata.
+//!
+//! The [`Timestamped`] wrapper pairs a frame with the time that it was
+//! received, and optionally the channel (interface) it came from. It's
+//! returned by timestamping reads on the sockets, and can be converted to
+//! and from the records used by the log parsers and capture utilities, so
+//! that timing metadata flows through the API in the same form.
+//!
+//! The time defaults to a [`SystemTime`], which is what the kernel reports
+//! and logs record, but any type can be used, such as an [`Instant`] for
+//! frames timestamped by the application.
+//!
+//! [`Instant`]: std::time::Instant
+
+use crate::{capture::Record, CanAnyFrame};
+use std::time::{Duration, SystemTime};
+
+/// A frame along with the time it was received, and optionally the channel
+/// on which it was received.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Timestamped<F, T = SystemTime> {
+    /// The frame
+    pub frame: F,
+    /// The time the frame was received
+    pub time: T,
+    /// The name of the channel (interface) on which the frame was received
+    pub channel: Option<String>,
+}
+
+impl<F, T> Timestamped<F, T> {
+    /// Creates a timestamped frame without a channel.
+    pub fn new(frame: F, time: T) -> Self {
+        Self {
+            frame,
+            time,
+            channel: None,
+        }
+    }
+
+    /// Sets the channel on which the frame was received.
+    pub fn with_channel(mut self, channel: impl Into<String>) -> Self {
+        self.channel = Some(channel.into());
+        self
+    }
+
+    /// Gets the name of the channel, if known.
+    pub fn channel(&self) -> Option<&str> {
+        self.channel.as_deref()
+    }
+
+    /// Converts the frame to another type, keeping the timing metadata.
+    pub fn map<G, M>(self, f: M) -> Timestamped<G, T>
+    where
+        M: FnOnce(F) -> G,
+    {
+        Timestamped {
+            frame: f(self.frame),
+            time: self.time,
+            channel: self.channel,
+        }
+    }
+
+    /// Splits this into the frame and the time.
+    pub fn into_parts(self) -> (F, T) {
+        (self.frame, self.time)
+    }
+}
+
+impl<F> Timestamped<F, SystemTime> {
+    /// Creates a timestamped frame from a time in microseconds since the
+    /// Unix epoch, as used in candump logs.
+    pub fn from_micros(frame: F, t_us: u64) -> Self {
+        Self::new(frame, SystemTime::UNIX_EPOCH + Duration::from_micros(t_us))
+    }
+
+    /// Gets the time in microseconds since the Unix epoch.
+    ///
+    /// This is zero for a time before the epoch.
+    pub fn micros(&self) -> u64 {
+        micros_since_epoch(self.time)
+    }
+}
+
+/// Gets a time in microseconds since the Unix epoch.
+///
+/// This is zero for a time before the epoch.
+pub(crate) fn micros_since_epoch(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_micros() as u64)
+}
+
+impl From<Record> for Timestamped<CanAnyFrame> {
+    fn from(rec: Record) -> Self {
+        Self::from_micros(rec.frame, rec.t_us).with_channel(rec.device)
+    }
+}
+
+impl<F: Into<CanAnyFrame>> From<Timestamped<F>> for Record {
+    /// Creates a capture record, with an empty device name if the channel
+    /// is not known.
+    fn from(ts: Timestamped<F>) -> Self {
+        let t_us = ts.micros();
+        Record::new(t_us, ts.channel.unwrap_or_default(), ts.frame)
+    }
+}
+
+#[cfg(feature = "dump")]
+impl From<crate::dump::CanDumpRecord<'_>> for Timestamped<CanAnyFrame> {
+    fn from(rec: crate::dump::CanDumpRecord<'_>) -> Self {
+        Self::from_micros(rec.frame, rec.t_us).with_channel(rec.device)
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CanFrame, Frame};
+
+    #[test]
+    fn test_record_conversion() {
+        let frame = CanFrame::from_raw_id(0x123, &[1, 2, 3]).unwrap();
+        let ts = Timestamped::from_micros(frame, 1_500_000).with_channel("can0");
+        assert_eq!(1_500_000, ts.micros());
+        assert_eq!(Some("can0"), ts.channel());
+
+        let rec = Record::from(ts.clone());
+        assert_eq!(1_500_000, rec.t_us);
+        assert_eq!("can0", rec.device);
+
+        let ts2 = Timestamped::from(rec);
+        assert_eq!(ts.map(CanAnyFrame::from), ts2);
+    }
+}
@@ -27,7 +27,10 @@
 //! }
 //! ```
 use crate::{
-    CanAddr, CanAnyFrame, CanFdFrame, CanFrame, Error, IoResult, Result, Socket, SocketOptions,
+    backend::{AsyncCanReceiver, AsyncCanTransmitter},
+    frame::AsPtr,
+    CanAddr, CanAnyFrame, CanEvent, CanFdFrame, CanFrame, Error, IoResult, Result, Socket,
+    SocketOptions,
 };
 use futures::{prelude::*, ready, task::Context};
 use std::{
@@ -78,6 +81,36 @@ impl<T: Socket> AsRawFd for AsyncCanSocket<T> {
     }
 }
 
+impl<T, F> AsyncCanTransmitter<F> for AsyncCanSocket<T>
+where
+    T: Socket,
+    F: Into<T::FrameType> + AsPtr + Clone,
+{
+    fn poll_transmit(&self, cx: &mut Context<'_>, frame: &F) -> Poll<IoResult<()>> {
+        loop {
+            let mut guard = ready!(self.0.poll_write_ready(cx))?;
+            match guard.try_io(|inner| inner.get_ref().write_frame(frame)) {
+                Ok(result) => return Poll::Ready(result),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+}
+
+impl<T: Socket> AsyncCanReceiver for AsyncCanSocket<T> {
+    type Frame = T::FrameType;
+
+    fn poll_receive(&self, cx: &mut Context<'_>) -> Poll<IoResult<Self::Frame>> {
+        loop {
+            let mut guard = ready!(self.0.poll_read_ready(cx))?;
+            match guard.try_io(|inner| inner.get_ref().read_frame()) {
+                Ok(result) => return Poll::Ready(result),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+}
+
 /// Asynchronous Can Socket
 pub type CanSocket = AsyncCanSocket<crate::CanSocket>;
 
@@ -95,6 +128,21 @@ impl CanSocket {
             .async_io(Interest::READABLE, |inner| inner.read_frame())
             .await
     }
+
+    /// Read a CAN frame from the socket asynchronously, with any error
+    /// frame split out as a typed event.
+    pub async fn read_event(&self) -> IoResult<CanEvent<CanFrame>> {
+        self.read_frame().await.map(CanEvent::from)
+    }
+
+    /// Gets a stream of the frames read from the socket, with error frames
+    /// split out as typed events.
+    ///
+    /// Error frames are only received if the error filter of the socket
+    /// is enabled, such as with `set_error_filter_accept_all()`.
+    pub fn events(&mut self) -> impl Stream<Item = Result<CanEvent<CanFrame>>> + '_ {
+        self.map(|res| res.map(CanEvent::from))
+    }
 }
 
 impl Stream for CanSocket {
@@ -199,6 +247,21 @@ impl CanFdSocket {
             .async_io(Interest::READABLE, |inner| inner.read_frame())
             .await
     }
+
+    /// Reads a frame from the socket asynchronously, with any error
+    /// frame split out as a typed event.
+    pub async fn read_event(&self) -> IoResult<CanEvent<CanAnyFrame>> {
+        self.read_frame().await.map(CanEvent::from)
+    }
+
+    /// Gets a stream of the frames read from the socket, with error frames
+    /// split out as typed events.
+    ///
+    /// Error frames are only received if the error filter of the socket
+    /// is enabled, such as with `set_error_filter_accept_all()`.
+    pub fn events(&mut self) -> impl Stream<Item = Result<CanEvent<CanAnyFrame>>> + '_ {
+        self.map(|res| res.map(CanEvent::from))
+    }
 }
 
 impl Stream for CanFdSocket {
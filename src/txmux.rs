@@ -0,0 +1,346 @@
+// socketcan/src/txmux.rs
+//
+// Merging of frames from many producers into a single socket writer.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! Merging of frames from many producers into a single socket writer.
+//!
+//! In a multi-threaded application, sharing a socket between threads
+//! usually means a mutex around it, and every sender waits its turn for
+//! the write to finish. A [`TxMux`] instead owns the socket on a writer
+//! thread, and hands out [`TxHandle`]s that any number of threads can use
+//! to queue frames. A handle is cheap to clone, and sending through it
+//! never blocks on the socket, as it only puts the frame on a lock-free
+//! channel.
+//!
+//! The writer takes whatever frames are waiting, and decides which to
+//! write next by its [`Scheduling`]: round-robin between the handles, so
+//! a chatty thread can't starve a quiet one, or strictly by bus priority,
+//! so urgent IDs jump ahead of a backlog.
+//!
+//! ```no_run
+//! use socketcan::{
+//!     backend::CanTransmitter,
+//!     txmux::{Scheduling, TxMux},
+//!     CanFrame, CanSocket, Frame, Socket,
+//! };
+//! use std::thread;
+//!
+//! let sock = CanSocket::open("can0").unwrap();
+//! let mux = TxMux::<CanFrame>::spawn(sock, Scheduling::Priority).unwrap();
+//!
+//! let workers: Vec<_> = (0..4u32)
+//!     .map(|i| {
+//!         let tx = mux.handle();
+//!         thread::spawn(move || {
+//!             let frame = CanFrame::from_raw_id(0x100 + i, &[1, 2, 3]).unwrap();
+//!             tx.transmit(&frame).unwrap();
+//!         })
+//!     })
+//!     .collect();
+//!
+//! for w in workers {
+//!     w.join().unwrap();
+//! }
+//! ```
+
+use crate::{backend::CanTransmitter, txqueue::TxQueue, Frame, IoErrorKind, IoResult};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        mpsc::{self, RecvTimeoutError},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// How often the idle writer thread checks whether it should stop.
+pub const STOP_POLL: Duration = Duration::from_millis(100);
+
+/// How the writer picks the next frame among those waiting.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Scheduling {
+    /// Take one frame from each handle with frames waiting, in turn.
+    /// Each handle's frames are sent in the order they were queued.
+    #[default]
+    Fair,
+    /// Take the waiting frame with the highest bus priority. Frames with
+    /// the same priority are sent in the order they were queued.
+    Priority,
+}
+
+/// Counts of the frames through the writer.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TxMuxStats {
+    /// The number of frames written to the socket
+    pub sent: u64,
+    /// The number of frames that failed to write, and were dropped
+    pub failed: u64,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    sent: AtomicU64,
+    failed: AtomicU64,
+}
+
+// ===== Pending =====
+
+/// The frames waiting for the writer.
+#[derive(Debug)]
+enum Pending<F> {
+    Fair {
+        queues: HashMap<usize, VecDeque<F>>,
+        turns: VecDeque<usize>,
+    },
+    Priority(TxQueue<F>),
+}
+
+impl<F: Frame> Pending<F> {
+    fn new(sched: Scheduling) -> Self {
+        match sched {
+            Scheduling::Fair => Pending::Fair {
+                queues: HashMap::new(),
+                turns: VecDeque::new(),
+            },
+            Scheduling::Priority => Pending::Priority(TxQueue::new()),
+        }
+    }
+
+    fn push(&mut self, producer: usize, frame: F) {
+        match self {
+            Pending::Fair { queues, turns } => {
+                let queue = queues.entry(producer).or_default();
+                if queue.is_empty() {
+                    turns.push_back(producer);
+                }
+                queue.push_back(frame);
+            }
+            Pending::Priority(queue) => {
+                queue.push(frame);
+            }
+        }
+    }
+
+    fn pop(&mut self) -> Option<F> {
+        match self {
+            Pending::Fair { queues, turns } => {
+                let producer = turns.pop_front()?;
+                let queue = queues.get_mut(&producer)?;
+                let frame = queue.pop_front();
+                if queue.is_empty() {
+                    queues.remove(&producer);
+                } else {
+                    turns.push_back(producer);
+                }
+                frame
+            }
+            Pending::Priority(queue) => queue.pop(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        match self {
+            Pending::Fair { turns, .. } => turns.is_empty(),
+            Pending::Priority(queue) => queue.is_empty(),
+        }
+    }
+}
+
+// ===== TxHandle =====
+
+/// A handle to queue frames for a [`TxMux`].
+///
+/// Each clone is a separate producer, as far as fair scheduling goes.
+#[derive(Debug)]
+pub struct TxHandle<F> {
+    id: usize,
+    next_id: Arc<AtomicUsize>,
+    tx: mpsc::Sender<(usize, F)>,
+}
+
+impl<F> Clone for TxHandle<F> {
+    fn clone(&self) -> Self {
+        Self {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            next_id: Arc::clone(&self.next_id),
+            tx: self.tx.clone(),
+        }
+    }
+}
+
+impl<F> TxHandle<F> {
+    /// Queues a frame for the writer, without waiting for it to be sent.
+    ///
+    /// This fails with `BrokenPipe` if the writer has stopped.
+    pub fn send(&self, frame: F) -> IoResult<()> {
+        self.tx
+            .send((self.id, frame))
+            .map_err(|_| IoErrorKind::BrokenPipe.into())
+    }
+}
+
+impl<F: Clone> CanTransmitter<F> for TxHandle<F> {
+    /// Queues the frame for the writer. A successful return means that
+    /// the frame was queued, not that it was sent.
+    fn transmit(&self, frame: &F) -> IoResult<()> {
+        self.send(frame.clone())
+    }
+}
+
+// ===== TxMux =====
+
+/// A writer thread that owns a socket, and merges the frames queued by
+/// any number of [`TxHandle`]s into it.
+///
+/// A frame that fails to write is counted and dropped. When this is
+/// dropped, the frames already queued are written, then the thread stops.
+#[derive(Debug)]
+pub struct TxMux<F> {
+    handle: TxHandle<F>,
+    stop: Arc<AtomicBool>,
+    counters: Arc<Counters>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl<F: Frame + Send + 'static> TxMux<F> {
+    /// Starts a writer thread for the socket, or any other transmitter.
+    pub fn spawn<T>(sock: T, sched: Scheduling) -> IoResult<Self>
+    where
+        T: CanTransmitter<F> + Send + 'static,
+    {
+        let (tx, rx) = mpsc::channel::<(usize, F)>();
+        let stop = Arc::new(AtomicBool::new(false));
+        let counters = Arc::new(Counters::default());
+
+        let thr_stop = Arc::clone(&stop);
+        let thr_counters = Arc::clone(&counters);
+        let thread = thread::Builder::new()
+            .name("tx-mux".into())
+            .spawn(move || {
+                let mut pending = Pending::new(sched);
+                loop {
+                    // Wait for work when there's none, then take all that's
+                    // waiting, so the scheduling sees every candidate.
+                    if pending.is_empty() {
+                        match rx.recv_timeout(STOP_POLL) {
+                            Ok((id, frame)) => pending.push(id, frame),
+                            Err(RecvTimeoutError::Timeout) if !thr_stop.load(Ordering::Acquire) => {
+                                continue
+                            }
+                            Err(_) => break,
+                        }
+                    }
+                    while let Ok((id, frame)) = rx.try_recv() {
+                        pending.push(id, frame);
+                    }
+
+                    if let Some(frame) = pending.pop() {
+                        match sock.transmit(&frame) {
+                            Ok(()) => thr_counters.sent.fetch_add(1, Ordering::Relaxed),
+                            Err(err) => {
+                                log::warn!("TxMux failed to send frame: {}", err);
+                                thr_counters.failed.fetch_add(1, Ordering::Relaxed)
+                            }
+                        };
+                    }
+                }
+            })?;
+
+        Ok(Self {
+            handle: TxHandle {
+                id: 0,
+                next_id: Arc::new(AtomicUsize::new(1)),
+                tx,
+            },
+            stop,
+            counters,
+            thread: Some(thread),
+        })
+    }
+
+    /// Gets a new handle to queue frames.
+    pub fn handle(&self) -> TxHandle<F> {
+        self.handle.clone()
+    }
+
+    /// Gets the counts of frames through the writer.
+    pub fn stats(&self) -> TxMuxStats {
+        TxMuxStats {
+            sent: self.counters.sent.load(Ordering::Relaxed),
+            failed: self.counters.failed.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl<F> Drop for TxMux<F> {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{backend::CanReceiver, vbus::VirtualBus, CanAnyFrame, CanFrame};
+
+    fn frame(id: u32) -> CanAnyFrame {
+        CanFrame::from_raw_id(id, &[]).unwrap().into()
+    }
+
+    #[test]
+    fn test_fair_scheduling() {
+        let mut pending = Pending::new(Scheduling::Fair);
+        for id in [0x100, 0x101, 0x102] {
+            pending.push(1, frame(id));
+        }
+        pending.push(2, frame(0x200));
+        pending.push(3, frame(0x300));
+
+        let order: Vec<u32> = std::iter::from_fn(|| pending.pop())
+            .map(|f| f.raw_id())
+            .collect();
+        assert_eq!(vec![0x100, 0x200, 0x300, 0x101, 0x102], order);
+    }
+
+    #[test]
+    fn test_tx_mux() {
+        let bus = VirtualBus::new();
+        let rx = bus.endpoint();
+        let mux = TxMux::spawn(bus.endpoint(), Scheduling::Priority).unwrap();
+
+        let threads: Vec<_> = (0..4u32)
+            .map(|i| {
+                let tx = mux.handle();
+                thread::spawn(move || {
+                    for j in 0..25 {
+                        tx.transmit(&frame(0x100 + i * 0x10 + j)).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for thr in threads {
+            thr.join().unwrap();
+        }
+        drop(mux);
+
+        let mut n = 0;
+        while rx.receive_timeout(Duration::from_millis(10)).is_ok() {
+            n += 1;
+        }
+        assert_eq!(100, n);
+    }
+}
@@ -0,0 +1,294 @@
+// socketcan/src/txqueue.rs
+//
+// A software transmit queue ordered by bus priority.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! A software transmit queue ordered by bus priority.
+//!
+//! The kernel sends the frames written to a socket in the order they were
+//! written. When the bus is busy and frames back up, that lets a burst of
+//! low-priority frames hold up an urgent one. The [`TxQueue`] holds the
+//! pending frames in userspace, ordered the way the bus would arbitrate
+//! them, and feeds them to the socket only as fast as it takes them.
+//!
+//! IDs can also be marked for freshness replacement. A cyclic message
+//! that's still waiting when the next value is queued then has its frame
+//! replaced, rather than both being sent, so that stale values don't pile
+//! up behind a congested bus.
+//!
+//! ```no_run
+//! use socketcan::{txqueue::TxQueue, CanFrame, CanId, CanSocket, Frame, Socket};
+//! use std::time::Duration;
+//!
+//! let sock = CanSocket::open("can0").unwrap();
+//! sock.set_nonblocking(true).unwrap();
+//!
+//! let mut queue = TxQueue::new().with_replace(CanId::standard(0x200).unwrap());
+//! queue.push(CanFrame::from_raw_id(0x300, &[1, 2, 3]).unwrap());
+//! queue.push(CanFrame::from_raw_id(0x200, &[0]).unwrap());
+//! queue.push(CanFrame::from_raw_id(0x200, &[1]).unwrap());
+//!
+//! // Sends 0x200 [1], then 0x300
+//! queue.drain(&sock, Duration::from_millis(100)).unwrap();
+//! ```
+
+use crate::{backend::CanTransmitter, CanId, Frame, IoError, IoResult, ShouldRetry};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    os::{raw::c_int, unix::io::AsRawFd},
+    time::{Duration, Instant},
+};
+
+/// What happened to a frame pushed onto the queue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pushed {
+    /// The frame was added to the queue
+    Queued,
+    /// The frame replaced a pending frame with the same ID
+    Replaced,
+}
+
+// ===== TxQueue =====
+
+/// A queue of frames waiting to be sent, in order of bus priority.
+///
+/// Frames with the same priority are sent in the order they were pushed.
+#[derive(Debug, Clone)]
+pub struct TxQueue<F> {
+    frames: BTreeMap<(u32, u64), F>,
+    pending: HashMap<CanId, (u32, u64)>,
+    replace: HashSet<CanId>,
+    replace_all: bool,
+    seq: u64,
+}
+
+impl<F> Default for TxQueue<F> {
+    fn default() -> Self {
+        Self {
+            frames: BTreeMap::new(),
+            pending: HashMap::new(),
+            replace: HashSet::new(),
+            replace_all: false,
+            seq: 0,
+        }
+    }
+}
+
+impl<F: Frame> TxQueue<F> {
+    /// Creates an empty queue.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Marks an ID for freshness replacement: a new frame with the ID
+    /// replaces one that's still waiting, rather than queuing behind it.
+    pub fn with_replace(mut self, id: CanId) -> Self {
+        self.replace.insert(id);
+        self
+    }
+
+    /// Marks every ID for freshness replacement.
+    pub fn with_replace_all(mut self) -> Self {
+        self.replace_all = true;
+        self
+    }
+
+    /// Determines if frames with the ID get replaced.
+    fn is_replaced(&self, id: &CanId) -> bool {
+        self.replace_all || self.replace.contains(id)
+    }
+
+    /// Adds a frame to the queue.
+    ///
+    /// If its ID is marked for replacement and a frame with it is still
+    /// waiting, the new frame takes its place in the queue.
+    pub fn push(&mut self, frame: F) -> Pushed {
+        let id = frame.can_id();
+        if self.is_replaced(&id) {
+            if let Some(key) = self.pending.get(&id) {
+                self.frames.insert(*key, frame);
+                return Pushed::Replaced;
+            }
+        }
+
+        let key = (frame.priority(), self.seq);
+        self.seq += 1;
+        if self.is_replaced(&id) {
+            self.pending.insert(id, key);
+        }
+        self.frames.insert(key, frame);
+        Pushed::Queued
+    }
+
+    /// Gets the frame that would be sent next.
+    pub fn peek(&self) -> Option<&F> {
+        self.frames.values().next()
+    }
+
+    /// Removes the frame that would be sent next.
+    pub fn pop(&mut self) -> Option<F> {
+        let (_, frame) = self.frames.pop_first()?;
+        self.pending.remove(&frame.can_id());
+        Some(frame)
+    }
+
+    /// The number of frames waiting.
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Determines if there are no frames waiting.
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Removes all the waiting frames.
+    pub fn clear(&mut self) {
+        self.frames.clear();
+        self.pending.clear();
+    }
+
+    /// Writes frames to the socket until the queue is empty or the socket
+    /// can't take any more, without blocking.
+    ///
+    /// The socket should be in non-blocking mode. A frame that the socket
+    /// doesn't take stays at the head of the queue.
+    ///
+    /// Returns the number of frames written.
+    pub fn flush<S>(&mut self, sock: &S) -> IoResult<usize>
+    where
+        S: CanTransmitter<F> + ?Sized,
+    {
+        let mut n = 0;
+        while let Some(frame) = self.peek() {
+            match sock.try_transmit(frame) {
+                Ok(()) => {
+                    self.pop();
+                    n += 1;
+                }
+                Err(err) if is_full(&err) => break,
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(n)
+    }
+
+    /// Writes frames to the socket until the queue is empty, waiting for
+    /// the socket to become writable (POLLOUT) when it's full, for up to
+    /// the timeout in all.
+    ///
+    /// Returns the number of frames written. Any that couldn't be sent in
+    /// time are left in the queue.
+    pub fn drain<S>(&mut self, sock: &S, timeout: Duration) -> IoResult<usize>
+    where
+        S: CanTransmitter<F> + AsRawFd,
+    {
+        use nix::poll::{poll, PollFd, PollFlags};
+
+        let deadline = Instant::now() + timeout;
+        let mut n = 0;
+
+        loop {
+            n += self.flush(sock)?;
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if self.is_empty() || remaining.is_zero() {
+                return Ok(n);
+            }
+            let pollfd = PollFd::new(sock.as_raw_fd(), PollFlags::POLLOUT);
+            if poll(&mut [pollfd], remaining.as_millis().max(1) as c_int)? == 0 {
+                return Ok(n);
+            }
+        }
+    }
+}
+
+/// Determines if a write failed because the socket's queue is full.
+///
+/// CAN_RAW sockets report this as `ENOBUFS` rather than `EAGAIN`.
+fn is_full(err: &IoError) -> bool {
+    err.should_retry() || err.raw_os_error() == Some(libc::ENOBUFS)
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CanFrame, CanRemoteFrame, EmbeddedFrame, Frame, StandardId};
+
+    #[test]
+    fn test_priority_order() {
+        let mut queue = TxQueue::new();
+        queue.push(CanFrame::from_raw_id(0x300, &[]).unwrap());
+        queue.push(CanFrame::from_raw_id(0x100 << 18, &[]).unwrap());
+        queue.push(CanFrame::Remote(
+            CanRemoteFrame::new_remote(StandardId::new(0x100).unwrap(), 0).unwrap(),
+        ));
+        queue.push(CanFrame::from_raw_id(0x100, &[1]).unwrap());
+        queue.push(CanFrame::from_raw_id(0x100, &[2]).unwrap());
+
+        let order: Vec<_> = std::iter::from_fn(|| queue.pop())
+            .map(|frame| (frame.id_word(), frame.data().to_vec()))
+            .collect();
+        assert_eq!(
+            vec![
+                (0x100, vec![1]),
+                (0x100, vec![2]),
+                (0x100 | libc::CAN_RTR_FLAG, vec![]),
+                ((0x100 << 18) | libc::CAN_EFF_FLAG, vec![]),
+                (0x300, vec![]),
+            ],
+            order
+        );
+    }
+
+    #[test]
+    fn test_replace() {
+        let mut queue = TxQueue::new().with_replace(CanId::standard(0x200).unwrap());
+        assert_eq!(
+            Pushed::Queued,
+            queue.push(CanFrame::from_raw_id(0x200, &[0]).unwrap())
+        );
+        queue.push(CanFrame::from_raw_id(0x100, &[0]).unwrap());
+        assert_eq!(
+            Pushed::Replaced,
+            queue.push(CanFrame::from_raw_id(0x200, &[1]).unwrap())
+        );
+        queue.push(CanFrame::from_raw_id(0x300, &[0]).unwrap());
+        queue.push(CanFrame::from_raw_id(0x300, &[1]).unwrap());
+        assert_eq!(4, queue.len());
+
+        queue.pop();
+        assert_eq!(&[1], queue.pop().unwrap().data());
+        // Once sent, the next value is queued again
+        assert_eq!(
+            Pushed::Queued,
+            queue.push(CanFrame::from_raw_id(0x200, &[2]).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_flush() {
+        use crate::{mock::MockCanSocket, IoErrorKind};
+
+        let sock = MockCanSocket::<CanFrame>::new();
+        let mut queue = TxQueue::new();
+        for id in [0x300, 0x100, 0x200] {
+            queue.push(CanFrame::from_raw_id(id, &[]).unwrap());
+        }
+
+        // The backend is full at first
+        sock.fail_tx(IoErrorKind::WouldBlock);
+        assert_eq!(0, queue.flush(&sock).unwrap());
+        assert_eq!(3, queue.flush(&sock).unwrap());
+        assert!(queue.is_empty());
+        let ids: Vec<_> = sock.sent().iter().map(|f| f.raw_id()).collect();
+        assert_eq!(vec![0x100, 0x200, 0x300], ids);
+    }
+}
@@ -0,0 +1,737 @@
+// socketcan/src/uds.rs
+//
+// UDS server for ECU simulation.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! The server side of Unified Diagnostic Services (UDS, ISO 14229).
+//!
+//! This is meant for building ECU simulators, to test diagnostic clients
+//! on a bench or in HIL setups. The [`UdsServer`] answers requests that
+//! arrive over an [`IsoTpSocket`], with handlers registered by service ID,
+//! and keeps the diagnostic session and security state.
+//!
+//! These services are handled by the server itself:
+//!
+//! - DiagnosticSessionControl (0x10), switching between the supported
+//!   sessions, and falling back to the default session when the tester
+//!   stays silent for longer than S3.
+//! - SecurityAccess (0x27), with the seed and key computed by
+//!   user-supplied functions.
+//! - TesterPresent (0x3E).
+//!
+//! Each handler gets the current state and the whole request, starting
+//! with the service ID, and returns a [`UdsReply`]. A handler that needs
+//! more time returns [`UdsReply::Pending`]; the server then sends a
+//! "response pending" (0x78) negative response, and calls the handler again
+//! with the same request until it returns a final reply, repeating the
+//! 0x78 response so the client's P2* timer doesn't expire.
+//!
+//! ```no_run
+//! use socketcan::{
+//!     isotp::IsoTpSocket,
+//!     uds::{Nrc, ServiceAccess, UdsReply, UdsServer, EXTENDED_SESSION},
+//!     StandardId,
+//! };
+//!
+//! let rx_id = StandardId::new(0x7E0).unwrap();
+//! let tx_id = StandardId::new(0x7E8).unwrap();
+//! let sock = IsoTpSocket::open("vcan0", rx_id, tx_id).unwrap();
+//!
+//! let mut server = UdsServer::new();
+//!
+//! // ReadDataByIdentifier: the VIN
+//! server.on_service(0x22, |_, req| match req {
+//!     [_, 0xF1, 0x90] => UdsReply::Positive(b"\xF1\x90WVWZZZ1JZXW000001".to_vec()),
+//!     _ => UdsReply::Negative(Nrc::REQUEST_OUT_OF_RANGE),
+//! });
+//!
+//! // ECUReset, only in the extended session
+//! let access = ServiceAccess::default().with_sessions(&[EXTENDED_SESSION]);
+//! server.on_service_with(0x11, access, |_, req| UdsReply::Positive(vec![req[1]]));
+//!
+//! server.serve(&sock).unwrap();
+//! ```
+
+use crate::{isotp::IsoTpSocket, IoErrorKind, IoResult};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    time::{Duration, Instant},
+};
+
+/// The DiagnosticSessionControl service
+pub const SID_DIAGNOSTIC_SESSION_CONTROL: u8 = 0x10;
+/// The SecurityAccess service
+pub const SID_SECURITY_ACCESS: u8 = 0x27;
+/// The TesterPresent service
+pub const SID_TESTER_PRESENT: u8 = 0x3E;
+/// The service ID of negative responses
+pub const SID_NEGATIVE_RESPONSE: u8 = 0x7F;
+
+/// The offset from a request SID to its positive response SID
+const POSITIVE_RESPONSE_OFFSET: u8 = 0x40;
+
+/// The bit in a sub-function to suppress the positive response
+const SUPPRESS_POS_RESPONSE: u8 = 0x80;
+
+/// The default diagnostic session
+pub const DEFAULT_SESSION: u8 = 0x01;
+/// The programming session
+pub const PROGRAMMING_SESSION: u8 = 0x02;
+/// The extended diagnostic session
+pub const EXTENDED_SESSION: u8 = 0x03;
+
+/// The default P2 server time, to start a response
+pub const DEFAULT_P2: Duration = Duration::from_millis(50);
+/// The default P2* server time, to respond after a pending response
+pub const DEFAULT_P2_STAR: Duration = Duration::from_millis(5000);
+/// The default S3 server time, to fall back to the default session
+pub const DEFAULT_S3: Duration = Duration::from_millis(5000);
+
+/// A UDS negative response code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Nrc(pub u8);
+
+impl Nrc {
+    /// General reject
+    pub const GENERAL_REJECT: Self = Self(0x10);
+    /// Service not supported
+    pub const SERVICE_NOT_SUPPORTED: Self = Self(0x11);
+    /// Sub-function not supported
+    pub const SUBFUNCTION_NOT_SUPPORTED: Self = Self(0x12);
+    /// Incorrect message length or invalid format
+    pub const INCORRECT_LENGTH: Self = Self(0x13);
+    /// Busy, repeat request
+    pub const BUSY_REPEAT_REQUEST: Self = Self(0x21);
+    /// Conditions not correct
+    pub const CONDITIONS_NOT_CORRECT: Self = Self(0x22);
+    /// Request sequence error
+    pub const REQUEST_SEQUENCE_ERROR: Self = Self(0x24);
+    /// Request out of range
+    pub const REQUEST_OUT_OF_RANGE: Self = Self(0x31);
+    /// Security access denied
+    pub const SECURITY_ACCESS_DENIED: Self = Self(0x33);
+    /// Invalid key
+    pub const INVALID_KEY: Self = Self(0x35);
+    /// Exceeded number of attempts
+    pub const EXCEEDED_ATTEMPTS: Self = Self(0x36);
+    /// Required time delay not expired
+    pub const TIME_DELAY_NOT_EXPIRED: Self = Self(0x37);
+    /// Request correctly received, response pending
+    pub const RESPONSE_PENDING: Self = Self(0x78);
+    /// Sub-function not supported in the active session
+    pub const SUBFUNCTION_NOT_SUPPORTED_IN_SESSION: Self = Self(0x7E);
+    /// Service not supported in the active session
+    pub const SERVICE_NOT_SUPPORTED_IN_SESSION: Self = Self(0x7F);
+
+    /// Gets the description of the code, if it's a common one.
+    pub fn description(&self) -> Option<&'static str> {
+        let s = match *self {
+            Self::GENERAL_REJECT => "General reject",
+            Self::SERVICE_NOT_SUPPORTED => "Service not supported",
+            Self::SUBFUNCTION_NOT_SUPPORTED => "Sub-function not supported",
+            Self::INCORRECT_LENGTH => "Incorrect message length or invalid format",
+            Self::BUSY_REPEAT_REQUEST => "Busy, repeat request",
+            Self::CONDITIONS_NOT_CORRECT => "Conditions not correct",
+            Self::REQUEST_SEQUENCE_ERROR => "Request sequence error",
+            Self::REQUEST_OUT_OF_RANGE => "Request out of range",
+            Self::SECURITY_ACCESS_DENIED => "Security access denied",
+            Self::INVALID_KEY => "Invalid key",
+            Self::EXCEEDED_ATTEMPTS => "Exceeded number of attempts",
+            Self::TIME_DELAY_NOT_EXPIRED => "Required time delay not expired",
+            Self::RESPONSE_PENDING => "Response pending",
+            Self::SUBFUNCTION_NOT_SUPPORTED_IN_SESSION => {
+                "Sub-function not supported in active session"
+            }
+            Self::SERVICE_NOT_SUPPORTED_IN_SESSION => "Service not supported in active session",
+            _ => return None,
+        };
+        Some(s)
+    }
+}
+
+impl fmt::Display for Nrc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.description() {
+            Some(s) => write!(f, "{} ({:02X}h)", s, self.0),
+            None => write!(f, "NRC {:02X}h", self.0),
+        }
+    }
+}
+
+/// The reply of a service handler to a request.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum UdsReply {
+    /// A positive response, with the data after the response service ID
+    Positive(Vec<u8>),
+    /// A negative response
+    Negative(Nrc),
+    /// The response isn't ready yet, so the handler should be called again
+    Pending,
+    /// No response is sent
+    NoResponse,
+}
+
+/// The diagnostic state of the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct UdsState {
+    /// The active diagnostic session
+    pub session: u8,
+    /// The unlocked security level, as the sub-function of its seed
+    /// request, if any
+    pub security_level: Option<u8>,
+}
+
+impl Default for UdsState {
+    fn default() -> Self {
+        Self {
+            session: DEFAULT_SESSION,
+            security_level: None,
+        }
+    }
+}
+
+/// The conditions for a service to be accepted.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct ServiceAccess {
+    sessions: Vec<u8>,
+    security_level: Option<u8>,
+}
+
+impl ServiceAccess {
+    /// Only accepts the service in these sessions.
+    pub fn with_sessions(mut self, sessions: &[u8]) -> Self {
+        self.sessions = sessions.to_vec();
+        self
+    }
+
+    /// Only accepts the service once the security level is unlocked.
+    pub fn with_security_level(mut self, level: u8) -> Self {
+        self.security_level = Some(level);
+        self
+    }
+
+    /// Checks the request against the state of the server.
+    fn check(&self, state: &UdsState) -> Result<(), Nrc> {
+        if !self.sessions.is_empty() && !self.sessions.contains(&state.session) {
+            Err(Nrc::SERVICE_NOT_SUPPORTED_IN_SESSION)
+        } else if self.security_level.is_some() && self.security_level != state.security_level {
+            Err(Nrc::SECURITY_ACCESS_DENIED)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// A service handler.
+type Handler<'a> = Box<dyn FnMut(&UdsState, &[u8]) -> UdsReply + 'a>;
+
+/// A function creating the seed for a security level.
+type SeedFn<'a> = Box<dyn FnMut(u8) -> Vec<u8> + 'a>;
+
+/// A function computing the expected key for a security level and seed.
+type KeyFn<'a> = Box<dyn FnMut(u8, &[u8]) -> Vec<u8> + 'a>;
+
+/// The seed and key functions for security access, and the state of an
+/// unlock attempt.
+struct Security<'a> {
+    seed: SeedFn<'a>,
+    key: KeyFn<'a>,
+    /// The level and seed of the last seed request
+    requested: Option<(u8, Vec<u8>)>,
+    /// The number of failed attempts
+    attempts: u32,
+    /// The time when the lockout after too many attempts ends
+    locked_until: Option<Instant>,
+}
+
+/// A request waiting for its handler to finish.
+#[derive(Debug)]
+struct Pending {
+    req: Vec<u8>,
+    next_nrc: Instant,
+}
+
+// ===== UdsServer =====
+
+/// A UDS server, answering diagnostic requests like an ECU.
+pub struct UdsServer<'a> {
+    services: BTreeMap<u8, (ServiceAccess, Handler<'a>)>,
+    sessions: Vec<u8>,
+    security: Option<Security<'a>>,
+    max_attempts: u32,
+    lockout: Duration,
+    state: UdsState,
+    p2: Duration,
+    p2_star: Duration,
+    s3: Duration,
+    last_request: Option<Instant>,
+    pending: Option<Pending>,
+}
+
+impl fmt::Debug for UdsServer<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UdsServer")
+            .field("services", &self.services.keys().collect::<Vec<_>>())
+            .field("sessions", &self.sessions)
+            .field("security", &self.security.is_some())
+            .field("state", &self.state)
+            .field("pending", &self.pending)
+            .finish()
+    }
+}
+
+impl Default for UdsServer<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> UdsServer<'a> {
+    /// Creates a server supporting the default, programming and extended
+    /// sessions, with the default timing.
+    pub fn new() -> Self {
+        Self {
+            services: BTreeMap::new(),
+            sessions: vec![DEFAULT_SESSION, PROGRAMMING_SESSION, EXTENDED_SESSION],
+            security: None,
+            max_attempts: 3,
+            lockout: Duration::from_secs(10),
+            state: UdsState::default(),
+            p2: DEFAULT_P2,
+            p2_star: DEFAULT_P2_STAR,
+            s3: DEFAULT_S3,
+            last_request: None,
+            pending: None,
+        }
+    }
+
+    /// Sets the supported sessions.
+    ///
+    /// The default session is always supported.
+    pub fn with_sessions(mut self, sessions: &[u8]) -> Self {
+        self.sessions = sessions.to_vec();
+        if !self.sessions.contains(&DEFAULT_SESSION) {
+            self.sessions.push(DEFAULT_SESSION);
+        }
+        self
+    }
+
+    /// Sets the P2 and P2* server times, reported to the client when the
+    /// session changes.
+    pub fn with_timing(mut self, p2: Duration, p2_star: Duration) -> Self {
+        self.p2 = p2;
+        self.p2_star = p2_star;
+        self
+    }
+
+    /// Sets the S3 time, after which a silent client drops back to the
+    /// default session.
+    pub fn with_s3(mut self, s3: Duration) -> Self {
+        self.s3 = s3;
+        self
+    }
+
+    /// Enables security access.
+    ///
+    /// The `seed` function creates the seed for a level, and the `key`
+    /// function computes the expected key for the level and seed. Levels
+    /// are identified by the odd sub-function of their seed request.
+    pub fn with_security<S, K>(mut self, seed: S, key: K) -> Self
+    where
+        S: FnMut(u8) -> Vec<u8> + 'a,
+        K: FnMut(u8, &[u8]) -> Vec<u8> + 'a,
+    {
+        self.security = Some(Security {
+            seed: Box::new(seed),
+            key: Box::new(key),
+            requested: None,
+            attempts: 0,
+            locked_until: None,
+        });
+        self
+    }
+
+    /// Sets the number of invalid keys before security access is locked,
+    /// and for how long it's locked.
+    pub fn with_max_attempts(mut self, max_attempts: u32, lockout: Duration) -> Self {
+        self.max_attempts = max_attempts;
+        self.lockout = lockout;
+        self
+    }
+
+    /// Registers the handler for a service, accepted in any session.
+    pub fn on_service<H>(&mut self, sid: u8, handler: H)
+    where
+        H: FnMut(&UdsState, &[u8]) -> UdsReply + 'a,
+    {
+        self.on_service_with(sid, ServiceAccess::default(), handler)
+    }
+
+    /// Registers the handler for a service, accepted only under the
+    /// conditions.
+    ///
+    /// This replaces any previous handler for the service, including the
+    /// ones built into the server.
+    pub fn on_service_with<H>(&mut self, sid: u8, access: ServiceAccess, handler: H)
+    where
+        H: FnMut(&UdsState, &[u8]) -> UdsReply + 'a,
+    {
+        self.services.insert(sid, (access, Box::new(handler)));
+    }
+
+    /// Gets the diagnostic state.
+    pub fn state(&self) -> &UdsState {
+        &self.state
+    }
+
+    /// Determines if a request is waiting for its handler to finish.
+    pub fn is_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Drops back to the default session, locking security access.
+    fn change_session(&mut self, session: u8) {
+        self.state.session = session;
+        self.state.security_level = None;
+        if let Some(sec) = self.security.as_mut() {
+            sec.requested = None;
+        }
+    }
+
+    /// Handles a request, returning the response to send, if any.
+    pub fn handle(&mut self, req: &[u8], now: Instant) -> Option<Vec<u8>> {
+        let sid = *req.first()?;
+        self.last_request = Some(now);
+
+        if self.pending.is_some() {
+            return Self::response(sid, UdsReply::Negative(Nrc::BUSY_REPEAT_REQUEST));
+        }
+
+        let reply = if let Some((access, handler)) = self.services.get_mut(&sid) {
+            match access.check(&self.state) {
+                Ok(()) => handler(&self.state, req),
+                Err(nrc) => UdsReply::Negative(nrc),
+            }
+        } else {
+            match sid {
+                SID_DIAGNOSTIC_SESSION_CONTROL => self.session_control(req),
+                SID_SECURITY_ACCESS if self.security.is_some() => self.security_access(req, now),
+                SID_TESTER_PRESENT => Self::tester_present(req),
+                _ => UdsReply::Negative(Nrc::SERVICE_NOT_SUPPORTED),
+            }
+        };
+
+        if reply == UdsReply::Pending {
+            self.pending = Some(Pending {
+                req: req.to_vec(),
+                next_nrc: now + self.p2_star / 2,
+            });
+        }
+        Self::response(sid, reply)
+    }
+
+    /// Services the pending request and the session timeout, returning a
+    /// response to send, if any.
+    ///
+    /// This should be called regularly, and often while a request is
+    /// pending.
+    pub fn poll(&mut self, now: Instant) -> Option<Vec<u8>> {
+        if let Some(mut pending) = self.pending.take() {
+            let sid = pending.req[0];
+            let reply = match self.services.get_mut(&sid) {
+                Some((_, handler)) => handler(&self.state, &pending.req),
+                None => UdsReply::Negative(Nrc::GENERAL_REJECT),
+            };
+            if reply != UdsReply::Pending {
+                return Self::response(sid, reply);
+            }
+            let resend = now >= pending.next_nrc;
+            if resend {
+                pending.next_nrc = now + self.p2_star / 2;
+            }
+            self.pending = Some(pending);
+            return if resend {
+                Self::response(sid, reply)
+            } else {
+                None
+            };
+        }
+
+        if self.state.session != DEFAULT_SESSION {
+            if let Some(last) = self.last_request {
+                if now.saturating_duration_since(last) >= self.s3 {
+                    self.change_session(DEFAULT_SESSION);
+                }
+            }
+        }
+        None
+    }
+
+    /// Waits for a request, for at most the timeout, and answers it.
+    pub fn serve_once(&mut self, sock: &IsoTpSocket, timeout: Duration) -> IoResult<()> {
+        match sock.read_timeout(timeout) {
+            Ok(req) => {
+                if let Some(resp) = self.handle(&req, Instant::now()) {
+                    sock.write(&resp)?;
+                }
+            }
+            Err(err) if err.kind() == IoErrorKind::TimedOut => (),
+            Err(err) => return Err(err),
+        }
+        if let Some(resp) = self.poll(Instant::now()) {
+            sock.write(&resp)?;
+        }
+        Ok(())
+    }
+
+    /// Answers requests on the socket until there's an error.
+    pub fn serve(&mut self, sock: &IsoTpSocket) -> IoResult<()> {
+        loop {
+            let timeout = if self.is_pending() {
+                Duration::from_millis(5)
+            } else {
+                Duration::from_millis(50)
+            };
+            self.serve_once(sock, timeout)?;
+        }
+    }
+
+    /// Creates the response message for the reply.
+    fn response(sid: u8, reply: UdsReply) -> Option<Vec<u8>> {
+        match reply {
+            UdsReply::Positive(data) => match sid.checked_add(POSITIVE_RESPONSE_OFFSET) {
+                Some(resp_sid) => {
+                    let mut resp = Vec::with_capacity(data.len() + 1);
+                    resp.push(resp_sid);
+                    resp.extend(data);
+                    Some(resp)
+                }
+                // A response SID can't be answered positively
+                None => Self::response(sid, UdsReply::Negative(Nrc::SERVICE_NOT_SUPPORTED)),
+            },
+            UdsReply::Negative(nrc) => Some(vec![SID_NEGATIVE_RESPONSE, sid, nrc.0]),
+            UdsReply::Pending => Some(vec![SID_NEGATIVE_RESPONSE, sid, Nrc::RESPONSE_PENDING.0]),
+            UdsReply::NoResponse => None,
+        }
+    }
+
+    /// Applies the suppress positive response bit of the request.
+    fn suppressible(req: &[u8], reply: UdsReply) -> UdsReply {
+        match reply {
+            UdsReply::Positive(_) if req[1] & SUPPRESS_POS_RESPONSE != 0 => UdsReply::NoResponse,
+            reply => reply,
+        }
+    }
+
+    /// The DiagnosticSessionControl service.
+    fn session_control(&mut self, req: &[u8]) -> UdsReply {
+        if req.len() != 2 {
+            return UdsReply::Negative(Nrc::INCORRECT_LENGTH);
+        }
+        let session = req[1] & !SUPPRESS_POS_RESPONSE;
+        if !self.sessions.contains(&session) {
+            return UdsReply::Negative(Nrc::SUBFUNCTION_NOT_SUPPORTED);
+        }
+        self.change_session(session);
+
+        let p2 = self.p2.as_millis().min(0xFFFF) as u16;
+        let p2_star = (self.p2_star.as_millis() / 10).min(0xFFFF) as u16;
+        let mut data = vec![session];
+        data.extend(p2.to_be_bytes());
+        data.extend(p2_star.to_be_bytes());
+        Self::suppressible(req, UdsReply::Positive(data))
+    }
+
+    /// The TesterPresent service.
+    fn tester_present(req: &[u8]) -> UdsReply {
+        if req.len() != 2 {
+            UdsReply::Negative(Nrc::INCORRECT_LENGTH)
+        } else if req[1] & !SUPPRESS_POS_RESPONSE != 0 {
+            UdsReply::Negative(Nrc::SUBFUNCTION_NOT_SUPPORTED)
+        } else {
+            Self::suppressible(req, UdsReply::Positive(vec![0]))
+        }
+    }
+
+    /// The SecurityAccess service.
+    fn security_access(&mut self, req: &[u8], now: Instant) -> UdsReply {
+        let sec = match self.security.as_mut() {
+            Some(sec) => sec,
+            None => return UdsReply::Negative(Nrc::SERVICE_NOT_SUPPORTED),
+        };
+        if req.len() < 2 {
+            return UdsReply::Negative(Nrc::INCORRECT_LENGTH);
+        }
+        let sub = req[1] & !SUPPRESS_POS_RESPONSE;
+        if sub == 0 || sub == 0x7F {
+            return UdsReply::Negative(Nrc::SUBFUNCTION_NOT_SUPPORTED);
+        }
+
+        if let Some(until) = sec.locked_until {
+            if now < until {
+                return UdsReply::Negative(Nrc::TIME_DELAY_NOT_EXPIRED);
+            }
+            sec.locked_until = None;
+            sec.attempts = 0;
+        }
+
+        if sub % 2 == 1 {
+            // Request seed; a zero seed if the level is already unlocked
+            let mut seed = (sec.seed)(sub);
+            if self.state.security_level == Some(sub) {
+                seed.iter_mut().for_each(|b| *b = 0);
+                sec.requested = None;
+            } else {
+                sec.requested = Some((sub, seed.clone()));
+            }
+            let mut data = vec![sub];
+            data.extend(seed);
+            return UdsReply::Positive(data);
+        }
+
+        // Send key
+        let level = sub - 1;
+        let seed = match sec.requested.take() {
+            Some((req_level, seed)) if req_level == level => seed,
+            _ => return UdsReply::Negative(Nrc::REQUEST_SEQUENCE_ERROR),
+        };
+        if (sec.key)(level, &seed) == req[2..] {
+            sec.attempts = 0;
+            self.state.security_level = Some(level);
+            return Self::suppressible(req, UdsReply::Positive(vec![sub]));
+        }
+
+        sec.attempts += 1;
+        if sec.attempts >= self.max_attempts {
+            sec.locked_until = Some(now + self.lockout);
+            UdsReply::Negative(Nrc::EXCEEDED_ATTEMPTS)
+        } else {
+            UdsReply::Negative(Nrc::INVALID_KEY)
+        }
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    #[test]
+    fn test_sessions() {
+        let now = Instant::now();
+        let mut server = UdsServer::new()
+            .with_s3(Duration::from_secs(5))
+            .with_security(
+                |_| vec![0x12, 0x34],
+                |_, seed| seed.iter().map(|b| b ^ 0xFF).collect(),
+            );
+        let access = ServiceAccess::default()
+            .with_sessions(&[EXTENDED_SESSION])
+            .with_security_level(1);
+        server.on_service_with(0x2E, access, |_, _| UdsReply::Positive(vec![0xF1, 0x90]));
+
+        assert_eq!(
+            Some(vec![0x7F, 0x22, 0x11]),
+            server.handle(&[0x22, 0xF1, 0x90], now)
+        );
+        assert_eq!(
+            Some(vec![0x7F, 0x2E, 0x7F]),
+            server.handle(&[0x2E, 0xF1, 0x90], now)
+        );
+
+        assert_eq!(
+            Some(vec![0x50, 0x03, 0x00, 0x32, 0x01, 0xF4]),
+            server.handle(&[0x10, 0x03], now)
+        );
+        assert_eq!(EXTENDED_SESSION, server.state().session);
+        assert_eq!(
+            Some(vec![0x7F, 0x2E, 0x33]),
+            server.handle(&[0x2E, 0xF1, 0x90], now)
+        );
+
+        // Unlock, with one bad key first
+        assert_eq!(
+            Some(vec![0x7F, 0x27, 0x24]),
+            server.handle(&[0x27, 0x02, 0, 0], now)
+        );
+        assert_eq!(
+            Some(vec![0x67, 0x01, 0x12, 0x34]),
+            server.handle(&[0x27, 0x01], now)
+        );
+        assert_eq!(
+            Some(vec![0x7F, 0x27, 0x35]),
+            server.handle(&[0x27, 0x02, 0, 0], now)
+        );
+        server.handle(&[0x27, 0x01], now);
+        assert_eq!(
+            Some(vec![0x67, 0x02]),
+            server.handle(&[0x27, 0x02, 0xED, 0xCB], now)
+        );
+        assert_eq!(Some(1), server.state().security_level);
+
+        assert_eq!(
+            Some(vec![0x6E, 0xF1, 0x90]),
+            server.handle(&[0x2E, 0xF1, 0x90], now)
+        );
+        assert_eq!(None, server.handle(&[0x3E, 0x80], now));
+
+        // S3 timeout
+        assert_eq!(None, server.poll(now + Duration::from_secs(4)));
+        assert_eq!(EXTENDED_SESSION, server.state().session);
+        server.poll(now + Duration::from_secs(5));
+        assert_eq!(UdsState::default(), *server.state());
+    }
+
+    #[test]
+    fn test_response_sid() {
+        let now = Instant::now();
+        let mut server = UdsServer::new();
+        server.on_service(0xC5, |_, _| UdsReply::Positive(vec![]));
+
+        assert_eq!(Some(vec![0x7F, 0xC5, 0x11]), server.handle(&[0xC5], now));
+    }
+
+    #[test]
+    fn test_pending() {
+        let now = Instant::now();
+        let calls = Cell::new(0);
+        let mut server = UdsServer::new().with_timing(DEFAULT_P2, Duration::from_secs(2));
+        server.on_service(0x31, |_, _| {
+            calls.set(calls.get() + 1);
+            match calls.get() {
+                n if n < 4 => UdsReply::Pending,
+                _ => UdsReply::Positive(vec![0x01, 0xFF, 0x00]),
+            }
+        });
+
+        assert_eq!(
+            Some(vec![0x7F, 0x31, 0x78]),
+            server.handle(&[0x31, 0x01, 0xFF, 0x00], now)
+        );
+        assert!(server.is_pending());
+        assert_eq!(
+            Some(vec![0x7F, 0x3E, 0x21]),
+            server.handle(&[0x3E, 0x00], now)
+        );
+
+        assert_eq!(None, server.poll(now + Duration::from_millis(500)));
+        assert_eq!(
+            Some(vec![0x7F, 0x31, 0x78]),
+            server.poll(now + Duration::from_secs(1))
+        );
+        assert_eq!(
+            Some(vec![0x71, 0x01, 0xFF, 0x00]),
+            server.poll(now + Duration::from_millis(1500))
+        );
+        assert!(!server.is_pending());
+    }
+}
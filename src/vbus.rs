@@ -0,0 +1,408 @@
+// socketcan/src/vbus.rs
+//
+// An in-process virtual CAN bus.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! An in-process virtual CAN bus.
+//!
+//! A [`VirtualBus`] connects any number of [`VirtualEndpoint`]s in the
+//! same process, without the kernel, so it needs neither a `vcan`
+//! interface nor root. Each endpoint implements the [`CanTransmitter`] and
+//! [`CanReceiver`] traits, so it can stand in for a socket when testing
+//! the code that sits on top of one, and a frame sent by one endpoint is
+//! received by all the others.
+//!
+//! The bus can simulate:
+//!
+//! - Arbitration. In manual mode, sent frames wait on the bus until
+//!   [`step`](VirtualBus::step) or [`run`](VirtualBus::run) is called, and
+//!   then go out highest priority first, as they would if the nodes had
+//!   all started sending at once.
+//! - Timing, with a fixed latency, and with the time each frame takes on
+//!   the wire at a bitrate. Frames go out one at a time, so a burst
+//!   spreads out as it would on a real bus.
+//! - Faults, with a callback that can drop a frame, or replace it with an
+//!   error frame.
+//!
+//! ```
+//! use socketcan::{
+//!     backend::{CanReceiver, CanTransmitter},
+//!     vbus::VirtualBus,
+//!     CanFrame, Frame,
+//! };
+//!
+//! let bus = VirtualBus::new();
+//! let a = bus.endpoint();
+//! let b = bus.endpoint();
+//!
+//! let frame = CanFrame::from_raw_id(0x123, &[1, 2, 3]).unwrap();
+//! a.transmit(&frame).unwrap();
+//!
+//! let rx = b.receive().unwrap();
+//! assert_eq!(0x123, rx.raw_id());
+//! ```
+
+use crate::{
+    backend::{CanReceiver, CanTransmitter},
+    errors::{CAN_ERR_BUSERROR, CAN_ERR_PROT},
+    CanAnyFrame, CanErrorFrame, Frame, IoErrorKind, IoResult,
+};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt,
+    sync::{Arc, Condvar, Mutex, Weak},
+    time::{Duration, Instant},
+};
+
+/// What happens to a frame on the bus.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// The frame gets through
+    Pass,
+    /// The frame is lost, without a trace
+    Drop,
+    /// The frame is destroyed, and the receivers get the error frame
+    /// instead
+    Error(CanErrorFrame),
+}
+
+impl Fault {
+    /// A fault that replaces the frame with a protocol error frame, as
+    /// when a bit error corrupts it.
+    pub fn bus_error() -> Self {
+        // The class bits always make a valid error frame
+        Self::Error(CanErrorFrame::new_error(CAN_ERR_PROT | CAN_ERR_BUSERROR, &[0; 8]).unwrap())
+    }
+}
+
+/// A callback that decides the fate of each frame.
+type FaultFn = Box<dyn FnMut(&CanAnyFrame) -> Fault + Send>;
+
+/// The receive queue of an endpoint.
+#[derive(Default)]
+struct Rx {
+    queue: Mutex<VecDeque<(Instant, CanAnyFrame)>>,
+    ready: Condvar,
+}
+
+/// The state of the bus.
+#[derive(Default)]
+struct Inner {
+    endpoints: Vec<(usize, Weak<Rx>)>,
+    next_endpoint: usize,
+    pending: BTreeMap<(u32, u64), (usize, CanAnyFrame)>,
+    seq: u64,
+    manual: bool,
+    latency: Duration,
+    bitrate: u32,
+    data_bitrate: u32,
+    busy_until: Option<Instant>,
+    fault: Option<FaultFn>,
+    delivered: u64,
+}
+
+impl Inner {
+    /// Puts the highest priority pending frame onto the bus.
+    fn step(&mut self) -> Option<CanAnyFrame> {
+        let (_, (src, frame)) = self.pending.pop_first()?;
+
+        // The frame starts once the bus is free, and arrives at the end
+        let now = Instant::now();
+        let start = self.busy_until.map_or(now, |t| t.max(now));
+        let end = start + frame.wire_duration(self.bitrate, self.data_bitrate);
+        self.busy_until = Some(end);
+        let at = end + self.latency;
+
+        let delivered = match self.fault.as_mut().map_or(Fault::Pass, |f| f(&frame)) {
+            Fault::Pass => frame,
+            Fault::Drop => return Some(frame),
+            Fault::Error(err) => CanAnyFrame::Error(err),
+        };
+
+        self.delivered += 1;
+        self.endpoints.retain(|(id, rx)| match rx.upgrade() {
+            Some(rx) => {
+                if *id != src {
+                    rx.queue.lock().unwrap().push_back((at, delivered));
+                    rx.ready.notify_all();
+                }
+                true
+            }
+            None => false,
+        });
+        Some(frame)
+    }
+}
+
+// ===== VirtualBus =====
+
+/// An in-process virtual CAN bus.
+///
+/// This is a handle to the bus; clones of it refer to the same bus.
+#[derive(Clone, Default)]
+pub struct VirtualBus(Arc<Mutex<Inner>>);
+
+impl VirtualBus {
+    /// Creates a bus that passes frames on right away, with no latency.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Holds sent frames on the bus until [`step`](Self::step) or
+    /// [`run`](Self::run) is called, so that they go out in order of
+    /// priority.
+    pub fn with_manual(self) -> Self {
+        self.0.lock().unwrap().manual = true;
+        self
+    }
+
+    /// Delays the arrival of each frame at the receivers.
+    pub fn with_latency(self, latency: Duration) -> Self {
+        self.0.lock().unwrap().latency = latency;
+        self
+    }
+
+    /// Simulates the time each frame takes on the wire at the bitrate.
+    ///
+    /// The data bitrate is used for the data phase of FD frames with a
+    /// bitrate switch. A bitrate of zero takes no time.
+    pub fn with_bitrate(self, bitrate: u32, data_bitrate: u32) -> Self {
+        {
+            let mut inner = self.0.lock().unwrap();
+            inner.bitrate = bitrate;
+            inner.data_bitrate = data_bitrate;
+        }
+        self
+    }
+
+    /// Sets a callback that decides the fate of each frame put onto the
+    /// bus.
+    pub fn with_fault<F>(self, fault: F) -> Self
+    where
+        F: FnMut(&CanAnyFrame) -> Fault + Send + 'static,
+    {
+        self.0.lock().unwrap().fault = Some(Box::new(fault));
+        self
+    }
+
+    /// Creates a new endpoint on the bus.
+    pub fn endpoint(&self) -> VirtualEndpoint {
+        let rx = Arc::new(Rx::default());
+        let mut inner = self.0.lock().unwrap();
+        let id = inner.next_endpoint;
+        inner.next_endpoint += 1;
+        inner.endpoints.push((id, Arc::downgrade(&rx)));
+        VirtualEndpoint {
+            bus: self.clone(),
+            id,
+            rx,
+        }
+    }
+
+    /// Puts the highest priority frame waiting to be sent onto the bus,
+    /// in manual mode.
+    ///
+    /// Returns the frame, or `None` if there were none waiting.
+    pub fn step(&self) -> Option<CanAnyFrame> {
+        self.0.lock().unwrap().step()
+    }
+
+    /// Puts all the frames waiting to be sent onto the bus, in order of
+    /// priority, and returns how many there were.
+    pub fn run(&self) -> usize {
+        let mut inner = self.0.lock().unwrap();
+        let mut n = 0;
+        while inner.step().is_some() {
+            n += 1;
+        }
+        n
+    }
+
+    /// Gets the number of frames waiting to be sent, in manual mode.
+    pub fn pending(&self) -> usize {
+        self.0.lock().unwrap().pending.len()
+    }
+
+    /// Gets the number of frames delivered to the receivers, including
+    /// error frames, but not dropped frames.
+    pub fn delivered(&self) -> u64 {
+        self.0.lock().unwrap().delivered
+    }
+
+    fn send(&self, src: usize, frame: CanAnyFrame) {
+        let mut inner = self.0.lock().unwrap();
+        let key = (frame.priority(), inner.seq);
+        inner.seq += 1;
+        inner.pending.insert(key, (src, frame));
+        if !inner.manual {
+            while inner.step().is_some() {}
+        }
+    }
+}
+
+impl fmt::Debug for VirtualBus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let inner = self.0.lock().unwrap();
+        f.debug_struct("VirtualBus")
+            .field("endpoints", &inner.endpoints.len())
+            .field("pending", &inner.pending.len())
+            .field("manual", &inner.manual)
+            .field("latency", &inner.latency)
+            .field("bitrate", &inner.bitrate)
+            .field("data_bitrate", &inner.data_bitrate)
+            .finish()
+    }
+}
+
+// ===== VirtualEndpoint =====
+
+/// A node on a virtual bus.
+///
+/// An endpoint doesn't receive the frames that it sends.
+pub struct VirtualEndpoint {
+    bus: VirtualBus,
+    id: usize,
+    rx: Arc<Rx>,
+}
+
+impl VirtualEndpoint {
+    /// Gets the bus that the endpoint is on.
+    pub fn bus(&self) -> &VirtualBus {
+        &self.bus
+    }
+
+    /// Gets the number of frames that have arrived and are waiting to be
+    /// received.
+    pub fn available(&self) -> usize {
+        let now = Instant::now();
+        let queue = self.rx.queue.lock().unwrap();
+        queue.iter().take_while(|(at, _)| *at <= now).count()
+    }
+
+    fn receive_until(&self, deadline: Option<Instant>) -> IoResult<CanAnyFrame> {
+        let mut queue = self.rx.queue.lock().unwrap();
+        loop {
+            let now = Instant::now();
+            let wake = match queue.front() {
+                Some((at, _)) if *at <= now => {
+                    return Ok(queue.pop_front().map(|(_, frame)| frame).unwrap());
+                }
+                Some((at, _)) => Some(deadline.map_or(*at, |d| d.min(*at))),
+                None => deadline,
+            };
+
+            queue = match wake {
+                Some(wake) => {
+                    if deadline.is_some_and(|d| d <= now) {
+                        return Err(IoErrorKind::TimedOut.into());
+                    }
+                    let timeout = wake.saturating_duration_since(now);
+                    self.rx.ready.wait_timeout(queue, timeout).unwrap().0
+                }
+                None => self.rx.ready.wait(queue).unwrap(),
+            };
+        }
+    }
+}
+
+impl fmt::Debug for VirtualEndpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("VirtualEndpoint")
+            .field("id", &self.id)
+            .finish()
+    }
+}
+
+impl<F> CanTransmitter<F> for VirtualEndpoint
+where
+    F: Into<CanAnyFrame> + Clone,
+{
+    fn transmit(&self, frame: &F) -> IoResult<()> {
+        self.bus.send(self.id, frame.clone().into());
+        Ok(())
+    }
+}
+
+impl CanReceiver for VirtualEndpoint {
+    type Frame = CanAnyFrame;
+
+    fn receive(&self) -> IoResult<CanAnyFrame> {
+        self.receive_until(None)
+    }
+
+    fn receive_timeout(&self, timeout: Duration) -> IoResult<CanAnyFrame> {
+        self.receive_until(Some(Instant::now() + timeout))
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CanFrame;
+
+    fn frame(id: u32) -> CanFrame {
+        CanFrame::from_raw_id(id, &[0; 8]).unwrap()
+    }
+
+    #[test]
+    fn test_arbitration() {
+        let bus = VirtualBus::new().with_manual();
+        let (a, b, c) = (bus.endpoint(), bus.endpoint(), bus.endpoint());
+
+        a.transmit(&frame(0x300)).unwrap();
+        b.transmit(&frame(0x100)).unwrap();
+        a.transmit(&frame(0x200)).unwrap();
+        assert_eq!(3, bus.pending());
+        assert_eq!(0, c.available());
+
+        assert_eq!(0x100, bus.step().unwrap().raw_id());
+        assert_eq!(2, bus.run());
+
+        let zero = Duration::ZERO;
+        let ids: Vec<u32> = (0..3)
+            .map(|_| c.receive_timeout(zero).unwrap().raw_id())
+            .collect();
+        assert_eq!(vec![0x100, 0x200, 0x300], ids);
+
+        // Nobody hears their own frames
+        assert_eq!(0x100, a.receive_timeout(zero).unwrap().raw_id());
+        assert!(a.receive_timeout(zero).is_err());
+        assert_eq!(2, b.available());
+    }
+
+    #[test]
+    fn test_timing_and_faults() {
+        let mut n = 0;
+        let bus = VirtualBus::new()
+            .with_latency(Duration::from_millis(20))
+            .with_fault(move |_| {
+                n += 1;
+                match n {
+                    2 => Fault::Drop,
+                    3 => Fault::bus_error(),
+                    _ => Fault::Pass,
+                }
+            });
+        let (a, b) = (bus.endpoint(), bus.endpoint());
+
+        let start = Instant::now();
+        for id in 1..=3 {
+            a.transmit(&frame(id)).unwrap();
+        }
+        assert_eq!(0, b.available());
+        assert!(b.receive_timeout(Duration::from_millis(1)).is_err());
+
+        assert_eq!(1, b.receive().unwrap().raw_id());
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        assert!(b.receive().unwrap().is_error_frame());
+        assert_eq!(2, bus.delivered());
+    }
+}
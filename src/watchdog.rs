@@ -0,0 +1,327 @@
+// socketcan/src/watchdog.rs
+//
+// A watchdog for silence on the bus.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! A watchdog for silence on the bus.
+//!
+//! "Is the bus alive?" The [`Watchdog`] answers it. It's fed the frames
+//! received, and raises a [`WatchdogEvent::Silent`] event when none have
+//! arrived for the timeout, then a [`WatchdogEvent::Resumed`] event when
+//! traffic comes back. It can be limited to the frames matching a
+//! [`Route`], to watch for a particular ECU or message rather than any
+//! traffic at all.
+//!
+//! Applications can get the events from the calls that feed the watchdog,
+//! with a callback, or through a channel.
+//!
+//! When the watchdog reads from a socket, it timestamps the frames with
+//! the system clock, or any other [`Clock`], such as a simulated one in a
+//! test.
+//!
+//! ```no_run
+//! use socketcan::{watchdog::Watchdog, CanFdSocket, CanId, Socket};
+//! use std::time::Duration;
+//!
+//! let sock = CanFdSocket::open("can0").unwrap();
+//!
+//! let mut watchdog = Watchdog::new(Duration::from_millis(500))
+//!     .with_route(CanId::standard(0x100).unwrap());
+//! watchdog.on_event(|ev| println!("{:?}", ev));
+//!
+//! loop {
+//!     watchdog.watch(&sock).unwrap();
+//! }
+//! ```
+
+use crate::{
+    backend::CanReceiver,
+    clock::{Clock, SharedClock, SystemClock},
+    dispatch::Route,
+    Frame, IoErrorKind, IoResult,
+};
+use std::{
+    fmt,
+    sync::{mpsc, Arc},
+    time::{Duration, Instant},
+};
+
+/// A change in whether frames are being received.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchdogEvent {
+    /// No frame was received for the timeout
+    Silent {
+        /// The time the silence was detected, in microseconds
+        t_us: u64,
+        /// The time of the last frame, if any was received
+        last_us: Option<u64>,
+    },
+    /// Frames are being received again after a silence
+    Resumed {
+        /// The time of the frame that ended the silence, in microseconds
+        t_us: u64,
+        /// How long the bus was silent, in microseconds
+        silent_us: u64,
+    },
+}
+
+/// A callback for watchdog events
+type EventCallback = Box<dyn FnMut(&WatchdogEvent) + Send>;
+
+// ===== Watchdog =====
+
+/// A watchdog that detects when frames stop arriving.
+pub struct Watchdog {
+    timeout_us: u64,
+    route: Option<Route>,
+    clock: SharedClock,
+    epoch: Instant,
+    start_us: Option<u64>,
+    last_us: Option<u64>,
+    silent: bool,
+    callbacks: Vec<EventCallback>,
+    subscribers: Vec<mpsc::Sender<WatchdogEvent>>,
+}
+
+impl Watchdog {
+    /// Creates a watchdog that fires after the timeout with no frames.
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout_us: timeout.as_micros() as u64,
+            route: None,
+            clock: Arc::new(SystemClock),
+            epoch: Instant::now(),
+            start_us: None,
+            last_us: None,
+            silent: false,
+            callbacks: Vec::new(),
+            subscribers: Vec::new(),
+        }
+    }
+
+    /// Limits the watchdog to the frames matching the route. Other frames
+    /// don't count as traffic.
+    pub fn with_route<R: Into<Route>>(mut self, route: R) -> Self {
+        self.route = Some(route.into());
+        self
+    }
+
+    /// Uses the clock to timestamp the frames read by
+    /// [`watch`](Self::watch), with the times relative to now.
+    pub fn with_clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.epoch = clock.now();
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Gets the time on the watchdog's clock, in microseconds.
+    pub fn now_us(&self) -> u64 {
+        self.clock.now().duration_since(self.epoch).as_micros() as u64
+    }
+
+    /// Registers a callback that is called on each event.
+    pub fn on_event<F>(&mut self, f: F)
+    where
+        F: FnMut(&WatchdogEvent) + Send + 'static,
+    {
+        self.callbacks.push(Box::new(f));
+    }
+
+    /// Subscribes to the events through a channel.
+    pub fn subscribe(&mut self) -> mpsc::Receiver<WatchdogEvent> {
+        let (tx, rx) = mpsc::channel();
+        self.subscribers.push(tx);
+        rx
+    }
+
+    /// Gets the timeout.
+    pub fn timeout(&self) -> Duration {
+        Duration::from_micros(self.timeout_us)
+    }
+
+    /// Determines if the watchdog has detected a silence that hasn't ended.
+    pub fn is_silent(&self) -> bool {
+        self.silent
+    }
+
+    /// Gets the time of the last frame received, in microseconds.
+    pub fn last_us(&self) -> Option<u64> {
+        self.last_us
+    }
+
+    /// Gets the time at which the watchdog will fire if no frame arrives,
+    /// or `None` if it's already fired or hasn't been started.
+    pub fn deadline_us(&self) -> Option<u64> {
+        match self.silent {
+            true => None,
+            false => self
+                .last_us
+                .or(self.start_us)
+                .map(|t| t.saturating_add(self.timeout_us)),
+        }
+    }
+
+    /// Starts timing from the time, in microseconds, if no frame has been
+    /// received yet. Otherwise, the timing starts at the first frame or
+    /// check.
+    pub fn start(&mut self, t_us: u64) {
+        self.start_us.get_or_insert(t_us);
+    }
+
+    /// Feeds a frame received at the time, in microseconds.
+    ///
+    /// Returns the event if the frame ended a silence.
+    pub fn feed<F: Frame>(&mut self, t_us: u64, frame: &F) -> Option<WatchdogEvent> {
+        self.start(t_us);
+        if self
+            .route
+            .as_ref()
+            .is_some_and(|route| !route.matches(frame))
+        {
+            return None;
+        }
+        let prev = self.last_us.replace(t_us).or(self.start_us);
+
+        if self.silent {
+            self.silent = false;
+            let silent_us = prev.map_or(0, |prev| t_us.saturating_sub(prev));
+            return Some(self.notify(WatchdogEvent::Resumed { t_us, silent_us }));
+        }
+        None
+    }
+
+    /// Checks for a silence at the time, in microseconds.
+    ///
+    /// Returns the event the first time the timeout has passed since the
+    /// last frame.
+    pub fn check(&mut self, t_us: u64) -> Option<WatchdogEvent> {
+        self.start(t_us);
+        match self.deadline_us() {
+            Some(deadline) if t_us >= deadline => {
+                self.silent = true;
+                Some(self.notify(WatchdogEvent::Silent {
+                    t_us,
+                    last_us: self.last_us,
+                }))
+            }
+            _ => None,
+        }
+    }
+
+    /// Sends an event to the callbacks and subscribers.
+    fn notify(&mut self, ev: WatchdogEvent) -> WatchdogEvent {
+        for cb in self.callbacks.iter_mut() {
+            cb(&ev);
+        }
+        self.subscribers.retain(|tx| tx.send(ev).is_ok());
+        ev
+    }
+
+    /// Reads frames from the socket until the next event, and returns it.
+    ///
+    /// Frames are timestamped on reception by the watchdog's clock,
+    /// relative to the creation of the watchdog.
+    pub fn watch<S>(&mut self, sock: &S) -> IoResult<WatchdogEvent>
+    where
+        S: CanReceiver,
+        S::Frame: Frame,
+    {
+        loop {
+            let now_us = self.now_us();
+            if let Some(ev) = self.check(now_us) {
+                return Ok(ev);
+            }
+            let frame = match self.deadline_us() {
+                Some(deadline) => {
+                    let timeout = Duration::from_micros(deadline.saturating_sub(now_us));
+                    match sock.receive_timeout(timeout) {
+                        Ok(frame) => frame,
+                        Err(err) if err.kind() == IoErrorKind::TimedOut => continue,
+                        Err(err) => return Err(err),
+                    }
+                }
+                None => sock.receive()?,
+            };
+            let t_us = self.now_us();
+            if let Some(ev) = self.feed(t_us, &frame) {
+                return Ok(ev);
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Watchdog {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Watchdog")
+            .field("timeout_us", &self.timeout_us)
+            .field("route", &self.route)
+            .field("last_us", &self.last_us)
+            .field("silent", &self.silent)
+            .field("callbacks", &self.callbacks.len())
+            .field("subscribers", &self.subscribers.len())
+            .finish()
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CanFrame, CanId};
+
+    #[test]
+    fn test_watchdog() {
+        let mut wd =
+            Watchdog::new(Duration::from_millis(100)).with_route(CanId::standard(0x100).unwrap());
+        let rx = wd.subscribe();
+        let hit = CanFrame::from_raw_id(0x100, &[]).unwrap();
+        let other = CanFrame::from_raw_id(0x200, &[]).unwrap();
+
+        wd.start(0);
+        assert_eq!(None, wd.check(99_000));
+        assert_eq!(None, wd.feed(50_000, &hit));
+        assert_eq!(Some(150_000), wd.deadline_us());
+
+        // Frames that don't match the route don't count
+        assert_eq!(None, wd.feed(140_000, &other));
+        assert_eq!(
+            Some(WatchdogEvent::Silent {
+                t_us: 150_000,
+                last_us: Some(50_000)
+            }),
+            wd.check(150_000)
+        );
+        assert!(wd.is_silent());
+        // Only fires once per silence
+        assert_eq!(None, wd.check(300_000));
+
+        assert_eq!(
+            Some(WatchdogEvent::Resumed {
+                t_us: 400_000,
+                silent_us: 350_000
+            }),
+            wd.feed(400_000, &hit)
+        );
+        assert_eq!(2, rx.try_iter().count());
+    }
+
+    #[test]
+    fn test_sim_clock() {
+        use crate::clock::SimClock;
+
+        let clock = SimClock::new();
+        let mut wd = Watchdog::new(Duration::from_secs(10)).with_clock(clock.clone());
+        clock.advance(Duration::from_secs(3600));
+        assert_eq!(3_600_000_000, wd.now_us());
+
+        wd.start(0);
+        assert!(wd.check(wd.now_us()).is_some());
+    }
+}
@@ -0,0 +1,512 @@
+// socketcan/src/websocket.rs
+//
+// A WebSocket bridge that streams frames as JSON.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! A WebSocket bridge that streams frames as JSON.
+//!
+//! A [`WsBridge`] is a small WebSocket server that lets browser
+//! dashboards and remote scripts tap a bus through one process. Each
+//! frame given to it is sent to every connected client as a JSON text
+//! message, in the serde representation of [`CanAnyFrame`], such as:
+//!
+//! ```text
+//! {"Normal":{"id":291,"ext":false,"data":[1,2,3]}}
+//! {"Fd":{"id":1110,"ext":false,"brs":true,"esi":false,"data":[...]}}
+//! ```
+//!
+//! Clients can send frames back in the same form, to be transmitted.
+//!
+//! The bridge implements the [`CanTransmitter`] and [`CanReceiver`]
+//! traits: transmitting a frame streams it out to the clients, and
+//! receiving gets the next frame sent in by a client. So a bus is bridged
+//! by forwarding frames both ways between it and a socket:
+//!
+//! ```no_run
+//! use socketcan::{
+//!     backend::{CanReceiver, CanTransmitter},
+//!     websocket::WsBridge,
+//!     CanFdSocket, Socket,
+//! };
+//! use std::{sync::Arc, thread};
+//!
+//! let sock = Arc::new(CanFdSocket::open("can0").unwrap());
+//! let bridge = Arc::new(WsBridge::bind("0.0.0.0:8080").unwrap());
+//!
+//! // Frames from the clients go onto the bus
+//! let (tx_sock, rx_bridge) = (Arc::clone(&sock), Arc::clone(&bridge));
+//! thread::spawn(move || loop {
+//!     let frame = rx_bridge.receive().unwrap();
+//!     tx_sock.write_frame(&frame).unwrap();
+//! });
+//!
+//! // Frames from the bus go to the clients
+//! loop {
+//!     let frame = sock.read_frame().unwrap();
+//!     bridge.transmit(&frame).unwrap();
+//! }
+//! ```
+//!
+//! The server only speaks WebSocket, over plain TCP. Put it behind a
+//! reverse proxy for TLS or authentication. The protocol is handled by
+//! [tungstenite](https://crates.io/crates/tungstenite), which limits the
+//! size of the handshake, and the connection to a client that breaks the
+//! protocol, such as by sending unmasked frames, is closed with a
+//! protocol error.
+//!
+//! The frames going out to each client, and coming in from all of them,
+//! are held in queues of up to [`QUEUE_LEN`] frames. If a client falls
+//! behind, or the frames from the clients aren't received quickly enough,
+//! the frames that don't fit are dropped and counted, rather than letting
+//! the queues grow without limit.
+
+use crate::{
+    backend::{CanReceiver, CanTransmitter},
+    CanAnyFrame, IoError, IoErrorKind, IoResult,
+};
+use std::{
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc::{self, TrySendError},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+use tungstenite::{
+    handshake::HandshakeError,
+    protocol::{frame::coding::CloseCode, CloseFrame, WebSocketConfig},
+    Error as WsError, Message,
+};
+
+/// The largest message accepted from a client.
+pub const MAX_MESSAGE_LEN: usize = 64 * 1024;
+
+/// The number of frames that can be queued for each client, and from the
+/// clients, before any more are dropped.
+pub const QUEUE_LEN: usize = 1024;
+
+// The time a client has to complete the handshake
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+// How often a client thread stops waiting for messages to send the queued
+// frames, and to check if the server is stopping
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+// The time a client has to take each write before it's dropped
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+// ===== WsBridge =====
+
+/// A client that has completed the handshake, to send frames to.
+#[derive(Debug)]
+struct Client {
+    id: u64,
+    tx: mpsc::SyncSender<String>,
+}
+
+/// The state shared between the bridge and its threads.
+#[derive(Debug)]
+struct Shared {
+    /// The clients to send frames to
+    clients: Mutex<Vec<Client>>,
+    /// The thread of every connection
+    threads: Mutex<Vec<JoinHandle<()>>>,
+    /// The queue of frames received from the clients
+    rx_queue: mpsc::SyncSender<CanAnyFrame>,
+    /// The number of frames dropped because a queue was full
+    dropped: AtomicU64,
+    /// Set when the server is stopping
+    stop: AtomicBool,
+}
+
+impl Shared {
+    /// Counts a frame dropped from a full queue.
+    fn drop_frame(&self) {
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// A WebSocket server that streams frames to its clients as JSON, and
+/// receives frames from them.
+///
+/// The server is stopped when this is dropped, which closes the
+/// connections to the clients, and waits for all of its threads to exit.
+#[derive(Debug)]
+pub struct WsBridge {
+    addr: SocketAddr,
+    shared: Arc<Shared>,
+    rx: Mutex<mpsc::Receiver<CanAnyFrame>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl WsBridge {
+    /// Starts a server listening on the address.
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> IoResult<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let addr = listener.local_addr()?;
+        let (rx_queue, rx) = mpsc::sync_channel(QUEUE_LEN);
+        let shared = Arc::new(Shared {
+            clients: Mutex::new(Vec::new()),
+            threads: Mutex::new(Vec::new()),
+            rx_queue,
+            dropped: AtomicU64::new(0),
+            stop: AtomicBool::new(false),
+        });
+
+        let thr_shared = Arc::clone(&shared);
+        let thread = thread::Builder::new()
+            .name("ws-bridge".into())
+            .spawn(move || Self::serve(listener, thr_shared))?;
+
+        Ok(Self {
+            addr,
+            shared,
+            rx: Mutex::new(rx),
+            thread: Some(thread),
+        })
+    }
+
+    /// Gets the address the server is listening on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Gets the number of connected clients.
+    pub fn clients(&self) -> usize {
+        self.shared.clients.lock().unwrap().len()
+    }
+
+    /// Gets the number of frames dropped because a client fell behind, or
+    /// because the frames from the clients weren't received in time.
+    pub fn dropped(&self) -> u64 {
+        self.shared.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Accepts connections, starting a thread for each.
+    fn serve(listener: TcpListener, shared: Arc<Shared>) {
+        for (id, stream) in (0..).zip(listener.incoming()) {
+            if shared.stop.load(Ordering::Acquire) {
+                break;
+            }
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(err) => {
+                    log::warn!("WebSocket accept failed: {}", err);
+                    continue;
+                }
+            };
+
+            let thr_shared = Arc::clone(&shared);
+            let res = thread::Builder::new()
+                .name("ws-client".into())
+                .spawn(move || {
+                    if let Err(err) = Self::client(id, stream, &thr_shared) {
+                        log::debug!("WebSocket client closed: {}", err);
+                    }
+                    thr_shared.clients.lock().unwrap().retain(|c| c.id != id);
+                });
+
+            match res {
+                Ok(thread) => {
+                    let mut threads = shared.threads.lock().unwrap();
+                    // Reap the threads of the clients that have gone
+                    let (done, running): (Vec<_>, _) =
+                        threads.drain(..).partition(JoinHandle::is_finished);
+                    for thread in done {
+                        let _ = thread.join();
+                    }
+                    *threads = running;
+                    threads.push(thread);
+                }
+                Err(err) => log::warn!("Unable to start WebSocket client thread: {}", err),
+            }
+        }
+    }
+
+    /// Runs a client connection, from the handshake until it closes.
+    fn client(id: u64, stream: TcpStream, shared: &Shared) -> IoResult<()> {
+        // Reads time out regularly, so that the stop flag is checked, even
+        // during the handshake
+        stream.set_read_timeout(Some(POLL_INTERVAL))?;
+        stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+        let _ = stream.set_nodelay(true);
+
+        let config = WebSocketConfig {
+            max_message_size: Some(MAX_MESSAGE_LEN),
+            max_frame_size: Some(MAX_MESSAGE_LEN),
+            ..WebSocketConfig::default()
+        };
+        let deadline = Instant::now() + HANDSHAKE_TIMEOUT;
+        let mut res = tungstenite::accept_with_config(stream, Some(config));
+        let mut ws = loop {
+            match res {
+                Ok(ws) => break ws,
+                Err(HandshakeError::Interrupted(mid))
+                    if !shared.stop.load(Ordering::Acquire) && Instant::now() < deadline =>
+                {
+                    res = mid.handshake();
+                }
+                Err(err) => return Err(IoError::new(IoErrorKind::InvalidData, err.to_string())),
+            }
+        };
+
+        let (tx, rx) = mpsc::sync_channel(QUEUE_LEN);
+        shared.clients.lock().unwrap().push(Client { id, tx });
+
+        let err = 'conn: loop {
+            if shared.stop.load(Ordering::Acquire) {
+                let code = CloseCode::Away;
+                let _ = ws.close(Some(CloseFrame {
+                    code,
+                    reason: "".into(),
+                }));
+                let _ = ws.flush();
+                return Ok(());
+            }
+
+            match ws.read() {
+                Ok(Message::Text(text)) => Self::queue_frame(text.as_bytes(), shared),
+                Ok(Message::Binary(data)) => Self::queue_frame(&data, shared),
+                Ok(_) => {}
+                Err(WsError::Io(err))
+                    if matches!(err.kind(), IoErrorKind::WouldBlock | IoErrorKind::TimedOut) => {}
+                Err(err) => break err,
+            }
+
+            for json in rx.try_iter() {
+                if let Err(err) = ws.write(Message::Text(json)) {
+                    break 'conn err;
+                }
+            }
+            if let Err(err) = ws.flush() {
+                break err;
+            }
+        };
+
+        // Tell the client why the connection is being closed
+        let code = match &err {
+            WsError::Protocol(_) => Some(CloseCode::Protocol),
+            WsError::Capacity(_) => Some(CloseCode::Size),
+            WsError::Utf8 => Some(CloseCode::Invalid),
+            _ => None,
+        };
+        if let Some(code) = code {
+            let _ = ws.close(Some(CloseFrame {
+                code,
+                reason: "".into(),
+            }));
+            let _ = ws.flush();
+        }
+
+        match err {
+            WsError::ConnectionClosed => Ok(()),
+            WsError::Io(err) => Err(err),
+            err => Err(IoError::new(IoErrorKind::InvalidData, err)),
+        }
+    }
+
+    /// Queues a frame sent in by a client, to be received.
+    fn queue_frame(json: &[u8], shared: &Shared) {
+        match serde_json::from_slice::<CanAnyFrame>(json) {
+            Ok(frame) => {
+                if let Err(TrySendError::Full(_)) = shared.rx_queue.try_send(frame) {
+                    shared.drop_frame();
+                }
+            }
+            Err(err) => log::warn!("Bad frame from WebSocket client: {}", err),
+        }
+    }
+
+    fn receive_with(&self, timeout: Option<Duration>) -> IoResult<CanAnyFrame> {
+        let rx = self.rx.lock().unwrap();
+        let res = match timeout {
+            Some(timeout) => rx.recv_timeout(timeout).map_err(|err| match err {
+                mpsc::RecvTimeoutError::Timeout => IoErrorKind::TimedOut,
+                mpsc::RecvTimeoutError::Disconnected => IoErrorKind::BrokenPipe,
+            }),
+            None => rx.recv().map_err(|_| IoErrorKind::BrokenPipe),
+        };
+        res.map_err(IoError::from)
+    }
+}
+
+impl Drop for WsBridge {
+    fn drop(&mut self) {
+        self.shared.stop.store(true, Ordering::Release);
+        // Wake the accept loop, so it sees the stop flag
+        let _ = TcpStream::connect(self.addr);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        // The client threads see the stop flag within a poll interval, and
+        // close their connections
+        for thread in self.shared.threads.lock().unwrap().drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl<F> CanTransmitter<F> for WsBridge
+where
+    F: Into<CanAnyFrame> + Clone,
+{
+    /// Queues the frame to be sent to all the connected clients. If a
+    /// client's queue is full, the frame is dropped for that client.
+    fn transmit(&self, frame: &F) -> IoResult<()> {
+        let json = serde_json::to_string(&frame.clone().into())?;
+        self.shared.clients.lock().unwrap().retain(|client| {
+            match client.tx.try_send(json.clone()) {
+                Ok(()) => true,
+                Err(TrySendError::Full(_)) => {
+                    self.shared.drop_frame();
+                    true
+                }
+                Err(TrySendError::Disconnected(_)) => false,
+            }
+        });
+        Ok(())
+    }
+}
+
+impl CanReceiver for WsBridge {
+    type Frame = CanAnyFrame;
+
+    fn receive(&self) -> IoResult<CanAnyFrame> {
+        self.receive_with(None)
+    }
+
+    fn receive_timeout(&self, timeout: Duration) -> IoResult<CanAnyFrame> {
+        self.receive_with(Some(timeout))
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CanFrame, Frame};
+    use std::io::{Read, Write};
+    use tungstenite::WebSocket;
+
+    const UPGRADE: &[u8] = b"GET / HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\n\
+        Connection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+        Sec-WebSocket-Version: 13\r\n";
+
+    fn wait_for(cond: impl Fn() -> bool) {
+        let start = Instant::now();
+        while !cond() {
+            assert!(start.elapsed() < Duration::from_secs(5));
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    fn connect(bridge: &WsBridge) -> WebSocket<TcpStream> {
+        let addr = bridge.local_addr();
+        let stream = TcpStream::connect(addr).unwrap();
+        let (ws, _) = tungstenite::client(format!("ws://{}/", addr), stream).unwrap();
+        wait_for(|| bridge.clients() > 0);
+        ws
+    }
+
+    /// Sends a raw upgrade request, and reads the response headers.
+    fn raw_upgrade(bridge: &WsBridge, extra: &[u8]) -> (TcpStream, String) {
+        let mut client = TcpStream::connect(bridge.local_addr()).unwrap();
+        client
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        client.write_all(UPGRADE).unwrap();
+        // The peer may close the connection before all of this is written
+        let _ = client.write_all(extra);
+        let _ = client.write_all(b"\r\n");
+
+        let mut resp = Vec::new();
+        let mut b = [0u8];
+        while !resp.ends_with(b"\r\n\r\n") && client.read(&mut b).unwrap_or(0) == 1 {
+            resp.push(b[0]);
+        }
+        (client, String::from_utf8_lossy(&resp).into_owned())
+    }
+
+    #[test]
+    fn test_bridge() {
+        let bridge = WsBridge::bind("127.0.0.1:0").unwrap();
+        let mut ws = connect(&bridge);
+
+        // Out to the client
+        let frame = CanFrame::from_raw_id(0x123, &[1, 2, 3]).unwrap();
+        bridge.transmit(&frame).unwrap();
+        assert_eq!(
+            Message::Text(r#"{"Normal":{"id":291,"ext":false,"data":[1,2,3]}}"#.into()),
+            ws.read().unwrap()
+        );
+
+        // In from the client
+        let json = r#"{"Normal":{"id":1110,"ext":false,"data":[9]}}"#;
+        ws.send(Message::Text(json.into())).unwrap();
+        let rx = bridge.receive_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(0x456, rx.raw_id());
+
+        // The client is told when the server stops
+        drop(bridge);
+        match ws.read().unwrap() {
+            Message::Close(Some(close)) => assert_eq!(CloseCode::Away, close.code),
+            msg => panic!("unexpected message: {:?}", msg),
+        }
+    }
+
+    #[test]
+    fn test_unmasked_frame() {
+        let bridge = WsBridge::bind("127.0.0.1:0").unwrap();
+        let (mut client, resp) = raw_upgrade(&bridge, b"");
+        assert!(resp.starts_with("HTTP/1.1 101"), "{}", resp);
+
+        // An unmasked text frame, which clients must never send
+        client.write_all(b"\x81\x02{}").unwrap();
+        let mut close = [0u8; 4];
+        client.read_exact(&mut close).unwrap();
+        assert_eq!([0x88, 0x02], close[..2]);
+        assert_eq!(1002, u16::from_be_bytes([close[2], close[3]]));
+    }
+
+    #[test]
+    fn test_bad_handshake() {
+        let bridge = WsBridge::bind("127.0.0.1:0").unwrap();
+
+        let headers: Vec<u8> = (0..200)
+            .flat_map(|i| format!("X-Header-{}: {}\r\n", i, i).into_bytes())
+            .collect();
+        let (_, resp) = raw_upgrade(&bridge, &headers);
+        assert!(!resp.starts_with("HTTP/1.1 101"), "{}", resp);
+
+        let long = format!("X-Long: {}\r\n", "x".repeat(100_000));
+        let (_, resp) = raw_upgrade(&bridge, long.as_bytes());
+        assert!(!resp.starts_with("HTTP/1.1 101"), "{}", resp);
+        assert_eq!(0, bridge.clients());
+    }
+
+    #[test]
+    fn test_rx_queue_full() {
+        let bridge = WsBridge::bind("127.0.0.1:0").unwrap();
+        let mut ws = connect(&bridge);
+
+        let json = r#"{"Normal":{"id":1,"ext":false,"data":[]}}"#;
+        for _ in 0..QUEUE_LEN + 10 {
+            ws.send(Message::Text(json.into())).unwrap();
+        }
+        wait_for(|| bridge.dropped() == 10);
+
+        for _ in 0..QUEUE_LEN {
+            bridge.receive_timeout(Duration::ZERO).unwrap();
+        }
+        assert!(bridge.receive_timeout(Duration::ZERO).is_err());
+    }
+}
@@ -0,0 +1,667 @@
+// socketcan/src/xcp.rs
+//
+// XCP on CAN transport layer.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! XCP on CAN.
+//!
+//! XCP (ASAM MCD-1) is the protocol used by measurement and calibration
+//! tools to read and write the memory of an ECU, and to receive periodic
+//! measurements from it. On CAN, the master sends its command packets
+//! (CTO) with one ID, and the slave sends its responses, errors, events,
+//! and data acquisition packets (DTO) with another. Each packet fits in
+//! a single frame, starting with its packet identifier (PID).
+//!
+//! The [`XcpMaster`] implements the transport: it frames the commands,
+//! waits for the responses with the T1 timeout, and recovers from lost
+//! packets with SYNCH and a retry, as described by the standard. DAQ
+//! packets that arrive while waiting for a response are queued, up to a
+//! limit, and can be read with [`XcpMaster::read_daq()`]. Only the absolute ODT number
+//! identification of DAQ packets is supported, and the memory transfers
+//! are limited to what fits in a single packet.
+//!
+//! ```no_run
+//! use socketcan::{xcp::XcpMaster, CanSocket, Socket, StandardId};
+//! use std::time::Duration;
+//!
+//! let sock = CanSocket::open("can0").unwrap();
+//! let tx_id = StandardId::new(0x7F0).unwrap();
+//! let rx_id = StandardId::new(0x7F1).unwrap();
+//! let mut xcp = XcpMaster::new(&sock, tx_id, rx_id);
+//!
+//! let info = xcp.connect().unwrap();
+//! println!("MAX_CTO: {}, MAX_DTO: {}", info.max_cto, info.max_dto);
+//!
+//! let data = xcp.short_upload(0x2000_1000, 0, 4).unwrap();
+//! println!("{:02X?}", data);
+//!
+//! while let Ok(daq) = xcp.read_daq(Duration::from_secs(1)) {
+//!     println!("ODT {}: {:02X?}", daq.pid, daq.data);
+//! }
+//! ```
+
+use crate::{
+    backend::{CanReceiver, CanTransmitter},
+    CanFrame, CanId, EmbeddedFrame, Frame,
+};
+use std::{
+    collections::VecDeque,
+    fmt, io,
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+
+/// The default T1 timeout, for the response to a command.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// The maximum size of a packet on classic CAN
+pub const MAX_CAN_PACKET: usize = 8;
+
+/// The default number of DAQ packets queued while waiting for responses.
+pub const DEFAULT_DAQ_CAPACITY: usize = 256;
+
+// Command packet identifiers
+const CMD_CONNECT: u8 = 0xFF;
+const CMD_DISCONNECT: u8 = 0xFE;
+const CMD_GET_STATUS: u8 = 0xFD;
+const CMD_SYNCH: u8 = 0xFC;
+const CMD_SET_MTA: u8 = 0xF6;
+const CMD_UPLOAD: u8 = 0xF5;
+const CMD_SHORT_UPLOAD: u8 = 0xF4;
+const CMD_DOWNLOAD: u8 = 0xF0;
+
+// Slave packet identifiers
+const PID_RES: u8 = 0xFF;
+const PID_ERR: u8 = 0xFE;
+const PID_EV: u8 = 0xFD;
+const PID_SERV: u8 = 0xFC;
+
+/// An XCP error code, from an error packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct XcpErrorCode(pub u8);
+
+impl XcpErrorCode {
+    /// Command processor synchronization
+    pub const CMD_SYNCH: Self = Self(0x00);
+    /// Command was not executed, the slave is busy
+    pub const CMD_BUSY: Self = Self(0x10);
+    /// Command rejected because DAQ is running
+    pub const DAQ_ACTIVE: Self = Self(0x11);
+    /// Command rejected because programming is running
+    pub const PGM_ACTIVE: Self = Self(0x12);
+    /// Unknown command, or not implemented
+    pub const CMD_UNKNOWN: Self = Self(0x20);
+    /// Command syntax invalid
+    pub const CMD_SYNTAX: Self = Self(0x21);
+    /// Command syntax valid, but a parameter is out of range
+    pub const OUT_OF_RANGE: Self = Self(0x22);
+    /// The memory location is write protected
+    pub const WRITE_PROTECTED: Self = Self(0x23);
+    /// The memory location is not accessible
+    pub const ACCESS_DENIED: Self = Self(0x24);
+    /// Access denied, seed and key are required
+    pub const ACCESS_LOCKED: Self = Self(0x25);
+    /// Selected page not available
+    pub const PAGE_NOT_VALID: Self = Self(0x26);
+    /// Selected page mode not available
+    pub const MODE_NOT_VALID: Self = Self(0x27);
+    /// Selected segment not valid
+    pub const SEGMENT_NOT_VALID: Self = Self(0x28);
+    /// Sequence error
+    pub const SEQUENCE: Self = Self(0x29);
+    /// DAQ configuration not valid
+    pub const DAQ_CONFIG: Self = Self(0x2A);
+    /// Memory overflow error
+    pub const MEMORY_OVERFLOW: Self = Self(0x30);
+    /// Generic error
+    pub const GENERIC: Self = Self(0x31);
+    /// The slave internal program verify routine detected an error
+    pub const VERIFY: Self = Self(0x32);
+
+    /// Gets the description of the code, if it's a known one.
+    pub fn description(&self) -> Option<&'static str> {
+        let s = match *self {
+            Self::CMD_SYNCH => "Command processor synchronization",
+            Self::CMD_BUSY => "Command was not executed",
+            Self::DAQ_ACTIVE => "Command rejected because DAQ is running",
+            Self::PGM_ACTIVE => "Command rejected because PGM is running",
+            Self::CMD_UNKNOWN => "Unknown command or not implemented optional command",
+            Self::CMD_SYNTAX => "Command syntax invalid",
+            Self::OUT_OF_RANGE => "Command syntax valid but command parameter(s) out of range",
+            Self::WRITE_PROTECTED => "The memory location is write protected",
+            Self::ACCESS_DENIED => "The memory location is not accessible",
+            Self::ACCESS_LOCKED => "Access denied, Seed & Key is required",
+            Self::PAGE_NOT_VALID => "Selected page not available",
+            Self::MODE_NOT_VALID => "Selected page mode not available",
+            Self::SEGMENT_NOT_VALID => "Selected segment not valid",
+            Self::SEQUENCE => "Sequence error",
+            Self::DAQ_CONFIG => "DAQ configuration not valid",
+            Self::MEMORY_OVERFLOW => "Memory overflow error",
+            Self::GENERIC => "Generic error",
+            Self::VERIFY => "The slave internal program verify routine detects an error",
+            _ => return None,
+        };
+        Some(s)
+    }
+}
+
+impl fmt::Display for XcpErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.description() {
+            Some(s) => write!(f, "{} ({:02X}h)", s, self.0),
+            None => write!(f, "error {:02X}h", self.0),
+        }
+    }
+}
+
+/// An error from an XCP command.
+#[derive(Error, Debug)]
+pub enum XcpError {
+    /// The slave replied with an error packet
+    #[error("XCP command failed: {0}")]
+    Command(XcpErrorCode),
+    /// The slave didn't respond in time, even after a retry
+    #[error("XCP command timed out")]
+    Timeout,
+    /// The command needs a connection to the slave
+    #[error("XCP slave not connected")]
+    NotConnected,
+    /// The slave sent an invalid packet, or a value is too large
+    #[error("XCP protocol error: {0}")]
+    Protocol(&'static str),
+    /// An I/O error on the socket
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+/// A data acquisition (DAQ) packet.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DaqPacket {
+    /// The packet identifier: the absolute ODT number
+    pub pid: u8,
+    /// The data after the identifier
+    pub data: Vec<u8>,
+}
+
+/// A packet sent by a slave.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum XcpPacket {
+    /// A positive response to a command, with the data after the PID
+    Response(Vec<u8>),
+    /// An error response to a command
+    Error(XcpErrorCode),
+    /// An asynchronous event, with the event code and data
+    Event(u8, Vec<u8>),
+    /// A service request, with the request code and data
+    Service(u8, Vec<u8>),
+    /// A data acquisition packet
+    Daq(DaqPacket),
+}
+
+impl XcpPacket {
+    /// Parses a packet sent by a slave.
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let (&pid, rest) = data.split_first()?;
+        let pkt = match pid {
+            PID_RES => Self::Response(rest.to_vec()),
+            PID_ERR => Self::Error(XcpErrorCode(*rest.first()?)),
+            PID_EV => Self::Event(*rest.first()?, rest[1..].to_vec()),
+            PID_SERV => Self::Service(*rest.first()?, rest[1..].to_vec()),
+            _ => Self::Daq(DaqPacket {
+                pid,
+                data: rest.to_vec(),
+            }),
+        };
+        Some(pkt)
+    }
+}
+
+/// The properties of a slave, from its response to CONNECT.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ConnectInfo {
+    /// The available resources: calibration, DAQ, STIM, programming
+    pub resource: u8,
+    /// The basic communication mode
+    pub comm_mode: u8,
+    /// The maximum size of a command packet
+    pub max_cto: u8,
+    /// The maximum size of a data packet
+    pub max_dto: u16,
+    /// The major version of the protocol layer
+    pub protocol_version: u8,
+    /// The major version of the transport layer
+    pub transport_version: u8,
+}
+
+impl ConnectInfo {
+    /// Parses the data of a positive response to CONNECT.
+    pub fn from_response(data: &[u8]) -> Option<Self> {
+        if data.len() < 7 {
+            return None;
+        }
+        let big_endian = data[1] & 0x01 != 0;
+        let max_dto = [data[3], data[4]];
+        Some(Self {
+            resource: data[0],
+            comm_mode: data[1],
+            max_cto: data[2],
+            max_dto: if big_endian {
+                u16::from_be_bytes(max_dto)
+            } else {
+                u16::from_le_bytes(max_dto)
+            },
+            protocol_version: data[5],
+            transport_version: data[6],
+        })
+    }
+
+    /// Determines if the slave uses the Motorola (big-endian) byte order
+    /// for multi-byte parameters.
+    pub fn is_big_endian(&self) -> bool {
+        self.comm_mode & 0x01 != 0
+    }
+
+    /// Encodes a 32-bit parameter in the byte order of the slave.
+    fn u32_bytes(&self, val: u32) -> [u8; 4] {
+        if self.is_big_endian() {
+            val.to_be_bytes()
+        } else {
+            val.to_le_bytes()
+        }
+    }
+}
+
+/// The status of a slave, from its response to GET_STATUS.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct XcpStatus {
+    /// The current session status
+    pub session_status: u8,
+    /// The resources protected by seed and key
+    pub resource_protection: u8,
+    /// The session configuration ID
+    pub session_config_id: u16,
+}
+
+// ===== XcpMaster =====
+
+/// An XCP master, talking to a slave over CAN.
+#[derive(Debug)]
+pub struct XcpMaster<'a, S> {
+    sock: &'a S,
+    tx_id: CanId,
+    rx_id: CanId,
+    timeout: Duration,
+    retries: u32,
+    padding: Option<u8>,
+    info: Option<ConnectInfo>,
+    daq: VecDeque<DaqPacket>,
+    daq_capacity: usize,
+    daq_overflows: u64,
+}
+
+impl<'a, S> XcpMaster<'a, S>
+where
+    S: CanTransmitter<CanFrame> + CanReceiver,
+    S::Frame: Frame,
+{
+    /// Creates a master that sends its commands with `tx_id`, and receives
+    /// the slave's packets with `rx_id`.
+    pub fn new<T, R>(sock: &'a S, tx_id: T, rx_id: R) -> Self
+    where
+        T: Into<CanId>,
+        R: Into<CanId>,
+    {
+        Self {
+            sock,
+            tx_id: tx_id.into(),
+            rx_id: rx_id.into(),
+            timeout: DEFAULT_TIMEOUT,
+            retries: 2,
+            padding: None,
+            info: None,
+            daq: VecDeque::new(),
+            daq_capacity: DEFAULT_DAQ_CAPACITY,
+            daq_overflows: 0,
+        }
+    }
+
+    /// Sets the T1 timeout for the response to a command.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets the number of times a command is repeated after a timeout.
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Pads the command frames to eight bytes with the byte, as some
+    /// slaves require.
+    pub fn with_padding(mut self, padding: u8) -> Self {
+        self.padding = Some(padding);
+        self
+    }
+
+    /// Sets the number of DAQ packets that are queued while waiting for
+    /// command responses. When the queue is full, the oldest packet is
+    /// dropped.
+    pub fn with_daq_capacity(mut self, capacity: usize) -> Self {
+        self.daq_capacity = capacity;
+        self
+    }
+
+    /// Gets the number of DAQ packets dropped because the queue was full.
+    pub fn daq_overflows(&self) -> u64 {
+        self.daq_overflows
+    }
+
+    /// Gets the properties of the slave, if connected.
+    pub fn connect_info(&self) -> Option<&ConnectInfo> {
+        self.info.as_ref()
+    }
+
+    /// Determines if the master is connected to the slave.
+    pub fn is_connected(&self) -> bool {
+        self.info.is_some()
+    }
+
+    /// Sends a command packet.
+    fn send(&self, cmd: &[u8]) -> Result<(), XcpError> {
+        if cmd.is_empty() || cmd.len() > MAX_CAN_PACKET {
+            return Err(XcpError::Protocol("command doesn't fit in a CAN packet"));
+        }
+        let mut buf = [self.padding.unwrap_or(0); MAX_CAN_PACKET];
+        let len = if self.padding.is_some() {
+            MAX_CAN_PACKET
+        } else {
+            cmd.len()
+        };
+        buf[..cmd.len()].copy_from_slice(cmd);
+        let frame = CanFrame::new(self.tx_id, &buf[..len])
+            .ok_or(XcpError::Protocol("command doesn't fit in a CAN packet"))?;
+        self.sock.transmit(&frame)?;
+        Ok(())
+    }
+
+    /// Receives the next packet from the slave, queueing DAQ packets.
+    ///
+    /// Returns `None` on a timeout.
+    fn recv_reply(&mut self, deadline: Instant) -> Result<Option<XcpPacket>, XcpError> {
+        loop {
+            match self.recv(deadline)? {
+                Some(XcpPacket::Daq(daq)) => self.queue_daq(daq),
+                other => return Ok(other),
+            }
+        }
+    }
+
+    /// Queues a DAQ packet, dropping the oldest one if the queue is full.
+    fn queue_daq(&mut self, daq: DaqPacket) {
+        if self.daq.len() >= self.daq_capacity {
+            self.daq_overflows += 1;
+            if self.daq.pop_front().is_none() {
+                return;
+            }
+        }
+        self.daq.push_back(daq);
+    }
+
+    /// Receives the next packet from the slave.
+    fn recv(&self, deadline: Instant) -> Result<Option<XcpPacket>, XcpError> {
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Ok(None);
+            }
+            let frame = match self.sock.receive_timeout(remaining) {
+                Ok(frame) => frame,
+                Err(err) if err.kind() == io::ErrorKind::TimedOut => return Ok(None),
+                Err(err) => return Err(err.into()),
+            };
+            if frame.can_id() == self.rx_id && !frame.is_remote_frame() {
+                if let Some(pkt) = XcpPacket::parse(frame.data()) {
+                    return Ok(Some(pkt));
+                }
+            }
+        }
+    }
+
+    /// Sends a command, and waits for its response.
+    ///
+    /// On a timeout, the command processor is resynchronized with SYNCH,
+    /// and the command is repeated. Events and service requests are
+    /// skipped.
+    pub fn command(&mut self, cmd: &[u8]) -> Result<Vec<u8>, XcpError> {
+        for attempt in 0..=self.retries {
+            if attempt > 0 {
+                self.synch()?;
+            }
+            self.send(cmd)?;
+            let deadline = Instant::now() + self.timeout;
+            loop {
+                match self.recv_reply(deadline)? {
+                    Some(XcpPacket::Response(data)) => return Ok(data),
+                    Some(XcpPacket::Error(code)) => return Err(XcpError::Command(code)),
+                    Some(_) => continue,
+                    None => break,
+                }
+            }
+        }
+        Err(XcpError::Timeout)
+    }
+
+    /// Resynchronizes the command processor of the slave, which answers
+    /// with a SYNCH error.
+    pub fn synch(&mut self) -> Result<(), XcpError> {
+        self.send(&[CMD_SYNCH])?;
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            match self.recv_reply(deadline)? {
+                Some(XcpPacket::Error(XcpErrorCode::CMD_SYNCH)) => return Ok(()),
+                Some(XcpPacket::Error(code)) => return Err(XcpError::Command(code)),
+                Some(_) => continue,
+                None => return Err(XcpError::Timeout),
+            }
+        }
+    }
+
+    /// Connects to the slave, in the normal mode.
+    pub fn connect(&mut self) -> Result<ConnectInfo, XcpError> {
+        let resp = self.command(&[CMD_CONNECT, 0])?;
+        let info = ConnectInfo::from_response(&resp)
+            .ok_or(XcpError::Protocol("invalid CONNECT response"))?;
+        self.info = Some(info);
+        Ok(info)
+    }
+
+    /// Disconnects from the slave.
+    pub fn disconnect(&mut self) -> Result<(), XcpError> {
+        self.command(&[CMD_DISCONNECT])?;
+        self.info = None;
+        self.daq.clear();
+        Ok(())
+    }
+
+    /// Gets the status of the slave.
+    pub fn get_status(&mut self) -> Result<XcpStatus, XcpError> {
+        let info = *self.info.as_ref().ok_or(XcpError::NotConnected)?;
+        let resp = self.command(&[CMD_GET_STATUS])?;
+        if resp.len() < 5 {
+            return Err(XcpError::Protocol("invalid GET_STATUS response"));
+        }
+        let id = [resp[3], resp[4]];
+        Ok(XcpStatus {
+            session_status: resp[0],
+            resource_protection: resp[1],
+            session_config_id: if info.is_big_endian() {
+                u16::from_be_bytes(id)
+            } else {
+                u16::from_le_bytes(id)
+            },
+        })
+    }
+
+    /// Gets the largest block that can be transferred in a single packet,
+    /// after `overhead` bytes of the command.
+    fn max_block(&self, overhead: usize) -> Result<(ConnectInfo, usize), XcpError> {
+        let info = *self.info.as_ref().ok_or(XcpError::NotConnected)?;
+        let max_cto = usize::from(info.max_cto).min(MAX_CAN_PACKET);
+        Ok((info, max_cto.saturating_sub(overhead)))
+    }
+
+    /// Sets the memory transfer address.
+    pub fn set_mta(&mut self, addr: u32, ext: u8) -> Result<(), XcpError> {
+        let (info, _) = self.max_block(0)?;
+        let a = info.u32_bytes(addr);
+        self.command(&[CMD_SET_MTA, 0, 0, ext, a[0], a[1], a[2], a[3]])
+            .map(|_| ())
+    }
+
+    /// Reads a block from the memory transfer address, which is then
+    /// advanced past it.
+    pub fn upload(&mut self, len: u8) -> Result<Vec<u8>, XcpError> {
+        let (_, max) = self.max_block(1)?;
+        if usize::from(len) > max {
+            return Err(XcpError::Protocol("block too large for a single packet"));
+        }
+        let mut resp = self.command(&[CMD_UPLOAD, len])?;
+        if resp.len() < usize::from(len) {
+            return Err(XcpError::Protocol("short UPLOAD response"));
+        }
+        resp.truncate(len.into());
+        Ok(resp)
+    }
+
+    /// Reads a block from an address.
+    pub fn short_upload(&mut self, addr: u32, ext: u8, len: u8) -> Result<Vec<u8>, XcpError> {
+        let (info, max) = self.max_block(1)?;
+        if usize::from(len) > max {
+            return Err(XcpError::Protocol("block too large for a single packet"));
+        }
+        let a = info.u32_bytes(addr);
+        let mut resp = self.command(&[CMD_SHORT_UPLOAD, len, 0, ext, a[0], a[1], a[2], a[3]])?;
+        if resp.len() < usize::from(len) {
+            return Err(XcpError::Protocol("short SHORT_UPLOAD response"));
+        }
+        resp.truncate(len.into());
+        Ok(resp)
+    }
+
+    /// Writes a block to the memory transfer address, which is then
+    /// advanced past it.
+    pub fn download(&mut self, data: &[u8]) -> Result<(), XcpError> {
+        let (_, max) = self.max_block(2)?;
+        if data.len() > max {
+            return Err(XcpError::Protocol("block too large for a single packet"));
+        }
+        let mut cmd = vec![CMD_DOWNLOAD, data.len() as u8];
+        cmd.extend_from_slice(data);
+        self.command(&cmd).map(|_| ())
+    }
+
+    /// Reads the next DAQ packet, waiting at most for the timeout.
+    ///
+    /// Packets received while waiting for command responses are returned
+    /// first.
+    pub fn read_daq(&mut self, timeout: Duration) -> Result<DaqPacket, XcpError> {
+        if let Some(daq) = self.daq.pop_front() {
+            return Ok(daq);
+        }
+        let deadline = Instant::now() + timeout;
+        loop {
+            match self.recv(deadline)? {
+                Some(XcpPacket::Daq(daq)) => return Ok(daq),
+                Some(_) => continue,
+                None => return Err(XcpError::Timeout),
+            }
+        }
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            Some(XcpPacket::Response(vec![0x12, 0x34])),
+            XcpPacket::parse(&[0xFF, 0x12, 0x34])
+        );
+        assert_eq!(
+            Some(XcpPacket::Error(XcpErrorCode::ACCESS_LOCKED)),
+            XcpPacket::parse(&[0xFE, 0x25])
+        );
+        assert_eq!(
+            Some(XcpPacket::Daq(DaqPacket {
+                pid: 3,
+                data: vec![1, 2, 3]
+            })),
+            XcpPacket::parse(&[0x03, 1, 2, 3])
+        );
+        assert_eq!(None, XcpPacket::parse(&[]));
+        assert_eq!(None, XcpPacket::parse(&[0xFE]));
+    }
+
+    #[test]
+    fn test_connect_info() {
+        let info = ConnectInfo::from_response(&[0x15, 0xC0, 8, 8, 0, 1, 1]).unwrap();
+        assert!(!info.is_big_endian());
+        assert_eq!((8, 8), (info.max_cto, info.max_dto));
+        assert_eq!([0x78, 0x56, 0x34, 0x12], info.u32_bytes(0x1234_5678));
+
+        let info = ConnectInfo::from_response(&[0x15, 0xC1, 8, 0, 8, 1, 1]).unwrap();
+        assert!(info.is_big_endian());
+        assert_eq!(8, info.max_dto);
+        assert_eq!([0x12, 0x34, 0x56, 0x78], info.u32_bytes(0x1234_5678));
+
+        assert!(ConnectInfo::from_response(&[0x15, 0xC0]).is_none());
+    }
+
+    #[test]
+    fn test_command_too_long() {
+        use crate::mock::MockCanSocket;
+
+        let sock = MockCanSocket::<CanFrame>::new();
+        let mut xcp = XcpMaster::new(
+            &sock,
+            CanId::standard(0x7F0).unwrap(),
+            CanId::standard(0x7F1).unwrap(),
+        );
+        assert!(matches!(
+            xcp.command(&[CMD_DOWNLOAD; 9]),
+            Err(XcpError::Protocol(_))
+        ));
+        assert!(matches!(xcp.command(&[]), Err(XcpError::Protocol(_))));
+        assert!(sock.sent().is_empty());
+    }
+
+    #[test]
+    fn test_daq_capacity() {
+        use crate::mock::MockCanSocket;
+
+        let sock = MockCanSocket::<CanFrame>::new();
+        let rx_id = CanId::standard(0x7F1).unwrap();
+        let mut xcp =
+            XcpMaster::new(&sock, CanId::standard(0x7F0).unwrap(), rx_id).with_daq_capacity(2);
+        for pid in 0..4 {
+            sock.push_rx(CanFrame::new(rx_id, &[pid, 0xAA]).unwrap());
+        }
+        sock.push_rx(CanFrame::new(rx_id, &[PID_RES]).unwrap());
+
+        xcp.command(&[CMD_GET_STATUS]).unwrap();
+        assert_eq!(2, xcp.daq_overflows());
+        assert_eq!(2, xcp.read_daq(Duration::ZERO).unwrap().pid);
+        assert_eq!(3, xcp.read_daq(Duration::ZERO).unwrap().pid);
+    }
+}
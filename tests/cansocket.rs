@@ -18,10 +18,24 @@ use socketcan::{
 #[cfg(feature = "vcan_tests")]
 use std::time;
 
+use serial_test::serial;
+
 // The virtual CAN interface to use for tests.
 #[cfg(feature = "vcan_tests")]
 const VCAN: &str = "vcan0";
 
+#[test]
+#[serial]
+fn test_open_default_error() {
+    use socketcan::{CanSocket, Socket};
+
+    std::env::set_var("CAN_INTERFACE", "nosuchcan0");
+    let err = CanSocket::open_default().unwrap_err().to_string();
+    std::env::remove_var("CAN_INTERFACE");
+    assert!(err.contains("'nosuchcan0' (from CAN_INTERFACE)"), "{}", err);
+    assert!(err.contains("available interfaces:"), "{}", err);
+}
+
 #[cfg(feature = "vcan_tests")]
 #[test]
 fn test_nonexistant_device() {
@@ -62,6 +76,31 @@ fn vcan_enable_own_loopback() {
     sock.read_frame().unwrap();
 }
 
+#[test]
+#[cfg(feature = "vcan_tests")]
+fn vcan_busy_poll() {
+    let sock = CanSocket::open(VCAN).unwrap();
+    sock.set_busy_poll(time::Duration::ZERO).unwrap();
+    assert_eq!(time::Duration::ZERO, sock.busy_poll().unwrap());
+}
+
+#[test]
+#[cfg(feature = "vcan_tests")]
+fn vcan_pause_resume() {
+    let sock = CanSocket::open(VCAN).unwrap();
+    sock.set_filters(&[(0x123, 0x7FF), (0x456, 0x7FF)]).unwrap();
+    sock.set_error_mask(ERR_MASK_ALL).unwrap();
+    let filters = sock.filters().unwrap();
+
+    let paused = sock.pause().unwrap();
+    assert!(sock.filters().unwrap().is_empty());
+    assert_eq!(0, sock.error_filter().unwrap());
+
+    paused.resume().unwrap();
+    assert_eq!(filters, sock.filters().unwrap());
+    assert_eq!(ERR_MASK_ALL, sock.error_filter().unwrap());
+}
+
 // #[test]
 // fn vcan_set_down() {
 //     let can_if = CanInterface::open(VCAN).unwrap();
@@ -80,6 +119,105 @@ fn vcan_test_nonblocking() {
     assert!(sock.read_frame().should_retry());
 }
 
+#[test]
+#[cfg(feature = "vcan_tests")]
+fn vcan_fd_embedded_can() {
+    use socketcan::{BlockingCan, CanAnyFrame, CanFdSocket};
+
+    let mut tx = CanFdSocket::open(VCAN).unwrap();
+    let mut rx = CanFdSocket::open(VCAN).unwrap();
+
+    let data = CanAnyFrame::new(StandardId::new(0x123).unwrap(), &[1, 2, 3]).unwrap();
+    tx.transmit(&data).unwrap();
+    let frame = rx.receive().unwrap();
+    assert!(matches!(frame, CanAnyFrame::Normal(_)));
+    assert_eq!(data.id(), frame.id());
+    assert_eq!(data.data(), frame.data());
+
+    let remote = CanAnyFrame::new_remote(StandardId::new(0x456).unwrap(), 2).unwrap();
+    tx.transmit(&remote).unwrap();
+    let frame = rx.receive().unwrap();
+    assert!(frame.is_remote_frame());
+    assert_eq!(remote.id(), frame.id());
+    assert_eq!(2, frame.dlc());
+}
+
+#[test]
+#[cfg(feature = "vcan_tests")]
+fn vcan_read_frame_into() {
+    use socketcan::{CanFdFrame, CanFdSocket, Frame};
+
+    let tx = CanFdSocket::open(VCAN).unwrap();
+    let rx = CanFdSocket::open(VCAN).unwrap();
+    rx.set_read_timeout(time::Duration::from_millis(500))
+        .unwrap();
+
+    let mut frame = CanFdFrame::default();
+    let fd = CanFdFrame::from_raw_id(0x123, &[0x55; 24]).unwrap();
+    tx.write_frame(&fd).unwrap();
+    assert_eq!(libc::CANFD_MTU, rx.read_frame_into(&mut frame).unwrap());
+    assert_eq!(fd, frame);
+
+    let classic = CanFrame::from_raw_id(0x456, &[1, 2, 3]).unwrap();
+    tx.write_frame(&classic).unwrap();
+    assert_eq!(libc::CAN_MTU, rx.read_frame_into(&mut frame).unwrap());
+    assert_eq!(0x456, frame.raw_id());
+    assert_eq!(&[1, 2, 3], frame.data());
+
+    let remote = CanFrame::remote_from_raw_id(0x789, 2).unwrap();
+    tx.write_frame(&remote).unwrap();
+    let err = rx.read_frame_into(&mut frame).unwrap_err();
+    assert_eq!(std::io::ErrorKind::InvalidData, err.kind());
+    assert!(!frame.is_remote_frame());
+}
+
+#[test]
+#[cfg(feature = "vcan_tests")]
+fn vcan_read_frames_into() {
+    use socketcan::{CanFdSocket, Frame};
+
+    let tx = CanFdSocket::open(VCAN).unwrap();
+    let rx = CanFdSocket::open(VCAN).unwrap();
+
+    let mut frames = Vec::new();
+    for round in 0..2 {
+        for i in 0..3 {
+            let frame = CanFrame::from_raw_id(0x100 + i, &[round]).unwrap();
+            tx.write_frame(&frame).unwrap();
+        }
+        assert_eq!(3, rx.read_frames_into(&mut frames).unwrap());
+        let ids: Vec<u32> = frames.iter().map(|f| f.frame.raw_id()).collect();
+        assert_eq!(vec![0x100, 0x101, 0x102], ids);
+    }
+}
+
+#[test]
+#[cfg(feature = "vcan_tests")]
+fn vcan_fd_conversion() {
+    use socketcan::{CanAnyFrame, CanFdFrame, CanFdSocket, FdConversion, Frame};
+
+    let tx = CanFdSocket::open(VCAN)
+        .unwrap()
+        .with_fd_conversion(FdConversion::ToFd { brs: true });
+    let rx = CanFdSocket::open(VCAN).unwrap();
+
+    let frame = CanFrame::from_raw_id(0x123, &[1, 2]).unwrap();
+    tx.write_frame(&frame).unwrap();
+    match rx.read_frame().unwrap() {
+        CanAnyFrame::Fd(fd) => assert!(fd.is_brs()),
+        other => panic!("expected an FD frame, got {:?}", other),
+    }
+
+    let classic = CanSocket::open(VCAN)
+        .unwrap()
+        .with_fd_conversion(FdConversion::ToClassic);
+    let short = CanAnyFrame::from(CanFdFrame::from_raw_id(0x123, &[1, 2]).unwrap());
+    let long = CanAnyFrame::from(CanFdFrame::from_raw_id(0x123, &[0; 12]).unwrap());
+    classic.write_any_frame(&short).unwrap();
+    assert!(matches!(rx.read_frame().unwrap(), CanAnyFrame::Normal(_)));
+    assert!(classic.write_any_frame(&long).is_err());
+}
+
 /*
 #[test]
 #[cfg(feature = "vcan_tests")]
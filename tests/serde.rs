@@ -0,0 +1,61 @@
+// socketcan/tests/serde.rs
+//
+// Integration tests for the serde support.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+#![cfg(feature = "serde")]
+
+use socketcan::{
+    frame::FdFlags, CanAnyFrame, CanDataFrame, CanError, CanErrorFrame, CanFdFrame, CanFrame,
+    EmbeddedFrame, ExtendedId, Frame, Id, StandardId,
+};
+
+const EXT_ID: Id = Id::Extended(ExtendedId::MAX);
+
+const DATA: &[u8] = &[0, 1, 2, 3];
+
+#[test]
+fn test_serde() {
+    let frame = CanFrame::new(StandardId::new(0x123).unwrap(), DATA).unwrap();
+    let json = serde_json::to_string(&frame).unwrap();
+    assert_eq!(r#"{"Data":{"id":291,"ext":false,"data":[0,1,2,3]}}"#, json);
+
+    let frame: CanFrame = serde_json::from_str(&json).unwrap();
+    assert!(matches!(frame, CanFrame::Data(_)));
+    assert_eq!(0x123, frame.raw_id());
+    assert_eq!(DATA, frame.data());
+
+    let frame = CanFrame::new_remote(EXT_ID, 2).unwrap();
+    let json = serde_json::to_string(&frame).unwrap();
+    let frame: CanFrame = serde_json::from_str(&json).unwrap();
+    assert!(frame.is_remote_frame());
+    assert_eq!(EXT_ID, frame.id());
+    assert_eq!(2, frame.dlc());
+
+    let frame = CanAnyFrame::Error(CanErrorFrame::from(CanError::BusOff));
+    let json = serde_json::to_string(&frame).unwrap();
+    let frame: CanAnyFrame = serde_json::from_str(&json).unwrap();
+    match frame {
+        CanAnyFrame::Error(frame) => assert!(matches!(frame.into_error(), CanError::BusOff)),
+        _ => panic!("Wrong frame type"),
+    }
+
+    let frame = CanFdFrame::with_flags(EXT_ID, DATA, FdFlags::BRS | FdFlags::FDF).unwrap();
+    let json = serde_json::to_string(&frame).unwrap();
+    let frame: CanFdFrame = serde_json::from_str(&json).unwrap();
+    assert_eq!(EXT_ID, frame.id());
+    assert!(frame.is_brs());
+    assert!(!frame.is_esi());
+    assert!(frame.is_fdf());
+    assert_eq!(DATA, frame.data());
+
+    // Standard ID out of range
+    let res = serde_json::from_str::<CanDataFrame>(r#"{"id":2048,"ext":false,"data":[]}"#);
+    assert!(res.is_err());
+}